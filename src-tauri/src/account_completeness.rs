@@ -0,0 +1,20 @@
+use serde::Serialize;
+
+/// Which steps of setting up an account well have been done, e.g. for showing a setup-progress indicator.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct AccountCompleteness {
+    /// The user has given the account a label.
+    pub has_label: bool,
+
+    /// The account has at least one relay configured.
+    pub has_relays: bool,
+
+    /// A NIP-05 identifier has been verified to point to the account.
+    pub has_verified_nip05: bool,
+
+    /// The user has confirmed that they've backed up the account's key.
+    pub has_acknowledged_backup: bool,
+
+    /// The vault the account's key is stored in is encrypted with a passphrase.
+    pub uses_encryption: bool,
+}
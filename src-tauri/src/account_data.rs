@@ -0,0 +1,78 @@
+use crate::profile::ProfileFields;
+use crate::relays::RelayPolicy;
+use nostr_sdk::nips::nip65;
+use nostr_sdk::{Event, JsonUtil, Metadata, RelayMetadata};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Longest that refreshing an account's data waits for relays, in total.
+pub const REFRESH_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// An account's stored profile and relay list after refreshing them from relays, and which of them changed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct AccountDataRefresh {
+    /// Whether a newer profile than the stored one was found.
+    pub profile_changed: bool,
+
+    /// Whether a newer relay list than the stored one was found.
+    pub relay_list_changed: bool,
+
+    /// Fields of the stored kind-0 profile, if there is one.
+    pub profile: Option<ProfileFields>,
+
+    /// Relays of the stored NIP-65 relay list, if there is one.
+    pub relay_list: Option<Vec<(String, RelayPolicy)>>,
+}
+
+/// Reads the editable fields out of a kind-0 event's content.
+pub fn parse_profile(event: &Event) -> anyhow::Result<ProfileFields> {
+    let metadata = Metadata::from_json(&event.content)?;
+    Ok(ProfileFields::from_metadata(metadata))
+}
+
+/// Reads the relays out of a kind-10002 relay list. Relays without a marker are both read from and written to.
+pub fn parse_relay_list(event: &Event) -> Vec<(String, RelayPolicy)> {
+    nip65::extract_relay_list(event)
+        .into_iter()
+        .map(|(url, metadata)| {
+            let policy = RelayPolicy {
+                read: metadata != Some(RelayMetadata::Write),
+                write: metadata != Some(RelayMetadata::Read),
+            };
+            (url.to_string(), policy)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{EventBuilder, Keys, UncheckedUrl};
+
+    #[test]
+    fn parses_relay_list_markers() {
+        let event = EventBuilder::relay_list([
+            (UncheckedUrl::from("wss://both.example.com"), None),
+            (
+                UncheckedUrl::from("wss://read.example.com"),
+                Some(RelayMetadata::Read),
+            ),
+            (
+                UncheckedUrl::from("wss://write.example.com"),
+                Some(RelayMetadata::Write),
+            ),
+        ])
+        .to_event(&Keys::generate())
+        .unwrap();
+
+        let policy = |read, write| RelayPolicy { read, write };
+        assert_eq!(
+            parse_relay_list(&event),
+            vec![
+                ("wss://both.example.com".to_string(), policy(true, true)),
+                ("wss://read.example.com".to_string(), policy(true, false)),
+                ("wss://write.example.com".to_string(), policy(false, true)),
+            ]
+        );
+    }
+}
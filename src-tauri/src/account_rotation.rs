@@ -0,0 +1,54 @@
+use crate::relays::RelayPublishResult;
+use nostr_sdk::{Event, EventBuilder, PublicKey, Tag, ToBech32, UnsignedEvent};
+use serde::Serialize;
+
+/// Response from rotating an account to a new key.
+#[derive(Clone, Debug, Serialize)]
+pub struct RotateAccountResponse {
+    /// The bech32-encoded public key of the account that replaces the rotated one.
+    pub new_npub: String,
+
+    /// The signed note announcing the move, or `None` if no announcement was requested.
+    pub announcement: Option<Event>,
+
+    /// How each relay responded to the announcement being published.
+    /// Empty if there was no announcement.
+    pub publish_results: Vec<RelayPublishResult>,
+}
+
+/// Builds a kind-1 note, authored by the retired account, telling its followers where it has moved to.
+/// The new account is mentioned so that clients can link to it.
+pub fn build_announcement_event(
+    old_public_key: PublicKey,
+    new_public_key: PublicKey,
+) -> anyhow::Result<UnsignedEvent> {
+    let content = format!(
+        "This account has been retired. I've moved to nostr:{}",
+        new_public_key.to_bech32()?
+    );
+    Ok(
+        EventBuilder::text_note(content, [Tag::public_key(new_public_key)])
+            .to_unsigned_event(old_public_key),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{Keys, Kind};
+
+    #[test]
+    fn announcement_points_to_new_account() {
+        let old_public_key = Keys::generate().public_key();
+        let new_public_key = Keys::generate().public_key();
+
+        let event = build_announcement_event(old_public_key, new_public_key).unwrap();
+
+        assert_eq!(event.kind, Kind::TextNote);
+        assert_eq!(event.pubkey, old_public_key);
+        assert!(event
+            .content
+            .ends_with(&format!("nostr:{}", new_public_key.to_bech32().unwrap())));
+        assert_eq!(event.tags, vec![Tag::public_key(new_public_key)]);
+    }
+}
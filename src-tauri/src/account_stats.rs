@@ -0,0 +1,25 @@
+use nostr_sdk::PublicKey;
+use serde::Serialize;
+
+/// How much an account has been used, e.g. for showing as a badge next to it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct AccountStats {
+    /// Number of events signed by the account.
+    pub sign_count: u64,
+
+    /// Number of invoices paid on behalf of the account.
+    pub payment_count: u64,
+
+    /// When the account last signed an event or paid an invoice, as an RFC 3339 timestamp.
+    /// `None` if it never has.
+    pub last_active_time: Option<String>,
+}
+
+/// Somewhere to record what the user's accounts have been used for.
+pub trait AccountActivityLog: Send + Sync {
+    /// Records that `count` events were signed by the account with `public_key`.
+    fn record_signs(&self, public_key: &PublicKey, count: u64) -> anyhow::Result<()>;
+
+    /// Records that an invoice was paid on behalf of the account with `public_key`.
+    fn record_payment(&self, public_key: &PublicKey) -> anyhow::Result<()>;
+}
@@ -0,0 +1,53 @@
+use nostr_sdk::hashes::Hash;
+use nostr_sdk::util::hkdf;
+use nostr_sdk::{Keys, PublicKey, SecretKey};
+
+/// HKDF salt for app identities, so that they can't match keys derived from the account's key for anything else.
+const APP_IDENTITY_SALT: &[u8] = b"keystache-app-identity-v1";
+
+/// Derives the keys of an account's separate identity for the app with `app_public_key`, so that the app is given
+/// a pubkey of its own rather than the account's. The secret key is HKDF-SHA256 of the account's secret key, with
+/// the app's public key as the info, so the same account and app always get the same identity.
+pub fn derive_app_keys(secret_key: &SecretKey, app_public_key: &PublicKey) -> anyhow::Result<Keys> {
+    let prk = hkdf::extract(APP_IDENTITY_SALT, &secret_key.secret_bytes());
+    let okm = hkdf::expand(&prk.to_byte_array(), &app_public_key.to_bytes(), 32);
+    // Only fails in the astronomically unlikely case that the output isn't a valid secret key.
+    let app_secret_key = SecretKey::from_slice(&okm)
+        .map_err(|_| anyhow::anyhow!("Derived an invalid key for the app"))?;
+    Ok(Keys::new(app_secret_key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn app_identity_is_stable_per_app() {
+        let account_keys = Keys::generate();
+        let secret_key = account_keys.secret_key().unwrap();
+        let app = Keys::generate().public_key();
+        let other_app = Keys::generate().public_key();
+
+        let app_keys = derive_app_keys(secret_key, &app).unwrap();
+        assert_eq!(
+            derive_app_keys(secret_key, &app).unwrap().public_key(),
+            app_keys.public_key()
+        );
+        assert_ne!(app_keys.public_key(), account_keys.public_key());
+        assert_ne!(
+            derive_app_keys(secret_key, &other_app)
+                .unwrap()
+                .public_key(),
+            app_keys.public_key()
+        );
+
+        // Other accounts get other identities for the same app.
+        let other_account_keys = Keys::generate();
+        assert_ne!(
+            derive_app_keys(other_account_keys.secret_key().unwrap(), &app)
+                .unwrap()
+                .public_key(),
+            app_keys.public_key()
+        );
+    }
+}
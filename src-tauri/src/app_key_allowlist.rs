@@ -0,0 +1,95 @@
+use crate::validation::validate_npub;
+use nostr_sdk::PublicKey;
+use serde::{Deserialize, Serialize};
+
+/// App keys that Keystache's NIP-46 servers accept requests from. Each pattern is either the npub that an app signs
+/// its requests with, or `*`, which matches every app.
+///
+/// Only apps that sign every request with the same key can be listed. NIP-55 clients usually sign each request with a
+/// new key, so they can only be allowed with `*`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AppKeyAllowlist {
+    patterns: Vec<String>,
+}
+
+impl Default for AppKeyAllowlist {
+    /// Allows every app.
+    fn default() -> Self {
+        Self {
+            patterns: vec!["*".to_string()],
+        }
+    }
+}
+
+impl AppKeyAllowlist {
+    /// Creates an allowlist from a list of patterns. An empty list blocks every app.
+    pub fn new(patterns: Vec<String>) -> anyhow::Result<Self> {
+        if let Some(pattern) = patterns
+            .iter()
+            .find(|pattern| *pattern != "*" && validate_npub(pattern).is_err())
+        {
+            return Err(anyhow::anyhow!("Invalid app key pattern: {:?}", pattern));
+        }
+
+        Ok(Self { patterns })
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Whether requests from the app with `app_public_key` should be accepted.
+    pub fn allows(&self, app_public_key: &PublicKey) -> bool {
+        self.patterns.iter().any(|pattern| {
+            pattern == "*"
+                || validate_npub(pattern).is_ok_and(|public_key| public_key == *app_public_key)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{Keys, ToBech32};
+
+    #[test]
+    fn default_allows_everything() {
+        let allowlist = AppKeyAllowlist::default();
+
+        for _ in 0..3 {
+            assert!(allowlist.allows(&Keys::generate().public_key()));
+        }
+    }
+
+    #[test]
+    fn disallowed_app_is_refused() {
+        let allowed_apps = [Keys::generate().public_key(), Keys::generate().public_key()];
+        let allowlist = AppKeyAllowlist::new(
+            allowed_apps
+                .iter()
+                .map(|app| app.to_bech32().unwrap())
+                .collect(),
+        )
+        .unwrap();
+
+        for app in &allowed_apps {
+            assert!(allowlist.allows(app));
+        }
+        assert!(!allowlist.allows(&Keys::generate().public_key()));
+    }
+
+    #[test]
+    fn empty_allowlist_refuses_everything() {
+        assert!(!AppKeyAllowlist::new(Vec::new())
+            .unwrap()
+            .allows(&Keys::generate().public_key()));
+    }
+
+    #[test]
+    fn invalid_patterns_error() {
+        for pattern in ["", "  ", "http://localhost:*", "npub1*"] {
+            assert!(AppKeyAllowlist::new(vec![pattern.to_string()]).is_err());
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use nostr_sdk::Kind;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Default time to wait for the user to respond to a request, in seconds.
+const DEFAULT_APPROVAL_TIMEOUT_SECS: u64 = 120;
+
+/// How long to wait for the user to approve or reject each kind of request before rejecting it.
+/// All timeouts are in seconds.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApprovalTimeouts {
+    /// Used for any request that doesn't have a more specific timeout.
+    pub default_secs: u64,
+
+    /// Timeouts for signing events, keyed by event kind.
+    #[serde(default)]
+    pub sign_event_kind_secs: BTreeMap<u64, u64>,
+
+    /// Timeout for paying invoices.
+    #[serde(default)]
+    pub pay_invoice_secs: Option<u64>,
+}
+
+impl Default for ApprovalTimeouts {
+    fn default() -> Self {
+        Self {
+            default_secs: DEFAULT_APPROVAL_TIMEOUT_SECS,
+            sign_event_kind_secs: BTreeMap::new(),
+            pay_invoice_secs: None,
+        }
+    }
+}
+
+impl ApprovalTimeouts {
+    /// Errors if any timeout is zero, since every request would be rejected before the user could respond.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.default_secs == 0
+            || self.pay_invoice_secs == Some(0)
+            || self.sign_event_kind_secs.values().any(|secs| *secs == 0)
+        {
+            return Err(anyhow::anyhow!(
+                "Approval timeouts must be at least one second"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// How long to wait for the user to respond to a request to sign an event of `kind`.
+    pub fn for_sign_event(&self, kind: Kind) -> Duration {
+        Duration::from_secs(
+            self.sign_event_kind_secs
+                .get(&kind.as_u64())
+                .copied()
+                .unwrap_or(self.default_secs),
+        )
+    }
+
+    /// How long to wait for the user to respond to a request to pay an invoice.
+    pub fn for_pay_invoice(&self) -> Duration {
+        Duration::from_secs(self.pay_invoice_secs.unwrap_or(self.default_secs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_to_default() {
+        let timeouts = ApprovalTimeouts {
+            default_secs: 60,
+            sign_event_kind_secs: BTreeMap::from([(4, 30)]),
+            pay_invoice_secs: None,
+        };
+
+        assert_eq!(
+            timeouts.for_sign_event(Kind::EncryptedDirectMessage),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            timeouts.for_sign_event(Kind::TextNote),
+            Duration::from_secs(60)
+        );
+        assert_eq!(timeouts.for_pay_invoice(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn zero_timeouts_are_invalid() {
+        assert!(ApprovalTimeouts::default().validate().is_ok());
+
+        for timeouts in [
+            ApprovalTimeouts {
+                default_secs: 0,
+                ..Default::default()
+            },
+            ApprovalTimeouts {
+                sign_event_kind_secs: BTreeMap::from([(1, 0)]),
+                ..Default::default()
+            },
+            ApprovalTimeouts {
+                pay_invoice_secs: Some(0),
+                ..Default::default()
+            },
+        ] {
+            assert!(timeouts.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let timeouts = ApprovalTimeouts {
+            default_secs: 60,
+            sign_event_kind_secs: BTreeMap::from([(1, 10), (4, 30)]),
+            pay_invoice_secs: Some(300),
+        };
+
+        let json = serde_json::to_string(&timeouts).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ApprovalTimeouts>(&json).unwrap(),
+            timeouts
+        );
+
+        // Only the default is required.
+        assert_eq!(
+            serde_json::from_str::<ApprovalTimeouts>(r#"{"default_secs":60}"#).unwrap(),
+            ApprovalTimeouts {
+                default_secs: 60,
+                ..Default::default()
+            }
+        );
+    }
+}
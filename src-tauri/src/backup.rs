@@ -0,0 +1,190 @@
+use crate::ncryptsec;
+use nostr_sdk::SecretKey;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Identifies a file as a Keystache backup.
+const BACKUP_MAGIC: &str = "keystache-backup";
+
+/// Version of the backup format that Keystache reads.
+const BACKUP_VERSION: u64 = 1;
+
+/// An encrypted backup of the user's accounts, as stored in a backup file.
+/// Each key is encrypted separately with the backup's passphrase, as a NIP-49 `ncryptsec`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct BackupFile {
+    magic: String,
+    version: u64,
+    accounts: Vec<BackupFileAccount>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct BackupFileAccount {
+    ncryptsec: String,
+    label: Option<String>,
+}
+
+/// An account read from a backup, with its key decrypted.
+pub struct BackupAccount {
+    pub secret_key: SecretKey,
+
+    /// Name the user had given the account, if any.
+    pub label: Option<String>,
+}
+
+/// Reads and decrypts the backup file at `path`. Every key is decrypted before any are returned,
+/// so a backup is either read in full or not at all.
+pub fn read_backup_file(path: &Path, passphrase: &str) -> anyhow::Result<Vec<BackupAccount>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| anyhow::anyhow!("Error reading backup file: {err}"))?;
+    decrypt_backup(&contents, passphrase)
+}
+
+/// Decrypts the contents of a backup file. Errors if the contents aren't a backup in a format Keystache knows,
+/// or if the passphrase doesn't decrypt every key.
+fn decrypt_backup(contents: &str, passphrase: &str) -> anyhow::Result<Vec<BackupAccount>> {
+    // Check the header before the rest, so that files that aren't backups get a clearer error than a parse failure.
+    let value: serde_json::Value = serde_json::from_str(contents)
+        .map_err(|_| anyhow::anyhow!("Unrecognized backup format"))?;
+    if value.get("magic").and_then(|magic| magic.as_str()) != Some(BACKUP_MAGIC) {
+        return Err(anyhow::anyhow!("Unrecognized backup format"));
+    }
+    match value.get("version").and_then(|version| version.as_u64()) {
+        Some(BACKUP_VERSION) => {}
+        Some(version) => return Err(anyhow::anyhow!("Unsupported backup version {version}")),
+        None => return Err(anyhow::anyhow!("Unrecognized backup format")),
+    }
+
+    let backup: BackupFile =
+        serde_json::from_value(value).map_err(|err| anyhow::anyhow!("Invalid backup: {err}"))?;
+    backup
+        .accounts
+        .into_iter()
+        .map(|account| {
+            Ok(BackupAccount {
+                secret_key: ncryptsec::decrypt_ncryptsec(&account.ncryptsec, passphrase)?,
+                label: account.label,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::key_manager::{BulkImportResult, KeystacheKeyManager};
+    use crate::ncryptsec::NcryptsecError;
+    use nostr_sdk::{Keys, ToBech32};
+
+    const PASSPHRASE: &str = "correct horse battery staple";
+
+    fn write_backup_file(folder: &Path, contents: &str) -> std::path::PathBuf {
+        let path = folder.join("backup.json");
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn backup_contents(accounts: &[(&Keys, Option<&str>)]) -> String {
+        serde_json::to_string(&BackupFile {
+            magic: BACKUP_MAGIC.to_string(),
+            version: BACKUP_VERSION,
+            accounts: accounts
+                .iter()
+                .map(|(keys, label)| BackupFileAccount {
+                    ncryptsec: ncryptsec::encrypt_ncryptsec(keys.secret_key().unwrap(), PASSPHRASE)
+                        .unwrap(),
+                    label: label.map(|label| label.to_string()),
+                })
+                .collect(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn imports_valid_backup_file() {
+        let folder = tempfile::TempDir::new().unwrap();
+        let existing_keys = Keys::generate();
+        let new_keys = Keys::generate();
+        let path = write_backup_file(
+            folder.path(),
+            &backup_contents(&[(&existing_keys, None), (&new_keys, Some("Work"))]),
+        );
+
+        let key_manager = KeystacheKeyManager::new_with_database(Database::new_in_temp_dir());
+        key_manager
+            .bulk_import(&[existing_keys.secret_key().unwrap().to_bech32().unwrap()])
+            .unwrap();
+
+        let accounts = read_backup_file(&path, PASSPHRASE).unwrap();
+        let results = key_manager.import_backup(accounts).unwrap();
+
+        assert_eq!(
+            results,
+            vec![
+                BulkImportResult::AlreadyPresent {
+                    npub: existing_keys.public_key().to_bech32().unwrap()
+                },
+                BulkImportResult::Imported {
+                    npub: new_keys.public_key().to_bech32().unwrap()
+                },
+            ]
+        );
+        assert_eq!(
+            key_manager
+                .get_account_metadata(&new_keys.public_key())
+                .unwrap()
+                .label,
+            Some("Work".to_string())
+        );
+    }
+
+    #[test]
+    fn wrong_passphrase_error() {
+        let folder = tempfile::TempDir::new().unwrap();
+        let path = write_backup_file(
+            folder.path(),
+            &backup_contents(&[(&Keys::generate(), None)]),
+        );
+
+        let err = read_backup_file(&path, "wrong passphrase").err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<NcryptsecError>(),
+            Some(&NcryptsecError::WrongPassword)
+        );
+    }
+
+    #[test]
+    fn unrecognized_format_error() {
+        let folder = tempfile::TempDir::new().unwrap();
+
+        for contents in [
+            "not json",
+            r#"{"accounts": []}"#,
+            r#"{"magic": "some-other-app", "version": 1, "accounts": []}"#,
+        ] {
+            let path = write_backup_file(folder.path(), contents);
+            assert_eq!(
+                read_backup_file(&path, PASSPHRASE)
+                    .err()
+                    .unwrap()
+                    .to_string(),
+                "Unrecognized backup format"
+            );
+        }
+
+        let path = write_backup_file(
+            folder.path(),
+            r#"{"magic": "keystache-backup", "version": 2, "accounts": []}"#,
+        );
+        assert_eq!(
+            read_backup_file(&path, PASSPHRASE)
+                .err()
+                .unwrap()
+                .to_string(),
+            "Unsupported backup version 2"
+        );
+
+        assert!(read_backup_file(&folder.path().join("missing.json"), PASSPHRASE).is_err());
+    }
+}
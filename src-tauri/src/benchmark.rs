@@ -0,0 +1,85 @@
+use nip_55::KeyManager;
+use nostr_sdk::{EventBuilder, Keys, Kind, PublicKey};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Most events that a benchmark can sign, so that it can't keep a CPU busy for long.
+pub const MAX_BENCHMARK_SIGNS: u32 = 10_000;
+
+/// How long signing a batch of throwaway events took.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SigningBenchmark {
+    /// Number of events signed.
+    pub count: u32,
+
+    /// Total time taken, in milliseconds.
+    pub total_millis: f64,
+
+    pub signs_per_second: f64,
+}
+
+/// Signs `count` throwaway events with the key for `public_key`, fetching the key from `key_manager` for each
+/// event as a real sign request would, and times it. The events are built here and dropped once signed, so
+/// nothing is shown to the user or sent anywhere. This is CPU-bound, so it should be run off the async runtime.
+pub fn benchmark_signing(
+    count: u32,
+    public_key: &PublicKey,
+    key_manager: &dyn KeyManager,
+) -> anyhow::Result<SigningBenchmark> {
+    if count == 0 || count > MAX_BENCHMARK_SIGNS {
+        return Err(anyhow::anyhow!(
+            "Benchmark must sign between 1 and {MAX_BENCHMARK_SIGNS} events"
+        ));
+    }
+
+    let start = Instant::now();
+    for i in 0..count {
+        let secret_key = key_manager
+            .get_secret_key(public_key)
+            .ok_or(anyhow::anyhow!("No key available for benchmark"))?;
+        EventBuilder::new(Kind::TextNote, format!("Keystache benchmark {i}"), None)
+            .to_event(&Keys::new(secret_key))?;
+    }
+
+    Ok(timing(count, start.elapsed()))
+}
+
+fn timing(count: u32, elapsed: Duration) -> SigningBenchmark {
+    // Guard against a zero elapsed time on coarse clocks, which would make the rate infinite.
+    let elapsed_secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    SigningBenchmark {
+        count,
+        total_millis: elapsed.as_secs_f64() * 1000.0,
+        signs_per_second: f64::from(count) / elapsed_secs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::SingleKeyManager;
+
+    #[test]
+    fn benchmark_reports_positive_rate() {
+        let keys = Keys::generate();
+        let key_manager = SingleKeyManager { keys: keys.clone() };
+
+        let benchmark = benchmark_signing(10, &keys.public_key(), &key_manager).unwrap();
+
+        assert_eq!(benchmark.count, 10);
+        assert!(benchmark.signs_per_second > 0.0);
+        assert!(benchmark.signs_per_second.is_finite());
+    }
+
+    #[test]
+    fn benchmark_rejects_bad_counts_and_unknown_keys() {
+        let keys = Keys::generate();
+        let key_manager = SingleKeyManager { keys: keys.clone() };
+
+        assert!(benchmark_signing(0, &keys.public_key(), &key_manager).is_err());
+        assert!(
+            benchmark_signing(MAX_BENCHMARK_SIGNS + 1, &keys.public_key(), &key_manager).is_err()
+        );
+        assert!(benchmark_signing(1, &Keys::generate().public_key(), &key_manager).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use crate::error_log;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The system clipboard.
+pub trait Clipboard: Send + Sync {
+    fn read_text(&self) -> anyhow::Result<Option<String>>;
+
+    fn write_text(&self, text: &str) -> anyhow::Result<()>;
+}
+
+/// Copies `value` to the clipboard and clears the clipboard after `timeout`, unless something else
+/// has been copied in the meantime. Returns a handle to the task that clears the clipboard.
+pub fn copy_with_auto_clear(
+    clipboard: Arc<dyn Clipboard>,
+    value: String,
+    timeout: Duration,
+) -> anyhow::Result<tokio::task::JoinHandle<()>> {
+    clipboard.write_text(&value)?;
+
+    Ok(tokio::spawn(async move {
+        tokio::time::sleep(timeout).await;
+
+        // Only clear the clipboard if it still holds the value, so that anything the user has copied since is kept.
+        if let Ok(Some(text)) = clipboard.read_text() {
+            if text == value {
+                if let Err(err) = clipboard.write_text("") {
+                    error_log::report(format!("Failed to clear clipboard: {err}"));
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MockClipboard {
+        text: Mutex<Option<String>>,
+    }
+
+    impl Clipboard for MockClipboard {
+        fn read_text(&self) -> anyhow::Result<Option<String>> {
+            Ok(self.text.lock().unwrap().clone())
+        }
+
+        fn write_text(&self, text: &str) -> anyhow::Result<()> {
+            *self.text.lock().unwrap() = Some(text.to_string());
+            Ok(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn clears_unchanged_clipboard_after_timeout() {
+        let clipboard = Arc::new(MockClipboard::default());
+
+        let clear_task = copy_with_auto_clear(
+            clipboard.clone(),
+            "nsec1secret".to_string(),
+            Duration::from_secs(30),
+        )
+        .unwrap();
+        assert_eq!(
+            clipboard.read_text().unwrap(),
+            Some("nsec1secret".to_string())
+        );
+
+        // The secret should still be there until the timeout.
+        tokio::time::sleep(Duration::from_secs(29)).await;
+        assert_eq!(
+            clipboard.read_text().unwrap(),
+            Some("nsec1secret".to_string())
+        );
+
+        clear_task.await.unwrap();
+        assert_eq!(clipboard.read_text().unwrap(), Some(String::new()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_changed_clipboard() {
+        let clipboard = Arc::new(MockClipboard::default());
+
+        let clear_task = copy_with_auto_clear(
+            clipboard.clone(),
+            "nsec1secret".to_string(),
+            Duration::from_secs(30),
+        )
+        .unwrap();
+
+        // The user copies something else before the timeout.
+        clipboard.write_text("something else").unwrap();
+
+        clear_task.await.unwrap();
+        assert_eq!(
+            clipboard.read_text().unwrap(),
+            Some("something else".to_string())
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+
+/// Actions that are hard or impossible to undo, which can be made to need a typed confirmation phrase on top of a
+/// click, so that they can't be confirmed by accident.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HighRiskAction {
+    /// Erasing every key and setting.
+    WipeAllData,
+
+    /// Exporting a secret key, even encrypted.
+    RevealSecret,
+
+    /// Paying an invoice that's over the maximum single payment.
+    LargePayment,
+}
+
+impl HighRiskAction {
+    /// What the user must type, exactly, to confirm the action.
+    pub fn confirmation_phrase(&self) -> &'static str {
+        match self {
+            Self::WipeAllData => "wipe all data",
+            Self::RevealSecret => "reveal secret",
+            Self::LargePayment => "pay invoice",
+        }
+    }
+}
+
+/// Which high-risk actions need a typed confirmation phrase. None do by default.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfirmationPhrasePolicy {
+    pub actions: Vec<HighRiskAction>,
+}
+
+impl ConfirmationPhrasePolicy {
+    /// The phrase that must be typed to confirm `action`, or `None` if it doesn't need one.
+    pub fn required_phrase(&self, action: HighRiskAction) -> Option<&'static str> {
+        self.actions
+            .contains(&action)
+            .then(|| action.confirmation_phrase())
+    }
+
+    /// Errors unless `action` doesn't need a phrase, or `typed_phrase` is its phrase.
+    pub fn check(
+        &self,
+        action: HighRiskAction,
+        typed_phrase: Option<&str>,
+    ) -> Result<(), ConfirmationPhraseError> {
+        check_phrase(self.required_phrase(action), typed_phrase)
+    }
+}
+
+/// Returned when a high-risk action is confirmed without typing its confirmation phrase.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConfirmationPhraseError {
+    pub expected: &'static str,
+}
+
+impl std::fmt::Display for ConfirmationPhraseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Type \"{}\" to confirm", self.expected)
+    }
+}
+
+impl std::error::Error for ConfirmationPhraseError {}
+
+/// Errors unless no phrase is required, or `typed_phrase` is exactly `required_phrase`. Nothing is trimmed or
+/// case-folded, since the point is that the user typed it deliberately.
+pub fn check_phrase(
+    required_phrase: Option<&'static str>,
+    typed_phrase: Option<&str>,
+) -> Result<(), ConfirmationPhraseError> {
+    match required_phrase {
+        Some(expected) if typed_phrase != Some(expected) => {
+            Err(ConfirmationPhraseError { expected })
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_exact_phrase_confirms() {
+        let policy = ConfirmationPhrasePolicy {
+            actions: vec![HighRiskAction::WipeAllData],
+        };
+        let err = Err(ConfirmationPhraseError {
+            expected: "wipe all data",
+        });
+
+        assert_eq!(
+            policy.check(HighRiskAction::WipeAllData, Some("wipe all data")),
+            Ok(())
+        );
+        for typed_phrase in [
+            None,
+            Some(""),
+            Some("Wipe all data"),
+            Some("wipe all data "),
+        ] {
+            assert_eq!(
+                policy.check(HighRiskAction::WipeAllData, typed_phrase),
+                err,
+                "{typed_phrase:?}"
+            );
+        }
+
+        // Actions that aren't in the policy don't need a phrase.
+        assert_eq!(policy.check(HighRiskAction::RevealSecret, None), Ok(()));
+        assert_eq!(
+            ConfirmationPhrasePolicy::default().check(HighRiskAction::WipeAllData, None),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,456 @@
+use crate::log_retention::PrunableLog;
+use crate::nip46_server::Nip46RequestHandler;
+use crate::signer::Signer;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use nip_55::KeyManager;
+use nostr_sdk::nips::nip46;
+use nostr_sdk::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+/// Most entries held while they can't be written, e.g. before the vault is unlocked. The oldest entries are dropped
+/// once this many are waiting.
+const MAX_PENDING_ENTRIES: usize = 10_000;
+
+/// Something that happened on a connection to a NIP-46 server.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConnectionEvent {
+    Connected,
+
+    /// A request was received. `method` is the NIP-46 method name (e.g. `sign_event`).
+    Request {
+        method: String,
+    },
+
+    Error {
+        message: String,
+    },
+
+    /// The connection closed. Includes how many requests of each method it received.
+    Disconnected {
+        request_counts: BTreeMap<String, u64>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ConnectionLogEntry {
+    /// Identifies the connection, so that its entries can be grouped together.
+    pub connection_id: u64,
+
+    /// The Unix domain socket address that the connection was made to.
+    pub server_address: String,
+
+    /// When the event happened, as an RFC 3339 timestamp.
+    pub time: String,
+
+    pub event: ConnectionEvent,
+}
+
+/// Somewhere to keep the connection log, e.g. the database.
+pub trait ConnectionLogStore: Send + Sync {
+    /// Records several entries at once. Implementations backed by a database should write them in a single
+    /// transaction.
+    fn record_connection_events(&self, entries: &[ConnectionLogEntry]) -> anyhow::Result<()>;
+
+    /// Entries recorded at or after `since`, oldest first.
+    fn list_connection_events_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<ConnectionLogEntry>>;
+
+    /// Deletes up to `limit` of the oldest entries recorded before `cutoff`, returning how many were deleted.
+    fn delete_connection_events_before(
+        &self,
+        cutoff: DateTime<Utc>,
+        limit: u64,
+    ) -> anyhow::Result<u64>;
+}
+
+/// Log of transport-level activity on Keystache's NIP-46 servers, kept in a [`ConnectionLogStore`].
+///
+/// NIP-55 handles each request on its own socket and doesn't expose when they're accepted or closed,
+/// so a connection here spans the lifetime of a server: from when it starts accepting requests until it stops.
+pub struct ConnectionLog {
+    store: Arc<dyn ConnectionLogStore>,
+
+    /// Entries that couldn't be written to the store yet, oldest first. The NIP-70 server starts before the vault
+    /// is unlocked, so its first entries are held here until the database is open.
+    pending_entries: Arc<Mutex<VecDeque<ConnectionLogEntry>>>,
+
+    /// Entries are sent here rather than written directly, so that logging never blocks the request path.
+    sender: mpsc::UnboundedSender<ConnectionLogEntry>,
+
+    next_connection_id: AtomicU64,
+}
+
+impl ConnectionLog {
+    /// **MUST** be called from within a tokio runtime.
+    pub fn new(store: Arc<dyn ConnectionLogStore>) -> Self {
+        let pending_entries = Arc::new(Mutex::new(VecDeque::new()));
+        let (sender, mut receiver) = mpsc::unbounded_channel::<ConnectionLogEntry>();
+
+        let task_store = store.clone();
+        let task_pending_entries = pending_entries.clone();
+        tokio::spawn(async move {
+            while let Some(entry) = receiver.recv().await {
+                let mut pending_entries = task_pending_entries.lock().unwrap();
+                if pending_entries.len() == MAX_PENDING_ENTRIES {
+                    pending_entries.pop_front();
+                }
+                pending_entries.push_back(entry);
+                // Entries that fail to be written are retried along with the next one.
+                if task_store
+                    .record_connection_events(pending_entries.make_contiguous())
+                    .is_ok()
+                {
+                    pending_entries.clear();
+                }
+            }
+        });
+
+        Self {
+            store,
+            pending_entries,
+            sender,
+            // Seeded with the current time, so that connections logged after a restart don't reuse the IDs of
+            // stored ones, without reading the database, which might not be open yet.
+            next_connection_id: AtomicU64::new(Utc::now().timestamp_micros().max(0) as u64),
+        }
+    }
+
+    /// Logs a new connection to the server at `server_address`. The connection is logged as
+    /// disconnected when the returned logger is dropped.
+    pub fn connect(&self, server_address: &str) -> ConnectionLogger {
+        let logger = ConnectionLogger {
+            connection_id: self.next_connection_id.fetch_add(1, Ordering::Relaxed),
+            server_address: server_address.to_string(),
+            sender: self.sender.clone(),
+            request_counts: Mutex::new(BTreeMap::new()),
+        };
+        logger.log(ConnectionEvent::Connected);
+        logger
+    }
+
+    /// Entries logged at or after `since`, oldest first, including any that haven't been written to the store yet.
+    pub fn entries_since(&self, since: DateTime<Utc>) -> anyhow::Result<Vec<ConnectionLogEntry>> {
+        // Lock the pending entries first, so that none are written to the store between reading the two.
+        let pending_entries = self.pending_entries.lock().unwrap();
+        let mut entries = self.store.list_connection_events_since(since)?;
+        entries.extend(
+            pending_entries
+                .iter()
+                .filter(|entry| {
+                    DateTime::parse_from_rfc3339(&entry.time)
+                        .map(|time| time >= since)
+                        .unwrap_or(false)
+                })
+                .cloned(),
+        );
+        Ok(entries)
+    }
+}
+
+impl PrunableLog for ConnectionLog {
+    fn delete_entries_before(&self, cutoff: DateTime<Utc>, limit: u64) -> anyhow::Result<u64> {
+        let deleted = self.store.delete_connection_events_before(cutoff, limit)?;
+
+        let mut pending_entries = self.pending_entries.lock().unwrap();
+        let mut deleted_pending = 0;
+        // Entries are logged in order, so the old ones are all at the front.
+        while deleted + deleted_pending < limit
+            && pending_entries.front().is_some_and(|entry| {
+                DateTime::parse_from_rfc3339(&entry.time)
+                    .map(|time| time < cutoff)
+                    .unwrap_or(true)
+            })
+        {
+            pending_entries.pop_front();
+            deleted_pending += 1;
+        }
+        Ok(deleted + deleted_pending)
+    }
+}
+
+/// Logs the activity of a single connection.
+pub struct ConnectionLogger {
+    connection_id: u64,
+    server_address: String,
+    sender: mpsc::UnboundedSender<ConnectionLogEntry>,
+
+    /// Number of requests received for each method, reported when the connection closes.
+    request_counts: Mutex<BTreeMap<String, u64>>,
+}
+
+impl ConnectionLogger {
+    pub fn request(&self, method: &str) {
+        *self
+            .request_counts
+            .lock()
+            .unwrap()
+            .entry(method.to_string())
+            .or_default() += 1;
+        self.log(ConnectionEvent::Request {
+            method: method.to_string(),
+        });
+    }
+
+    pub fn error(&self, message: impl Into<String>) {
+        self.log(ConnectionEvent::Error {
+            message: message.into(),
+        });
+    }
+
+    fn log(&self, event: ConnectionEvent) {
+        // The receiver only goes away if the runtime is shutting down, in which case nobody will read the log.
+        let _ = self.sender.send(ConnectionLogEntry {
+            connection_id: self.connection_id,
+            server_address: self.server_address.clone(),
+            time: Utc::now().to_rfc3339(),
+            event,
+        });
+    }
+}
+
+impl Drop for ConnectionLogger {
+    fn drop(&mut self) {
+        let request_counts = std::mem::take(&mut *self.request_counts.lock().unwrap());
+        self.log(ConnectionEvent::Disconnected { request_counts });
+    }
+}
+
+/// Wraps a request handler so that every request it receives is logged to a connection's log.
+pub struct LoggingRequestHandler {
+    inner: Arc<dyn Nip46RequestHandler>,
+    logger: ConnectionLogger,
+}
+
+impl LoggingRequestHandler {
+    pub fn new(inner: Arc<dyn Nip46RequestHandler>, logger: ConnectionLogger) -> Self {
+        Self { inner, logger }
+    }
+
+    pub fn logger(&self) -> &ConnectionLogger {
+        &self.logger
+    }
+}
+
+#[async_trait]
+impl Nip46RequestHandler for LoggingRequestHandler {
+    fn allows_app(&self, app_public_key: &PublicKey) -> bool {
+        let allowed = self.inner.allows_app(app_public_key);
+        if !allowed {
+            self.logger.error(format!(
+                "Refused connection from app {}",
+                app_public_key.to_hex()
+            ));
+        }
+        allowed
+    }
+
+    fn signer_for(
+        &self,
+        user_public_key: &PublicKey,
+        key_manager: &dyn KeyManager,
+    ) -> Option<Arc<dyn Signer>> {
+        self.inner.signer_for(user_public_key, key_manager)
+    }
+
+    async fn handle_request(
+        &self,
+        request: nip46::Request,
+        user_public_key: PublicKey,
+        app_public_key: PublicKey,
+        key_manager: &dyn KeyManager,
+    ) -> Result<nip46::ResponseResult, String> {
+        self.logger.request(&request.method().to_string());
+        self.inner
+            .handle_request(request, user_public_key, app_public_key, key_manager)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::key_manager::KeystacheKeyManager;
+    use crate::nip46_server::AutoSigningHandler;
+    use crate::test_support::SingleKeyManager;
+    use nostr_sdk::{EventBuilder, Keys, Kind};
+
+    /// Fails to record entries until `available` is set, like a vault that's still locked.
+    #[derive(Default)]
+    struct UnavailableStore {
+        available: std::sync::atomic::AtomicBool,
+        entries: Mutex<Vec<ConnectionLogEntry>>,
+    }
+
+    impl ConnectionLogStore for UnavailableStore {
+        fn record_connection_events(&self, entries: &[ConnectionLogEntry]) -> anyhow::Result<()> {
+            if !self.available.load(Ordering::Relaxed) {
+                return Err(anyhow::anyhow!("No database available"));
+            }
+            self.entries.lock().unwrap().extend_from_slice(entries);
+            Ok(())
+        }
+
+        fn list_connection_events_since(
+            &self,
+            _since: DateTime<Utc>,
+        ) -> anyhow::Result<Vec<ConnectionLogEntry>> {
+            Ok(self.entries.lock().unwrap().clone())
+        }
+
+        fn delete_connection_events_before(
+            &self,
+            _cutoff: DateTime<Utc>,
+            _limit: u64,
+        ) -> anyhow::Result<u64> {
+            Ok(0)
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_connection_lifecycle() {
+        let connection_log = ConnectionLog::new(Arc::new(KeystacheKeyManager::new_with_database(
+            Database::new_in_temp_dir(),
+        )));
+        let start = Utc::now();
+
+        let handler = LoggingRequestHandler::new(
+            Arc::new(AutoSigningHandler),
+            connection_log.connect("/tmp/test.sock"),
+        );
+        let key_manager = SingleKeyManager {
+            keys: Keys::generate(),
+        };
+        let public_key = key_manager.keys.public_key();
+        let app_public_key = Keys::generate().public_key();
+        let event = EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(public_key);
+        for (request, succeeds) in [
+            (nip46::Request::SignEvent(event.clone()), true),
+            (nip46::Request::SignEvent(event), true),
+            (nip46::Request::GetPublicKey, false),
+        ] {
+            assert_eq!(
+                handler
+                    .handle_request(request, public_key, app_public_key, &key_manager)
+                    .await
+                    .is_ok(),
+                succeeds
+            );
+        }
+        handler.logger().error("health check failed");
+        drop(handler);
+
+        // Let the log catch up with the entries sent to it.
+        tokio::task::yield_now().await;
+
+        let entries = connection_log.entries_since(start).unwrap();
+        let events: Vec<ConnectionEvent> = entries
+            .iter()
+            .map(|entry| {
+                assert_eq!(entry.connection_id, entries[0].connection_id);
+                assert_eq!(entry.server_address, "/tmp/test.sock");
+                entry.event.clone()
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                ConnectionEvent::Connected,
+                ConnectionEvent::Request {
+                    method: "sign_event".to_string()
+                },
+                ConnectionEvent::Request {
+                    method: "sign_event".to_string()
+                },
+                ConnectionEvent::Request {
+                    method: "get_public_key".to_string()
+                },
+                ConnectionEvent::Error {
+                    message: "health check failed".to_string()
+                },
+                ConnectionEvent::Disconnected {
+                    request_counts: BTreeMap::from([
+                        ("get_public_key".to_string(), 1),
+                        ("sign_event".to_string(), 2),
+                    ])
+                },
+            ]
+        );
+
+        assert!(connection_log.entries_since(Utc::now()).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn entries_survive_reopening_the_database() {
+        let folder = tempfile::TempDir::new().unwrap();
+        let connection_log = ConnectionLog::new(Arc::new(KeystacheKeyManager::new_with_database(
+            Database::new_in_dir(folder.path()),
+        )));
+        let logger = connection_log.connect("/tmp/test.sock");
+        logger.request("sign_event");
+        drop(logger);
+        tokio::task::yield_now().await;
+        let entries = connection_log
+            .entries_since(DateTime::<Utc>::MIN_UTC)
+            .unwrap();
+        assert_eq!(entries.len(), 3);
+        drop(connection_log);
+
+        let connection_log = ConnectionLog::new(Arc::new(KeystacheKeyManager::new_with_database(
+            Database::new_in_dir(folder.path()),
+        )));
+        assert_eq!(
+            connection_log
+                .entries_since(DateTime::<Utc>::MIN_UTC)
+                .unwrap(),
+            entries
+        );
+
+        // Connections logged after reopening don't reuse the IDs of stored ones.
+        drop(connection_log.connect("/tmp/test.sock"));
+        tokio::task::yield_now().await;
+        let reopened_entries = connection_log
+            .entries_since(DateTime::<Utc>::MIN_UTC)
+            .unwrap();
+        assert_eq!(reopened_entries.len(), 5);
+        assert_ne!(reopened_entries[3].connection_id, entries[0].connection_id);
+    }
+
+    #[tokio::test]
+    async fn entries_are_held_until_the_store_is_available() {
+        let store = Arc::new(UnavailableStore::default());
+        let connection_log = ConnectionLog::new(store.clone());
+
+        drop(connection_log.connect("/tmp/test.sock"));
+        tokio::task::yield_now().await;
+        assert!(store.entries.lock().unwrap().is_empty());
+        assert_eq!(
+            connection_log
+                .entries_since(DateTime::<Utc>::MIN_UTC)
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // The held entries are written along with the next one.
+        store.available.store(true, Ordering::Relaxed);
+        drop(connection_log.connect("/tmp/test.sock"));
+        tokio::task::yield_now().await;
+        assert_eq!(store.entries.lock().unwrap().len(), 4);
+        assert_eq!(
+            connection_log
+                .entries_since(DateTime::<Utc>::MIN_UTC)
+                .unwrap()
+                .len(),
+            4
+        );
+    }
+}
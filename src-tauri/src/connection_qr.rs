@@ -0,0 +1,37 @@
+use nostr_sdk::{PublicKey, ToBech32};
+use serde::Serialize;
+
+/// Canonical strings for the frontend to render as QR codes, so that other clients can connect to an account.
+///
+/// There's no `bunker://` URI, since Keystache only serves NIP-46 over NIP-55 and doesn't listen on relays.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ConnectionQrPayload {
+    /// The account's npub.
+    pub npub: String,
+}
+
+impl ConnectionQrPayload {
+    pub fn new(public_key: PublicKey) -> anyhow::Result<Self> {
+        Ok(Self {
+            npub: public_key.to_bech32()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{FromBech32, Keys};
+
+    #[test]
+    fn payload_has_valid_npub() {
+        let public_key = Keys::generate().public_key();
+
+        let payload = ConnectionQrPayload::new(public_key).unwrap();
+
+        assert_eq!(
+            PublicKey::from_bech32(payload.npub.as_str()).unwrap(),
+            public_key
+        );
+    }
+}
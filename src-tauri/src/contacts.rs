@@ -0,0 +1,223 @@
+use crate::relays::RelayPublishResult;
+use crate::validation;
+use nostr_sdk::{Event, EventBuilder, Kind, PublicKey, Tag, ToBech32, UncheckedUrl, UnsignedEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// An account that another account follows, from a `p` tag of its NIP-02 follow list.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contact {
+    /// The followed account's `npub`. Contacts passed in to be saved may also use its hex public key.
+    pub npub: String,
+
+    /// Relay that the followed account can be found on, if the follow list names one.
+    pub relay_url: Option<String>,
+
+    /// Local name that the follower gave the followed account, if any.
+    pub petname: Option<String>,
+}
+
+/// Response from updating an account's follow list.
+#[derive(Clone, Debug, Serialize)]
+pub struct UpdateFollowListResponse {
+    /// The signed kind-3 event.
+    pub event: Event,
+
+    /// How each relay responded to the event being published.
+    /// Empty if the event wasn't published.
+    pub publish_results: Vec<RelayPublishResult>,
+}
+
+/// Parses a contact's public key, given either as an `npub` or as hex.
+fn parse_contact_public_key(key: &str) -> anyhow::Result<PublicKey> {
+    let key = key.trim();
+    if key.len() == 64 && key.chars().all(|c| c.is_ascii_hexdigit()) {
+        return PublicKey::from_hex(key).map_err(|_| anyhow::anyhow!("Invalid public key {key}"));
+    }
+    validation::validate_npub(key).map_err(|err| anyhow::anyhow!("Invalid contact {key}: {err}"))
+}
+
+/// Builds an unsigned kind-3 follow list for `public_key` with a `p` tag for each contact, keeping any relay
+/// hints and petnames. Fails if any contact's public key is invalid.
+pub fn build_follow_list_event(
+    contacts: &[Contact],
+    public_key: PublicKey,
+) -> anyhow::Result<UnsignedEvent> {
+    let tags = contacts
+        .iter()
+        .map(|contact| {
+            // The relay hint comes before the petname, so it has to be there (if empty) for the petname to be.
+            let relay_url = match (&contact.relay_url, &contact.petname) {
+                (Some(relay_url), _) => Some(UncheckedUrl::from(relay_url)),
+                (None, Some(_)) => Some(UncheckedUrl::empty()),
+                (None, None) => None,
+            };
+            Ok(Tag::PublicKey {
+                public_key: parse_contact_public_key(&contact.npub)?,
+                relay_url,
+                alias: contact.petname.clone(),
+                uppercase: false,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(EventBuilder::new(Kind::ContactList, "", tags).to_unsigned_event(public_key))
+}
+
+/// Parses the `p` tags of a kind-3 follow list into contacts, in the order they're listed.
+/// Tags with an invalid pubkey are skipped, and only the first tag for each pubkey is kept.
+pub fn parse_follow_list(event: &Event) -> anyhow::Result<Vec<Contact>> {
+    if event.kind != Kind::ContactList {
+        return Err(anyhow::anyhow!(
+            "Expected a kind-3 follow list, got a kind-{} event",
+            event.kind.as_u64()
+        ));
+    }
+
+    let non_empty = |value: Option<&String>| value.filter(|value| !value.is_empty()).cloned();
+
+    let mut seen = HashSet::new();
+    let mut contacts = Vec::new();
+    for tag in &event.tags {
+        let values = tag.as_vec();
+        if values.first().map(String::as_str) != Some("p") {
+            continue;
+        }
+        let Some(public_key) = values.get(1).and_then(|hex| PublicKey::from_hex(hex).ok()) else {
+            continue;
+        };
+        if !seen.insert(public_key) {
+            continue;
+        }
+        contacts.push(Contact {
+            npub: public_key.to_bech32()?,
+            relay_url: non_empty(values.get(2)),
+            petname: non_empty(values.get(3)),
+        });
+    }
+    Ok(contacts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::Keys;
+
+    #[test]
+    fn parses_follow_list_with_petnames() {
+        let alice = Keys::generate().public_key();
+        let bob = Keys::generate().public_key();
+        let carol = Keys::generate().public_key();
+        let tags = [
+            vec![
+                "p".to_string(),
+                alice.to_hex(),
+                "wss://alice.example.com".to_string(),
+                "alice".to_string(),
+            ],
+            vec![
+                "p".to_string(),
+                bob.to_hex(),
+                String::new(),
+                "bob".to_string(),
+            ],
+            vec!["p".to_string(), carol.to_hex()],
+            vec!["p".to_string(), "not a pubkey".to_string()],
+            vec![
+                "p".to_string(),
+                alice.to_hex(),
+                String::new(),
+                "again".to_string(),
+            ],
+            vec!["t".to_string(), "nostr".to_string()],
+        ]
+        .into_iter()
+        .map(|tag| Tag::parse(&tag).unwrap());
+        let event = EventBuilder::new(Kind::ContactList, "", tags)
+            .to_event(&Keys::generate())
+            .unwrap();
+
+        assert_eq!(
+            parse_follow_list(&event).unwrap(),
+            vec![
+                Contact {
+                    npub: alice.to_bech32().unwrap(),
+                    relay_url: Some("wss://alice.example.com".to_string()),
+                    petname: Some("alice".to_string()),
+                },
+                Contact {
+                    npub: bob.to_bech32().unwrap(),
+                    relay_url: None,
+                    petname: Some("bob".to_string()),
+                },
+                Contact {
+                    npub: carol.to_bech32().unwrap(),
+                    relay_url: None,
+                    petname: None,
+                },
+            ]
+        );
+
+        // Other kinds of event aren't follow lists.
+        let note = EventBuilder::new(Kind::TextNote, "hello", None)
+            .to_event(&Keys::generate())
+            .unwrap();
+        assert!(parse_follow_list(&note).is_err());
+    }
+
+    #[test]
+    fn builds_follow_list_from_contacts() {
+        let keys = Keys::generate();
+        let alice = Keys::generate().public_key();
+        let bob = Keys::generate().public_key();
+        let contacts = vec![
+            Contact {
+                npub: alice.to_bech32().unwrap(),
+                relay_url: Some("wss://alice.example.com".to_string()),
+                petname: Some("alice".to_string()),
+            },
+            // Contacts can also be given by hex public key.
+            Contact {
+                npub: bob.to_hex(),
+                relay_url: None,
+                petname: Some("bob".to_string()),
+            },
+        ];
+
+        let event = build_follow_list_event(&contacts, keys.public_key()).unwrap();
+
+        assert_eq!(event.kind, Kind::ContactList);
+        assert_eq!(event.pubkey, keys.public_key());
+        assert_eq!(
+            event.tags.iter().map(Tag::as_vec).collect::<Vec<_>>(),
+            vec![
+                vec![
+                    "p".to_string(),
+                    alice.to_hex(),
+                    "wss://alice.example.com".to_string(),
+                    "alice".to_string(),
+                ],
+                vec![
+                    "p".to_string(),
+                    bob.to_hex(),
+                    String::new(),
+                    "bob".to_string(),
+                ],
+            ]
+        );
+
+        // Signing and parsing the follow list gives back the contacts, with every key as an npub.
+        let mut expected = contacts;
+        expected[1].npub = bob.to_bech32().unwrap();
+        assert_eq!(
+            parse_follow_list(&event.sign(&keys).unwrap()).unwrap(),
+            expected
+        );
+
+        let invalid = Contact {
+            npub: "npub1invalid".to_string(),
+            relay_url: None,
+            petname: None,
+        };
+        assert!(build_follow_list_event(&[invalid], keys.public_key()).is_err());
+    }
+}
@@ -1,7 +1,16 @@
-use chrono::Utc;
+use crate::account_stats::AccountStats;
+use crate::connection_log::ConnectionLogEntry;
+use crate::contacts::Contact;
+use crate::payment_log::{PaymentHistoryFilter, PaymentLogEntry};
+use crate::relays::RelayPolicy;
+use crate::sign_decisions::RememberedSignDecision;
+use crate::signing_log::SigningLogEntry;
+use chrono::{DateTime, Utc};
 use nostr_sdk::secp256k1::{Keypair, Secp256k1};
-use nostr_sdk::{FromBech32, PublicKey, SecretKey, ToBech32};
-use rusqlite::{params, Connection};
+use nostr_sdk::{Event, FromBech32, JsonUtil, Kind, PublicKey, SecretKey, ToBech32};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
@@ -9,33 +18,125 @@ const DATABASE_NAME: &str = "keystache.db";
 
 // TODO: Handle database migrations.
 
+/// Result of checking the stored keys for corruption. Doesn't include any of the keys themselves.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct VaultIntegrityReport {
+    /// Number of stored keys that are intact.
+    pub valid_entries: usize,
+
+    /// Number of stored keys that are corrupt, i.e. whose nsec doesn't decode or doesn't match the stored npub.
+    pub corrupt_entries: usize,
+
+    /// Number of database pages whose HMAC doesn't match. Always zero if the database isn't encrypted.
+    pub corrupt_pages: usize,
+}
+
+/// Differences between the database's schema and the one this version of Keystache expects.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DatabaseDiagnosis {
+    /// Tables that don't exist.
+    pub missing_tables: Vec<String>,
+
+    /// Columns that don't exist, as `table.column`. Doesn't include the columns of missing tables.
+    pub missing_columns: Vec<String>,
+
+    /// Indexes that don't exist.
+    pub missing_indexes: Vec<String>,
+
+    /// Missing tables and indexes that were re-created. Missing columns are never repaired.
+    pub repaired: Vec<String>,
+}
+
+impl DatabaseDiagnosis {
+    /// Whether the schema is as expected.
+    pub fn is_healthy(&self) -> bool {
+        self.missing_tables.is_empty()
+            && self.missing_columns.is_empty()
+            && self.missing_indexes.is_empty()
+    }
+}
+
+/// Details about an account besides its key.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct AccountMetadata {
+    /// Name the user has given the account.
+    pub label: Option<String>,
+
+    /// When the account was retired by rotating to a new key, or `None` if it's still in use.
+    pub retire_time: Option<String>,
+
+    /// npub of the account that replaced this one, if it has been retired.
+    pub successor_npub: Option<String>,
+
+    /// The NIP-06 account index the key was derived at, if it was derived from a mnemonic.
+    pub derivation_index: Option<u32>,
+}
+
+/// Returned when a write that spans several rows fails partway through. Every change made by the write is
+/// rolled back, so the database is left as it was before.
+#[derive(Debug)]
+pub struct DbError {
+    reason: String,
+}
+
+impl std::fmt::Display for DbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Database write rolled back: {}", self.reason)
+    }
+}
+
+impl std::error::Error for DbError {}
+
 /// Database handle for Keystache data.
 #[derive(Clone)]
 pub struct Database {
     db_connection: Arc<Mutex<Connection>>,
+
+    /// Path of the database file.
+    path: Arc<Path>,
+
+    /// The key the database was unlocked with, or `None` if the database is not encrypted.
+    /// Set when a plaintext database is encrypted with [`Self::encrypt`].
+    encryption_key_or: Arc<Mutex<Option<Arc<str>>>>,
 }
 
 impl Database {
-    /// Creates a new database handle in the app's data directory.
-    /// If an existing database is found, it will be opened.
-    /// If the database does not exist, it will be created.
+    /// Creates a new unencrypted database in a temporary folder.
+    #[cfg(test)]
+    pub fn new_in_temp_dir() -> Self {
+        let folder = tempfile::TempDir::new()
+            .expect("Failed to create temporary directory")
+            .path()
+            .to_path_buf();
+        Self::new(&folder, DATABASE_NAME, None).unwrap()
+    }
+
+    /// Creates a new database in a temporary folder, encrypted with `encryption_key`.
+    #[cfg(test)]
+    pub fn new_encrypted_in_temp_dir(encryption_key: &str) -> Self {
+        let folder = tempfile::TempDir::new()
+            .expect("Failed to create temporary directory")
+            .path()
+            .to_path_buf();
+        Self::new(&folder, DATABASE_NAME, Some(encryption_key)).unwrap()
+    }
+
+    /// Opens (or creates) an unencrypted database in `folder`, e.g. to check that data survives reopening it.
+    #[cfg(test)]
+    pub fn new_in_dir(folder: &Path) -> Self {
+        Self::new(folder, DATABASE_NAME, None).unwrap()
+    }
+
+    /// Opens the database in `folder`, creating it (and the folder) if it doesn't exist.
     ///
     /// # Arguments
     ///
-    /// * `app_handle` - The Tauri application handle.
+    /// * `folder` - The folder the database is kept in, e.g. the app's data directory.
     /// * `encryption_key_or` - The encryption key for the database, or `None` if the database is not encrypted.
     ///                         If there is no existing database, the encryption key will be used to create a new encrypted database (if provided).
     ///                         If there is an existing database, the encryption key will be used to unlock the database (if provided) and an error will be returned if the key is incorrect.
-    pub fn new_in_app_data_dir(
-        app_handle: tauri::AppHandle,
-        encryption_key_or: Option<&str>,
-    ) -> anyhow::Result<Self> {
-        let data_dir = match app_handle.path_resolver().app_data_dir() {
-            Some(x) => x,
-            None => return Err(anyhow::anyhow!("App data dir not found")),
-        };
-
-        Self::new(&data_dir, DATABASE_NAME, encryption_key_or)
+    pub fn open_in_dir(folder: &Path, encryption_key_or: Option<&str>) -> anyhow::Result<Self> {
+        Self::new(folder, DATABASE_NAME, encryption_key_or)
     }
 
     fn new(
@@ -50,13 +151,30 @@ impl Database {
             std::fs::create_dir_all(folder)?;
         }
 
-        let db_connection = Connection::open(folder.join(file_name))?;
+        let path = folder.join(file_name);
+        let db_connection = Self::open_connection(&path, encryption_key_or)?;
+
+        Self::create_tables(&db_connection)?;
+
+        Ok(Database {
+            db_connection: Arc::from(Mutex::from(db_connection)),
+            path: Arc::from(path),
+            encryption_key_or: Arc::new(Mutex::new(encryption_key_or.map(Arc::from))),
+        })
+    }
+
+    fn open_connection(path: &Path, encryption_key_or: Option<&str>) -> anyhow::Result<Connection> {
+        let db_connection = Connection::open(path)?;
 
         if let Some(encryption_key) = encryption_key_or {
             // Unlock the database with the encryption key.
             db_connection.pragma_update(None, "key", encryption_key)?;
         }
 
+        Ok(db_connection)
+    }
+
+    fn create_tables(db_connection: &Connection) -> anyhow::Result<()> {
         db_connection.execute(
             "CREATE TABLE IF NOT EXISTS keys (
                 id INTEGER PRIMARY KEY,
@@ -67,6 +185,15 @@ impl Database {
             [],
         )?;
 
+        db_connection.execute(
+            "CREATE TABLE IF NOT EXISTS watch_only_accounts (
+                id INTEGER PRIMARY KEY,
+                npub TEXT NOT NULL UNIQUE,
+                create_time TEXT NOT NULL
+            )",
+            [],
+        )?;
+
         db_connection.execute(
             "CREATE TABLE IF NOT EXISTS registered_applications (
                 id INTEGER PRIMARY KEY,
@@ -79,11 +206,422 @@ impl Database {
             [],
         )?;
 
-        Ok(Database {
-            db_connection: Arc::from(Mutex::from(db_connection)),
+        db_connection.execute(
+            "CREATE TABLE IF NOT EXISTS relays (
+                id INTEGER PRIMARY KEY,
+                url TEXT NOT NULL,
+                read INTEGER NOT NULL,
+                write INTEGER NOT NULL,
+                create_time TEXT NOT NULL,
+                key_id INTEGER NOT NULL,
+                FOREIGN KEY (key_id) REFERENCES keys(id) ON DELETE CASCADE,
+                UNIQUE (url, key_id)
+            )",
+            [],
+        )?;
+
+        // Relays are always looked up by account. The unique constraint's index can't be used for that, since
+        // `key_id` isn't its first column.
+        db_connection.execute(
+            "CREATE INDEX IF NOT EXISTS relays_key_id ON relays (key_id)",
+            [],
+        )?;
+
+        db_connection.execute(
+            "CREATE TABLE IF NOT EXISTS account_metadata (
+                key_id INTEGER PRIMARY KEY,
+                label TEXT,
+                retire_time TEXT,
+                successor_npub TEXT,
+                FOREIGN KEY (key_id) REFERENCES keys(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        db_connection.execute(
+            "CREATE TABLE IF NOT EXISTS seed_derivations (
+                key_id INTEGER PRIMARY KEY,
+                account_index INTEGER NOT NULL,
+                FOREIGN KEY (key_id) REFERENCES keys(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        db_connection.execute(
+            "CREATE TABLE IF NOT EXISTS account_stats (
+                key_id INTEGER PRIMARY KEY,
+                sign_count INTEGER NOT NULL DEFAULT 0,
+                payment_count INTEGER NOT NULL DEFAULT 0,
+                last_active_time TEXT,
+                FOREIGN KEY (key_id) REFERENCES keys(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        db_connection.execute(
+            "CREATE TABLE IF NOT EXISTS payment_log (
+                id INTEGER PRIMARY KEY,
+                invoice TEXT NOT NULL,
+                amount_msats INTEGER,
+                description TEXT,
+                app_npub TEXT,
+                create_time TEXT NOT NULL,
+                outcome TEXT NOT NULL,
+                preimage TEXT
+            )",
+            [],
+        )?;
+
+        // The payment log can be filtered by app.
+        db_connection.execute(
+            "CREATE INDEX IF NOT EXISTS payment_log_app_npub ON payment_log (app_npub)",
+            [],
+        )?;
+
+        db_connection.execute(
+            "CREATE TABLE IF NOT EXISTS signing_log (
+                id INTEGER PRIMARY KEY,
+                app_npub TEXT NOT NULL,
+                kind INTEGER NOT NULL,
+                create_time TEXT NOT NULL,
+                outcome TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Each app's signing history is listed on its own.
+        db_connection.execute(
+            "CREATE INDEX IF NOT EXISTS signing_log_app_npub ON signing_log (app_npub)",
+            [],
+        )?;
+
+        db_connection.execute(
+            "CREATE TABLE IF NOT EXISTS connection_log (
+                id INTEGER PRIMARY KEY,
+                connection_id INTEGER NOT NULL,
+                server_address TEXT NOT NULL,
+                create_time TEXT NOT NULL,
+                event TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        db_connection.execute(
+            "CREATE TABLE IF NOT EXISTS sign_decisions (
+                application_npub TEXT NOT NULL,
+                kind INTEGER NOT NULL,
+                approved INTEGER NOT NULL,
+                PRIMARY KEY (application_npub, kind)
+            )",
+            [],
+        )?;
+
+        db_connection.execute(
+            "CREATE TABLE IF NOT EXISTS contacts (
+                key_id INTEGER NOT NULL,
+                npub TEXT NOT NULL,
+                relay_url TEXT,
+                petname TEXT,
+                PRIMARY KEY (key_id, npub),
+                FOREIGN KEY (key_id) REFERENCES keys(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        db_connection.execute(
+            "CREATE TABLE IF NOT EXISTS cached_events (
+                npub TEXT NOT NULL,
+                kind INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                event TEXT NOT NULL,
+                PRIMARY KEY (npub, kind)
+            )",
+            [],
+        )?;
+
+        db_connection.execute(
+            "CREATE TABLE IF NOT EXISTS settings (
+                name TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        Ok(())
+    }
+
+    /// Whether `passphrase` is the key that the database was unlocked with.
+    /// Always `false` if the database is not encrypted.
+    pub fn verify_encryption_key(&self, passphrase: &str) -> bool {
+        match &*self.encryption_key_or.lock().unwrap() {
+            // Compare every byte so that how long the check takes doesn't reveal how much of the passphrase was right.
+            Some(encryption_key) => {
+                encryption_key.len() == passphrase.len()
+                    && encryption_key
+                        .bytes()
+                        .zip(passphrase.bytes())
+                        .fold(0, |diff, (a, b)| diff | (a ^ b))
+                        == 0
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption_key_or.lock().unwrap().is_some()
+    }
+
+    /// Encrypts a plaintext database with `passphrase`. From then on the database can only be opened with the
+    /// passphrase. Every table is copied into a new encrypted file, which only replaces the plaintext file once the
+    /// copy is complete, so a failure part way through leaves the plaintext database as it was.
+    /// Errors if the database is already encrypted.
+    pub fn encrypt(&self, passphrase: &str) -> anyhow::Result<()> {
+        if passphrase.is_empty() {
+            return Err(anyhow::anyhow!("Passphrase must not be empty"));
+        }
+
+        let mut db_connection = self.db_connection.lock().unwrap();
+        let mut encryption_key_or = self.encryption_key_or.lock().unwrap();
+        if encryption_key_or.is_some() {
+            return Err(anyhow::anyhow!("Database is already encrypted"));
+        }
+
+        let mut encrypted_path = self.path.as_os_str().to_owned();
+        encrypted_path.push(".encrypting");
+        let encrypted_path = std::path::PathBuf::from(encrypted_path);
+        // Left behind if an earlier attempt was interrupted.
+        if encrypted_path.try_exists()? {
+            std::fs::remove_file(&encrypted_path)?;
+        }
+
+        let exported = Self::export_encrypted(&db_connection, &encrypted_path, passphrase);
+        if let Err(err) = exported {
+            let _ = std::fs::remove_file(&encrypted_path);
+            return Err(err);
+        }
+
+        // The plaintext file must be closed before it can be replaced on every platform.
+        drop(std::mem::replace(
+            &mut *db_connection,
+            Connection::open_in_memory()?,
+        ));
+        if let Err(err) = std::fs::rename(&encrypted_path, &self.path) {
+            *db_connection = Self::open_connection(&self.path, None)?;
+            let _ = std::fs::remove_file(&encrypted_path);
+            return Err(err.into());
+        }
+
+        *db_connection = Self::open_connection(&self.path, Some(passphrase))?;
+        *encryption_key_or = Some(Arc::from(passphrase));
+        Ok(())
+    }
+
+    /// Copies every table into a new database at `encrypted_path`, encrypted with `passphrase`.
+    fn export_encrypted(
+        db_connection: &Connection,
+        encrypted_path: &Path,
+        passphrase: &str,
+    ) -> anyhow::Result<()> {
+        let encrypted_path = encrypted_path
+            .to_str()
+            .ok_or(anyhow::anyhow!("Database path isn't valid UTF-8"))?;
+        db_connection.execute(
+            "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+            params![encrypted_path, passphrase],
+        )?;
+        let exported =
+            db_connection.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()));
+        db_connection.execute("DETACH DATABASE encrypted", [])?;
+        Ok(exported?)
+    }
+
+    /// Checks every stored key for corruption, and if the database is encrypted, the HMAC of every page.
+    /// A corrupt key doesn't stop the rest from being checked.
+    pub fn verify_integrity(&self) -> anyhow::Result<VaultIntegrityReport> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let corrupt_pages = if self.is_encrypted() {
+            // Returns one row per problem found, so no rows means every page is intact.
+            let mut stmt = db_connection.prepare("PRAGMA cipher_integrity_check")?;
+            let problems = stmt.query_map([], |_| Ok(()))?;
+            problems.count()
+        } else {
+            0
+        };
+
+        let mut stmt = db_connection.prepare("SELECT npub, nsec FROM keys")?;
+        let entries = stmt.query_map([], |row| {
+            Ok((row.get::<usize, String>(0)?, row.get::<usize, String>(1)?))
+        })?;
+
+        let secp = Secp256k1::new();
+        let mut valid_entries = 0;
+        let mut corrupt_entries = 0;
+        for entry in entries {
+            let is_valid = entry.ok().is_some_and(|(npub, nsec)| {
+                // The nsec's bech32 checksum catches most corruption, and the npub derived from it must match the stored one.
+                match (PublicKey::from_bech32(npub), SecretKey::from_bech32(nsec)) {
+                    (Ok(public_key), Ok(secret_key)) => {
+                        secret_key.keypair(&secp).x_only_public_key().0 == *public_key
+                    }
+                    _ => false,
+                }
+            });
+
+            if is_valid {
+                valid_entries += 1;
+            } else {
+                corrupt_entries += 1;
+            }
+        }
+
+        Ok(VaultIntegrityReport {
+            valid_entries,
+            corrupt_entries,
+            corrupt_pages,
         })
     }
 
+    /// Re-derives every stored npub from its nsec, and overwrites any stored npub that doesn't match.
+    /// Returns how many were fixed. Keys whose nsec doesn't decode are left alone, since there's nothing to derive from.
+    pub fn repair_npubs(&self) -> anyhow::Result<usize> {
+        let mut db_connection = self.db_connection.lock().unwrap();
+        let transaction = db_connection.transaction()?;
+
+        let entries = {
+            let mut stmt = transaction
+                .prepare("SELECT id, npub, nsec FROM keys")
+                .map_err(|err| anyhow::anyhow!("Error reading stored keys: {err}"))?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<usize, i64>(0)?,
+                    row.get::<usize, String>(1)?,
+                    row.get::<usize, String>(2)?,
+                ))
+            })?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+
+        let secp = Secp256k1::new();
+        let mut fixed = 0;
+        for (id, npub, nsec) in entries {
+            let Ok(secret_key) = SecretKey::from_bech32(nsec) else {
+                continue;
+            };
+            let public_key: PublicKey = secret_key.keypair(&secp).x_only_public_key().0.into();
+            let derived_npub = public_key.to_bech32()?;
+            if derived_npub != npub {
+                transaction.execute(
+                    "UPDATE keys SET npub = ?1 WHERE id = ?2",
+                    params![derived_npub, id],
+                )?;
+                fixed += 1;
+            }
+        }
+
+        transaction.commit()?;
+        Ok(fixed)
+    }
+
+    /// Erases all data, leaving an empty database that is still encrypted with the same key (if any).
+    /// Deleted content is overwritten rather than just unlinked, so it can't be recovered from the database file.
+    pub fn wipe(&self) -> anyhow::Result<()> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        db_connection.pragma_update(None, "secure_delete", true)?;
+        db_connection.execute_batch(
+            "BEGIN;
+            DROP TABLE IF EXISTS relays;
+            DROP TABLE IF EXISTS contacts;
+            DROP TABLE IF EXISTS account_metadata;
+            DROP TABLE IF EXISTS seed_derivations;
+            DROP TABLE IF EXISTS account_stats;
+            DROP TABLE IF EXISTS registered_applications;
+            DROP TABLE IF EXISTS keys;
+            DROP TABLE IF EXISTS watch_only_accounts;
+            DROP TABLE IF EXISTS settings;
+            DROP TABLE IF EXISTS payment_log;
+            DROP TABLE IF EXISTS signing_log;
+            DROP TABLE IF EXISTS connection_log;
+            DROP TABLE IF EXISTS sign_decisions;
+            DROP TABLE IF EXISTS cached_events;
+            COMMIT;",
+        )?;
+        // Rewrites the database file so that no freed pages are left behind.
+        db_connection.execute_batch("VACUUM;")?;
+
+        Self::create_tables(&db_connection)
+    }
+
+    /// Compares the database's tables, columns and indexes with the ones this version of Keystache creates, e.g. to
+    /// find out why a database that was restored from a backup or edited by hand is misbehaving. If `repair` is
+    /// `true`, missing tables and indexes are re-created. Nothing is ever altered or deleted, so missing columns are
+    /// only reported.
+    pub fn diagnose(&self, repair: bool) -> anyhow::Result<DatabaseDiagnosis> {
+        // The expected schema is whatever `create_tables` creates in an empty database.
+        let expected_connection = Connection::open_in_memory()?;
+        Self::create_tables(&expected_connection)?;
+
+        let db_connection = self.db_connection.lock().unwrap();
+        let mut diagnosis = DatabaseDiagnosis::default();
+
+        let tables = Self::schema_object_names(&db_connection, "table")?;
+        for table in Self::schema_object_names(&expected_connection, "table")? {
+            if !tables.contains(&table) {
+                diagnosis.missing_tables.push(table);
+                continue;
+            }
+            let columns = Self::column_names(&db_connection, &table)?;
+            for column in Self::column_names(&expected_connection, &table)? {
+                if !columns.contains(&column) {
+                    diagnosis.missing_columns.push(format!("{table}.{column}"));
+                }
+            }
+        }
+
+        let indexes = Self::schema_object_names(&db_connection, "index")?;
+        diagnosis.missing_indexes = Self::schema_object_names(&expected_connection, "index")?
+            .into_iter()
+            .filter(|index| !indexes.contains(index))
+            .collect();
+
+        if repair && !diagnosis.is_healthy() {
+            // Everything is created with `IF NOT EXISTS`, so this only adds what's missing.
+            Self::create_tables(&db_connection)?;
+            diagnosis.repaired = diagnosis
+                .missing_tables
+                .iter()
+                .chain(&diagnosis.missing_indexes)
+                .cloned()
+                .collect();
+        }
+
+        Ok(diagnosis)
+    }
+
+    /// Names of the tables or indexes (depending on `object_type`) in the schema, leaving out SQLite's own, such as
+    /// the indexes it creates for `UNIQUE` constraints.
+    fn schema_object_names(
+        db_connection: &Connection,
+        object_type: &str,
+    ) -> anyhow::Result<Vec<String>> {
+        let mut stmt = db_connection.prepare(
+            "SELECT name FROM sqlite_master WHERE type = ?1 AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        )?;
+        let names = stmt
+            .query_map([object_type], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(names)
+    }
+
+    fn column_names(db_connection: &Connection, table: &str) -> anyhow::Result<Vec<String>> {
+        let mut stmt = db_connection.prepare("SELECT name FROM pragma_table_info(?1)")?;
+        let names = stmt
+            .query_map([table], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(names)
+    }
+
     /// Saves a keypair to the database.
     pub fn save_keypair(&self, keypair: &Keypair) -> anyhow::Result<()> {
         let db_connection = self.db_connection.lock().unwrap();
@@ -103,6 +641,159 @@ impl Database {
         Ok(())
     }
 
+    /// Saves a keypair as a new account, along with its label and relays, in a single transaction.
+    /// If any part fails, nothing is saved and a [`DbError`] is returned.
+    pub fn create_account(
+        &self,
+        keypair: &Keypair,
+        label: Option<&str>,
+        relays: &[(String, RelayPolicy)],
+    ) -> anyhow::Result<()> {
+        let mut db_connection = self.db_connection.lock().unwrap();
+        Self::insert_account(&mut db_connection, keypair, label, relays).map_err(|err| {
+            DbError {
+                reason: err.to_string(),
+            }
+            .into()
+        })
+    }
+
+    fn insert_account(
+        db_connection: &mut Connection,
+        keypair: &Keypair,
+        label: Option<&str>,
+        relays: &[(String, RelayPolicy)],
+    ) -> anyhow::Result<()> {
+        // Returning early drops the transaction without committing it, which rolls back whatever was written.
+        let transaction = db_connection.transaction()?;
+
+        let public_key: PublicKey = keypair.x_only_public_key().0.into();
+        let secret_key: SecretKey = keypair.secret_key().into();
+        let now = Utc::now().to_rfc3339();
+
+        transaction.execute(
+            "INSERT INTO keys (npub, nsec, create_time) VALUES (?1, ?2, ?3)",
+            params![public_key.to_bech32()?, secret_key.to_bech32()?, now],
+        )?;
+        let key_id = transaction.last_insert_rowid();
+
+        if let Some(label) = label {
+            transaction.execute(
+                "INSERT INTO account_metadata (key_id, label) VALUES (?1, ?2)",
+                params![key_id, label],
+            )?;
+        }
+        for (url, policy) in relays {
+            transaction.execute(
+                "INSERT INTO relays (url, read, write, create_time, key_id) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![url, policy.read, policy.write, now, key_id],
+            )?;
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Saves a keypair that was derived from a mnemonic, along with the NIP-06 account index it was derived at.
+    pub fn save_derived_keypair(
+        &self,
+        keypair: &Keypair,
+        account_index: u32,
+    ) -> anyhow::Result<()> {
+        let mut db_connection = self.db_connection.lock().unwrap();
+        let transaction = db_connection.transaction()?;
+
+        let public_key: PublicKey = keypair.x_only_public_key().0.into();
+        let secret_key: SecretKey = keypair.secret_key().into();
+
+        transaction.execute(
+            "INSERT INTO keys (npub, nsec, create_time) VALUES (?1, ?2, ?3)",
+            params![
+                public_key.to_bech32()?,
+                secret_key.to_bech32()?,
+                Utc::now().to_rfc3339()
+            ],
+        )?;
+        transaction.execute(
+            "INSERT INTO seed_derivations (key_id, account_index) VALUES (?1, ?2)",
+            params![transaction.last_insert_rowid(), account_index],
+        )?;
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Replaces a keypair with a new one. The new keypair is saved with the old one's label and relays,
+    /// and the old keypair is kept but marked as retired, pointing at the new one.
+    pub fn rotate_keypair(
+        &self,
+        old_public_key: &PublicKey,
+        new_keypair: &Keypair,
+    ) -> anyhow::Result<()> {
+        let mut db_connection = self.db_connection.lock().unwrap();
+        let transaction = db_connection.transaction()?;
+
+        let old_npub = old_public_key.to_bech32()?;
+        let new_public_key: PublicKey = new_keypair.x_only_public_key().0.into();
+        let new_secret_key: SecretKey = new_keypair.secret_key().into();
+        let new_npub = new_public_key.to_bech32()?;
+        let now = Utc::now().to_rfc3339();
+
+        let old_key_id = transaction
+            .query_row(
+                "SELECT keys.id, account_metadata.retire_time FROM keys
+                LEFT JOIN account_metadata ON account_metadata.key_id = keys.id
+                WHERE npub = ?1",
+                params![old_npub],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<String>>(1)?)),
+            )
+            .optional()?;
+        let old_key_id = match old_key_id {
+            Some((_, Some(_))) => return Err(anyhow::anyhow!("Account is already retired")),
+            Some((old_key_id, None)) => old_key_id,
+            None => return Err(anyhow::anyhow!("Account {old_npub} not found")),
+        };
+
+        transaction.execute(
+            "INSERT INTO keys (npub, nsec, create_time) VALUES (?1, ?2, ?3)",
+            params![new_npub, new_secret_key.to_bech32()?, now],
+        )?;
+        let new_key_id = transaction.last_insert_rowid();
+
+        transaction.execute(
+            "INSERT INTO relays (url, read, write, create_time, key_id)
+            SELECT url, read, write, ?1, ?2 FROM relays WHERE key_id = ?3",
+            params![now, new_key_id, old_key_id],
+        )?;
+        transaction.execute(
+            "INSERT INTO account_metadata (key_id, label)
+            SELECT ?1, label FROM account_metadata WHERE key_id = ?2",
+            params![new_key_id, old_key_id],
+        )?;
+        transaction.execute(
+            "INSERT INTO account_metadata (key_id, retire_time, successor_npub) VALUES (?1, ?2, ?3)
+            ON CONFLICT(key_id) DO UPDATE SET retire_time = excluded.retire_time, successor_npub = excluded.successor_npub",
+            params![old_key_id, now, new_npub],
+        )?;
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Returns the nsec stored for `public_key` as it's stored, without decoding it, or `None` if there's no key
+    /// for it.
+    pub fn get_stored_nsec(&self, public_key: &PublicKey) -> anyhow::Result<Option<String>> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        Ok(db_connection
+            .query_row(
+                "SELECT nsec FROM keys WHERE npub = ?1",
+                params![public_key.to_bech32()?],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
     /// Removes a keypair from the database.
     /// If the keypair is associated with any registered applications, the
     /// caller must first unregister the applications or swap their
@@ -159,11 +850,54 @@ impl Database {
     }
 
     /// Returns the first keypair in the database, or `None` if there are no keypairs.
-    pub fn get_first_keypair(&self) -> anyhow::Result<Option<Keypair>> {
-        Ok(self.list_keypairs(1, 0)?.first().cloned())
-    }
+    /// Saves an npub as a watch-only account, which can be monitored but not signed with because there's no key
+    /// for it. Errors if there's already a key for the npub.
+    pub fn add_watch_only_account(&self, public_key: &PublicKey) -> anyhow::Result<()> {
+        let db_connection = self.db_connection.lock().unwrap();
+        let npub = public_key.to_bech32()?;
+
+        let has_key = db_connection
+            .query_row("SELECT 1 FROM keys WHERE npub = ?1", params![npub], |_| {
+                Ok(())
+            })
+            .optional()?
+            .is_some();
+        if has_key {
+            return Err(anyhow::anyhow!("Account {npub} already has a key"));
+        }
 
-    /// Returns the public key of the first keypair in the database, or `None` if there are no keypairs.
+        db_connection.execute(
+            "INSERT INTO watch_only_accounts (npub, create_time) VALUES (?1, ?2)",
+            params![npub, Utc::now().to_rfc3339()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Lists the npubs of watch-only accounts, in the order they were added. Accounts whose key has since been
+    /// added are left out, since they're no longer watch-only.
+    pub fn list_watch_only_public_keys(&self) -> anyhow::Result<Vec<PublicKey>> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let mut stmt = db_connection.prepare(
+            "SELECT npub FROM watch_only_accounts WHERE npub NOT IN (SELECT npub FROM keys) ORDER BY id ASC",
+        )?;
+
+        let npub_iter = stmt.query_map([], |row| row.get::<usize, String>(0))?;
+
+        let mut npubs = Vec::new();
+        for npub in npub_iter {
+            npubs.push(PublicKey::from_bech32(npub?)?);
+        }
+
+        Ok(npubs)
+    }
+
+    pub fn get_first_keypair(&self) -> anyhow::Result<Option<Keypair>> {
+        Ok(self.list_keypairs(1, 0)?.first().cloned())
+    }
+
+    /// Returns the public key of the first keypair in the database, or `None` if there are no keypairs.
     pub fn get_first_public_key(&self) -> anyhow::Result<Option<PublicKey>> {
         Ok(self.list_public_keys(1, 0)?.first().cloned())
     }
@@ -185,75 +919,749 @@ impl Database {
         Ok(())
     }
 
-    /// Removes a registered application from the database.
-    pub fn unregister_application(&self, application_npub: &PublicKey) -> anyhow::Result<()> {
+    /// Removes a registered application from the database, along with its remembered sign decisions.
+    pub fn unregister_application(&self, application_npub: &PublicKey) -> anyhow::Result<()> {
+        let mut db_connection = self.db_connection.lock().unwrap();
+        let transaction = db_connection.transaction()?;
+
+        transaction.execute(
+            "DELETE FROM registered_applications WHERE application_npub = ?1",
+            params![application_npub.to_bech32()?],
+        )?;
+        transaction.execute(
+            "DELETE FROM sign_decisions WHERE application_npub = ?1",
+            params![application_npub.to_bech32()?],
+        )?;
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Sets the name a registered application is shown with, or clears it if `display_name` is `None`.
+    pub fn set_application_display_name(
+        &self,
+        application_npub: &PublicKey,
+        display_name: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let updated_row_count = db_connection.execute(
+            "UPDATE registered_applications SET display_name = ?1 WHERE application_npub = ?2",
+            params![display_name, application_npub.to_bech32()?],
+        )?;
+
+        if updated_row_count == 0 {
+            return Err(anyhow::anyhow!(
+                "Application with application_npub {} not found",
+                application_npub.to_bech32()?
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Switches the keypair that a registered application is operating as.
+    pub fn swap_application_identity(
+        &self,
+        application_npub: &PublicKey,
+        new_application_identity: &PublicKey,
+    ) -> anyhow::Result<()> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let updated_row_count = db_connection.execute(
+            "UPDATE registered_applications SET application_identity = (SELECT id FROM keys WHERE npub = ?1) WHERE application_npub = ?2",
+            params![new_application_identity.to_bech32()?, application_npub.to_bech32()?],
+        )?;
+
+        if updated_row_count == 0 {
+            return Err(anyhow::anyhow!(
+                "Application with application_npub {} not found",
+                application_npub.to_bech32()?
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Lists registered applications in the database. Ordered by id in ascending order.
+    /// Use limit and offset parameters for pagination.
+    /// Returns whether the user approved signing events of `kind` for the application, or `None` if no
+    /// decision is remembered.
+    pub fn get_sign_decision(
+        &self,
+        application_npub: &PublicKey,
+        kind: Kind,
+    ) -> anyhow::Result<Option<bool>> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        Ok(db_connection
+            .query_row(
+                "SELECT approved FROM sign_decisions WHERE application_npub = ?1 AND kind = ?2",
+                params![application_npub.to_bech32()?, kind.as_u64()],
+                |row| row.get::<_, bool>(0),
+            )
+            .optional()?)
+    }
+
+    /// Remembers whether the user approved signing events of `kind` for the application, replacing any earlier decision.
+    pub fn set_sign_decision(
+        &self,
+        application_npub: &PublicKey,
+        kind: Kind,
+        approved: bool,
+    ) -> anyhow::Result<()> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        db_connection.execute(
+            "INSERT INTO sign_decisions (application_npub, kind, approved) VALUES (?1, ?2, ?3)
+            ON CONFLICT(application_npub, kind) DO UPDATE SET approved = excluded.approved",
+            params![application_npub.to_bech32()?, kind.as_u64(), approved],
+        )?;
+
+        Ok(())
+    }
+
+    /// Forgets the remembered decision for the application and kind. Does nothing if there isn't one.
+    pub fn remove_sign_decision(
+        &self,
+        application_npub: &PublicKey,
+        kind: Kind,
+    ) -> anyhow::Result<()> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        db_connection.execute(
+            "DELETE FROM sign_decisions WHERE application_npub = ?1 AND kind = ?2",
+            params![application_npub.to_bech32()?, kind.as_u64()],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn list_sign_decisions(&self) -> anyhow::Result<Vec<RememberedSignDecision>> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let mut stmt = db_connection.prepare(
+            "SELECT application_npub, kind, approved FROM sign_decisions ORDER BY application_npub, kind",
+        )?;
+
+        let decision_iter = stmt.query_map([], |row| {
+            Ok(RememberedSignDecision {
+                app_npub: row.get(0)?,
+                kind: row.get(1)?,
+                approved: row.get(2)?,
+            })
+        })?;
+
+        let mut decisions = Vec::new();
+        for decision in decision_iter {
+            decisions.push(decision?);
+        }
+
+        Ok(decisions)
+    }
+
+    pub fn list_registered_applications(
+        &self,
+        limit: u64,
+        offset: u64,
+    ) -> anyhow::Result<Vec<(Option<String>, PublicKey, PublicKey)>> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let mut stmt = db_connection.prepare(
+            "SELECT display_name, application_npub, npub FROM registered_applications
+            INNER JOIN keys ON registered_applications.application_identity = keys.id
+            ORDER BY registered_applications.id ASC LIMIT ?1 OFFSET ?2",
+        )?;
+
+        let application_iter = stmt.query_map(params![limit, offset], |row| {
+            Ok((
+                row.get::<_, Option<String>>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+
+        let mut applications = Vec::new();
+        for application in application_iter {
+            let (display_name, application_npub, application_identity_string) = application?;
+            applications.push((
+                display_name,
+                PublicKey::from_bech32(application_npub)?,
+                PublicKey::from_bech32(application_identity_string)?,
+            ));
+        }
+
+        Ok(applications)
+    }
+
+    /// Sets the label of a keypair's account, or clears it if `label` is `None`.
+    pub fn set_account_label(
+        &self,
+        public_key: &PublicKey,
+        label: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let updated_row_count = db_connection.execute(
+            "INSERT INTO account_metadata (key_id, label) SELECT id, ?1 FROM keys WHERE npub = ?2
+            ON CONFLICT(key_id) DO UPDATE SET label = excluded.label",
+            params![label, public_key.to_bech32()?],
+        )?;
+
+        if updated_row_count == 0 {
+            return Err(anyhow::anyhow!(
+                "Account {} not found",
+                public_key.to_bech32()?
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the metadata of a keypair's account. Accounts that have never had any metadata set get the default.
+    pub fn get_account_metadata(&self, public_key: &PublicKey) -> anyhow::Result<AccountMetadata> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let metadata_or = db_connection
+            .query_row(
+                "SELECT label, retire_time, successor_npub FROM account_metadata
+                WHERE key_id = (SELECT id FROM keys WHERE npub = ?1)",
+                params![public_key.to_bech32()?],
+                |row| {
+                    Ok(AccountMetadata {
+                        label: row.get(0)?,
+                        retire_time: row.get(1)?,
+                        successor_npub: row.get(2)?,
+                        derivation_index: None,
+                    })
+                },
+            )
+            .optional()?;
+        let derivation_index = db_connection
+            .query_row(
+                "SELECT account_index FROM seed_derivations
+                WHERE key_id = (SELECT id FROM keys WHERE npub = ?1)",
+                params![public_key.to_bech32()?],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(AccountMetadata {
+            derivation_index,
+            ..metadata_or.unwrap_or_default()
+        })
+    }
+
+    /// Adds to the counts of events signed and invoices paid by a keypair's account, and marks it as active now.
+    /// Does nothing if the keypair isn't stored.
+    pub fn record_account_activity(
+        &self,
+        public_key: &PublicKey,
+        sign_count: u64,
+        payment_count: u64,
+    ) -> anyhow::Result<()> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        db_connection.execute(
+            "INSERT INTO account_stats (key_id, sign_count, payment_count, last_active_time)
+            SELECT id, ?1, ?2, ?3 FROM keys WHERE npub = ?4
+            ON CONFLICT(key_id) DO UPDATE SET
+                sign_count = sign_count + excluded.sign_count,
+                payment_count = payment_count + excluded.payment_count,
+                last_active_time = excluded.last_active_time",
+            params![
+                sign_count,
+                payment_count,
+                Utc::now().to_rfc3339(),
+                public_key.to_bech32()?
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns the activity counts of a keypair's account. Accounts that have never been used get the default.
+    pub fn get_account_stats(&self, public_key: &PublicKey) -> anyhow::Result<AccountStats> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let stats_or = db_connection
+            .query_row(
+                "SELECT sign_count, payment_count, last_active_time FROM account_stats
+                WHERE key_id = (SELECT id FROM keys WHERE npub = ?1)",
+                params![public_key.to_bech32()?],
+                |row| {
+                    Ok(AccountStats {
+                        sign_count: row.get(0)?,
+                        payment_count: row.get(1)?,
+                        last_active_time: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?;
+
+        Ok(stats_or.unwrap_or_default())
+    }
+
+    /// Adds a relay to a keypair's relay list, or updates the relay's policy if it's already in the list.
+    pub fn set_relay_policy(
+        &self,
+        public_key: &PublicKey,
+        url: &str,
+        policy: RelayPolicy,
+    ) -> anyhow::Result<()> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        db_connection.execute(
+            "INSERT INTO relays (url, read, write, create_time, key_id) VALUES (?1, ?2, ?3, ?4, (SELECT id FROM keys WHERE npub = ?5))
+            ON CONFLICT(url, key_id) DO UPDATE SET read = excluded.read, write = excluded.write",
+            params![url, policy.read, policy.write, Utc::now().to_rfc3339(), public_key.to_bech32()?],
+        )?;
+
+        Ok(())
+    }
+
+    /// Removes a relay from a keypair's relay list.
+    pub fn remove_relay(&self, public_key: &PublicKey, url: &str) -> anyhow::Result<()> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        db_connection.execute(
+            "DELETE FROM relays WHERE url = ?1 AND key_id = (SELECT id FROM keys WHERE npub = ?2)",
+            params![url, public_key.to_bech32()?],
+        )?;
+
+        Ok(())
+    }
+
+    /// Lists the relays in a keypair's relay list. Ordered by id in ascending order.
+    pub fn list_relays(
+        &self,
+        public_key: &PublicKey,
+    ) -> anyhow::Result<Vec<(String, RelayPolicy)>> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let mut stmt = db_connection.prepare(
+            "SELECT url, read, write FROM relays
+            WHERE key_id = (SELECT id FROM keys WHERE npub = ?1)
+            ORDER BY id ASC",
+        )?;
+
+        let relay_iter = stmt.query_map(params![public_key.to_bech32()?], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                RelayPolicy {
+                    read: row.get::<_, bool>(1)?,
+                    write: row.get::<_, bool>(2)?,
+                },
+            ))
+        })?;
+
+        let mut relays = Vec::new();
+        for relay in relay_iter {
+            relays.push(relay?);
+        }
+
+        Ok(relays)
+    }
+
+    /// Replaces the keypair's contacts with `contacts`, e.g. with the ones from a freshly imported follow list.
+    pub fn replace_contacts(
+        &self,
+        public_key: &PublicKey,
+        contacts: &[Contact],
+    ) -> anyhow::Result<()> {
+        let mut db_connection = self.db_connection.lock().unwrap();
+        let transaction = db_connection.transaction()?;
+        let npub = public_key.to_bech32()?;
+
+        let key_id: i64 = transaction
+            .query_row(
+                "SELECT id FROM keys WHERE npub = ?1",
+                params![npub],
+                |row| row.get(0),
+            )
+            .map_err(|_| anyhow::anyhow!("Keypair not found"))?;
+
+        transaction.execute("DELETE FROM contacts WHERE key_id = ?1", params![key_id])?;
+        for contact in contacts {
+            transaction.execute(
+                "INSERT OR IGNORE INTO contacts (key_id, npub, relay_url, petname) VALUES (?1, ?2, ?3, ?4)",
+                params![key_id, contact.npub, contact.relay_url, contact.petname],
+            )?;
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Lists the keypair's contacts, ordered by npub.
+    pub fn list_contacts(&self, public_key: &PublicKey) -> anyhow::Result<Vec<Contact>> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let mut stmt = db_connection.prepare(
+            "SELECT npub, relay_url, petname FROM contacts
+            WHERE key_id = (SELECT id FROM keys WHERE npub = ?1)
+            ORDER BY npub ASC",
+        )?;
+
+        let contact_iter = stmt.query_map(params![public_key.to_bech32()?], |row| {
+            Ok(Contact {
+                npub: row.get(0)?,
+                relay_url: row.get(1)?,
+                petname: row.get(2)?,
+            })
+        })?;
+
+        let mut contacts = Vec::new();
+        for contact in contact_iter {
+            contacts.push(contact?);
+        }
+
+        Ok(contacts)
+    }
+
+    /// Stores a copy of a replaceable event (e.g. an account's profile), unless a copy of the same or a newer one
+    /// from the same author and of the same kind is already stored. Returns whether the event was stored.
+    pub fn save_cached_event(&self, event: &Event) -> anyhow::Result<bool> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let updated = db_connection.execute(
+            "INSERT INTO cached_events (npub, kind, created_at, event) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (npub, kind) DO UPDATE SET created_at = excluded.created_at, event = excluded.event
+            WHERE excluded.created_at > cached_events.created_at",
+            params![
+                event.pubkey.to_bech32()?,
+                event.kind.as_u64(),
+                event.created_at.as_u64(),
+                event.as_json()
+            ],
+        )?;
+
+        Ok(updated > 0)
+    }
+
+    /// Returns the stored copy of the author's replaceable event of `kind`, if there is one.
+    pub fn get_cached_event(
+        &self,
+        public_key: &PublicKey,
+        kind: Kind,
+    ) -> anyhow::Result<Option<Event>> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let event_json: Option<String> = db_connection
+            .query_row(
+                "SELECT event FROM cached_events WHERE npub = ?1 AND kind = ?2",
+                params![public_key.to_bech32()?, kind.as_u64()],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        event_json
+            .map(|event_json| Event::from_json(event_json).map_err(anyhow::Error::from))
+            .transpose()
+    }
+
+    /// Returns the value of a setting, or `None` if the setting has never been set.
+    pub fn get_setting<T: DeserializeOwned>(&self, name: &str) -> anyhow::Result<Option<T>> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let value_or = db_connection
+            .query_row(
+                "SELECT value FROM settings WHERE name = ?1",
+                params![name],
+                |row| row.get::<usize, String>(0),
+            )
+            .optional()?;
+
+        match value_or {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Sets the value of a setting, overwriting any existing value.
+    pub fn set_setting<T: Serialize>(&self, name: &str, value: &T) -> anyhow::Result<()> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        db_connection.execute(
+            "INSERT INTO settings (name, value) VALUES (?1, ?2)
+            ON CONFLICT(name) DO UPDATE SET value = excluded.value",
+            params![name, serde_json::to_string(value)?],
+        )?;
+
+        Ok(())
+    }
+
+    /// Adds an entry to the payment history.
+    pub fn add_payment_log_entry(&self, entry: &PaymentLogEntry) -> anyhow::Result<()> {
+        self.add_payment_log_entries(std::slice::from_ref(entry))
+    }
+
+    /// Adds several entries to the payment history in a single transaction, so either all of them are added or none are.
+    pub fn add_payment_log_entries(&self, entries: &[PaymentLogEntry]) -> anyhow::Result<()> {
+        let mut db_connection = self.db_connection.lock().unwrap();
+        let transaction = db_connection.transaction()?;
+
+        for entry in entries {
+            transaction.execute(
+                "INSERT INTO payment_log (invoice, amount_msats, description, app_npub, create_time, outcome, preimage) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    entry.invoice,
+                    entry.amount_msats,
+                    entry.description,
+                    entry.app_npub,
+                    entry.create_time,
+                    entry.outcome.as_str(),
+                    entry.preimage
+                ],
+            )?;
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Lists the entries in the payment history that match `filter`. Ordered by id in descending order, so the most recent come first.
+    /// Use limit and offset parameters for pagination.
+    pub fn list_payment_log_entries(
+        &self,
+        limit: u64,
+        offset: u64,
+        filter: &PaymentHistoryFilter,
+    ) -> anyhow::Result<Vec<PaymentLogEntry>> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let mut stmt = db_connection.prepare(
+            "SELECT invoice, amount_msats, description, app_npub, create_time, outcome, preimage FROM payment_log
+            WHERE (?1 IS NULL OR outcome = ?1) AND (?2 IS NULL OR app_npub = ?2)
+            ORDER BY id DESC LIMIT ?3 OFFSET ?4",
+        )?;
+
+        let entry_iter = stmt.query_map(
+            params![
+                filter.outcome.map(|outcome| outcome.as_str()),
+                filter.app_npub,
+                limit,
+                offset
+            ],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<u64>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                ))
+            },
+        )?;
+
+        let mut entries = Vec::new();
+        for entry in entry_iter {
+            let (invoice, amount_msats, description, app_npub, create_time, outcome, preimage) =
+                entry?;
+            entries.push(PaymentLogEntry {
+                invoice,
+                amount_msats,
+                description,
+                app_npub,
+                create_time,
+                outcome: outcome.parse()?,
+                preimage,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// Deletes up to `limit` of the oldest entries in the payment history that were added before `cutoff`,
+    /// returning how many were deleted.
+    pub fn delete_payment_log_entries_before(
+        &self,
+        cutoff: DateTime<Utc>,
+        limit: u64,
+    ) -> anyhow::Result<u64> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let deleted = db_connection.execute(
+            "DELETE FROM payment_log WHERE id IN (
+                SELECT id FROM payment_log WHERE julianday(create_time) < julianday(?1)
+                ORDER BY id LIMIT ?2
+            )",
+            params![cutoff.to_rfc3339(), limit],
+        )?;
+
+        Ok(deleted as u64)
+    }
+
+    /// Adds an entry to an app's signing history.
+    pub fn add_signing_log_entry(&self, entry: &SigningLogEntry) -> anyhow::Result<()> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        db_connection.execute(
+            "INSERT INTO signing_log (app_npub, kind, create_time, outcome) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                entry.app_npub,
+                entry.kind,
+                entry.create_time,
+                entry.outcome.as_str()
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Lists the entries in the signing history of the app with `app_npub`. Ordered by id in descending order, so the
+    /// most recent come first. Use limit and offset parameters for pagination.
+    pub fn list_signing_log_entries(
+        &self,
+        app_npub: &str,
+        limit: u64,
+        offset: u64,
+    ) -> anyhow::Result<Vec<SigningLogEntry>> {
         let db_connection = self.db_connection.lock().unwrap();
 
-        db_connection.execute(
-            "DELETE FROM registered_applications WHERE application_npub = ?1",
-            params![application_npub.to_bech32()?],
+        let mut stmt = db_connection.prepare(
+            "SELECT app_npub, kind, create_time, outcome FROM signing_log
+            WHERE app_npub = ?1
+            ORDER BY id DESC LIMIT ?2 OFFSET ?3",
         )?;
 
-        Ok(())
+        let entry_iter = stmt.query_map(params![app_npub, limit, offset], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, u64>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?;
+
+        let mut entries = Vec::new();
+        for entry in entry_iter {
+            let (app_npub, kind, create_time, outcome) = entry?;
+            entries.push(SigningLogEntry {
+                app_npub,
+                kind,
+                create_time,
+                outcome: outcome.parse()?,
+            });
+        }
+
+        Ok(entries)
     }
 
-    /// Switches the keypair that a registered application is operating as.
-    pub fn swap_application_identity(
+    /// Returns when the app with `app_npub` last had an event signed or asked for a payment, as an RFC 3339
+    /// timestamp, or `None` if it never has.
+    pub fn get_application_last_used_time(&self, app_npub: &str) -> anyhow::Result<Option<String>> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        Ok(db_connection.query_row(
+            "SELECT MAX(create_time) FROM (
+                SELECT create_time FROM signing_log WHERE app_npub = ?1
+                UNION ALL
+                SELECT create_time FROM payment_log WHERE app_npub = ?1
+            )",
+            params![app_npub],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Deletes up to `limit` of the oldest entries in the signing history that were added before `cutoff`,
+    /// returning how many were deleted.
+    pub fn delete_signing_log_entries_before(
         &self,
-        application_npub: &PublicKey,
-        new_application_identity: &PublicKey,
-    ) -> anyhow::Result<()> {
+        cutoff: DateTime<Utc>,
+        limit: u64,
+    ) -> anyhow::Result<u64> {
         let db_connection = self.db_connection.lock().unwrap();
 
-        let updated_row_count = db_connection.execute(
-            "UPDATE registered_applications SET application_identity = (SELECT id FROM keys WHERE npub = ?1) WHERE application_npub = ?2",
-            params![new_application_identity.to_bech32()?, application_npub.to_bech32()?],
+        let deleted = db_connection.execute(
+            "DELETE FROM signing_log WHERE id IN (
+                SELECT id FROM signing_log WHERE julianday(create_time) < julianday(?1)
+                ORDER BY id LIMIT ?2
+            )",
+            params![cutoff.to_rfc3339(), limit],
         )?;
 
-        if updated_row_count == 0 {
-            return Err(anyhow::anyhow!(
-                "Application with application_npub {} not found",
-                application_npub.to_bech32()?
-            ));
+        Ok(deleted as u64)
+    }
+
+    /// Adds several entries to the connection log in a single transaction, so either all of them are added or none are.
+    pub fn add_connection_log_entries(&self, entries: &[ConnectionLogEntry]) -> anyhow::Result<()> {
+        let mut db_connection = self.db_connection.lock().unwrap();
+        let transaction = db_connection.transaction()?;
+
+        for entry in entries {
+            transaction.execute(
+                "INSERT INTO connection_log (connection_id, server_address, create_time, event) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    entry.connection_id,
+                    entry.server_address,
+                    entry.time,
+                    serde_json::to_string(&entry.event)?
+                ],
+            )?;
         }
 
+        transaction.commit()?;
         Ok(())
     }
 
-    /// Lists registered applications in the database. Ordered by id in ascending order.
-    /// Use limit and offset parameters for pagination.
-    pub fn list_registered_applications(
+    /// Lists the entries in the connection log that were added at or after `since`, oldest first.
+    pub fn list_connection_log_entries_since(
         &self,
-        limit: u64,
-        offset: u64,
-    ) -> anyhow::Result<Vec<(Option<String>, PublicKey, PublicKey)>> {
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<ConnectionLogEntry>> {
         let db_connection = self.db_connection.lock().unwrap();
 
         let mut stmt = db_connection.prepare(
-            "SELECT display_name, application_npub, npub FROM registered_applications
-            INNER JOIN keys ON registered_applications.application_identity = keys.id
-            ORDER BY registered_applications.id ASC LIMIT ?1 OFFSET ?2",
+            "SELECT connection_id, server_address, create_time, event FROM connection_log
+            WHERE julianday(create_time) >= julianday(?1) ORDER BY id",
         )?;
 
-        let application_iter = stmt.query_map(params![limit, offset], |row| {
+        // SQLite can't parse years before 0, e.g. that of `DateTime::MIN_UTC`. Nothing was logged before 1970 anyway.
+        let since = since.max(DateTime::<Utc>::from_timestamp(0, 0).unwrap());
+        let entry_iter = stmt.query_map(params![since.to_rfc3339()], |row| {
             Ok((
-                row.get::<_, Option<String>>(0)?,
+                row.get::<_, u64>(0)?,
                 row.get::<_, String>(1)?,
                 row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
             ))
         })?;
 
-        let mut applications = Vec::new();
-        for application in application_iter {
-            let (display_name, application_npub, application_identity_string) = application?;
-            applications.push((
-                display_name,
-                PublicKey::from_bech32(application_npub)?,
-                PublicKey::from_bech32(application_identity_string)?,
-            ));
+        let mut entries = Vec::new();
+        for entry in entry_iter {
+            let (connection_id, server_address, time, event) = entry?;
+            entries.push(ConnectionLogEntry {
+                connection_id,
+                server_address,
+                time,
+                event: serde_json::from_str(&event)?,
+            });
         }
 
-        Ok(applications)
+        Ok(entries)
+    }
+
+    /// Deletes up to `limit` of the oldest entries in the connection log that were added before `cutoff`,
+    /// returning how many were deleted.
+    pub fn delete_connection_log_entries_before(
+        &self,
+        cutoff: DateTime<Utc>,
+        limit: u64,
+    ) -> anyhow::Result<u64> {
+        let db_connection = self.db_connection.lock().unwrap();
+
+        let deleted = db_connection.execute(
+            "DELETE FROM connection_log WHERE id IN (
+                SELECT id FROM connection_log WHERE julianday(create_time) < julianday(?1)
+                ORDER BY id LIMIT ?2
+            )",
+            params![cutoff.to_rfc3339(), limit],
+        )?;
+
+        Ok(deleted as u64)
     }
 }
 
@@ -369,6 +1777,34 @@ mod tests {
         assert!(db.is_err());
     }
 
+    #[test]
+    fn encrypt_unencrypted_db() {
+        let folder = get_temp_folder();
+        let db = Database::new(&folder, "test.db", None).unwrap();
+        let keypairs = vec![get_random_keypair(), get_random_keypair()];
+        for keypair in &keypairs {
+            db.save_keypair(keypair).unwrap();
+        }
+
+        db.encrypt("hello world").unwrap();
+
+        // The keys can still be read without reopening the database.
+        assert!(db.is_encrypted());
+        assert!(db.verify_encryption_key("hello world"));
+        assert_eq!(db.list_keypairs(10, 0).unwrap(), keypairs);
+
+        // Encrypting twice should cause an error.
+        assert!(db.encrypt("other key").is_err());
+        drop(db);
+
+        // From now on the database can only be opened with the key.
+        assert!(Database::new(&folder, "test.db", None).is_err());
+        let db = Database::new(&folder, "test.db", Some("hello world")).unwrap();
+        assert_eq!(db.list_keypairs(10, 0).unwrap(), keypairs);
+        assert_eq!(db.verify_integrity().unwrap().corrupt_pages, 0);
+        assert!(!folder.join("test.db.encrypting").exists());
+    }
+
     #[test]
     fn save_and_remove_keypair() {
         let db = Database::new(&get_temp_folder(), "test.db", None).unwrap();
@@ -872,4 +2308,390 @@ mod tests {
         db.remove_keypair(&keypair.x_only_public_key().0.into())
             .unwrap();
     }
+
+    #[test]
+    fn get_and_set_setting() {
+        let db = Database::new(&get_temp_folder(), "test.db", None).unwrap();
+
+        // Returns `None` since the setting has never been set.
+        assert_eq!(db.get_setting::<u64>("foo").unwrap(), None);
+
+        db.set_setting("foo", &1_u64).unwrap();
+        assert_eq!(db.get_setting::<u64>("foo").unwrap(), Some(1));
+
+        // Setting the value again should overwrite the existing value.
+        db.set_setting("foo", &2_u64).unwrap();
+        assert_eq!(db.get_setting::<u64>("foo").unwrap(), Some(2));
+
+        // Other settings are unaffected.
+        assert_eq!(db.get_setting::<u64>("bar").unwrap(), None);
+    }
+
+    #[test]
+    fn get_setting_with_wrong_type_error() {
+        let db = Database::new(&get_temp_folder(), "test.db", None).unwrap();
+
+        db.set_setting("foo", &"hello world").unwrap();
+
+        // Reading a setting as a different type than it was saved as should cause an error.
+        assert!(db.get_setting::<u64>("foo").is_err());
+    }
+
+    #[test]
+    fn verify_integrity_flags_corrupt_entry() {
+        let db = Database::new(&get_temp_folder(), "test.db", Some("hello world")).unwrap();
+        let keypairs = [
+            get_random_keypair(),
+            get_random_keypair(),
+            get_random_keypair(),
+        ];
+        for keypair in &keypairs {
+            db.save_keypair(keypair).unwrap();
+        }
+
+        assert_eq!(
+            db.verify_integrity().unwrap(),
+            VaultIntegrityReport {
+                valid_entries: 3,
+                corrupt_entries: 0,
+                corrupt_pages: 0,
+            }
+        );
+
+        // Corrupt one stored secret by changing a single character.
+        let public_key: PublicKey = keypairs[1].x_only_public_key().0.into();
+        let nsec = SecretKey::from(keypairs[1].secret_key())
+            .to_bech32()
+            .unwrap();
+        let replacement = if nsec.ends_with('q') { 'p' } else { 'q' };
+        let corrupt_nsec = format!("{}{}", &nsec[..nsec.len() - 1], replacement);
+        db.db_connection
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE keys SET nsec = ?1 WHERE npub = ?2",
+                params![corrupt_nsec, public_key.to_bech32().unwrap()],
+            )
+            .unwrap();
+
+        assert_eq!(
+            db.verify_integrity().unwrap(),
+            VaultIntegrityReport {
+                valid_entries: 2,
+                corrupt_entries: 1,
+                corrupt_pages: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn create_account_rolls_back_on_failure() {
+        let db = Database::new(&get_temp_folder(), "test.db", None).unwrap();
+        let keypair = get_random_keypair();
+        let public_key: PublicKey = keypair.x_only_public_key().0.into();
+        let relays = vec![(
+            "wss://relay.example.com".to_string(),
+            RelayPolicy {
+                read: true,
+                write: true,
+            },
+        )];
+
+        // Make the second insert, of the account's label, fail.
+        db.db_connection
+            .lock()
+            .unwrap()
+            .execute(
+                "CREATE TEMP TRIGGER fail_label BEFORE INSERT ON account_metadata
+                BEGIN SELECT RAISE(ABORT, 'injected failure'); END",
+                [],
+            )
+            .unwrap();
+        let err = db
+            .create_account(&keypair, Some("Work"), &relays)
+            .unwrap_err();
+        assert!(err.downcast_ref::<DbError>().is_some());
+        assert!(db.list_keypairs(10, 0).unwrap().is_empty());
+        assert!(db.list_relays(&public_key).unwrap().is_empty());
+
+        // Once the failure is gone, the whole account is saved.
+        db.db_connection
+            .lock()
+            .unwrap()
+            .execute("DROP TRIGGER fail_label", [])
+            .unwrap();
+        db.create_account(&keypair, Some("Work"), &relays).unwrap();
+        assert_eq!(db.list_keypairs(10, 0).unwrap(), vec![keypair]);
+        assert_eq!(
+            db.get_account_metadata(&public_key).unwrap().label,
+            Some("Work".to_string())
+        );
+        assert_eq!(db.list_relays(&public_key).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn repair_npubs_fixes_mismatched_npub() {
+        let db = Database::new(&get_temp_folder(), "test.db", None).unwrap();
+        let keypair = get_random_keypair();
+        db.save_keypair(&keypair).unwrap();
+        db.save_keypair(&get_random_keypair()).unwrap();
+
+        // Store the wrong npub for the first key.
+        let public_key: PublicKey = keypair.x_only_public_key().0.into();
+        let wrong_public_key: PublicKey = get_random_keypair().x_only_public_key().0.into();
+        db.db_connection
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE keys SET npub = ?1 WHERE npub = ?2",
+                params![
+                    wrong_public_key.to_bech32().unwrap(),
+                    public_key.to_bech32().unwrap()
+                ],
+            )
+            .unwrap();
+        assert_eq!(db.verify_integrity().unwrap().corrupt_entries, 1);
+
+        assert_eq!(db.repair_npubs().unwrap(), 1);
+        assert_eq!(db.verify_integrity().unwrap().corrupt_entries, 0);
+        assert!(db.list_keypairs(10, 0).unwrap().contains(&keypair));
+
+        // Nothing is left to fix.
+        assert_eq!(db.repair_npubs().unwrap(), 0);
+    }
+
+    #[test]
+    fn diagnose_recreates_missing_index() {
+        let db = Database::new_in_temp_dir();
+        assert_eq!(db.diagnose(false).unwrap(), DatabaseDiagnosis::default());
+
+        let keypair = get_random_keypair();
+        let public_key: PublicKey = keypair.x_only_public_key().0.into();
+        let policy = RelayPolicy {
+            read: true,
+            write: false,
+        };
+        db.save_keypair(&keypair).unwrap();
+        db.set_relay_policy(&public_key, "wss://relay.example.com", policy)
+            .unwrap();
+
+        db.db_connection
+            .lock()
+            .unwrap()
+            .execute("DROP INDEX relays_key_id", [])
+            .unwrap();
+
+        // Without `repair`, the drift is only reported.
+        let diagnosis = db.diagnose(false).unwrap();
+        assert_eq!(diagnosis.missing_indexes, vec!["relays_key_id".to_string()]);
+        assert!(diagnosis.repaired.is_empty());
+        assert!(!diagnosis.is_healthy());
+
+        let diagnosis = db.diagnose(true).unwrap();
+        assert_eq!(diagnosis.missing_tables, Vec::<String>::new());
+        assert_eq!(diagnosis.missing_columns, Vec::<String>::new());
+        assert_eq!(diagnosis.repaired, vec!["relays_key_id".to_string()]);
+
+        // The index is back, and the rows are untouched.
+        assert!(db.diagnose(false).unwrap().is_healthy());
+        assert!(db.list_keypairs(10, 0).unwrap().contains(&keypair));
+        assert_eq!(
+            db.list_relays(&public_key).unwrap(),
+            vec![("wss://relay.example.com".to_string(), policy)]
+        );
+    }
+
+    #[test]
+    fn settings_persist_across_reopen() {
+        let folder = get_temp_folder();
+        let db = Database::new(&folder, "test.db", None).unwrap();
+        db.set_setting("foo", &true).unwrap();
+
+        drop(db);
+
+        let db = Database::new(&folder, "test.db", None).unwrap();
+        assert_eq!(db.get_setting::<bool>("foo").unwrap(), Some(true));
+    }
+
+    #[test]
+    fn set_and_list_relays() {
+        let db = Database::new(&get_temp_folder(), "test.db", None).unwrap();
+        let keypair = get_random_keypair();
+        let public_key = keypair.x_only_public_key().0.into();
+
+        db.save_keypair(&keypair).unwrap();
+
+        // Returns an empty list since no relays have been added.
+        assert!(db.list_relays(&public_key).unwrap().is_empty());
+
+        let read_write = RelayPolicy {
+            read: true,
+            write: true,
+        };
+        let read_only = RelayPolicy {
+            read: true,
+            write: false,
+        };
+
+        db.set_relay_policy(&public_key, "wss://relay1.example.com", read_write)
+            .unwrap();
+        db.set_relay_policy(&public_key, "wss://relay2.example.com", read_only)
+            .unwrap();
+
+        assert_eq!(
+            db.list_relays(&public_key).unwrap(),
+            vec![
+                ("wss://relay1.example.com".to_string(), read_write),
+                ("wss://relay2.example.com".to_string(), read_only)
+            ]
+        );
+
+        // Setting the policy for an existing relay should update it in place.
+        db.set_relay_policy(&public_key, "wss://relay1.example.com", read_only)
+            .unwrap();
+
+        assert_eq!(
+            db.list_relays(&public_key).unwrap(),
+            vec![
+                ("wss://relay1.example.com".to_string(), read_only),
+                ("wss://relay2.example.com".to_string(), read_only)
+            ]
+        );
+    }
+
+    #[test]
+    fn relays_are_scoped_to_keypair() {
+        let db = Database::new(&get_temp_folder(), "test.db", None).unwrap();
+        let keypair_1 = get_random_keypair();
+        let keypair_2 = get_random_keypair();
+        let public_key_1 = keypair_1.x_only_public_key().0.into();
+        let public_key_2 = keypair_2.x_only_public_key().0.into();
+        let policy = RelayPolicy {
+            read: true,
+            write: true,
+        };
+
+        db.save_keypair(&keypair_1).unwrap();
+        db.save_keypair(&keypair_2).unwrap();
+
+        // The same relay can be in multiple keypairs' relay lists.
+        db.set_relay_policy(&public_key_1, "wss://relay.example.com", policy)
+            .unwrap();
+        db.set_relay_policy(&public_key_2, "wss://relay.example.com", policy)
+            .unwrap();
+        db.set_relay_policy(&public_key_2, "wss://other.example.com", policy)
+            .unwrap();
+
+        assert_eq!(db.list_relays(&public_key_1).unwrap().len(), 1);
+        assert_eq!(db.list_relays(&public_key_2).unwrap().len(), 2);
+
+        // Removing a relay from one keypair's list doesn't affect the other.
+        db.remove_relay(&public_key_2, "wss://relay.example.com")
+            .unwrap();
+
+        assert_eq!(
+            db.list_relays(&public_key_1).unwrap(),
+            vec![("wss://relay.example.com".to_string(), policy)]
+        );
+        assert_eq!(
+            db.list_relays(&public_key_2).unwrap(),
+            vec![("wss://other.example.com".to_string(), policy)]
+        );
+    }
+
+    #[test]
+    fn replace_and_list_contacts() {
+        let db = Database::new(&get_temp_folder(), "test.db", None).unwrap();
+        let keypair = get_random_keypair();
+        let public_key = keypair.x_only_public_key().0.into();
+        let contact = |npub: &str, petname: Option<&str>| Contact {
+            npub: npub.to_string(),
+            relay_url: None,
+            petname: petname.map(str::to_string),
+        };
+
+        // Replacing the contacts of a keypair that doesn't exist should cause an error.
+        assert!(db
+            .replace_contacts(&public_key, &[contact("npub1b", None)])
+            .is_err());
+
+        db.save_keypair(&keypair).unwrap();
+        assert!(db.list_contacts(&public_key).unwrap().is_empty());
+
+        db.replace_contacts(
+            &public_key,
+            &[contact("npub1b", Some("bob")), contact("npub1a", None)],
+        )
+        .unwrap();
+        assert_eq!(
+            db.list_contacts(&public_key).unwrap(),
+            vec![contact("npub1a", None), contact("npub1b", Some("bob"))]
+        );
+
+        // Contacts that aren't in the new list are removed.
+        db.replace_contacts(&public_key, &[contact("npub1c", Some("carol"))])
+            .unwrap();
+        assert_eq!(
+            db.list_contacts(&public_key).unwrap(),
+            vec![contact("npub1c", Some("carol"))]
+        );
+    }
+
+    #[test]
+    fn set_relay_policy_for_unknown_keypair_error() {
+        let db = Database::new(&get_temp_folder(), "test.db", None).unwrap();
+        let keypair = get_random_keypair();
+
+        // Attempting to add a relay for a keypair that doesn't exist should cause an error.
+        let response = db.set_relay_policy(
+            &keypair.x_only_public_key().0.into(),
+            "wss://relay.example.com",
+            RelayPolicy {
+                read: true,
+                write: true,
+            },
+        );
+        assert!(response.is_err());
+    }
+
+    #[test]
+    fn remove_relay_that_doesnt_exist() {
+        let db = Database::new(&get_temp_folder(), "test.db", None).unwrap();
+        let keypair = get_random_keypair();
+
+        db.save_keypair(&keypair).unwrap();
+
+        // Removing a relay that isn't in the relay list should not cause an error.
+        assert!(db
+            .remove_relay(
+                &keypair.x_only_public_key().0.into(),
+                "wss://relay.example.com"
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn removing_keypair_removes_its_relays() {
+        let db = Database::new(&get_temp_folder(), "test.db", None).unwrap();
+        let keypair = get_random_keypair();
+        let public_key = keypair.x_only_public_key().0.into();
+
+        db.save_keypair(&keypair).unwrap();
+        db.set_relay_policy(
+            &public_key,
+            "wss://relay.example.com",
+            RelayPolicy {
+                read: true,
+                write: true,
+            },
+        )
+        .unwrap();
+
+        // Relays shouldn't prevent the keypair from being removed.
+        db.remove_keypair(&public_key).unwrap();
+
+        // Saving the keypair again should start with an empty relay list.
+        db.save_keypair(&keypair).unwrap();
+        assert!(db.list_relays(&public_key).unwrap().is_empty());
+    }
 }
@@ -0,0 +1,161 @@
+use nostr_sdk::nips::{nip04, nip44};
+use nostr_sdk::{Event, PublicKey, SecretKey, ToBech32};
+use serde::Serialize;
+
+/// Encryption scheme used by a direct message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DmScheme {
+    /// NIP-04: AES-256-CBC, with the IV appended to the content after `?iv=`.
+    Nip04,
+
+    /// NIP-44: versioned, base64-encoded payload.
+    Nip44,
+}
+
+impl DmScheme {
+    /// Works out how a direct message is encrypted from its content.
+    /// NIP-04 content always contains an `?iv=` separator, which can't appear in base64-encoded NIP-44 payloads.
+    pub fn detect(encrypted_content: &str) -> Self {
+        if encrypted_content.contains("?iv=") {
+            Self::Nip04
+        } else {
+            Self::Nip44
+        }
+    }
+}
+
+/// Payload of the `decrypt_dm_request` event emitted to the frontend when the plaintext of a
+/// direct message is requested, and the user needs to approve decrypting it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DecryptDmRequestPayload {
+    /// Hex-encoded ID of the direct message event.
+    pub event_id: String,
+
+    /// The bech32-encoded public key of the account that sent the message.
+    pub sender_npub: String,
+
+    /// The bech32-encoded public key of the account the message will be decrypted with.
+    pub recipient_npub: String,
+
+    pub scheme: DmScheme,
+}
+
+impl DecryptDmRequestPayload {
+    pub fn new(event: &Event, recipient_public_key: &PublicKey) -> anyhow::Result<Self> {
+        Ok(Self {
+            event_id: event.id().to_hex(),
+            sender_npub: event.author().to_bech32()?,
+            recipient_npub: recipient_public_key.to_bech32()?,
+            scheme: DmScheme::detect(event.content()),
+        })
+    }
+}
+
+/// Checks that a direct message is validly signed and addressed to `recipient_public_key` (i.e. has a `p` tag for it).
+pub fn verify_dm_recipient(event: &Event, recipient_public_key: &PublicKey) -> anyhow::Result<()> {
+    event
+        .verify()
+        .map_err(|err| anyhow::anyhow!("Invalid event: {err}"))?;
+
+    if !event
+        .public_keys()
+        .any(|public_key| public_key == recipient_public_key)
+    {
+        return Err(anyhow::anyhow!(
+            "Direct message isn't addressed to this account"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Decrypts a direct message addressed to the owner of `recipient_secret_key`, using whichever scheme it was encrypted with.
+pub fn decrypt_dm(event: &Event, recipient_secret_key: &SecretKey) -> anyhow::Result<String> {
+    let sender_public_key = event.author();
+
+    match DmScheme::detect(event.content()) {
+        DmScheme::Nip04 => {
+            nip04::decrypt(recipient_secret_key, &sender_public_key, event.content())
+                .map_err(|err| anyhow::anyhow!("Error decrypting NIP-04 message: {err}"))
+        }
+        DmScheme::Nip44 => {
+            nip44::decrypt(recipient_secret_key, &sender_public_key, event.content())
+                .map_err(|err| anyhow::anyhow!("Error decrypting NIP-44 message: {err}"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{EventBuilder, Keys, Kind, Tag};
+
+    fn nip44_dm(sender: &Keys, recipient: &PublicKey, message: &str) -> Event {
+        let content = nip44::encrypt(
+            sender.secret_key().unwrap(),
+            recipient,
+            message,
+            nip44::Version::V2,
+        )
+        .unwrap();
+        EventBuilder::new(Kind::SealedDirect, content, [Tag::public_key(*recipient)])
+            .to_event(sender)
+            .unwrap()
+    }
+
+    #[test]
+    fn decrypts_nip04_dm() {
+        let sender = Keys::generate();
+        let recipient = Keys::generate();
+        let event = EventBuilder::encrypted_direct_msg(
+            &sender,
+            recipient.public_key(),
+            "hello over NIP-04",
+            None,
+        )
+        .unwrap()
+        .to_event(&sender)
+        .unwrap();
+
+        assert_eq!(DmScheme::detect(event.content()), DmScheme::Nip04);
+        assert!(verify_dm_recipient(&event, &recipient.public_key()).is_ok());
+        assert_eq!(
+            decrypt_dm(&event, recipient.secret_key().unwrap()).unwrap(),
+            "hello over NIP-04"
+        );
+    }
+
+    #[test]
+    fn decrypts_nip44_dm() {
+        let sender = Keys::generate();
+        let recipient = Keys::generate();
+        let event = nip44_dm(&sender, &recipient.public_key(), "hello over NIP-44");
+
+        assert_eq!(DmScheme::detect(event.content()), DmScheme::Nip44);
+        assert!(verify_dm_recipient(&event, &recipient.public_key()).is_ok());
+        assert_eq!(
+            decrypt_dm(&event, recipient.secret_key().unwrap()).unwrap(),
+            "hello over NIP-44"
+        );
+
+        let payload = DecryptDmRequestPayload::new(&event, &recipient.public_key()).unwrap();
+        assert_eq!(
+            payload.sender_npub,
+            sender.public_key().to_bech32().unwrap()
+        );
+        assert_eq!(payload.scheme, DmScheme::Nip44);
+    }
+
+    #[test]
+    fn rejects_misaddressed_dm() {
+        let sender = Keys::generate();
+        let recipient = Keys::generate();
+        let someone_else = Keys::generate();
+        let event = nip44_dm(&sender, &recipient.public_key(), "not for you");
+
+        assert!(verify_dm_recipient(&event, &someone_else.public_key()).is_err());
+        // Even if it were attempted, the wrong key can't decrypt it.
+        assert!(decrypt_dm(&event, someone_else.secret_key().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+use nostr_sdk::bech32;
+use nostr_sdk::nips::nip19::Nip19;
+use nostr_sdk::FromBech32;
+use serde::Serialize;
+
+/// Human-readable part of the deprecated NIP-19 `nrelay` entity, which `nostr-sdk` no longer decodes.
+const NRELAY_PREFIX: &str = "nrelay";
+
+/// TLV type holding the relay URL in an `nrelay`.
+const TLV_SPECIAL: u8 = 0;
+
+/// The components of a decoded NIP-19 entity. Public keys and event IDs are hex-encoded.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DecodedEntity {
+    Npub {
+        public_key: String,
+    },
+
+    /// A secret key was pasted. Its value is deliberately left out, so that it's never shown or passed around.
+    Nsec,
+
+    /// A password-encrypted secret key (NIP-49) was pasted. Like [`DecodedEntity::Nsec`], its value is left out.
+    Ncryptsec,
+
+    Note {
+        event_id: String,
+    },
+
+    Nevent {
+        event_id: String,
+        author: Option<String>,
+        relays: Vec<String>,
+    },
+
+    Nprofile {
+        public_key: String,
+        relays: Vec<String>,
+    },
+
+    Naddr {
+        kind: u64,
+        public_key: String,
+        identifier: String,
+        relays: Vec<String>,
+    },
+
+    Nrelay {
+        url: String,
+    },
+}
+
+/// Decodes any NIP-19 entity, with or without a NIP-21 `nostr:` prefix.
+/// Errors describe what's wrong with the input, so they can be shown to the user as-is.
+pub fn decode_entity(input: &str) -> anyhow::Result<DecodedEntity> {
+    let input = input.trim();
+    let input = input.strip_prefix("nostr:").unwrap_or(input);
+    if input.is_empty() {
+        return Err(anyhow::anyhow!("Nothing to decode"));
+    }
+
+    let (hrp, data) =
+        bech32::decode(input).map_err(|_| anyhow::anyhow!("Not a bech32-encoded Nostr entity"))?;
+    let prefix = hrp.to_string();
+    if prefix == NRELAY_PREFIX {
+        return decode_nrelay(&data);
+    }
+
+    let entity = Nip19::from_bech32(input).map_err(|err| {
+        if known_prefix(&prefix) {
+            anyhow::anyhow!("Invalid {prefix}: {err}")
+        } else {
+            anyhow::anyhow!("Unsupported entity type \"{prefix}\"")
+        }
+    })?;
+
+    Ok(match entity {
+        Nip19::Secret(_) => DecodedEntity::Nsec,
+        Nip19::EncryptedSecret(_) => DecodedEntity::Ncryptsec,
+        Nip19::Pubkey(public_key) => DecodedEntity::Npub {
+            public_key: public_key.to_hex(),
+        },
+        Nip19::EventId(event_id) => DecodedEntity::Note {
+            event_id: event_id.to_hex(),
+        },
+        Nip19::Event(event) => DecodedEntity::Nevent {
+            event_id: event.event_id.to_hex(),
+            author: event.author.map(|author| author.to_hex()),
+            relays: event.relays,
+        },
+        Nip19::Profile(profile) => DecodedEntity::Nprofile {
+            public_key: profile.public_key.to_hex(),
+            relays: profile
+                .relays
+                .iter()
+                .map(|relay| relay.to_string())
+                .collect(),
+        },
+        Nip19::Coordinate(coordinate) => DecodedEntity::Naddr {
+            kind: coordinate.kind.as_u64(),
+            public_key: coordinate.public_key.to_hex(),
+            identifier: coordinate.identifier,
+            relays: coordinate.relays,
+        },
+    })
+}
+
+fn known_prefix(prefix: &str) -> bool {
+    matches!(
+        prefix,
+        "npub" | "nsec" | "ncryptsec" | "note" | "nevent" | "nprofile" | "naddr"
+    )
+}
+
+/// An `nrelay` is a list of TLV entries, of which the first `special` entry is the relay URL.
+fn decode_nrelay(mut data: &[u8]) -> anyhow::Result<DecodedEntity> {
+    while let [tlv_type, length, rest @ ..] = data {
+        let length = *length as usize;
+        if rest.len() < length {
+            break;
+        }
+
+        let (value, remaining) = rest.split_at(length);
+        if *tlv_type == TLV_SPECIAL {
+            let url = std::str::from_utf8(value)
+                .map_err(|_| anyhow::anyhow!("Invalid nrelay: relay URL isn't valid UTF-8"))?;
+            return Ok(DecodedEntity::Nrelay {
+                url: url.to_string(),
+            });
+        }
+        data = remaining;
+    }
+
+    Err(anyhow::anyhow!("Invalid nrelay: relay URL is missing"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::bech32::{Bech32, Hrp};
+    use nostr_sdk::nips::nip01::Coordinate;
+    use nostr_sdk::nips::nip19::{Nip19Event, Nip19Profile};
+    use nostr_sdk::{EventId, Keys, Kind, ToBech32, Url};
+    use proptest::prelude::*;
+
+    #[test]
+    fn decodes_keys() {
+        let keys = Keys::generate();
+
+        assert_eq!(
+            decode_entity(&keys.public_key().to_bech32().unwrap()).unwrap(),
+            DecodedEntity::Npub {
+                public_key: keys.public_key().to_hex()
+            }
+        );
+        assert_eq!(
+            decode_entity(&format!("nostr:{}", keys.public_key().to_bech32().unwrap())).unwrap(),
+            DecodedEntity::Npub {
+                public_key: keys.public_key().to_hex()
+            }
+        );
+
+        let nsec = keys.secret_key().unwrap().to_bech32().unwrap();
+        let decoded = decode_entity(&nsec).unwrap();
+        assert_eq!(decoded, DecodedEntity::Nsec);
+        assert!(!serde_json::to_string(&decoded)
+            .unwrap()
+            .contains(&nsec[5..]));
+    }
+
+    #[test]
+    fn decodes_events() {
+        let event_id = EventId::all_zeros();
+        let author = Keys::generate().public_key();
+
+        assert_eq!(
+            decode_entity(&event_id.to_bech32().unwrap()).unwrap(),
+            DecodedEntity::Note {
+                event_id: event_id.to_hex()
+            }
+        );
+
+        // `nostr-sdk` doesn't encode an nevent's author, so it's appended as an `author` TLV entry by hand.
+        let nevent = Nip19Event::new(event_id, ["wss://relay.example.com"])
+            .to_bech32()
+            .unwrap();
+        let (hrp, mut data) = bech32::decode(&nevent).unwrap();
+        data.extend([2, 32]);
+        data.extend(author.to_bytes());
+        let nevent = bech32::encode::<Bech32>(hrp, &data).unwrap();
+        assert_eq!(
+            decode_entity(&nevent).unwrap(),
+            DecodedEntity::Nevent {
+                event_id: event_id.to_hex(),
+                author: Some(author.to_hex()),
+                relays: vec!["wss://relay.example.com".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn decodes_nprofile_and_naddr() {
+        let public_key = Keys::generate().public_key();
+
+        let nprofile =
+            Nip19Profile::new(public_key, [Url::parse("wss://relay.example.com").unwrap()])
+                .unwrap();
+        assert_eq!(
+            decode_entity(&nprofile.to_bech32().unwrap()).unwrap(),
+            DecodedEntity::Nprofile {
+                public_key: public_key.to_hex(),
+                relays: vec!["wss://relay.example.com/".to_string()],
+            }
+        );
+
+        let mut naddr = Coordinate::new(Kind::LongFormTextNote, public_key).identifier("post");
+        naddr.relays = vec!["wss://relay.example.com".to_string()];
+        assert_eq!(
+            decode_entity(&naddr.to_bech32().unwrap()).unwrap(),
+            DecodedEntity::Naddr {
+                kind: 30023,
+                public_key: public_key.to_hex(),
+                identifier: "post".to_string(),
+                relays: vec!["wss://relay.example.com".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn decodes_nrelay() {
+        let url = "wss://relay.example.com";
+        let mut data = vec![TLV_SPECIAL, url.len() as u8];
+        data.extend_from_slice(url.as_bytes());
+        let nrelay = bech32::encode::<Bech32>(Hrp::parse(NRELAY_PREFIX).unwrap(), &data).unwrap();
+
+        assert_eq!(
+            decode_entity(&nrelay).unwrap(),
+            DecodedEntity::Nrelay {
+                url: url.to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert!(decode_entity("").is_err());
+        assert!(decode_entity("not an entity").is_err());
+
+        // Valid bech32 with a prefix that isn't a Nostr entity.
+        let unknown = bech32::encode::<Bech32>(Hrp::parse("lnbc").unwrap(), &[0; 32]).unwrap();
+        assert_eq!(
+            decode_entity(&unknown).unwrap_err().to_string(),
+            "Unsupported entity type \"lnbc\""
+        );
+
+        // A known prefix whose data is the wrong length for its type.
+        let short_npub = bech32::encode::<Bech32>(Hrp::parse("npub").unwrap(), &[0; 16]).unwrap();
+        assert!(decode_entity(&short_npub)
+            .unwrap_err()
+            .to_string()
+            .starts_with("Invalid npub"));
+
+        // A corrupted checksum.
+        let npub = Keys::generate().public_key().to_bech32().unwrap();
+        let replacement = if npub.ends_with('q') { 'p' } else { 'q' };
+        assert!(decode_entity(&format!("{}{}", &npub[..npub.len() - 1], replacement)).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn decoding_arbitrary_input_never_panics(input in any::<String>()) {
+            let _ = decode_entity(&input);
+        }
+
+        // Well-formed bech32 gets past the checksum, so that the data of each entity type is parsed.
+        #[test]
+        fn decoding_arbitrary_entity_data_never_panics(
+            prefix in prop::sample::select(vec![
+                "npub", "nsec", "ncryptsec", "note", "nevent", "nprofile", "naddr", NRELAY_PREFIX,
+            ]),
+            data in prop::collection::vec(any::<u8>(), 0..256),
+        ) {
+            let entity = bech32::encode::<Bech32>(Hrp::parse(prefix).unwrap(), &data).unwrap();
+            let _ = decode_entity(&entity);
+        }
+    }
+}
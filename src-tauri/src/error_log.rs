@@ -0,0 +1,9 @@
+//! The one place that errors are reported when there's no caller to return them to, e.g. in background tasks, or
+//! when they shouldn't fail the request they happened in, like failing to record a signing in the history.
+
+use chrono::Utc;
+
+/// Reports an error that can't be returned to a caller, with the time it was reported.
+pub fn report(message: impl std::fmt::Display) {
+    eprintln!("[{}] {message}", Utc::now().to_rfc3339());
+}
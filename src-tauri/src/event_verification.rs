@@ -0,0 +1,154 @@
+use nostr_sdk::nips::nip26::{DelegationTag, Error as Nip26Error, EventProperties};
+use nostr_sdk::{Event, JsonUtil};
+use serde::Serialize;
+
+/// Name of the NIP-26 delegation tag.
+const DELEGATION_TAG: &str = "delegation";
+
+/// Whether an event is authentic.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct EventVerification {
+    /// Whether the event's ID matches its contents and its signature is the author's.
+    pub signature_valid: bool,
+
+    /// Set if the event carries a NIP-26 delegation tag.
+    pub delegation: Option<DelegationVerification>,
+}
+
+/// Whether a NIP-26 delegation tag lets the event's author publish the event on the delegator's behalf.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DelegationVerification {
+    /// Hex-encoded public key of the delegator, if the tag could be read.
+    pub delegator: Option<String>,
+
+    /// Whether the delegator signed the delegation and its conditions allow the event's kind and creation time.
+    pub valid: bool,
+
+    /// Why the delegation isn't valid. Unset if it is.
+    pub reason: Option<String>,
+}
+
+/// Verifies the signature of an event given as JSON and, if it has one, its NIP-26 delegation tag.
+/// Only errors if `event_json` isn't an event; anything wrong with a parsed event is reported in the result.
+pub fn verify_event(event_json: &str) -> anyhow::Result<EventVerification> {
+    let event =
+        Event::from_json(event_json).map_err(|err| anyhow::anyhow!("Invalid event: {err}"))?;
+
+    Ok(EventVerification {
+        signature_valid: event.verify().is_ok(),
+        delegation: event
+            .tags()
+            .iter()
+            .map(|tag| tag.as_vec())
+            .find(|tag| tag.first().map(String::as_str) == Some(DELEGATION_TAG))
+            .map(|tag| verify_delegation(&event, tag)),
+    })
+}
+
+fn verify_delegation(event: &Event, tag: Vec<String>) -> DelegationVerification {
+    let delegation_tag = match DelegationTag::try_from(tag) {
+        Ok(delegation_tag) => delegation_tag,
+        Err(err) => {
+            return DelegationVerification {
+                delegator: None,
+                valid: false,
+                reason: Some(format!("Invalid delegation tag: {err}")),
+            }
+        }
+    };
+
+    let reason = match delegation_tag.validate(event.author(), &EventProperties::from_event(event))
+    {
+        Ok(()) => None,
+        // The library describes every failed condition as "Conditions not satisfied", so use the underlying error.
+        Err(Nip26Error::ConditionsValidation(err)) => Some(err.to_string()),
+        Err(err) => Some(err.to_string()),
+    };
+    DelegationVerification {
+        delegator: Some(delegation_tag.delegator_pubkey().to_hex()),
+        valid: reason.is_none(),
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::nips::nip26::Conditions;
+    use nostr_sdk::{EventBuilder, Keys, Kind, Tag, Timestamp};
+    use std::str::FromStr;
+
+    #[test]
+    fn valid_event() {
+        let event = EventBuilder::text_note("Hello", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+
+        assert_eq!(
+            verify_event(&event.as_json()).unwrap(),
+            EventVerification {
+                signature_valid: true,
+                delegation: None,
+            }
+        );
+    }
+
+    #[test]
+    fn tampered_event() {
+        let event = EventBuilder::text_note("Hello", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let tampered_json = event.as_json().replace("Hello", "Goodbye");
+
+        assert!(!verify_event(&tampered_json).unwrap().signature_valid);
+        assert!(verify_event("not an event").is_err());
+    }
+
+    #[test]
+    fn valid_delegated_event() {
+        let delegator_keys = Keys::generate();
+        let delegatee_keys = Keys::generate();
+        let now = Timestamp::now().as_u64();
+        let conditions = Conditions::from_str(&format!(
+            "kind=1&created_at>{}&created_at<{}",
+            now - 60,
+            now + 60
+        ))
+        .unwrap();
+        let delegation_tag =
+            DelegationTag::new(&delegator_keys, delegatee_keys.public_key(), conditions).unwrap();
+        let delegation_tag =
+            Tag::parse(&serde_json::from_str::<Vec<String>>(&delegation_tag.as_json()).unwrap())
+                .unwrap();
+
+        let event = EventBuilder::text_note("Hello", [delegation_tag.clone()])
+            .to_event(&delegatee_keys)
+            .unwrap();
+        assert_eq!(
+            verify_event(&event.as_json()).unwrap(),
+            EventVerification {
+                signature_valid: true,
+                delegation: Some(DelegationVerification {
+                    delegator: Some(delegator_keys.public_key().to_hex()),
+                    valid: true,
+                    reason: None,
+                }),
+            }
+        );
+
+        // The delegation only covers text notes.
+        let event = EventBuilder::new(Kind::Reaction, "+", [delegation_tag])
+            .to_event(&delegatee_keys)
+            .unwrap();
+        let verification = verify_event(&event.as_json()).unwrap();
+        assert!(verification.signature_valid);
+        assert_eq!(
+            verification.delegation,
+            Some(DelegationVerification {
+                delegator: Some(delegator_keys.public_key().to_hex()),
+                valid: false,
+                reason: Some("Event kind does not match".to_string()),
+            })
+        );
+    }
+}
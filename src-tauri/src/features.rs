@@ -0,0 +1,145 @@
+//! Optional features that can be left out of a build with Cargo feature flags, and the commands that need them.
+//! A build with `--no-default-features` only has the core commands.
+
+use crate::payment_backend::PaymentBackend;
+use serde::Serialize;
+
+/// Name of the feature that gates high-value operations behind a WebAuthn passkey.
+pub const WEBAUTHN: &str = "webauthn";
+
+/// Commands that are only registered if the `webauthn` feature is compiled in.
+pub const WEBAUTHN_COMMANDS: [&str; 3] = [
+    "get_passkey_challenge",
+    "register_passkey",
+    "remove_passkey",
+];
+
+/// Name of the feature that lets Keystache run as a NIP-47 wallet service.
+pub const NWC_SERVICE: &str = "nwc-service";
+
+/// Commands that are only registered if the `nwc-service` feature is compiled in.
+pub const NWC_SERVICE_COMMANDS: [&str; 2] = ["sign_nwc_info_event", "sign_nwc_response"];
+
+/// Name of the feature that lets accounts sign with a key on a PKCS#11 token instead of one in the vault.
+pub const PKCS11: &str = "pkcs11";
+
+/// Commands that are only registered if the `pkcs11` feature is compiled in.
+pub const PKCS11_COMMANDS: [&str; 1] = ["bind_hardware_key"];
+
+/// NIPs that Keystache supports, as their two-digit numbers.
+pub const SUPPORTED_NIPS: [&str; 15] = [
+    "01", "04", "06", "07", "13", "19", "21", "26", "36", "42", "44", "46", "49", "57", "70",
+];
+
+/// What this build of Keystache supports, so that connecting apps can adapt to it (e.g. by not using NIP-44).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    /// See [`SUPPORTED_NIPS`].
+    pub nips: Vec<&'static str>,
+
+    /// Names of the payment backends that invoices can be paid through. Empty if none is configured.
+    pub payment_backends: Vec<&'static str>,
+
+    /// Optional features compiled into this build.
+    pub features: Vec<&'static str>,
+}
+
+impl Capabilities {
+    pub fn new(payment_backend: &dyn PaymentBackend) -> Self {
+        Self {
+            nips: SUPPORTED_NIPS.to_vec(),
+            payment_backends: payment_backend.name().into_iter().collect(),
+            features: compiled_features(),
+        }
+    }
+}
+
+/// Returns the optional features compiled into this build.
+pub fn compiled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "webauthn") {
+        features.push(WEBAUTHN);
+    }
+    if cfg!(feature = "nwc-service") {
+        features.push(NWC_SERVICE);
+    }
+    if cfg!(feature = "pkcs11") {
+        features.push(PKCS11);
+    }
+    features
+}
+
+/// Returns the feature that `command` needs if it isn't compiled into this build, or `None` if the command is
+/// available.
+pub fn missing_feature(command: &str) -> Option<&'static str> {
+    if !cfg!(feature = "webauthn") && WEBAUTHN_COMMANDS.contains(&command) {
+        return Some(WEBAUTHN);
+    }
+    if !cfg!(feature = "nwc-service") && NWC_SERVICE_COMMANDS.contains(&command) {
+        return Some(NWC_SERVICE);
+    }
+    if !cfg!(feature = "pkcs11") && PKCS11_COMMANDS.contains(&command) {
+        return Some(PKCS11);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payment_backend::NoPaymentBackend;
+
+    #[test]
+    fn core_commands_are_always_available() {
+        assert_eq!(missing_feature("sign_event"), None);
+        assert_eq!(missing_feature("export_ncryptsec"), None);
+    }
+
+    #[test]
+    fn capabilities_reflect_build() {
+        let capabilities = Capabilities::new(&NoPaymentBackend);
+
+        assert!(capabilities.nips.contains(&"44"));
+        assert!(capabilities.nips.contains(&"46"));
+        assert_eq!(capabilities.payment_backends, Vec::<&str>::new());
+        assert_eq!(
+            capabilities.features.contains(&WEBAUTHN),
+            cfg!(feature = "webauthn")
+        );
+        for command in WEBAUTHN_COMMANDS {
+            assert_eq!(
+                missing_feature(command).is_none(),
+                capabilities.features.contains(&WEBAUTHN)
+            );
+        }
+        for command in NWC_SERVICE_COMMANDS {
+            assert_eq!(
+                missing_feature(command).is_none(),
+                capabilities.features.contains(&NWC_SERVICE)
+            );
+        }
+        for command in PKCS11_COMMANDS {
+            assert_eq!(
+                missing_feature(command).is_none(),
+                capabilities.features.contains(&PKCS11)
+            );
+        }
+    }
+
+    #[cfg(feature = "webauthn")]
+    #[test]
+    fn webauthn_commands_are_available_with_webauthn() {
+        for command in WEBAUTHN_COMMANDS {
+            assert_eq!(missing_feature(command), None);
+        }
+    }
+
+    // Run with `cargo test --no-default-features` to check the core build.
+    #[cfg(not(feature = "webauthn"))]
+    #[test]
+    fn webauthn_commands_are_absent_from_core_build() {
+        for command in WEBAUTHN_COMMANDS {
+            assert_eq!(missing_feature(command), Some(WEBAUTHN));
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use nostr_sdk::hashes::{sha256, Hash};
+use nostr_sdk::{PublicKey, ToBech32};
+use serde::Serialize;
+
+/// How many bytes of the public key's hash make up its fingerprint.
+const FINGERPRINT_LEN: usize = 8;
+
+/// Hashed in front of the public key for identicon seeds, so that they're unrelated to the fingerprint.
+const IDENTICON_SEED_PREFIX: &[u8] = b"keystache-identicon";
+
+/// A short fingerprint of an account's public key, which the frontend shows (e.g. as an identicon) so that the
+/// user can recognise at a glance which identity is active.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct IdentityFingerprint {
+    pub npub: String,
+
+    /// The first bytes of the SHA-256 hash of the public key, hex-encoded in groups of four characters, e.g.
+    /// `1a2b-3c4d-5e6f-7a8b`.
+    pub fingerprint: String,
+}
+
+impl IdentityFingerprint {
+    pub fn new(public_key: &PublicKey) -> anyhow::Result<Self> {
+        let hex = sha256::Hash::hash(&public_key.to_bytes()).to_string();
+        let groups: Vec<&str> = (0..FINGERPRINT_LEN * 2)
+            .step_by(4)
+            .map(|start| &hex[start..start + 4])
+            .collect();
+
+        Ok(Self {
+            npub: public_key.to_bech32()?,
+            fingerprint: groups.join("-"),
+        })
+    }
+}
+
+/// Deterministic bytes derived from an account's public key, for drawing the same avatar and color for the account
+/// every time when it has no profile picture.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct IdenticonSeed {
+    pub npub: String,
+
+    /// Hex-encoded SHA-256 hash of the public key, for deriving the identicon's shapes.
+    pub seed: String,
+
+    /// Hue of the account's color, in degrees from 0 up to 360, taken from the seed's first two bytes.
+    pub hue: u16,
+}
+
+impl IdenticonSeed {
+    pub fn new(public_key: &PublicKey) -> anyhow::Result<Self> {
+        let mut preimage = IDENTICON_SEED_PREFIX.to_vec();
+        preimage.extend_from_slice(&public_key.to_bytes());
+        let hash = sha256::Hash::hash(&preimage);
+
+        Ok(Self {
+            npub: public_key.to_bech32()?,
+            seed: hash.to_string(),
+            hue: u16::from_be_bytes([hash[0], hash[1]]) % 360,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::Keys;
+
+    #[test]
+    fn fingerprint_is_deterministic_and_differs_across_keys() {
+        let public_key = Keys::generate().public_key();
+        let fingerprint = IdentityFingerprint::new(&public_key).unwrap();
+
+        assert_eq!(IdentityFingerprint::new(&public_key).unwrap(), fingerprint);
+        assert_eq!(fingerprint.npub, public_key.to_bech32().unwrap());
+        assert_eq!(fingerprint.fingerprint.len(), 19);
+        assert_eq!(fingerprint.fingerprint.split('-').count(), 4);
+
+        let other_public_key = Keys::generate().public_key();
+        assert_ne!(
+            IdentityFingerprint::new(&other_public_key)
+                .unwrap()
+                .fingerprint,
+            fingerprint.fingerprint
+        );
+    }
+
+    #[test]
+    fn identicon_seed_is_deterministic_and_differs_across_keys() {
+        let public_key =
+            PublicKey::from_hex("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+                .unwrap();
+        let seed = IdenticonSeed::new(&public_key).unwrap();
+
+        // Pinned, so that accounts keep their avatar across runs and versions.
+        assert_eq!(
+            seed.seed,
+            "6dd0eeeafc437ee1615f8aa4f8975c3aad454ac42a235d09010b1c7565d1c3cd"
+        );
+        assert_eq!(seed.hue, 32);
+        assert_eq!(IdenticonSeed::new(&public_key).unwrap(), seed);
+        assert_ne!(
+            seed.seed,
+            sha256::Hash::hash(&public_key.to_bytes()).to_string()
+        );
+
+        let other_seed = IdenticonSeed::new(&Keys::generate().public_key()).unwrap();
+        assert_ne!(other_seed.seed, seed.seed);
+        assert!(other_seed.hue < 360);
+    }
+}
@@ -0,0 +1,30 @@
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// How long fetching a JSON document can take before it's given up on.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fetches JSON documents over HTTPS, e.g. from `.well-known` endpoints.
+#[async_trait]
+pub trait JsonFetcher: Send + Sync {
+    async fn get_json(&self, url: &str) -> anyhow::Result<serde_json::Value>;
+}
+
+/// Fetches JSON documents with `reqwest`, giving up after [`REQUEST_TIMEOUT`].
+pub struct HttpJsonFetcher;
+
+#[async_trait]
+impl JsonFetcher for HttpJsonFetcher {
+    async fn get_json(&self, url: &str) -> anyhow::Result<serde_json::Value> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+        Ok(client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
@@ -0,0 +1,81 @@
+use crate::nprofile;
+use crate::profile::ProfileFields;
+use nostr_sdk::{PublicKey, ToBech32};
+use serde::Serialize;
+
+/// Everything someone needs to follow an account and check that it's who it claims to be, for the user to share.
+/// Only ever holds public data.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct IdentityCard {
+    pub npub: String,
+
+    /// The account's `nprofile`, with some of its write relays as hints.
+    pub nprofile: String,
+
+    /// From the account's profile metadata, if it has any.
+    pub name: Option<String>,
+
+    /// From the account's profile metadata, if it has any.
+    pub nip05: Option<String>,
+
+    /// The lightning address from the account's profile metadata, if it has any.
+    pub lud16: Option<String>,
+}
+
+impl IdentityCard {
+    /// Builds the card for `public_key` from its write relays and, if they could be fetched, its profile fields.
+    pub fn new(
+        public_key: PublicKey,
+        write_relay_urls: &[String],
+        profile_fields: Option<ProfileFields>,
+    ) -> anyhow::Result<Self> {
+        let profile_fields = profile_fields.unwrap_or_default();
+        Ok(Self {
+            npub: public_key.to_bech32()?,
+            nprofile: nprofile::build_nprofile(public_key, write_relay_urls)?,
+            name: profile_fields.name,
+            nip05: profile_fields.nip05,
+            lud16: profile_fields.lud16,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::Keys;
+
+    #[test]
+    fn card_has_public_fields_only() {
+        let keys = Keys::generate();
+        let card = IdentityCard::new(
+            keys.public_key(),
+            &["wss://relay.example.com".to_string()],
+            Some(ProfileFields {
+                name: Some("alice".to_string()),
+                about: Some("Hi".to_string()),
+                picture: None,
+                nip05: Some("alice@example.com".to_string()),
+                lud16: Some("alice@wallet.example.com".to_string()),
+            }),
+        )
+        .unwrap();
+
+        assert_eq!(card.npub, keys.public_key().to_bech32().unwrap());
+        assert!(card.nprofile.starts_with("nprofile1"));
+        assert_eq!(card.name.as_deref(), Some("alice"));
+        assert_eq!(card.nip05.as_deref(), Some("alice@example.com"));
+        assert_eq!(card.lud16.as_deref(), Some("alice@wallet.example.com"));
+
+        // Nothing in the card reveals the secret key.
+        let secret_key = keys.secret_key().unwrap();
+        let json = serde_json::to_string(&card).unwrap();
+        assert!(!json.contains("nsec"));
+        assert!(!json.contains(&secret_key.to_bech32().unwrap()));
+        assert!(!json.contains(&secret_key.to_secret_hex()));
+
+        // Without profile fields, only the key and relay hints are included.
+        let card = IdentityCard::new(keys.public_key(), &[], None).unwrap();
+        assert_eq!((card.name, card.nip05, card.lud16), (None, None, None));
+    }
+}
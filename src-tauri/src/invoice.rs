@@ -0,0 +1,157 @@
+use crate::validation;
+use lightning_invoice::{Bolt11InvoiceDescription, Currency};
+use serde::Serialize;
+use std::time::Duration;
+
+/// The fields of a BOLT11 invoice that the user needs to see before deciding whether to pay it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DecodedInvoice {
+    /// Hex-encoded payment hash.
+    pub payment_hash: String,
+
+    /// Unset if the invoice lets the payer choose the amount.
+    pub amount_msats: Option<u64>,
+
+    /// Set if the invoice carries its description.
+    pub description: Option<String>,
+
+    /// Hex-encoded hash of the description, set if the invoice only commits to its description.
+    pub description_hash: Option<String>,
+
+    /// Hex-encoded public key of the node being paid.
+    pub payee_public_key: String,
+
+    /// The network the invoice is payable on: `bitcoin`, `testnet`, `regtest`, `simnet` or `signet`.
+    pub network: &'static str,
+
+    /// When the invoice was created, in seconds since the Unix epoch.
+    pub created_at: u64,
+
+    /// When the invoice expires, in seconds since the Unix epoch.
+    pub expires_at: u64,
+}
+
+/// Decodes a BOLT11 invoice without paying it. `now` is the time since the Unix epoch, and the invoice is
+/// rejected if it has expired by then.
+pub fn decode_invoice(invoice: &str, now: Duration) -> anyhow::Result<DecodedInvoice> {
+    let invoice = validation::validate_invoice(invoice)?;
+
+    let created_at = invoice.duration_since_epoch().as_secs();
+    let expires_at = created_at.saturating_add(invoice.expiry_time().as_secs());
+    if invoice.would_expire(now) {
+        return Err(anyhow::anyhow!(
+            "Invoice expired {} seconds ago",
+            now.as_secs().saturating_sub(expires_at)
+        ));
+    }
+
+    let (description, description_hash) = match invoice.description() {
+        Bolt11InvoiceDescription::Direct(description) => (Some(description.to_string()), None),
+        Bolt11InvoiceDescription::Hash(hash) => (None, Some(hash.0.to_string())),
+    };
+
+    Ok(DecodedInvoice {
+        payment_hash: invoice.payment_hash().to_string(),
+        amount_msats: invoice.amount_milli_satoshis(),
+        description,
+        description_hash,
+        payee_public_key: invoice.get_payee_pub_key().to_string(),
+        network: match invoice.currency() {
+            Currency::Bitcoin => "bitcoin",
+            Currency::BitcoinTestnet => "testnet",
+            Currency::Regtest => "regtest",
+            Currency::Simnet => "simnet",
+            Currency::Signet => "signet",
+        },
+        created_at,
+        expires_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::bech32::{self, Bech32, Hrp};
+    use proptest::prelude::*;
+
+    /// The example invoice from BOLT11, for 250,000 sats and one minute from creation to expiry.
+    const INVOICE: &str = "lnbc2500u1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdq5xysxxatsyp3k7enxv4jsxqzpu9qrsgquk0rl77nj30yxdy8j9vdx85fkpmdla2087ne0xh8nhedh8w27kyke0lp53ut353s06fv3qfegext0eh0ymjpf39tuven09sam30g4vgpfna3rh";
+
+    const CREATED_AT: u64 = 1_496_314_658;
+
+    #[test]
+    fn valid_invoice() {
+        let decoded = decode_invoice(INVOICE, Duration::from_secs(CREATED_AT + 30)).unwrap();
+
+        assert_eq!(
+            decoded,
+            DecodedInvoice {
+                payment_hash: "0001020304050607080900010203040506070809000102030405060708090102"
+                    .to_string(),
+                amount_msats: Some(250_000_000),
+                description: Some("1 cup coffee".to_string()),
+                description_hash: None,
+                payee_public_key:
+                    "03e7156ae33b0a208d0744199163177e909e80176e55d97a2f221ede0f934dd9ad".to_string(),
+                network: "bitcoin",
+                created_at: CREATED_AT,
+                expires_at: CREATED_AT + 60,
+            }
+        );
+
+        // The `lightning:` prefix from payment links is ignored.
+        assert_eq!(
+            decode_invoice(
+                &format!("lightning:{INVOICE}"),
+                Duration::from_secs(CREATED_AT)
+            )
+            .unwrap(),
+            decoded
+        );
+    }
+
+    #[test]
+    fn expired_invoice() {
+        assert_eq!(
+            decode_invoice(INVOICE, Duration::from_secs(CREATED_AT + 90))
+                .unwrap_err()
+                .to_string(),
+            "Invoice expired 30 seconds ago"
+        );
+    }
+
+    #[test]
+    fn malformed_invoice() {
+        assert_eq!(
+            decode_invoice(&INVOICE[..100], Duration::from_secs(CREATED_AT))
+                .unwrap_err()
+                .to_string(),
+            "Invalid invoice: the invoice is mistyped or truncated"
+        );
+        assert_eq!(
+            decode_invoice("not an invoice", Duration::from_secs(CREATED_AT))
+                .unwrap_err()
+                .to_string(),
+            "Invalid invoice: Lightning invoices start with \"ln\""
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn decoding_arbitrary_input_never_panics(input in any::<String>(), now in any::<u64>()) {
+            let _ = decode_invoice(&input, Duration::from_secs(now));
+            let _ = decode_invoice(&format!("lnbc{input}"), Duration::from_secs(now));
+        }
+
+        // Well-formed bech32 gets past the checksum, so that the invoice's fields are parsed.
+        #[test]
+        fn decoding_arbitrary_invoice_data_never_panics(
+            hrp in prop::sample::select(vec!["lnbc", "lnbc2500u", "lntb1m", "lnbcrt"]),
+            data in prop::collection::vec(any::<u8>(), 0..512),
+            now in any::<u64>(),
+        ) {
+            let invoice = bech32::encode::<Bech32>(Hrp::parse(hrp).unwrap(), &data).unwrap();
+            let _ = decode_invoice(&invoice, Duration::from_secs(now));
+        }
+    }
+}
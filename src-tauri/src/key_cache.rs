@@ -0,0 +1,60 @@
+use nostr_sdk::{PublicKey, SecretKey};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// How well the key cache is doing. Deliberately leaves out which keys are cached.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct KeyCacheStats {
+    /// Number of accounts whose key is currently cached.
+    pub cached_keys: usize,
+
+    /// Number of lookups answered from the cache.
+    pub hits: u64,
+
+    /// Number of lookups that had to go to the database.
+    pub misses: u64,
+}
+
+/// Secret keys that have been read from the database, by public key, so that signing doesn't have to read them
+/// again. Dropping a [`SecretKey`] erases it, so keys are zeroized as they leave the cache.
+#[derive(Default)]
+pub struct KeyCache {
+    keys: Mutex<HashMap<PublicKey, SecretKey>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl KeyCache {
+    /// Returns the cached key for `public_key`, or loads it with `load` and caches it if it isn't cached.
+    pub fn get_or_load(
+        &self,
+        public_key: &PublicKey,
+        load: impl FnOnce() -> Option<SecretKey>,
+    ) -> Option<SecretKey> {
+        let mut keys = self.keys.lock().unwrap();
+        if let Some(secret_key) = keys.get(public_key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Some(secret_key.clone());
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let secret_key = load()?;
+        keys.insert(*public_key, secret_key.clone());
+        Some(secret_key)
+    }
+
+    pub fn stats(&self) -> KeyCacheStats {
+        KeyCacheStats {
+            cached_keys: self.keys.lock().unwrap().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Zeroizes and forgets every cached key. The hit and miss counters are kept.
+    pub fn clear(&self) {
+        self.keys.lock().unwrap().clear();
+    }
+}
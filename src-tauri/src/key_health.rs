@@ -0,0 +1,86 @@
+use nostr_sdk::secp256k1::Secp256k1;
+use nostr_sdk::{FromBech32, PublicKey, SecretKey};
+use serde::Serialize;
+
+/// Something about a stored key that means it shouldn't be trusted with anything of value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyHealthWarning {
+    /// The stored secret doesn't decode to a valid secp256k1 secret key: it's corrupt, zero, or not less than the
+    /// curve order.
+    InvalidSecret,
+
+    /// The stored secret is valid, but isn't the secret key of the npub it's stored under.
+    PublicKeyMismatch,
+
+    /// The npub is on the list of known leaked keys, so its secret key must be assumed to be public.
+    Leaked,
+}
+
+/// Result of checking a stored key. Doesn't include the key itself.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct KeyHealth {
+    pub warnings: Vec<KeyHealthWarning>,
+}
+
+/// Checks the secret stored for `public_key`, as the nsec it's stored as, and whether the key is in `leaked_keys`.
+pub fn check_key_health(
+    public_key: &PublicKey,
+    stored_nsec: &str,
+    leaked_keys: &[PublicKey],
+) -> KeyHealth {
+    let mut warnings = Vec::new();
+
+    // Decoding checks the bech32 checksum, and that the scalar is non-zero and in range.
+    match SecretKey::from_bech32(stored_nsec) {
+        Ok(secret_key) => {
+            if secret_key.keypair(&Secp256k1::new()).x_only_public_key().0 != **public_key {
+                warnings.push(KeyHealthWarning::PublicKeyMismatch);
+            }
+        }
+        Err(_) => warnings.push(KeyHealthWarning::InvalidSecret),
+    }
+
+    if leaked_keys.contains(public_key) {
+        warnings.push(KeyHealthWarning::Leaked);
+    }
+
+    KeyHealth { warnings }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{Keys, ToBech32};
+
+    #[test]
+    fn valid_key_is_healthy() {
+        let keys = Keys::generate();
+        let nsec = keys.secret_key().unwrap().to_bech32().unwrap();
+
+        let health = check_key_health(&keys.public_key(), &nsec, &[Keys::generate().public_key()]);
+        assert!(health.warnings.is_empty());
+
+        assert_eq!(
+            check_key_health(&Keys::generate().public_key(), &nsec, &[]).warnings,
+            vec![KeyHealthWarning::PublicKeyMismatch]
+        );
+        // Changing the last character breaks the checksum.
+        let last_char = if nsec.ends_with('q') { 'p' } else { 'q' };
+        let corrupt_nsec = format!("{}{last_char}", &nsec[..nsec.len() - 1]);
+        assert_eq!(
+            check_key_health(&keys.public_key(), &corrupt_nsec, &[]).warnings,
+            vec![KeyHealthWarning::InvalidSecret]
+        );
+    }
+
+    #[test]
+    fn leaked_key_is_warned_about() {
+        let keys = Keys::generate();
+        let nsec = keys.secret_key().unwrap().to_bech32().unwrap();
+
+        let health = check_key_health(&keys.public_key(), &nsec, &[keys.public_key()]);
+        assert!(!health.warnings.is_empty());
+        assert_eq!(health.warnings, vec![KeyHealthWarning::Leaked]);
+    }
+}
@@ -0,0 +1,2173 @@
+use crate::account_completeness::AccountCompleteness;
+use crate::account_data::{self, AccountDataRefresh};
+use crate::account_stats::{AccountActivityLog, AccountStats};
+use crate::app_identity;
+use crate::app_key_allowlist::AppKeyAllowlist;
+use crate::approval_timeouts::ApprovalTimeouts;
+use crate::backup::BackupAccount;
+use crate::confirmation_phrase::ConfirmationPhrasePolicy;
+use crate::connection_log::{ConnectionLogEntry, ConnectionLogStore};
+use crate::contacts::Contact;
+use crate::database::{AccountMetadata, Database, DatabaseDiagnosis, VaultIntegrityReport};
+use crate::key_cache::{KeyCache, KeyCacheStats};
+use crate::key_health::{self, KeyHealth};
+use crate::log_retention::PrunableLog;
+use crate::passkey::PasskeyCredential;
+use crate::payment_cap::MaxSinglePayment;
+use crate::payment_ledger;
+use crate::payment_log::{PaymentHistoryFilter, PaymentLog, PaymentLogEntry};
+#[cfg(feature = "pkcs11")]
+use crate::pkcs11::HardwareKeyBinding;
+use crate::quiet_hours::QuietHours;
+use crate::relay_visibility::RelayVisibilityPolicy;
+use crate::relays::{self, RelayPolicy};
+use crate::replaceable_event::{self, ReplaceableEventLookup};
+use crate::request_attention::RequestAttention;
+use crate::scam_list::ScamList;
+use crate::seed;
+use crate::session_grants::StoredSessionGrant;
+use crate::sign_decisions::{RememberedSignDecision, SignDecisionStore};
+use crate::sign_event_request::ContentWarningPolicy;
+use crate::signing_log::{SigningLog, SigningLogEntry};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use nip_55::KeyManager;
+use nostr_sdk::secp256k1::rand::thread_rng;
+use nostr_sdk::secp256k1::{Keypair, Secp256k1};
+use nostr_sdk::{Event, Filter, FromBech32, Kind, PublicKey, SecretKey, ToBech32};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Name of the setting that stores how long settled payments are remembered for, in seconds.
+const PAYMENT_DEDUP_WINDOW_SETTING: &str = "payment_dedup_window_secs";
+
+/// Name of the setting that stores how long to wait for the user to respond to each kind of request.
+const APPROVAL_TIMEOUTS_SETTING: &str = "approval_timeouts";
+
+/// Name of the setting that stores the user's quiet hours.
+const QUIET_HOURS_SETTING: &str = "quiet_hours";
+
+/// Name of the setting that stores the cap on the amount of any single payment.
+const MAX_SINGLE_PAYMENT_SETTING: &str = "max_single_payment";
+
+/// Name of the setting that stores the session grants that haven't expired yet.
+const SESSION_GRANTS_SETTING: &str = "session_grants";
+
+/// Name of the setting that stores the user's scam list entries.
+const SCAM_LIST_SETTING: &str = "scam_list";
+
+/// Name of the setting that stores the maximum age of events that can be signed, in seconds.
+const MAX_EVENT_AGE_SETTING: &str = "max_event_age_secs";
+
+/// Name of the setting that stores how many days log entries are kept for. 0 keeps them forever.
+const LOG_RETENTION_DAYS_SETTING: &str = "log_retention_days";
+
+/// Name of the setting that stores the npubs of the apps that are given an identity of their own.
+const APP_IDENTITIES_SETTING: &str = "app_identity_npubs";
+
+/// Name of the setting that stores the npubs of the accounts that have relay hints added to their events.
+const RELAY_HINTS_SETTING: &str = "relay_hints_npubs";
+
+/// Name of the setting that stores how long after signing each account's events expire, in seconds, by npub.
+/// Accounts that aren't in it have no expiration added to their events.
+const DEFAULT_EXPIRATIONS_SETTING: &str = "default_expiration_secs";
+
+/// Name of the setting that stores what to do about events that are signed without a content warning.
+const CONTENT_WARNING_POLICY_SETTING: &str = "content_warning_policy";
+
+/// Name of the setting that stores which high-risk actions need a typed confirmation phrase.
+const CONFIRMATION_PHRASES_SETTING: &str = "confirmation_phrases";
+
+/// Name of the setting that stores how the user's attention is drawn to incoming requests.
+const REQUEST_ATTENTION_SETTING: &str = "request_attention";
+
+/// Name of the setting that stores the npubs of keys that are known to have leaked.
+const LEAKED_KEYS_SETTING: &str = "leaked_key_npubs";
+
+/// Name of the setting that stores whether offline mode is enabled.
+const OFFLINE_MODE_SETTING: &str = "offline_mode";
+
+/// Name of the setting that stores which app keys are allowed to connect.
+const APP_KEY_ALLOWLIST_SETTING: &str = "app_key_allowlist";
+
+/// Name of the setting that stores which relays apps are shown.
+const RELAY_VISIBILITY_SETTING: &str = "relay_visibility";
+
+/// Name of the setting that stores the registered passkeys.
+const PASSKEY_CREDENTIALS_SETTING: &str = "passkey_credentials";
+
+/// Name of the setting that stores which accounts sign with a key on a PKCS#11 token, and where the key is.
+#[cfg(feature = "pkcs11")]
+const HARDWARE_KEY_BINDINGS_SETTING: &str = "hardware_key_bindings";
+
+/// Name of the setting that stores the npubs of accounts whose key the user has confirmed they've backed up.
+const BACKUP_ACKNOWLEDGED_SETTING: &str = "backup_acknowledged_npubs";
+
+/// Name of the setting that stores the NIP-05 identifier last verified for each account, by npub.
+const VERIFIED_NIP05S_SETTING: &str = "verified_nip05s";
+
+/// Returned when a feature that needs network access is used while offline mode is enabled.
+#[derive(Debug, PartialEq, Eq)]
+pub struct OfflineModeError;
+
+impl std::fmt::Display for OfflineModeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Keystache is in offline mode")
+    }
+}
+
+impl std::error::Error for OfflineModeError {}
+
+/// Returned when the passphrase given to confirm a sensitive operation isn't the vault's passphrase.
+#[derive(Debug, PartialEq, Eq)]
+pub struct WrongPassphraseError;
+
+impl std::fmt::Display for WrongPassphraseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Wrong passphrase")
+    }
+}
+
+impl std::error::Error for WrongPassphraseError {}
+
+/// Returned when an operation needs the vault's keys, but the vault hasn't been unlocked.
+#[derive(Debug, PartialEq, Eq)]
+pub struct VaultLockedError;
+
+impl std::fmt::Display for VaultLockedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Vault is locked")
+    }
+}
+
+impl std::error::Error for VaultLockedError {}
+
+/// Outcome of importing a single nsec in a bulk import.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BulkImportResult {
+    /// The key was saved.
+    Imported { npub: String },
+
+    /// The key was already saved (or appeared earlier in the same import).
+    AlreadyPresent { npub: String },
+
+    /// The entry couldn't be imported.
+    Invalid { reason: String },
+}
+
+/// How far along first-run setup is, so the frontend knows which screen to show.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SetupState {
+    /// The vault is encrypted, and can't be opened until the user enters its passphrase.
+    VaultLocked,
+
+    /// The vault isn't encrypted yet, so the user needs to choose a passphrase for it.
+    NeedsVaultPassphrase,
+
+    /// The vault is open, but there's no account to sign with yet.
+    NeedsFirstAccount,
+
+    /// Setup is complete.
+    Ready,
+}
+
+/// An account, as listed to the user.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AccountListEntry {
+    pub npub: String,
+
+    /// Whether Keystache has the account's key. Watch-only accounts can't sign.
+    pub can_sign: bool,
+}
+
+/// An app that has been paired with one of the user's accounts.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AppAuthorization {
+    /// The app's npub, which identifies it.
+    pub app_npub: String,
+
+    pub display_name: Option<String>,
+
+    /// The npub of the account that the app is paired with.
+    pub identity_npub: String,
+
+    /// The sign decisions the user asked to remember for the app, by event kind.
+    pub sign_decisions: Vec<RememberedSignDecision>,
+
+    /// The app's session grant, if it has one that hasn't expired.
+    pub session_grant: Option<StoredSessionGrant>,
+
+    /// The most the app can pay in a single payment. There are no per-app limits, so this is the same for every app.
+    pub max_single_payment: Option<MaxSinglePayment>,
+
+    /// When the app last had an event signed or asked for a payment, as an RFC 3339 timestamp, or `None` if it
+    /// never has.
+    pub last_used_time: Option<String>,
+}
+
+pub struct KeystacheKeyManager {
+    /// Database handle. Unset if the database couldn't be opened without a passphrase and hasn't been unlocked
+    /// with [`Self::unlock_vault`] yet.
+    database_or: OnceLock<Database>,
+
+    /// Folder the database is kept in, or `None` if the app's data directory couldn't be found.
+    database_dir_or: Option<PathBuf>,
+
+    /// Secret keys that have already been read from the database.
+    key_cache: KeyCache,
+}
+
+impl KeystacheKeyManager {
+    pub fn new(app_handle: tauri::AppHandle) -> Self {
+        Self::new_in_dir(app_handle.path_resolver().app_data_dir())
+    }
+
+    /// Opens the database in `database_dir_or` if it isn't encrypted. An encrypted database stays locked until
+    /// [`Self::unlock_vault`] is called.
+    fn new_in_dir(database_dir_or: Option<PathBuf>) -> Self {
+        let database_or = OnceLock::new();
+        if let Some(database) = database_dir_or
+            .as_deref()
+            .and_then(|database_dir| Database::open_in_dir(database_dir, None).ok())
+        {
+            let _ = database_or.set(database);
+        }
+
+        Self {
+            database_or,
+            database_dir_or,
+            key_cache: KeyCache::default(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn new_with_database(database: Database) -> Self {
+        Self {
+            database_or: OnceLock::from(database),
+            database_dir_or: None,
+            key_cache: KeyCache::default(),
+        }
+    }
+
+    fn database(&self) -> anyhow::Result<&Database> {
+        match self.database_or.get() {
+            Some(database) => Ok(database),
+            None => Err(anyhow::Error::msg("No database available")),
+        }
+    }
+
+    /// Wipe all existing keypairs and save a new one.
+    /// TODO: Once we support multiple keypairs, we should remove this.
+    pub fn set_keypair(&self, keypair: Keypair) -> anyhow::Result<()> {
+        let database = self.database()?;
+
+        // Wipe all existing keypairs.
+        // TODO: Hardcoding the limit here isn't very robust. Should we allow for
+        // setting it to `None` to allow for iterating through all keypairs?
+        for keypair in database.list_keypairs(10_000, 0)? {
+            database.remove_keypair(&keypair.x_only_public_key().0.into())?;
+        }
+        self.key_cache.clear();
+
+        // Save the new keypair.
+        database.save_keypair(&keypair)
+    }
+
+    /// Saves a keypair alongside any existing ones.
+    pub fn add_keypair(&self, keypair: &Keypair) -> anyhow::Result<()> {
+        let database = self.database()?;
+        database.save_keypair(keypair)
+    }
+
+    /// Saves a keypair as a new account with its label and relays. Either all of it is saved or, if any part
+    /// fails, none of it is.
+    pub fn create_account(
+        &self,
+        keypair: &Keypair,
+        label: Option<&str>,
+        relays: &[(String, RelayPolicy)],
+    ) -> anyhow::Result<()> {
+        self.database()?.create_account(keypair, label, relays)
+    }
+
+    /// Adds the account at NIP-06 account `index` of a BIP-39 mnemonic. The mnemonic itself isn't stored,
+    /// but the index is, so that the user can tell which of their seed's accounts each key is.
+    pub fn derive_account(&self, mnemonic: &str, index: u32) -> anyhow::Result<PublicKey> {
+        let keys = seed::derive_account(mnemonic, index)?;
+        let keypair = Keypair::from_secret_key(&Secp256k1::new(), keys.secret_key()?);
+        self.database()?.save_derived_keypair(&keypair, index)?;
+        Ok(keys.public_key())
+    }
+
+    /// Replaces a (e.g. compromised) account with a newly generated key. The new account gets the old
+    /// one's label and relays, and the old account is kept but marked as retired. Returns the new key.
+    pub fn rotate_account(&self, old_public_key: &PublicKey) -> anyhow::Result<Keypair> {
+        let new_keypair = Keypair::new(&Secp256k1::new(), &mut thread_rng());
+        self.database()?
+            .rotate_keypair(old_public_key, &new_keypair)?;
+        Ok(new_keypair)
+    }
+
+    pub fn set_account_label(
+        &self,
+        public_key: &PublicKey,
+        label: Option<&str>,
+    ) -> anyhow::Result<()> {
+        self.database()?.set_account_label(public_key, label)
+    }
+
+    pub fn get_account_metadata(&self, public_key: &PublicKey) -> anyhow::Result<AccountMetadata> {
+        self.database()?.get_account_metadata(public_key)
+    }
+
+    /// Imports each nsec alongside any existing keys, skipping ones that are already saved.
+    /// An invalid entry doesn't stop the rest from being imported. Results are in the same order as `nsecs`.
+    pub fn bulk_import(&self, nsecs: &[String]) -> anyhow::Result<Vec<BulkImportResult>> {
+        let database = self.database()?;
+        let secp = Secp256k1::new();
+
+        let mut results = Vec::with_capacity(nsecs.len());
+        for nsec in nsecs {
+            let keypair = match SecretKey::from_bech32(nsec.trim()) {
+                Ok(secret_key) => secret_key.keypair(&secp),
+                Err(_) => {
+                    results.push(BulkImportResult::Invalid {
+                        reason: "Error parsing nsec".to_string(),
+                    });
+                    continue;
+                }
+            };
+            results.push(self.import_keypair(database, &keypair, None)?);
+        }
+
+        Ok(results)
+    }
+
+    /// Imports the accounts read from a backup alongside any existing keys, skipping ones that are already saved.
+    /// Newly imported accounts keep the label they had in the backup. Results are in the same order as `accounts`.
+    pub fn import_backup(
+        &self,
+        accounts: Vec<BackupAccount>,
+    ) -> anyhow::Result<Vec<BulkImportResult>> {
+        let database = self.database()?;
+        let secp = Secp256k1::new();
+
+        let mut results = Vec::with_capacity(accounts.len());
+        for account in accounts {
+            let keypair = account.secret_key.keypair(&secp);
+            results.push(self.import_keypair(database, &keypair, account.label.as_deref())?);
+        }
+
+        Ok(results)
+    }
+
+    fn import_keypair(
+        &self,
+        database: &Database,
+        keypair: &Keypair,
+        label: Option<&str>,
+    ) -> anyhow::Result<BulkImportResult> {
+        let public_key = PublicKey::from(keypair.x_only_public_key().0);
+        let npub = public_key.to_bech32()?;
+
+        if self.get_secret_key(&public_key).is_some() {
+            Ok(BulkImportResult::AlreadyPresent { npub })
+        } else {
+            database.create_account(keypair, label, &[])?;
+            Ok(BulkImportResult::Imported { npub })
+        }
+    }
+
+    /// Lists the public keys of all saved keypairs, in the order they were added.
+    pub fn list_accounts(&self) -> anyhow::Result<Vec<PublicKey>> {
+        let database = self.database()?;
+        // TODO: Hardcoding the limit here isn't very robust.
+        database.list_public_keys(10_000, 0)
+    }
+
+    /// Where the user is in first-run setup. Watch-only accounts don't count as a first account, since they can't sign.
+    pub fn get_setup_state(&self) -> anyhow::Result<SetupState> {
+        let Some(database) = self.database_or.get() else {
+            return Ok(SetupState::VaultLocked);
+        };
+        if !database.is_encrypted() {
+            return Ok(SetupState::NeedsVaultPassphrase);
+        }
+        match database.get_first_public_key()? {
+            Some(_) => Ok(SetupState::Ready),
+            None => Ok(SetupState::NeedsFirstAccount),
+        }
+    }
+
+    /// Adds an npub as a watch-only account, for monitoring an account without being able to sign for it.
+    pub fn add_watch_only_account(&self, public_key: &PublicKey) -> anyhow::Result<()> {
+        self.database()?.add_watch_only_account(public_key)
+    }
+
+    /// Lists every account, including watch-only ones: first the accounts that can sign, in the order they were
+    /// added, then the watch-only accounts.
+    pub fn list_account_entries(&self) -> anyhow::Result<Vec<AccountListEntry>> {
+        let mut entries = Vec::new();
+        for public_key in self.list_accounts()? {
+            entries.push(AccountListEntry {
+                npub: public_key.to_bech32()?,
+                can_sign: true,
+            });
+        }
+        for public_key in self.database()?.list_watch_only_public_keys()? {
+            entries.push(AccountListEntry {
+                npub: public_key.to_bech32()?,
+                can_sign: false,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Opens the encrypted vault with its passphrase. Does nothing if the vault is already open.
+    pub fn unlock_vault(&self, passphrase: &str) -> anyhow::Result<()> {
+        if self.database_or.get().is_some() {
+            return Ok(());
+        }
+        let database_dir = self
+            .database_dir_or
+            .as_deref()
+            .ok_or(anyhow::anyhow!("App data dir not found"))?;
+        let database = Database::open_in_dir(database_dir, Some(passphrase))
+            .map_err(|_| WrongPassphraseError)?;
+        // Another unlock may have won the race, in which case its database is used.
+        let _ = self.database_or.set(database);
+        Ok(())
+    }
+
+    /// Encrypts every stored key, along with the rest of the vault, with `passphrase`, for existing installs
+    /// whose vault was created without one. From the next launch on, the vault must be unlocked with the
+    /// passphrase. Errors if the vault is already encrypted.
+    pub fn encrypt_existing_keys(&self, passphrase: &str) -> anyhow::Result<()> {
+        self.database()?.encrypt(passphrase)
+    }
+
+    /// Confirms that `passphrase` is the vault's passphrase, before a gated operation when no passkey is enrolled.
+    /// Always fails if the vault hasn't been encrypted with a passphrase (see `encrypt_existing_keys`).
+    pub fn verify_passphrase(&self, passphrase: &str) -> anyhow::Result<()> {
+        if !self.database()?.verify_encryption_key(passphrase) {
+            return Err(WrongPassphraseError.into());
+        }
+        Ok(())
+    }
+
+    /// Erases every key and setting, once `passphrase` is confirmed to be the vault's passphrase.
+    /// Afterwards the vault is empty, and an account must be added again before Keystache can sign.
+    pub fn wipe_all_data(&self, passphrase: &str) -> anyhow::Result<()> {
+        let database = self.database()?;
+        if !database.verify_encryption_key(passphrase) {
+            return Err(WrongPassphraseError.into());
+        }
+        self.key_cache.clear();
+        database.wipe()
+    }
+
+    pub fn get_key_cache_stats(&self) -> KeyCacheStats {
+        self.key_cache.stats()
+    }
+
+    /// Zeroizes and forgets every cached secret key. Keys are read from the database again when next needed.
+    pub fn clear_key_cache(&self) {
+        self.key_cache.clear();
+    }
+
+    /// Checks the vault for corruption, once `passphrase` is confirmed to be the vault's passphrase.
+    /// Reports how many keys are intact or corrupt, without returning any of them.
+    pub fn verify_vault_integrity(&self, passphrase: &str) -> anyhow::Result<VaultIntegrityReport> {
+        let database = self.database()?;
+        if !database.verify_encryption_key(passphrase) {
+            return Err(WrongPassphraseError.into());
+        }
+        database.verify_integrity()
+    }
+
+    /// Fixes any stored npub that doesn't match the nsec it's stored with, by re-deriving it from the nsec.
+    /// Returns how many npubs were fixed. Fails if the vault is locked, since the nsecs can't be read.
+    pub fn repair_npubs(&self) -> anyhow::Result<usize> {
+        let Some(database) = self.database_or.get() else {
+            return Err(VaultLockedError.into());
+        };
+        database.repair_npubs()
+    }
+
+    /// Checks that the secret stored for `public_key` is a valid secp256k1 secret key for it, and that the key isn't
+    /// on the list of leaked keys. Errors if there's no key stored for it.
+    pub fn check_key_health(&self, public_key: &PublicKey) -> anyhow::Result<KeyHealth> {
+        let stored_nsec = self
+            .database()?
+            .get_stored_nsec(public_key)?
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "No key stored for {}",
+                    public_key.to_bech32().unwrap_or_default()
+                )
+            })?;
+        Ok(key_health::check_key_health(
+            public_key,
+            &stored_nsec,
+            &self.get_leaked_keys()?,
+        ))
+    }
+
+    /// Returns the keys that are known to have leaked, e.g. from a published breach.
+    pub fn get_leaked_keys(&self) -> anyhow::Result<Vec<PublicKey>> {
+        self.database()?
+            .get_setting::<Vec<String>>(LEAKED_KEYS_SETTING)?
+            .unwrap_or_default()
+            .iter()
+            .map(|npub| Ok(PublicKey::from_bech32(npub)?))
+            .collect()
+    }
+
+    pub fn set_leaked_keys(&self, public_keys: &[PublicKey]) -> anyhow::Result<()> {
+        let npubs = public_keys
+            .iter()
+            .map(ToBech32::to_bech32)
+            .collect::<Result<Vec<String>, _>>()?;
+        self.database()?.set_setting(LEAKED_KEYS_SETTING, &npubs)
+    }
+
+    /// Whether the user has confirmed that they've backed up the account's key.
+    pub fn is_backup_acknowledged(&self, public_key: &PublicKey) -> anyhow::Result<bool> {
+        Ok(self
+            .backup_acknowledged_npubs()?
+            .contains(&public_key.to_bech32()?))
+    }
+
+    pub fn set_backup_acknowledged(
+        &self,
+        public_key: &PublicKey,
+        acknowledged: bool,
+    ) -> anyhow::Result<()> {
+        let npub = public_key.to_bech32()?;
+        let mut npubs = self.backup_acknowledged_npubs()?;
+        npubs.retain(|existing_npub| *existing_npub != npub);
+        if acknowledged {
+            npubs.push(npub);
+        }
+        self.database()?
+            .set_setting(BACKUP_ACKNOWLEDGED_SETTING, &npubs)
+    }
+
+    fn backup_acknowledged_npubs(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .database()?
+            .get_setting::<Vec<String>>(BACKUP_ACKNOWLEDGED_SETTING)?
+            .unwrap_or_default())
+    }
+
+    /// Returns the NIP-05 identifier that was last verified to point to the account, if any.
+    pub fn get_verified_nip05(&self, public_key: &PublicKey) -> anyhow::Result<Option<String>> {
+        Ok(self.verified_nip05s()?.remove(&public_key.to_bech32()?))
+    }
+
+    /// Records `identifier` as verified to point to the account, or forgets the account's verified identifier if
+    /// `None`.
+    pub fn set_verified_nip05(
+        &self,
+        public_key: &PublicKey,
+        identifier: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let npub = public_key.to_bech32()?;
+        let mut verified_nip05s = self.verified_nip05s()?;
+        match identifier {
+            Some(identifier) => verified_nip05s.insert(npub, identifier.to_string()),
+            None => verified_nip05s.remove(&npub),
+        };
+        self.database()?
+            .set_setting(VERIFIED_NIP05S_SETTING, &verified_nip05s)
+    }
+
+    fn verified_nip05s(&self) -> anyhow::Result<HashMap<String, String>> {
+        Ok(self
+            .database()?
+            .get_setting::<HashMap<String, String>>(VERIFIED_NIP05S_SETTING)?
+            .unwrap_or_default())
+    }
+
+    /// Checks which steps of setting up the account well have been done.
+    pub fn get_account_completeness(
+        &self,
+        public_key: &PublicKey,
+    ) -> anyhow::Result<AccountCompleteness> {
+        let database = self.database()?;
+        Ok(AccountCompleteness {
+            has_label: self
+                .get_account_metadata(public_key)?
+                .label
+                .is_some_and(|label| !label.trim().is_empty()),
+            has_relays: !self.list_relays(public_key)?.is_empty(),
+            has_verified_nip05: self.get_verified_nip05(public_key)?.is_some(),
+            has_acknowledged_backup: self.is_backup_acknowledged(public_key)?,
+            uses_encryption: database.is_encrypted(),
+        })
+    }
+
+    /// Checks the vault's database for drift from the expected schema, re-creating missing tables and indexes if
+    /// `repair` is `true`. Never deletes data.
+    pub fn diagnose_database(&self, repair: bool) -> anyhow::Result<DatabaseDiagnosis> {
+        self.database()?.diagnose(repair)
+    }
+
+    /// Lists every app that has been paired with an account, along with what it has been granted.
+    pub fn list_authorizations(&self) -> anyhow::Result<Vec<AppAuthorization>> {
+        let database = self.database()?;
+        let sign_decisions = database.list_sign_decisions()?;
+        let session_grants = self.get_session_grants()?;
+        let max_single_payment = self.get_max_single_payment()?;
+        let now = nostr_sdk::Timestamp::now().as_u64();
+        // TODO: Hardcoding the limit here isn't very robust.
+        database
+            .list_registered_applications(10_000, 0)?
+            .into_iter()
+            .map(|(display_name, app_public_key, identity_public_key)| {
+                let app_npub = app_public_key.to_bech32()?;
+                Ok(AppAuthorization {
+                    sign_decisions: sign_decisions
+                        .iter()
+                        .filter(|decision| decision.app_npub == app_npub)
+                        .cloned()
+                        .collect(),
+                    session_grant: session_grants
+                        .iter()
+                        .find(|grant| grant.app_npub == app_npub && grant.expires_at > now)
+                        .cloned(),
+                    max_single_payment: max_single_payment.clone(),
+                    last_used_time: database.get_application_last_used_time(&app_npub)?,
+                    app_npub,
+                    display_name,
+                    identity_npub: identity_public_key.to_bech32()?,
+                })
+            })
+            .collect()
+    }
+
+    /// Removes an app's pairing, along with everything it has been granted.
+    pub fn revoke_authorization(&self, app_public_key: &PublicKey) -> anyhow::Result<()> {
+        let database = self.database()?;
+        database.unregister_application(app_public_key)
+    }
+
+    /// The saved session grants. Some may have expired since they were saved.
+    pub fn get_session_grants(&self) -> anyhow::Result<Vec<StoredSessionGrant>> {
+        let database = self.database()?;
+        Ok(database
+            .get_setting::<Vec<StoredSessionGrant>>(SESSION_GRANTS_SETTING)?
+            .unwrap_or_default())
+    }
+
+    pub fn set_session_grants(&self, session_grants: &[StoredSessionGrant]) -> anyhow::Result<()> {
+        let database = self.database()?;
+        database.set_setting(SESSION_GRANTS_SETTING, &session_grants)
+    }
+
+    /// Lists the sign decisions the user asked to remember, by app and kind.
+    pub fn list_sign_decisions(&self) -> anyhow::Result<Vec<RememberedSignDecision>> {
+        let database = self.database()?;
+        database.list_sign_decisions()
+    }
+
+    /// Forgets the remembered decision for an app and kind, so the user is asked again next time.
+    pub fn forget_sign_decision(
+        &self,
+        app_public_key: &PublicKey,
+        kind: Kind,
+    ) -> anyhow::Result<()> {
+        let database = self.database()?;
+        database.remove_sign_decision(app_public_key, kind)
+    }
+
+    /// Sets the friendly name an app is shown with in approval prompts, or clears it if `name` is `None`.
+    pub fn set_app_name(
+        &self,
+        app_public_key: &PublicKey,
+        name: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let database = self.database()?;
+        database.set_application_display_name(app_public_key, name)
+    }
+
+    /// Map of paired apps to the names the user has given them. Apps without a name are left out.
+    pub fn get_app_names(&self) -> anyhow::Result<HashMap<PublicKey, String>> {
+        let database = self.database()?;
+        // TODO: Hardcoding the limit here isn't very robust.
+        Ok(database
+            .list_registered_applications(10_000, 0)?
+            .into_iter()
+            .filter_map(|(display_name, app_public_key, _)| {
+                display_name.map(|display_name| (app_public_key, display_name))
+            })
+            .collect())
+    }
+
+    /// Lists requests to pay invoices that match `filter`, most recent first.
+    pub fn get_payment_history(
+        &self,
+        limit: u64,
+        offset: u64,
+        filter: &PaymentHistoryFilter,
+    ) -> anyhow::Result<Vec<PaymentLogEntry>> {
+        self.database()?
+            .list_payment_log_entries(limit, offset, filter)
+    }
+
+    /// Lists what the app with `app_public_key` has asked to sign, most recent first.
+    pub fn get_app_signing_history(
+        &self,
+        app_public_key: &PublicKey,
+        limit: u64,
+        offset: u64,
+    ) -> anyhow::Result<Vec<SigningLogEntry>> {
+        self.database()?
+            .list_signing_log_entries(&app_public_key.to_bech32()?, limit, offset)
+    }
+
+    pub fn get_scam_list(&self) -> anyhow::Result<ScamList> {
+        let database = self.database()?;
+        match database.get_setting::<Vec<String>>(SCAM_LIST_SETTING)? {
+            Some(entries) => ScamList::from_entries(&entries),
+            None => Ok(ScamList::default()),
+        }
+    }
+
+    /// Replaces the scam list with `entries`. Errors without saving anything if any entry is invalid.
+    pub fn set_scam_list(&self, entries: &[String]) -> anyhow::Result<ScamList> {
+        let scam_list = ScamList::from_entries(entries)?;
+        self.database()?
+            .set_setting(SCAM_LIST_SETTING, &scam_list.entries()?)?;
+        Ok(scam_list)
+    }
+
+    pub fn get_public_key(&self) -> anyhow::Result<Option<PublicKey>> {
+        let database = self.database()?;
+        database.get_first_public_key()
+    }
+
+    pub fn get_payment_dedup_window(&self) -> anyhow::Result<Duration> {
+        let database = self.database()?;
+        Ok(database
+            .get_setting::<u64>(PAYMENT_DEDUP_WINDOW_SETTING)?
+            .map(Duration::from_secs)
+            .unwrap_or(payment_ledger::DEFAULT_DEDUP_WINDOW))
+    }
+
+    pub fn set_payment_dedup_window(&self, dedup_window: Duration) -> anyhow::Result<()> {
+        let database = self.database()?;
+        database.set_setting(PAYMENT_DEDUP_WINDOW_SETTING, &dedup_window.as_secs())
+    }
+
+    pub fn get_approval_timeouts(&self) -> anyhow::Result<ApprovalTimeouts> {
+        let database = self.database()?;
+        Ok(database
+            .get_setting::<ApprovalTimeouts>(APPROVAL_TIMEOUTS_SETTING)?
+            .unwrap_or_default())
+    }
+
+    pub fn set_approval_timeouts(
+        &self,
+        approval_timeouts: &ApprovalTimeouts,
+    ) -> anyhow::Result<()> {
+        approval_timeouts.validate()?;
+        let database = self.database()?;
+        database.set_setting(APPROVAL_TIMEOUTS_SETTING, approval_timeouts)
+    }
+
+    /// Returns `None` if quiet hours are off.
+    pub fn get_quiet_hours(&self) -> anyhow::Result<Option<QuietHours>> {
+        let database = self.database()?;
+        Ok(database
+            .get_setting::<Option<QuietHours>>(QUIET_HOURS_SETTING)?
+            .flatten())
+    }
+
+    pub fn set_quiet_hours(&self, quiet_hours: Option<&QuietHours>) -> anyhow::Result<()> {
+        if let Some(quiet_hours) = quiet_hours {
+            quiet_hours.validate()?;
+        }
+        let database = self.database()?;
+        database.set_setting(QUIET_HOURS_SETTING, &quiet_hours)
+    }
+
+    /// Returns `None` if there's no cap on single payments.
+    pub fn get_max_single_payment(&self) -> anyhow::Result<Option<MaxSinglePayment>> {
+        let database = self.database()?;
+        Ok(database
+            .get_setting::<Option<MaxSinglePayment>>(MAX_SINGLE_PAYMENT_SETTING)?
+            .flatten())
+    }
+
+    pub fn set_max_single_payment(
+        &self,
+        max_single_payment: Option<&MaxSinglePayment>,
+    ) -> anyhow::Result<()> {
+        if let Some(max_single_payment) = max_single_payment {
+            max_single_payment.validate()?;
+        }
+        let database = self.database()?;
+        database.set_setting(MAX_SINGLE_PAYMENT_SETTING, &max_single_payment)
+    }
+
+    /// Whether relay hints are added to the `e` and `p` tags of events the account signs.
+    pub fn is_relay_hints_enabled(&self, public_key: &PublicKey) -> anyhow::Result<bool> {
+        Ok(self.relay_hints_npubs()?.contains(&public_key.to_bech32()?))
+    }
+
+    pub fn set_relay_hints_enabled(
+        &self,
+        public_key: &PublicKey,
+        enabled: bool,
+    ) -> anyhow::Result<()> {
+        let npub = public_key.to_bech32()?;
+        let mut npubs = self.relay_hints_npubs()?;
+        npubs.retain(|existing_npub| *existing_npub != npub);
+        if enabled {
+            npubs.push(npub);
+        }
+        self.database()?.set_setting(RELAY_HINTS_SETTING, &npubs)
+    }
+
+    /// Whether the app is given an identity of its own, derived from the account's key, instead of the account's.
+    pub fn is_app_identity_enabled(&self, app_public_key: &PublicKey) -> anyhow::Result<bool> {
+        Ok(self
+            .app_identity_npubs()?
+            .contains(&app_public_key.to_bech32()?))
+    }
+
+    pub fn set_app_identity_enabled(
+        &self,
+        app_public_key: &PublicKey,
+        enabled: bool,
+    ) -> anyhow::Result<()> {
+        let npub = app_public_key.to_bech32()?;
+        let mut npubs = self.app_identity_npubs()?;
+        npubs.retain(|existing_npub| *existing_npub != npub);
+        if enabled {
+            npubs.push(npub);
+        }
+        self.database()?
+            .set_setting(APP_IDENTITIES_SETTING, &npubs)?;
+        // Cached keys of the app's identity would otherwise still sign after it's turned off.
+        self.key_cache.clear();
+        Ok(())
+    }
+
+    /// The public key that the app is given for the active account: the app's own identity if it has one turned
+    /// on, or the account's otherwise. `None` if there's no account.
+    pub fn get_public_key_for_app(
+        &self,
+        app_public_key: &PublicKey,
+    ) -> anyhow::Result<Option<PublicKey>> {
+        let Some(public_key) = self.get_public_key()? else {
+            return Ok(None);
+        };
+        if !self.is_app_identity_enabled(app_public_key)? {
+            return Ok(Some(public_key));
+        }
+        let secret_key = self
+            .get_secret_key(&public_key)
+            .ok_or_else(|| anyhow::anyhow!("No key available for the active account"))?;
+        Ok(Some(
+            app_identity::derive_app_keys(&secret_key, app_public_key)?.public_key(),
+        ))
+    }
+
+    /// The relay hint to add to each account's events, for the accounts that have relay hints turned on.
+    /// The hint is the account's first write relay, so accounts without one are left out.
+    pub fn get_relay_hints(&self) -> anyhow::Result<HashMap<PublicKey, String>> {
+        let mut relay_hints = HashMap::new();
+        for npub in self.relay_hints_npubs()? {
+            let public_key = PublicKey::from_bech32(&npub)?;
+            if let Some(relay_url) = self.list_write_relay_urls(&public_key)?.into_iter().next() {
+                relay_hints.insert(public_key, relay_url);
+            }
+        }
+        Ok(relay_hints)
+    }
+
+    /// How long after signing the account's events expire, or `None` if no expiration is added to them.
+    pub fn get_default_expiration(
+        &self,
+        public_key: &PublicKey,
+    ) -> anyhow::Result<Option<Duration>> {
+        Ok(self.get_default_expirations()?.remove(public_key))
+    }
+
+    /// Pass `None` to stop adding an expiration to the account's events.
+    pub fn set_default_expiration(
+        &self,
+        public_key: &PublicKey,
+        default_expiration: Option<Duration>,
+    ) -> anyhow::Result<()> {
+        let npub = public_key.to_bech32()?;
+        let mut expiration_secs = self.default_expiration_secs()?;
+        match default_expiration {
+            Some(default_expiration) => {
+                expiration_secs.insert(npub, default_expiration.as_secs());
+            }
+            None => {
+                expiration_secs.remove(&npub);
+            }
+        }
+        self.database()?
+            .set_setting(DEFAULT_EXPIRATIONS_SETTING, &expiration_secs)
+    }
+
+    /// How long after signing each account's events expire, for the accounts that have a default expiration.
+    pub fn get_default_expirations(&self) -> anyhow::Result<HashMap<PublicKey, Duration>> {
+        self.default_expiration_secs()?
+            .into_iter()
+            .map(|(npub, secs)| Ok((PublicKey::from_bech32(npub)?, Duration::from_secs(secs))))
+            .collect()
+    }
+
+    fn default_expiration_secs(&self) -> anyhow::Result<HashMap<String, u64>> {
+        Ok(self
+            .database()?
+            .get_setting::<HashMap<String, u64>>(DEFAULT_EXPIRATIONS_SETTING)?
+            .unwrap_or_default())
+    }
+
+    fn app_identity_npubs(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .database()?
+            .get_setting::<Vec<String>>(APP_IDENTITIES_SETTING)?
+            .unwrap_or_default())
+    }
+
+    fn relay_hints_npubs(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .database()?
+            .get_setting::<Vec<String>>(RELAY_HINTS_SETTING)?
+            .unwrap_or_default())
+    }
+
+    /// Returns `None` if events of any age can be signed.
+    pub fn get_max_event_age(&self) -> anyhow::Result<Option<Duration>> {
+        let database = self.database()?;
+        Ok(database
+            .get_setting::<Option<u64>>(MAX_EVENT_AGE_SETTING)?
+            .flatten()
+            .map(Duration::from_secs))
+    }
+
+    pub fn set_max_event_age(&self, max_event_age: Option<Duration>) -> anyhow::Result<()> {
+        let database = self.database()?;
+        database.set_setting(
+            MAX_EVENT_AGE_SETTING,
+            &max_event_age.map(|max_event_age| max_event_age.as_secs()),
+        )
+    }
+
+    pub fn get_content_warning_policy(&self) -> anyhow::Result<ContentWarningPolicy> {
+        let database = self.database()?;
+        Ok(database
+            .get_setting::<ContentWarningPolicy>(CONTENT_WARNING_POLICY_SETTING)?
+            .unwrap_or_default())
+    }
+
+    pub fn set_content_warning_policy(
+        &self,
+        content_warning_policy: ContentWarningPolicy,
+    ) -> anyhow::Result<()> {
+        let database = self.database()?;
+        database.set_setting(CONTENT_WARNING_POLICY_SETTING, &content_warning_policy)
+    }
+
+    pub fn get_confirmation_phrases(&self) -> anyhow::Result<ConfirmationPhrasePolicy> {
+        let database = self.database()?;
+        Ok(database
+            .get_setting::<ConfirmationPhrasePolicy>(CONFIRMATION_PHRASES_SETTING)?
+            .unwrap_or_default())
+    }
+
+    pub fn set_confirmation_phrases(
+        &self,
+        confirmation_phrases: &ConfirmationPhrasePolicy,
+    ) -> anyhow::Result<()> {
+        let database = self.database()?;
+        database.set_setting(CONFIRMATION_PHRASES_SETTING, confirmation_phrases)
+    }
+
+    pub fn get_request_attention(&self) -> anyhow::Result<RequestAttention> {
+        let database = self.database()?;
+        Ok(database
+            .get_setting::<RequestAttention>(REQUEST_ATTENTION_SETTING)?
+            .unwrap_or_default())
+    }
+
+    pub fn set_request_attention(&self, request_attention: RequestAttention) -> anyhow::Result<()> {
+        let database = self.database()?;
+        database.set_setting(REQUEST_ATTENTION_SETTING, &request_attention)
+    }
+
+    /// How many days log entries are kept for before they're pruned. 0, the default, keeps them forever.
+    pub fn get_log_retention_days(&self) -> anyhow::Result<u64> {
+        let database = self.database()?;
+        Ok(database
+            .get_setting::<u64>(LOG_RETENTION_DAYS_SETTING)?
+            .unwrap_or(0))
+    }
+
+    pub fn set_log_retention_days(&self, log_retention_days: u64) -> anyhow::Result<()> {
+        let database = self.database()?;
+        database.set_setting(LOG_RETENTION_DAYS_SETTING, &log_retention_days)
+    }
+
+    /// Whether offline mode is enabled. While it is, Keystache makes no outbound
+    /// connections. Signing and reading public keys still work.
+    pub fn is_offline_mode(&self) -> anyhow::Result<bool> {
+        let database = self.database()?;
+        Ok(database
+            .get_setting::<bool>(OFFLINE_MODE_SETTING)?
+            .unwrap_or(false))
+    }
+
+    pub fn set_offline_mode(&self, offline_mode: bool) -> anyhow::Result<()> {
+        let database = self.database()?;
+        database.set_setting(OFFLINE_MODE_SETTING, &offline_mode)
+    }
+
+    /// Returns an [`OfflineModeError`] if offline mode is enabled.
+    /// Call this before doing anything that needs network access.
+    pub fn ensure_online(&self) -> anyhow::Result<()> {
+        if self.is_offline_mode()? {
+            return Err(OfflineModeError.into());
+        }
+        Ok(())
+    }
+
+    /// Returns which apps are allowed to connect. Allows every app unless set otherwise.
+    pub fn get_app_key_allowlist(&self) -> anyhow::Result<AppKeyAllowlist> {
+        let database = self.database()?;
+        Ok(database
+            .get_setting::<AppKeyAllowlist>(APP_KEY_ALLOWLIST_SETTING)?
+            .unwrap_or_default())
+    }
+
+    pub fn set_app_key_allowlist(&self, app_key_allowlist: &AppKeyAllowlist) -> anyhow::Result<()> {
+        let database = self.database()?;
+        database.set_setting(APP_KEY_ALLOWLIST_SETTING, app_key_allowlist)
+    }
+
+    /// Returns which relays apps are shown. Every relay is shown to every app unless set otherwise.
+    pub fn get_relay_visibility_policy(&self) -> anyhow::Result<RelayVisibilityPolicy> {
+        let database = self.database()?;
+        Ok(database
+            .get_setting::<RelayVisibilityPolicy>(RELAY_VISIBILITY_SETTING)?
+            .unwrap_or_default())
+    }
+
+    pub fn set_relay_visibility_policy(
+        &self,
+        policy: &RelayVisibilityPolicy,
+    ) -> anyhow::Result<()> {
+        let database = self.database()?;
+        database.set_setting(RELAY_VISIBILITY_SETTING, policy)
+    }
+
+    pub fn list_passkey_credentials(&self) -> anyhow::Result<Vec<PasskeyCredential>> {
+        let database = self.database()?;
+        Ok(database
+            .get_setting::<Vec<PasskeyCredential>>(PASSKEY_CREDENTIALS_SETTING)?
+            .unwrap_or_default())
+    }
+
+    /// Registers a passkey. Errors if a passkey with the same credential ID is already registered.
+    #[cfg(feature = "webauthn")]
+    pub fn add_passkey_credential(&self, credential: PasskeyCredential) -> anyhow::Result<()> {
+        let mut credentials = self.list_passkey_credentials()?;
+        if credentials
+            .iter()
+            .any(|existing| existing.credential_id == credential.credential_id)
+        {
+            return Err(anyhow::anyhow!("Passkey is already registered"));
+        }
+        credentials.push(credential);
+        self.database()?
+            .set_setting(PASSKEY_CREDENTIALS_SETTING, &credentials)
+    }
+
+    /// Unregisters a passkey. Does nothing if no passkey has the credential ID.
+    #[cfg(feature = "webauthn")]
+    pub fn remove_passkey_credential(&self, credential_id: &[u8]) -> anyhow::Result<()> {
+        let mut credentials = self.list_passkey_credentials()?;
+        credentials.retain(|credential| credential.credential_id != credential_id);
+        self.database()?
+            .set_setting(PASSKEY_CREDENTIALS_SETTING, &credentials)
+    }
+
+    /// Returns where the keys of the accounts that sign on a PKCS#11 token are, in the order they were bound.
+    #[cfg(feature = "pkcs11")]
+    pub fn list_hardware_key_bindings(&self) -> anyhow::Result<Vec<HardwareKeyBinding>> {
+        let database = self.database()?;
+        Ok(database
+            .get_setting::<Vec<HardwareKeyBinding>>(HARDWARE_KEY_BINDINGS_SETTING)?
+            .unwrap_or_default())
+    }
+
+    /// Binds an account to a key on a PKCS#11 token, replacing any binding the account already has.
+    #[cfg(feature = "pkcs11")]
+    pub fn bind_hardware_key(&self, binding: HardwareKeyBinding) -> anyhow::Result<()> {
+        let mut bindings = self.list_hardware_key_bindings()?;
+        match bindings
+            .iter_mut()
+            .find(|existing| existing.npub == binding.npub)
+        {
+            Some(existing) => *existing = binding,
+            None => bindings.push(binding),
+        }
+        self.database()?
+            .set_setting(HARDWARE_KEY_BINDINGS_SETTING, &bindings)
+    }
+
+    pub fn set_relay_policy(
+        &self,
+        public_key: &PublicKey,
+        url: &str,
+        policy: RelayPolicy,
+    ) -> anyhow::Result<()> {
+        self.database()?.set_relay_policy(public_key, url, policy)
+    }
+
+    pub fn remove_relay(&self, public_key: &PublicKey, url: &str) -> anyhow::Result<()> {
+        self.database()?.remove_relay(public_key, url)
+    }
+
+    pub fn list_relays(
+        &self,
+        public_key: &PublicKey,
+    ) -> anyhow::Result<Vec<(String, RelayPolicy)>> {
+        self.database()?.list_relays(public_key)
+    }
+
+    pub fn replace_contacts(
+        &self,
+        public_key: &PublicKey,
+        contacts: &[Contact],
+    ) -> anyhow::Result<()> {
+        self.database()?.replace_contacts(public_key, contacts)
+    }
+
+    pub fn list_contacts(&self, public_key: &PublicKey) -> anyhow::Result<Vec<Contact>> {
+        self.database()?.list_contacts(public_key)
+    }
+
+    /// Returns how many events the account has signed and invoices it has paid, and when it was last used.
+    pub fn get_account_stats(&self, public_key: &PublicKey) -> anyhow::Result<AccountStats> {
+        self.database()?.get_account_stats(public_key)
+    }
+
+    /// Re-fetches the account's kind-0 profile and kind-10002 relay list from its read relays, storing any that are
+    /// newer than the stored copies, and returns the stored copies. Gives up on relays after
+    /// [`account_data::REFRESH_TIMEOUT`]. In offline mode, nothing is fetched and the stored copies are returned.
+    pub async fn refresh_account_data(
+        &self,
+        public_key: &PublicKey,
+    ) -> anyhow::Result<AccountDataRefresh> {
+        let mut refresh = AccountDataRefresh::default();
+        if !self.is_offline_mode()? {
+            let relay_urls = self.list_read_relay_urls(public_key)?;
+            let filter = Filter::new()
+                .author(*public_key)
+                .kinds([Kind::Metadata, Kind::RelayList]);
+            let events =
+                relays::query_relays_first_ok(&relay_urls, filter, account_data::REFRESH_TIMEOUT)
+                    .await?;
+
+            let database = self.database()?;
+            for kind in [Kind::Metadata, Kind::RelayList] {
+                let Some(latest_event) = events
+                    .iter()
+                    .filter(|event| event.kind == kind && event.pubkey == *public_key)
+                    .max_by_key(|event| event.created_at)
+                else {
+                    continue;
+                };
+                let changed = database.save_cached_event(latest_event)?;
+                match kind {
+                    Kind::Metadata => refresh.profile_changed = changed,
+                    _ => refresh.relay_list_changed = changed,
+                }
+            }
+        }
+
+        let database = self.database()?;
+        refresh.profile = database
+            .get_cached_event(public_key, Kind::Metadata)?
+            .map(|event| account_data::parse_profile(&event))
+            .transpose()?;
+        refresh.relay_list = database
+            .get_cached_event(public_key, Kind::RelayList)?
+            .map(|event| account_data::parse_relay_list(&event));
+        Ok(refresh)
+    }
+
+    /// Lists the URLs of relays that the keypair reads from.
+    pub fn list_read_relay_urls(&self, public_key: &PublicKey) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .list_relays(public_key)?
+            .into_iter()
+            .filter(|(_, policy)| policy.read)
+            .map(|(url, _)| url)
+            .collect())
+    }
+
+    /// Lists the URLs of relays that the keypair publishes to.
+    pub fn list_write_relay_urls(&self, public_key: &PublicKey) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .list_relays(public_key)?
+            .into_iter()
+            .filter(|(_, policy)| policy.write)
+            .map(|(url, _)| url)
+            .collect())
+    }
+}
+
+#[async_trait]
+impl KeyManager for KeystacheKeyManager {
+    fn get_secret_key(&self, public_key: &PublicKey) -> Option<SecretKey> {
+        let database = self.database_or.get()?;
+        self.key_cache.get_or_load(public_key, || {
+            // TODO: Fetch the secret key using the public key rather than iterating through all keypairs.
+            let keypairs = database.list_keypairs(999, 0).ok()?;
+            if let Some(keypair) = keypairs
+                .iter()
+                .find(|keypair| keypair.x_only_public_key().0 == **public_key)
+            {
+                return Some(keypair.secret_key().into());
+            }
+
+            // The public key may be an app's own identity for one of the accounts.
+            let app_npubs = database
+                .get_setting::<Vec<String>>(APP_IDENTITIES_SETTING)
+                .ok()?
+                .unwrap_or_default();
+            let app_public_keys: Vec<PublicKey> = app_npubs
+                .iter()
+                .filter_map(|npub| PublicKey::from_bech32(npub).ok())
+                .collect();
+            keypairs.iter().find_map(|keypair| {
+                let secret_key = keypair.secret_key().into();
+                app_public_keys.iter().find_map(|app_public_key| {
+                    app_identity::derive_app_keys(&secret_key, app_public_key)
+                        .ok()
+                        .filter(|app_keys| app_keys.public_key() == *public_key)
+                        .and_then(|app_keys| app_keys.secret_key().ok().cloned())
+                })
+            })
+        })
+    }
+}
+
+impl AccountActivityLog for KeystacheKeyManager {
+    fn record_signs(&self, public_key: &PublicKey, count: u64) -> anyhow::Result<()> {
+        self.database()?
+            .record_account_activity(public_key, count, 0)
+    }
+
+    fn record_payment(&self, public_key: &PublicKey) -> anyhow::Result<()> {
+        self.database()?.record_account_activity(public_key, 0, 1)
+    }
+}
+
+impl SignDecisionStore for KeystacheKeyManager {
+    fn get_sign_decision(
+        &self,
+        app_public_key: &PublicKey,
+        kind: Kind,
+    ) -> anyhow::Result<Option<bool>> {
+        self.database()?.get_sign_decision(app_public_key, kind)
+    }
+
+    fn remember_sign_decision(
+        &self,
+        app_public_key: &PublicKey,
+        kind: Kind,
+        approved: bool,
+    ) -> anyhow::Result<()> {
+        self.database()?
+            .set_sign_decision(app_public_key, kind, approved)
+    }
+}
+
+#[async_trait]
+impl ReplaceableEventLookup for KeystacheKeyManager {
+    /// Queries the author's read relays. Nothing is looked up in offline mode.
+    async fn latest_replaceable_event(
+        &self,
+        author: PublicKey,
+        kind: Kind,
+        identifier: &str,
+    ) -> anyhow::Result<Option<Event>> {
+        if self.is_offline_mode()? {
+            return Ok(None);
+        }
+        let relay_urls = self.list_read_relay_urls(&author)?;
+        let filter = Filter::new()
+            .author(author)
+            .kind(kind)
+            .identifier(identifier)
+            .limit(1);
+        let events =
+            relays::query_relays_first_ok(&relay_urls, filter, replaceable_event::LOOKUP_TIMEOUT)
+                .await?;
+        Ok(events.into_iter().max_by_key(|event| event.created_at))
+    }
+}
+
+impl PaymentLog for KeystacheKeyManager {
+    fn record_payment(&self, entry: &PaymentLogEntry) -> anyhow::Result<()> {
+        self.database()?.add_payment_log_entry(entry)
+    }
+
+    fn record_payments(&self, entries: &[PaymentLogEntry]) -> anyhow::Result<()> {
+        self.database()?.add_payment_log_entries(entries)
+    }
+}
+
+impl SigningLog for KeystacheKeyManager {
+    fn record_signing(&self, entry: &SigningLogEntry) -> anyhow::Result<()> {
+        self.database()?.add_signing_log_entry(entry)
+    }
+}
+
+impl ConnectionLogStore for KeystacheKeyManager {
+    fn record_connection_events(&self, entries: &[ConnectionLogEntry]) -> anyhow::Result<()> {
+        self.database()?.add_connection_log_entries(entries)
+    }
+
+    fn list_connection_events_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<ConnectionLogEntry>> {
+        self.database()?.list_connection_log_entries_since(since)
+    }
+
+    fn delete_connection_events_before(
+        &self,
+        cutoff: DateTime<Utc>,
+        limit: u64,
+    ) -> anyhow::Result<u64> {
+        self.database()?
+            .delete_connection_log_entries_before(cutoff, limit)
+    }
+}
+
+impl PrunableLog for KeystacheKeyManager {
+    fn delete_entries_before(&self, cutoff: DateTime<Utc>, limit: u64) -> anyhow::Result<u64> {
+        // The payment history is pruned first, and then the signing history with whatever is left of `limit`.
+        let database = self.database()?;
+        let deleted = database.delete_payment_log_entries_before(cutoff, limit)?;
+        if deleted == limit {
+            return Ok(deleted);
+        }
+        Ok(deleted + database.delete_signing_log_entries_before(cutoff, limit - deleted)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_retention;
+    use crate::mock_relay::MockRelay;
+    use crate::payment_cap::OverCapAction;
+    use crate::payment_log::PaymentOutcome;
+    use crate::signing_log::SigningOutcome;
+    use nostr_sdk::{
+        ClientMessage, EventBuilder, Keys, Kind, RelayMessage, RelayMetadata, Timestamp,
+        UncheckedUrl,
+    };
+    use std::sync::Arc;
+
+    fn get_key_manager_with_keypair() -> (KeystacheKeyManager, Keys) {
+        let key_manager = KeystacheKeyManager::new_with_database(Database::new_in_temp_dir());
+        let keypair = Keypair::new(&Secp256k1::new(), &mut thread_rng());
+        key_manager.set_keypair(keypair).unwrap();
+        (key_manager, Keys::new(keypair.secret_key().into()))
+    }
+
+    #[test]
+    fn bulk_import_reports_each_entry() {
+        let (key_manager, existing_keys) = get_key_manager_with_keypair();
+        let new_keys = Keys::generate();
+
+        let results = key_manager
+            .bulk_import(&[
+                new_keys.secret_key().unwrap().to_bech32().unwrap(),
+                existing_keys.secret_key().unwrap().to_bech32().unwrap(),
+                "nsec1notavalidnsec".to_string(),
+                // Duplicates within the same import should only be imported once.
+                format!(" {} ", new_keys.secret_key().unwrap().to_bech32().unwrap()),
+                String::new(),
+            ])
+            .unwrap();
+
+        let new_npub = new_keys.public_key().to_bech32().unwrap();
+        assert_eq!(
+            results,
+            vec![
+                BulkImportResult::Imported {
+                    npub: new_npub.clone()
+                },
+                BulkImportResult::AlreadyPresent {
+                    npub: existing_keys.public_key().to_bech32().unwrap()
+                },
+                BulkImportResult::Invalid {
+                    reason: "Error parsing nsec".to_string()
+                },
+                BulkImportResult::AlreadyPresent { npub: new_npub },
+                BulkImportResult::Invalid {
+                    reason: "Error parsing nsec".to_string()
+                },
+            ]
+        );
+
+        // The existing key should be kept alongside the new one.
+        assert!(key_manager
+            .get_secret_key(&existing_keys.public_key())
+            .is_some());
+        assert!(key_manager.get_secret_key(&new_keys.public_key()).is_some());
+    }
+
+    #[test]
+    fn wipe_all_data_erases_everything() {
+        let key_manager =
+            KeystacheKeyManager::new_with_database(Database::new_encrypted_in_temp_dir("hunter2"));
+        let keys = Keys::generate();
+        key_manager
+            .add_keypair(&keys.secret_key().unwrap().keypair(&Secp256k1::new()))
+            .unwrap();
+        key_manager
+            .set_relay_policy(
+                &keys.public_key(),
+                "wss://relay.example.com",
+                RelayPolicy {
+                    read: true,
+                    write: true,
+                },
+            )
+            .unwrap();
+        key_manager.set_offline_mode(true).unwrap();
+
+        // Wiping with the wrong passphrase should cause an error and leave everything in place.
+        assert!(key_manager
+            .wipe_all_data("not the passphrase")
+            .unwrap_err()
+            .is::<WrongPassphraseError>());
+        assert_eq!(
+            key_manager.list_accounts().unwrap(),
+            vec![keys.public_key()]
+        );
+
+        key_manager.wipe_all_data("hunter2").unwrap();
+
+        assert!(key_manager.list_accounts().unwrap().is_empty());
+        assert_eq!(key_manager.get_public_key().unwrap(), None);
+        assert!(key_manager.get_secret_key(&keys.public_key()).is_none());
+        assert!(key_manager
+            .list_relays(&keys.public_key())
+            .unwrap()
+            .is_empty());
+        assert!(!key_manager.is_offline_mode().unwrap());
+
+        // The vault should be usable again once an account is added.
+        key_manager
+            .add_keypair(&keys.secret_key().unwrap().keypair(&Secp256k1::new()))
+            .unwrap();
+        assert_eq!(
+            key_manager.list_accounts().unwrap(),
+            vec![keys.public_key()]
+        );
+    }
+
+    #[test]
+    fn wipe_all_data_unencrypted_vault() {
+        let (key_manager, keys) = get_key_manager_with_keypair();
+
+        // An unencrypted vault has no passphrase to confirm with, so wiping it should cause an error.
+        assert!(key_manager.wipe_all_data("").is_err());
+        assert_eq!(
+            key_manager.list_accounts().unwrap(),
+            vec![keys.public_key()]
+        );
+    }
+
+    #[test]
+    fn revoke_authorization_leaves_other_apps() {
+        let (key_manager, keys) = get_key_manager_with_keypair();
+        let first_app = Keys::generate().public_key();
+        let second_app = Keys::generate().public_key();
+        let expires_at = Timestamp::now().as_u64() + 600;
+        for (display_name, app) in [("First app", first_app), ("Second app", second_app)] {
+            key_manager
+                .database()
+                .unwrap()
+                .register_application(Some(display_name.to_string()), &app, &keys.public_key())
+                .unwrap();
+            key_manager
+                .remember_sign_decision(&app, Kind::TextNote, true)
+                .unwrap();
+            key_manager
+                .record_signing(
+                    &SigningLogEntry::new(&app, Kind::TextNote, SigningOutcome::Approved).unwrap(),
+                )
+                .unwrap();
+        }
+        key_manager
+            .set_session_grants(&[StoredSessionGrant {
+                app_npub: second_app.to_bech32().unwrap(),
+                allowed_kinds: vec![Kind::Reaction.as_u64()],
+                expires_at,
+            }])
+            .unwrap();
+        let max_single_payment = MaxSinglePayment {
+            max_sats: 1_000,
+            action: OverCapAction::Reject,
+        };
+        key_manager
+            .set_max_single_payment(Some(&max_single_payment))
+            .unwrap();
+        assert_eq!(key_manager.list_authorizations().unwrap().len(), 2);
+
+        key_manager.revoke_authorization(&first_app).unwrap();
+
+        let authorizations = key_manager.list_authorizations().unwrap();
+        assert_eq!(authorizations.len(), 1);
+        let authorization = &authorizations[0];
+        assert_eq!(authorization.app_npub, second_app.to_bech32().unwrap());
+        assert_eq!(authorization.display_name, Some("Second app".to_string()));
+        assert_eq!(
+            authorization.identity_npub,
+            keys.public_key().to_bech32().unwrap()
+        );
+        assert_eq!(
+            authorization.sign_decisions,
+            vec![RememberedSignDecision {
+                app_npub: second_app.to_bech32().unwrap(),
+                kind: Kind::TextNote.as_u64(),
+                approved: true,
+            }]
+        );
+        assert_eq!(
+            authorization.session_grant,
+            Some(StoredSessionGrant {
+                app_npub: second_app.to_bech32().unwrap(),
+                allowed_kinds: vec![Kind::Reaction.as_u64()],
+                expires_at,
+            })
+        );
+        assert_eq!(authorization.max_single_payment, Some(max_single_payment));
+        assert!(authorization.last_used_time.is_some());
+
+        // The revoked app's remembered decisions go with it.
+        assert_eq!(
+            key_manager
+                .get_sign_decision(&first_app, Kind::TextNote)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn clear_key_cache_empties_cache() {
+        let (key_manager, keys) = get_key_manager_with_keypair();
+        let public_key = keys.public_key();
+
+        let secret_key = key_manager.get_secret_key(&public_key).unwrap();
+        EventBuilder::new(Kind::TextNote, "hello", None)
+            .to_event(&Keys::new(secret_key))
+            .unwrap();
+        assert_eq!(
+            key_manager.get_secret_key(&public_key),
+            keys.secret_key().ok().cloned()
+        );
+        assert_eq!(
+            key_manager.get_key_cache_stats(),
+            KeyCacheStats {
+                cached_keys: 1,
+                hits: 1,
+                misses: 1,
+            }
+        );
+
+        key_manager.clear_key_cache();
+        assert_eq!(key_manager.get_key_cache_stats().cached_keys, 0);
+
+        // The key is read from the database again rather than from the cache.
+        assert!(key_manager.get_secret_key(&public_key).is_some());
+        assert_eq!(
+            key_manager.get_key_cache_stats(),
+            KeyCacheStats {
+                cached_keys: 1,
+                hits: 1,
+                misses: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn encrypted_vault_must_be_unlocked_after_relaunch() {
+        let folder = tempfile::TempDir::new().unwrap();
+        let key_manager = KeystacheKeyManager::new_in_dir(Some(folder.path().to_path_buf()));
+        let keys: Vec<Keys> = (0..2).map(|_| Keys::generate()).collect();
+        for keys in &keys {
+            key_manager
+                .add_keypair(&Keypair::from_secret_key(
+                    &Secp256k1::new(),
+                    keys.secret_key().unwrap(),
+                ))
+                .unwrap();
+        }
+
+        key_manager.encrypt_existing_keys("hunter2").unwrap();
+        assert!(key_manager.encrypt_existing_keys("hunter2").is_err());
+        drop(key_manager);
+
+        let key_manager = KeystacheKeyManager::new_in_dir(Some(folder.path().to_path_buf()));
+        assert_eq!(
+            key_manager.get_setup_state().unwrap(),
+            SetupState::VaultLocked
+        );
+        assert!(key_manager.get_secret_key(&keys[0].public_key()).is_none());
+
+        assert!(key_manager
+            .unlock_vault("wrong")
+            .unwrap_err()
+            .is::<WrongPassphraseError>());
+        key_manager.unlock_vault("hunter2").unwrap();
+        assert_eq!(key_manager.get_setup_state().unwrap(), SetupState::Ready);
+        for keys in &keys {
+            assert_eq!(
+                key_manager.get_secret_key(&keys.public_key()).as_ref(),
+                keys.secret_key().ok()
+            );
+        }
+    }
+
+    #[test]
+    fn setup_state_transitions() {
+        let folder = tempfile::TempDir::new().unwrap();
+
+        // A fresh install's vault isn't encrypted until the user chooses a passphrase.
+        let key_manager = KeystacheKeyManager::new_in_dir(Some(folder.path().to_path_buf()));
+        assert_eq!(
+            key_manager.get_setup_state().unwrap(),
+            SetupState::NeedsVaultPassphrase
+        );
+        key_manager.encrypt_existing_keys("hunter2").unwrap();
+        assert_eq!(
+            key_manager.get_setup_state().unwrap(),
+            SetupState::NeedsFirstAccount
+        );
+        drop(key_manager);
+
+        // An encrypted vault can't be opened without its passphrase.
+        let key_manager = KeystacheKeyManager::new_in_dir(Some(folder.path().to_path_buf()));
+        assert_eq!(
+            key_manager.get_setup_state().unwrap(),
+            SetupState::VaultLocked
+        );
+
+        key_manager.unlock_vault("hunter2").unwrap();
+        assert_eq!(
+            key_manager.get_setup_state().unwrap(),
+            SetupState::NeedsFirstAccount
+        );
+
+        // A watch-only account can't sign, so it doesn't complete setup.
+        key_manager
+            .add_watch_only_account(&Keys::generate().public_key())
+            .unwrap();
+        assert_eq!(
+            key_manager.get_setup_state().unwrap(),
+            SetupState::NeedsFirstAccount
+        );
+
+        key_manager
+            .add_keypair(&Keypair::new(&Secp256k1::new(), &mut thread_rng()))
+            .unwrap();
+        assert_eq!(key_manager.get_setup_state().unwrap(), SetupState::Ready);
+
+        // Wiping the vault starts setup over, from adding an account.
+        key_manager.wipe_all_data("hunter2").unwrap();
+        assert_eq!(
+            key_manager.get_setup_state().unwrap(),
+            SetupState::NeedsFirstAccount
+        );
+    }
+
+    #[test]
+    fn set_app_name_renames_app() {
+        let (key_manager, keys) = get_key_manager_with_keypair();
+        let app = Keys::generate().public_key();
+        let unnamed_app = Keys::generate().public_key();
+        for app in [app, unnamed_app] {
+            key_manager
+                .database()
+                .unwrap()
+                .register_application(None, &app, &keys.public_key())
+                .unwrap();
+        }
+
+        key_manager.set_app_name(&app, Some("Damus")).unwrap();
+
+        assert_eq!(
+            key_manager.get_app_names().unwrap(),
+            HashMap::from([(app, "Damus".to_string())])
+        );
+        assert!(key_manager
+            .set_app_name(&Keys::generate().public_key(), Some("Unknown"))
+            .is_err());
+    }
+
+    #[test]
+    fn apps_with_their_own_identity_sign_with_it() {
+        let (key_manager, keys) = get_key_manager_with_keypair();
+        let app = Keys::generate().public_key();
+        let other_app = Keys::generate().public_key();
+
+        // Until turned on, apps are given the account's public key.
+        assert_eq!(
+            key_manager.get_public_key_for_app(&app).unwrap(),
+            Some(keys.public_key())
+        );
+
+        key_manager.set_app_identity_enabled(&app, true).unwrap();
+        key_manager
+            .set_app_identity_enabled(&other_app, true)
+            .unwrap();
+        let app_public_key = key_manager.get_public_key_for_app(&app).unwrap().unwrap();
+        assert_ne!(app_public_key, keys.public_key());
+        assert_eq!(
+            key_manager.get_public_key_for_app(&app).unwrap(),
+            Some(app_public_key)
+        );
+        assert_ne!(
+            key_manager.get_public_key_for_app(&other_app).unwrap(),
+            Some(app_public_key)
+        );
+
+        // Events with the app's identity as their pubkey are signed with its key.
+        let app_secret_key = key_manager.get_secret_key(&app_public_key).unwrap();
+        assert_eq!(Keys::new(app_secret_key).public_key(), app_public_key);
+
+        key_manager.set_app_identity_enabled(&app, false).unwrap();
+        assert!(key_manager.get_secret_key(&app_public_key).is_none());
+        assert_eq!(
+            key_manager.get_public_key_for_app(&app).unwrap(),
+            Some(keys.public_key())
+        );
+    }
+
+    #[test]
+    fn derived_accounts_record_their_index() {
+        let key_manager = KeystacheKeyManager::new_with_database(Database::new_in_temp_dir());
+        let mnemonic =
+            "leader monkey parrot ring guide accident before fence cannon height naive bean";
+
+        let account_0 = key_manager.derive_account(mnemonic, 0).unwrap();
+        let account_1 = key_manager.derive_account(mnemonic, 1).unwrap();
+
+        assert_eq!(
+            key_manager.list_accounts().unwrap(),
+            vec![account_0, account_1]
+        );
+        assert!(key_manager.get_secret_key(&account_1).is_some());
+        for (public_key, index) in [(account_0, 0), (account_1, 1)] {
+            assert_eq!(
+                key_manager
+                    .get_account_metadata(&public_key)
+                    .unwrap()
+                    .derivation_index,
+                Some(index)
+            );
+        }
+
+        // Deriving the same account twice doesn't add a duplicate.
+        assert!(key_manager.derive_account(mnemonic, 1).is_err());
+    }
+
+    #[test]
+    fn rotated_account_inherits_label_and_relays() {
+        let (key_manager, old_keys) = get_key_manager_with_keypair();
+        let old_public_key = old_keys.public_key();
+        key_manager
+            .set_account_label(&old_public_key, Some("Main"))
+            .unwrap();
+        let relays = vec![
+            (
+                "wss://read.example.com".to_string(),
+                RelayPolicy {
+                    read: true,
+                    write: false,
+                },
+            ),
+            (
+                "wss://write.example.com".to_string(),
+                RelayPolicy {
+                    read: false,
+                    write: true,
+                },
+            ),
+        ];
+        for (url, policy) in &relays {
+            key_manager
+                .set_relay_policy(&old_public_key, url, *policy)
+                .unwrap();
+        }
+
+        let new_keypair = key_manager.rotate_account(&old_public_key).unwrap();
+        let new_public_key = PublicKey::from(new_keypair.x_only_public_key().0);
+
+        assert_eq!(
+            key_manager.list_accounts().unwrap(),
+            vec![old_public_key, new_public_key]
+        );
+        assert_eq!(
+            key_manager.get_secret_key(&new_public_key),
+            Some(new_keypair.secret_key().into())
+        );
+
+        let new_metadata = key_manager.get_account_metadata(&new_public_key).unwrap();
+        assert_eq!(new_metadata.label, Some("Main".to_string()));
+        assert_eq!(new_metadata.retire_time, None);
+        assert_eq!(new_metadata.derivation_index, None);
+        assert_eq!(key_manager.list_relays(&new_public_key).unwrap(), relays);
+
+        // The old account is kept for reference, but marked as retired.
+        let old_metadata = key_manager.get_account_metadata(&old_public_key).unwrap();
+        assert_eq!(old_metadata.label, Some("Main".to_string()));
+        assert!(old_metadata.retire_time.is_some());
+        assert_eq!(
+            old_metadata.successor_npub,
+            Some(new_public_key.to_bech32().unwrap())
+        );
+        assert_eq!(key_manager.list_relays(&old_public_key).unwrap(), relays);
+
+        // A retired account can't be rotated again.
+        assert!(key_manager.rotate_account(&old_public_key).is_err());
+        assert!(key_manager
+            .rotate_account(&Keys::generate().public_key())
+            .is_err());
+    }
+
+    #[test]
+    fn offline_mode_defaults_to_off() {
+        let (key_manager, _) = get_key_manager_with_keypair();
+
+        assert!(!key_manager.is_offline_mode().unwrap());
+        assert!(key_manager.ensure_online().is_ok());
+    }
+
+    #[test]
+    fn set_and_get_app_key_allowlist() {
+        let (key_manager, _) = get_key_manager_with_keypair();
+        assert_eq!(
+            key_manager.get_app_key_allowlist().unwrap(),
+            AppKeyAllowlist::default()
+        );
+
+        let app = Keys::generate().public_key();
+        let app_key_allowlist = AppKeyAllowlist::new(vec![app.to_bech32().unwrap()]).unwrap();
+        key_manager
+            .set_app_key_allowlist(&app_key_allowlist)
+            .unwrap();
+
+        let saved_app_key_allowlist = key_manager.get_app_key_allowlist().unwrap();
+        assert_eq!(saved_app_key_allowlist, app_key_allowlist);
+        assert!(saved_app_key_allowlist.allows(&app));
+        assert!(!saved_app_key_allowlist.allows(&Keys::generate().public_key()));
+    }
+
+    #[cfg(feature = "webauthn")]
+    #[test]
+    fn add_and_remove_passkey_credentials() {
+        let (key_manager, _) = get_key_manager_with_keypair();
+        assert!(key_manager.list_passkey_credentials().unwrap().is_empty());
+
+        let credential_1 = PasskeyCredential {
+            credential_id: vec![1],
+            public_key: vec![1, 1],
+        };
+        let credential_2 = PasskeyCredential {
+            credential_id: vec![2],
+            public_key: vec![2, 2],
+        };
+        key_manager
+            .add_passkey_credential(credential_1.clone())
+            .unwrap();
+        key_manager
+            .add_passkey_credential(credential_2.clone())
+            .unwrap();
+
+        // Adding a passkey that's already registered should cause an error.
+        assert!(key_manager
+            .add_passkey_credential(credential_1.clone())
+            .is_err());
+
+        assert_eq!(
+            key_manager.list_passkey_credentials().unwrap(),
+            vec![credential_1, credential_2.clone()]
+        );
+
+        key_manager.remove_passkey_credential(&[1]).unwrap();
+        assert_eq!(
+            key_manager.list_passkey_credentials().unwrap(),
+            vec![credential_2]
+        );
+    }
+
+    #[cfg(feature = "pkcs11")]
+    #[test]
+    fn bind_hardware_keys() {
+        let (key_manager, _) = get_key_manager_with_keypair();
+        assert!(key_manager.list_hardware_key_bindings().unwrap().is_empty());
+
+        let get_binding = |npub: &str, key_label: &str| HardwareKeyBinding {
+            npub: npub.to_string(),
+            module_path: "/usr/lib/softhsm/libsofthsm2.so".to_string(),
+            token_label: "keystache".to_string(),
+            key_label: key_label.to_string(),
+            schnorr_mechanism: 0x8000_0001,
+            pin: Some("1234".to_string()),
+        };
+        let npub_1 = Keys::generate().public_key().to_bech32().unwrap();
+        let npub_2 = Keys::generate().public_key().to_bech32().unwrap();
+        key_manager
+            .bind_hardware_key(get_binding(&npub_1, "nostr"))
+            .unwrap();
+        key_manager
+            .bind_hardware_key(get_binding(&npub_2, "nostr"))
+            .unwrap();
+
+        // Binding an account again replaces its binding, in place.
+        key_manager
+            .bind_hardware_key(get_binding(&npub_1, "other"))
+            .unwrap();
+        assert_eq!(
+            key_manager.list_hardware_key_bindings().unwrap(),
+            vec![get_binding(&npub_1, "other"), get_binding(&npub_2, "nostr")]
+        );
+    }
+
+    #[test]
+    fn offline_mode_blocks_network_but_not_signing() {
+        let (key_manager, keys) = get_key_manager_with_keypair();
+        key_manager.set_offline_mode(true).unwrap();
+
+        // Anything that needs the network should fail with a clear error.
+        let err = key_manager.ensure_online().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<OfflineModeError>(),
+            Some(&OfflineModeError)
+        );
+
+        // Reading the public key and signing should still work.
+        assert_eq!(
+            key_manager.get_public_key().unwrap(),
+            Some(keys.public_key())
+        );
+        let secret_key = key_manager.get_secret_key(&keys.public_key()).unwrap();
+        let event = EventBuilder::new(Kind::TextNote, "hello world", None)
+            .to_unsigned_event(keys.public_key())
+            .sign(&Keys::new(secret_key))
+            .unwrap();
+        assert!(event.verify().is_ok());
+
+        // Turning offline mode back off should allow network access again.
+        key_manager.set_offline_mode(false).unwrap();
+        assert!(key_manager.ensure_online().is_ok());
+    }
+
+    #[tokio::test]
+    async fn refresh_account_data_stores_newer_events() {
+        let (key_manager, keys) = get_key_manager_with_keypair();
+        let metadata_event = |name: &str, created_at: u64| {
+            EventBuilder::new(Kind::Metadata, format!(r#"{{"name":"{name}"}}"#), None)
+                .custom_created_at(Timestamp::from(created_at))
+                .to_event(&keys)
+                .unwrap()
+        };
+        let relay_list_event = EventBuilder::relay_list([(
+            UncheckedUrl::from("wss://relay.example.com"),
+            Some(RelayMetadata::Read),
+        )])
+        .to_event(&keys)
+        .unwrap();
+
+        let events = Arc::new(std::sync::Mutex::new(vec![metadata_event("old", 1000)]));
+        let relay_events = events.clone();
+        let relay = MockRelay::start(move |message| match message {
+            ClientMessage::Req {
+                subscription_id, ..
+            } => {
+                let mut messages: Vec<RelayMessage> = relay_events
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|event| RelayMessage::event(subscription_id.clone(), event.clone()))
+                    .collect();
+                messages.push(RelayMessage::eose(subscription_id));
+                messages
+            }
+            _ => Vec::new(),
+        })
+        .await;
+        key_manager
+            .set_relay_policy(
+                &keys.public_key(),
+                &relay.url(),
+                RelayPolicy {
+                    read: true,
+                    write: false,
+                },
+            )
+            .unwrap();
+
+        let refresh = key_manager
+            .refresh_account_data(&keys.public_key())
+            .await
+            .unwrap();
+        assert!(refresh.profile_changed);
+        assert!(!refresh.relay_list_changed);
+        assert_eq!(refresh.profile.unwrap().name.as_deref(), Some("old"));
+        assert_eq!(refresh.relay_list, None);
+
+        // The relay now has a newer profile and a relay list.
+        *events.lock().unwrap() = vec![metadata_event("new", 2000), relay_list_event];
+        let refresh = key_manager
+            .refresh_account_data(&keys.public_key())
+            .await
+            .unwrap();
+        assert!(refresh.profile_changed);
+        assert!(refresh.relay_list_changed);
+        assert_eq!(refresh.profile.unwrap().name.as_deref(), Some("new"));
+        assert_eq!(
+            refresh.relay_list,
+            Some(vec![(
+                "wss://relay.example.com".to_string(),
+                RelayPolicy {
+                    read: true,
+                    write: false,
+                }
+            )])
+        );
+
+        // An older copy on the relay doesn't replace the stored one.
+        *events.lock().unwrap() = vec![metadata_event("older", 500)];
+        let refresh = key_manager
+            .refresh_account_data(&keys.public_key())
+            .await
+            .unwrap();
+        assert!(!refresh.profile_changed);
+        assert_eq!(refresh.profile.unwrap().name.as_deref(), Some("new"));
+
+        // In offline mode, the stored copies are returned without asking relays.
+        drop(relay);
+        key_manager.set_offline_mode(true).unwrap();
+        let refresh = key_manager
+            .refresh_account_data(&keys.public_key())
+            .await
+            .unwrap();
+        assert!(!refresh.profile_changed && !refresh.relay_list_changed);
+        assert_eq!(refresh.profile.unwrap().name.as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn pruning_deletes_only_old_payment_log_entries() {
+        let (key_manager, _) = get_key_manager_with_keypair();
+        let now = Utc::now();
+        let entry = |invoice: &str, age_days: i64| PaymentLogEntry {
+            invoice: invoice.to_string(),
+            amount_msats: Some(1000),
+            description: None,
+            app_npub: None,
+            create_time: (now - chrono::Duration::days(age_days)).to_rfc3339(),
+            outcome: PaymentOutcome::Rejected,
+            preimage: None,
+        };
+        key_manager
+            .record_payments(&[
+                entry("lnbc1old", 120),
+                entry("lnbc1recent", 30),
+                entry("lnbc1older", 365),
+                entry("lnbc1new", 0),
+            ])
+            .unwrap();
+
+        // A retention of 0 days keeps everything.
+        assert_eq!(log_retention::prune_log(&key_manager, 0, now).unwrap(), 0);
+
+        assert_eq!(log_retention::prune_log(&key_manager, 90, now).unwrap(), 2);
+        let invoices: Vec<String> = key_manager
+            .get_payment_history(10, 0, &PaymentHistoryFilter::default())
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.invoice)
+            .collect();
+        assert_eq!(invoices, vec!["lnbc1new", "lnbc1recent"]);
+    }
+
+    #[test]
+    fn account_missing_relays_and_nip05_is_incomplete() {
+        let (key_manager, keys) = get_key_manager_with_keypair();
+        key_manager
+            .set_account_label(&keys.public_key(), Some("Main"))
+            .unwrap();
+        key_manager
+            .set_backup_acknowledged(&keys.public_key(), true)
+            .unwrap();
+
+        assert_eq!(
+            key_manager
+                .get_account_completeness(&keys.public_key())
+                .unwrap(),
+            AccountCompleteness {
+                has_label: true,
+                has_relays: false,
+                has_verified_nip05: false,
+                has_acknowledged_backup: true,
+                uses_encryption: false,
+            }
+        );
+
+        key_manager
+            .set_relay_policy(
+                &keys.public_key(),
+                "wss://relay.example.com",
+                RelayPolicy {
+                    read: true,
+                    write: true,
+                },
+            )
+            .unwrap();
+        key_manager
+            .set_verified_nip05(&keys.public_key(), Some("bob@example.com"))
+            .unwrap();
+        let completeness = key_manager
+            .get_account_completeness(&keys.public_key())
+            .unwrap();
+        assert!(completeness.has_relays && completeness.has_verified_nip05);
+
+        // Another account's steps don't count towards this one.
+        let other_keys = Keys::generate();
+        key_manager
+            .add_keypair(&other_keys.secret_key().unwrap().keypair(&Secp256k1::new()))
+            .unwrap();
+        assert_eq!(
+            key_manager
+                .get_account_completeness(&other_keys.public_key())
+                .unwrap(),
+            AccountCompleteness::default()
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use serde::{Deserialize, Serialize};
+
+use crate::http::JsonFetcher;
+use crate::profile;
+
+/// An account's lightning address, and whether it can currently be paid (e.g. zapped) through.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct LightningAddress {
+    /// The `lud16` field of the account's profile metadata.
+    pub address: String,
+
+    pub status: LightningAddressStatus,
+}
+
+/// Whether a lightning address resolves to a working LNURL-pay endpoint.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LightningAddressStatus {
+    Resolved {
+        min_sendable_msats: u64,
+        max_sendable_msats: u64,
+
+        /// Whether the endpoint accepts NIP-57 zap requests, so that payments to it can be zaps.
+        allows_nostr: bool,
+    },
+
+    Unresolvable {
+        reason: String,
+    },
+}
+
+/// The parts of an LNURL-pay endpoint's response (LUD-06) that show whether it can be paid through.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PayResponse {
+    tag: String,
+    callback: String,
+    min_sendable: u64,
+    max_sendable: u64,
+    #[serde(default)]
+    allows_nostr: bool,
+}
+
+/// Returns the URL of a lightning address's LNURL-pay endpoint (LUD-16), or `None` if it isn't a lightning
+/// address.
+pub fn lnurlp_url(address: &str) -> Option<String> {
+    if !profile::is_lightning_address(address) {
+        return None;
+    }
+    let (name, domain) = address.split_once('@')?;
+    Some(format!(
+        "https://{}/.well-known/lnurlp/{}",
+        domain.to_lowercase(),
+        name.to_lowercase()
+    ))
+}
+
+/// Fetches a lightning address's LNURL-pay endpoint and checks that it can be paid through.
+pub async fn resolve_lightning_address(
+    address: &str,
+    fetcher: &dyn JsonFetcher,
+) -> LightningAddress {
+    let status = match check_pay_endpoint(address, fetcher).await {
+        Ok(pay_response) => LightningAddressStatus::Resolved {
+            min_sendable_msats: pay_response.min_sendable,
+            max_sendable_msats: pay_response.max_sendable,
+            allows_nostr: pay_response.allows_nostr,
+        },
+        Err(err) => LightningAddressStatus::Unresolvable {
+            reason: err.to_string(),
+        },
+    };
+    LightningAddress {
+        address: address.to_string(),
+        status,
+    }
+}
+
+async fn check_pay_endpoint(
+    address: &str,
+    fetcher: &dyn JsonFetcher,
+) -> anyhow::Result<PayResponse> {
+    let url = lnurlp_url(address).ok_or(anyhow::anyhow!("Not a lightning address"))?;
+    let response = fetcher
+        .get_json(&url)
+        .await
+        .map_err(|err| anyhow::anyhow!("Error fetching {url}: {err}"))?;
+
+    // LNURL services report errors in the body, e.g. for unknown users.
+    if response["status"] == "ERROR" {
+        return Err(anyhow::anyhow!(
+            "LNURL service error: {}",
+            response["reason"].as_str().unwrap_or("no reason given")
+        ));
+    }
+
+    let pay_response: PayResponse = serde_json::from_value(response)
+        .map_err(|err| anyhow::anyhow!("Invalid LNURL-pay response: {err}"))?;
+    if pay_response.tag != "payRequest" {
+        return Err(anyhow::anyhow!(
+            "Expected a payRequest, got a {}",
+            pay_response.tag
+        ));
+    }
+    if pay_response.callback.is_empty() {
+        return Err(anyhow::anyhow!("LNURL-pay response has no callback"));
+    }
+    if pay_response.min_sendable > pay_response.max_sendable {
+        return Err(anyhow::anyhow!(
+            "LNURL-pay response's minimum amount is above its maximum"
+        ));
+    }
+    Ok(pay_response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    /// Serves canned responses in place of LNURL-pay endpoints.
+    struct MockFetcher {
+        responses: HashMap<String, serde_json::Value>,
+    }
+
+    #[async_trait]
+    impl JsonFetcher for MockFetcher {
+        async fn get_json(&self, url: &str) -> anyhow::Result<serde_json::Value> {
+            self.responses
+                .get(url)
+                .cloned()
+                .ok_or(anyhow::anyhow!("404 Not Found"))
+        }
+    }
+
+    fn get_fetcher() -> MockFetcher {
+        MockFetcher {
+            responses: HashMap::from([
+                (
+                    "https://example.com/.well-known/lnurlp/satoshi".to_string(),
+                    json!({
+                        "tag": "payRequest",
+                        "callback": "https://example.com/lnurlp/satoshi/callback",
+                        "minSendable": 1_000,
+                        "maxSendable": 100_000_000,
+                        "metadata": "[[\"text/plain\",\"Pay satoshi\"]]",
+                        "allowsNostr": true,
+                        "nostrPubkey": "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+                    }),
+                ),
+                (
+                    "https://example.com/.well-known/lnurlp/hal".to_string(),
+                    json!({ "status": "ERROR", "reason": "Unknown user" }),
+                ),
+            ]),
+        }
+    }
+
+    #[tokio::test]
+    async fn resolvable_address() {
+        assert_eq!(
+            resolve_lightning_address("Satoshi@example.com", &get_fetcher()).await,
+            LightningAddress {
+                address: "Satoshi@example.com".to_string(),
+                status: LightningAddressStatus::Resolved {
+                    min_sendable_msats: 1_000,
+                    max_sendable_msats: 100_000_000,
+                    allows_nostr: true,
+                },
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn unresolvable_address() {
+        let fetcher = get_fetcher();
+        for (address, reason) in [
+            ("hal@example.com", "LNURL service error: Unknown user"),
+            (
+                "nobody@example.com",
+                "Error fetching https://example.com/.well-known/lnurlp/nobody: 404 Not Found",
+            ),
+            ("not an address", "Not a lightning address"),
+        ] {
+            assert_eq!(
+                resolve_lightning_address(address, &fetcher).await.status,
+                LightningAddressStatus::Unresolvable {
+                    reason: reason.to_string()
+                }
+            );
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use crate::connection_log::{ConnectionEvent, ConnectionLogEntry};
+use crate::payment_log::PaymentLogEntry;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::borrow::Cow;
+
+const PAYMENT_LOG_HEADER: [&str; 7] = [
+    "time",
+    "outcome",
+    "amount_msats",
+    "description",
+    "app_npub",
+    "invoice",
+    "preimage",
+];
+
+const CONNECTION_LOG_HEADER: [&str; 5] =
+    ["time", "connection_id", "server_address", "event", "detail"];
+
+/// Which log to export.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogKind {
+    /// Requests to pay invoices.
+    Payments,
+
+    /// Activity on the NIP-46 servers.
+    Connections,
+}
+
+/// Exports the payment log entries from `since` up to and including `until` as CSV, oldest first.
+/// `entries` can be in any order. If none are in range, only the header is returned.
+pub fn payment_log_csv(
+    entries: &[PaymentLogEntry],
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> String {
+    let mut entries: Vec<&PaymentLogEntry> = entries
+        .iter()
+        .filter(|entry| is_in_range(&entry.create_time, since, until))
+        .collect();
+    entries.sort_by_key(|entry| DateTime::parse_from_rfc3339(&entry.create_time).ok());
+
+    let mut csv = csv_row(&PAYMENT_LOG_HEADER);
+    for entry in entries {
+        csv.push_str(&csv_row(&[
+            &entry.create_time,
+            entry.outcome.as_str(),
+            &entry
+                .amount_msats
+                .map(|amount_msats| amount_msats.to_string())
+                .unwrap_or_default(),
+            entry.description.as_deref().unwrap_or_default(),
+            entry.app_npub.as_deref().unwrap_or_default(),
+            &entry.invoice,
+            entry.preimage.as_deref().unwrap_or_default(),
+        ]));
+    }
+    csv
+}
+
+/// Exports the connection log entries from `since` up to and including `until` as CSV, in the order given.
+/// If none are in range, only the header is returned.
+pub fn connection_log_csv(
+    entries: &[ConnectionLogEntry],
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> String {
+    let mut csv = csv_row(&CONNECTION_LOG_HEADER);
+    for entry in entries
+        .iter()
+        .filter(|entry| is_in_range(&entry.time, since, until))
+    {
+        let (event, detail) = match &entry.event {
+            ConnectionEvent::Connected => ("connected", String::new()),
+            ConnectionEvent::Request { method } => ("request", method.clone()),
+            ConnectionEvent::Error { message } => ("error", message.clone()),
+            ConnectionEvent::Disconnected { request_counts } => (
+                "disconnected",
+                request_counts
+                    .iter()
+                    .map(|(method, count)| format!("{method}={count}"))
+                    .collect::<Vec<_>>()
+                    .join(";"),
+            ),
+        };
+        csv.push_str(&csv_row(&[
+            &entry.time,
+            &entry.connection_id.to_string(),
+            &entry.server_address,
+            event,
+            &detail,
+        ]));
+    }
+    csv
+}
+
+fn is_in_range(time: &str, since: DateTime<Utc>, until: DateTime<Utc>) -> bool {
+    DateTime::parse_from_rfc3339(time)
+        .map(|time| time >= since && time <= until)
+        .unwrap_or(false)
+}
+
+fn csv_row(fields: &[&str]) -> String {
+    let mut row = fields
+        .iter()
+        .map(|field| escape_csv_field(field))
+        .collect::<Vec<_>>()
+        .join(",");
+    row.push_str("\r\n");
+    row
+}
+
+/// Quotes a field if it contains anything that would otherwise break the row, as described in RFC 4180.
+fn escape_csv_field(field: &str) -> Cow<str> {
+    if field.contains([',', '"', '\r', '\n']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payment_log::PaymentOutcome;
+
+    #[test]
+    fn payment_log_csv_escapes_fields() {
+        let entry = PaymentLogEntry {
+            invoice: "lnbc1".to_string(),
+            amount_msats: Some(1000),
+            description: Some("Coffee, with \"extra\" milk".to_string()),
+            app_npub: None,
+            create_time: "2024-03-01T12:00:00+00:00".to_string(),
+            outcome: PaymentOutcome::Paid,
+            preimage: Some("00".repeat(32)),
+        };
+
+        assert_eq!(
+            payment_log_csv(
+                &[entry],
+                DateTime::<Utc>::MIN_UTC,
+                DateTime::<Utc>::MAX_UTC
+            ),
+            format!(
+                "time,outcome,amount_msats,description,app_npub,invoice,preimage\r\n\
+                2024-03-01T12:00:00+00:00,paid,1000,\"Coffee, with \"\"extra\"\" milk\",,lnbc1,{}\r\n",
+                "00".repeat(32)
+            )
+        );
+    }
+
+    #[test]
+    fn empty_range_is_only_header() {
+        let entry = PaymentLogEntry {
+            invoice: "lnbc1".to_string(),
+            amount_msats: None,
+            description: None,
+            app_npub: None,
+            create_time: "2024-03-01T12:00:00+00:00".to_string(),
+            outcome: PaymentOutcome::Rejected,
+            preimage: None,
+        };
+        let since = DateTime::parse_from_rfc3339("2024-04-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(
+            payment_log_csv(&[entry], since, DateTime::<Utc>::MAX_UTC),
+            "time,outcome,amount_msats,description,app_npub,invoice,preimage\r\n"
+        );
+        assert_eq!(
+            connection_log_csv(&[], DateTime::<Utc>::MIN_UTC, DateTime::<Utc>::MAX_UTC),
+            "time,connection_id,server_address,event,detail\r\n"
+        );
+    }
+}
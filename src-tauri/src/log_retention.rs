@@ -0,0 +1,57 @@
+use crate::error_log;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often old log entries are pruned.
+pub const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Most entries deleted from a log at once, so that pruning a large backlog doesn't hold the log's lock for long.
+const PRUNE_BATCH_SIZE: u64 = 500;
+
+/// A log whose old entries can be deleted.
+pub trait PrunableLog: Send + Sync {
+    /// Deletes up to `limit` of the entries logged before `cutoff`, returning how many were deleted.
+    fn delete_entries_before(&self, cutoff: DateTime<Utc>, limit: u64) -> anyhow::Result<u64>;
+}
+
+/// Deletes the entries logged more than `retention_days` days before `now`, in batches. A retention of 0 days
+/// keeps every entry. Returns how many entries were deleted.
+pub fn prune_log(
+    log: &dyn PrunableLog,
+    retention_days: u64,
+    now: DateTime<Utc>,
+) -> anyhow::Result<u64> {
+    if retention_days == 0 {
+        return Ok(0);
+    }
+    let cutoff = now - chrono::Duration::days(retention_days.try_into()?);
+
+    let mut deleted = 0;
+    loop {
+        let batch_deleted = log.delete_entries_before(cutoff, PRUNE_BATCH_SIZE)?;
+        deleted += batch_deleted;
+        if batch_deleted < PRUNE_BATCH_SIZE {
+            return Ok(deleted);
+        }
+    }
+}
+
+/// Prunes every log once per [`PRUNE_INTERVAL`], starting straight away. `retention_days` is read before each run,
+/// so that changes to the retention apply from the next run. Never returns.
+pub async fn prune_logs_periodically(
+    logs: Vec<Arc<dyn PrunableLog>>,
+    retention_days: impl Fn() -> u64,
+) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let retention_days = retention_days();
+        for log in &logs {
+            if let Err(err) = prune_log(log.as_ref(), retention_days, Utc::now()) {
+                error_log::report(format!("Failed to prune log: {err}"));
+            }
+        }
+    }
+}
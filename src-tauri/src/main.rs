@@ -1,233 +1,423 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod account_completeness;
+mod account_data;
+mod account_rotation;
+mod account_stats;
+mod app_identity;
+mod app_key_allowlist;
+mod approval_timeouts;
+mod backup;
+mod benchmark;
+mod clipboard;
+mod confirmation_phrase;
+mod connection_log;
+mod connection_qr;
+mod contacts;
 mod database;
+mod dm;
+mod entity;
+mod error_log;
+mod event_verification;
+mod features;
+mod fingerprint;
+mod http;
+mod identity_card;
+mod invoice;
+mod key_cache;
+mod key_health;
+mod key_manager;
+mod lightning_address;
+mod log_export;
+mod log_retention;
+#[cfg(test)]
+mod mock_relay;
+mod ncryptsec;
+mod nip05;
+mod nip46_server;
+mod nprofile;
+#[cfg(feature = "nwc-service")]
+mod nwc_service;
+mod passkey;
+mod payment_backend;
+mod payment_cap;
+mod payment_ledger;
+mod payment_log;
+#[cfg(feature = "pkcs11")]
+mod pkcs11;
+mod pow;
+mod profile;
+mod quiet_hours;
+mod relay_visibility;
+mod relays;
+mod replaceable_event;
+mod request_approver;
+mod request_attention;
+mod scam_list;
+mod seed;
+mod server_registry;
+mod session_grants;
+mod shared_secret;
+mod sign_decisions;
+mod sign_event_request;
+mod signer;
+mod signing_log;
+#[cfg(test)]
+mod test_support;
+mod validation;
+mod watchdog;
+mod zap_receipt;
 
-use async_trait::async_trait;
-use database::Database;
-use lightning_invoice::Bolt11Invoice;
-use nip_55::nip46::{Nip46OverNip55Server, Nip46RequestApproval, Nip46RequestApprover};
+use account_completeness::AccountCompleteness;
+use account_data::AccountDataRefresh;
+use account_rotation::RotateAccountResponse;
+use account_stats::AccountStats;
+use app_key_allowlist::AppKeyAllowlist;
+use approval_timeouts::ApprovalTimeouts;
+use benchmark::SigningBenchmark;
+use confirmation_phrase::{ConfirmationPhrasePolicy, HighRiskAction};
+use connection_log::{ConnectionLog, ConnectionLogEntry, LoggingRequestHandler};
+use connection_qr::ConnectionQrPayload;
+use contacts::{Contact, UpdateFollowListResponse};
+use database::{AccountMetadata, DatabaseDiagnosis, DbError, VaultIntegrityReport};
+use entity::DecodedEntity;
+use event_verification::EventVerification;
+use features::Capabilities;
+use fingerprint::{IdenticonSeed, IdentityFingerprint};
+use http::HttpJsonFetcher;
+use identity_card::IdentityCard;
+use invoice::DecodedInvoice;
+use key_cache::KeyCacheStats;
+use key_health::KeyHealth;
+use key_manager::{
+    AccountListEntry, AppAuthorization, BulkImportResult, KeystacheKeyManager, SetupState,
+};
+use lightning_address::LightningAddress;
+use log_export::LogKind;
+use log_retention::PrunableLog;
+use nip05::Nip05Profile;
+use nip46_server::Nip46Server;
 use nip_55::KeyManager;
-use nostr_sdk::key::SecretKey;
-use nostr_sdk::nips::nip46;
-use nostr_sdk::secp256k1::{Keypair, Secp256k1};
-use nostr_sdk::{EventId, FromBech32, PublicKey, ToBech32};
-use std::collections::HashMap;
+#[cfg(feature = "nwc-service")]
+use nostr_sdk::nips::nip47::{Method, Response};
+use nostr_sdk::secp256k1::Secp256k1;
+use nostr_sdk::util::hex;
+use nostr_sdk::{
+    Event, Filter, JsonUtil, Keys, Kind, Metadata, PublicKey, Timestamp, ToBech32, UnsignedEvent,
+};
+#[cfg(feature = "webauthn")]
+use passkey::{Es256AssertionVerifier, PasskeyCredential};
+use passkey::{PasskeyAssertion, PasskeyGate, UnlockMethod};
+use payment_backend::{
+    FeeEstimate, NoPaymentBackend, PayInvoiceResponse, PaymentBackend, PaymentResult,
+};
+use payment_cap::MaxSinglePayment;
+use payment_log::{BatchedPaymentLog, PaymentHistoryFilter, PaymentLogEntry};
+#[cfg(feature = "pkcs11")]
+use pkcs11::HardwareKeyBinding;
+use profile::{ProfileFields, UpdateProfileMetadataResponse};
+use quiet_hours::QuietHours;
+use relay_visibility::RelayVisibilityPolicy;
+use relays::{
+    AuthEventCache, DuplicateRelays, RelayAuth, RelayPolicy, RelayPublishResult,
+    RelayReachabilityReport, RelayTestResult,
+};
+use request_approver::{
+    EventEmitter, KeystacheRequestApprover, NoApprovalWindowError, PendingRequestEntry,
+};
+use request_attention::{RequestAttention, RequestNotification};
+use server_registry::{ServerInfo, ServerRegistry};
+use session_grants::SessionGrantEntry;
+use sign_decisions::RememberedSignDecision;
+use sign_event_request::ContentWarningPolicy;
+use signing_log::SigningLogEntry;
 use std::sync::Arc;
-use tauri::Manager;
-use tokio::sync::Mutex;
+use std::time::Duration;
+use tauri::{ClipboardManager, Manager};
+use watchdog::RestartPolicy;
+use zap_receipt::ZapReceiptValidation;
 
-struct KeystacheKeyManager {
-    /// Database handle. `None` if there was an error opening the database, otherwise `Some`.
-    database_or: Option<Database>,
-}
-
-impl KeystacheKeyManager {
-    fn new(app_handle: tauri::AppHandle) -> Self {
-        Self {
-            database_or: Database::new_in_app_data_dir(app_handle.clone(), None).ok(),
-        }
+impl EventEmitter for tauri::AppHandle {
+    fn emit(&self, event: &str, payload: serde_json::Value) -> anyhow::Result<()> {
+        Ok(self.emit_all(event, payload)?)
     }
 
-    /// Wipe all existing keypairs and save a new one.
-    /// TODO: Once we support multiple keypairs, we should remove this.
-    fn set_keypair(&self, keypair: Keypair) -> anyhow::Result<()> {
-        let database = match &self.database_or {
-            Some(database) => database,
-            None => return Err(anyhow::Error::msg("No database available")),
-        };
-
-        // Wipe all existing keypairs.
-        // TODO: Hardcoding the limit here isn't very robust. Should we allow for
-        // setting it to `None` to allow for iterating through all keypairs?
-        for keypair in database.list_keypairs(10_000, 0)? {
-            database.remove_keypair(&keypair.x_only_public_key().0.into())?;
+    fn ensure_listener(&self) -> anyhow::Result<()> {
+        if !self.windows().is_empty() {
+            return Ok(());
         }
-
-        // Save the new keypair.
-        database.save_keypair(&keypair)
-    }
-
-    fn get_public_key(&self) -> anyhow::Result<Option<PublicKey>> {
-        let database = match &self.database_or {
-            Some(database) => database,
-            None => return Err(anyhow::Error::msg("No database available")),
-        };
-        database.get_first_public_key()
+        // The window was closed while Keystache kept running, so open it again. The request is emitted before the
+        // new window's frontend starts listening, so it picks the request up with `list_pending_requests` instead.
+        let window_config = self
+            .config()
+            .tauri
+            .windows
+            .first()
+            .cloned()
+            .unwrap_or_default();
+        tauri::WindowBuilder::from_config(self, window_config)
+            .build()
+            .map_err(|err| NoApprovalWindowError {
+                reason: err.to_string(),
+            })?;
+        Ok(())
     }
-}
 
-#[async_trait]
-impl KeyManager for KeystacheKeyManager {
-    fn get_secret_key(&self, public_key: &PublicKey) -> Option<SecretKey> {
-        let database = match &self.database_or {
-            Some(database) => database,
-            None => return None,
+    fn request_attention(
+        &self,
+        focus_window: bool,
+        notification: Option<RequestNotification>,
+    ) -> anyhow::Result<()> {
+        let Some(window) = self.windows().into_values().next() else {
+            return Ok(());
         };
-        // TODO: Fetch the secret key using the public key rather than iterating through all keypairs.
-        let keypairs = database.list_keypairs(999, 0).ok()?;
-        keypairs
-            .into_iter()
-            .find(|keypair| keypair.x_only_public_key().0 == **public_key)
-            .map(|keypair| keypair.secret_key().into())
+        if window.is_focused()? {
+            return Ok(());
+        }
+        if focus_window {
+            window.unminimize()?;
+            window.show()?;
+            window.set_focus()?;
+        } else {
+            // Short of focusing the window, at least flash it in the taskbar or bounce it in the dock.
+            window.request_user_attention(Some(tauri::UserAttentionType::Informational))?;
+        }
+        // The frontend shows the notification, so that it can use the system's notifications without Keystache
+        // having to be built with Tauri's notification API.
+        if let Some(notification) = notification {
+            self.emit_all("request_notification", notification)?;
+        }
+        Ok(())
     }
 }
 
-struct KeystacheRequestApprover {
-    /// Map of hex-encoded event IDs to channels for signaling when the signing of an event has been approved/rejected.
-    in_progress_event_signings:
-        Mutex<HashMap<String, tokio::sync::oneshot::Sender<Nip46RequestApproval>>>,
-
-    /// Map of Bolt11 invoice strings to channels for signaling when the payment of an invoice has been paid/failed/rejected.
-    in_progress_invoice_payments:
-        Mutex<HashMap<String, tokio::sync::oneshot::Sender<Nip46RequestApproval>>>,
-
-    /// Handle to the Tauri application. Used to emit events.
-    app_handle: tauri::AppHandle,
-}
-
-impl KeystacheRequestApprover {
-    fn new(app_handle: tauri::AppHandle) -> Self {
-        Self {
-            in_progress_event_signings: Mutex::new(HashMap::new()),
-            in_progress_invoice_payments: Mutex::new(HashMap::new()),
-            app_handle,
-        }
+impl clipboard::Clipboard for tauri::AppHandle {
+    fn read_text(&self) -> anyhow::Result<Option<String>> {
+        Ok(self.clipboard_manager().read_text()?)
     }
 
-    async fn pay_invoice(&self, invoice: Bolt11Invoice) -> anyhow::Result<Nip46RequestApproval> {
-        let invoice_string = invoice.to_string();
-
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        self.in_progress_invoice_payments
-            .lock()
-            .await
-            .insert(invoice_string.clone(), tx);
-
-        self.app_handle
-            .emit_all("pay_invoice_request", invoice_string)?;
-
-        Ok(rx.await?)
+    fn write_text(&self, text: &str) -> anyhow::Result<()> {
+        Ok(self.clipboard_manager().write_text(text)?)
     }
 }
 
-#[async_trait]
-impl Nip46RequestApprover for KeystacheRequestApprover {
-    async fn handle_batch_request(
-        &self,
-        requests: Vec<(nip46::Request, PublicKey)>,
-    ) -> Nip46RequestApproval {
-        // TODO: IMPORTANT!!! Currently we ignore all but the first request. We should handle all requests.
-        // TODO: We should use `_user_pubkey` and pass it to the frontend.
-        let (request, user_pubkey) = match requests.into_iter().next() {
-            Some(request) => request,
-            None => return Nip46RequestApproval::Reject,
-        };
-
-        // TODO: Handle more than just signing events.
-        let mut event = match request {
-            nip46::Request::SignEvent(event) => event,
-            _ => return Nip46RequestApproval::Reject,
-        };
+/// Unix domain socket address that the NIP-70 server listens on.
+const NIP_70_UDS_ADDRESS: &str = "/tmp/nip55-kind24133";
 
-        // TODO: Is this seriously the best way to do this?!
-        let event_id = EventId::new(
-            &event.pubkey,
-            event.created_at,
-            &event.kind,
-            &event.tags,
-            &event.content,
-        );
-
-        event.id = Some(event_id);
-
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        self.in_progress_event_signings
-            .lock()
-            .await
-            .insert(event_id.to_hex(), tx);
-
-        if self
-            .app_handle
-            .emit_all(
-                "sign_event_request",
-                (event, user_pubkey.to_bech32().unwrap()),
-            )
-            .is_err()
-        {
-            return Nip46RequestApproval::Reject;
-        }
+/// WebAuthn relying party ID that passkeys are registered under. Tauri serves the frontend from `localhost`.
+#[cfg(feature = "webauthn")]
+const PASSKEY_RELYING_PARTY_ID: &str = "localhost";
 
-        rx.await.unwrap_or(Nip46RequestApproval::Reject)
-    }
-}
+/// How often the NIP-70 server is checked to still be accepting connections.
+const NIP_70_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
 
+/// Responds to a sign event request. If `edited_event` is given, it is signed instead of the original,
+/// as long as it has the same pubkey and kind. If `remember` is set and the request can be remembered,
+/// the response is also used for later events of the same kind from the same app.
 #[tauri::command]
 async fn respond_to_sign_event_request(
     event_id: String,
     approved: bool,
+    edited_event: Option<UnsignedEvent>,
+    remember: Option<bool>,
     state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
 ) -> Result<(), ()> {
-    if let Some(tx) = state
-        .in_progress_event_signings
-        .lock()
-        .await
-        .remove(&event_id)
-    {
-        let approval = if approved {
-            Nip46RequestApproval::Approve
-        } else {
-            Nip46RequestApproval::Reject
-        };
-        let _ = tx.send(approval);
-    }
+    state
+        .respond_to_sign_event_request(&event_id, approved, edited_event, remember.unwrap_or(false))
+        .await;
+    Ok(())
+}
 
+/// Responds to a request to sign a batch of events.
+#[tauri::command]
+async fn respond_to_sign_events_request(
+    batch_id: String,
+    approved: bool,
+    state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), ()> {
+    state
+        .respond_to_sign_events_request(&batch_id, approved)
+        .await;
     Ok(())
 }
 
+/// Responds to a pay invoice request. Payments that need a confirmation phrase (see `set_confirmation_phrases`)
+/// can only be approved with `confirmation_phrase` set to it; otherwise this errors and the request keeps waiting.
 #[tauri::command]
 async fn respond_to_pay_invoice_request(
     invoice: String,
     approved: bool,
+    confirmation_phrase: Option<String>,
     state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
-) -> Result<(), ()> {
-    if let Some(tx) = state
-        .in_progress_invoice_payments
-        .lock()
+) -> Result<(), String> {
+    state
+        .respond_to_pay_invoice_request(&invoice, approved, confirmation_phrase.as_deref())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Lists the requests waiting for the user, with the payloads they were emitted with, so that the frontend can
+/// rebuild its approval queue after a reload.
+#[tauri::command]
+async fn list_pending_requests(
+    state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<Vec<PendingRequestEntry>, ()> {
+    Ok(state.list_pending_requests())
+}
+
+/// Estimates the routing fee for paying an invoice through the configured payment backend.
+#[tauri::command]
+async fn estimate_payment_fee(
+    invoice: String,
+    state: tauri::State<'_, Arc<dyn PaymentBackend>>,
+) -> Result<FeeEstimate, String> {
+    let invoice = validation::validate_invoice(&invoice).map_err(|err| err.to_string())?;
+    state
+        .estimate_fee(&invoice)
+        .await
+        .map_err(|err| format!("Error estimating fee: {}", err))
+}
+
+/// Asks the user to approve paying an invoice from the active account, and pays it through the configured payment
+/// backend if they do. `app_id` is the npub of the app asking, if any. Errors without asking the user if no payment
+/// backend is configured. Paying an invoice that was paid recently returns the earlier result rather than paying
+/// it twice.
+#[tauri::command]
+async fn pay_invoice(
+    invoice: String,
+    app_id: Option<String>,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<PayInvoiceResponse, String> {
+    let invoice = validation::validate_invoice(&invoice).map_err(|err| err.to_string())?;
+    let app_public_key = app_id
+        .map(|app_id| validation::validate_npub(&app_id))
+        .transpose()
+        .map_err(|err| err.to_string())?;
+    let public_key = key_manager_state
+        .get_public_key()
+        .map_err(|_| "Error reading active account")?
+        .ok_or("No public key available")?;
+    match request_approver_state
+        .pay_invoice(invoice, public_key, app_public_key)
         .await
-        .remove(&invoice)
+        .map_err(|err| err.to_string())?
     {
-        let approval = if approved {
-            Nip46RequestApproval::Approve
-        } else {
-            Nip46RequestApproval::Reject
-        };
-        let _ = tx.send(approval);
+        PaymentResult::Paid(response) => Ok(response),
+        PaymentResult::Rejected => Err("Payment rejected".to_string()),
+        PaymentResult::Failed { reason } => Err(format!("Payment failed: {reason}")),
     }
+}
 
-    Ok(())
+/// Lists the NIPs, payment backends and optional features that this build supports, so that connecting apps can
+/// adapt to them.
+#[tauri::command]
+async fn get_capabilities(
+    state: tauri::State<'_, Arc<dyn PaymentBackend>>,
+) -> Result<Capabilities, ()> {
+    Ok(Capabilities::new(state.as_ref()))
 }
 
+/// Returns the active account's public key. If `app_id` (the app's npub) is set and the app has an identity of
+/// its own turned on, that identity's public key is returned instead, and events with it as their pubkey are signed
+/// with its key. If the vault has no keys, the active account is the first one bound to a hardware key that could
+/// be opened.
 #[tauri::command]
 async fn get_public_key(
+    app_id: Option<String>,
     state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    // Only hardware keys need the request approver's signers.
+    #[cfg_attr(not(feature = "pkcs11"), allow(unused_variables))]
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
 ) -> Result<PublicKey, String> {
-    match state
-        .get_public_key()
-        .map_err(|err| format!("Error: {:?}", err))?
-    {
+    let public_key = match app_id {
+        Some(app_id) => {
+            let app_public_key =
+                validation::validate_npub(&app_id).map_err(|err| err.to_string())?;
+            state.get_public_key_for_app(&app_public_key)
+        }
+        None => state.get_public_key(),
+    };
+    #[cfg(feature = "pkcs11")]
+    let public_key = public_key.map(|public_key| {
+        public_key.or_else(|| first_hardware_public_key(&state, &request_approver_state))
+    });
+    match public_key.map_err(|err| format!("Error: {:?}", err))? {
         Some(public_key) => Ok(public_key),
         None => Err("No public key available".to_string()),
     }
 }
 
+/// Returns the first account bound to a hardware key whose token was opened.
+#[cfg(feature = "pkcs11")]
+fn first_hardware_public_key(
+    key_manager: &KeystacheKeyManager,
+    request_approver: &KeystacheRequestApprover,
+) -> Option<PublicKey> {
+    key_manager
+        .list_hardware_key_bindings()
+        .ok()?
+        .iter()
+        .filter_map(|binding| validation::validate_npub(&binding.npub).ok())
+        .find(|public_key| request_approver.has_hardware_signer(public_key))
+}
+
+/// Whether the app (by npub) is given an identity of its own instead of the active account's.
+#[tauri::command]
+async fn get_app_identity_enabled(
+    app_id: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<bool, String> {
+    let app_public_key = validation::validate_npub(&app_id).map_err(|err| err.to_string())?;
+    state
+        .is_app_identity_enabled(&app_public_key)
+        .map_err(|_| "Error reading app identity setting".to_string())
+}
+
+/// Turns an app's own identity on or off. While on, the app is given a pubkey derived from the account's key and
+/// the app's npub, so that apps can't link what the user does in each of them. The same app always gets the same
+/// identity.
+#[tauri::command]
+async fn set_app_identity_enabled(
+    app_id: String,
+    enabled: bool,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<(), String> {
+    let app_public_key = validation::validate_npub(&app_id).map_err(|err| err.to_string())?;
+    state
+        .set_app_identity_enabled(&app_public_key, enabled)
+        .map_err(|_| "Error saving app identity setting".to_string())
+}
+
+/// Returns a short fingerprint of the active account's public key, so that the user can check at a glance that
+/// the expected identity is active.
+#[tauri::command]
+async fn get_identity_fingerprint(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<IdentityFingerprint, String> {
+    let public_key = state
+        .get_public_key()
+        .map_err(|_| "Error reading active account")?
+        .ok_or("No public key available")?;
+    IdentityFingerprint::new(&public_key).map_err(|_| "Error encoding public key".to_string())
+}
+
+/// Returns bytes derived from an npub that are always the same for it, for drawing a consistent avatar and color for
+/// an account without a profile picture.
+#[tauri::command]
+async fn get_identicon_seed(npub: String) -> Result<IdenticonSeed, String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    IdenticonSeed::new(&public_key).map_err(|_| "Error encoding public key".to_string())
+}
+
 #[tauri::command]
 async fn set_nsec(
     nsec: String,
     state: tauri::State<'_, Arc<KeystacheKeyManager>>,
 ) -> Result<(), String> {
-    let keypair = SecretKey::from_bech32(nsec)
-        .map_err(|_| "Error parsing nsec")?
+    let keypair = validation::validate_nsec(&nsec)
+        .map_err(|err| err.to_string())?
         .keypair(&Secp256k1::new());
     state
         .set_keypair(keypair)
@@ -235,29 +425,2329 @@ async fn set_nsec(
     Ok(())
 }
 
-#[tokio::main]
-async fn main() {
-    tauri::Builder::default()
-        .invoke_handler(tauri::generate_handler![
-            respond_to_sign_event_request,
-            respond_to_pay_invoice_request,
-            get_public_key,
-            set_nsec
-        ])
-        .setup(|app| {
-            let keystache_key_manager = Arc::new(KeystacheKeyManager::new(app.handle()));
-            let keystache_request_approver = Arc::new(KeystacheRequestApprover::new(app.handle()));
-            let nip_70_server_or = Nip46OverNip55Server::start(
-                "/tmp/nip55-kind24133",
-                keystache_key_manager.clone(),
-                keystache_request_approver.clone(),
-            )
-            .ok();
+/// Adds an account alongside any existing ones, with an optional label and relays that are both read from and
+/// written to. Returns the account's npub. Nothing is saved unless the whole account is.
+#[tauri::command]
+async fn create_account(
+    nsec: String,
+    label: Option<String>,
+    relay_urls: Vec<String>,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<String, String> {
+    let keypair = validation::validate_nsec(&nsec)
+        .map_err(|err| err.to_string())?
+        .keypair(&Secp256k1::new());
+    let relays = relay_urls
+        .iter()
+        .map(|url| {
+            let url = validation::normalize_relay_url(url).map_err(|err| err.to_string())?;
+            Ok((
+                url,
+                RelayPolicy {
+                    read: true,
+                    write: true,
+                },
+            ))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    state
+        .create_account(&keypair, label.as_deref(), &relays)
+        .map_err(|err| match err.downcast_ref::<DbError>() {
+            Some(_) => "Error creating account. Nothing was saved".to_string(),
+            None => err.to_string(),
+        })?;
+    PublicKey::from(keypair.x_only_public_key().0)
+        .to_bech32()
+        .map_err(|_| "Error encoding npub".to_string())
+}
+
+/// Adds the account at NIP-06 account `index` of a BIP-39 mnemonic, and returns its npub.
+/// Deriving indices 0, 1, 2... from one mnemonic gives the user many accounts backed up by a single seed.
+#[tauri::command]
+async fn derive_account(
+    mnemonic: String,
+    index: u32,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<String, String> {
+    state
+        .derive_account(&mnemonic, index)
+        .map_err(|err| err.to_string())?
+        .to_bech32()
+        .map_err(|_| "Error encoding npub".to_string())
+}
+
+/// Copies a secret (e.g. an nsec) to the clipboard, and clears it after `seconds` unless something else has been copied since.
+#[tauri::command]
+async fn copy_secret_to_clipboard_with_timeout(
+    value: String,
+    seconds: u64,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    if seconds == 0 {
+        return Err("Timeout must be at least one second".to_string());
+    }
+
+    clipboard::copy_with_auto_clear(Arc::new(app_handle), value, Duration::from_secs(seconds))
+        .map_err(|_| "Error copying to clipboard".to_string())?;
+    Ok(())
+}
+
+/// Lists every app that has been paired with an account, along with what it has been granted.
+#[tauri::command]
+async fn list_authorizations(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Vec<AppAuthorization>, String> {
+    state
+        .list_authorizations()
+        .map_err(|_| "Error listing authorizations".to_string())
+}
+
+/// Revokes an app's pairing and everything it has been granted. `app_id` is the app's npub.
+#[tauri::command]
+async fn revoke_authorization(
+    app_id: String,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    let app_public_key = validation::validate_npub(&app_id).map_err(|err| err.to_string())?;
+    key_manager_state
+        .revoke_authorization(&app_public_key)
+        .map_err(|_| "Error revoking authorization")?;
+    request_approver_state.revoke_session(&app_public_key);
+    save_session_grants(&key_manager_state, &request_approver_state)
+}
+
+/// Lists the sign decisions the user asked to remember for each app and event kind.
+#[tauri::command]
+async fn list_sign_decisions(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Vec<RememberedSignDecision>, String> {
+    state
+        .list_sign_decisions()
+        .map_err(|_| "Error listing sign decisions".to_string())
+}
+
+/// Forgets the remembered sign decision for an app and event kind, so the user is asked again next time.
+/// `app_id` is the app's npub.
+#[tauri::command]
+async fn forget_sign_decision(
+    app_id: String,
+    kind: u64,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<(), String> {
+    let app_public_key = validation::validate_npub(&app_id).map_err(|err| err.to_string())?;
+    state
+        .forget_sign_decision(&app_public_key, Kind::from(kind))
+        .map_err(|_| "Error forgetting sign decision".to_string())
+}
+
+/// Lets an app have events of `allowed_kinds` signed without asking for the next `duration_secs` seconds, e.g.
+/// "sign freely for the next 10 minutes". Replaces any session the app already has. `app_id` is the app's npub.
+#[tauri::command]
+async fn grant_session(
+    app_id: String,
+    duration_secs: u64,
+    allowed_kinds: Vec<u64>,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    let app_public_key = validation::validate_npub(&app_id).map_err(|err| err.to_string())?;
+    request_approver_state
+        .grant_session(
+            app_public_key,
+            Duration::from_secs(duration_secs),
+            allowed_kinds.into_iter().map(Kind::from).collect(),
+        )
+        .map_err(|err| err.to_string())?;
+    save_session_grants(&key_manager_state, &request_approver_state)
+}
+
+/// Ends an app's session early, so that its requests are asked about again. `app_id` is the app's npub.
+#[tauri::command]
+async fn revoke_session(
+    app_id: String,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    let app_public_key = validation::validate_npub(&app_id).map_err(|err| err.to_string())?;
+    request_approver_state.revoke_session(&app_public_key);
+    save_session_grants(&key_manager_state, &request_approver_state)
+}
+
+/// Saves the session grants, keyed by app rather than by connection, so that an app that reconnects keeps its
+/// session until it expires.
+fn save_session_grants(
+    key_manager: &KeystacheKeyManager,
+    request_approver: &KeystacheRequestApprover,
+) -> Result<(), String> {
+    let session_grants = request_approver
+        .stored_session_grants()
+        .map_err(|_| "Error reading sessions")?;
+    key_manager
+        .set_session_grants(&session_grants)
+        .map_err(|_| "Error saving sessions".to_string())
+}
+
+/// Lists the apps with a session that hasn't expired yet.
+#[tauri::command]
+async fn list_session_grants(
+    state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<Vec<SessionGrantEntry>, String> {
+    state
+        .list_session_grants()
+        .map_err(|_| "Error listing sessions".to_string())
+}
+
+/// Sets the friendly name that approval prompts show for an app, or clears it if `name` is `None`.
+/// `app_id` is the app's npub.
+#[tauri::command]
+async fn set_app_name(
+    app_id: String,
+    name: Option<String>,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    let app_public_key = validation::validate_npub(&app_id).map_err(|err| err.to_string())?;
+    let name = name.map(|name| name.trim().to_string());
+    if name.as_deref() == Some("") {
+        return Err("App name can't be empty".to_string());
+    }
+
+    key_manager_state
+        .set_app_name(&app_public_key, name.as_deref())
+        .map_err(|err| err.to_string())?;
+    request_approver_state.set_app_names(
+        key_manager_state
+            .get_app_names()
+            .map_err(|_| "Error reading app names")?,
+    );
+    Ok(())
+}
+
+/// Lists requests to pay invoices, most recent first. Use `limit` and `offset` for pagination.
+/// If `filter` is provided, only the payments matching it are returned.
+#[tauri::command]
+async fn get_payment_history(
+    limit: u64,
+    offset: u64,
+    filter: Option<PaymentHistoryFilter>,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Vec<PaymentLogEntry>, String> {
+    state
+        .get_payment_history(limit, offset, &filter.unwrap_or_default())
+        .map_err(|_| "Error listing payment history".to_string())
+}
+
+/// Lists what an app has asked to sign, most recent first, with the kind of each event, when the request ended, and
+/// whether it was approved. Use `limit` and `offset` for pagination.
+#[tauri::command]
+async fn get_app_signing_history(
+    app_id: String,
+    limit: u64,
+    offset: u64,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Vec<SigningLogEntry>, String> {
+    let app_public_key = validation::validate_npub(&app_id).map_err(|err| err.to_string())?;
+    state
+        .get_app_signing_history(&app_public_key, limit, offset)
+        .map_err(|_| "Error listing signing history".to_string())
+}
+
+/// Replaces the list of pubkeys and LNURLs that requests are flagged for referencing.
+/// Each entry is a pubkey (hex or npub), an LNURL, or a lightning address.
+#[tauri::command]
+async fn update_scam_list(
+    entries: Vec<String>,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    let scam_list = key_manager_state
+        .set_scam_list(&entries)
+        .map_err(|err| err.to_string())?;
+    request_approver_state.set_scam_list(scam_list);
+    Ok(())
+}
+
+/// Lists the entries of the scam list, with pubkeys as npubs.
+#[tauri::command]
+async fn get_scam_list(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Vec<String>, String> {
+    state
+        .get_scam_list()
+        .and_then(|scam_list| scam_list.entries())
+        .map_err(|_| "Error getting scam list".to_string())
+}
+
+/// Returns where the user is in first-run setup, so the frontend can show the right screen.
+#[tauri::command]
+async fn get_setup_state(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<SetupState, String> {
+    state
+        .get_setup_state()
+        .map_err(|_| "Error reading setup state".to_string())
+}
+
+/// Opens the encrypted vault with its passphrase, then loads the settings that couldn't be read while it was locked.
+#[tauri::command]
+async fn unlock_vault(
+    passphrase: String,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    key_manager_state
+        .unlock_vault(&passphrase)
+        .map_err(|err| err.to_string())?;
+    load_request_approver_settings(&request_approver_state, &key_manager_state);
+    Ok(())
+}
+
+/// Encrypts a vault that was created without a passphrase, including every stored key. This only needs to be done
+/// once: from the next launch on, the vault has to be unlocked with `unlock_vault`.
+#[tauri::command]
+async fn encrypt_existing_keys(
+    passphrase: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<(), String> {
+    state
+        .encrypt_existing_keys(&passphrase)
+        .map_err(|err| format!("Error encrypting vault: {err}"))
+}
+
+/// Lists the npubs of all accounts, including watch-only ones, and whether each can sign.
+#[tauri::command]
+async fn list_accounts(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Vec<AccountListEntry>, String> {
+    state
+        .list_account_entries()
+        .map_err(|_| "Error listing accounts".to_string())
+}
+
+/// Adds an npub as a watch-only account, which can be monitored but can't sign.
+#[tauri::command]
+async fn add_watch_only_account(
+    npub: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<(), String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    state
+        .add_watch_only_account(&public_key)
+        .map_err(|err| err.to_string())
+}
+
+/// Replaces an account with a newly generated key that inherits its label and relays, and marks the old account as retired.
+/// If `announce` is set, the old account signs a note pointing to the new one, which is published to its write relays.
+#[tauri::command]
+async fn rotate_account(
+    npub: String,
+    announce: bool,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+    auth_event_cache_state: tauri::State<'_, AuthEventCache>,
+) -> Result<RotateAccountResponse, String> {
+    if announce {
+        // Fail before rotating anything if the announcement can't be published.
+        key_manager_state
+            .ensure_online()
+            .map_err(|err| err.to_string())?;
+    }
+
+    let old_public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    let new_keypair = key_manager_state
+        .rotate_account(&old_public_key)
+        .map_err(|err| err.to_string())?;
+    let new_public_key = PublicKey::from(new_keypair.x_only_public_key().0);
+    let new_npub = new_public_key
+        .to_bech32()
+        .map_err(|_| "Error encoding npub")?;
+
+    if !announce {
+        return Ok(RotateAccountResponse {
+            new_npub,
+            announcement: None,
+            publish_results: Vec::new(),
+        });
+    }
+
+    let unsigned_event = account_rotation::build_announcement_event(old_public_key, new_public_key)
+        .map_err(|_| "Error building announcement")?;
+    let event = request_approver_state
+        .sign_event_with_approval(unsigned_event, key_manager_state.as_ref())
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let relay_urls = key_manager_state
+        .list_write_relay_urls(&old_public_key)
+        .map_err(|_| "Error listing relays")?;
+    let auth_keys = key_manager_state
+        .get_secret_key(&old_public_key)
+        .map(Keys::new);
+    let publish_results = relays::publish_event(
+        &relay_urls,
+        &event,
+        auth_keys.as_ref().map(|keys| RelayAuth {
+            keys,
+            auth_event_cache: &auth_event_cache_state,
+        }),
+        relays::DEFAULT_RELAY_TIMEOUT,
+    )
+    .await;
+
+    Ok(RotateAccountResponse {
+        new_npub,
+        announcement: Some(event),
+        publish_results,
+    })
+}
+
+/// Sets the account's display label, or clears it if `label` is `None`.
+#[tauri::command]
+async fn set_account_label(
+    npub: String,
+    label: Option<String>,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<(), String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    state
+        .set_account_label(&public_key, label.as_deref())
+        .map_err(|err| err.to_string())
+}
+
+/// Returns the account's label, and whether (and in favour of what account) it has been retired.
+#[tauri::command]
+async fn get_account_metadata(
+    npub: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<AccountMetadata, String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    state
+        .get_account_metadata(&public_key)
+        .map_err(|_| "Error getting account metadata".to_string())
+}
+
+/// Returns how many events the account has signed and invoices it has paid, and when it was last used.
+#[tauri::command]
+async fn get_account_stats(
+    npub: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<AccountStats, String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    state
+        .get_account_stats(&public_key)
+        .map_err(|_| "Error getting account stats".to_string())
+}
+
+/// Returns which steps of setting up the account well have been done, for showing setup progress.
+#[tauri::command]
+async fn get_account_completeness(
+    npub: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<AccountCompleteness, String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    state
+        .get_account_completeness(&public_key)
+        .map_err(|_| "Error getting account completeness".to_string())
+}
+
+/// Records whether the user has confirmed that they've backed up the account's key.
+#[tauri::command]
+async fn set_backup_acknowledged(
+    npub: String,
+    acknowledged: bool,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<(), String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    state
+        .set_backup_acknowledged(&public_key, acknowledged)
+        .map_err(|_| "Error saving backup acknowledgement".to_string())
+}
+
+/// Returns how many secret keys are cached and how often the cache has been used, without returning any keys.
+#[tauri::command]
+async fn get_cache_stats(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<KeyCacheStats, String> {
+    Ok(state.get_key_cache_stats())
+}
+
+/// Zeroizes and forgets every cached secret key.
+#[tauri::command]
+async fn clear_key_cache(state: tauri::State<'_, Arc<KeystacheKeyManager>>) -> Result<(), String> {
+    state.clear_key_cache();
+    Ok(())
+}
+
+/// Signs `count` throwaway events with the active account's key, without prompting the user or contacting relays,
+/// and reports how fast signing is. Only the Keystache UI can run this, never a connected app, so it only runs
+/// when the user asks. `count` can be at most [`benchmark::MAX_BENCHMARK_SIGNS`].
+#[tauri::command]
+async fn benchmark_signing(
+    count: u32,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<SigningBenchmark, String> {
+    let public_key = state
+        .get_public_key()
+        .map_err(|_| "Error reading active account")?
+        .ok_or("No public key available")?;
+    let key_manager = state.inner().clone();
+    tokio::task::spawn_blocking(move || {
+        benchmark::benchmark_signing(count, &public_key, key_manager.as_ref())
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+/// Erases all keys, settings, and pending requests, and stops any per-account servers.
+/// Requires the vault passphrase to guard against accidental loss, and the confirmation phrase if one is required
+/// (see `set_confirmation_phrases`).
+/// Always fails if the vault hasn't been encrypted with a passphrase (see `encrypt_existing_keys`).
+#[tauri::command]
+async fn wipe_all_data(
+    passphrase: String,
+    confirmation_phrase: Option<String>,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+    server_registry_state: tauri::State<'_, ServerRegistry>,
+) -> Result<(), String> {
+    check_confirmation_phrase(
+        &key_manager_state,
+        HighRiskAction::WipeAllData,
+        confirmation_phrase.as_deref(),
+    )?;
+    key_manager_state
+        .wipe_all_data(&passphrase)
+        .map_err(|err| err.to_string())?;
+
+    request_approver_state.clear_pending_requests().await;
+    for server in server_registry_state.list_servers() {
+        let _ = server_registry_state.stop_server(&server.uds_address);
+    }
+
+    Ok(())
+}
+
+/// Checks that the vault isn't corrupted, reporting how many keys are intact or corrupt.
+/// Always fails if the vault hasn't been encrypted with a passphrase (see `encrypt_existing_keys`).
+#[tauri::command]
+async fn verify_vault_integrity(
+    passphrase: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<VaultIntegrityReport, String> {
+    state
+        .verify_vault_integrity(&passphrase)
+        .map_err(|err| err.to_string())
+}
+
+/// Checks that the key stored for an npub is a valid secret key for it, and that it isn't on the list of leaked keys
+/// (see `set_leaked_keys`). Never returns the key itself.
+#[tauri::command]
+async fn check_key_health(
+    npub: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<KeyHealth, String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    state
+        .check_key_health(&public_key)
+        .map_err(|err| err.to_string())
+}
+
+/// Lists the npubs of keys that are known to have leaked.
+#[tauri::command]
+async fn get_leaked_keys(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Vec<String>, String> {
+    state
+        .get_leaked_keys()
+        .and_then(|public_keys| {
+            public_keys
+                .iter()
+                .map(|public_key| Ok(public_key.to_bech32()?))
+                .collect()
+        })
+        .map_err(|_| "Error getting leaked keys".to_string())
+}
+
+/// Replaces the list of keys that are known to have leaked, which `check_key_health` warns about. Errors without
+/// saving anything if any npub is invalid.
+#[tauri::command]
+async fn set_leaked_keys(
+    npubs: Vec<String>,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<(), String> {
+    let public_keys = npubs
+        .iter()
+        .map(|npub| validation::validate_npub(npub))
+        .collect::<Result<Vec<PublicKey>, _>>()
+        .map_err(|err| err.to_string())?;
+    state
+        .set_leaked_keys(&public_keys)
+        .map_err(|_| "Error saving leaked keys".to_string())
+}
+
+/// Fixes any stored npub that doesn't match its nsec, returning how many were fixed.
+#[tauri::command]
+async fn repair_npubs(state: tauri::State<'_, Arc<KeystacheKeyManager>>) -> Result<usize, String> {
+    state.repair_npubs().map_err(|err| err.to_string())
+}
+
+/// Checks the database for missing tables, columns and indexes, re-creating missing tables and indexes if `repair`
+/// is `true`. Never deletes data.
+#[tauri::command]
+async fn diagnose_database(
+    repair: bool,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<DatabaseDiagnosis, String> {
+    state
+        .diagnose_database(repair)
+        .map_err(|err| err.to_string())
+}
+
+/// Imports a list of nsecs alongside any existing keys, e.g. when migrating from another signer.
+/// Reports whether each entry was imported, was already present, or was invalid.
+#[tauri::command]
+async fn bulk_import(
+    nsecs: Vec<String>,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Vec<BulkImportResult>, String> {
+    state
+        .bulk_import(&nsecs)
+        .map_err(|_| "Error importing keys".to_string())
+}
+
+/// Reads an encrypted backup file (e.g. one dropped onto the window) and imports its accounts alongside any existing keys.
+/// Nothing is imported unless the passphrase decrypts every key in the backup.
+/// Reports whether each account was imported or was already present.
+#[tauri::command]
+async fn import_backup_file(
+    path: String,
+    passphrase: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Vec<BulkImportResult>, String> {
+    let accounts = backup::read_backup_file(std::path::Path::new(&path), &passphrase)
+        .map_err(|err| err.to_string())?;
+    state
+        .import_backup(accounts)
+        .map_err(|_| "Error importing keys".to_string())
+}
+
+/// Decrypts a NIP-49 `ncryptsec` with a password and saves the key. Returns the key's npub.
+#[tauri::command]
+async fn import_ncryptsec(
+    ncryptsec: String,
+    password: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<String, String> {
+    let secret_key =
+        ncryptsec::decrypt_ncryptsec(&ncryptsec, &password).map_err(|err| err.to_string())?;
+    let keypair = secret_key.keypair(&Secp256k1::new());
+    state
+        .add_keypair(&keypair)
+        .map_err(|_| "Error saving keypair")?;
+
+    PublicKey::from(keypair.x_only_public_key().0)
+        .to_bech32()
+        .map_err(|_| "Error encoding npub".to_string())
+}
+
+/// Checks that a gated operation may go ahead. Requires a passkey assertion if a passkey is enrolled,
+/// otherwise falls back to the vault's passphrase.
+fn authorize_gated_operation(
+    key_manager: &KeystacheKeyManager,
+    passkey_gate: &PasskeyGate,
+    assertion_or: Option<&PasskeyAssertion>,
+    passphrase: Option<&str>,
+) -> Result<(), String> {
+    let credentials = key_manager
+        .list_passkey_credentials()
+        .map_err(|_| "Error listing passkeys")?;
+    let unlock_method = passkey_gate
+        .authorize(&credentials, assertion_or)
+        .map_err(|err| format!("Passkey check failed: {}", err))?;
+    if unlock_method == UnlockMethod::Passphrase {
+        key_manager
+            .verify_passphrase(passphrase.unwrap_or_default())
+            .map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+/// Returns how gated operations (e.g. exporting a key) must currently be authorized.
+#[tauri::command]
+async fn get_unlock_method(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<UnlockMethod, String> {
+    let credentials = state
+        .list_passkey_credentials()
+        .map_err(|_| "Error listing passkeys")?;
+    Ok(passkey::required_unlock_method(&credentials))
+}
+
+/// Returns a one-time challenge for the frontend to request a passkey assertion over.
+#[cfg(feature = "webauthn")]
+#[tauri::command]
+async fn get_passkey_challenge(state: tauri::State<'_, PasskeyGate>) -> Result<Vec<u8>, String> {
+    Ok(state.issue_challenge())
+}
+
+/// Registers a passkey. If a passkey is already enrolled, an assertion from it is required. Otherwise,
+/// `passphrase` must be the vault's.
+#[cfg(feature = "webauthn")]
+#[tauri::command]
+async fn register_passkey(
+    credential_id: Vec<u8>,
+    public_key: Vec<u8>,
+    passphrase: Option<String>,
+    assertion: Option<PasskeyAssertion>,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    passkey_gate_state: tauri::State<'_, PasskeyGate>,
+) -> Result<(), String> {
+    authorize_gated_operation(
+        &state,
+        &passkey_gate_state,
+        assertion.as_ref(),
+        passphrase.as_deref(),
+    )?;
+
+    let credential = PasskeyCredential {
+        credential_id,
+        public_key,
+    };
+    credential
+        .validate()
+        .map_err(|err| format!("Invalid passkey: {}", err))?;
+    state
+        .add_passkey_credential(credential)
+        .map_err(|err| format!("Error registering passkey: {}", err))
+}
+
+/// Unregisters a passkey. Requires an assertion from an enrolled passkey.
+#[cfg(feature = "webauthn")]
+#[tauri::command]
+async fn remove_passkey(
+    credential_id: Vec<u8>,
+    assertion: PasskeyAssertion,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    passkey_gate_state: tauri::State<'_, PasskeyGate>,
+) -> Result<(), String> {
+    authorize_gated_operation(&state, &passkey_gate_state, Some(&assertion), None)?;
+    state
+        .remove_passkey_credential(&credential_id)
+        .map_err(|_| "Error removing passkey".to_string())
+}
+
+/// Returns the active account's keys, for signing as it.
+#[cfg(feature = "nwc-service")]
+fn active_account_keys(key_manager: &KeystacheKeyManager) -> Result<Keys, String> {
+    let public_key = key_manager
+        .get_public_key()
+        .map_err(|err| err.to_string())?
+        .ok_or("No public key available")?;
+    key_manager
+        .get_secret_key(&public_key)
+        .map(Keys::new)
+        .ok_or_else(|| "No key available for npub".to_string())
+}
+
+/// Signs the NIP-47 info event that advertises the active account as a wallet service supporting `methods`.
+/// Returns the event for the frontend to publish to the wallet service's relay.
+#[cfg(feature = "nwc-service")]
+#[tauri::command]
+async fn sign_nwc_info_event(
+    methods: Vec<Method>,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Event, String> {
+    let keys = active_account_keys(&state)?;
+    nwc_service::sign_info_event(&keys, &methods).map_err(|err| err.to_string())
+}
+
+/// Signs the active account's response to an app's NIP-47 request, encrypted to the app. `response` is the
+/// response as NIP-47 JSON.
+#[cfg(feature = "nwc-service")]
+#[tauri::command]
+async fn sign_nwc_response(
+    request: Event,
+    response: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Event, String> {
+    let response =
+        Response::from_json(response).map_err(|err| format!("Invalid response: {}", err))?;
+    let keys = active_account_keys(&state)?;
+    nwc_service::sign_response(&keys, &request, &response).map_err(|err| err.to_string())
+}
+
+/// Binds an account to a key on a PKCS#11 token, so that its events are signed, and its NIP-46 requests answered,
+/// on the token. The account needs no key in the vault. Replaces any binding the account already has. Errors if the
+/// token can't be opened, or its key isn't the account's.
+#[cfg(feature = "pkcs11")]
+#[tauri::command]
+async fn bind_hardware_key(
+    binding: HardwareKeyBinding,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    let signer = binding
+        .open_signer()
+        .map_err(|err| format!("Error opening hardware key: {}", err))?;
+    key_manager_state
+        .bind_hardware_key(binding)
+        .map_err(|_| "Error saving hardware key binding")?;
+    request_approver_state.set_hardware_signer(Arc::new(signer));
+    Ok(())
+}
+
+/// Returns the hex-encoded NIP-04 style ECDH shared secret between the active account and a peer (by npub), for
+/// apps that build their own encrypted channels. **Dangerous:** the secret decrypts every NIP-04 message between the
+/// two keys, so this is gated like exporting a key. Without an enrolled passkey, `passphrase` must be the vault's.
+#[tauri::command]
+async fn derive_shared_secret(
+    peer_pubkey: String,
+    passphrase: Option<String>,
+    assertion: Option<PasskeyAssertion>,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    passkey_gate_state: tauri::State<'_, PasskeyGate>,
+) -> Result<String, String> {
+    let peer_public_key = validation::validate_npub(&peer_pubkey).map_err(|err| err.to_string())?;
+    authorize_gated_operation(
+        &state,
+        &passkey_gate_state,
+        assertion.as_ref(),
+        passphrase.as_deref(),
+    )?;
+
+    let public_key = state
+        .get_public_key()
+        .map_err(|err| err.to_string())?
+        .ok_or("No public key available")?;
+    let secret_key = state
+        .get_secret_key(&public_key)
+        .ok_or("No key available for npub")?;
+    Ok(hex::encode(shared_secret::derive_shared_secret(
+        &secret_key,
+        &peer_public_key,
+    )))
+}
+
+/// Errors unless `action` doesn't need a confirmation phrase, or `typed_phrase` is its phrase.
+fn check_confirmation_phrase(
+    key_manager: &KeystacheKeyManager,
+    action: HighRiskAction,
+    typed_phrase: Option<&str>,
+) -> Result<(), String> {
+    key_manager
+        .get_confirmation_phrases()
+        .map_err(|_| "Error reading confirmation phrases")?
+        .check(action, typed_phrase)
+        .map_err(|err| err.to_string())
+}
+
+/// Returns which high-risk actions need a typed confirmation phrase.
+#[tauri::command]
+async fn get_confirmation_phrases(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<ConfirmationPhrasePolicy, String> {
+    state
+        .get_confirmation_phrases()
+        .map_err(|_| "Error reading confirmation phrases".to_string())
+}
+
+/// Sets which high-risk actions need the user to type a confirmation phrase (e.g. "wipe all data") before they
+/// go ahead, on top of whatever else they need.
+#[tauri::command]
+async fn set_confirmation_phrases(
+    confirmation_phrases: ConfirmationPhrasePolicy,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    key_manager_state
+        .set_confirmation_phrases(&confirmation_phrases)
+        .map_err(|_| "Error saving confirmation phrases")?;
+    request_approver_state.set_confirmation_phrases(confirmation_phrases);
+    Ok(())
+}
+
+/// Encrypts the key for an npub with a password into a NIP-49 `ncryptsec`, for use as a backup. Needs the
+/// confirmation phrase if one is required (see `set_confirmation_phrases`). Without an enrolled passkey,
+/// `passphrase` must be the vault's.
+#[tauri::command]
+async fn export_ncryptsec(
+    npub: String,
+    password: String,
+    passphrase: Option<String>,
+    assertion: Option<PasskeyAssertion>,
+    confirmation_phrase: Option<String>,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    passkey_gate_state: tauri::State<'_, PasskeyGate>,
+) -> Result<String, String> {
+    check_confirmation_phrase(
+        &state,
+        HighRiskAction::RevealSecret,
+        confirmation_phrase.as_deref(),
+    )?;
+    authorize_gated_operation(
+        &state,
+        &passkey_gate_state,
+        assertion.as_ref(),
+        passphrase.as_deref(),
+    )?;
+
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    let secret_key = state
+        .get_secret_key(&public_key)
+        .ok_or("No key available for npub")?;
+    ncryptsec::encrypt_ncryptsec(&secret_key, &password)
+        .map_err(|_| "Error encrypting key".to_string())
+}
+
+#[tauri::command]
+async fn set_payment_dedup_window(
+    seconds: u64,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    let dedup_window = Duration::from_secs(seconds);
+    key_manager_state
+        .set_payment_dedup_window(dedup_window)
+        .map_err(|_| "Error saving payment dedup window")?;
+    request_approver_state.set_payment_dedup_window(dedup_window);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_approval_timeouts(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<ApprovalTimeouts, String> {
+    state
+        .get_approval_timeouts()
+        .map_err(|_| "Error reading approval timeouts".to_string())
+}
+
+/// Sets how long Keystache waits for the user to respond to each kind of request before rejecting it.
+#[tauri::command]
+async fn set_approval_timeouts(
+    approval_timeouts: ApprovalTimeouts,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    key_manager_state
+        .set_approval_timeouts(&approval_timeouts)
+        .map_err(|err| format!("Error saving approval timeouts: {}", err))?;
+    request_approver_state.set_approval_timeouts(approval_timeouts);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_quiet_hours(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Option<QuietHours>, String> {
+    state
+        .get_quiet_hours()
+        .map_err(|_| "Error reading quiet hours".to_string())
+}
+
+/// Sets the daily window, in local time, during which requests to sign events or pay invoices are
+/// rejected or need Keystache to be unlocked. Pass `None` to turn quiet hours off.
+#[tauri::command]
+async fn set_quiet_hours(
+    quiet_hours: Option<QuietHours>,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    key_manager_state
+        .set_quiet_hours(quiet_hours.as_ref())
+        .map_err(|err| format!("Error saving quiet hours: {}", err))?;
+    request_approver_state.set_quiet_hours(quiet_hours);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_max_single_payment(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Option<MaxSinglePayment>, String> {
+    state
+        .get_max_single_payment()
+        .map_err(|_| "Error reading maximum single payment".to_string())
+}
+
+/// Caps the amount of any single payment, whichever app requests it. Invoices over the cap are rejected before
+/// the user is asked, or need Keystache to be unlocked, depending on the cap's action. Pass `None` to remove the cap.
+#[tauri::command]
+async fn set_max_single_payment(
+    max_single_payment: Option<MaxSinglePayment>,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    key_manager_state
+        .set_max_single_payment(max_single_payment.as_ref())
+        .map_err(|err| format!("Error saving maximum single payment: {}", err))?;
+    request_approver_state.set_max_single_payment(max_single_payment);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_max_event_age(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Option<u64>, String> {
+    state
+        .get_max_event_age()
+        .map(|max_event_age| max_event_age.map(|max_event_age| max_event_age.as_secs()))
+        .map_err(|_| "Error reading max event age".to_string())
+}
+
+/// Sets how old, in seconds, an event can be and still be signed. Older events are rejected without
+/// asking the user. Pass `None` to accept events of any age.
+#[tauri::command]
+async fn set_max_event_age(
+    seconds: Option<u64>,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    if seconds == Some(0) {
+        return Err("Max event age must be greater than zero".to_string());
+    }
+    let max_event_age = seconds.map(Duration::from_secs);
+    key_manager_state
+        .set_max_event_age(max_event_age)
+        .map_err(|_| "Error saving max event age")?;
+    request_approver_state.set_max_event_age(max_event_age);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_log_retention_days(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<u64, String> {
+    state
+        .get_log_retention_days()
+        .map_err(|_| "Error reading log retention".to_string())
+}
+
+/// Sets how many days payment and connection log entries are kept for. 0 keeps them forever.
+/// Entries older than the new retention are pruned straight away, rather than on the next periodic prune.
+#[tauri::command]
+async fn set_log_retention_days(
+    days: u64,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    connection_log_state: tauri::State<'_, Arc<ConnectionLog>>,
+) -> Result<(), String> {
+    key_manager_state
+        .set_log_retention_days(days)
+        .map_err(|_| "Error saving log retention")?;
+    let now = chrono::Utc::now();
+    log_retention::prune_log(key_manager_state.as_ref(), days, now)
+        .map_err(|_| "Error pruning payment log")?;
+    log_retention::prune_log(connection_log_state.as_ref(), days, now)
+        .map_err(|_| "Error pruning connection log")?;
+    Ok(())
+}
+
+/// Returns how the user's attention is drawn to requests that arrive while the window isn't focused.
+#[tauri::command]
+async fn get_request_attention(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<RequestAttention, String> {
+    state
+        .get_request_attention()
+        .map_err(|_| "Error reading request attention settings".to_string())
+}
+
+/// Sets whether requests that arrive while the window isn't focused focus it, and whether they show a
+/// notification naming the app and what it's asking for.
+#[tauri::command]
+async fn set_request_attention(
+    request_attention: RequestAttention,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    key_manager_state
+        .set_request_attention(request_attention)
+        .map_err(|_| "Error saving request attention settings")?;
+    request_approver_state.set_request_attention(request_attention);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_content_warning_policy(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<ContentWarningPolicy, String> {
+    state
+        .get_content_warning_policy()
+        .map_err(|_| "Error reading content warning policy".to_string())
+}
+
+/// Sets what to do about events that are signed without a NIP-36 `content-warning` tag: nothing, warn the user,
+/// or warn and suggest a copy of the event with the tag added.
+#[tauri::command]
+async fn set_content_warning_policy(
+    content_warning_policy: ContentWarningPolicy,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    key_manager_state
+        .set_content_warning_policy(content_warning_policy)
+        .map_err(|_| "Error saving content warning policy")?;
+    request_approver_state.set_content_warning_policy(content_warning_policy);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_offline_mode(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<bool, String> {
+    state
+        .is_offline_mode()
+        .map_err(|_| "Error reading offline mode".to_string())
+}
+
+/// Enables or disables offline mode. While enabled, anything that would connect
+/// to the network (e.g. publishing to relays) fails rather than connecting.
+#[tauri::command]
+async fn set_offline_mode(
+    enabled: bool,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<(), String> {
+    state
+        .set_offline_mode(enabled)
+        .map_err(|_| "Error setting offline mode")?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_app_key_allowlist(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Vec<String>, String> {
+    state
+        .get_app_key_allowlist()
+        .map(|app_key_allowlist| app_key_allowlist.patterns().to_vec())
+        .map_err(|_| "Error reading app key allowlist".to_string())
+}
+
+/// Sets which app keys the NIP-46 servers accept requests from. Each pattern is the npub an app signs its
+/// requests with, or `*`. `["*"]` allows every app, and an empty list blocks every app. Only apps that sign
+/// every request with the same key can be listed.
+#[tauri::command]
+async fn set_app_key_allowlist(
+    patterns: Vec<String>,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    let app_key_allowlist = AppKeyAllowlist::new(patterns).map_err(|err| err.to_string())?;
+    key_manager_state
+        .set_app_key_allowlist(&app_key_allowlist)
+        .map_err(|_| "Error setting app key allowlist")?;
+    request_approver_state.set_app_key_allowlist(app_key_allowlist);
+    Ok(())
+}
+
+/// Signs an event with an explicitly chosen `created_at` rather than the one the event was built with.
+/// The timestamp is shown to the user as part of the approval request.
+#[tauri::command]
+async fn sign_event_with_timestamp(
+    event: UnsignedEvent,
+    created_at: i64,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<Event, String> {
+    let created_at = sign_event_request::validate_created_at(created_at, Timestamp::now())
+        .map_err(|err| format!("Invalid created_at: {}", err))?;
+
+    let mut event = event;
+    event.id = None;
+    event.created_at = created_at;
+
+    request_approver_state
+        .sign_event_with_approval(event, key_manager_state.as_ref())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Signs an event given as JSON and returns the signed event as JSON, once the user approves. For air-gapped
+/// workflows: this never connects to relays, even if relays are configured, so it works in offline mode too.
+#[tauri::command]
+async fn sign_event_offline(
+    event_json: String,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<String, String> {
+    request_approver_state
+        .sign_event_json_with_approval(&event_json, key_manager_state.as_ref())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Computes the ID that an unsigned event will have once it's signed, without signing it or asking for approval.
+#[tauri::command]
+async fn compute_event_id(event: UnsignedEvent) -> Result<String, ()> {
+    Ok(request_approver::compute_event_id(&event).to_hex())
+}
+
+/// Signs an event as the stored account with `npub`, once the user approves, without changing the active account.
+/// The event's pubkey is replaced with the account's. Fails if the account isn't stored.
+#[tauri::command]
+async fn sign_event_as(
+    npub: String,
+    event: UnsignedEvent,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<Event, String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+
+    request_approver_state
+        .sign_event_as_with_approval(event, public_key, key_manager_state.as_ref())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Signs an event with NIP-13 proof of work, for relays that require it. Once the user approves, a nonce is
+/// mined so that the event's ID has at least `difficulty` leading zero bits, reporting progress with
+/// `pow_progress` events. Fails if mining is cancelled with `cancel_pow` or takes too long.
+#[tauri::command]
+async fn sign_event_with_pow(
+    event: UnsignedEvent,
+    difficulty: u8,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<Event, String> {
+    if difficulty == 0 {
+        return Err("Difficulty must be greater than zero".to_string());
+    }
+
+    let mut event = event;
+    event.id = None;
+
+    request_approver_state
+        .sign_event_with_pow_and_approval(event, difficulty, key_manager_state.as_ref())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Stops mining proof of work for a sign event request. `event_id` is the ID of the request's event.
+#[tauri::command]
+async fn cancel_pow(
+    event_id: String,
+    state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), ()> {
+    state.cancel_pow(&event_id).await;
+    Ok(())
+}
+
+/// Decrypts a NIP-04 or NIP-44 direct message addressed to the active account, once the user approves.
+#[tauri::command]
+async fn decrypt_dm(
+    event: Event,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<String, String> {
+    let public_key = key_manager_state
+        .get_public_key()
+        .map_err(|_| "Error getting public key")?
+        .ok_or("No public key available")?;
+    request_approver_state
+        .decrypt_dm_with_approval(&event, &public_key, key_manager_state.as_ref())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Responds to a request to decrypt a direct message.
+#[tauri::command]
+async fn respond_to_decrypt_dm_request(
+    event_id: String,
+    approved: bool,
+    state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), ()> {
+    state
+        .respond_to_decrypt_dm_request(&event_id, approved)
+        .await;
+    Ok(())
+}
+
+/// Signs a batch of events, which may be for several accounts, after a single approval from the user.
+/// The whole batch is rejected if Keystache doesn't have the key for any of the events.
+#[tauri::command]
+async fn sign_events(
+    events: Vec<UnsignedEvent>,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<Vec<Event>, String> {
+    request_approver_state
+        .sign_events_with_approval(events, key_manager_state.as_ref())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Publishes a signed event to the given relays, reporting how each relay responded.
+/// Relays that require NIP-42 authentication are authenticated with the event author's key, if Keystache has it.
+/// NIP-70 protected events are only published to relays in the author's relay list.
+#[tauri::command]
+async fn publish_event(
+    event: Event,
+    relay_urls: Vec<String>,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    auth_event_cache_state: tauri::State<'_, AuthEventCache>,
+) -> Result<Vec<RelayPublishResult>, String> {
+    for relay_url in &relay_urls {
+        validation::validate_relay_url(relay_url).map_err(|err| err.to_string())?;
+    }
+    state.ensure_online().map_err(|err| err.to_string())?;
+    event
+        .verify()
+        .map_err(|err| format!("Invalid event: {}", err))?;
+    let auth_keys = state.get_secret_key(&event.author()).map(Keys::new);
+    let auth = auth_keys.as_ref().map(|keys| RelayAuth {
+        keys,
+        auth_event_cache: &auth_event_cache_state,
+    });
+
+    if sign_event_request::is_protected(event.tags()) {
+        // Protected events only go to the author's own relays. If the author isn't one of
+        // Keystache's accounts, it has no relays, so nothing is published.
+        let own_relay_urls: Vec<String> = state
+            .list_relays(&event.author())
+            .map(|relays| relays.into_iter().map(|(url, _)| url).collect())
+            .unwrap_or_default();
+        return Ok(relays::publish_protected_event(
+            &relay_urls,
+            &own_relay_urls,
+            &event,
+            auth,
+            relays::DEFAULT_RELAY_TIMEOUT,
+        )
+        .await);
+    }
+
+    Ok(relays::publish_event(&relay_urls, &event, auth, relays::DEFAULT_RELAY_TIMEOUT).await)
+}
+
+/// Lists an account's relays. If `app_id` is set, the relays are for that app, and private relays are left out
+/// unless the app is trusted (see `set_relay_visibility_policy`).
+#[tauri::command]
+async fn get_relays(
+    npub: String,
+    app_id: Option<String>,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Vec<(String, RelayPolicy)>, String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    if let Some(app_id) = &app_id {
+        validation::validate_npub(app_id).map_err(|err| err.to_string())?;
+    }
+    let relays = state
+        .list_relays(&public_key)
+        .map_err(|_| "Error listing relays")?;
+    let policy = state
+        .get_relay_visibility_policy()
+        .map_err(|_| "Error reading relay visibility policy")?;
+    Ok(policy.visible_relays(relays, app_id.as_deref().map(str::trim)))
+}
+
+#[tauri::command]
+async fn get_relay_visibility_policy(
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<RelayVisibilityPolicy, String> {
+    state
+        .get_relay_visibility_policy()
+        .map_err(|_| "Error reading relay visibility policy".to_string())
+}
+
+/// Sets which relays are private, and which apps are trusted to see them.
+#[tauri::command]
+async fn set_relay_visibility_policy(
+    policy: RelayVisibilityPolicy,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<(), String> {
+    policy.validate().map_err(|err| err.to_string())?;
+    // Stored the same way as account relays, so that they match.
+    let policy = RelayVisibilityPolicy {
+        private_relays: policy
+            .private_relays
+            .iter()
+            .map(|url| validation::normalize_relay_url(url))
+            .collect::<Result<_, _>>()
+            .map_err(|err| err.to_string())?,
+        ..policy
+    };
+    state
+        .set_relay_visibility_policy(&policy)
+        .map_err(|_| "Error setting relay visibility policy")?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_relay_policy(
+    npub: String,
+    url: String,
+    read: bool,
+    write: bool,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    let url = validation::normalize_relay_url(&url).map_err(|err| err.to_string())?;
+    key_manager_state
+        .set_relay_policy(&public_key, &url, RelayPolicy { read, write })
+        .map_err(|_| "Error setting relay policy")?;
+    refresh_relay_hints(&key_manager_state, &request_approver_state)
+}
+
+/// Reports an account's relay entries that point to the same relay, e.g. ones added before relay URLs were
+/// normalized, so that the user can remove the extras.
+#[tauri::command]
+async fn find_duplicate_relays(
+    npub: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Vec<DuplicateRelays>, String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    let relay_urls: Vec<String> = state
+        .list_relays(&public_key)
+        .map_err(|_| "Error listing relays")?
+        .into_iter()
+        .map(|(url, _)| url)
+        .collect();
+    Ok(relays::find_duplicate_relays(&relay_urls))
+}
+
+#[tauri::command]
+async fn remove_relay(
+    npub: String,
+    url: String,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    key_manager_state
+        .remove_relay(&public_key, &url)
+        .map_err(|_| "Error removing relay")?;
+    refresh_relay_hints(&key_manager_state, &request_approver_state)
+}
+
+#[tauri::command]
+async fn get_relay_hints_enabled(
+    npub: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<bool, String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    state
+        .is_relay_hints_enabled(&public_key)
+        .map_err(|_| "Error reading relay hints setting".to_string())
+}
+
+/// Turns adding relay hints on or off for an account. While on, the account's first write relay is added as the
+/// relay hint of any `e` or `p` tag without one in the events it signs. The approval prompt shows the added hint.
+#[tauri::command]
+async fn set_relay_hints_enabled(
+    npub: String,
+    enabled: bool,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    key_manager_state
+        .set_relay_hints_enabled(&public_key, enabled)
+        .map_err(|_| "Error saving relay hints setting")?;
+    refresh_relay_hints(&key_manager_state, &request_approver_state)
+}
+
+/// Returns how many seconds after signing the account's events expire, or `None` if no expiration is added.
+#[tauri::command]
+async fn get_default_expiration(
+    npub: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Option<u64>, String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    state
+        .get_default_expiration(&public_key)
+        .map(|default_expiration| default_expiration.map(|duration| duration.as_secs()))
+        .map_err(|_| "Error reading default expiration".to_string())
+}
+
+/// Sets how many seconds after signing the account's events expire. While set, a NIP-40 `expiration` tag is
+/// added to the events the account signs that don't already have one, and the approval prompt shows it. Pass
+/// `None` to stop adding expirations.
+#[tauri::command]
+async fn set_default_expiration(
+    npub: String,
+    seconds: Option<u64>,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    if seconds == Some(0) {
+        return Err("Default expiration must be greater than zero".to_string());
+    }
+    key_manager_state
+        .set_default_expiration(&public_key, seconds.map(Duration::from_secs))
+        .map_err(|_| "Error saving default expiration")?;
+    request_approver_state.set_default_expirations(
+        key_manager_state
+            .get_default_expirations()
+            .map_err(|_| "Error reading default expirations")?,
+    );
+    Ok(())
+}
+
+/// Gives the request approver the current relay hints, after anything that changes them.
+fn refresh_relay_hints(
+    key_manager: &KeystacheKeyManager,
+    request_approver: &KeystacheRequestApprover,
+) -> Result<(), String> {
+    request_approver.set_relay_hints(
+        key_manager
+            .get_relay_hints()
+            .map_err(|_| "Error reading relay hints")?,
+    );
+    Ok(())
+}
+
+/// Fetches the latest profile metadata for an account from the first of its read relays to have one.
+/// Returns `None` if none of the relays have a profile for the account.
+#[tauri::command]
+async fn get_profile_metadata(
+    npub: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Option<ProfileFields>, String> {
+    fetch_profile_fields(&npub, &state).await
+}
+
+/// Re-fetches an account's profile (kind 0) and relay list (kind 10002) from its read relays, updating the stored
+/// copies if the relays have newer ones. Returns the stored copies and which of them changed. In offline mode,
+/// nothing is fetched and the stored copies are returned unchanged.
+#[tauri::command]
+async fn refresh_account_data(
+    npub: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<AccountDataRefresh, String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    state
+        .refresh_account_data(&public_key)
+        .await
+        .map_err(|err| format!("Error refreshing account data: {}", err))
+}
+
+/// Returns the lightning address in an account's profile metadata, and whether its LNURL-pay endpoint works,
+/// so that the user can share it to be zapped. Returns `None` if the account's profile has no lightning address.
+#[tauri::command]
+async fn get_lightning_address(
+    npub: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Option<LightningAddress>, String> {
+    let Some(address) = fetch_profile_fields(&npub, &state)
+        .await?
+        .and_then(|fields| fields.lud16)
+    else {
+        return Ok(None);
+    };
+    Ok(Some(
+        lightning_address::resolve_lightning_address(&address, &HttpJsonFetcher).await,
+    ))
+}
+
+/// Looks up the account that a NIP-05 identifier (`name@domain`, or just `domain`) points to, e.g. to send a DM
+/// or zap to someone by their NIP-05. Fails if the domain has no pubkey for the name.
+#[tauri::command]
+async fn resolve_nip05(
+    identifier: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Nip05Profile, String> {
+    state.ensure_online().map_err(|err| err.to_string())?;
+    nip05::resolve_nip05(&identifier, &HttpJsonFetcher)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Checks that a NIP-05 identifier points to the account, and records it as the account's verified identifier if
+/// it does. If it doesn't, any previously verified identifier is forgotten. Returns whether it points to the account.
+#[tauri::command]
+async fn verify_account_nip05(
+    npub: String,
+    identifier: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<bool, String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    state.ensure_online().map_err(|err| err.to_string())?;
+    let profile = nip05::resolve_nip05(&identifier, &HttpJsonFetcher)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let verified = profile.npub == public_key.to_bech32().map_err(|err| err.to_string())?;
+    state
+        .set_verified_nip05(&public_key, verified.then_some(identifier.trim()))
+        .map_err(|_| "Error saving verified NIP-05".to_string())?;
+    Ok(verified)
+}
+
+/// Imports an account's NIP-02 follow list from its read relays, replacing its stored contacts.
+/// If no follow list is found, the stored contacts are left as they are and an empty list is returned.
+#[tauri::command]
+async fn import_follow_list(
+    npub: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Vec<Contact>, String> {
+    state.ensure_online().map_err(|err| err.to_string())?;
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    let relay_urls = state
+        .list_read_relay_urls(&public_key)
+        .map_err(|_| "Error listing relays")?;
+
+    let filter = Filter::new()
+        .author(public_key)
+        .kind(Kind::ContactList)
+        .limit(1);
+    let events = relays::query_relays_first_ok(&relay_urls, filter, relays::DEFAULT_RELAY_TIMEOUT)
+        .await
+        .map_err(|_| "Error fetching follow list from relays")?;
+
+    let Some(event) = events.into_iter().max_by_key(|event| event.created_at) else {
+        return Ok(Vec::new());
+    };
+    let contacts = contacts::parse_follow_list(&event).map_err(|err| err.to_string())?;
+    state
+        .replace_contacts(&public_key, &contacts)
+        .map_err(|_| "Error saving contacts")?;
+    Ok(contacts)
+}
+
+/// Builds and signs a new kind-3 follow list for an account from `contacts`, saves them as its stored contacts,
+/// and publishes the follow list to the account's write relays if `publish` is set.
+#[tauri::command]
+async fn update_follow_list(
+    npub: String,
+    contacts: Vec<Contact>,
+    publish: bool,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+    auth_event_cache_state: tauri::State<'_, AuthEventCache>,
+) -> Result<UpdateFollowListResponse, String> {
+    if publish {
+        // Fail before asking the user to sign anything that can't be published.
+        key_manager_state
+            .ensure_online()
+            .map_err(|err| err.to_string())?;
+    }
+
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    let unsigned_event =
+        contacts::build_follow_list_event(&contacts, public_key).map_err(|err| err.to_string())?;
+
+    let event = request_approver_state
+        .sign_event_with_approval(unsigned_event, key_manager_state.as_ref())
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let saved_contacts = contacts::parse_follow_list(&event).map_err(|err| err.to_string())?;
+    key_manager_state
+        .replace_contacts(&public_key, &saved_contacts)
+        .map_err(|_| "Error saving contacts")?;
+
+    let publish_results = if publish {
+        let relay_urls = key_manager_state
+            .list_write_relay_urls(&public_key)
+            .map_err(|_| "Error listing relays")?;
+        let auth_keys = key_manager_state.get_secret_key(&public_key).map(Keys::new);
+        relays::publish_event(
+            &relay_urls,
+            &event,
+            auth_keys.as_ref().map(|keys| RelayAuth {
+                keys,
+                auth_event_cache: &auth_event_cache_state,
+            }),
+            relays::DEFAULT_RELAY_TIMEOUT,
+        )
+        .await
+    } else {
+        Vec::new()
+    };
+
+    Ok(UpdateFollowListResponse {
+        event,
+        publish_results,
+    })
+}
+
+/// Lists an account's stored contacts, as last imported with `import_follow_list` or saved with `update_follow_list`.
+#[tauri::command]
+async fn list_contacts(
+    npub: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<Vec<Contact>, String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    state
+        .list_contacts(&public_key)
+        .map_err(|_| "Error listing contacts".to_string())
+}
+
+async fn fetch_profile_fields(
+    npub: &str,
+    key_manager: &KeystacheKeyManager,
+) -> Result<Option<ProfileFields>, String> {
+    key_manager.ensure_online().map_err(|err| err.to_string())?;
+    let public_key = validation::validate_npub(npub).map_err(|err| err.to_string())?;
+    let relay_urls = key_manager
+        .list_read_relay_urls(&public_key)
+        .map_err(|_| "Error listing relays")?;
+
+    let filter = Filter::new()
+        .author(public_key)
+        .kind(Kind::Metadata)
+        .limit(1);
+    let events = relays::query_relays_first_ok(&relay_urls, filter, relays::DEFAULT_RELAY_TIMEOUT)
+        .await
+        .map_err(|_| "Error fetching profile metadata from relays")?;
+
+    match events.into_iter().max_by_key(|event| event.created_at) {
+        Some(event) => {
+            let metadata = Metadata::from_json(&event.content)
+                .map_err(|_| "Error parsing profile metadata")?;
+            Ok(Some(ProfileFields::from_metadata(metadata)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Builds and signs a new kind-0 profile metadata event for an account,
+/// and publishes it to the account's write relays if `publish` is set.
+#[tauri::command]
+async fn update_profile_metadata(
+    npub: String,
+    fields: ProfileFields,
+    publish: bool,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+    auth_event_cache_state: tauri::State<'_, AuthEventCache>,
+) -> Result<UpdateProfileMetadataResponse, String> {
+    if publish {
+        // Fail before asking the user to sign anything that can't be published.
+        key_manager_state
+            .ensure_online()
+            .map_err(|err| err.to_string())?;
+    }
+
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    let unsigned_event = profile::build_metadata_event(&fields, public_key)
+        .map_err(|err| format!("Invalid profile metadata: {}", err))?;
+
+    let event = request_approver_state
+        .sign_event_with_approval(unsigned_event, key_manager_state.as_ref())
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let publish_results = if publish {
+        let relay_urls = key_manager_state
+            .list_write_relay_urls(&public_key)
+            .map_err(|_| "Error listing relays")?;
+        let auth_keys = key_manager_state.get_secret_key(&public_key).map(Keys::new);
+        relays::publish_event(
+            &relay_urls,
+            &event,
+            auth_keys.as_ref().map(|keys| RelayAuth {
+                keys,
+                auth_event_cache: &auth_event_cache_state,
+            }),
+            relays::DEFAULT_RELAY_TIMEOUT,
+        )
+        .await
+    } else {
+        Vec::new()
+    };
+
+    Ok(UpdateProfileMetadataResponse {
+        event,
+        publish_results,
+    })
+}
+
+/// Checks how many of the account's relays can currently be connected to (e.g. to show whether a DM to it is likely
+/// to be delivered). Nothing is signed or published.
+#[tauri::command]
+async fn check_relay_reachability(
+    npub: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<RelayReachabilityReport, String> {
+    state.ensure_online().map_err(|err| err.to_string())?;
+
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    let relay_urls: Vec<String> = state
+        .list_relays(&public_key)
+        .map_err(|_| "Error listing relays")?
+        .into_iter()
+        .map(|(url, _)| url)
+        .collect();
+
+    Ok(relays::check_relay_reachability(&relay_urls, relays::DEFAULT_RELAY_TIMEOUT).await)
+}
+
+/// Parses a relay URL as typed by the user, returning it as it would be stored (e.g. `relay.example.com` becomes
+/// `wss://relay.example.com`), or an error explaining what's wrong with it.
+#[tauri::command]
+async fn normalize_relay_url(input: String) -> Result<String, String> {
+    validation::normalize_relay_url(&input).map_err(|err| err.to_string())
+}
+
+/// Checks that a relay can be connected to and answers a subscription, e.g. before it's added to an account.
+/// Nothing is stored or published.
+#[tauri::command]
+async fn test_relay(
+    url: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<RelayTestResult, String> {
+    validation::validate_relay_url(&url).map_err(|err| err.to_string())?;
+    state.ensure_online().map_err(|err| err.to_string())?;
+
+    Ok(relays::test_relay(&url, relays::DEFAULT_RELAY_TIMEOUT).await)
+}
+
+/// Returns the strings the frontend renders as QR codes so that other clients (e.g. mobile apps) can connect to an account.
+#[tauri::command]
+async fn get_connection_qr(npub: String) -> Result<ConnectionQrPayload, String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    ConnectionQrPayload::new(public_key)
+        .map_err(|_| "Error building connection QR payload".to_string())
+}
+
+/// Decodes any pasted NIP-19 entity (e.g. an `npub` or `nevent`) into its components.
+/// Secret keys are only reported as detected, never decoded.
+#[tauri::command]
+async fn decode_entity(input: String) -> Result<DecodedEntity, String> {
+    entity::decode_entity(&input).map_err(|err| err.to_string())
+}
+
+/// Checks whether a pasted event is authentic: that its signature is valid and, if it was published under a
+/// NIP-26 delegation, that the delegation covers it.
+#[tauri::command]
+async fn verify_event(event_json: String) -> Result<EventVerification, String> {
+    event_verification::verify_event(&event_json).map_err(|err| err.to_string())
+}
+
+/// Decodes a BOLT11 invoice so that it can be shown to the user, without paying it or asking for approval.
+/// Errors if the invoice is malformed or has expired.
+#[tauri::command]
+async fn decode_invoice(invoice: String) -> Result<DecodedInvoice, String> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|err| err.to_string())?;
+    invoice::decode_invoice(&invoice, now).map_err(|err| err.to_string())
+}
+
+/// Checks a NIP-57 zap receipt against the zap request it's for, reporting every discrepancy found.
+#[tauri::command]
+async fn validate_zap_receipt(
+    receipt_event: Event,
+    zap_request: Event,
+) -> Result<ZapReceiptValidation, String> {
+    zap_receipt::validate_zap_receipt(&receipt_event, &zap_request).map_err(|err| err.to_string())
+}
+
+/// Returns the account's NIP-19 `nprofile`, with some of its write relays as hints so that others can find its events.
+#[tauri::command]
+async fn get_nprofile(
+    npub: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<String, String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    let relay_urls = state
+        .list_write_relay_urls(&public_key)
+        .map_err(|_| "Error listing relays")?;
+    nprofile::build_nprofile(public_key, &relay_urls)
+        .map_err(|_| "Error encoding nprofile".to_string())
+}
+
+/// Returns a shareable card with everything needed to follow and verify an account: its npub, an `nprofile` with
+/// relay hints, and its NIP-05 and lightning address. Only public data is included. The NIP-05 and lightning
+/// address come from the account's profile metadata on its relays, so they're left out in offline mode.
+#[tauri::command]
+async fn export_identity_card(
+    npub: String,
+    state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+) -> Result<IdentityCard, String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    let relay_urls = state
+        .list_write_relay_urls(&public_key)
+        .map_err(|_| "Error listing relays")?;
+    let offline = state
+        .is_offline_mode()
+        .map_err(|_| "Error reading offline mode")?;
+    let profile_fields = if offline {
+        None
+    } else {
+        fetch_profile_fields(&npub, &state).await?
+    };
+
+    IdentityCard::new(public_key, &relay_urls, profile_fields)
+        .map_err(|_| "Error building identity card".to_string())
+}
+
+/// Starts an additional NIP-46 server on `uds_address` that only signs for the given account.
+#[tauri::command]
+async fn start_server(
+    npub: String,
+    uds_address: String,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    request_approver_state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+    server_registry_state: tauri::State<'_, ServerRegistry>,
+    connection_log_state: tauri::State<'_, Arc<ConnectionLog>>,
+) -> Result<(), String> {
+    let public_key = validation::validate_npub(&npub).map_err(|err| err.to_string())?;
+    server_registry_state
+        .start_server(
+            &uds_address,
+            public_key,
+            key_manager_state.inner().clone(),
+            Arc::new(LoggingRequestHandler::new(
+                request_approver_state.inner().clone(),
+                connection_log_state.connect(&uds_address),
+            )),
+        )
+        .map_err(|err| format!("Error starting server: {}", err))
+}
+
+#[tauri::command]
+async fn stop_server(
+    uds_address: String,
+    state: tauri::State<'_, ServerRegistry>,
+) -> Result<(), String> {
+    state
+        .stop_server(&uds_address)
+        .map_err(|err| format!("Error stopping server: {}", err))
+}
+
+/// Returns the connection log entries of every NIP-46 server, oldest first.
+/// If `since` (an RFC 3339 timestamp) is given, only entries from then onwards are returned.
+#[tauri::command]
+async fn get_connection_logs(
+    since: Option<String>,
+    state: tauri::State<'_, Arc<ConnectionLog>>,
+) -> Result<Vec<ConnectionLogEntry>, String> {
+    let since = match since {
+        Some(since) => chrono::DateTime::parse_from_rfc3339(&since)
+            .map_err(|_| "Invalid timestamp")?
+            .with_timezone(&chrono::Utc),
+        None => chrono::DateTime::<chrono::Utc>::MIN_UTC,
+    };
+    state
+        .entries_since(since)
+        .map_err(|_| "Error reading connection log".to_string())
+}
+
+/// Exports the payment or connection log as CSV, oldest entry first. `since` and `until` are optional RFC 3339
+/// timestamps bounding which entries are included. If no entries are in range, only the header is returned.
+#[tauri::command]
+async fn export_logs_csv(
+    kind: LogKind,
+    since: Option<String>,
+    until: Option<String>,
+    key_manager_state: tauri::State<'_, Arc<KeystacheKeyManager>>,
+    connection_log_state: tauri::State<'_, Arc<ConnectionLog>>,
+) -> Result<String, String> {
+    let parse_timestamp = |timestamp: &str| {
+        chrono::DateTime::parse_from_rfc3339(timestamp)
+            .map(|timestamp| timestamp.with_timezone(&chrono::Utc))
+            .map_err(|_| "Invalid timestamp".to_string())
+    };
+    let since = match since {
+        Some(since) => parse_timestamp(&since)?,
+        None => chrono::DateTime::<chrono::Utc>::MIN_UTC,
+    };
+    let until = match until {
+        Some(until) => parse_timestamp(&until)?,
+        None => chrono::DateTime::<chrono::Utc>::MAX_UTC,
+    };
+
+    match kind {
+        LogKind::Payments => {
+            // TODO: Hardcoding the limit here isn't very robust.
+            let entries = key_manager_state
+                .get_payment_history(10_000, 0, &PaymentHistoryFilter::default())
+                .map_err(|_| "Error reading payment history")?;
+            Ok(log_export::payment_log_csv(&entries, since, until))
+        }
+        LogKind::Connections => {
+            let entries = connection_log_state
+                .entries_since(since)
+                .map_err(|_| "Error reading connection log")?;
+            Ok(log_export::connection_log_csv(&entries, since, until))
+        }
+    }
+}
+
+/// Lists the servers started with `start_server`, and the account each one is bound to.
+#[tauri::command]
+async fn list_servers(state: tauri::State<'_, ServerRegistry>) -> Result<Vec<ServerInfo>, String> {
+    Ok(state.list_servers())
+}
+
+/// Temporarily stops the NIP-70 server and the servers started with `start_server` from handling requests: new
+/// requests are rejected without asking the user, while requests already waiting for the user can still be answered.
+/// The servers keep running, so `resume_server` takes effect straight away.
+#[tauri::command]
+async fn pause_server(
+    state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    state.pause_servers();
+    Ok(())
+}
+
+/// Lets the servers handle requests again after `pause_server`.
+#[tauri::command]
+async fn resume_server(
+    state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<(), String> {
+    state.resume_servers();
+    Ok(())
+}
+
+/// Returns whether the servers are paused (see `pause_server`).
+#[tauri::command]
+async fn is_server_paused(
+    state: tauri::State<'_, Arc<KeystacheRequestApprover>>,
+) -> Result<bool, String> {
+    Ok(state.are_servers_paused())
+}
+
+/// Runs the NIP-70 server until it stops accepting connections.
+/// Only returns if the server fails to start or dies, so that it can be restarted.
+async fn run_nip70_server(
+    key_manager: Arc<KeystacheKeyManager>,
+    request_approver: Arc<KeystacheRequestApprover>,
+    connection_log: Arc<ConnectionLog>,
+) -> anyhow::Result<()> {
+    let request_handler = Arc::new(LoggingRequestHandler::new(
+        request_approver,
+        connection_log.connect(NIP_70_UDS_ADDRESS),
+    ));
+
+    let mut server = Nip46Server::start(NIP_70_UDS_ADDRESS, key_manager, request_handler.clone())?;
+
+    loop {
+        let err = tokio::select! {
+            err = server.stopped() => err,
+            () = tokio::time::sleep(NIP_70_HEALTH_CHECK_INTERVAL) => {
+                match tokio::net::UnixStream::connect(NIP_70_UDS_ADDRESS).await {
+                    Ok(_) => continue,
+                    Err(err) => anyhow::anyhow!("server stopped accepting connections: {}", err),
+                }
+            }
+        };
+        request_handler.logger().error(err.to_string());
+        return Err(err);
+    }
+}
+
+/// Passes the settings stored in the vault to the request approver. Settings that can't be read, e.g. because the
+/// vault is locked, are left at their defaults.
+fn load_request_approver_settings(
+    request_approver: &KeystacheRequestApprover,
+    key_manager: &KeystacheKeyManager,
+) {
+    request_approver.set_approval_timeouts(key_manager.get_approval_timeouts().unwrap_or_default());
+    request_approver.set_scam_list(key_manager.get_scam_list().unwrap_or_default());
+    request_approver.set_app_names(key_manager.get_app_names().unwrap_or_default());
+    request_approver.restore_session_grants(&key_manager.get_session_grants().unwrap_or_default());
+    request_approver.set_quiet_hours(key_manager.get_quiet_hours().unwrap_or_default());
+    request_approver
+        .set_max_single_payment(key_manager.get_max_single_payment().unwrap_or_default());
+    request_approver.set_relay_hints(key_manager.get_relay_hints().unwrap_or_default());
+    request_approver
+        .set_default_expirations(key_manager.get_default_expirations().unwrap_or_default());
+    request_approver.set_max_event_age(key_manager.get_max_event_age().unwrap_or_default());
+    request_approver
+        .set_content_warning_policy(key_manager.get_content_warning_policy().unwrap_or_default());
+    request_approver.set_request_attention(key_manager.get_request_attention().unwrap_or_default());
+    request_approver
+        .set_confirmation_phrases(key_manager.get_confirmation_phrases().unwrap_or_default());
+    request_approver.set_app_key_allowlist(key_manager.get_app_key_allowlist().unwrap_or_default());
+    #[cfg(feature = "pkcs11")]
+    for binding in key_manager.list_hardware_key_bindings().unwrap_or_default() {
+        match binding.open_signer() {
+            Ok(signer) => request_approver.set_hardware_signer(Arc::new(signer)),
+            Err(err) => error_log::report(format!(
+                "Error opening hardware key for {}: {}",
+                binding.npub, err
+            )),
+        }
+    }
+}
+
+/// Registers the commands, leaving out those of optional features that aren't compiled in. Calling a command
+/// that's left out fails with an error naming the feature it needs.
+fn invoke_handler() -> impl Fn(tauri::Invoke) + Send + Sync + 'static {
+    let core_handler = core_invoke_handler();
+    #[cfg(feature = "webauthn")]
+    let webauthn_handler = webauthn_invoke_handler();
+    #[cfg(feature = "nwc-service")]
+    let nwc_service_handler = nwc_service_invoke_handler();
+    #[cfg(feature = "pkcs11")]
+    let pkcs11_handler = pkcs11_invoke_handler();
+
+    move |invoke| {
+        let command = invoke.message.command().to_string();
+        if let Some(feature) = features::missing_feature(&command) {
+            invoke.resolver.reject(format!(
+                "Command {command} needs Keystache to be built with the {feature} feature"
+            ));
+            return;
+        }
+
+        #[cfg(feature = "webauthn")]
+        if features::WEBAUTHN_COMMANDS.contains(&command.as_str()) {
+            return webauthn_handler(invoke);
+        }
+        #[cfg(feature = "nwc-service")]
+        if features::NWC_SERVICE_COMMANDS.contains(&command.as_str()) {
+            return nwc_service_handler(invoke);
+        }
+        #[cfg(feature = "pkcs11")]
+        if features::PKCS11_COMMANDS.contains(&command.as_str()) {
+            return pkcs11_handler(invoke);
+        }
+        core_handler(invoke)
+    }
+}
+
+/// Registers the commands that every build has.
+fn core_invoke_handler() -> impl Fn(tauri::Invoke) + Send + Sync + 'static {
+    tauri::generate_handler![
+        respond_to_sign_event_request,
+        respond_to_sign_events_request,
+        respond_to_pay_invoice_request,
+        list_pending_requests,
+        estimate_payment_fee,
+        pay_invoice,
+        get_capabilities,
+        get_public_key,
+        get_app_identity_enabled,
+        set_app_identity_enabled,
+        get_identity_fingerprint,
+        get_identicon_seed,
+        set_nsec,
+        create_account,
+        derive_account,
+        bulk_import,
+        import_backup_file,
+        get_setup_state,
+        unlock_vault,
+        encrypt_existing_keys,
+        list_accounts,
+        add_watch_only_account,
+        rotate_account,
+        set_account_label,
+        get_account_metadata,
+        get_account_stats,
+        get_account_completeness,
+        set_backup_acknowledged,
+        get_cache_stats,
+        clear_key_cache,
+        benchmark_signing,
+        wipe_all_data,
+        verify_vault_integrity,
+        check_key_health,
+        get_leaked_keys,
+        set_leaked_keys,
+        repair_npubs,
+        diagnose_database,
+        copy_secret_to_clipboard_with_timeout,
+        list_authorizations,
+        revoke_authorization,
+        list_sign_decisions,
+        forget_sign_decision,
+        grant_session,
+        revoke_session,
+        list_session_grants,
+        set_app_name,
+        get_payment_history,
+        get_app_signing_history,
+        update_scam_list,
+        get_scam_list,
+        import_ncryptsec,
+        derive_shared_secret,
+        export_ncryptsec,
+        get_confirmation_phrases,
+        set_confirmation_phrases,
+        get_unlock_method,
+        set_payment_dedup_window,
+        get_approval_timeouts,
+        set_approval_timeouts,
+        get_quiet_hours,
+        set_quiet_hours,
+        get_max_single_payment,
+        set_max_single_payment,
+        get_max_event_age,
+        set_max_event_age,
+        get_content_warning_policy,
+        set_content_warning_policy,
+        get_request_attention,
+        set_request_attention,
+        get_log_retention_days,
+        set_log_retention_days,
+        get_offline_mode,
+        set_offline_mode,
+        get_app_key_allowlist,
+        set_app_key_allowlist,
+        sign_event_with_timestamp,
+        sign_event_offline,
+        sign_event_as,
+        compute_event_id,
+        sign_event_with_pow,
+        cancel_pow,
+        sign_events,
+        decrypt_dm,
+        respond_to_decrypt_dm_request,
+        publish_event,
+        get_relays,
+        get_relay_visibility_policy,
+        set_relay_visibility_policy,
+        set_relay_policy,
+        find_duplicate_relays,
+        remove_relay,
+        get_relay_hints_enabled,
+        set_relay_hints_enabled,
+        get_default_expiration,
+        set_default_expiration,
+        get_profile_metadata,
+        refresh_account_data,
+        get_lightning_address,
+        resolve_nip05,
+        verify_account_nip05,
+        import_follow_list,
+        update_follow_list,
+        list_contacts,
+        update_profile_metadata,
+        check_relay_reachability,
+        normalize_relay_url,
+        test_relay,
+        start_server,
+        stop_server,
+        list_servers,
+        pause_server,
+        resume_server,
+        is_server_paused,
+        get_connection_logs,
+        export_logs_csv,
+        get_connection_qr,
+        get_nprofile,
+        export_identity_card,
+        decode_entity,
+        verify_event,
+        decode_invoice,
+        validate_zap_receipt
+    ]
+}
+
+/// Registers the commands of the `webauthn` feature, listed in [`features::WEBAUTHN_COMMANDS`].
+#[cfg(feature = "webauthn")]
+fn webauthn_invoke_handler() -> impl Fn(tauri::Invoke) + Send + Sync + 'static {
+    tauri::generate_handler![get_passkey_challenge, register_passkey, remove_passkey]
+}
+
+/// Registers the commands of the `nwc-service` feature, listed in [`features::NWC_SERVICE_COMMANDS`].
+#[cfg(feature = "nwc-service")]
+fn nwc_service_invoke_handler() -> impl Fn(tauri::Invoke) + Send + Sync + 'static {
+    tauri::generate_handler![sign_nwc_info_event, sign_nwc_response]
+}
+
+/// Registers the commands of the `pkcs11` feature, listed in [`features::PKCS11_COMMANDS`].
+#[cfg(feature = "pkcs11")]
+fn pkcs11_invoke_handler() -> impl Fn(tauri::Invoke) + Send + Sync + 'static {
+    tauri::generate_handler![bind_hardware_key]
+}
+
+#[tokio::main]
+async fn main() {
+    tauri::Builder::default()
+        .invoke_handler(invoke_handler())
+        .setup(|app| {
+            let keystache_key_manager = Arc::new(KeystacheKeyManager::new(app.handle()));
+            let payment_dedup_window = keystache_key_manager
+                .get_payment_dedup_window()
+                .unwrap_or(payment_ledger::DEFAULT_DEDUP_WINDOW);
+            let approval_timeouts = keystache_key_manager
+                .get_approval_timeouts()
+                .unwrap_or_default();
+            // TODO: Use the user's payment backend (e.g. NWC or Fedimint) once one can be configured. Until then,
+            // `pay_invoice` refuses every payment without asking the user.
+            let payment_backend: Arc<dyn PaymentBackend> = Arc::new(NoPaymentBackend);
+            let payment_log = Arc::new(BatchedPaymentLog::new(
+                keystache_key_manager.clone(),
+                payment_log::DEFAULT_FLUSH_INTERVAL,
+            ));
+            let keystache_request_approver = Arc::new(KeystacheRequestApprover::new(
+                Arc::new(app.handle()),
+                payment_dedup_window,
+                payment_backend.clone(),
+                payment_log.clone(),
+                keystache_key_manager.clone(),
+                keystache_key_manager.clone(),
+                keystache_key_manager.clone(),
+                keystache_key_manager.clone(),
+                approval_timeouts,
+            ));
+            load_request_approver_settings(&keystache_request_approver, &keystache_key_manager);
+
+            let connection_log = Arc::new(ConnectionLog::new(keystache_key_manager.clone()));
+
+            let pruned_logs: Vec<Arc<dyn PrunableLog>> =
+                vec![keystache_key_manager.clone(), connection_log.clone()];
+            let retention_key_manager = keystache_key_manager.clone();
+            tokio::spawn(log_retention::prune_logs_periodically(
+                pruned_logs,
+                move || {
+                    retention_key_manager
+                        .get_log_retention_days()
+                        .unwrap_or_default()
+                },
+            ));
+
+            let key_manager = keystache_key_manager.clone();
+            let request_approver = keystache_request_approver.clone();
+            let server_connection_log = connection_log.clone();
+            let app_handle = app.handle();
+            tokio::spawn(async move {
+                let restart_app_handle = app_handle.clone();
+                let cause = watchdog::supervise(
+                    move || {
+                        run_nip70_server(
+                            key_manager.clone(),
+                            request_approver.clone(),
+                            server_connection_log.clone(),
+                        )
+                    },
+                    RestartPolicy::default(),
+                    move |restart| {
+                        let _ = restart_app_handle.emit_all("server_restarted", restart);
+                    },
+                )
+                .await;
+
+                // The server won't be relaunched again, so apps can't connect until Keystache is restarted.
+                let _ = app_handle.emit_all("server_stopped", cause);
+            });
+
             app.manage(keystache_key_manager);
             app.manage(keystache_request_approver);
-            app.manage(nip_70_server_or);
+            app.manage(payment_backend);
+            app.manage(payment_log);
+            app.manage(ServerRegistry::new());
+            app.manage(connection_log);
+            app.manage(AuthEventCache::default());
+            #[cfg(feature = "webauthn")]
+            app.manage(PasskeyGate::new(Box::new(Es256AssertionVerifier::new(
+                PASSKEY_RELYING_PARTY_ID,
+            ))));
+            #[cfg(not(feature = "webauthn"))]
+            app.manage(PasskeyGate::new(Box::new(
+                passkey::UnsupportedAssertionVerifier,
+            )));
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app_handle, event| {
+            if let tauri::RunEvent::Exit = event {
+                // Don't lose payments that are still waiting to be written to the payment history.
+                let payment_log = app_handle.state::<Arc<BatchedPaymentLog>>();
+                tokio::task::block_in_place(|| {
+                    tokio::runtime::Handle::current().block_on(payment_log.flush())
+                });
+            }
+        });
 }
@@ -0,0 +1,82 @@
+//! Minimal Nostr relay for tests. Responds to each client message using a handler function.
+
+#![cfg(test)]
+
+use futures::{SinkExt, StreamExt};
+use nostr_sdk::{ClientMessage, JsonUtil, RelayMessage};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+
+type Handler = dyn Fn(ClientMessage) -> Vec<RelayMessage> + Send + Sync;
+
+/// Nostr relay listening on a random local port. Stops when dropped.
+pub struct MockRelay {
+    url: String,
+    server_handle: tokio::task::JoinHandle<()>,
+}
+
+impl MockRelay {
+    /// Starts a relay that responds to every message from a client with the messages returned by `handler`.
+    pub async fn start(
+        handler: impl Fn(ClientMessage) -> Vec<RelayMessage> + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let server_handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    let mut websocket = match tokio_tungstenite::accept_async(stream).await {
+                        Ok(websocket) => websocket,
+                        Err(_) => return,
+                    };
+
+                    while let Some(Ok(message)) = websocket.next().await {
+                        let client_message = match message {
+                            Message::Text(text) => match ClientMessage::from_json(text) {
+                                Ok(client_message) => client_message,
+                                Err(_) => continue,
+                            },
+                            Message::Close(_) => break,
+                            _ => continue,
+                        };
+
+                        for relay_message in handler(client_message) {
+                            if websocket
+                                .send(Message::Text(relay_message.as_json()))
+                                .await
+                                .is_err()
+                            {
+                                return;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        Self { url, server_handle }
+    }
+
+    /// The `ws://` URL that the relay is listening on.
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+}
+
+impl Drop for MockRelay {
+    fn drop(&mut self) {
+        self.server_handle.abort();
+    }
+}
+
+/// Returns a URL that no relay is listening on.
+pub async fn unreachable_relay_url() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    drop(listener);
+    url
+}
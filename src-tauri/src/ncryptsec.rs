@@ -0,0 +1,108 @@
+use nostr_sdk::nips::nip49::{self, EncryptedSecretKey, KeySecurity};
+use nostr_sdk::{FromBech32, SecretKey, ToBech32};
+
+/// scrypt work factor (as a power of 2) used when exporting keys. NIP-49 suggests 16 or higher.
+const EXPORT_LOG_N: u8 = 16;
+
+/// Error from decrypting an `ncryptsec` string.
+#[derive(Debug, PartialEq, Eq)]
+pub enum NcryptsecError {
+    /// The string isn't a valid `ncryptsec`.
+    InvalidNcryptsec,
+
+    /// The password doesn't decrypt the key.
+    WrongPassword,
+}
+
+impl std::fmt::Display for NcryptsecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidNcryptsec => write!(f, "Invalid ncryptsec"),
+            Self::WrongPassword => write!(f, "Wrong password"),
+        }
+    }
+}
+
+impl std::error::Error for NcryptsecError {}
+
+/// Decrypts a NIP-49 `ncryptsec` string with a password.
+pub fn decrypt_ncryptsec(ncryptsec: &str, password: &str) -> Result<SecretKey, NcryptsecError> {
+    let encrypted_secret_key =
+        EncryptedSecretKey::from_bech32(ncryptsec).map_err(|_| NcryptsecError::InvalidNcryptsec)?;
+
+    encrypted_secret_key
+        .to_secret_key(password)
+        .map_err(|err| match err {
+            // Decryption is authenticated, so a wrong password shows up as a failure to decrypt.
+            nip49::Error::ChaCha20Poly1305(_) => NcryptsecError::WrongPassword,
+            _ => NcryptsecError::InvalidNcryptsec,
+        })
+}
+
+/// Encrypts a secret key with a password into a NIP-49 `ncryptsec` string.
+pub fn encrypt_ncryptsec(secret_key: &SecretKey, password: &str) -> anyhow::Result<String> {
+    let encrypted_secret_key = EncryptedSecretKey::new(
+        secret_key,
+        password,
+        EXPORT_LOG_N,
+        // Keystache doesn't track whether a key has ever been handled insecurely.
+        KeySecurity::Unknown,
+    )
+    .map_err(|err| anyhow::anyhow!("Error encrypting key: {:?}", err))?;
+
+    Ok(encrypted_secret_key.to_bech32()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::Keys;
+
+    // Test vector from NIP-49.
+    const NIP_49_NCRYPTSEC: &str = "ncryptsec1qgg9947rlpvqu76pj5ecreduf9jxhselq2nae2kghhvd5g7dgjtcxfqtd67p9m0w57lspw8gsq6yphnm8623nsl8xn9j4jdzz84zm3frztj3z7s35vpzmqf6ksu8r89qk5z2zxfmu5gv8th8wclt0h4p";
+    const NIP_49_PASSWORD: &str = "nostr";
+    const NIP_49_SECRET_KEY_HEX: &str =
+        "3501454135014541350145413501453fefb02227e449e57cf4d3a3ce05378683";
+
+    #[test]
+    fn decrypt_nip_49_test_vector() {
+        let secret_key = decrypt_ncryptsec(NIP_49_NCRYPTSEC, NIP_49_PASSWORD).unwrap();
+        assert_eq!(secret_key.to_secret_hex(), NIP_49_SECRET_KEY_HEX);
+    }
+
+    #[test]
+    fn encrypt_decrypt_round_trip() {
+        let secret_key = Keys::generate().secret_key().unwrap().clone();
+
+        let ncryptsec = encrypt_ncryptsec(&secret_key, "hunter2").unwrap();
+        assert!(ncryptsec.starts_with("ncryptsec1"));
+
+        assert_eq!(
+            decrypt_ncryptsec(&ncryptsec, "hunter2").unwrap(),
+            secret_key
+        );
+    }
+
+    #[test]
+    fn decrypt_with_wrong_password() {
+        // A wrong password should be reported distinctly from a malformed ncryptsec.
+        assert_eq!(
+            decrypt_ncryptsec(NIP_49_NCRYPTSEC, "not the password"),
+            Err(NcryptsecError::WrongPassword)
+        );
+    }
+
+    #[test]
+    fn decrypt_invalid_ncryptsec() {
+        for ncryptsec in [
+            "",
+            "ncryptsec1",
+            "nsec1j4c6269y9w0q2er2xjw8sv2ehyrtfxq3jwgdlxj6qfn8z4gjsq5qfvfk99",
+        ] {
+            assert_eq!(
+                decrypt_ncryptsec(ncryptsec, NIP_49_PASSWORD),
+                Err(NcryptsecError::InvalidNcryptsec)
+            );
+        }
+    }
+}
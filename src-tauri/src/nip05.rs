@@ -0,0 +1,163 @@
+use nostr_sdk::{PublicKey, ToBech32};
+use serde::Serialize;
+
+use crate::http::JsonFetcher;
+
+/// The account that a NIP-05 identifier points to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Nip05Profile {
+    pub npub: String,
+
+    /// Relays that the domain says the account can be found on. Empty if it doesn't say.
+    pub relays: Vec<String>,
+}
+
+/// Splits a NIP-05 identifier into its lowercased local part and domain. A bare domain is treated as
+/// `_@domain`, the domain's root identifier.
+fn parse_identifier(identifier: &str) -> anyhow::Result<(String, String)> {
+    let identifier = identifier.trim().to_lowercase();
+    let (name, domain) = identifier
+        .split_once('@')
+        .unwrap_or(("_", identifier.as_str()));
+
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(anyhow::anyhow!(
+            "Invalid NIP-05 identifier: the name may only contain a-z, 0-9, '-', '_' and '.'"
+        ));
+    }
+    if !domain.contains('.')
+        || domain.starts_with('.')
+        || domain.ends_with('.')
+        || domain.contains(['/', '@', '?', '#'])
+        || domain.chars().any(char::is_whitespace)
+    {
+        return Err(anyhow::anyhow!("Invalid NIP-05 identifier: invalid domain"));
+    }
+    Ok((name.to_string(), domain.to_string()))
+}
+
+/// Looks up the pubkey and relays for a NIP-05 identifier (`name@domain`, or just `domain` for `_@domain`) in
+/// the domain's `/.well-known/nostr.json`.
+pub async fn resolve_nip05(
+    identifier: &str,
+    fetcher: &dyn JsonFetcher,
+) -> anyhow::Result<Nip05Profile> {
+    let (name, domain) = parse_identifier(identifier)?;
+    let url = format!("https://{domain}/.well-known/nostr.json?name={name}");
+    let response = fetcher
+        .get_json(&url)
+        .await
+        .map_err(|err| anyhow::anyhow!("Error fetching {url}: {err}"))?;
+
+    let public_key_hex = response["names"][&name]
+        .as_str()
+        .ok_or(anyhow::anyhow!("{domain} has no pubkey for {name}"))?;
+    let public_key = PublicKey::from_hex(public_key_hex)
+        .map_err(|_| anyhow::anyhow!("{domain} returned an invalid pubkey for {name}"))?;
+    let relays = response["relays"][public_key_hex]
+        .as_array()
+        .map(|relays| {
+            relays
+                .iter()
+                .filter_map(|relay| relay.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(Nip05Profile {
+        npub: public_key.to_bech32()?,
+        relays,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use nostr_sdk::Keys;
+    use serde_json::json;
+
+    /// Serves `example.com`'s `nostr.json`, which only has the names it was built with.
+    struct MockFetcher {
+        names: serde_json::Value,
+        relays: serde_json::Value,
+    }
+
+    #[async_trait]
+    impl JsonFetcher for MockFetcher {
+        async fn get_json(&self, url: &str) -> anyhow::Result<serde_json::Value> {
+            let name = url
+                .strip_prefix("https://example.com/.well-known/nostr.json?name=")
+                .ok_or(anyhow::anyhow!("404 Not Found"))?;
+            // Like many servers, only return the requested name.
+            Ok(json!({
+                "names": self.names.get(name).map(|public_key| json!({ name: public_key })).unwrap_or(json!({})),
+                "relays": self.relays,
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn resolves_names_and_root_name() {
+        let bob = Keys::generate().public_key();
+        let root = Keys::generate().public_key();
+        let fetcher = MockFetcher {
+            names: json!({ "bob": bob.to_hex(), "_": root.to_hex() }),
+            relays: json!({ bob.to_hex(): ["wss://relay.example.com"] }),
+        };
+
+        assert_eq!(
+            resolve_nip05("Bob@Example.com", &fetcher).await.unwrap(),
+            Nip05Profile {
+                npub: bob.to_bech32().unwrap(),
+                relays: vec!["wss://relay.example.com".to_string()],
+            }
+        );
+        for identifier in ["_@example.com", "example.com"] {
+            assert_eq!(
+                resolve_nip05(identifier, &fetcher).await.unwrap(),
+                Nip05Profile {
+                    npub: root.to_bech32().unwrap(),
+                    relays: Vec::new(),
+                }
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_or_invalid_names_are_errors() {
+        let fetcher = MockFetcher {
+            names: json!({ "bob": "not a pubkey" }),
+            relays: json!({}),
+        };
+
+        for (identifier, error) in [
+            ("alice@example.com", "example.com has no pubkey for alice"),
+            (
+                "bob@example.com",
+                "example.com returned an invalid pubkey for bob",
+            ),
+            (
+                "bob@example.org",
+                "Error fetching https://example.org/.well-known/nostr.json?name=bob: 404 Not Found",
+            ),
+            (
+                "bob smith@example.com",
+                "Invalid NIP-05 identifier: the name may only contain a-z, 0-9, '-', '_' and '.'",
+            ),
+            ("bob@localhost", "Invalid NIP-05 identifier: invalid domain"),
+        ] {
+            assert_eq!(
+                resolve_nip05(identifier, &fetcher)
+                    .await
+                    .unwrap_err()
+                    .to_string(),
+                error
+            );
+        }
+    }
+}
@@ -0,0 +1,463 @@
+//! Keystache's NIP-46 server over NIP-55. It speaks the same wire format as nip_55's `Nip46OverNip55Server`, but
+//! tells its handler which app sent each request and leaves signing to the handler, neither of which nip_55's
+//! server does.
+
+use crate::signer::Signer;
+use async_trait::async_trait;
+use nip_55::json_rpc::{
+    JsonRpcError, JsonRpcErrorCode, JsonRpcRequest, JsonRpcResponse, JsonRpcResponseData,
+};
+use nip_55::KeyManager;
+use nostr_sdk::nips::nip46;
+use nostr_sdk::{Event, EventBuilder, JsonUtil, Keys, PublicKey, Tag};
+use serde_json::Value;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+
+/// Most bytes a request can be. Connections that send more are closed without a response.
+const MAX_REQUEST_BYTES: u64 = 1024 * 1024;
+
+/// How long a client has to send its whole request. Connections that take longer are closed without a response.
+const REQUEST_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait before accepting connections again after failing to accept one, e.g. because Keystache has
+/// run out of file descriptors.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// How many times in a row accepting a connection can fail before the server stops, so that whatever supervises it
+/// can restart it.
+const MAX_CONSECUTIVE_ACCEPT_ERRORS: u32 = 5;
+
+/// Handles the NIP-46 requests received by a [`Nip46Server`].
+#[async_trait]
+pub trait Nip46RequestHandler: Send + Sync {
+    /// Whether to handle requests from the app with `app_public_key` at all. Connections from apps that aren't
+    /// allowed are closed before their request is decrypted, without a response.
+    fn allows_app(&self, _app_public_key: &PublicKey) -> bool {
+        true
+    }
+
+    /// Returns what answers requests to the account with `user_public_key`: it decrypts each request, and encrypts
+    /// and signs the response. By default this is the account's key from `key_manager`, the server's. Connections
+    /// to accounts that have no signer are closed without a response.
+    fn signer_for(
+        &self,
+        user_public_key: &PublicKey,
+        key_manager: &dyn KeyManager,
+    ) -> Option<Arc<dyn Signer>> {
+        let secret_key = key_manager.get_secret_key(user_public_key)?;
+        Some(Arc::new(Keys::new(secret_key)))
+    }
+
+    /// Handles `request` from the app with `app_public_key` to the account with `user_public_key`, returning the
+    /// result to send back to the app, or why the request failed. `key_manager` is the server's, which only has
+    /// the keys of the accounts that the server serves.
+    ///
+    /// The app is identified by the key its request is signed with. nip_55's own client signs each request with
+    /// a new key, so apps using it look like a new app on every request.
+    async fn handle_request(
+        &self,
+        request: nip46::Request,
+        user_public_key: PublicKey,
+        app_public_key: PublicKey,
+        key_manager: &dyn KeyManager,
+    ) -> Result<nip46::ResponseResult, String>;
+}
+
+/// Server that handles NIP-46 requests over NIP-55, listening on a Unix domain socket. Each connection carries a
+/// single request, as a NIP-04 encrypted event addressed to the account, and is answered with a NIP-04 encrypted
+/// event from the account. Requests are handled concurrently.
+pub struct Nip46Server {
+    /// Finishes with the error that made the server stop accepting connections.
+    task_handle: tokio::task::JoinHandle<std::io::Error>,
+    uds_address: String,
+}
+
+impl Nip46Server {
+    /// Starts listening on `uds_address`, replacing any socket file already there.
+    /// **MUST** be called from within a tokio runtime.
+    pub fn start(
+        uds_address: &str,
+        key_manager: Arc<dyn KeyManager>,
+        handler: Arc<dyn Nip46RequestHandler>,
+    ) -> std::io::Result<Self> {
+        if Path::new(uds_address).exists() {
+            std::fs::remove_file(uds_address)?;
+        }
+        let listener = UnixListener::bind(uds_address)?;
+
+        let task_handle = tokio::spawn(async move {
+            let mut consecutive_errors = 0;
+            loop {
+                let socket = match listener.accept().await {
+                    Ok((socket, _)) => socket,
+                    Err(err) => {
+                        consecutive_errors += 1;
+                        if consecutive_errors >= MAX_CONSECUTIVE_ACCEPT_ERRORS {
+                            return err;
+                        }
+                        tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                        continue;
+                    }
+                };
+                consecutive_errors = 0;
+                let key_manager = key_manager.clone();
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    // Connections that fail or send something other than a request for an account with a signer
+                    // are closed without a response, since there's no key to encrypt one with.
+                    let _ = handle_connection(socket, key_manager.as_ref(), handler.as_ref()).await;
+                });
+            }
+        });
+
+        Ok(Self {
+            task_handle,
+            uds_address: uds_address.to_string(),
+        })
+    }
+
+    /// Waits until the server stops accepting connections by itself, returning why. Never returns while the server is
+    /// healthy, and mustn't be called again once it has returned.
+    pub async fn stopped(&mut self) -> anyhow::Error {
+        match (&mut self.task_handle).await {
+            Ok(err) => anyhow::anyhow!("failed to accept connections: {err}"),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Stops listening. Dropping the server also stops it.
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for Nip46Server {
+    fn drop(&mut self) {
+        self.task_handle.abort();
+        // The socket file is only left behind, so failing to remove it doesn't matter.
+        let _ = std::fs::remove_file(&self.uds_address);
+    }
+}
+
+async fn handle_connection(
+    mut socket: UnixStream,
+    key_manager: &dyn KeyManager,
+    handler: &dyn Nip46RequestHandler,
+) -> anyhow::Result<()> {
+    let mut buf = Vec::new();
+    // Read one byte past the limit, to tell requests that are exactly at it from those over it.
+    tokio::time::timeout(
+        REQUEST_READ_TIMEOUT,
+        (&mut socket)
+            .take(MAX_REQUEST_BYTES + 1)
+            .read_to_end(&mut buf),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("Request wasn't sent in time"))??;
+    if buf.len() as u64 > MAX_REQUEST_BYTES {
+        return Err(anyhow::anyhow!("Request is too large"));
+    }
+    let request_event = Event::from_json(&buf)?;
+    request_event.verify()?;
+    if !handler.allows_app(request_event.author_ref()) {
+        return Err(anyhow::anyhow!("App isn't allowed to connect"));
+    }
+
+    let user_public_key = *request_event
+        .public_keys()
+        .next()
+        .ok_or(anyhow::anyhow!("Request isn't addressed to an account"))?;
+    let signer = handler
+        .signer_for(&user_public_key, key_manager)
+        .ok_or(anyhow::anyhow!("No key available for account"))?;
+
+    let request = decrypt_request(&request_event, signer.as_ref())?;
+    let response_data = match parse_nip46_request(&request) {
+        Some(nip46_request) => {
+            match handler
+                .handle_request(
+                    nip46_request.0,
+                    user_public_key,
+                    request_event.author(),
+                    key_manager,
+                )
+                .await
+            {
+                Ok(result) => to_response_data(&nip46_request.1, result)?,
+                Err(message) => error_response_data(JsonRpcErrorCode::InternalError, message),
+            }
+        }
+        None => error_response_data(
+            JsonRpcErrorCode::InvalidRequest,
+            "Request is not a valid NIP-46 request".to_string(),
+        ),
+    };
+    let response = JsonRpcResponse::new(response_data, request.id().clone());
+
+    let response_event = signer.sign_event(
+        EventBuilder::new(
+            request_event.kind(),
+            signer.nip04_encrypt(
+                request_event.author_ref(),
+                &serde_json::to_string(&response)?,
+            )?,
+            [Tag::public_key(request_event.author())],
+        )
+        .to_unsigned_event(user_public_key),
+    )?;
+    socket
+        .write_all(response_event.as_json().as_bytes())
+        .await?;
+    socket.shutdown().await?;
+    Ok(())
+}
+
+fn decrypt_request(request_event: &Event, signer: &dyn Signer) -> anyhow::Result<JsonRpcRequest> {
+    let mut request_json: serde_json::Map<String, Value> = serde_json::from_str(
+        &signer.nip04_decrypt(request_event.author_ref(), request_event.content())?,
+    )?;
+    // NIP-46 messages don't need the `jsonrpc` field, but JSON-RPC 2.0 requests do.
+    request_json
+        .entry("jsonrpc")
+        .or_insert_with(|| "2.0".into());
+    Ok(serde_json::from_value(Value::Object(request_json))?)
+}
+
+/// Returns the NIP-46 request and its ID, or `None` if the request isn't a NIP-46 request.
+fn parse_nip46_request(request: &JsonRpcRequest) -> Option<(nip46::Request, String)> {
+    let message: nip46::Message = serde_json::from_value(serde_json::json!(request)).ok()?;
+    let id = message.id().to_string();
+    Some((message.to_request().ok()?, id))
+}
+
+fn to_response_data(
+    id: &str,
+    result: nip46::ResponseResult,
+) -> anyhow::Result<JsonRpcResponseData> {
+    Ok(serde_json::from_value(serde_json::json!(
+        nip46::Message::response(id, Some(result), None)
+    ))?)
+}
+
+fn error_response_data(code: JsonRpcErrorCode, message: String) -> JsonRpcResponseData {
+    JsonRpcResponseData::Error {
+        error: JsonRpcError::new(code, message, None),
+    }
+}
+
+/// Signs every event it's asked to sign with the account's key from the server's key manager, without asking.
+#[cfg(test)]
+pub struct AutoSigningHandler;
+
+#[cfg(test)]
+#[async_trait]
+impl Nip46RequestHandler for AutoSigningHandler {
+    async fn handle_request(
+        &self,
+        request: nip46::Request,
+        user_public_key: PublicKey,
+        _app_public_key: PublicKey,
+        key_manager: &dyn KeyManager,
+    ) -> Result<nip46::ResponseResult, String> {
+        let nip46::Request::SignEvent(event) = request else {
+            return Err("Method not implemented".to_string());
+        };
+        let secret_key = key_manager
+            .get_secret_key(&user_public_key)
+            .ok_or("No key available for account")?;
+        event
+            .sign(&Keys::new(secret_key))
+            .map(nip46::ResponseResult::SignEvent)
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// Sends `request` to the server at `uds_address` from the app with `app_keys`, and returns the server's response.
+#[cfg(test)]
+pub async fn send_request(
+    uds_address: &str,
+    app_keys: &Keys,
+    user_public_key: PublicKey,
+    request: nip46::Request,
+) -> anyhow::Result<JsonRpcResponse> {
+    use nostr_sdk::nips::nip04;
+
+    let content = serde_json::json!(nip46::Message::request(request)).to_string();
+    let request_event = EventBuilder::new(
+        nostr_sdk::Kind::NostrConnect,
+        nip04::encrypt(app_keys.secret_key()?, &user_public_key, content)?,
+        [Tag::public_key(user_public_key)],
+    )
+    .to_event(app_keys)?;
+
+    let mut socket = UnixStream::connect(uds_address).await?;
+    socket.write_all(request_event.as_json().as_bytes()).await?;
+    socket.shutdown().await?;
+    let mut buf = Vec::new();
+    socket.read_to_end(&mut buf).await?;
+
+    let response_event = Event::from_json(&buf)?;
+    response_event.verify()?;
+    Ok(serde_json::from_str(&nip04::decrypt(
+        app_keys.secret_key()?,
+        &user_public_key,
+        response_event.content(),
+    )?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::SingleKeyManager;
+    use nip_55::nip46::Nip46OverNip55Client;
+    use nostr_sdk::nips::nip04;
+    use nostr_sdk::{EventBuilder, Kind};
+
+    /// Answers every request with the pubkey of the app that sent it.
+    struct EchoAppHandler;
+
+    #[async_trait]
+    impl Nip46RequestHandler for EchoAppHandler {
+        async fn handle_request(
+            &self,
+            _request: nip46::Request,
+            _user_public_key: PublicKey,
+            app_public_key: PublicKey,
+            _key_manager: &dyn KeyManager,
+        ) -> Result<nip46::ResponseResult, String> {
+            Ok(nip46::ResponseResult::GetPublicKey(app_public_key))
+        }
+    }
+
+    fn get_uds_address() -> String {
+        tempfile::TempDir::new()
+            .unwrap()
+            .into_path()
+            .join("nip55.sock")
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn signs_for_nip_55_clients() {
+        let keys = Keys::generate();
+        let uds_address = get_uds_address();
+        let _server = Nip46Server::start(
+            &uds_address,
+            Arc::new(SingleKeyManager { keys: keys.clone() }),
+            Arc::new(AutoSigningHandler),
+        )
+        .unwrap();
+
+        let client = Nip46OverNip55Client::new(uds_address.clone());
+        let event = client
+            .sign_event(
+                EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(keys.public_key()),
+                keys.public_key(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(event.author(), keys.public_key());
+        assert!(event.verify().is_ok());
+
+        // Requests for accounts the server has no key for go unanswered.
+        let other_public_key = Keys::generate().public_key();
+        assert!(client
+            .sign_event(
+                EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(other_public_key),
+                other_public_key,
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn oversized_and_slow_requests_are_closed() {
+        let keys = Keys::generate();
+        let uds_address = get_uds_address();
+        let _server = Nip46Server::start(
+            &uds_address,
+            Arc::new(SingleKeyManager { keys: keys.clone() }),
+            Arc::new(EchoAppHandler),
+        )
+        .unwrap();
+
+        // A valid request padded past the limit is closed without a response.
+        let app_keys = Keys::generate();
+        let content = serde_json::json!(nip46::Message::request(nip46::Request::GetPublicKey));
+        let mut request = EventBuilder::new(
+            Kind::NostrConnect,
+            nip04::encrypt(
+                app_keys.secret_key().unwrap(),
+                &keys.public_key(),
+                content.to_string(),
+            )
+            .unwrap(),
+            [Tag::public_key(keys.public_key())],
+        )
+        .to_event(&app_keys)
+        .unwrap()
+        .as_json()
+        .into_bytes();
+        request.resize(MAX_REQUEST_BYTES as usize + 1, b' ');
+        let mut socket = UnixStream::connect(&uds_address).await.unwrap();
+        // The server may close the connection before everything is written.
+        let _ = socket.write_all(&request).await;
+        let _ = socket.shutdown().await;
+        let mut response = Vec::new();
+        let _ = socket.read_to_end(&mut response).await;
+        assert!(response.is_empty());
+
+        // At the limit, the same request is answered.
+        request.truncate(MAX_REQUEST_BYTES as usize);
+        let mut socket = UnixStream::connect(&uds_address).await.unwrap();
+        socket.write_all(&request).await.unwrap();
+        socket.shutdown().await.unwrap();
+        let mut response = Vec::new();
+        socket.read_to_end(&mut response).await.unwrap();
+        assert!(!response.is_empty());
+
+        // As is a client that never finishes sending its request.
+        tokio::time::pause();
+        let mut socket = UnixStream::connect(&uds_address).await.unwrap();
+        socket.write_all(b"{").await.unwrap();
+        let mut response = Vec::new();
+        tokio::time::timeout(REQUEST_READ_TIMEOUT * 2, socket.read_to_end(&mut response))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(response.is_empty());
+    }
+
+    #[tokio::test]
+    async fn handler_is_told_which_app_sent_the_request() {
+        let keys = Keys::generate();
+        let uds_address = get_uds_address();
+        let _server = Nip46Server::start(
+            &uds_address,
+            Arc::new(SingleKeyManager { keys: keys.clone() }),
+            Arc::new(EchoAppHandler),
+        )
+        .unwrap();
+
+        let app_keys = Keys::generate();
+        let response = send_request(
+            &uds_address,
+            &app_keys,
+            keys.public_key(),
+            nip46::Request::GetPublicKey,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            response.data(),
+            &JsonRpcResponseData::Success {
+                result: Value::String(app_keys.public_key().to_hex())
+            }
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use nostr_sdk::nips::nip19::Nip19Profile;
+use nostr_sdk::{PublicKey, ToBech32, Url};
+
+/// Most relay hints embedded in an `nprofile`. Keeps the string short enough to share or render as a QR code.
+pub const MAX_NPROFILE_RELAYS: usize = 3;
+
+/// Encodes a public key as a NIP-19 `nprofile`, with up to [`MAX_NPROFILE_RELAYS`] of `relay_urls` as hints.
+/// URLs that aren't valid `ws://` or `wss://` URLs, and duplicates, are left out.
+pub fn build_nprofile(public_key: PublicKey, relay_urls: &[String]) -> anyhow::Result<String> {
+    let mut relays: Vec<Url> = Vec::new();
+    for relay_url in relay_urls {
+        if relays.len() == MAX_NPROFILE_RELAYS {
+            break;
+        }
+
+        let relay = match Url::parse(relay_url) {
+            Ok(relay) if relay.scheme() == "ws" || relay.scheme() == "wss" => relay,
+            _ => continue,
+        };
+        if !relays.contains(&relay) {
+            relays.push(relay);
+        }
+    }
+
+    Ok(Nip19Profile::new(public_key, relays)?.to_bech32()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::key_manager::KeystacheKeyManager;
+    use crate::relays::RelayPolicy;
+    use nostr_sdk::secp256k1::Secp256k1;
+    use nostr_sdk::{FromBech32, Keys};
+
+    #[test]
+    fn nprofile_from_stored_relays() {
+        let key_manager = KeystacheKeyManager::new_with_database(Database::new_in_temp_dir());
+        let keys = Keys::generate();
+        key_manager
+            .add_keypair(&keys.secret_key().unwrap().keypair(&Secp256k1::new()))
+            .unwrap();
+        for url in ["wss://relay.example.com", "wss://other-relay.example.com"] {
+            key_manager
+                .set_relay_policy(
+                    &keys.public_key(),
+                    url,
+                    RelayPolicy {
+                        read: true,
+                        write: true,
+                    },
+                )
+                .unwrap();
+        }
+
+        let nprofile = build_nprofile(
+            keys.public_key(),
+            &key_manager
+                .list_write_relay_urls(&keys.public_key())
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(nprofile.starts_with("nprofile1"));
+
+        let decoded = Nip19Profile::from_bech32(&nprofile).unwrap();
+        assert_eq!(decoded.public_key, keys.public_key());
+        let mut relays: Vec<String> = decoded
+            .relays
+            .iter()
+            .map(|relay| relay.to_string())
+            .collect();
+        relays.sort();
+        assert_eq!(
+            relays,
+            vec!["wss://other-relay.example.com/", "wss://relay.example.com/"]
+        );
+    }
+
+    #[test]
+    fn nprofile_caps_and_validates_relays() {
+        let public_key = Keys::generate().public_key();
+        let relay_urls: Vec<String> = [
+            "not a url",
+            "https://relay.example.com",
+            "wss://relay1.example.com",
+            "wss://relay1.example.com",
+            "wss://relay2.example.com",
+            "wss://relay3.example.com",
+            "wss://relay4.example.com",
+        ]
+        .iter()
+        .map(|url| url.to_string())
+        .collect();
+
+        let decoded =
+            Nip19Profile::from_bech32(build_nprofile(public_key, &relay_urls).unwrap()).unwrap();
+
+        assert_eq!(decoded.public_key, public_key);
+        assert_eq!(
+            decoded.relays,
+            vec![
+                Url::parse("wss://relay1.example.com").unwrap(),
+                Url::parse("wss://relay2.example.com").unwrap(),
+                Url::parse("wss://relay3.example.com").unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn nprofile_without_relays() {
+        let public_key = Keys::generate().public_key();
+
+        let decoded = Nip19Profile::from_bech32(build_nprofile(public_key, &[]).unwrap()).unwrap();
+
+        assert_eq!(decoded.public_key, public_key);
+        assert!(decoded.relays.is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+//! Running Keystache as a NIP-47 (Nostr Wallet Connect) wallet service, which apps connect to in order to make
+//! payments, rather than as a client of someone else's wallet service.
+
+use nostr_sdk::nips::nip04;
+use nostr_sdk::nips::nip47::{Method, Response};
+use nostr_sdk::{Event, EventBuilder, JsonUtil, Keys, Kind, Tag};
+
+/// Signs the kind-13194 info event that tells apps which methods the wallet service supports. The methods are
+/// listed space-separated in the event's content, in the order given.
+pub fn sign_info_event(keys: &Keys, methods: &[Method]) -> anyhow::Result<Event> {
+    if methods.is_empty() {
+        return Err(anyhow::anyhow!(
+            "A wallet service must support at least one method"
+        ));
+    }
+    let content = methods
+        .iter()
+        .map(Method::to_string)
+        .collect::<Vec<_>>()
+        .join(" ");
+    Ok(EventBuilder::new(Kind::WalletConnectInfo, content, []).to_event(keys)?)
+}
+
+/// Signs the kind-23195 response to an app's kind-23194 request, encrypted to the app with NIP-04.
+pub fn sign_response(keys: &Keys, request: &Event, response: &Response) -> anyhow::Result<Event> {
+    if request.kind() != Kind::WalletConnectRequest {
+        return Err(anyhow::anyhow!(
+            "Event is kind {}, not a wallet connect request",
+            request.kind()
+        ));
+    }
+    request.verify()?;
+
+    let content = nip04::encrypt(keys.secret_key()?, request.author_ref(), response.as_json())?;
+    let tags = [Tag::public_key(request.author()), Tag::event(request.id())];
+    Ok(EventBuilder::new(Kind::WalletConnectResponse, content, tags).to_event(keys)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::nips::nip47::{GetBalanceResponseResult, ResponseResult};
+
+    #[test]
+    fn info_event_lists_supported_methods() {
+        let keys = Keys::generate();
+        let methods = [Method::PayInvoice, Method::GetBalance, Method::GetInfo];
+
+        let event = sign_info_event(&keys, &methods).unwrap();
+        assert_eq!(event.kind(), Kind::WalletConnectInfo);
+        assert_eq!(event.author(), keys.public_key());
+        assert_eq!(event.content(), "pay_invoice get_balance get_info");
+        assert!(event.verify().is_ok());
+
+        assert!(sign_info_event(&keys, &[]).is_err());
+    }
+
+    #[test]
+    fn response_is_encrypted_to_requesting_app() {
+        let service_keys = Keys::generate();
+        let app_keys = Keys::generate();
+        let request = EventBuilder::new(Kind::WalletConnectRequest, "", [])
+            .to_event(&app_keys)
+            .unwrap();
+        let response = Response {
+            result_type: Method::GetBalance,
+            error: None,
+            result: Some(ResponseResult::GetBalance(GetBalanceResponseResult {
+                balance: 21_000,
+            })),
+        };
+
+        let event = sign_response(&service_keys, &request, &response).unwrap();
+        assert_eq!(event.kind(), Kind::WalletConnectResponse);
+        assert!(event.verify().is_ok());
+        assert_eq!(
+            event.tags().to_vec(),
+            vec![
+                Tag::public_key(app_keys.public_key()),
+                Tag::event(request.id()),
+            ]
+        );
+        let decrypted = nip04::decrypt(
+            app_keys.secret_key().unwrap(),
+            &service_keys.public_key(),
+            event.content(),
+        )
+        .unwrap();
+        assert_eq!(Response::from_json(decrypted).unwrap(), response);
+
+        // Only requests can be responded to.
+        let not_a_request = EventBuilder::text_note("hi", [])
+            .to_event(&app_keys)
+            .unwrap();
+        assert!(sign_response(&service_keys, &not_a_request, &response).is_err());
+    }
+}
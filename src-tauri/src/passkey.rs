@@ -0,0 +1,486 @@
+//! Gating of high-value operations (e.g. exporting a key) behind a WebAuthn passkey.
+//! Passkeys are created and asserted by the platform authenticator in the frontend.
+//! The backend only stores the credentials' public keys and checks assertions against them.
+
+use nostr_sdk::base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use nostr_sdk::base64::Engine;
+#[cfg(feature = "webauthn")]
+use nostr_sdk::hashes::{sha256, Hash};
+#[cfg(feature = "webauthn")]
+use nostr_sdk::secp256k1::rand::{thread_rng, RngCore};
+#[cfg(feature = "webauthn")]
+use p256::ecdsa::signature::Verifier;
+#[cfg(feature = "webauthn")]
+use p256::ecdsa::{Signature, VerifyingKey};
+#[cfg(feature = "webauthn")]
+use p256::pkcs8::DecodePublicKey;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// Size of the challenges that assertions must sign, in bytes.
+#[cfg(feature = "webauthn")]
+const CHALLENGE_SIZE: usize = 32;
+
+/// Flag in the authenticator data that is set when the user was present for the assertion.
+#[cfg(feature = "webauthn")]
+const USER_PRESENT_FLAG: u8 = 0x01;
+
+/// A passkey registered with Keystache.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PasskeyCredential {
+    /// The credential ID assigned by the authenticator.
+    pub credential_id: Vec<u8>,
+
+    /// The credential's P-256 public key, DER-encoded as a SubjectPublicKeyInfo
+    /// (as returned by `AuthenticatorAttestationResponse.getPublicKey()`).
+    pub public_key: Vec<u8>,
+}
+
+#[cfg(feature = "webauthn")]
+impl PasskeyCredential {
+    /// Checks that the public key is a P-256 public key, since only ES256 passkeys are supported.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.credential_id.is_empty() {
+            return Err(anyhow::anyhow!("Credential ID must not be empty"));
+        }
+        VerifyingKey::from_public_key_der(&self.public_key)
+            .map_err(|_| anyhow::anyhow!("Public key must be a DER-encoded P-256 key"))?;
+        Ok(())
+    }
+}
+
+/// A WebAuthn assertion (the result of `navigator.credentials.get()`).
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct PasskeyAssertion {
+    pub credential_id: Vec<u8>,
+    pub authenticator_data: Vec<u8>,
+    pub client_data_json: Vec<u8>,
+
+    /// DER-encoded ECDSA signature over `authenticator_data || SHA-256(client_data_json)`.
+    pub signature: Vec<u8>,
+}
+
+/// How a gated operation must be authorized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnlockMethod {
+    /// A passkey is enrolled, so an assertion from it is required.
+    Passkey,
+
+    /// No passkey is enrolled, so the operation falls back to a passphrase.
+    Passphrase,
+}
+
+/// Returns how gated operations must be authorized, given the enrolled passkeys.
+pub fn required_unlock_method(credentials: &[PasskeyCredential]) -> UnlockMethod {
+    if credentials.is_empty() {
+        UnlockMethod::Passphrase
+    } else {
+        UnlockMethod::Passkey
+    }
+}
+
+/// Checks that an assertion was made by a credential over a given challenge.
+pub trait AssertionVerifier: Send + Sync {
+    fn verify(
+        &self,
+        credential: &PasskeyCredential,
+        assertion: &PasskeyAssertion,
+        challenge: &[u8],
+    ) -> anyhow::Result<()>;
+}
+
+/// Verifies ES256 (ECDSA P-256 with SHA-256) assertions, as described in the WebAuthn spec.
+#[cfg(feature = "webauthn")]
+pub struct Es256AssertionVerifier {
+    relying_party_id: String,
+}
+
+#[cfg(feature = "webauthn")]
+impl Es256AssertionVerifier {
+    pub fn new(relying_party_id: impl Into<String>) -> Self {
+        Self {
+            relying_party_id: relying_party_id.into(),
+        }
+    }
+}
+
+#[cfg(feature = "webauthn")]
+impl AssertionVerifier for Es256AssertionVerifier {
+    fn verify(
+        &self,
+        credential: &PasskeyCredential,
+        assertion: &PasskeyAssertion,
+        challenge: &[u8],
+    ) -> anyhow::Result<()> {
+        let client_data = ClientData::parse(&assertion.client_data_json)?;
+        if client_data.type_ != "webauthn.get" {
+            return Err(anyhow::anyhow!("Client data is not for an assertion"));
+        }
+        if client_data.challenge()? != challenge {
+            return Err(anyhow::anyhow!("Assertion is for a different challenge"));
+        }
+
+        // Authenticator data starts with the 32-byte relying party ID hash, then a flags byte.
+        let authenticator_data = &assertion.authenticator_data;
+        if authenticator_data.len() < 37 {
+            return Err(anyhow::anyhow!("Authenticator data is too short"));
+        }
+        let relying_party_id_hash = sha256::Hash::hash(self.relying_party_id.as_bytes());
+        if authenticator_data[..32] != relying_party_id_hash[..] {
+            return Err(anyhow::anyhow!(
+                "Assertion is for a different relying party"
+            ));
+        }
+        if authenticator_data[32] & USER_PRESENT_FLAG == 0 {
+            return Err(anyhow::anyhow!("User was not present for the assertion"));
+        }
+
+        let verifying_key = VerifyingKey::from_public_key_der(&credential.public_key)
+            .map_err(|_| anyhow::anyhow!("Invalid credential public key"))?;
+        let signature = Signature::from_der(&assertion.signature)
+            .map_err(|_| anyhow::anyhow!("Invalid assertion signature"))?;
+
+        let mut signed_data = authenticator_data.clone();
+        signed_data.extend_from_slice(&sha256::Hash::hash(&assertion.client_data_json)[..]);
+        verifying_key
+            .verify(&signed_data, &signature)
+            .map_err(|_| anyhow::anyhow!("Assertion signature is invalid"))
+    }
+}
+
+/// Rejects every assertion. Builds without the `webauthn` feature can't check assertions, so gated operations
+/// stay locked if a passkey was enrolled by a build that could.
+#[cfg(not(feature = "webauthn"))]
+pub struct UnsupportedAssertionVerifier;
+
+#[cfg(not(feature = "webauthn"))]
+impl AssertionVerifier for UnsupportedAssertionVerifier {
+    fn verify(
+        &self,
+        _credential: &PasskeyCredential,
+        _assertion: &PasskeyAssertion,
+        _challenge: &[u8],
+    ) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Passkeys aren't supported by this build of Keystache"
+        ))
+    }
+}
+
+/// The fields of WebAuthn client data that are checked.
+#[derive(Deserialize)]
+struct ClientData {
+    #[cfg(feature = "webauthn")]
+    #[serde(rename = "type")]
+    type_: String,
+
+    /// Base64url-encoded challenge.
+    challenge: String,
+}
+
+impl ClientData {
+    fn parse(client_data_json: &[u8]) -> anyhow::Result<Self> {
+        serde_json::from_slice(client_data_json).map_err(|_| anyhow::anyhow!("Invalid client data"))
+    }
+
+    fn challenge(&self) -> anyhow::Result<Vec<u8>> {
+        URL_SAFE_NO_PAD
+            .decode(&self.challenge)
+            .map_err(|_| anyhow::anyhow!("Invalid client data challenge"))
+    }
+}
+
+/// Issues one-time challenges for passkey assertions and authorizes gated operations.
+pub struct PasskeyGate {
+    /// Challenges that have been issued but not yet used.
+    pending_challenges: Mutex<HashSet<Vec<u8>>>,
+
+    verifier: Box<dyn AssertionVerifier>,
+}
+
+impl PasskeyGate {
+    pub fn new(verifier: Box<dyn AssertionVerifier>) -> Self {
+        Self {
+            pending_challenges: Mutex::new(HashSet::new()),
+            verifier,
+        }
+    }
+
+    /// Returns a new random challenge for the frontend to request an assertion over.
+    #[cfg(feature = "webauthn")]
+    pub fn issue_challenge(&self) -> Vec<u8> {
+        let mut challenge = vec![0; CHALLENGE_SIZE];
+        thread_rng().fill_bytes(&mut challenge);
+        self.pending_challenges
+            .lock()
+            .unwrap()
+            .insert(challenge.clone());
+        challenge
+    }
+
+    /// Checks whether a gated operation may go ahead.
+    /// If no passkey is enrolled, returns [`UnlockMethod::Passphrase`] and the caller must fall back to a passphrase.
+    /// Otherwise `assertion_or` must be a valid assertion from an enrolled passkey over a challenge
+    /// from [`Self::issue_challenge`]. Each challenge can only be used once, even if the assertion is invalid.
+    pub fn authorize(
+        &self,
+        credentials: &[PasskeyCredential],
+        assertion_or: Option<&PasskeyAssertion>,
+    ) -> anyhow::Result<UnlockMethod> {
+        if required_unlock_method(credentials) == UnlockMethod::Passphrase {
+            return Ok(UnlockMethod::Passphrase);
+        }
+
+        let assertion = assertion_or.ok_or(anyhow::anyhow!("Passkey assertion required"))?;
+
+        let challenge = ClientData::parse(&assertion.client_data_json)?.challenge()?;
+        if !self.pending_challenges.lock().unwrap().remove(&challenge) {
+            return Err(anyhow::anyhow!("Unknown or already used challenge"));
+        }
+
+        let credential = credentials
+            .iter()
+            .find(|credential| credential.credential_id == assertion.credential_id)
+            .ok_or(anyhow::anyhow!("Passkey is not registered"))?;
+
+        self.verifier.verify(credential, assertion, &challenge)?;
+
+        Ok(UnlockMethod::Passkey)
+    }
+}
+
+#[cfg(all(test, feature = "webauthn"))]
+mod tests {
+    use super::*;
+    use p256::ecdsa::signature::Signer;
+    use p256::ecdsa::SigningKey;
+    use p256::pkcs8::EncodePublicKey;
+
+    const RELYING_PARTY_ID: &str = "localhost";
+
+    /// Verifier that accepts or rejects every assertion.
+    struct MockVerifier {
+        accept: bool,
+    }
+
+    impl AssertionVerifier for MockVerifier {
+        fn verify(
+            &self,
+            _credential: &PasskeyCredential,
+            _assertion: &PasskeyAssertion,
+            _challenge: &[u8],
+        ) -> anyhow::Result<()> {
+            if self.accept {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("Rejected by mock verifier"))
+            }
+        }
+    }
+
+    fn get_signing_key_and_credential() -> (SigningKey, PasskeyCredential) {
+        let signing_key = SigningKey::random(&mut p256::elliptic_curve::rand_core::OsRng);
+        let public_key = signing_key
+            .verifying_key()
+            .to_public_key_der()
+            .unwrap()
+            .into_vec();
+        let credential = PasskeyCredential {
+            credential_id: vec![1, 2, 3],
+            public_key,
+        };
+        (signing_key, credential)
+    }
+
+    fn get_client_data_json(type_: &str, challenge: &[u8]) -> Vec<u8> {
+        serde_json::json!({
+            "type": type_,
+            "challenge": URL_SAFE_NO_PAD.encode(challenge),
+            "origin": "tauri://localhost",
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    fn get_authenticator_data(relying_party_id: &str, flags: u8) -> Vec<u8> {
+        let mut authenticator_data = sha256::Hash::hash(relying_party_id.as_bytes())[..].to_vec();
+        authenticator_data.push(flags);
+        // Signature counter.
+        authenticator_data.extend_from_slice(&[0, 0, 0, 1]);
+        authenticator_data
+    }
+
+    /// Builds an assertion the way an authenticator would.
+    fn sign_assertion(
+        signing_key: &SigningKey,
+        credential: &PasskeyCredential,
+        authenticator_data: Vec<u8>,
+        client_data_json: Vec<u8>,
+    ) -> PasskeyAssertion {
+        let mut signed_data = authenticator_data.clone();
+        signed_data.extend_from_slice(&sha256::Hash::hash(&client_data_json)[..]);
+        let signature: Signature = signing_key.sign(&signed_data);
+
+        PasskeyAssertion {
+            credential_id: credential.credential_id.clone(),
+            authenticator_data,
+            client_data_json,
+            signature: signature.to_der().as_bytes().to_vec(),
+        }
+    }
+
+    fn get_mock_assertion(credential: &PasskeyCredential, challenge: &[u8]) -> PasskeyAssertion {
+        PasskeyAssertion {
+            credential_id: credential.credential_id.clone(),
+            authenticator_data: Vec::new(),
+            client_data_json: get_client_data_json("webauthn.get", challenge),
+            signature: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn falls_back_to_passphrase_without_passkey() {
+        assert_eq!(required_unlock_method(&[]), UnlockMethod::Passphrase);
+
+        let gate = PasskeyGate::new(Box::new(MockVerifier { accept: false }));
+        assert_eq!(gate.authorize(&[], None).unwrap(), UnlockMethod::Passphrase);
+    }
+
+    #[test]
+    fn passkey_required_once_enrolled() {
+        let (_, credential) = get_signing_key_and_credential();
+        let credentials = vec![credential.clone()];
+        assert_eq!(required_unlock_method(&credentials), UnlockMethod::Passkey);
+
+        let gate = PasskeyGate::new(Box::new(MockVerifier { accept: true }));
+
+        // Not providing an assertion should cause an error.
+        assert!(gate.authorize(&credentials, None).is_err());
+
+        let challenge = gate.issue_challenge();
+        let assertion = get_mock_assertion(&credential, &challenge);
+        assert_eq!(
+            gate.authorize(&credentials, Some(&assertion)).unwrap(),
+            UnlockMethod::Passkey
+        );
+
+        // Reusing a challenge should cause an error.
+        assert!(gate.authorize(&credentials, Some(&assertion)).is_err());
+    }
+
+    #[test]
+    fn authorize_rejects_unissued_challenge() {
+        let (_, credential) = get_signing_key_and_credential();
+        let gate = PasskeyGate::new(Box::new(MockVerifier { accept: true }));
+
+        let assertion = get_mock_assertion(&credential, &[0; CHALLENGE_SIZE]);
+        assert!(gate.authorize(&[credential], Some(&assertion)).is_err());
+    }
+
+    #[test]
+    fn authorize_rejects_unregistered_passkey() {
+        let (_, credential) = get_signing_key_and_credential();
+        let gate = PasskeyGate::new(Box::new(MockVerifier { accept: true }));
+
+        let mut assertion = get_mock_assertion(&credential, &gate.issue_challenge());
+        assertion.credential_id = vec![4, 5, 6];
+        assert!(gate.authorize(&[credential], Some(&assertion)).is_err());
+    }
+
+    #[test]
+    fn authorize_rejects_failed_verification() {
+        let (_, credential) = get_signing_key_and_credential();
+        let gate = PasskeyGate::new(Box::new(MockVerifier { accept: false }));
+
+        let assertion = get_mock_assertion(&credential, &gate.issue_challenge());
+        assert!(gate.authorize(&[credential], Some(&assertion)).is_err());
+    }
+
+    #[test]
+    fn es256_verifier_accepts_valid_assertion() {
+        let (signing_key, credential) = get_signing_key_and_credential();
+        let challenge = [7; CHALLENGE_SIZE];
+
+        let assertion = sign_assertion(
+            &signing_key,
+            &credential,
+            get_authenticator_data(RELYING_PARTY_ID, USER_PRESENT_FLAG),
+            get_client_data_json("webauthn.get", &challenge),
+        );
+
+        let verifier = Es256AssertionVerifier::new(RELYING_PARTY_ID);
+        assert!(verifier.verify(&credential, &assertion, &challenge).is_ok());
+    }
+
+    #[test]
+    fn es256_verifier_rejects_invalid_assertions() {
+        let (signing_key, credential) = get_signing_key_and_credential();
+        let (other_signing_key, _) = get_signing_key_and_credential();
+        let challenge = [7; CHALLENGE_SIZE];
+        let verifier = Es256AssertionVerifier::new(RELYING_PARTY_ID);
+
+        let invalid_assertions = [
+            // Different challenge.
+            sign_assertion(
+                &signing_key,
+                &credential,
+                get_authenticator_data(RELYING_PARTY_ID, USER_PRESENT_FLAG),
+                get_client_data_json("webauthn.get", &[8; CHALLENGE_SIZE]),
+            ),
+            // Registration rather than assertion.
+            sign_assertion(
+                &signing_key,
+                &credential,
+                get_authenticator_data(RELYING_PARTY_ID, USER_PRESENT_FLAG),
+                get_client_data_json("webauthn.create", &challenge),
+            ),
+            // Different relying party.
+            sign_assertion(
+                &signing_key,
+                &credential,
+                get_authenticator_data("example.com", USER_PRESENT_FLAG),
+                get_client_data_json("webauthn.get", &challenge),
+            ),
+            // User not present.
+            sign_assertion(
+                &signing_key,
+                &credential,
+                get_authenticator_data(RELYING_PARTY_ID, 0),
+                get_client_data_json("webauthn.get", &challenge),
+            ),
+            // Signed by a different key.
+            sign_assertion(
+                &other_signing_key,
+                &credential,
+                get_authenticator_data(RELYING_PARTY_ID, USER_PRESENT_FLAG),
+                get_client_data_json("webauthn.get", &challenge),
+            ),
+        ];
+
+        for assertion in invalid_assertions {
+            assert!(verifier
+                .verify(&credential, &assertion, &challenge)
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn validate_credential() {
+        let (_, credential) = get_signing_key_and_credential();
+        assert!(credential.validate().is_ok());
+
+        let empty_id = PasskeyCredential {
+            credential_id: Vec::new(),
+            ..credential.clone()
+        };
+        assert!(empty_id.validate().is_err());
+
+        let invalid_public_key = PasskeyCredential {
+            public_key: vec![1, 2, 3],
+            ..credential
+        };
+        assert!(invalid_public_key.validate().is_err());
+    }
+}
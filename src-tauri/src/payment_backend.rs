@@ -0,0 +1,344 @@
+use async_trait::async_trait;
+use lightning_invoice::Bolt11Invoice;
+use nostr_sdk::hashes::{sha256, Hash};
+use nostr_sdk::util::hex;
+use serde::Serialize;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// How long a backend is given to pay an invoice before the payment is cancelled.
+pub const PAYMENT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a backend is given to wind down a payment after it's cancelled, before it's given up on.
+const CANCEL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Estimated routing fee for paying an invoice.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FeeEstimate {
+    /// The backend estimated the fee.
+    // Only `NoPaymentBackend` exists so far, and it can't estimate fees. Backends that can will construct this.
+    #[cfg_attr(not(test), allow(dead_code))]
+    Estimated { fee_sats: u64 },
+
+    /// The backend can't estimate fees.
+    NotSupported,
+}
+
+/// Something that invoices can be paid through (e.g. an NWC wallet or a Fedimint client).
+#[async_trait]
+pub trait PaymentBackend: Send + Sync {
+    /// Name of the backend reported to clients (e.g. `nwc`), or `None` if it can't make payments.
+    fn name(&self) -> Option<&'static str>;
+
+    /// Estimates the routing fee for paying an invoice, without paying it.
+    async fn estimate_fee(&self, invoice: &Bolt11Invoice) -> anyhow::Result<FeeEstimate>;
+
+    /// Pays an invoice, returning the hex-encoded preimage. Once `cancellation` is cancelled, the backend should
+    /// abort the payment (e.g. by cancelling its request to the wallet) and return an error.
+    async fn pay_invoice(
+        &self,
+        invoice: &Bolt11Invoice,
+        cancellation: CancellationToken,
+    ) -> anyhow::Result<String>;
+}
+
+/// Used when no payment backend is configured. Doesn't support fee estimation.
+pub struct NoPaymentBackend;
+
+#[async_trait]
+impl PaymentBackend for NoPaymentBackend {
+    fn name(&self) -> Option<&'static str> {
+        None
+    }
+
+    async fn estimate_fee(&self, _invoice: &Bolt11Invoice) -> anyhow::Result<FeeEstimate> {
+        Ok(FeeEstimate::NotSupported)
+    }
+
+    async fn pay_invoice(
+        &self,
+        _invoice: &Bolt11Invoice,
+        _cancellation: CancellationToken,
+    ) -> anyhow::Result<String> {
+        Err(anyhow::anyhow!("No payment backend is configured"))
+    }
+}
+
+/// Pays an invoice through `payment_backend`, cancelling the payment if it takes longer than `timeout` or
+/// `cancellation` is cancelled (e.g. by the user). After cancelling, the backend is given a moment to abort the
+/// payment before it's given up on, so that the payment is actually stopped rather than just no longer awaited.
+pub async fn pay_invoice_cancellably(
+    payment_backend: &dyn PaymentBackend,
+    invoice: &Bolt11Invoice,
+    timeout: Duration,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<PayInvoiceResponse> {
+    let payment_cancellation = cancellation.child_token();
+    let payment = payment_backend.pay_invoice(invoice, payment_cancellation.clone());
+    tokio::pin!(payment);
+
+    let error = tokio::select! {
+        preimage = &mut payment => match preimage {
+            Ok(preimage) => return PayInvoiceResponse::new(invoice, &preimage),
+            // The backend saw the caller's cancellation before this did.
+            Err(_) if cancellation.is_cancelled() => return Err(anyhow::anyhow!("Payment was cancelled")),
+            Err(err) => return Err(err),
+        },
+        () = tokio::time::sleep(timeout) => {
+            anyhow::anyhow!("Payment took longer than {} seconds and was cancelled", timeout.as_secs())
+        }
+        () = cancellation.cancelled() => anyhow::anyhow!("Payment was cancelled"),
+    };
+
+    payment_cancellation.cancel();
+    // The payment may still have gone through before the backend saw the cancellation.
+    if let Ok(Ok(preimage)) = tokio::time::timeout(CANCEL_GRACE_PERIOD, payment).await {
+        return PayInvoiceResponse::new(invoice, &preimage);
+    }
+    Err(error)
+}
+
+/// Payload of the `pay_invoice_request` event sent to the frontend for approval.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PayInvoiceRequestPayload {
+    /// The Bolt11 invoice string.
+    pub invoice: String,
+
+    /// Hex-encoded payment hash of the invoice.
+    pub payment_hash: String,
+
+    /// The estimated routing fee, or `None` if estimating the fee failed.
+    pub fee_estimate: Option<FeeEstimate>,
+
+    /// Entries of the user's scam list that the invoice pays (e.g. the recipient of a zap).
+    pub scam_list_matches: Vec<String>,
+
+    /// Whether the request arrived during quiet hours or the invoice is over the maximum single payment, so
+    /// Keystache must be unlocked before it can be approved.
+    pub requires_unlock: bool,
+
+    /// Phrase the user must type to approve the payment (see [`crate::confirmation_phrase`]), if any.
+    pub confirmation_phrase: Option<&'static str>,
+
+    /// The npub of the app that asked for the invoice to be paid, if known.
+    pub app_npub: Option<String>,
+
+    /// What to call the app in the prompt: the name the user gave it, or its npub if it hasn't been named.
+    /// `None` if the app isn't known.
+    pub app_name: Option<String>,
+}
+
+impl PayInvoiceRequestPayload {
+    /// Builds the approval prompt for an invoice, including the backend's fee estimate.
+    pub async fn new(invoice: &Bolt11Invoice, payment_backend: &dyn PaymentBackend) -> Self {
+        Self {
+            invoice: invoice.to_string(),
+            payment_hash: invoice.payment_hash().to_string(),
+            fee_estimate: payment_backend.estimate_fee(invoice).await.ok(),
+            scam_list_matches: Vec::new(),
+            requires_unlock: false,
+            confirmation_phrase: None,
+            app_npub: None,
+            app_name: None,
+        }
+    }
+}
+
+/// Result of successfully paying an invoice. Clients can use the payment hash as an idempotency key when retrying.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PayInvoiceResponse {
+    /// Hex-encoded payment hash of the invoice.
+    pub payment_hash: String,
+
+    /// Hex-encoded preimage proving the invoice was paid.
+    pub preimage: String,
+}
+
+impl PayInvoiceResponse {
+    /// Builds the response for a payment of `invoice`, checking that the preimage returned by the
+    /// payment backend actually hashes to the invoice's payment hash.
+    pub fn new(invoice: &Bolt11Invoice, preimage: &str) -> anyhow::Result<Self> {
+        let preimage_bytes =
+            hex::decode(preimage).map_err(|_| anyhow::anyhow!("Preimage isn't valid hex"))?;
+        if sha256::Hash::hash(&preimage_bytes)[..] != invoice.payment_hash()[..] {
+            return Err(anyhow::anyhow!(
+                "Preimage doesn't match the invoice's payment hash"
+            ));
+        }
+
+        Ok(Self {
+            payment_hash: invoice.payment_hash().to_string(),
+            preimage: preimage.to_lowercase(),
+        })
+    }
+}
+
+/// What became of a request to pay an invoice.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PaymentResult {
+    /// The user approved the payment, and the payment backend paid the invoice.
+    Paid(PayInvoiceResponse),
+
+    /// The user rejected the payment, or it wasn't approved in time.
+    Rejected,
+
+    /// The user approved the payment, but the payment backend didn't pay the invoice, or returned a preimage that
+    /// doesn't match the invoice's payment hash.
+    Failed { reason: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    // https://github.com/lightning/bolts/blob/master/11-payment-encoding.md#examples
+    const INVOICE: &str = "lnbc2500u1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdq5xysxxatsyp3k7enxv4jsxqzpu9qrsgquk0rl77nj30yxdy8j9vdx85fkpmdla2087ne0xh8nhedh8w27kyke0lp53ut353s06fv3qfegext0eh0ymjpf39tuven09sam30g4vgpfna3rh";
+
+    struct MockBackend {
+        estimate: anyhow::Result<FeeEstimate>,
+    }
+
+    #[async_trait]
+    impl PaymentBackend for MockBackend {
+        fn name(&self) -> Option<&'static str> {
+            Some("mock")
+        }
+
+        async fn estimate_fee(&self, _invoice: &Bolt11Invoice) -> anyhow::Result<FeeEstimate> {
+            match &self.estimate {
+                Ok(estimate) => Ok(estimate.clone()),
+                Err(err) => Err(anyhow::anyhow!("{}", err)),
+            }
+        }
+
+        async fn pay_invoice(
+            &self,
+            _invoice: &Bolt11Invoice,
+            _cancellation: CancellationToken,
+        ) -> anyhow::Result<String> {
+            Err(anyhow::anyhow!("Not supported"))
+        }
+    }
+
+    /// Never finishes paying, but aborts once the payment is cancelled.
+    #[derive(Default)]
+    struct HangingBackend {
+        cancelled: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl PaymentBackend for HangingBackend {
+        fn name(&self) -> Option<&'static str> {
+            Some("hanging")
+        }
+
+        async fn estimate_fee(&self, _invoice: &Bolt11Invoice) -> anyhow::Result<FeeEstimate> {
+            Ok(FeeEstimate::NotSupported)
+        }
+
+        async fn pay_invoice(
+            &self,
+            _invoice: &Bolt11Invoice,
+            cancellation: CancellationToken,
+        ) -> anyhow::Result<String> {
+            cancellation.cancelled().await;
+            self.cancelled.store(true, Ordering::SeqCst);
+            Err(anyhow::anyhow!("Aborted"))
+        }
+    }
+
+    fn get_invoice() -> Bolt11Invoice {
+        Bolt11Invoice::from_str(INVOICE).unwrap()
+    }
+
+    #[tokio::test]
+    async fn payload_includes_estimate() {
+        let backend = MockBackend {
+            estimate: Ok(FeeEstimate::Estimated { fee_sats: 12 }),
+        };
+
+        let payload = PayInvoiceRequestPayload::new(&get_invoice(), &backend).await;
+
+        assert_eq!(payload.invoice, INVOICE);
+        assert_eq!(
+            payload.payment_hash,
+            "0001020304050607080900010203040506070809000102030405060708090102"
+        );
+        assert_eq!(
+            payload.fee_estimate,
+            Some(FeeEstimate::Estimated { fee_sats: 12 })
+        );
+    }
+
+    #[tokio::test]
+    async fn payload_with_backend_that_does_not_support_estimation() {
+        let payload = PayInvoiceRequestPayload::new(&get_invoice(), &NoPaymentBackend).await;
+
+        assert_eq!(payload.fee_estimate, Some(FeeEstimate::NotSupported));
+    }
+
+    #[tokio::test]
+    async fn payload_when_estimation_fails() {
+        let backend = MockBackend {
+            estimate: Err(anyhow::anyhow!("wallet unreachable")),
+        };
+
+        let payload = PayInvoiceRequestPayload::new(&get_invoice(), &backend).await;
+
+        assert_eq!(payload.fee_estimate, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hanging_payment_is_cancelled() {
+        // On timeout.
+        let invoice = get_invoice();
+        let backend = HangingBackend::default();
+        let result = pay_invoice_cancellably(
+            &backend,
+            &invoice,
+            PAYMENT_TIMEOUT,
+            &CancellationToken::new(),
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Payment took longer than 60 seconds and was cancelled"
+        );
+        assert!(backend.cancelled.load(Ordering::SeqCst));
+
+        // When the caller cancels.
+        let backend = HangingBackend::default();
+        let cancellation = CancellationToken::new();
+        let (result, ()) = tokio::join!(
+            pay_invoice_cancellably(&backend, &invoice, PAYMENT_TIMEOUT, &cancellation),
+            async { cancellation.cancel() }
+        );
+        assert_eq!(result.unwrap_err().to_string(), "Payment was cancelled");
+        assert!(backend.cancelled.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn response_rejects_mismatched_preimage() {
+        // The example invoice's payment hash is 0001...0102, which this doesn't hash to.
+        let preimage = "00".repeat(32);
+        assert!(PayInvoiceResponse::new(&get_invoice(), &preimage).is_err());
+
+        assert!(PayInvoiceResponse::new(&get_invoice(), "not hex").is_err());
+    }
+
+    #[test]
+    fn fee_estimate_serialization() {
+        assert_eq!(
+            serde_json::to_value(FeeEstimate::Estimated { fee_sats: 12 }).unwrap(),
+            serde_json::json!({ "type": "estimated", "fee_sats": 12 })
+        );
+        assert_eq!(
+            serde_json::to_value(FeeEstimate::NotSupported).unwrap(),
+            serde_json::json!({ "type": "not_supported" })
+        );
+    }
+}
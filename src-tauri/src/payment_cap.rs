@@ -0,0 +1,116 @@
+use lightning_invoice::Bolt11Invoice;
+use serde::{Deserialize, Serialize};
+
+/// What happens to invoices that are over the maximum single payment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverCapAction {
+    /// The invoice is rejected without prompting the user.
+    Reject,
+
+    /// The user is still prompted, but has to unlock Keystache before the invoice can be paid.
+    RequireUnlock,
+}
+
+/// A hard cap on the amount of any single payment, whichever app requests it. Unlike budgets, it doesn't depend on
+/// what's been paid before.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaxSinglePayment {
+    pub max_sats: u64,
+    pub action: OverCapAction,
+}
+
+/// Returned when an invoice can't be paid as requested.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PayInvoiceError {
+    /// The invoice is over the maximum single payment. `amount_sats` is `None` if the invoice doesn't have an
+    /// amount, since the app could then pay any amount.
+    AmountTooLarge {
+        amount_sats: Option<u64>,
+        max_sats: u64,
+    },
+
+    /// No payment backend is configured, so the invoice can't be paid even if the user approves it.
+    NoPaymentBackend,
+}
+
+impl std::fmt::Display for PayInvoiceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AmountTooLarge {
+                amount_sats: Some(amount_sats),
+                max_sats,
+            } => write!(
+                f,
+                "Invoice for {amount_sats} sats is over the maximum single payment of {max_sats} sats"
+            ),
+            Self::AmountTooLarge {
+                amount_sats: None,
+                max_sats,
+            } => write!(
+                f,
+                "Invoice has no amount, so it could be over the maximum single payment of {max_sats} sats"
+            ),
+            Self::NoPaymentBackend => write!(f, "No payment backend is configured"),
+        }
+    }
+}
+
+impl std::error::Error for PayInvoiceError {}
+
+impl MaxSinglePayment {
+    /// Errors if the cap is zero, which would block every payment.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.max_sats == 0 {
+            return Err(anyhow::anyhow!(
+                "Maximum single payment must be at least 1 sat"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Errors if the invoice is over the cap. Invoices without an amount are treated as over it.
+    pub fn check(&self, invoice: &Bolt11Invoice) -> Result<(), PayInvoiceError> {
+        // Rounded up, so that a fraction of a sat over the cap is still over it.
+        let amount_sats = invoice
+            .amount_milli_satoshis()
+            .map(|amount_msats| amount_msats.div_ceil(1000));
+        match amount_sats {
+            Some(amount_sats) if amount_sats <= self.max_sats => Ok(()),
+            amount_sats => Err(PayInvoiceError::AmountTooLarge {
+                amount_sats,
+                max_sats: self.max_sats,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// Invoice for 250,000 sats.
+    const INVOICE: &str = "lnbc2500u1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdq5xysxxatsyp3k7enxv4jsxqzpu9qrsgquk0rl77nj30yxdy8j9vdx85fkpmdla2087ne0xh8nhedh8w27kyke0lp53ut353s06fv3qfegext0eh0ymjpf39tuven09sam30g4vgpfna3rh";
+
+    #[test]
+    fn checks_invoice_amount_against_cap() {
+        let invoice = Bolt11Invoice::from_str(INVOICE).unwrap();
+        let cap = |max_sats| MaxSinglePayment {
+            max_sats,
+            action: OverCapAction::Reject,
+        };
+
+        assert_eq!(cap(250_000).check(&invoice), Ok(()));
+        assert_eq!(
+            cap(249_999).check(&invoice),
+            Err(PayInvoiceError::AmountTooLarge {
+                amount_sats: Some(250_000),
+                max_sats: 249_999,
+            })
+        );
+
+        assert!(cap(1).validate().is_ok());
+        assert!(cap(0).validate().is_err());
+    }
+}
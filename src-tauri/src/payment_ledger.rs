@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Default amount of time that a settled payment is remembered for.
+pub const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+enum LedgerEntry<T> {
+    /// A payment is currently being made. Resolves to `Some` once the payment settles.
+    InFlight(watch::Receiver<Option<T>>),
+
+    /// A payment has already settled.
+    Settled { result: T, settled_at: Instant },
+}
+
+enum PaymentClaim<T> {
+    /// The payment already settled with this result.
+    Settled(T),
+
+    /// The payment is already in flight.
+    InFlight(watch::Receiver<Option<T>>),
+
+    /// No existing payment was found, so the caller is now responsible for paying.
+    Claimed(watch::Sender<Option<T>>),
+}
+
+/// Ledger of in-flight and recently settled payments, keyed by payment hash.
+/// Used to prevent paying the same invoice twice when a client retries a request.
+pub struct PaymentLedger<T> {
+    entries: Mutex<HashMap<String, LedgerEntry<T>>>,
+
+    /// How long a settled payment is remembered for.
+    dedup_window: Mutex<Duration>,
+}
+
+impl<T: Clone> PaymentLedger<T> {
+    pub fn new(dedup_window: Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            dedup_window: Mutex::new(dedup_window),
+        }
+    }
+
+    /// Sets how long a settled payment is remembered for.
+    /// Applies to payments that have already settled as well as future ones.
+    pub fn set_dedup_window(&self, dedup_window: Duration) {
+        *self.dedup_window.lock().unwrap() = dedup_window;
+    }
+
+    /// Makes a payment using `pay`, unless a payment with the same payment hash is
+    /// already in flight or has settled within the dedup window. In that case, the
+    /// existing result is returned and `pay` is never called.
+    ///
+    /// # Arguments
+    ///
+    /// * `payment_hash` - The payment hash of the invoice being paid.
+    /// * `is_settled` - Whether a result should be remembered. Results that aren't settled (e.g. rejections)
+    ///                  are forgotten once the payment completes so that retries can try again.
+    /// * `pay` - Makes the actual payment.
+    pub async fn pay_once<F, Fut>(
+        &self,
+        payment_hash: &str,
+        is_settled: impl Fn(&T) -> bool,
+        pay: F,
+    ) -> anyhow::Result<T>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let tx = match self.claim_payment(payment_hash) {
+            PaymentClaim::Settled(result) => return Ok(result),
+            PaymentClaim::InFlight(mut rx) => return Self::wait_for_result(&mut rx).await,
+            PaymentClaim::Claimed(tx) => tx,
+        };
+
+        let result = pay().await;
+
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if is_settled(&result) {
+                entries.insert(
+                    payment_hash.to_string(),
+                    LedgerEntry::Settled {
+                        result: result.clone(),
+                        settled_at: Instant::now(),
+                    },
+                );
+            } else {
+                entries.remove(payment_hash);
+            }
+
+            // Remove any settled payments that are too old to be returned.
+            let dedup_window = *self.dedup_window.lock().unwrap();
+            entries.retain(|_, entry| match entry {
+                LedgerEntry::InFlight(_) => true,
+                LedgerEntry::Settled { settled_at, .. } => settled_at.elapsed() < dedup_window,
+            });
+        }
+
+        // Any callers waiting on this payment will receive the result. There may be none.
+        let _ = tx.send(Some(result.clone()));
+
+        Ok(result)
+    }
+
+    /// Checks for an existing payment with the given payment hash, and if there
+    /// isn't one, marks a new payment as in flight.
+    fn claim_payment(&self, payment_hash: &str) -> PaymentClaim<T> {
+        let mut entries = self.entries.lock().unwrap();
+        let dedup_window = *self.dedup_window.lock().unwrap();
+
+        match entries.get(payment_hash) {
+            Some(LedgerEntry::Settled { result, settled_at })
+                if settled_at.elapsed() < dedup_window =>
+            {
+                return PaymentClaim::Settled(result.clone());
+            }
+            // If the sender was dropped, the payment was abandoned before it
+            // settled, so it's safe to treat the payment as never having been made.
+            Some(LedgerEntry::InFlight(rx)) if rx.has_changed().is_ok() => {
+                return PaymentClaim::InFlight(rx.clone());
+            }
+            _ => {}
+        }
+
+        let (tx, rx) = watch::channel(None);
+        entries.insert(payment_hash.to_string(), LedgerEntry::InFlight(rx));
+        PaymentClaim::Claimed(tx)
+    }
+
+    async fn wait_for_result(rx: &mut watch::Receiver<Option<T>>) -> anyhow::Result<T> {
+        match rx.wait_for(|result| result.is_some()).await {
+            Ok(result) => result
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("Payment result missing")),
+            Err(_) => Err(anyhow::anyhow!("Payment was abandoned before it settled")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn concurrent_payments_for_same_hash_only_pay_once() {
+        let ledger = Arc::new(PaymentLedger::<bool>::new(DEFAULT_DEDUP_WINDOW));
+        let payment_count = Arc::new(AtomicUsize::new(0));
+        let (release_tx, release_rx) = tokio::sync::oneshot::channel::<()>();
+
+        let first_payment = {
+            let ledger = ledger.clone();
+            let payment_count = payment_count.clone();
+            tokio::spawn(async move {
+                ledger
+                    .pay_once(
+                        "hash",
+                        |paid| *paid,
+                        || async move {
+                            payment_count.fetch_add(1, Ordering::SeqCst);
+                            // Hold the payment in flight until the second request has been made.
+                            release_rx.await.unwrap();
+                            true
+                        },
+                    )
+                    .await
+            })
+        };
+
+        // Wait for the first payment to start.
+        while payment_count.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let second_payment = {
+            let ledger = ledger.clone();
+            let payment_count = payment_count.clone();
+            tokio::spawn(async move {
+                ledger
+                    .pay_once(
+                        "hash",
+                        |paid| *paid,
+                        || async move {
+                            payment_count.fetch_add(1, Ordering::SeqCst);
+                            true
+                        },
+                    )
+                    .await
+            })
+        };
+
+        release_tx.send(()).unwrap();
+
+        assert!(first_payment.await.unwrap().unwrap());
+        assert!(second_payment.await.unwrap().unwrap());
+
+        // Only the first request should have actually paid.
+        assert_eq!(payment_count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn settled_payment_is_returned_within_dedup_window() {
+        let ledger = PaymentLedger::<bool>::new(DEFAULT_DEDUP_WINDOW);
+        let payment_count = AtomicUsize::new(0);
+
+        for _ in 0..2 {
+            let paid = ledger
+                .pay_once(
+                    "hash",
+                    |paid| *paid,
+                    || async {
+                        payment_count.fetch_add(1, Ordering::SeqCst);
+                        true
+                    },
+                )
+                .await
+                .unwrap();
+            assert!(paid);
+        }
+
+        assert_eq!(payment_count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn settled_payment_is_forgotten_after_dedup_window() {
+        let ledger = PaymentLedger::<bool>::new(Duration::ZERO);
+        let payment_count = AtomicUsize::new(0);
+
+        for _ in 0..2 {
+            ledger
+                .pay_once(
+                    "hash",
+                    |paid| *paid,
+                    || async {
+                        payment_count.fetch_add(1, Ordering::SeqCst);
+                        true
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        // With a dedup window of zero, every request should pay.
+        assert_eq!(payment_count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn unsettled_result_is_not_remembered() {
+        let ledger = PaymentLedger::<bool>::new(DEFAULT_DEDUP_WINDOW);
+        let payment_count = AtomicUsize::new(0);
+
+        for _ in 0..2 {
+            let paid = ledger
+                .pay_once(
+                    "hash",
+                    |paid| *paid,
+                    || async {
+                        payment_count.fetch_add(1, Ordering::SeqCst);
+                        false
+                    },
+                )
+                .await
+                .unwrap();
+            assert!(!paid);
+        }
+
+        // A failed payment shouldn't stop a retry from paying.
+        assert_eq!(payment_count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn different_hashes_are_paid_separately() {
+        let ledger = PaymentLedger::<bool>::new(DEFAULT_DEDUP_WINDOW);
+        let payment_count = AtomicUsize::new(0);
+
+        for payment_hash in ["hash_1", "hash_2"] {
+            ledger
+                .pay_once(
+                    payment_hash,
+                    |paid| *paid,
+                    || async {
+                        payment_count.fetch_add(1, Ordering::SeqCst);
+                        true
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(payment_count.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::error_log;
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
+use nostr_sdk::{PublicKey, ToBech32};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/// How often [`BatchedPaymentLog`] writes recorded payments, if fewer than [`MAX_BATCH_SIZE`] have built up in the meantime.
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Most payments that [`BatchedPaymentLog`] holds before writing them, regardless of the flush interval.
+const MAX_BATCH_SIZE: usize = 100;
+
+/// How a request to pay an invoice ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentOutcome {
+    /// The user approved the payment and it was paid.
+    Paid,
+
+    /// The user approved the payment but paying it failed.
+    Failed,
+
+    /// The user rejected the payment, or didn't respond in time.
+    Rejected,
+}
+
+impl PaymentOutcome {
+    /// Name the outcome is stored under in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Paid => "paid",
+            Self::Failed => "failed",
+            Self::Rejected => "rejected",
+        }
+    }
+}
+
+impl std::str::FromStr for PaymentOutcome {
+    type Err = anyhow::Error;
+
+    fn from_str(outcome: &str) -> anyhow::Result<Self> {
+        match outcome {
+            "paid" => Ok(Self::Paid),
+            "failed" => Ok(Self::Failed),
+            "rejected" => Ok(Self::Rejected),
+            _ => Err(anyhow::anyhow!("Unknown payment outcome: {outcome}")),
+        }
+    }
+}
+
+/// A request to pay an invoice, as recorded in the payment history.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PaymentLogEntry {
+    /// The Bolt11 invoice string.
+    pub invoice: String,
+
+    /// Amount the invoice is for, or `None` if it doesn't specify one.
+    pub amount_msats: Option<u64>,
+
+    /// The invoice's description, or `None` if it only has a description hash.
+    pub description: Option<String>,
+
+    /// npub of the application that asked for the payment, if known.
+    pub app_npub: Option<String>,
+
+    /// When the request ended, as an RFC 3339 timestamp.
+    pub create_time: String,
+
+    pub outcome: PaymentOutcome,
+
+    /// Preimage proving the invoice was paid. Only set if the outcome is [`PaymentOutcome::Paid`].
+    pub preimage: Option<String>,
+}
+
+impl PaymentLogEntry {
+    /// Builds an entry for a request to pay `invoice` that ended now, decoding the invoice's amount and description.
+    pub fn new(
+        invoice: &Bolt11Invoice,
+        app_public_key: Option<&PublicKey>,
+        outcome: PaymentOutcome,
+        preimage: Option<String>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            invoice: invoice.to_string(),
+            amount_msats: invoice.amount_milli_satoshis(),
+            description: match invoice.description() {
+                Bolt11InvoiceDescription::Direct(description) => Some(description.to_string()),
+                Bolt11InvoiceDescription::Hash(_) => None,
+            },
+            app_npub: app_public_key
+                .map(|app_public_key| app_public_key.to_bech32())
+                .transpose()?,
+            create_time: chrono::Utc::now().to_rfc3339(),
+            outcome,
+            preimage: preimage.filter(|_| outcome == PaymentOutcome::Paid),
+        })
+    }
+}
+
+/// Narrows down which entries of the payment history are returned. Unset fields match every entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct PaymentHistoryFilter {
+    pub outcome: Option<PaymentOutcome>,
+
+    /// Only return payments requested by this application.
+    pub app_npub: Option<String>,
+}
+
+/// Somewhere to record requests to pay invoices.
+pub trait PaymentLog: Send + Sync {
+    fn record_payment(&self, entry: &PaymentLogEntry) -> anyhow::Result<()>;
+
+    /// Records several payments at once. Implementations backed by a database should write them in a single transaction.
+    fn record_payments(&self, entries: &[PaymentLogEntry]) -> anyhow::Result<()> {
+        for entry in entries {
+            self.record_payment(entry)?;
+        }
+        Ok(())
+    }
+}
+
+enum BatchMessage {
+    Record(PaymentLogEntry),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Records payments to another payment log from a background task, so that the request path never waits on a write.
+/// Payments are written in batches, once [`MAX_BATCH_SIZE`] have built up or the flush interval passes, whichever comes first.
+pub struct BatchedPaymentLog {
+    sender: mpsc::UnboundedSender<BatchMessage>,
+}
+
+impl BatchedPaymentLog {
+    /// **MUST** be called from within a tokio runtime.
+    pub fn new(inner: Arc<dyn PaymentLog>, flush_interval: Duration) -> Self {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let mut batch = Vec::new();
+            let mut interval = tokio::time::interval(flush_interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    message = receiver.recv() => match message {
+                        Some(BatchMessage::Record(entry)) => {
+                            batch.push(entry);
+                            if batch.len() >= MAX_BATCH_SIZE {
+                                write_batch(inner.as_ref(), &mut batch);
+                            }
+                        }
+                        Some(BatchMessage::Flush(done)) => {
+                            write_batch(inner.as_ref(), &mut batch);
+                            let _ = done.send(());
+                        }
+                        None => {
+                            write_batch(inner.as_ref(), &mut batch);
+                            break;
+                        }
+                    },
+                    _ = interval.tick() => write_batch(inner.as_ref(), &mut batch),
+                }
+            }
+        });
+
+        Self { sender }
+    }
+
+    /// Writes every payment recorded so far, returning once they've been written.
+    /// Should be called before Keystache exits, so that no recent payments are lost.
+    pub async fn flush(&self) {
+        let (done, flushed) = oneshot::channel();
+        if self.sender.send(BatchMessage::Flush(done)).is_ok() {
+            let _ = flushed.await;
+        }
+    }
+}
+
+impl PaymentLog for BatchedPaymentLog {
+    fn record_payment(&self, entry: &PaymentLogEntry) -> anyhow::Result<()> {
+        self.sender
+            .send(BatchMessage::Record(entry.clone()))
+            .map_err(|_| anyhow::anyhow!("Payment log writer has stopped"))
+    }
+}
+
+fn write_batch(inner: &dyn PaymentLog, batch: &mut Vec<PaymentLogEntry>) {
+    if batch.is_empty() {
+        return;
+    }
+
+    if let Err(err) = inner.record_payments(batch) {
+        error_log::report(format!("Failed to record {} payments: {err}", batch.len()));
+    }
+    batch.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::key_manager::KeystacheKeyManager;
+    use nostr_sdk::Keys;
+    use std::str::FromStr;
+
+    // https://github.com/lightning/bolts/blob/master/11-payment-encoding.md#examples
+    const INVOICE: &str = "lnbc2500u1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdq5xysxxatsyp3k7enxv4jsxqzpu9qrsgquk0rl77nj30yxdy8j9vdx85fkpmdla2087ne0xh8nhedh8w27kyke0lp53ut353s06fv3qfegext0eh0ymjpf39tuven09sam30g4vgpfna3rh";
+
+    #[test]
+    fn entry_decodes_invoice() {
+        let app_public_key = Keys::generate().public_key();
+
+        let entry = PaymentLogEntry::new(
+            &Bolt11Invoice::from_str(INVOICE).unwrap(),
+            Some(&app_public_key),
+            PaymentOutcome::Rejected,
+            Some("preimage".to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(entry.invoice, INVOICE);
+        assert_eq!(entry.amount_msats, Some(250_000_000));
+        assert_eq!(entry.description, Some("1 cup coffee".to_string()));
+        assert_eq!(entry.app_npub, Some(app_public_key.to_bech32().unwrap()));
+        // Only paid invoices have a preimage.
+        assert_eq!(entry.preimage, None);
+    }
+
+    #[test]
+    fn outcome_round_trip() {
+        for outcome in [
+            PaymentOutcome::Paid,
+            PaymentOutcome::Failed,
+            PaymentOutcome::Rejected,
+        ] {
+            assert_eq!(PaymentOutcome::from_str(outcome.as_str()).unwrap(), outcome);
+            assert_eq!(
+                serde_json::to_value(outcome).unwrap(),
+                serde_json::Value::String(outcome.as_str().to_string())
+            );
+        }
+
+        assert!(PaymentOutcome::from_str("pending").is_err());
+    }
+
+    #[tokio::test]
+    async fn batched_log_persists_every_entry_after_flush() {
+        let key_manager = Arc::new(KeystacheKeyManager::new_with_database(
+            Database::new_in_temp_dir(),
+        ));
+        // A long interval, so that only the batch size and the explicit flush cause writes.
+        let payment_log = BatchedPaymentLog::new(key_manager.clone(), Duration::from_secs(3600));
+
+        let entry = PaymentLogEntry::new(
+            &Bolt11Invoice::from_str(INVOICE).unwrap(),
+            None,
+            PaymentOutcome::Rejected,
+            None,
+        )
+        .unwrap();
+        let entry_count = MAX_BATCH_SIZE * 2 + 50;
+        for _ in 0..entry_count {
+            payment_log.record_payment(&entry).unwrap();
+        }
+
+        payment_log.flush().await;
+
+        let history = key_manager
+            .get_payment_history(10_000, 0, &PaymentHistoryFilter::default())
+            .unwrap();
+        assert_eq!(history.len(), entry_count);
+        assert!(history.iter().all(|logged| *logged == entry));
+    }
+}
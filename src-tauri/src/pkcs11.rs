@@ -0,0 +1,416 @@
+//! Signing with keys held on a PKCS#11 token (e.g. a hardware security module), so that the secret key is never
+//! exposed to Keystache.
+
+use crate::request_approver::compute_event_id;
+use crate::signer::Signer;
+use crate::validation::validate_npub;
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use aes::Aes256;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use cryptoki::context::{CInitializeArgs, Pkcs11};
+use cryptoki::mechanism::elliptic_curve::{EcKdf, Ecdh1DeriveParams};
+use cryptoki::mechanism::vendor_defined::VendorDefinedMechanism;
+use cryptoki::mechanism::{Mechanism, MechanismType};
+use cryptoki::object::{Attribute, AttributeType, KeyType, ObjectClass, ObjectHandle};
+use cryptoki::session::{Session, UserType};
+use cryptoki::types::AuthPin;
+use nostr_sdk::secp256k1::rand::{thread_rng, RngCore};
+use nostr_sdk::secp256k1::schnorr::Signature;
+use nostr_sdk::secp256k1::{Parity, XOnlyPublicKey};
+use nostr_sdk::{Event, PublicKey, UnsignedEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Where the key of an account that signs on a PKCS#11 token is.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HardwareKeyBinding {
+    /// The account's npub, which must be the key's public key.
+    pub npub: String,
+
+    /// Path to the token's PKCS#11 module (e.g. `/usr/lib/softhsm/libsofthsm2.so`).
+    pub module_path: String,
+
+    pub token_label: String,
+    pub key_label: String,
+
+    /// The token's vendor-defined mechanism for BIP-340 Schnorr signing, e.g. `0x80000001`.
+    pub schnorr_mechanism: u64,
+
+    /// The user PIN to log in to the token with, or `None` to log in through the token's own PIN entry, e.g. a PIN
+    /// pad. Only ever stored in the vault.
+    pub pin: Option<String>,
+}
+
+impl HardwareKeyBinding {
+    /// Opens the token and checks that its key is the account's.
+    pub fn open_signer(&self) -> anyhow::Result<Pkcs11Signer> {
+        let public_key = validate_npub(&self.npub)?;
+        let token = CryptokiToken::open(
+            &self.module_path,
+            &self.token_label,
+            self.pin.as_deref(),
+            self.schnorr_mechanism,
+        )?;
+        let signer = Pkcs11Signer::new(Arc::new(token), &self.key_label)?;
+        if signer.public_key() != public_key {
+            return Err(anyhow::anyhow!(
+                "Key {} on token {} isn't the key of {}",
+                self.key_label,
+                self.token_label,
+                self.npub
+            ));
+        }
+        Ok(signer)
+    }
+}
+
+/// The operations Keystache needs from a PKCS#11 token holding secp256k1 keys. BIP-340 Schnorr signing isn't a
+/// standard PKCS#11 mechanism, so implementations use their token's vendor-defined mechanism for it.
+pub trait Pkcs11Token: Send + Sync {
+    /// The public key of the key pair with the label `key_label`.
+    fn public_key(&self, key_label: &str) -> anyhow::Result<XOnlyPublicKey>;
+
+    /// Signs the 32-byte `message` with the key with the label `key_label`, returning a BIP-340 Schnorr signature.
+    fn sign_schnorr(&self, key_label: &str, message: &[u8; 32]) -> anyhow::Result<[u8; 64]>;
+
+    /// Derives the secret shared between the key with the label `key_label` and `peer_public_key` the way NIP-04
+    /// does: the x-coordinate of the ECDH point, taking the peer's key to have an even y-coordinate.
+    fn ecdh(&self, key_label: &str, peer_public_key: &PublicKey) -> anyhow::Result<[u8; 32]>;
+}
+
+/// Signs events for an account whose key is on a PKCS#11 token.
+pub struct Pkcs11Signer {
+    token: Arc<dyn Pkcs11Token>,
+    key_label: String,
+
+    /// Read from the token once, when the signer is created.
+    public_key: PublicKey,
+}
+
+impl Pkcs11Signer {
+    /// Errors if the token has no key pair with the label `key_label`.
+    pub fn new(token: Arc<dyn Pkcs11Token>, key_label: &str) -> anyhow::Result<Self> {
+        let public_key = token.public_key(key_label)?.into();
+        Ok(Self {
+            token,
+            key_label: key_label.to_string(),
+            public_key,
+        })
+    }
+}
+
+impl Signer for Pkcs11Signer {
+    fn public_key(&self) -> PublicKey {
+        self.public_key
+    }
+
+    fn sign_event(&self, mut event: UnsignedEvent) -> anyhow::Result<Event> {
+        if event.pubkey != self.public_key {
+            return Err(anyhow::anyhow!(
+                "Event pubkey doesn't match the token's key"
+            ));
+        }
+        let id = compute_event_id(&event);
+        event.id = Some(id);
+
+        let signature = self.token.sign_schnorr(&self.key_label, &id.to_bytes())?;
+        // The signature is verified when it's added, so a token that signs with the wrong key is caught here.
+        Ok(event.add_signature(Signature::from_slice(&signature)?)?)
+    }
+
+    // NIP-04 is AES-256-CBC encryption with the shared secret as the key. Only the key derivation needs the token.
+    fn nip04_encrypt(
+        &self,
+        peer_public_key: &PublicKey,
+        plaintext: &str,
+    ) -> anyhow::Result<String> {
+        let shared_secret = self.token.ecdh(&self.key_label, peer_public_key)?;
+        let mut iv = [0; 16];
+        thread_rng().fill_bytes(&mut iv);
+        let ciphertext = cbc::Encryptor::<Aes256>::new(&shared_secret.into(), &iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(plaintext.as_bytes());
+        Ok(format!(
+            "{}?iv={}",
+            BASE64.encode(ciphertext),
+            BASE64.encode(iv)
+        ))
+    }
+
+    fn nip04_decrypt(&self, peer_public_key: &PublicKey, content: &str) -> anyhow::Result<String> {
+        let (ciphertext, iv) = content
+            .split_once("?iv=")
+            .ok_or(anyhow::anyhow!("Invalid NIP-04 content"))?;
+        let ciphertext = BASE64.decode(ciphertext)?;
+        let iv: [u8; 16] = BASE64
+            .decode(iv)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid NIP-04 IV"))?;
+
+        let shared_secret = self.token.ecdh(&self.key_label, peer_public_key)?;
+        let plaintext = cbc::Decryptor::<Aes256>::new(&shared_secret.into(), &iv.into())
+            .decrypt_padded_vec_mut::<Pkcs7>(&ciphertext)
+            .map_err(|_| anyhow::anyhow!("Error decrypting NIP-04 content"))?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
+
+/// PKCS#11 modules that have been loaded, by path. A module is only initialized once, however many of its tokens are
+/// opened.
+static MODULES: OnceLock<Mutex<HashMap<String, Pkcs11>>> = OnceLock::new();
+
+fn load_module(module_path: &str) -> anyhow::Result<Pkcs11> {
+    let mut modules = MODULES.get_or_init(Default::default).lock().unwrap();
+    if let Some(module) = modules.get(module_path) {
+        return Ok(module.clone());
+    }
+    let module = Pkcs11::new(module_path)?;
+    module.initialize(CInitializeArgs::OsThreads)?;
+    modules.insert(module_path.to_string(), module.clone());
+    Ok(module)
+}
+
+/// A token behind a PKCS#11 module, with a session that's logged in for as long as the token is open.
+pub struct CryptokiToken {
+    session: Mutex<Session>,
+    schnorr_mechanism: MechanismType,
+}
+
+impl CryptokiToken {
+    /// Opens the token labelled `token_label` in the PKCS#11 module at `module_path`, logging in with `pin`, or
+    /// through the token's own PIN entry if `pin` is `None`. `schnorr_mechanism` is the token's vendor-defined
+    /// mechanism for BIP-340 Schnorr signing.
+    pub fn open(
+        module_path: &str,
+        token_label: &str,
+        pin: Option<&str>,
+        schnorr_mechanism: u64,
+    ) -> anyhow::Result<Self> {
+        let schnorr_mechanism = MechanismType::new_vendor_defined(schnorr_mechanism)
+            .map_err(|_| anyhow::anyhow!("Schnorr mechanism isn't vendor-defined"))?;
+        let module = load_module(module_path)?;
+        let slot = module
+            .get_slots_with_token()?
+            .into_iter()
+            .find(|slot| {
+                module
+                    .get_token_info(*slot)
+                    .is_ok_and(|token_info| token_info.label() == token_label)
+            })
+            .ok_or(anyhow::anyhow!("No token labelled {token_label}"))?;
+
+        let session = module.open_ro_session(slot)?;
+        session.login(
+            UserType::User,
+            pin.map(|pin| AuthPin::new(pin.to_string())).as_ref(),
+        )?;
+        Ok(Self {
+            session: Mutex::new(session),
+            schnorr_mechanism,
+        })
+    }
+
+    fn find_key(
+        session: &Session,
+        class: ObjectClass,
+        key_label: &str,
+    ) -> anyhow::Result<ObjectHandle> {
+        session
+            .find_objects(&[
+                Attribute::Class(class),
+                Attribute::KeyType(KeyType::EC),
+                Attribute::Label(key_label.as_bytes().to_vec()),
+            ])?
+            .into_iter()
+            .next()
+            .ok_or(anyhow::anyhow!("No key labelled {key_label}"))
+    }
+}
+
+impl Pkcs11Token for CryptokiToken {
+    fn public_key(&self, key_label: &str) -> anyhow::Result<XOnlyPublicKey> {
+        let session = self.session.lock().unwrap();
+        let key = Self::find_key(&session, ObjectClass::PUBLIC_KEY, key_label)?;
+        let Some(Attribute::EcPoint(ec_point)) = session
+            .get_attributes(key, &[AttributeType::EcPoint])?
+            .into_iter()
+            .next()
+        else {
+            return Err(anyhow::anyhow!("Key labelled {key_label} has no EC point"));
+        };
+
+        // The point should be wrapped in a DER octet string, but some tokens return it bare.
+        let point = match ec_point.as_slice() {
+            [0x04, len, point @ ..] if usize::from(*len) == point.len() => point,
+            point => point,
+        };
+        let public_key = nostr_sdk::secp256k1::PublicKey::from_slice(point)
+            .or_else(|_| nostr_sdk::secp256k1::PublicKey::from_slice(&ec_point))
+            .map_err(|_| anyhow::anyhow!("Key labelled {key_label} isn't a secp256k1 key"))?;
+        Ok(public_key.x_only_public_key().0)
+    }
+
+    fn sign_schnorr(&self, key_label: &str, message: &[u8; 32]) -> anyhow::Result<[u8; 64]> {
+        let session = self.session.lock().unwrap();
+        let key = Self::find_key(&session, ObjectClass::PRIVATE_KEY, key_label)?;
+        let mechanism = Mechanism::VendorDefined(VendorDefinedMechanism::new::<()>(
+            self.schnorr_mechanism,
+            None,
+        ));
+        session
+            .sign(&mechanism, key, message)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Token returned a signature that isn't 64 bytes"))
+    }
+
+    fn ecdh(&self, key_label: &str, peer_public_key: &PublicKey) -> anyhow::Result<[u8; 32]> {
+        let session = self.session.lock().unwrap();
+        let key = Self::find_key(&session, ObjectClass::PRIVATE_KEY, key_label)?;
+        let peer_point = peer_public_key
+            .public_key(Parity::Even)
+            .serialize_uncompressed();
+        // Without a KDF, the derived key is the x-coordinate of the shared point, as NIP-04 needs.
+        let shared_key = session.derive_key(
+            &Mechanism::Ecdh1Derive(Ecdh1DeriveParams::new(EcKdf::null(), &peer_point)),
+            key,
+            &[
+                Attribute::Class(ObjectClass::SECRET_KEY),
+                Attribute::KeyType(KeyType::GENERIC_SECRET),
+                Attribute::ValueLen(32.into()),
+                Attribute::Token(false),
+                Attribute::Sensitive(false),
+                Attribute::Extractable(true),
+            ],
+        )?;
+        let value = session.get_attributes(shared_key, &[AttributeType::Value]);
+        // The shared key only lives as long as the session anyway, so failing to destroy it early doesn't matter.
+        let _ = session.destroy_object(shared_key);
+
+        let Some(Attribute::Value(shared_secret)) = value?.into_iter().next() else {
+            return Err(anyhow::anyhow!("Token didn't return the shared secret"));
+        };
+        shared_secret
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Token returned a shared secret that isn't 32 bytes"))
+    }
+}
+
+/// A token with a single key pair, which never hands out its secret key.
+#[cfg(test)]
+pub struct MockToken {
+    pub key_label: String,
+    pub keys: nostr_sdk::Keys,
+}
+
+#[cfg(test)]
+impl Pkcs11Token for MockToken {
+    fn public_key(&self, key_label: &str) -> anyhow::Result<XOnlyPublicKey> {
+        if key_label != self.key_label {
+            return Err(anyhow::anyhow!("No key labelled {key_label}"));
+        }
+        Ok(*self.keys.public_key())
+    }
+
+    fn sign_schnorr(&self, key_label: &str, message: &[u8; 32]) -> anyhow::Result<[u8; 64]> {
+        if key_label != self.key_label {
+            return Err(anyhow::anyhow!("No key labelled {key_label}"));
+        }
+        let message = nostr_sdk::secp256k1::Message::from_digest_slice(message)?;
+        Ok(*self.keys.sign_schnorr(&message)?.as_ref())
+    }
+
+    fn ecdh(&self, key_label: &str, peer_public_key: &PublicKey) -> anyhow::Result<[u8; 32]> {
+        if key_label != self.key_label {
+            return Err(anyhow::anyhow!("No key labelled {key_label}"));
+        }
+        Ok(crate::shared_secret::derive_shared_secret(
+            self.keys.secret_key()?,
+            peer_public_key,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{EventBuilder, Keys, Kind};
+
+    #[test]
+    fn signs_events_on_the_token() {
+        let keys = Keys::generate();
+        let token = Arc::new(MockToken {
+            key_label: "nostr".to_string(),
+            keys: keys.clone(),
+        });
+        assert!(Pkcs11Signer::new(token.clone(), "other").is_err());
+
+        let signer = Pkcs11Signer::new(token, "nostr").unwrap();
+        assert_eq!(signer.public_key(), keys.public_key());
+
+        let event = signer
+            .sign_event(
+                EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(keys.public_key()),
+            )
+            .unwrap();
+        assert_eq!(event.author(), keys.public_key());
+        assert!(event.verify().is_ok());
+
+        // Events for other accounts can't be signed with the token's key.
+        let other_public_key = Keys::generate().public_key();
+        assert!(signer
+            .sign_event(
+                EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(other_public_key)
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn encrypts_nip04_messages_on_the_token() {
+        use nostr_sdk::nips::nip04;
+
+        let keys = Keys::generate();
+        let signer = Pkcs11Signer::new(
+            Arc::new(MockToken {
+                key_label: "nostr".to_string(),
+                keys: keys.clone(),
+            }),
+            "nostr",
+        )
+        .unwrap();
+        let peer_keys = Keys::generate();
+
+        // Messages either way are the same as with NIP-04 and the key itself.
+        let content = signer
+            .nip04_encrypt(&peer_keys.public_key(), "hello peer")
+            .unwrap();
+        assert_eq!(
+            nip04::decrypt(peer_keys.secret_key().unwrap(), &keys.public_key(), content).unwrap(),
+            "hello peer"
+        );
+        let content = nip04::encrypt(
+            peer_keys.secret_key().unwrap(),
+            &keys.public_key(),
+            "hello token",
+        )
+        .unwrap();
+        assert_eq!(
+            signer
+                .nip04_decrypt(&peer_keys.public_key(), &content)
+                .unwrap(),
+            "hello token"
+        );
+
+        // Messages for someone else can't be decrypted.
+        let content = nip04::encrypt(
+            peer_keys.secret_key().unwrap(),
+            &Keys::generate().public_key(),
+            "hello stranger",
+        )
+        .unwrap();
+        assert!(signer
+            .nip04_decrypt(&peer_keys.public_key(), &content)
+            .is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use nostr_sdk::nips::nip13;
+use nostr_sdk::{EventId, Tag, UnsignedEvent};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// How long mining can take before it's given up on.
+pub const MAX_MINING_TIME: Duration = Duration::from_secs(60);
+
+/// How many nonces are tried between progress reports, and between checks for cancellation or the time limit.
+const ATTEMPTS_PER_PROGRESS_REPORT: u64 = 50_000;
+
+/// Name of the NIP-13 tag holding the nonce and the target difficulty.
+const NONCE_TAG: &str = "nonce";
+
+/// Payload of the `pow_progress` event emitted to the frontend while mining proof of work for an event.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PowProgressPayload {
+    /// ID of the sign event request that the proof of work is for.
+    pub event_id: String,
+
+    /// Number of leading zero bits the event ID must have.
+    pub difficulty: u8,
+
+    /// Number of nonces tried so far.
+    pub attempts: u64,
+
+    /// Most leading zero bits of any event ID found so far.
+    pub best_leading_zero_bits: u8,
+}
+
+/// Returned when mining stops before it finds a nonce.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PowError {
+    Cancelled,
+    TimedOut,
+}
+
+impl std::fmt::Display for PowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cancelled => write!(f, "Proof of work was cancelled"),
+            Self::TimedOut => write!(
+                f,
+                "Proof of work took longer than {} seconds",
+                MAX_MINING_TIME.as_secs()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PowError {}
+
+/// Mines a NIP-13 nonce so that the event's ID has at least `difficulty` leading zero bits, replacing any
+/// `nonce` tag the event already has. Returns the event with the nonce tag and its new ID.
+///
+/// This is CPU-bound and blocks until it's done, so it should be run off the async runtime. `on_progress` is
+/// called every so often with the number of nonces tried and the best result so far. Mining stops with an error
+/// once `cancelled` is set or `time_limit` has passed.
+pub fn mine_event(
+    mut event: UnsignedEvent,
+    difficulty: u8,
+    time_limit: Duration,
+    cancelled: &AtomicBool,
+    mut on_progress: impl FnMut(u64, u8),
+) -> Result<UnsignedEvent, PowError> {
+    let start = Instant::now();
+    event
+        .tags
+        .retain(|tag| tag.as_vec().first().map(String::as_str) != Some(NONCE_TAG));
+
+    let mut best_leading_zero_bits = 0;
+    for nonce in 0u128.. {
+        let attempts = nonce as u64;
+        if attempts > 0 && attempts % ATTEMPTS_PER_PROGRESS_REPORT == 0 {
+            if cancelled.load(Ordering::Relaxed) {
+                return Err(PowError::Cancelled);
+            }
+            if start.elapsed() > time_limit {
+                return Err(PowError::TimedOut);
+            }
+            on_progress(attempts, best_leading_zero_bits);
+        }
+
+        event.tags.push(Tag::POW { nonce, difficulty });
+        let id = EventId::new(
+            &event.pubkey,
+            event.created_at,
+            &event.kind,
+            &event.tags,
+            &event.content,
+        );
+        let leading_zero_bits = nip13::get_leading_zero_bits(id.inner());
+        if leading_zero_bits >= difficulty {
+            event.id = Some(id);
+            return Ok(event);
+        }
+        best_leading_zero_bits = best_leading_zero_bits.max(leading_zero_bits);
+        event.tags.pop();
+    }
+
+    unreachable!("every nonce has been tried")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{EventBuilder, Keys, Kind};
+
+    #[test]
+    fn mines_event_to_difficulty() {
+        let keys = Keys::generate();
+        let event = EventBuilder::new(Kind::TextNote, "hello world", [Tag::Hashtag("pow".into())])
+            .to_unsigned_event(keys.public_key());
+
+        let mined_event = mine_event(
+            event.clone(),
+            12,
+            MAX_MINING_TIME,
+            &AtomicBool::new(false),
+            |_, _| {},
+        )
+        .unwrap();
+
+        let id = mined_event.id.unwrap();
+        assert!(nip13::get_leading_zero_bits(id.inner()) >= 12);
+        assert_eq!(
+            mined_event.tags[0].as_vec(),
+            vec!["t".to_string(), "pow".to_string()]
+        );
+        assert_eq!(mined_event.tags[1].as_vec()[0], "nonce");
+        assert_eq!(mined_event.tags[1].as_vec()[2], "12");
+        assert_eq!(mined_event.content, event.content);
+
+        // The ID is the one the event gets when it's signed.
+        assert_eq!(mined_event.sign(&keys).unwrap().id(), id);
+    }
+
+    #[test]
+    fn mining_stops_when_cancelled_or_out_of_time() {
+        let event = EventBuilder::new(Kind::TextNote, "hello world", None)
+            .to_unsigned_event(Keys::generate().public_key());
+
+        assert_eq!(
+            mine_event(
+                event.clone(),
+                255,
+                MAX_MINING_TIME,
+                &AtomicBool::new(true),
+                |_, _| {}
+            ),
+            Err(PowError::Cancelled)
+        );
+        assert_eq!(
+            mine_event(
+                event,
+                255,
+                Duration::ZERO,
+                &AtomicBool::new(false),
+                |_, _| {}
+            ),
+            Err(PowError::TimedOut)
+        );
+    }
+}
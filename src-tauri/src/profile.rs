@@ -0,0 +1,202 @@
+use nostr_sdk::{Event, EventBuilder, Metadata, PublicKey, UnsignedEvent, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::relays::RelayPublishResult;
+
+/// Editable fields of a Nostr profile (kind-0 metadata).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileFields {
+    pub name: Option<String>,
+    pub about: Option<String>,
+    pub picture: Option<String>,
+    pub nip05: Option<String>,
+    pub lud16: Option<String>,
+}
+
+/// Response from updating the user's profile metadata.
+#[derive(Clone, Debug, Serialize)]
+pub struct UpdateProfileMetadataResponse {
+    /// The signed kind-0 event.
+    pub event: Event,
+
+    /// How each relay responded to the event being published.
+    /// Empty if the event wasn't published.
+    pub publish_results: Vec<RelayPublishResult>,
+}
+
+impl ProfileFields {
+    /// Checks that each field that is set is well-formed.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(picture) = &self.picture {
+            match Url::parse(picture) {
+                Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+                _ => return Err(anyhow::anyhow!("picture must be an http(s) URL")),
+            }
+        }
+
+        if let Some(lud16) = &self.lud16 {
+            if !is_lightning_address(lud16) {
+                return Err(anyhow::anyhow!(
+                    "lud16 must be a lightning address (e.g. name@example.com)"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the editable fields out of a kind-0 event's metadata.
+    pub fn from_metadata(metadata: Metadata) -> Self {
+        Self {
+            name: metadata.name,
+            about: metadata.about,
+            picture: metadata.picture,
+            nip05: metadata.nip05,
+            lud16: metadata.lud16,
+        }
+    }
+
+    fn to_metadata(&self) -> Metadata {
+        let mut metadata = Metadata::new();
+        metadata.name = self.name.clone();
+        metadata.about = self.about.clone();
+        metadata.picture = self.picture.clone();
+        metadata.nip05 = self.nip05.clone();
+        metadata.lud16 = self.lud16.clone();
+        metadata
+    }
+}
+
+/// Validates the profile fields and builds an unsigned kind-0 event from them.
+pub fn build_metadata_event(
+    fields: &ProfileFields,
+    public_key: PublicKey,
+) -> anyhow::Result<UnsignedEvent> {
+    fields.validate()?;
+    Ok(EventBuilder::metadata(&fields.to_metadata()).to_unsigned_event(public_key))
+}
+
+/// Whether a string looks like a lightning address (`name@domain.tld`).
+pub fn is_lightning_address(address: &str) -> bool {
+    let (name, domain) = match address.split_once('@') {
+        Some(parts) => parts,
+        None => return false,
+    };
+
+    !name.is_empty()
+        && !domain.is_empty()
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !address.chars().any(char::is_whitespace)
+        && !domain.contains('@')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{JsonUtil, Keys, Kind};
+
+    fn get_valid_fields() -> ProfileFields {
+        ProfileFields {
+            name: Some("satoshi".to_string()),
+            about: Some("hello world".to_string()),
+            picture: Some("https://example.com/picture.png".to_string()),
+            nip05: Some("satoshi@example.com".to_string()),
+            lud16: Some("satoshi@example.com".to_string()),
+        }
+    }
+
+    #[test]
+    fn build_metadata_event_success() {
+        let public_key = Keys::generate().public_key();
+        let fields = get_valid_fields();
+
+        let event = build_metadata_event(&fields, public_key).unwrap();
+
+        assert_eq!(event.kind, Kind::Metadata);
+        assert_eq!(event.pubkey, public_key);
+        assert!(event.tags.is_empty());
+
+        // The content should round-trip back into the same fields.
+        let metadata = Metadata::from_json(&event.content).unwrap();
+        assert_eq!(ProfileFields::from_metadata(metadata), fields);
+    }
+
+    #[test]
+    fn build_metadata_event_with_no_fields() {
+        let public_key = Keys::generate().public_key();
+
+        let event = build_metadata_event(&ProfileFields::default(), public_key).unwrap();
+
+        assert_eq!(event.kind, Kind::Metadata);
+        assert_eq!(
+            ProfileFields::from_metadata(Metadata::from_json(&event.content).unwrap()),
+            ProfileFields::default()
+        );
+    }
+
+    #[test]
+    fn build_metadata_event_invalid_fields_error() {
+        let fields = ProfileFields {
+            picture: Some("not a url".to_string()),
+            ..get_valid_fields()
+        };
+
+        assert!(build_metadata_event(&fields, Keys::generate().public_key()).is_err());
+    }
+
+    #[test]
+    fn validate_picture() {
+        assert!(get_valid_fields().validate().is_ok());
+
+        for picture in ["http://example.com/picture.png", "https://example.com"] {
+            let fields = ProfileFields {
+                picture: Some(picture.to_string()),
+                ..get_valid_fields()
+            };
+            assert!(fields.validate().is_ok(), "{picture} should be valid");
+        }
+
+        for picture in [
+            "",
+            "not a url",
+            "example.com/picture.png",
+            "ftp://example.com",
+        ] {
+            let fields = ProfileFields {
+                picture: Some(picture.to_string()),
+                ..get_valid_fields()
+            };
+            assert!(fields.validate().is_err(), "{picture} should be invalid");
+        }
+    }
+
+    #[test]
+    fn validate_lud16() {
+        for lud16 in ["satoshi@example.com", "_@sub.example.com"] {
+            let fields = ProfileFields {
+                lud16: Some(lud16.to_string()),
+                ..get_valid_fields()
+            };
+            assert!(fields.validate().is_ok(), "{lud16} should be valid");
+        }
+
+        for lud16 in [
+            "",
+            "satoshi",
+            "@example.com",
+            "satoshi@",
+            "satoshi@example",
+            "satoshi@.com",
+            "sat oshi@example.com",
+            "satoshi@example@example.com",
+        ] {
+            let fields = ProfileFields {
+                lud16: Some(lud16.to_string()),
+                ..get_valid_fields()
+            };
+            assert!(fields.validate().is_err(), "{lud16} should be invalid");
+        }
+    }
+}
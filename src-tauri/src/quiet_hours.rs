@@ -0,0 +1,129 @@
+use chrono::{NaiveTime, Timelike};
+use serde::{Deserialize, Serialize};
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// What happens to requests that arrive during quiet hours.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuietHoursAction {
+    /// Requests are rejected without prompting the user.
+    Reject,
+
+    /// The user is still prompted, but has to unlock Keystache before the request can be approved.
+    RequireUnlock,
+}
+
+/// A daily window, in local time, during which requests to sign events or pay invoices are held back.
+/// Times are minutes since midnight. If `start_minute` is after `end_minute`, the window spans midnight.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuietHours {
+    /// Start of the window (inclusive).
+    pub start_minute: u16,
+
+    /// End of the window (exclusive).
+    pub end_minute: u16,
+
+    pub action: QuietHoursAction,
+}
+
+impl QuietHours {
+    /// Errors if either time isn't within a day, or if the window is empty.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.start_minute >= MINUTES_PER_DAY || self.end_minute >= MINUTES_PER_DAY {
+            return Err(anyhow::anyhow!(
+                "Quiet hours must start and end between 00:00 and 23:59"
+            ));
+        }
+
+        if self.start_minute == self.end_minute {
+            return Err(anyhow::anyhow!(
+                "Quiet hours can't start and end at the same time"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Whether `time` falls within the window.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        // Seconds are ignored, so 22:00:59 counts as 22:00.
+        let minute = (time.hour() * 60 + time.minute()) as u16;
+        if self.start_minute <= self.end_minute {
+            self.start_minute <= minute && minute < self.end_minute
+        } else {
+            self.start_minute <= minute || minute < self.end_minute
+        }
+    }
+
+    /// The action to take for a request that arrives at `time`, or `None` if it's outside the window.
+    pub fn action_at(&self, time: NaiveTime) -> Option<QuietHoursAction> {
+        if self.contains(time) {
+            Some(self.action)
+        } else {
+            None
+        }
+    }
+}
+
+/// The current local time of day. Quiet hours are always checked against the user's own clock.
+pub fn local_time() -> NaiveTime {
+    chrono::Local::now().time()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn window_spanning_midnight() {
+        let quiet_hours = QuietHours {
+            start_minute: 22 * 60,
+            end_minute: 7 * 60,
+            action: QuietHoursAction::Reject,
+        };
+
+        assert!(quiet_hours.contains(at(22, 0)));
+        assert!(quiet_hours.contains(at(0, 0)));
+        assert!(quiet_hours.contains(at(6, 59)));
+        assert!(!quiet_hours.contains(at(7, 0)));
+        assert!(!quiet_hours.contains(at(12, 0)));
+        assert!(!quiet_hours.contains(at(21, 59)));
+    }
+
+    #[test]
+    fn window_within_a_day() {
+        let quiet_hours = QuietHours {
+            start_minute: 9 * 60,
+            end_minute: 17 * 60,
+            action: QuietHoursAction::RequireUnlock,
+        };
+
+        assert_eq!(
+            quiet_hours.action_at(at(9, 0)),
+            Some(QuietHoursAction::RequireUnlock)
+        );
+        assert_eq!(quiet_hours.action_at(at(17, 0)), None);
+        assert_eq!(quiet_hours.action_at(at(3, 0)), None);
+    }
+
+    #[test]
+    fn validate_rejects_invalid_windows() {
+        let mut quiet_hours = QuietHours {
+            start_minute: 22 * 60,
+            end_minute: 7 * 60,
+            action: QuietHoursAction::Reject,
+        };
+        assert!(quiet_hours.validate().is_ok());
+
+        quiet_hours.end_minute = MINUTES_PER_DAY;
+        assert!(quiet_hours.validate().is_err());
+
+        quiet_hours.end_minute = quiet_hours.start_minute;
+        assert!(quiet_hours.validate().is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use crate::relays::RelayPolicy;
+use crate::validation;
+use serde::{Deserialize, Serialize};
+
+/// Which relays apps are shown when they ask for an account's relays. Private relays (e.g. a personal relay) are
+/// only shown to trusted apps. Every other relay is public, and unknown apps only see public relays.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelayVisibilityPolicy {
+    /// URLs of relays that only trusted apps are shown.
+    pub private_relays: Vec<String>,
+
+    /// npubs of the apps that are shown private relays.
+    pub trusted_apps: Vec<String>,
+}
+
+impl RelayVisibilityPolicy {
+    /// Checks that every relay URL and app npub is well-formed.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for url in &self.private_relays {
+            validation::normalize_relay_url(url)?;
+        }
+        for app_npub in &self.trusted_apps {
+            validation::validate_npub(app_npub)?;
+        }
+        Ok(())
+    }
+
+    /// Filters an account's relays down to the ones that the app with `app_npub` may see.
+    /// `None` means the relays are for the user themselves, who sees every relay.
+    pub fn visible_relays(
+        &self,
+        relays: Vec<(String, RelayPolicy)>,
+        app_npub: Option<&str>,
+    ) -> Vec<(String, RelayPolicy)> {
+        let trusted = app_npub.map_or(true, |app_npub| {
+            self.trusted_apps
+                .iter()
+                .any(|trusted_app| trusted_app == app_npub)
+        });
+        if trusted {
+            return relays;
+        }
+        relays
+            .into_iter()
+            .filter(|(url, _)| !self.private_relays.contains(url))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{Keys, ToBech32};
+
+    #[test]
+    fn only_trusted_apps_see_private_relays() {
+        let trusted_app = Keys::generate().public_key().to_bech32().unwrap();
+        let untrusted_app = Keys::generate().public_key().to_bech32().unwrap();
+        let policy = RelayVisibilityPolicy {
+            private_relays: vec!["wss://private.example.com".to_string()],
+            trusted_apps: vec![trusted_app.clone()],
+        };
+        policy.validate().unwrap();
+
+        let relay_policy = RelayPolicy {
+            read: true,
+            write: true,
+        };
+        let relays = vec![
+            ("wss://public.example.com".to_string(), relay_policy),
+            ("wss://private.example.com".to_string(), relay_policy),
+        ];
+
+        assert_eq!(
+            policy.visible_relays(relays.clone(), Some(&trusted_app)),
+            relays
+        );
+        assert_eq!(
+            policy.visible_relays(relays.clone(), Some(&untrusted_app)),
+            vec![("wss://public.example.com".to_string(), relay_policy)]
+        );
+        assert_eq!(policy.visible_relays(relays.clone(), None), relays);
+
+        // With the default policy, every relay is public.
+        assert_eq!(
+            RelayVisibilityPolicy::default().visible_relays(relays.clone(), Some(&untrusted_app)),
+            relays
+        );
+
+        let invalid = RelayVisibilityPolicy {
+            private_relays: vec!["https://private.example.com".to_string()],
+            trusted_apps: Vec::new(),
+        };
+        assert!(invalid.validate().is_err());
+    }
+}
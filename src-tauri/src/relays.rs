@@ -0,0 +1,1026 @@
+use futures::stream::{FuturesUnordered, StreamExt};
+use nostr_sdk::pool::relay::{Error as RelayError, RelayNotification};
+use nostr_sdk::{
+    ClientMessage, Event, EventBuilder, Filter, FilterOptions, Keys, Relay, RelayMessage,
+    RelaySendOptions, Tag, Timestamp, Url,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// How long to wait for a relay to connect and respond before giving up on it.
+pub const DEFAULT_RELAY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Prefix of the `OK` message that relays reject events with until the client authenticates (NIP-42).
+const AUTH_REQUIRED_PREFIX: &str = "auth-required:";
+
+/// How long a signed NIP-42 auth event is reused for. Relays typically accept auth events created within the last ten minutes.
+const AUTH_EVENT_VALIDITY: Duration = Duration::from_secs(5 * 60);
+
+/// Keys to authenticate to relays with (NIP-42), and where to cache the auth events signed with them.
+#[derive(Clone, Copy)]
+pub struct RelayAuth<'a> {
+    pub keys: &'a Keys,
+    pub auth_event_cache: &'a AuthEventCache,
+}
+
+/// The most recently signed NIP-42 auth event for each relay, so that reconnecting to a relay
+/// that presents the same challenge doesn't mean signing a new auth event every time.
+#[derive(Default)]
+pub struct AuthEventCache {
+    /// Map of relay URLs to the last auth event signed for them.
+    auth_events: Mutex<HashMap<String, Event>>,
+}
+
+impl AuthEventCache {
+    /// Returns the cached auth event for the relay if it answers `challenge`, was signed by `keys`,
+    /// and is still fresh at `now`. Otherwise signs a new one and caches it in place of the old one.
+    pub fn get_or_sign(
+        &self,
+        relay_url: &Url,
+        challenge: &str,
+        keys: &Keys,
+        now: Timestamp,
+    ) -> anyhow::Result<Event> {
+        let mut auth_events = self.auth_events.lock().unwrap();
+
+        if let Some(auth_event) = auth_events.get(relay_url.as_str()) {
+            let age_secs = now.as_u64().checked_sub(auth_event.created_at().as_u64());
+            let is_fresh =
+                age_secs.is_some_and(|age_secs| age_secs < AUTH_EVENT_VALIDITY.as_secs());
+            if is_fresh
+                && auth_event.author() == keys.public_key()
+                && auth_event
+                    .tags()
+                    .contains(&Tag::Challenge(challenge.to_string()))
+            {
+                return Ok(auth_event.clone());
+            }
+        }
+
+        let auth_event = EventBuilder::auth(challenge, relay_url.clone())
+            .custom_created_at(now)
+            .to_event(keys)?;
+        auth_events.insert(relay_url.to_string(), auth_event.clone());
+        Ok(auth_event)
+    }
+
+    /// Forgets the cached auth event for a relay, e.g. because the relay refused it.
+    pub fn evict(&self, relay_url: &Url) {
+        self.auth_events.lock().unwrap().remove(relay_url.as_str());
+    }
+}
+
+/// How an account uses a relay.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelayPolicy {
+    /// Whether events are fetched from the relay.
+    pub read: bool,
+
+    /// Whether events are published to the relay.
+    pub write: bool,
+}
+
+/// Relay entries of an account that all point to the same relay, e.g. `wss://relay.example.com` and
+/// `wss://Relay.example.com:443/`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DuplicateRelays {
+    /// The URL that every entry normalizes to.
+    pub normalized_url: String,
+
+    /// The entries as they're stored, in the order they were given.
+    pub urls: Vec<String>,
+}
+
+/// Returns the form that relay URLs are stored in, so that equivalent URLs are only stored once. The host is
+/// lowercased and the default port is dropped by [`Url`] itself, and trailing slashes are dropped here.
+pub fn normalize_relay_url(url: &Url) -> String {
+    let mut url = url.clone();
+    let path = url.path().trim_end_matches('/').to_string();
+    url.set_path(&path);
+    let normalized = url.to_string();
+    // `Url` always gives a URL with no path a `/` path.
+    match normalized.strip_suffix('/') {
+        Some(stripped) if url.query().is_none() && url.fragment().is_none() => stripped.to_string(),
+        _ => normalized,
+    }
+}
+
+/// Groups relay URLs that normalize to the same URL. URLs that don't parse are only grouped with identical URLs.
+/// Only groups with more than one URL are returned, in the order their first URL was given.
+pub fn find_duplicate_relays(urls: &[String]) -> Vec<DuplicateRelays> {
+    let mut groups: Vec<DuplicateRelays> = Vec::new();
+    for url in urls {
+        let normalized_url = Url::parse(url.trim())
+            .map(|parsed| normalize_relay_url(&parsed))
+            .unwrap_or_else(|_| url.trim().to_string());
+        match groups
+            .iter_mut()
+            .find(|group| group.normalized_url == normalized_url)
+        {
+            Some(group) => group.urls.push(url.clone()),
+            None => groups.push(DuplicateRelays {
+                normalized_url,
+                urls: vec![url.clone()],
+            }),
+        }
+    }
+    groups.retain(|group| group.urls.len() > 1);
+    groups
+}
+
+/// Outcome of publishing an event to a single relay.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RelayPublishResult {
+    /// The URL of the relay.
+    pub url: String,
+
+    /// Whether the relay accepted the event.
+    pub accepted: bool,
+
+    /// The reason given in the relay's `OK` message (e.g. `blocked: spam`), or a description
+    /// of what went wrong if the relay never responded with an `OK` message.
+    pub message: String,
+}
+
+/// Whether a single relay could be connected to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RelayReachability {
+    /// The URL of the relay.
+    pub url: String,
+
+    /// Whether a connection to the relay was established within the timeout.
+    pub reachable: bool,
+}
+
+/// How many of an account's relays could be connected to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RelayReachabilityReport {
+    /// Number of relays that were reachable. Zero if every relay is down, or there are no relays to check.
+    pub reachable_count: usize,
+
+    /// Each relay checked, in the order they were given.
+    pub relays: Vec<RelayReachability>,
+}
+
+/// Tries to connect to each of the given relays concurrently, and reports which of them connected within `timeout`.
+/// Nothing is sent to the relays, so this never fails. Relays that can't be reached are reported as such.
+pub async fn check_relay_reachability(
+    relay_urls: &[String],
+    timeout: Duration,
+) -> RelayReachabilityReport {
+    let relays: Vec<RelayReachability> =
+        futures::future::join_all(relay_urls.iter().map(|relay_url| async move {
+            RelayReachability {
+                url: relay_url.clone(),
+                reachable: is_relay_reachable(relay_url, timeout).await,
+            }
+        }))
+        .await;
+
+    RelayReachabilityReport {
+        reachable_count: relays.iter().filter(|relay| relay.reachable).count(),
+        relays,
+    }
+}
+
+async fn is_relay_reachable(relay_url: &str, timeout: Duration) -> bool {
+    let url = match Url::parse(relay_url) {
+        Ok(url) => url,
+        Err(_) => return false,
+    };
+
+    let relay = Relay::new(url);
+    relay.connect(Some(timeout)).await;
+    let reachable = relay.is_connected().await;
+    let _ = relay.terminate().await;
+    reachable
+}
+
+/// Outcome of testing whether a relay is usable, before it's added to an account.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RelayTestResult {
+    /// The relay accepted a connection and answered a subscription with `EOSE`.
+    Connected,
+
+    /// The relay accepted a connection, but didn't answer the subscription within the timeout.
+    TimedOut,
+
+    /// The relay couldn't be connected to, or the subscription failed.
+    Error { message: String },
+}
+
+/// Connects to a relay and sends it a trivial subscription, to check that it's usable before saving it.
+/// Waits up to `timeout` each for the connection and for the relay's `EOSE`. Nothing is published.
+pub async fn test_relay(relay_url: &str, timeout: Duration) -> RelayTestResult {
+    let url = match Url::parse(relay_url) {
+        Ok(url) => url,
+        Err(_) => {
+            return RelayTestResult::Error {
+                message: "invalid relay URL".to_string(),
+            }
+        }
+    };
+
+    let relay = Relay::new(url);
+    relay.connect(Some(timeout)).await;
+
+    let result = if relay.is_connected().await {
+        match relay
+            .get_events_of(
+                vec![Filter::new().limit(1)],
+                timeout,
+                FilterOptions::ExitOnEOSE,
+            )
+            .await
+        {
+            Ok(_) => RelayTestResult::Connected,
+            Err(RelayError::Timeout) => RelayTestResult::TimedOut,
+            Err(err) => RelayTestResult::Error {
+                message: err.to_string(),
+            },
+        }
+    } else {
+        RelayTestResult::Error {
+            message: "could not connect to relay".to_string(),
+        }
+    };
+
+    let _ = relay.terminate().await;
+    result
+}
+
+/// Publishes an event to each of the given relays concurrently and reports how each relay responded.
+/// Results are in the same order as `relay_urls`.
+///
+/// If `auth` is provided, relays that reject the event until the client authenticates are sent
+/// a NIP-42 auth event signed with `auth.keys`, and the event is published again once they accept it.
+pub async fn publish_event(
+    relay_urls: &[String],
+    event: &Event,
+    auth: Option<RelayAuth<'_>>,
+    timeout: Duration,
+) -> Vec<RelayPublishResult> {
+    futures::future::join_all(
+        relay_urls
+            .iter()
+            .map(|relay_url| publish_event_to_relay(relay_url, event.clone(), auth, timeout)),
+    )
+    .await
+}
+
+/// Publishes a NIP-70 protected event, which only its author should publish, to the relays in `relay_urls`
+/// that are also in `own_relay_urls`. Every other relay is reported as not accepting the event, without being contacted.
+/// Results are in the same order as `relay_urls`.
+pub async fn publish_protected_event(
+    relay_urls: &[String],
+    own_relay_urls: &[String],
+    event: &Event,
+    auth: Option<RelayAuth<'_>>,
+    timeout: Duration,
+) -> Vec<RelayPublishResult> {
+    let (own, other): (Vec<String>, Vec<String>) = relay_urls
+        .iter()
+        .cloned()
+        .partition(|relay_url| own_relay_urls.contains(relay_url));
+    let mut own_results = publish_event(&own, event, auth, timeout).await.into_iter();
+
+    relay_urls
+        .iter()
+        .map(|relay_url| {
+            if other.contains(relay_url) {
+                RelayPublishResult {
+                    url: relay_url.clone(),
+                    accepted: false,
+                    message: "Not published: the event is protected and this isn't one of the author's relays"
+                        .to_string(),
+                }
+            } else {
+                own_results
+                    .next()
+                    .expect("Every own relay has a publish result")
+            }
+        })
+        .collect()
+}
+
+/// Queries the given relays concurrently and returns the events from the first relay to respond with
+/// at least one validly signed event matching `filter`, or an empty list if every relay that responded
+/// had none. Only fails if every relay fails (i.e. can't be reached, doesn't respond within `timeout`, or errors).
+pub async fn query_relays_first_ok(
+    relay_urls: &[String],
+    filter: Filter,
+    timeout: Duration,
+) -> anyhow::Result<Vec<Event>> {
+    // Each query runs in its own task so that relays that are still being queried once
+    // a result is found run to completion and disconnect, rather than being dropped mid-query.
+    let mut queries: FuturesUnordered<_> = relay_urls
+        .iter()
+        .map(|relay_url| {
+            tokio::spawn(fetch_events_from_relay(
+                relay_url.clone(),
+                filter.clone(),
+                timeout,
+            ))
+        })
+        .collect();
+
+    let mut any_relay_responded = relay_urls.is_empty();
+    let mut last_error = None;
+    while let Some(result) = queries.next().await {
+        match result
+            .map_err(anyhow::Error::from)
+            .and_then(|result| result)
+        {
+            Ok(events) if !events.is_empty() => return Ok(events),
+            Ok(_) => any_relay_responded = true,
+            Err(err) => last_error = Some(err),
+        }
+    }
+
+    match last_error {
+        Some(err) if !any_relay_responded => {
+            Err(err.context("Every relay failed to respond to the query"))
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+async fn fetch_events_from_relay(
+    relay_url: String,
+    filter: Filter,
+    timeout: Duration,
+) -> anyhow::Result<Vec<Event>> {
+    let relay = Relay::new(Url::parse(&relay_url)?);
+    relay.connect(Some(timeout)).await;
+
+    let result = relay
+        .get_events_of(vec![filter], timeout, FilterOptions::ExitOnEOSE)
+        .await;
+
+    let _ = relay.terminate().await;
+
+    // Events from a relay are untrusted, so only keep the ones that are validly signed.
+    Ok(result?
+        .into_iter()
+        .filter(|event| event.verify().is_ok())
+        .collect())
+}
+
+async fn publish_event_to_relay(
+    relay_url: &str,
+    event: Event,
+    auth: Option<RelayAuth<'_>>,
+    timeout: Duration,
+) -> RelayPublishResult {
+    let (accepted, message) = match send_event(relay_url, event, auth, timeout).await {
+        Ok(()) => (true, String::new()),
+        Err(err) => (false, err),
+    };
+
+    RelayPublishResult {
+        url: relay_url.to_string(),
+        accepted,
+        message,
+    }
+}
+
+async fn send_event(
+    relay_url: &str,
+    event: Event,
+    auth: Option<RelayAuth<'_>>,
+    timeout: Duration,
+) -> Result<(), String> {
+    let url = Url::parse(relay_url).map_err(|_| "invalid relay URL".to_string())?;
+
+    let relay = Relay::new(url);
+    // Listen before connecting so that an `AUTH` challenge sent as soon as we connect isn't missed.
+    let mut notifications = relay.notifications();
+    relay.connect(Some(timeout)).await;
+
+    let result = if relay.is_connected().await {
+        match (
+            send_event_to_connected_relay(&relay, event.clone(), timeout).await,
+            auth,
+        ) {
+            (Err(message), Some(auth)) if message.starts_with(AUTH_REQUIRED_PREFIX) => {
+                match authenticate(&relay, &mut notifications, auth, timeout).await {
+                    Ok(()) => send_event_to_connected_relay(&relay, event, timeout).await,
+                    Err(auth_error) => Err(format!("{message} ({auth_error})")),
+                }
+            }
+            (result, _) => result,
+        }
+    } else {
+        Err("could not connect to relay".to_string())
+    };
+
+    let _ = relay.terminate().await;
+
+    result
+}
+
+async fn send_event_to_connected_relay(
+    relay: &Relay,
+    event: Event,
+    timeout: Duration,
+) -> Result<(), String> {
+    relay
+        .send_event(event, RelaySendOptions::new().timeout(Some(timeout)))
+        .await
+        .map(|_| ())
+        .map_err(|err| match err {
+            RelayError::EventNotPublished(message) => message,
+            RelayError::Timeout | RelayError::RecvTimeout => {
+                "timed out waiting for relay to respond".to_string()
+            }
+            err => err.to_string(),
+        })
+}
+
+/// Answers the relay's latest NIP-42 `AUTH` challenge (waiting for one if it hasn't sent any yet)
+/// with an auth event signed by `auth.keys`, and waits for the relay to accept it.
+/// Reuses the relay's cached auth event if it's still valid for the challenge.
+///
+/// Auth events are signed without asking the user, since they only prove to a relay that the user
+/// controls the key that they're already publishing with.
+async fn authenticate(
+    relay: &Relay,
+    notifications: &mut broadcast::Receiver<RelayNotification>,
+    auth: RelayAuth<'_>,
+    timeout: Duration,
+) -> Result<(), String> {
+    let relay_url = relay.url();
+    let handshake = async {
+        let mut challenge = None;
+        while let Ok(notification) = notifications.try_recv() {
+            if let Some(RelayMessage::Auth { challenge: latest }) = relay_message(notification) {
+                challenge = Some(latest);
+            }
+        }
+        let challenge = match challenge {
+            Some(challenge) => challenge,
+            None => loop {
+                if let RelayMessage::Auth { challenge } = next_relay_message(notifications).await? {
+                    break challenge;
+                }
+            },
+        };
+
+        let auth_event = auth
+            .auth_event_cache
+            .get_or_sign(&relay_url, &challenge, auth.keys, Timestamp::now())
+            .map_err(|_| "error signing auth event".to_string())?;
+        let auth_event_id = auth_event.id;
+        relay
+            .send_msg(ClientMessage::auth(auth_event), RelaySendOptions::new())
+            .await
+            .map_err(|err| err.to_string())?;
+
+        loop {
+            if let RelayMessage::Ok {
+                event_id,
+                status,
+                message,
+            } = next_relay_message(notifications).await?
+            {
+                if event_id == auth_event_id {
+                    if !status {
+                        auth.auth_event_cache.evict(&relay_url);
+                    }
+                    break if status { Ok(()) } else { Err(message) };
+                }
+            }
+        }
+    };
+
+    tokio::time::timeout(timeout, handshake)
+        .await
+        .unwrap_or_else(|_| Err("timed out waiting for relay to accept authentication".to_string()))
+}
+
+/// Waits for the next message from the relay, skipping any other notifications.
+async fn next_relay_message(
+    notifications: &mut broadcast::Receiver<RelayNotification>,
+) -> Result<RelayMessage, String> {
+    loop {
+        match notifications.recv().await {
+            Ok(notification) => {
+                if let Some(message) = relay_message(notification) {
+                    return Ok(message);
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => {
+                return Err("relay disconnected".to_string())
+            }
+        }
+    }
+}
+
+fn relay_message(notification: RelayNotification) -> Option<RelayMessage> {
+    match notification {
+        RelayNotification::Message { message } => Some(message),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_relay::{unreachable_relay_url, MockRelay};
+    use nostr_sdk::Kind;
+    use std::sync::{Arc, Mutex};
+
+    fn get_signed_event() -> Event {
+        EventBuilder::new(Kind::TextNote, "hello world", None)
+            .to_event(&Keys::generate())
+            .unwrap()
+    }
+
+    /// Starts a relay that responds to every subscription with the given events.
+    async fn start_relay_with_events(events: Vec<Event>) -> MockRelay {
+        MockRelay::start(move |message| match message {
+            ClientMessage::Req {
+                subscription_id, ..
+            } => {
+                let mut messages: Vec<RelayMessage> = events
+                    .iter()
+                    .map(|event| RelayMessage::event(subscription_id.clone(), event.clone()))
+                    .collect();
+                messages.push(RelayMessage::eose(subscription_id));
+                messages
+            }
+            _ => Vec::new(),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn query_relays_first_ok_skips_relay_that_times_out() {
+        let keys = Keys::generate();
+        let event = EventBuilder::new(Kind::Metadata, "{}", None)
+            .to_event(&keys)
+            .unwrap();
+
+        // This relay never responds, so the query to it times out.
+        let silent_relay = MockRelay::start(|_| Vec::new()).await;
+        let relay = start_relay_with_events(vec![event.clone()]).await;
+
+        let events = query_relays_first_ok(
+            &[silent_relay.url(), relay.url()],
+            Filter::new().author(keys.public_key()).kind(Kind::Metadata),
+            Duration::from_secs(2),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(events, vec![event]);
+    }
+
+    #[tokio::test]
+    async fn query_relays_first_ok_waits_for_relay_with_events() {
+        let keys = Keys::generate();
+        let event = EventBuilder::new(Kind::Metadata, "{}", None)
+            .to_event(&keys)
+            .unwrap();
+
+        let empty_relay = start_relay_with_events(Vec::new()).await;
+        let relay = start_relay_with_events(vec![event.clone()]).await;
+        let unreachable_url = unreachable_relay_url().await;
+
+        let events = query_relays_first_ok(
+            &[empty_relay.url(), unreachable_url, relay.url()],
+            Filter::new(),
+            Duration::from_secs(2),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(events, vec![event]);
+    }
+
+    #[tokio::test]
+    async fn query_relays_first_ok_ignores_invalid_events() {
+        let event = EventBuilder::new(Kind::Metadata, "{}", None)
+            .to_event(&Keys::generate())
+            .unwrap();
+        let mut event_json = serde_json::to_value(&event).unwrap();
+        event_json["content"] = "tampered".into();
+        let event: Event = serde_json::from_value(event_json).unwrap();
+
+        let relay = start_relay_with_events(vec![event]).await;
+
+        let events = query_relays_first_ok(&[relay.url()], Filter::new(), Duration::from_secs(2))
+            .await
+            .unwrap();
+
+        assert!(events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn query_relays_first_ok_with_no_matching_events() {
+        let relay = start_relay_with_events(Vec::new()).await;
+        let unreachable_url = unreachable_relay_url().await;
+
+        // One relay failing shouldn't fail the query if another responds.
+        let events = query_relays_first_ok(
+            &[relay.url(), unreachable_url],
+            Filter::new(),
+            Duration::from_secs(2),
+        )
+        .await
+        .unwrap();
+
+        assert!(events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn query_relays_first_ok_all_relays_fail() {
+        let silent_relay = MockRelay::start(|_| Vec::new()).await;
+        let unreachable_url = unreachable_relay_url().await;
+
+        assert!(query_relays_first_ok(
+            &[silent_relay.url(), unreachable_url, "not a url".to_string()],
+            Filter::new(),
+            Duration::from_secs(1),
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn publish_event_captures_rejection_reason() {
+        let relay = MockRelay::start(|message| match message {
+            ClientMessage::Event(event) => vec![RelayMessage::ok(event.id, false, "blocked: spam")],
+            _ => Vec::new(),
+        })
+        .await;
+
+        let results = publish_event(
+            &[relay.url()],
+            &get_signed_event(),
+            None,
+            Duration::from_secs(5),
+        )
+        .await;
+
+        assert_eq!(
+            results,
+            vec![RelayPublishResult {
+                url: relay.url(),
+                accepted: false,
+                message: "blocked: spam".to_string(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn publish_event_reports_each_relay() {
+        let accepting_relay = MockRelay::start(|message| match message {
+            ClientMessage::Event(event) => vec![RelayMessage::ok(event.id, true, "")],
+            _ => Vec::new(),
+        })
+        .await;
+        let rejecting_relay = MockRelay::start(|message| match message {
+            ClientMessage::Event(event) => {
+                vec![RelayMessage::ok(
+                    event.id,
+                    false,
+                    "auth-required: please authenticate",
+                )]
+            }
+            _ => Vec::new(),
+        })
+        .await;
+        let unreachable_url = unreachable_relay_url().await;
+
+        let results = publish_event(
+            &[
+                accepting_relay.url(),
+                rejecting_relay.url(),
+                unreachable_url.clone(),
+            ],
+            &get_signed_event(),
+            None,
+            Duration::from_secs(2),
+        )
+        .await;
+
+        assert_eq!(results.len(), 3);
+
+        assert_eq!(results[0].url, accepting_relay.url());
+        assert!(results[0].accepted);
+
+        assert_eq!(results[1].url, rejecting_relay.url());
+        assert!(!results[1].accepted);
+        assert_eq!(results[1].message, "auth-required: please authenticate");
+
+        // An unreachable relay should be reported as such rather than failing the whole publish.
+        assert_eq!(results[2].url, unreachable_url);
+        assert!(!results[2].accepted);
+        assert!(!results[2].message.is_empty());
+    }
+
+    #[tokio::test]
+    async fn publish_event_authenticates_when_relay_requires_auth() {
+        const CHALLENGE: &str = "challenge-string";
+
+        // The public key that the client has authenticated as, if any.
+        let authenticated_public_key = Arc::new(Mutex::new(None));
+
+        let relay = {
+            let authenticated_public_key = authenticated_public_key.clone();
+            MockRelay::start(move |message| match message {
+                ClientMessage::Event(event) => {
+                    if authenticated_public_key.lock().unwrap().is_some() {
+                        vec![RelayMessage::ok(event.id, true, "")]
+                    } else {
+                        vec![
+                            RelayMessage::auth(CHALLENGE),
+                            RelayMessage::ok(
+                                event.id,
+                                false,
+                                "auth-required: we only accept events from authenticated users",
+                            ),
+                        ]
+                    }
+                }
+                ClientMessage::Auth(auth_event) => {
+                    let is_valid = auth_event.verify().is_ok()
+                        && auth_event.kind() == Kind::Authentication
+                        && auth_event
+                            .tags()
+                            .contains(&Tag::Challenge(CHALLENGE.to_string()));
+                    if is_valid {
+                        *authenticated_public_key.lock().unwrap() = Some(auth_event.author());
+                    }
+                    vec![RelayMessage::ok(auth_event.id, is_valid, "")]
+                }
+                _ => Vec::new(),
+            })
+            .await
+        };
+
+        let keys = Keys::generate();
+        let event = EventBuilder::new(Kind::TextNote, "hello world", None)
+            .to_event(&keys)
+            .unwrap();
+
+        let auth_event_cache = AuthEventCache::default();
+        let results = publish_event(
+            &[relay.url()],
+            &event,
+            Some(RelayAuth {
+                keys: &keys,
+                auth_event_cache: &auth_event_cache,
+            }),
+            Duration::from_secs(5),
+        )
+        .await;
+
+        assert_eq!(
+            results,
+            vec![RelayPublishResult {
+                url: relay.url(),
+                accepted: true,
+                message: String::new(),
+            }]
+        );
+        assert_eq!(
+            *authenticated_public_key.lock().unwrap(),
+            Some(keys.public_key())
+        );
+    }
+
+    #[test]
+    fn auth_event_cache_reuses_fresh_auth_event() {
+        let cache = AuthEventCache::default();
+        let keys = Keys::generate();
+        let relay_url = Url::parse("wss://relay.example.com").unwrap();
+        let now = Timestamp::from(1_700_000_000);
+
+        let first = cache
+            .get_or_sign(&relay_url, "challenge", &keys, now)
+            .unwrap();
+        assert!(first.verify().is_ok());
+
+        // Reconnecting within the validity window reuses the same signed event.
+        let second = cache
+            .get_or_sign(
+                &relay_url,
+                "challenge",
+                &keys,
+                now + Duration::from_secs(60),
+            )
+            .unwrap();
+        assert_eq!(second, first);
+        let third = cache
+            .get_or_sign(
+                &relay_url,
+                "challenge",
+                &keys,
+                now + Duration::from_secs(4 * 60),
+            )
+            .unwrap();
+        assert_eq!(third, first);
+
+        // Once the event has expired, a new one is signed.
+        let expired_at = now + AUTH_EVENT_VALIDITY;
+        let fourth = cache
+            .get_or_sign(&relay_url, "challenge", &keys, expired_at)
+            .unwrap();
+        assert_ne!(fourth, first);
+        assert_eq!(fourth.created_at(), expired_at);
+    }
+
+    #[test]
+    fn auth_event_cache_re_signs_for_new_challenge_or_key() {
+        let cache = AuthEventCache::default();
+        let keys = Keys::generate();
+        let relay_url = Url::parse("wss://relay.example.com").unwrap();
+        let now = Timestamp::from(1_700_000_000);
+
+        let first = cache
+            .get_or_sign(&relay_url, "challenge", &keys, now)
+            .unwrap();
+
+        let new_challenge = cache
+            .get_or_sign(&relay_url, "new challenge", &keys, now)
+            .unwrap();
+        assert_ne!(new_challenge, first);
+        assert!(new_challenge
+            .tags()
+            .contains(&Tag::Challenge("new challenge".to_string())));
+
+        let other_keys = Keys::generate();
+        let new_key = cache
+            .get_or_sign(&relay_url, "new challenge", &other_keys, now)
+            .unwrap();
+        assert_eq!(new_key.author(), other_keys.public_key());
+
+        // Evicting forces a new event to be signed.
+        cache.evict(&relay_url);
+        assert_ne!(
+            cache
+                .get_or_sign(&relay_url, "new challenge", &other_keys, now)
+                .unwrap(),
+            new_key
+        );
+    }
+
+    #[tokio::test]
+    async fn publish_event_relay_never_responds() {
+        let relay = MockRelay::start(|_| Vec::new()).await;
+
+        let results = publish_event(
+            &[relay.url()],
+            &get_signed_event(),
+            None,
+            Duration::from_secs(1),
+        )
+        .await;
+
+        assert_eq!(results.len(), 1);
+        assert!(!results[0].accepted);
+        assert_eq!(results[0].message, "timed out waiting for relay to respond");
+    }
+
+    #[tokio::test]
+    async fn test_relay_reports_each_outcome() {
+        let relay = start_relay_with_events(Vec::new()).await;
+        assert_eq!(
+            test_relay(&relay.url(), Duration::from_secs(2)).await,
+            RelayTestResult::Connected
+        );
+
+        let silent_relay = MockRelay::start(|_| Vec::new()).await;
+        assert_eq!(
+            test_relay(&silent_relay.url(), Duration::from_secs(1)).await,
+            RelayTestResult::TimedOut
+        );
+
+        assert_eq!(
+            test_relay(&unreachable_relay_url().await, Duration::from_secs(1)).await,
+            RelayTestResult::Error {
+                message: "could not connect to relay".to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn check_relay_reachability_counts_reachable_relays() {
+        let relay = MockRelay::start(|_| Vec::new()).await;
+        let unreachable_url = unreachable_relay_url().await;
+
+        let report = check_relay_reachability(
+            &[relay.url(), unreachable_url.clone()],
+            Duration::from_secs(2),
+        )
+        .await;
+
+        assert_eq!(
+            report,
+            RelayReachabilityReport {
+                reachable_count: 1,
+                relays: vec![
+                    RelayReachability {
+                        url: relay.url(),
+                        reachable: true,
+                    },
+                    RelayReachability {
+                        url: unreachable_url.clone(),
+                        reachable: false,
+                    },
+                ],
+            }
+        );
+
+        // Every relay being down isn't an error.
+        let report = check_relay_reachability(
+            &[unreachable_url, "not a url".to_string()],
+            Duration::from_secs(1),
+        )
+        .await;
+        assert_eq!(report.reachable_count, 0);
+        assert_eq!(report.relays.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn publish_event_invalid_relay_url() {
+        let results = publish_event(
+            &["not a url".to_string()],
+            &get_signed_event(),
+            None,
+            Duration::from_secs(1),
+        )
+        .await;
+
+        assert_eq!(
+            results,
+            vec![RelayPublishResult {
+                url: "not a url".to_string(),
+                accepted: false,
+                message: "invalid relay URL".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn normalizes_relay_urls() {
+        for (url, normalized) in [
+            ("wss://relay.example.com", "wss://relay.example.com"),
+            ("wss://relay.example.com/", "wss://relay.example.com"),
+            ("wss://Relay.Example.com:443", "wss://relay.example.com"),
+            ("ws://relay.example.com:80/", "ws://relay.example.com"),
+            (
+                "wss://relay.example.com:4443/",
+                "wss://relay.example.com:4443",
+            ),
+            (
+                "wss://relay.example.com/path/",
+                "wss://relay.example.com/path",
+            ),
+            (
+                "wss://relay.example.com/?key=1",
+                "wss://relay.example.com/?key=1",
+            ),
+        ] {
+            assert_eq!(normalize_relay_url(&Url::parse(url).unwrap()), normalized);
+        }
+    }
+
+    #[test]
+    fn finds_equivalent_relay_urls() {
+        let urls = [
+            "wss://relay.example.com",
+            "wss://other.example.com",
+            "wss://RELAY.example.com:443/",
+            "not a url",
+            "not a url",
+            "wss://third.example.com",
+        ]
+        .map(str::to_string);
+
+        assert_eq!(
+            find_duplicate_relays(&urls),
+            vec![
+                DuplicateRelays {
+                    normalized_url: "wss://relay.example.com".to_string(),
+                    urls: vec![
+                        "wss://relay.example.com".to_string(),
+                        "wss://RELAY.example.com:443/".to_string(),
+                    ],
+                },
+                DuplicateRelays {
+                    normalized_url: "not a url".to_string(),
+                    urls: vec!["not a url".to_string(), "not a url".to_string()],
+                },
+            ]
+        );
+        assert!(find_duplicate_relays(&urls[..2]).is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+use async_trait::async_trait;
+use nostr_sdk::{Event, Kind, PublicKey, UnsignedEvent};
+use std::time::Duration;
+
+/// How long looking up the event that a parameterized replaceable event would replace can take. If the lookup
+/// takes longer, the user is asked to sign the event without being told whether it replaces one.
+pub const LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Somewhere to look up the current version of a parameterized replaceable event, e.g. the author's relays.
+#[async_trait]
+pub trait ReplaceableEventLookup: Send + Sync {
+    /// Returns the latest event by `author` of `kind` with the `d` tag `identifier`, or `None` if there isn't
+    /// one or it can't be looked up (e.g. in offline mode).
+    async fn latest_replaceable_event(
+        &self,
+        author: PublicKey,
+        kind: Kind,
+        identifier: &str,
+    ) -> anyhow::Result<Option<Event>>;
+}
+
+/// Returns the `d` tag that identifies a parameterized replaceable event (kinds 30000-39999), or `None` for
+/// events of other kinds. Events without a `d` tag are identified by the empty string (NIP-01).
+pub fn identifier(event: &UnsignedEvent) -> Option<String> {
+    if !event.kind.is_parameterized_replaceable() {
+        return None;
+    }
+    let identifier = event
+        .tags
+        .iter()
+        .map(|tag| tag.as_vec())
+        .find(|tag| tag.first().map(String::as_str) == Some("d"))
+        .and_then(|tag| tag.get(1).cloned())
+        .unwrap_or_default();
+    Some(identifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{EventBuilder, Keys, Tag};
+
+    #[test]
+    fn only_parameterized_replaceable_events_have_an_identifier() {
+        let public_key = Keys::generate().public_key();
+        let event = |kind: u64, tags: Vec<Tag>| {
+            EventBuilder::new(Kind::from(kind), "", tags).to_unsigned_event(public_key)
+        };
+
+        assert_eq!(
+            identifier(&event(30023, vec![Tag::Identifier("post".to_string())])),
+            Some("post".to_string())
+        );
+        assert_eq!(identifier(&event(30023, vec![])), Some(String::new()));
+        assert_eq!(
+            identifier(&event(1, vec![Tag::Identifier("post".to_string())])),
+            None
+        );
+        assert_eq!(identifier(&event(10002, vec![])), None);
+    }
+}
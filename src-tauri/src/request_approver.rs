@@ -0,0 +1,3746 @@
+use async_trait::async_trait;
+use chrono::NaiveTime;
+use lightning_invoice::Bolt11Invoice;
+use nip_55::nip46::Nip46RequestApproval;
+use nip_55::KeyManager;
+use nostr_sdk::nips::nip46;
+use nostr_sdk::{
+    Event, EventId, JsonUtil, Keys, Kind, PublicKey, Timestamp, ToBech32, UnsignedEvent,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::account_stats::AccountActivityLog;
+use crate::app_key_allowlist::AppKeyAllowlist;
+use crate::approval_timeouts::ApprovalTimeouts;
+use crate::confirmation_phrase::{self, ConfirmationPhrasePolicy, HighRiskAction};
+use crate::dm::{self, DecryptDmRequestPayload};
+use crate::error_log;
+use crate::nip46_server::Nip46RequestHandler;
+use crate::payment_backend::{
+    self, PayInvoiceRequestPayload, PaymentBackend, PaymentResult, PAYMENT_TIMEOUT,
+};
+use crate::payment_cap::{MaxSinglePayment, OverCapAction, PayInvoiceError};
+use crate::payment_ledger::PaymentLedger;
+use crate::payment_log::{PaymentLog, PaymentLogEntry, PaymentOutcome};
+use crate::pow::{self, PowProgressPayload};
+use crate::quiet_hours::{self, QuietHours, QuietHoursAction};
+use crate::replaceable_event::{self, ReplaceableEventLookup};
+use crate::request_attention::{RequestAttention, RequestNotification};
+use crate::scam_list::ScamList;
+use crate::session_grants::{SessionGrantEntry, SessionGrants, StoredSessionGrant};
+use crate::sign_decisions::SignDecisionStore;
+use crate::sign_event_request::{
+    ContentWarningPolicy, SignEventRequestPayload, SignEventWarning, SignEventsRequestPayload,
+};
+use crate::signer::Signer;
+use crate::signing_log::{SigningLog, SigningLogEntry, SigningOutcome};
+
+/// Sends named events to the frontend.
+pub trait EventEmitter: Send + Sync {
+    fn emit(&self, event: &str, payload: serde_json::Value) -> anyhow::Result<()>;
+
+    /// Makes sure that there's a frontend to show requests to the user, e.g. by reopening a closed window, before
+    /// one is emitted. Errors (typically with [`NoApprovalWindowError`]) if there isn't one and can't be, since the
+    /// request would otherwise go unseen until it times out.
+    fn ensure_listener(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Draws the user's attention to a request that was just emitted, if the window isn't already focused: by
+    /// focusing the window if `focus_window` is set, and by showing `notification` if there is one.
+    fn request_attention(
+        &self,
+        _focus_window: bool,
+        _notification: Option<RequestNotification>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returned when a request can't be shown to the user, because no window is open and one couldn't be opened.
+#[derive(Debug, PartialEq, Eq)]
+pub struct NoApprovalWindowError {
+    pub reason: String,
+}
+
+impl std::fmt::Display for NoApprovalWindowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "No window is open to ask for approval in, and opening one failed: {}",
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for NoApprovalWindowError {}
+
+/// The user's response to a sign event request.
+struct SignEventResponse {
+    approval: Nip46RequestApproval,
+
+    /// The event as edited by the user, if they changed it before approving.
+    edited_event: Option<UnsignedEvent>,
+
+    /// Whether the user asked for their decision to be remembered for the app and the event's kind.
+    remember: bool,
+}
+
+/// What to show in a sign event request besides the event itself.
+#[derive(Default)]
+struct SignEventRequestOptions {
+    /// The relay hint that was added to the event, if any.
+    added_relay_hint: Option<String>,
+
+    /// The expiration that was added to the event, if any.
+    added_expiration: Option<Timestamp>,
+
+    /// Whether an edited event can be approved in place of the original.
+    edits_allowed: bool,
+
+    /// Leading zero bits that proof of work will be mined to once the user approves, if requested.
+    pow_difficulty: Option<u8>,
+
+    /// Whether to ask without looking anything up on relays, e.g. whether the event replaces one.
+    skip_relay_lookups: bool,
+}
+
+/// Kind of request that's waiting for the user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PendingRequestKind {
+    SignEvent,
+    SignEvents,
+    DecryptDm,
+    PayInvoice,
+}
+
+impl PendingRequestKind {
+    /// Name of the event that requests of this kind are emitted to the frontend as.
+    fn event_name(&self) -> &'static str {
+        match self {
+            Self::SignEvent => "sign_event_request",
+            Self::SignEvents => "sign_events_request",
+            Self::DecryptDm => "decrypt_dm_request",
+            Self::PayInvoice => "pay_invoice_request",
+        }
+    }
+}
+
+/// A request that's waiting for the user, as listed to the frontend so that it can rebuild its approval queue
+/// (e.g. after a reload).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PendingRequestEntry {
+    /// What the request is responded to by: the ID of the event or batch, or the invoice.
+    pub id: String,
+    pub kind: PendingRequestKind,
+
+    /// npub of the app that made the request, if known.
+    pub app_npub: Option<String>,
+
+    /// Unix timestamp of when the user was asked.
+    pub requested_at: u64,
+
+    /// The payload of the event that the request was emitted as.
+    pub payload: serde_json::Value,
+}
+
+/// A pending request's channel, and what the user was shown about the request once they've been asked.
+struct PendingEntry<T> {
+    tx: tokio::sync::oneshot::Sender<T>,
+
+    /// Tells apart requests with the same key, e.g. an app retrying a request that's still waiting. Only the
+    /// [`PendingRequest`] that inserted the entry removes it.
+    token: u64,
+
+    request: Option<PendingRequestEntry>,
+
+    /// Phrase the user must type to approve the request, if any.
+    confirmation_phrase: Option<&'static str>,
+}
+
+/// Map of pending requests to the channels that they're waiting on.
+type PendingMap<T> = std::sync::Mutex<HashMap<String, PendingEntry<T>>>;
+
+/// Removes the channel for a pending request, so that it can be responded to.
+/// The lock on `pending` is released before this returns, so it's never held while responding.
+fn take_pending<T>(pending: &PendingMap<T>, key: &str) -> Option<tokio::sync::oneshot::Sender<T>> {
+    pending.lock().unwrap().remove(key).map(|entry| entry.tx)
+}
+
+/// Like [`take_pending`], but if the request is being approved and needs a confirmation phrase, errors unless
+/// `typed_phrase` is that phrase. The request is then left pending, so that the user can try again.
+fn take_pending_confirmed<T>(
+    pending: &PendingMap<T>,
+    key: &str,
+    approved: bool,
+    typed_phrase: Option<&str>,
+) -> anyhow::Result<Option<tokio::sync::oneshot::Sender<T>>> {
+    let mut pending = pending.lock().unwrap();
+    if let (true, Some(entry)) = (approved, pending.get(key)) {
+        confirmation_phrase::check_phrase(entry.confirmation_phrase, typed_phrase)?;
+    }
+    Ok(pending.remove(key).map(|entry| entry.tx))
+}
+
+/// Lists the requests in `pending` that the user has been asked about.
+fn list_pending<T>(pending: &PendingMap<T>) -> Vec<PendingRequestEntry> {
+    pending
+        .lock()
+        .unwrap()
+        .values()
+        .filter_map(|entry| entry.request.clone())
+        .collect()
+}
+
+/// A request's entry in a [`PendingMap`], which is removed once this is dropped. It's held for as long as the
+/// request is waiting, so the entry goes away however the wait ends: with a response, a timeout, an error, or
+/// the waiting future being dropped because the client disconnected.
+///
+/// A request with the same key as one that's already waiting replaces it. The earlier request then stops waiting
+/// and is rejected, since its channel is dropped.
+struct PendingRequest<'a, T> {
+    pending: &'a PendingMap<T>,
+    key: String,
+    token: u64,
+}
+
+/// The token of the next [`PendingEntry`] inserted into any [`PendingMap`].
+static NEXT_PENDING_TOKEN: AtomicU64 = AtomicU64::new(0);
+
+impl<'a, T> PendingRequest<'a, T> {
+    /// Adds a request to `pending`, returning the receiver that its response is sent on.
+    fn insert(
+        pending: &'a PendingMap<T>,
+        key: String,
+    ) -> (Self, tokio::sync::oneshot::Receiver<T>) {
+        Self::insert_with_confirmation_phrase(pending, key, None)
+    }
+
+    /// Like [`Self::insert`], but the request can only be approved by typing `confirmation_phrase`, if it's set.
+    fn insert_with_confirmation_phrase(
+        pending: &'a PendingMap<T>,
+        key: String,
+        confirmation_phrase: Option<&'static str>,
+    ) -> (Self, tokio::sync::oneshot::Receiver<T>) {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let token = NEXT_PENDING_TOKEN.fetch_add(1, Ordering::Relaxed);
+        pending.lock().unwrap().insert(
+            key.clone(),
+            PendingEntry {
+                tx,
+                token,
+                request: None,
+                confirmation_phrase,
+            },
+        );
+        (
+            Self {
+                pending,
+                key,
+                token,
+            },
+            rx,
+        )
+    }
+
+    /// Asks the user about the request by emitting `payload` to the frontend, and records it so that it's listed
+    /// until it stops waiting.
+    fn emit(
+        &self,
+        event_emitter: &dyn EventEmitter,
+        kind: PendingRequestKind,
+        app_npub: Option<String>,
+        payload: impl Serialize,
+    ) -> anyhow::Result<()> {
+        event_emitter.ensure_listener()?;
+        let payload = serde_json::to_value(payload)?;
+        if let Some(entry) = self
+            .pending
+            .lock()
+            .unwrap()
+            .get_mut(&self.key)
+            .filter(|entry| entry.token == self.token)
+        {
+            entry.request = Some(PendingRequestEntry {
+                id: self.key.clone(),
+                kind,
+                app_npub,
+                requested_at: Timestamp::now().as_u64(),
+                payload: payload.clone(),
+            });
+        }
+        event_emitter.emit(kind.event_name(), payload)
+    }
+}
+
+impl<T> Drop for PendingRequest<'_, T> {
+    fn drop(&mut self) {
+        // Already removed if the request was responded to, or replaced if it was retried while waiting.
+        let mut pending = self.pending.lock().unwrap();
+        if pending
+            .get(&self.key)
+            .is_some_and(|entry| entry.token == self.token)
+        {
+            pending.remove(&self.key);
+        }
+    }
+}
+
+/// The `in_progress_*` maps are only ever locked to insert or remove a request's channel. Their locks are
+/// never held across an `.await`, so waiting for the user or emitting to the frontend can't block other requests.
+/// Entries for requests awaiting the user are held by a [`PendingRequest`], which removes them once the request
+/// stops waiting.
+pub struct KeystacheRequestApprover {
+    /// Map of hex-encoded event IDs to channels for signaling when the signing of an event has been approved/rejected.
+    in_progress_event_signings: PendingMap<SignEventResponse>,
+
+    /// Map of batch IDs to channels for signaling when the signing of a batch of events has been approved/rejected.
+    in_progress_batch_signings: PendingMap<Nip46RequestApproval>,
+
+    /// Map of hex-encoded sign event request IDs to flags that cancel mining proof of work for the request.
+    in_progress_pow_minings: Mutex<HashMap<String, Arc<AtomicBool>>>,
+
+    /// ID given to the next batch of events to be signed.
+    next_batch_id: AtomicU64,
+
+    /// Map of hex-encoded event IDs to channels for signaling when the decryption of a direct message has been approved/rejected.
+    in_progress_dm_decryptions: PendingMap<Nip46RequestApproval>,
+
+    /// Map of Bolt11 invoice strings to channels for signaling when the payment of an invoice has been paid/failed/rejected.
+    in_progress_invoice_payments: PendingMap<Nip46RequestApproval>,
+
+    /// Ledger of in-flight and recently paid invoices. Prevents paying the same invoice twice.
+    payment_ledger: PaymentLedger<PaymentResult>,
+
+    /// Backend that approved invoices are paid through. Also used to estimate fees for approval prompts.
+    payment_backend: Arc<dyn PaymentBackend>,
+
+    /// Used to send events to the frontend.
+    event_emitter: Arc<dyn EventEmitter>,
+
+    /// Where the outcome of every request to pay an invoice is recorded.
+    payment_log: Arc<dyn PaymentLog>,
+
+    /// Where each account's signed events and paid invoices are counted.
+    activity_log: Arc<dyn AccountActivityLog>,
+
+    /// Where the user's remembered decisions for each app and event kind are kept.
+    sign_decisions: Arc<dyn SignDecisionStore>,
+
+    /// Where the outcome of every request from a known app to sign an event is recorded.
+    signing_log: Arc<dyn SigningLog>,
+
+    /// Where to find the events that parameterized replaceable events would replace, to warn the user about them.
+    replaceable_events: Arc<dyn ReplaceableEventLookup>,
+
+    /// How long to wait for the user to respond to each kind of request.
+    approval_timeouts: std::sync::RwLock<ApprovalTimeouts>,
+
+    /// Pubkeys and LNURLs that requests are flagged for referencing.
+    scam_list: std::sync::RwLock<ScamList>,
+
+    /// Apps that the user has let sign certain kinds of event without asking, until their grant expires.
+    session_grants: std::sync::Mutex<SessionGrants>,
+
+    /// Names the user has given to apps, shown in approval prompts instead of the app's npub.
+    app_names: std::sync::RwLock<HashMap<PublicKey, String>>,
+
+    /// Daily window during which requests to sign events or pay invoices are held back, if the user has set one.
+    quiet_hours: std::sync::RwLock<Option<QuietHours>>,
+
+    /// Cap on the amount of any single payment, if the user has set one.
+    max_single_payment: std::sync::RwLock<Option<MaxSinglePayment>>,
+
+    /// Relay hint to add to each account's events, for the accounts that have relay hints turned on.
+    relay_hints: std::sync::RwLock<HashMap<PublicKey, String>>,
+
+    /// How long after signing each account's events expire, for the accounts that have a default expiration.
+    default_expirations: std::sync::RwLock<HashMap<PublicKey, Duration>>,
+
+    /// Events older than this are rejected without asking the user, if the user has set a maximum age.
+    max_event_age: std::sync::RwLock<Option<Duration>>,
+
+    /// Whether to call out events that are signed without a NIP-36 `content-warning` tag.
+    content_warning_policy: std::sync::RwLock<ContentWarningPolicy>,
+
+    /// How to draw the user's attention to requests when the window isn't focused.
+    request_attention: std::sync::RwLock<RequestAttention>,
+
+    /// Which high-risk requests can only be approved by typing a confirmation phrase.
+    confirmation_phrases: std::sync::RwLock<ConfirmationPhrasePolicy>,
+
+    /// Whether requests from NIP-46 servers are being rejected without asking, until the servers are resumed.
+    servers_paused: AtomicBool,
+
+    /// Which app keys NIP-46 servers accept requests from.
+    app_key_allowlist: std::sync::RwLock<AppKeyAllowlist>,
+
+    /// Signers for accounts whose keys are on a PKCS#11 token rather than in the vault.
+    #[cfg(feature = "pkcs11")]
+    hardware_signers: std::sync::RwLock<HashMap<PublicKey, Arc<dyn Signer>>>,
+
+    /// Returns the local time of day that quiet hours are checked against.
+    local_time: fn() -> NaiveTime,
+}
+
+impl KeystacheRequestApprover {
+    // Each argument is a separate dependency that tests swap out independently.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        event_emitter: Arc<dyn EventEmitter>,
+        payment_dedup_window: Duration,
+        payment_backend: Arc<dyn PaymentBackend>,
+        payment_log: Arc<dyn PaymentLog>,
+        activity_log: Arc<dyn AccountActivityLog>,
+        sign_decisions: Arc<dyn SignDecisionStore>,
+        signing_log: Arc<dyn SigningLog>,
+        replaceable_events: Arc<dyn ReplaceableEventLookup>,
+        approval_timeouts: ApprovalTimeouts,
+    ) -> Self {
+        Self {
+            in_progress_event_signings: std::sync::Mutex::new(HashMap::new()),
+            in_progress_batch_signings: std::sync::Mutex::new(HashMap::new()),
+            in_progress_pow_minings: Mutex::new(HashMap::new()),
+            next_batch_id: AtomicU64::new(0),
+            in_progress_dm_decryptions: std::sync::Mutex::new(HashMap::new()),
+            in_progress_invoice_payments: std::sync::Mutex::new(HashMap::new()),
+            payment_ledger: PaymentLedger::new(payment_dedup_window),
+            payment_backend,
+            event_emitter,
+            payment_log,
+            activity_log,
+            sign_decisions,
+            signing_log,
+            replaceable_events,
+            approval_timeouts: std::sync::RwLock::new(approval_timeouts),
+            scam_list: std::sync::RwLock::new(ScamList::default()),
+            session_grants: std::sync::Mutex::new(SessionGrants::default()),
+            app_names: std::sync::RwLock::new(HashMap::new()),
+            quiet_hours: std::sync::RwLock::new(None),
+            max_single_payment: std::sync::RwLock::new(None),
+            max_event_age: std::sync::RwLock::new(None),
+            relay_hints: std::sync::RwLock::new(HashMap::new()),
+            default_expirations: std::sync::RwLock::new(HashMap::new()),
+            content_warning_policy: std::sync::RwLock::new(ContentWarningPolicy::default()),
+            request_attention: std::sync::RwLock::new(RequestAttention::default()),
+            confirmation_phrases: std::sync::RwLock::new(ConfirmationPhrasePolicy::default()),
+            servers_paused: AtomicBool::new(false),
+            app_key_allowlist: std::sync::RwLock::new(AppKeyAllowlist::default()),
+            #[cfg(feature = "pkcs11")]
+            hardware_signers: std::sync::RwLock::new(HashMap::new()),
+            local_time: quiet_hours::local_time,
+        }
+    }
+
+    /// Asks the user to approve paying an invoice requested by `app_public_key` on behalf of `user_pubkey`'s account,
+    /// and if they approve, pays it through the payment backend. If the same invoice is already being paid or was
+    /// paid recently, the existing result is returned instead. A payment only counts as paid once the backend's
+    /// preimage is confirmed to match the invoice's payment hash. The outcome is recorded in the payment log.
+    ///
+    /// Invoices over the maximum single payment either fail with `PayInvoiceError::AmountTooLarge` without the user
+    /// being asked, or need Keystache to be unlocked before they can be approved, depending on the cap's action.
+    /// Without a payment backend, every invoice fails with `PayInvoiceError::NoPaymentBackend` without the user
+    /// being asked, since approving it couldn't pay it.
+    pub async fn pay_invoice(
+        &self,
+        invoice: Bolt11Invoice,
+        user_pubkey: PublicKey,
+        app_public_key: Option<PublicKey>,
+    ) -> anyhow::Result<PaymentResult> {
+        if self.payment_backend.name().is_none() {
+            return Err(PayInvoiceError::NoPaymentBackend.into());
+        }
+
+        let over_cap = self
+            .max_single_payment
+            .read()
+            .unwrap()
+            .as_ref()
+            .and_then(|cap| cap.check(&invoice).err().map(|err| (cap.action, err)));
+        if let Some((OverCapAction::Reject, err)) = over_cap {
+            self.record_payment_outcome(
+                &invoice,
+                app_public_key.as_ref(),
+                PaymentOutcome::Rejected,
+                None,
+            );
+            return Err(err.into());
+        }
+
+        let payment_hash = invoice.payment_hash().to_string();
+        self.payment_ledger
+            .pay_once(
+                &payment_hash,
+                |result| matches!(result, PaymentResult::Paid(_)),
+                || async {
+                    let (result, outcome) = match self
+                        .request_invoice_payment(
+                            invoice.clone(),
+                            app_public_key,
+                            over_cap.is_some(),
+                        )
+                        .await
+                    {
+                        Ok(Nip46RequestApproval::Approve) => {
+                            match payment_backend::pay_invoice_cancellably(
+                                self.payment_backend.as_ref(),
+                                &invoice,
+                                PAYMENT_TIMEOUT,
+                                &CancellationToken::new(),
+                            )
+                            .await
+                            {
+                                Ok(response) => {
+                                    (PaymentResult::Paid(response), PaymentOutcome::Paid)
+                                }
+                                Err(err) => (
+                                    PaymentResult::Failed {
+                                        reason: err.to_string(),
+                                    },
+                                    PaymentOutcome::Failed,
+                                ),
+                            }
+                        }
+                        Ok(_) => (PaymentResult::Rejected, PaymentOutcome::Rejected),
+                        Err(err) => {
+                            error_log::report(format!("Failed to ask for payment approval: {err}"));
+                            (PaymentResult::Rejected, PaymentOutcome::Failed)
+                        }
+                    };
+                    let preimage = match &result {
+                        PaymentResult::Paid(response) => Some(response.preimage.clone()),
+                        _ => None,
+                    };
+                    self.record_payment_outcome(
+                        &invoice,
+                        app_public_key.as_ref(),
+                        outcome,
+                        preimage,
+                    );
+                    if outcome == PaymentOutcome::Paid {
+                        self.record_activity(self.activity_log.record_payment(&user_pubkey));
+                    }
+                    result
+                },
+            )
+            .await
+    }
+
+    /// Records the payment in the payment log. Failing to record it never fails the payment.
+    fn record_payment_outcome(
+        &self,
+        invoice: &Bolt11Invoice,
+        app_public_key: Option<&PublicKey>,
+        outcome: PaymentOutcome,
+        preimage: Option<String>,
+    ) {
+        if let Err(err) = PaymentLogEntry::new(invoice, app_public_key, outcome, preimage)
+            .and_then(|entry| self.payment_log.record_payment(&entry))
+        {
+            error_log::report(format!("Failed to record payment: {err}"));
+        }
+    }
+
+    /// If `over_cap` is set, the invoice is over the maximum single payment and needs Keystache to be unlocked.
+    async fn request_invoice_payment(
+        &self,
+        invoice: Bolt11Invoice,
+        app_public_key: Option<PublicKey>,
+        over_cap: bool,
+    ) -> anyhow::Result<Nip46RequestApproval> {
+        let quiet_hours_action = self.quiet_hours_action();
+        if quiet_hours_action == Some(QuietHoursAction::Reject) {
+            return Ok(Nip46RequestApproval::Reject);
+        }
+
+        let mut payload =
+            PayInvoiceRequestPayload::new(&invoice, self.payment_backend.as_ref()).await;
+        payload.scam_list_matches = self.scam_list.read().unwrap().matches_in_invoice(&invoice);
+        payload.requires_unlock =
+            over_cap || quiet_hours_action == Some(QuietHoursAction::RequireUnlock);
+        if over_cap {
+            payload.confirmation_phrase = self
+                .confirmation_phrases
+                .read()
+                .unwrap()
+                .required_phrase(HighRiskAction::LargePayment);
+        }
+        if let Some(app_public_key) = app_public_key {
+            payload.app_npub = Some(app_public_key.to_bech32()?);
+            payload.app_name = Some(self.app_name(&app_public_key)?);
+        }
+        let timeout = self.approval_timeouts.read().unwrap().for_pay_invoice();
+
+        let (pending_request, rx) = PendingRequest::insert_with_confirmation_phrase(
+            &self.in_progress_invoice_payments,
+            payload.invoice.clone(),
+            payload.confirmation_phrase,
+        );
+
+        let app_name = payload.app_name.clone();
+        let emit_result = pending_request.emit(
+            self.event_emitter.as_ref(),
+            PendingRequestKind::PayInvoice,
+            payload.app_npub.clone(),
+            payload,
+        );
+        // If emitting fails, nothing will ever respond to the request, and returning drops it from the map.
+        emit_result?;
+        self.draw_attention(PendingRequestKind::PayInvoice, app_name.as_deref());
+
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(approval) => Ok(approval?),
+            Err(_) => Ok(Nip46RequestApproval::Reject),
+        }
+    }
+
+    /// Asks the user whether to sign an event requested by `app_public_key` (if known). Resolves once the user has
+    /// approved or rejected it. The caller signs the original event, so approvals with an edited event are treated as rejections.
+    /// If the app has a session grant for the event's kind, or the user asked to remember their decision for the
+    /// app and the kind, the event is approved or rejected without asking. The outcome is recorded in the app's
+    /// signing history.
+    pub async fn request_sign_event_approval(
+        &self,
+        event: UnsignedEvent,
+        user_pubkey: PublicKey,
+        app_public_key: Option<PublicKey>,
+    ) -> Nip46RequestApproval {
+        let kind = event.kind;
+        let approval = self
+            .decide_sign_event(event, user_pubkey, app_public_key)
+            .await;
+        if let Some(app_public_key) = app_public_key {
+            self.record_signing_outcome(&app_public_key, kind, approval);
+        }
+        approval
+    }
+
+    async fn decide_sign_event(
+        &self,
+        event: UnsignedEvent,
+        user_pubkey: PublicKey,
+        app_public_key: Option<PublicKey>,
+    ) -> Nip46RequestApproval {
+        if let Some(approval) = self.remembered_sign_decision(&event, app_public_key.as_ref()) {
+            return approval;
+        }
+
+        let kind = event.kind;
+        let response = self
+            .request_sign_event_response(
+                event,
+                user_pubkey,
+                app_public_key,
+                SignEventRequestOptions::default(),
+            )
+            .await
+            .unwrap_or_else(|err| {
+                error_log::report(format!("Failed to ask for approval: {err}"));
+                SignEventResponse::reject()
+            });
+        if response.edited_event.is_some() {
+            return Nip46RequestApproval::Reject;
+        }
+        if let (true, Some(app_public_key)) = (response.remember, app_public_key) {
+            let approved = response.approval == Nip46RequestApproval::Approve;
+            if let Err(err) =
+                self.sign_decisions
+                    .remember_sign_decision(&app_public_key, kind, approved)
+            {
+                error_log::report(format!("Failed to remember sign decision: {err}"));
+            }
+        }
+        response.approval
+    }
+
+    /// Records the outcome in the app's signing history. Failing to record it never fails the request.
+    fn record_signing_outcome(
+        &self,
+        app_public_key: &PublicKey,
+        kind: Kind,
+        approval: Nip46RequestApproval,
+    ) {
+        let outcome = match approval {
+            Nip46RequestApproval::Approve => SigningOutcome::Approved,
+            _ => SigningOutcome::Rejected,
+        };
+        if let Err(err) = SigningLogEntry::new(app_public_key, kind, outcome)
+            .and_then(|entry| self.signing_log.record_signing(&entry))
+        {
+            error_log::report(format!("Failed to record signing: {err}"));
+        }
+    }
+
+    /// Returns approval if the app has a session grant for the event's kind, or otherwise the decision the user
+    /// asked to remember for the app and the kind, if it can be used for this event. Neither is used during quiet
+    /// hours, or for events that would be rejected or warned about anyway, so that the user still sees those.
+    fn remembered_sign_decision(
+        &self,
+        event: &UnsignedEvent,
+        app_public_key: Option<&PublicKey>,
+    ) -> Option<Nip46RequestApproval> {
+        let app_public_key = app_public_key?;
+        if self.quiet_hours_action().is_some()
+            || self.validate_event_age(event).is_err()
+            || !self.scam_list_warnings(event).is_empty()
+        {
+            return None;
+        }
+
+        if self.session_grants.lock().unwrap().allows(
+            app_public_key,
+            event.kind,
+            tokio::time::Instant::now(),
+        ) {
+            return Some(Nip46RequestApproval::Approve);
+        }
+
+        // If the decision can't be read, fall back to asking the user.
+        self.sign_decisions
+            .get_sign_decision(app_public_key, event.kind)
+            .ok()
+            .flatten()
+            .map(to_approval)
+    }
+
+    async fn request_sign_event_response(
+        &self,
+        mut event: UnsignedEvent,
+        user_pubkey: PublicKey,
+        app_public_key: Option<PublicKey>,
+        options: SignEventRequestOptions,
+    ) -> anyhow::Result<SignEventResponse> {
+        let quiet_hours_action = self.quiet_hours_action();
+        if quiet_hours_action == Some(QuietHoursAction::Reject) {
+            return Ok(SignEventResponse::reject());
+        }
+        if self.validate_event_age(&event).is_err() {
+            return Ok(SignEventResponse::reject());
+        }
+
+        let event_id = compute_event_id(&event);
+        event.id = Some(event_id);
+        let timeout = self
+            .approval_timeouts
+            .read()
+            .unwrap()
+            .for_sign_event(event.kind);
+
+        let (pending_request, rx) =
+            PendingRequest::insert(&self.in_progress_event_signings, event_id.to_hex());
+
+        let mut payload =
+            SignEventRequestPayload::new(event, user_pubkey.to_bech32().unwrap(), Timestamp::now());
+        payload
+            .warnings
+            .extend(self.scam_list_warnings(&payload.event));
+        if !options.skip_relay_lookups {
+            payload
+                .warnings
+                .extend(self.replaced_event_warning(&payload.event).await);
+        }
+        payload.requires_unlock = quiet_hours_action == Some(QuietHoursAction::RequireUnlock);
+        payload.added_relay_hint = options.added_relay_hint;
+        payload.added_expiration = options
+            .added_expiration
+            .map(|expiration| expiration.as_u64());
+        payload.pow_difficulty = options.pow_difficulty;
+        payload.apply_content_warning_policy(
+            *self.content_warning_policy.read().unwrap(),
+            options.edits_allowed,
+        );
+        if let Some(app_public_key) = app_public_key {
+            payload.can_remember = true;
+            payload.app_npub = app_public_key.to_bech32().ok();
+            payload.app_name = self.app_name(&app_public_key).ok();
+        }
+
+        let app_name = payload.app_name.clone();
+        let emit_result = pending_request.emit(
+            self.event_emitter.as_ref(),
+            PendingRequestKind::SignEvent,
+            payload.app_npub.clone(),
+            payload,
+        );
+        // If emitting fails, nothing will ever respond to the request, and returning drops it from the map.
+        emit_result?;
+        self.draw_attention(PendingRequestKind::SignEvent, app_name.as_deref());
+
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(response) => Ok(response.unwrap_or_else(|_| SignEventResponse::reject())),
+            Err(_) => Ok(SignEventResponse::reject()),
+        }
+    }
+
+    pub fn set_payment_dedup_window(&self, dedup_window: Duration) {
+        self.payment_ledger.set_dedup_window(dedup_window);
+    }
+
+    /// Applies to requests made after the change. Requests that are already waiting keep their timeout.
+    pub fn set_approval_timeouts(&self, approval_timeouts: ApprovalTimeouts) {
+        *self.approval_timeouts.write().unwrap() = approval_timeouts;
+    }
+
+    /// Applies to requests made after the change.
+    pub fn set_scam_list(&self, scam_list: ScamList) {
+        *self.scam_list.write().unwrap() = scam_list;
+    }
+
+    /// Applies to requests made after the change.
+    pub fn set_app_names(&self, app_names: HashMap<PublicKey, String>) {
+        *self.app_names.write().unwrap() = app_names;
+    }
+
+    /// The name the user has given an app, or its npub if they haven't named it.
+    fn app_name(&self, app_public_key: &PublicKey) -> anyhow::Result<String> {
+        match self.app_names.read().unwrap().get(app_public_key) {
+            Some(app_name) => Ok(app_name.clone()),
+            None => Ok(app_public_key.to_bech32()?),
+        }
+    }
+
+    /// Applies to requests made after the change. Pass `None` to turn quiet hours off.
+    pub fn set_quiet_hours(&self, quiet_hours: Option<QuietHours>) {
+        *self.quiet_hours.write().unwrap() = quiet_hours;
+    }
+
+    /// Applies to invoices paid after the change. Pass `None` to remove the cap.
+    pub fn set_max_single_payment(&self, max_single_payment: Option<MaxSinglePayment>) {
+        *self.max_single_payment.write().unwrap() = max_single_payment;
+    }
+
+    /// Applies to requests made after the change. Accounts that aren't in `relay_hints` get no hints added.
+    pub fn set_relay_hints(&self, relay_hints: HashMap<PublicKey, String>) {
+        *self.relay_hints.write().unwrap() = relay_hints;
+    }
+
+    /// Adds the account's relay hint to the event's bare `e` and `p` tags, if the account has relay hints
+    /// turned on. Returns the hint if it was added to any tags.
+    fn add_relay_hints(&self, event: &mut UnsignedEvent) -> Option<String> {
+        let relay_url = self.relay_hints.read().unwrap().get(&event.pubkey)?.clone();
+        event.tags = crate::sign_event_request::add_relay_hints(&event.tags, &relay_url)?;
+        Some(relay_url)
+    }
+
+    /// Applies to requests made after the change. Accounts that aren't in `default_expirations` get no expiration
+    /// added.
+    pub fn set_default_expirations(&self, default_expirations: HashMap<PublicKey, Duration>) {
+        *self.default_expirations.write().unwrap() = default_expirations;
+    }
+
+    /// Adds a NIP-40 expiration of now plus the account's default expiration to the event, if the account has one
+    /// and the event doesn't already expire. Returns the expiration if it was added.
+    fn add_default_expiration(&self, event: &mut UnsignedEvent) -> Option<Timestamp> {
+        let default_expiration = *self
+            .default_expirations
+            .read()
+            .unwrap()
+            .get(&event.pubkey)?;
+        let expiration = Timestamp::now() + default_expiration;
+        event.tags = crate::sign_event_request::add_expiration(&event.tags, expiration)?;
+        Some(expiration)
+    }
+
+    /// Applies to requests made after the change. Pass `None` to accept events of any age.
+    pub fn set_max_event_age(&self, max_event_age: Option<Duration>) {
+        *self.max_event_age.write().unwrap() = max_event_age;
+    }
+
+    /// Applies to requests made after the change.
+    pub fn set_content_warning_policy(&self, content_warning_policy: ContentWarningPolicy) {
+        *self.content_warning_policy.write().unwrap() = content_warning_policy;
+    }
+
+    /// Applies to requests made after the change.
+    pub fn set_confirmation_phrases(&self, confirmation_phrases: ConfirmationPhrasePolicy) {
+        *self.confirmation_phrases.write().unwrap() = confirmation_phrases;
+    }
+
+    /// Rejects every request that NIP-46 servers receive from now on, without asking the user, until
+    /// [`Self::resume_servers`] is called. Requests that are already waiting for the user aren't affected, and the
+    /// servers keep running, so nothing needs restarting on resume. Not persisted.
+    pub fn pause_servers(&self) {
+        self.servers_paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume_servers(&self) {
+        self.servers_paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn are_servers_paused(&self) -> bool {
+        self.servers_paused.load(Ordering::Relaxed)
+    }
+
+    /// Applies to connections made after the change.
+    pub fn set_app_key_allowlist(&self, app_key_allowlist: AppKeyAllowlist) {
+        *self.app_key_allowlist.write().unwrap() = app_key_allowlist;
+    }
+
+    /// Signs events for `signer`'s account with it from now on, instead of with a key from the vault. NIP-46
+    /// servers also decrypt requests and encrypt responses for the account with it, so the account needs no key in
+    /// the vault. Replaces any signer already set for the account.
+    #[cfg(feature = "pkcs11")]
+    pub fn set_hardware_signer(&self, signer: Arc<dyn Signer>) {
+        self.hardware_signers
+            .write()
+            .unwrap()
+            .insert(signer.public_key(), signer);
+    }
+
+    /// Whether events for `public_key` are signed with a hardware signer.
+    #[cfg(feature = "pkcs11")]
+    pub fn has_hardware_signer(&self, public_key: &PublicKey) -> bool {
+        self.hardware_signers
+            .read()
+            .unwrap()
+            .contains_key(public_key)
+    }
+
+    /// Applies to requests made after the change.
+    pub fn set_request_attention(&self, request_attention: RequestAttention) {
+        *self.request_attention.write().unwrap() = request_attention;
+    }
+
+    /// Draws the user's attention to a request of `kind` that was just emitted, as configured. The request is
+    /// already in front of the frontend, so failing to draw attention to it never fails it.
+    fn draw_attention(&self, kind: PendingRequestKind, app_name: Option<&str>) {
+        let request_attention = *self.request_attention.read().unwrap();
+        if let Err(err) = self.event_emitter.request_attention(
+            request_attention.focus_window,
+            request_attention.notification(kind, app_name),
+        ) {
+            error_log::report(format!("Failed to draw attention to request: {err}"));
+        }
+    }
+
+    /// Lets the app have events of `allowed_kinds` signed without asking for the next `duration`, replacing any
+    /// session grant it already has.
+    pub fn grant_session(
+        &self,
+        app_public_key: PublicKey,
+        duration: Duration,
+        allowed_kinds: Vec<Kind>,
+    ) -> anyhow::Result<()> {
+        self.session_grants.lock().unwrap().grant(
+            app_public_key,
+            duration,
+            allowed_kinds,
+            tokio::time::Instant::now(),
+        )
+    }
+
+    /// Ends the app's session grant, so that its requests are asked about again.
+    pub fn revoke_session(&self, app_public_key: &PublicKey) {
+        self.session_grants.lock().unwrap().revoke(app_public_key);
+    }
+
+    pub fn list_session_grants(&self) -> anyhow::Result<Vec<SessionGrantEntry>> {
+        self.session_grants
+            .lock()
+            .unwrap()
+            .list(tokio::time::Instant::now())
+    }
+
+    /// The session grants that haven't expired yet, to be saved so that they outlast the app's connection.
+    pub fn stored_session_grants(&self) -> anyhow::Result<Vec<StoredSessionGrant>> {
+        self.session_grants
+            .lock()
+            .unwrap()
+            .list_stored(tokio::time::Instant::now(), Timestamp::now().as_u64())
+    }
+
+    /// Restores saved session grants, e.g. on launch, so that apps keep their sessions until they expire.
+    pub fn restore_session_grants(&self, stored_grants: &[StoredSessionGrant]) {
+        self.session_grants.lock().unwrap().restore(
+            stored_grants,
+            tokio::time::Instant::now(),
+            Timestamp::now().as_u64(),
+        );
+    }
+
+    fn validate_event_age(&self, event: &UnsignedEvent) -> anyhow::Result<()> {
+        crate::sign_event_request::validate_event_age(
+            event.created_at,
+            Timestamp::now(),
+            *self.max_event_age.read().unwrap(),
+        )
+    }
+
+    /// What to do with a request that arrives now, or `None` if it isn't quiet hours.
+    fn quiet_hours_action(&self) -> Option<QuietHoursAction> {
+        self.quiet_hours
+            .read()
+            .unwrap()
+            .as_ref()
+            .and_then(|quiet_hours| quiet_hours.action_at((self.local_time)()))
+    }
+
+    /// Activity is only counted for display, so failing to record it never fails the request.
+    fn record_activity(&self, result: anyhow::Result<()>) {
+        if let Err(err) = result {
+            error_log::report(format!("Failed to record account activity: {err}"));
+        }
+    }
+
+    fn scam_list_warnings(&self, event: &UnsignedEvent) -> Vec<SignEventWarning> {
+        self.scam_list
+            .read()
+            .unwrap()
+            .matches_in_event(event)
+            .into_iter()
+            .map(|entry| SignEventWarning::ScamListMatch { entry })
+            .collect()
+    }
+
+    /// Warns if a parameterized replaceable event would replace one that the author's relays already have. No
+    /// warning is given if the lookup fails or takes longer than [`replaceable_event::LOOKUP_TIMEOUT`].
+    async fn replaced_event_warning(&self, event: &UnsignedEvent) -> Option<SignEventWarning> {
+        let identifier = replaceable_event::identifier(event)?;
+        let lookup =
+            self.replaceable_events
+                .latest_replaceable_event(event.pubkey, event.kind, &identifier);
+        let existing_event =
+            match tokio::time::timeout(replaceable_event::LOOKUP_TIMEOUT, lookup).await {
+                Ok(Ok(existing_event)) => existing_event?,
+                Ok(Err(err)) => {
+                    error_log::report(format!("Failed to look up replaceable event: {err}"));
+                    return None;
+                }
+                Err(_) => return None,
+            };
+        Some(SignEventWarning::ReplacesExistingEvent {
+            identifier,
+            existing_event_id: existing_event.id.to_hex(),
+            existing_created_at: existing_event.created_at.as_u64(),
+        })
+    }
+
+    /// Lists the requests that the user has been asked about and hasn't responded to yet, oldest first.
+    pub fn list_pending_requests(&self) -> Vec<PendingRequestEntry> {
+        let mut requests = list_pending(&self.in_progress_event_signings);
+        requests.extend(list_pending(&self.in_progress_batch_signings));
+        requests.extend(list_pending(&self.in_progress_dm_decryptions));
+        requests.extend(list_pending(&self.in_progress_invoice_payments));
+        requests.sort_by(|a, b| (a.requested_at, &a.id).cmp(&(b.requested_at, &b.id)));
+        requests
+    }
+
+    /// Rejects every pending sign event and pay invoice request.
+    pub async fn clear_pending_requests(&self) {
+        // Dropping the senders rejects the requests that are waiting on them.
+        self.in_progress_event_signings.lock().unwrap().clear();
+        self.in_progress_batch_signings.lock().unwrap().clear();
+        self.in_progress_dm_decryptions.lock().unwrap().clear();
+        self.in_progress_invoice_payments.lock().unwrap().clear();
+    }
+
+    /// Resolves a pending sign event request with the user's response. If the user approved an edited
+    /// version of the event, `edited_event` is signed instead of the original, as long as it has the same
+    /// pubkey and kind. If `remember` is set and the request shows it can be remembered, later events of the
+    /// same kind from the same app get the same response without asking. Does nothing if there is no pending
+    /// request for the event.
+    pub async fn respond_to_sign_event_request(
+        &self,
+        event_id: &str,
+        approved: bool,
+        edited_event: Option<UnsignedEvent>,
+        remember: bool,
+    ) {
+        if let Some(tx) = take_pending(&self.in_progress_event_signings, event_id) {
+            let _ = tx.send(SignEventResponse {
+                approval: to_approval(approved),
+                edited_event: edited_event.filter(|_| approved),
+                remember,
+            });
+        }
+    }
+
+    /// Resolves a pending request to sign a batch of events with the user's response.
+    /// Does nothing if there is no pending request for the batch.
+    pub async fn respond_to_sign_events_request(&self, batch_id: &str, approved: bool) {
+        if let Some(tx) = take_pending(&self.in_progress_batch_signings, batch_id) {
+            let _ = tx.send(to_approval(approved));
+        }
+    }
+
+    /// Resolves a pending request to decrypt a direct message with the user's response.
+    /// Does nothing if there is no pending request for the message.
+    pub async fn respond_to_decrypt_dm_request(&self, event_id: &str, approved: bool) {
+        if let Some(tx) = take_pending(&self.in_progress_dm_decryptions, event_id) {
+            let _ = tx.send(to_approval(approved));
+        }
+    }
+
+    /// Resolves a pending pay invoice request with the user's response.
+    /// Does nothing if there is no pending request for the invoice.
+    /// Errors without responding if the payment is approved without typing the confirmation phrase it needs, so
+    /// that the user can try again.
+    pub async fn respond_to_pay_invoice_request(
+        &self,
+        invoice: &str,
+        approved: bool,
+        typed_phrase: Option<&str>,
+    ) -> anyhow::Result<()> {
+        if let Some(tx) = take_pending_confirmed(
+            &self.in_progress_invoice_payments,
+            invoice,
+            approved,
+            typed_phrase,
+        )? {
+            let _ = tx.send(to_approval(approved));
+        }
+        Ok(())
+    }
+
+    /// Mines proof of work for the event off the async runtime, reporting progress under the sign event request's ID.
+    async fn mine_pow(
+        &self,
+        request_id: &str,
+        event: UnsignedEvent,
+        difficulty: u8,
+    ) -> anyhow::Result<UnsignedEvent> {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.in_progress_pow_minings
+            .lock()
+            .await
+            .insert(request_id.to_string(), cancelled.clone());
+
+        let event_emitter = self.event_emitter.clone();
+        let progress_event_id = request_id.to_string();
+        let result = tokio::task::spawn_blocking(move || {
+            pow::mine_event(
+                event,
+                difficulty,
+                pow::MAX_MINING_TIME,
+                &cancelled,
+                |attempts, best_leading_zero_bits| {
+                    let payload = PowProgressPayload {
+                        event_id: progress_event_id.clone(),
+                        difficulty,
+                        attempts,
+                        best_leading_zero_bits,
+                    };
+                    // Progress is only for display, so failing to report it doesn't stop mining.
+                    if let Ok(payload) = serde_json::to_value(payload) {
+                        let _ = event_emitter.emit("pow_progress", payload);
+                    }
+                },
+            )
+        })
+        .await;
+        self.in_progress_pow_minings.lock().await.remove(request_id);
+
+        Ok(result??)
+    }
+
+    /// Asks the user to approve signing an event, and if they approve, signs it with the key for the event's pubkey.
+    /// If the account has relay hints turned on, they're added to the event before the user is asked.
+    /// Once signed, the event is also sent to the frontend as an `event_signed` event, so that it can be shown or copied.
+    pub async fn sign_event_with_approval(
+        &self,
+        event: UnsignedEvent,
+        key_manager: &dyn KeyManager,
+    ) -> anyhow::Result<Event> {
+        self.sign_event_with_approval_and_pow(event, None, false, key_manager)
+            .await
+    }
+
+    /// Like [`Self::sign_event_with_approval`], but signs as the stored account with `public_key`, whatever the
+    /// event's pubkey is. The event's pubkey is rewritten to the account's before the user is asked, so that they
+    /// approve the event that's actually signed. Errors without asking the user if the account isn't stored.
+    pub async fn sign_event_as_with_approval(
+        &self,
+        mut event: UnsignedEvent,
+        public_key: PublicKey,
+        key_manager: &dyn KeyManager,
+    ) -> anyhow::Result<Event> {
+        if self.signer_for(&public_key, key_manager).is_none() {
+            return Err(anyhow::anyhow!(
+                "No stored account for {}",
+                public_key.to_bech32()?
+            ));
+        }
+
+        event.pubkey = public_key;
+        // The ID depends on the pubkey, so the old one no longer applies.
+        event.id = None;
+        self.sign_event_with_approval(event, key_manager).await
+    }
+
+    /// Like [`Self::sign_event_with_approval`], but once the user approves, mines a NIP-13 nonce so that the
+    /// event's ID has at least `difficulty` leading zero bits before signing it. While mining, `pow_progress` events
+    /// are sent to the frontend. Mining can be stopped with [`Self::cancel_pow`], and gives up after
+    /// [`pow::MAX_MINING_TIME`].
+    pub async fn sign_event_with_pow_and_approval(
+        &self,
+        event: UnsignedEvent,
+        difficulty: u8,
+        key_manager: &dyn KeyManager,
+    ) -> anyhow::Result<Event> {
+        self.sign_event_with_approval_and_pow(event, Some(difficulty), false, key_manager)
+            .await
+    }
+
+    /// Stops mining proof of work for the sign event request with the given ID, so that the request fails.
+    /// Does nothing if proof of work isn't being mined for the request.
+    pub async fn cancel_pow(&self, event_id: &str) {
+        if let Some(cancelled) = self.in_progress_pow_minings.lock().await.get(event_id) {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    async fn sign_event_with_approval_and_pow(
+        &self,
+        mut event: UnsignedEvent,
+        pow_difficulty: Option<u8>,
+        skip_relay_lookups: bool,
+        key_manager: &dyn KeyManager,
+    ) -> anyhow::Result<Event> {
+        self.validate_event_age(&event)?;
+        // Check for the key before asking the user, so that they're never asked to approve an event that can't
+        // be signed, e.g. for a watch-only account.
+        let signer = self
+            .signer_for(&event.pubkey, key_manager)
+            .ok_or(anyhow::anyhow!("No key available for event pubkey"))?;
+        let added_relay_hint = self.add_relay_hints(&mut event);
+        let added_expiration = self.add_default_expiration(&mut event);
+        let request_id = compute_event_id(&event).to_hex();
+        let response = self
+            .request_sign_event_response(
+                event.clone(),
+                event.pubkey,
+                None,
+                SignEventRequestOptions {
+                    added_relay_hint,
+                    added_expiration,
+                    edits_allowed: true,
+                    pow_difficulty,
+                    skip_relay_lookups,
+                },
+            )
+            .await?;
+        if response.approval != Nip46RequestApproval::Approve {
+            return Err(anyhow::anyhow!("Sign event request rejected"));
+        }
+
+        let event = match response.edited_event {
+            Some(mut edited_event) => {
+                // Only allow edits that keep the request recognisably the same, so that
+                // the approval UI can't swap in an entirely different event.
+                if edited_event.pubkey != event.pubkey || edited_event.kind != event.kind {
+                    return Err(anyhow::anyhow!(
+                        "Edited event must have the same pubkey and kind as the original"
+                    ));
+                }
+                // The ID is recomputed when signing, since the edit changes it.
+                edited_event.id = None;
+                edited_event
+            }
+            None => event,
+        };
+        let event = match pow_difficulty {
+            Some(difficulty) => self.mine_pow(&request_id, event, difficulty).await?,
+            None => event,
+        };
+
+        let event = signer.sign_event(event)?;
+        self.record_activity(self.activity_log.record_signs(&event.pubkey, 1));
+
+        // The event is already signed, so failing to tell the frontend shouldn't fail the request.
+        let _ = self
+            .event_emitter
+            .emit("event_signed", serde_json::Value::String(event.as_json()));
+
+        Ok(event)
+    }
+
+    /// Signs an event given as JSON, e.g. one handed over from an air-gapped device, once the user approves, and
+    /// returns the signed event as JSON. Nothing is sent to or fetched from relays, whatever the relay settings.
+    pub async fn sign_event_json_with_approval(
+        &self,
+        event_json: &str,
+        key_manager: &dyn KeyManager,
+    ) -> anyhow::Result<String> {
+        let mut event = UnsignedEvent::from_json(event_json)
+            .map_err(|err| anyhow::anyhow!("Invalid event: {err}"))?;
+        // The ID is recomputed when signing, so a stale or made-up ID can't end up in the signed event.
+        event.id = None;
+
+        let event = self
+            .sign_event_with_approval_and_pow(event, None, true, key_manager)
+            .await?;
+        Ok(event.as_json())
+    }
+
+    /// Asks the user to approve decrypting a direct message addressed to `recipient_public_key`, and if they
+    /// approve, decrypts it with that account's key. Messages that aren't addressed to the account are
+    /// rejected without asking the user.
+    pub async fn decrypt_dm_with_approval(
+        &self,
+        event: &Event,
+        recipient_public_key: &PublicKey,
+        key_manager: &dyn KeyManager,
+    ) -> anyhow::Result<String> {
+        dm::verify_dm_recipient(event, recipient_public_key)?;
+        let secret_key = key_manager
+            .get_secret_key(recipient_public_key)
+            .ok_or(anyhow::anyhow!("No key available for recipient"))?;
+
+        let payload = DecryptDmRequestPayload::new(event, recipient_public_key)?;
+        let event_id = payload.event_id.clone();
+        let timeout = Duration::from_secs(self.approval_timeouts.read().unwrap().default_secs);
+
+        let (pending_request, rx) =
+            PendingRequest::insert(&self.in_progress_dm_decryptions, event_id);
+
+        pending_request.emit(
+            self.event_emitter.as_ref(),
+            PendingRequestKind::DecryptDm,
+            None,
+            payload,
+        )?;
+        self.draw_attention(PendingRequestKind::DecryptDm, None);
+
+        let approval = match tokio::time::timeout(timeout, rx).await {
+            Ok(approval) => approval.unwrap_or(Nip46RequestApproval::Reject),
+            Err(_) => Nip46RequestApproval::Reject,
+        };
+        if approval != Nip46RequestApproval::Approve {
+            return Err(anyhow::anyhow!("Decrypt DM request rejected"));
+        }
+
+        dm::decrypt_dm(event, &secret_key)
+    }
+
+    /// Asks the user to approve signing a batch of events in a single prompt, and if they approve, signs each
+    /// event with the key for its pubkey. The events may be for any of the user's accounts, and are shown to
+    /// the user grouped by account. The batch is rejected without asking the user if there's no key for any
+    /// of the events. Signed events are returned in the order they were given, and each is sent to the
+    /// frontend as an `event_signed` event.
+    pub async fn sign_events_with_approval(
+        &self,
+        events: Vec<UnsignedEvent>,
+        key_manager: &dyn KeyManager,
+    ) -> anyhow::Result<Vec<Event>> {
+        if events.is_empty() {
+            return Err(anyhow::anyhow!("No events to sign"));
+        }
+
+        let quiet_hours_action = self.quiet_hours_action();
+        if quiet_hours_action == Some(QuietHoursAction::Reject) {
+            return Err(anyhow::anyhow!(
+                "Sign events request rejected during quiet hours"
+            ));
+        }
+
+        for event in &events {
+            self.validate_event_age(event)?;
+        }
+
+        // Resolve every key before asking the user, so that they're never asked to approve a batch that can only be partly signed.
+        let mut signers_by_public_key: HashMap<PublicKey, Arc<dyn Signer>> = HashMap::new();
+        for event in &events {
+            if signers_by_public_key.contains_key(&event.pubkey) {
+                continue;
+            }
+            let signer = self.signer_for(&event.pubkey, key_manager).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "No key available for {}",
+                    event.pubkey.to_bech32().unwrap_or_default()
+                )
+            })?;
+            signers_by_public_key.insert(event.pubkey, signer);
+        }
+
+        let events: Vec<UnsignedEvent> = events
+            .into_iter()
+            .map(|mut event| {
+                event.id = Some(compute_event_id(&event));
+                event
+            })
+            .collect();
+
+        // Give the user as long as the most generous timeout of any event in the batch.
+        let timeout = {
+            let approval_timeouts = self.approval_timeouts.read().unwrap();
+            events
+                .iter()
+                .map(|event| approval_timeouts.for_sign_event(event.kind))
+                .max()
+                .unwrap_or_default()
+        };
+
+        let batch_id = self
+            .next_batch_id
+            .fetch_add(1, Ordering::Relaxed)
+            .to_string();
+        let (pending_request, rx) =
+            PendingRequest::insert(&self.in_progress_batch_signings, batch_id.clone());
+
+        let mut payload =
+            SignEventsRequestPayload::new(batch_id.clone(), events.clone(), Timestamp::now())?;
+        let content_warning_policy = *self.content_warning_policy.read().unwrap();
+        for event_payload in payload
+            .accounts
+            .iter_mut()
+            .flat_map(|account| account.events.iter_mut())
+        {
+            let warnings = self.scam_list_warnings(&event_payload.event);
+            event_payload.warnings.extend(warnings);
+            // Events in a batch can't be edited, so no tagged copy is suggested.
+            event_payload.apply_content_warning_policy(content_warning_policy, false);
+        }
+        payload.requires_unlock = quiet_hours_action == Some(QuietHoursAction::RequireUnlock);
+        pending_request.emit(
+            self.event_emitter.as_ref(),
+            PendingRequestKind::SignEvents,
+            None,
+            payload,
+        )?;
+        self.draw_attention(PendingRequestKind::SignEvents, None);
+
+        let approval = match tokio::time::timeout(timeout, rx).await {
+            Ok(approval) => approval.unwrap_or(Nip46RequestApproval::Reject),
+            Err(_) => Nip46RequestApproval::Reject,
+        };
+        if approval != Nip46RequestApproval::Approve {
+            return Err(anyhow::anyhow!("Sign events request rejected"));
+        }
+
+        let signed_events = events
+            .into_iter()
+            .map(|event| signers_by_public_key[&event.pubkey].sign_event(event))
+            .collect::<anyhow::Result<Vec<Event>>>()?;
+        for public_key in signers_by_public_key.keys() {
+            let count = signed_events
+                .iter()
+                .filter(|event| event.pubkey == *public_key)
+                .count();
+            self.record_activity(self.activity_log.record_signs(public_key, count as u64));
+        }
+
+        // The events are already signed, so failing to tell the frontend shouldn't fail the request.
+        for event in &signed_events {
+            let _ = self
+                .event_emitter
+                .emit("event_signed", serde_json::Value::String(event.as_json()));
+        }
+
+        Ok(signed_events)
+    }
+}
+
+impl SignEventResponse {
+    fn reject() -> Self {
+        Self {
+            approval: Nip46RequestApproval::Reject,
+            edited_event: None,
+            remember: false,
+        }
+    }
+}
+
+/// Computes the ID that the event will have once it's signed, from its canonical NIP-01 serialization.
+/// Any ID already set on the event is ignored.
+pub fn compute_event_id(event: &UnsignedEvent) -> EventId {
+    // TODO: Is this seriously the best way to do this?!
+    EventId::new(
+        &event.pubkey,
+        event.created_at,
+        &event.kind,
+        &event.tags,
+        &event.content,
+    )
+}
+
+fn to_approval(approved: bool) -> Nip46RequestApproval {
+    if approved {
+        Nip46RequestApproval::Approve
+    } else {
+        Nip46RequestApproval::Reject
+    }
+}
+
+/// What apps are told when a request is rejected. They aren't told why.
+const REQUEST_REJECTED: &str = "Request rejected";
+
+#[async_trait]
+impl Nip46RequestHandler for KeystacheRequestApprover {
+    fn allows_app(&self, app_public_key: &PublicKey) -> bool {
+        self.app_key_allowlist
+            .read()
+            .unwrap()
+            .allows(app_public_key)
+    }
+
+    /// Returns what signs events for the account, whether for NIP-46 requests or otherwise: its hardware signer
+    /// if it has one, or otherwise its key from `key_manager`. `None` if there's neither, e.g. for a watch-only
+    /// account.
+    fn signer_for(
+        &self,
+        user_public_key: &PublicKey,
+        key_manager: &dyn KeyManager,
+    ) -> Option<Arc<dyn Signer>> {
+        #[cfg(feature = "pkcs11")]
+        if let Some(signer) = self.hardware_signers.read().unwrap().get(user_public_key) {
+            return Some(signer.clone());
+        }
+        let secret_key = key_manager.get_secret_key(user_public_key)?;
+        Some(Arc::new(Keys::new(secret_key)))
+    }
+
+    async fn handle_request(
+        &self,
+        request: nip46::Request,
+        user_public_key: PublicKey,
+        app_public_key: PublicKey,
+        key_manager: &dyn KeyManager,
+    ) -> Result<nip46::ResponseResult, String> {
+        if self.are_servers_paused() {
+            return Err(REQUEST_REJECTED.to_string());
+        }
+
+        // TODO: Handle more than just signing events.
+        let event = match request {
+            nip46::Request::SignEvent(event) => event,
+            _ => return Err("Method not implemented".to_string()),
+        };
+
+        if event.pubkey != user_public_key
+            || crate::sign_event_request::validate_created_at(
+                event.created_at.as_i64(),
+                Timestamp::now(),
+            )
+            .is_err()
+        {
+            return Err(REQUEST_REJECTED.to_string());
+        }
+
+        let approval = self
+            .request_sign_event_approval(event.clone(), user_public_key, Some(app_public_key))
+            .await;
+        if approval != Nip46RequestApproval::Approve {
+            return Err(REQUEST_REJECTED.to_string());
+        }
+
+        let event = self
+            .signer_for(&user_public_key, key_manager)
+            .ok_or("No key available for account")?
+            .sign_event(event)
+            .map_err(|err| err.to_string())?;
+        self.record_activity(self.activity_log.record_signs(&user_public_key, 1));
+
+        // The event is already signed, so failing to tell the frontend shouldn't fail the request.
+        let _ = self
+            .event_emitter
+            .emit("event_signed", serde_json::Value::String(event.as_json()));
+
+        Ok(nip46::ResponseResult::SignEvent(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account_stats::AccountStats;
+    use crate::database::Database;
+    use crate::key_manager::{AccountListEntry, KeystacheKeyManager};
+    use crate::payment_backend::{FeeEstimate, NoPaymentBackend, PayInvoiceResponse};
+    use crate::payment_log::PaymentHistoryFilter;
+    use crate::relays::RelayPolicy;
+    use crate::test_support::SingleKeyManager;
+    use nostr_sdk::secp256k1::{Keypair, Secp256k1};
+    use nostr_sdk::{EventBuilder, Kind, SecretKey, Tag};
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+    use tokio::sync::mpsc;
+
+    // https://github.com/lightning/bolts/blob/master/11-payment-encoding.md#examples
+    const INVOICE: &str = "lnbc2500u1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdq5xysxxatsyp3k7enxv4jsxqzpu9qrsgquk0rl77nj30yxdy8j9vdx85fkpmdla2087ne0xh8nhedh8w27kyke0lp53ut353s06fv3qfegext0eh0ymjpf39tuven09sam30g4vgpfna3rh";
+
+    /// Preimage that [`MockPaymentBackend`] pays every invoice with. Only [`payable_invoice`]'s payment hash matches it.
+    const PREIMAGE: [u8; 32] = [1; 32];
+
+    /// Builds an invoice like [`INVOICE`], for 250,000 sats with the description "1 cup coffee", but with
+    /// [`PREIMAGE`] as its preimage, so that paying it succeeds.
+    fn payable_invoice() -> Bolt11Invoice {
+        use bitcoin_hashes::Hash as _;
+        use lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret};
+
+        let node_secret_key = secp256k1::SecretKey::from_slice(&[0x42; 32]).unwrap();
+        InvoiceBuilder::new(Currency::Bitcoin)
+            .description("1 cup coffee".to_string())
+            .payment_hash(bitcoin_hashes::sha256::Hash::hash(&PREIMAGE))
+            .payment_secret(PaymentSecret([0; 32]))
+            .amount_milli_satoshis(250_000_000)
+            .current_timestamp()
+            .min_final_cltv_expiry_delta(144)
+            .build_signed(|message| {
+                secp256k1::Secp256k1::new().sign_ecdsa_recoverable(message, &node_secret_key)
+            })
+            .unwrap()
+    }
+
+    /// Pays every invoice with [`PREIMAGE`].
+    struct MockPaymentBackend;
+
+    #[async_trait]
+    impl PaymentBackend for MockPaymentBackend {
+        fn name(&self) -> Option<&'static str> {
+            Some("mock")
+        }
+
+        async fn estimate_fee(&self, _invoice: &Bolt11Invoice) -> anyhow::Result<FeeEstimate> {
+            Ok(FeeEstimate::NotSupported)
+        }
+
+        async fn pay_invoice(
+            &self,
+            _invoice: &Bolt11Invoice,
+            _cancellation: CancellationToken,
+        ) -> anyhow::Result<String> {
+            Ok(nostr_sdk::util::hex::encode(PREIMAGE))
+        }
+    }
+
+    /// Emits events into a channel so that tests can respond to them.
+    struct ChannelEventEmitter {
+        sender: mpsc::UnboundedSender<(String, serde_json::Value)>,
+    }
+
+    impl EventEmitter for ChannelEventEmitter {
+        fn emit(&self, event: &str, payload: serde_json::Value) -> anyhow::Result<()> {
+            self.sender.send((event.to_string(), payload))?;
+            Ok(())
+        }
+    }
+
+    /// Fails to emit every event, as if the frontend were unreachable.
+    struct FailingEventEmitter;
+
+    impl EventEmitter for FailingEventEmitter {
+        fn emit(&self, _event: &str, _payload: serde_json::Value) -> anyhow::Result<()> {
+            Err(anyhow::anyhow!("Frontend unreachable"))
+        }
+    }
+
+    /// Emits nowhere, as if every window were closed and none could be opened.
+    struct WindowlessEventEmitter;
+
+    impl EventEmitter for WindowlessEventEmitter {
+        fn emit(&self, _event: &str, _payload: serde_json::Value) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn ensure_listener(&self) -> anyhow::Result<()> {
+            Err(NoApprovalWindowError {
+                reason: "no display".to_string(),
+            }
+            .into())
+        }
+    }
+
+    struct MultiKeyManager {
+        keys: Vec<Keys>,
+    }
+
+    impl KeyManager for MultiKeyManager {
+        fn get_secret_key(&self, public_key: &PublicKey) -> Option<SecretKey> {
+            self.keys
+                .iter()
+                .find(|keys| keys.public_key() == *public_key)
+                .and_then(|keys| keys.secret_key().ok().cloned())
+        }
+    }
+
+    fn get_request_approver() -> (
+        Arc<KeystacheRequestApprover>,
+        mpsc::UnboundedReceiver<(String, serde_json::Value)>,
+    ) {
+        get_request_approver_with_timeouts(ApprovalTimeouts::default())
+    }
+
+    fn get_request_approver_with_timeouts(
+        approval_timeouts: ApprovalTimeouts,
+    ) -> (
+        Arc<KeystacheRequestApprover>,
+        mpsc::UnboundedReceiver<(String, serde_json::Value)>,
+    ) {
+        get_request_approver_with(
+            approval_timeouts,
+            Arc::new(KeystacheKeyManager::new_with_database(
+                Database::new_in_temp_dir(),
+            )),
+        )
+    }
+
+    fn get_request_approver_with(
+        approval_timeouts: ApprovalTimeouts,
+        key_manager: Arc<KeystacheKeyManager>,
+    ) -> (
+        Arc<KeystacheRequestApprover>,
+        mpsc::UnboundedReceiver<(String, serde_json::Value)>,
+    ) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let request_approver = Arc::new(KeystacheRequestApprover::new(
+            Arc::new(ChannelEventEmitter { sender }),
+            Duration::from_secs(60),
+            Arc::new(MockPaymentBackend),
+            key_manager.clone(),
+            key_manager.clone(),
+            key_manager.clone(),
+            key_manager.clone(),
+            key_manager,
+            approval_timeouts,
+        ));
+        (request_approver, receiver)
+    }
+
+    /// Responds to the next sign event request with `approved`, then returns the events emitted after it.
+    fn respond_to_next_sign_event_request(
+        request_approver: Arc<KeystacheRequestApprover>,
+        mut receiver: mpsc::UnboundedReceiver<(String, serde_json::Value)>,
+        approved: bool,
+        edited_event: Option<UnsignedEvent>,
+    ) -> tokio::task::JoinHandle<Vec<(String, serde_json::Value)>> {
+        tokio::spawn(async move {
+            let (name, payload) = receiver.recv().await.unwrap();
+            assert_eq!(name, "sign_event_request");
+            let event_id = payload["event"]["id"].as_str().unwrap().to_string();
+            request_approver
+                .respond_to_sign_event_request(&event_id, approved, edited_event, false)
+                .await;
+
+            // The request approver is the only other sender, so this collects everything it emits after the response.
+            drop(request_approver);
+            let mut emitted_events = Vec::new();
+            while let Some(emitted_event) = receiver.recv().await {
+                emitted_events.push(emitted_event);
+            }
+            emitted_events
+        })
+    }
+
+    #[tokio::test]
+    async fn approved_sign_emits_signed_event() {
+        let keys = Keys::generate();
+        let key_manager = SingleKeyManager { keys: keys.clone() };
+        let (request_approver, receiver) = get_request_approver();
+
+        let responder =
+            respond_to_next_sign_event_request(request_approver.clone(), receiver, true, None);
+
+        let unsigned_event = EventBuilder::new(Kind::TextNote, "hello world", None)
+            .to_unsigned_event(keys.public_key());
+        let event = request_approver
+            .sign_event_with_approval(unsigned_event, &key_manager)
+            .await
+            .unwrap();
+        drop(request_approver);
+
+        let emitted_events = responder.await.unwrap();
+        assert_eq!(emitted_events.len(), 1);
+        assert_eq!(emitted_events[0].0, "event_signed");
+
+        // The emitted JSON should be exactly the signed event.
+        let emitted_json = emitted_events[0].1.as_str().unwrap();
+        assert_eq!(emitted_json, event.as_json());
+        let emitted_event = Event::from_json(emitted_json).unwrap();
+        assert!(emitted_event.verify().is_ok());
+        assert_eq!(emitted_event, event);
+    }
+
+    #[test]
+    fn computed_event_id_matches_signed_event_id() {
+        let keys = Keys::generate();
+        let mut unsigned_event =
+            EventBuilder::new(Kind::TextNote, "hello world", [Tag::Hashtag("id".into())])
+                .to_unsigned_event(keys.public_key());
+        let event_id = compute_event_id(&unsigned_event);
+
+        // A stale ID on the event doesn't change the result.
+        unsigned_event.id = Some(EventId::all_zeros());
+        assert_eq!(compute_event_id(&unsigned_event), event_id);
+
+        unsigned_event.id = None;
+        assert_eq!(unsigned_event.sign(&keys).unwrap().id(), event_id);
+    }
+
+    #[tokio::test]
+    async fn sign_event_as_signs_with_the_given_account() {
+        let keys_1 = Keys::generate();
+        let keys_2 = Keys::generate();
+        let key_manager = MultiKeyManager {
+            keys: vec![keys_1.clone(), keys_2.clone()],
+        };
+        // The event's own pubkey is ignored.
+        let unsigned_event = EventBuilder::new(Kind::TextNote, "hello world", None)
+            .to_unsigned_event(Keys::generate().public_key());
+
+        let mut events = Vec::new();
+        for keys in [&keys_1, &keys_2] {
+            let (request_approver, receiver) = get_request_approver();
+            let responder =
+                respond_to_next_sign_event_request(request_approver.clone(), receiver, true, None);
+            events.push(
+                request_approver
+                    .sign_event_as_with_approval(
+                        unsigned_event.clone(),
+                        keys.public_key(),
+                        &key_manager,
+                    )
+                    .await
+                    .unwrap(),
+            );
+            drop(request_approver);
+            responder.await.unwrap();
+        }
+
+        assert_eq!(events[0].author(), keys_1.public_key());
+        assert_eq!(events[1].author(), keys_2.public_key());
+        for event in &events {
+            assert!(event.verify().is_ok());
+            assert_eq!(event.content(), "hello world");
+        }
+        assert_ne!(events[0].id(), events[1].id());
+        assert_ne!(events[0].signature(), events[1].signature());
+
+        // Accounts that aren't stored are rejected without asking the user.
+        let (request_approver, _receiver) = get_request_approver();
+        assert!(request_approver
+            .sign_event_as_with_approval(
+                unsigned_event,
+                Keys::generate().public_key(),
+                &key_manager
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn approved_sign_is_counted_in_account_stats() {
+        let folder = tempfile::TempDir::new().unwrap();
+        let key_manager = Arc::new(KeystacheKeyManager::new_with_database(
+            Database::new_in_dir(folder.path()),
+        ));
+        let keys = Keys::generate();
+        key_manager
+            .add_keypair(&keys.secret_key().unwrap().keypair(&Secp256k1::new()))
+            .unwrap();
+        let (request_approver, receiver) =
+            get_request_approver_with(ApprovalTimeouts::default(), key_manager.clone());
+        assert_eq!(
+            key_manager.get_account_stats(&keys.public_key()).unwrap(),
+            AccountStats::default()
+        );
+
+        let responder =
+            respond_to_next_sign_event_request(request_approver.clone(), receiver, true, None);
+        let unsigned_event = EventBuilder::new(Kind::TextNote, "hello world", None)
+            .to_unsigned_event(keys.public_key());
+        request_approver
+            .sign_event_with_approval(unsigned_event, key_manager.as_ref())
+            .await
+            .unwrap();
+        drop(request_approver);
+        responder.await.unwrap();
+
+        let stats = key_manager.get_account_stats(&keys.public_key()).unwrap();
+        assert_eq!(stats.sign_count, 1);
+        assert_eq!(stats.payment_count, 0);
+        assert!(stats.last_active_time.is_some());
+
+        // The count is kept across restarts.
+        drop(key_manager);
+        let key_manager =
+            KeystacheKeyManager::new_with_database(Database::new_in_dir(folder.path()));
+        assert_eq!(
+            key_manager.get_account_stats(&keys.public_key()).unwrap(),
+            stats
+        );
+    }
+
+    #[tokio::test]
+    async fn signs_event_json_in_offline_mode() {
+        let key_manager = Arc::new(KeystacheKeyManager::new_with_database(
+            Database::new_in_temp_dir(),
+        ));
+        let keys = Keys::generate();
+        key_manager
+            .add_keypair(&keys.secret_key().unwrap().keypair(&Secp256k1::new()))
+            .unwrap();
+        key_manager.set_offline_mode(true).unwrap();
+        let (request_approver, receiver) =
+            get_request_approver_with(ApprovalTimeouts::default(), key_manager.clone());
+
+        let unsigned_event = EventBuilder::new(Kind::TextNote, "from an air-gapped device", None)
+            .to_unsigned_event(keys.public_key());
+        let responder =
+            respond_to_next_sign_event_request(request_approver.clone(), receiver, true, None);
+        let signed_json = request_approver
+            .sign_event_json_with_approval(&unsigned_event.as_json(), key_manager.as_ref())
+            .await
+            .unwrap();
+        drop(request_approver);
+
+        let event = Event::from_json(&signed_json).unwrap();
+        assert!(event.verify().is_ok());
+        assert_eq!(event.pubkey, keys.public_key());
+        assert_eq!(event.content, "from an air-gapped device");
+
+        // The approver has no relay connections, so the only thing emitted besides the prompt is the signed event.
+        let emitted_events = responder.await.unwrap();
+        assert_eq!(emitted_events.len(), 1);
+        assert_eq!(emitted_events[0].0, "event_signed");
+
+        let (request_approver, _receiver) = get_request_approver();
+        assert!(request_approver
+            .sign_event_json_with_approval("not an event", key_manager.as_ref())
+            .await
+            .unwrap_err()
+            .to_string()
+            .starts_with("Invalid event"));
+    }
+
+    #[tokio::test]
+    async fn dropped_request_is_no_longer_pending() {
+        let keys = Keys::generate();
+        let (request_approver, mut receiver) = get_request_approver();
+
+        // Sign an event, then drop the future waiting on the user as if the client disconnected.
+        let signing = tokio::spawn({
+            let request_approver = request_approver.clone();
+            async move {
+                let unsigned_event = EventBuilder::new(Kind::TextNote, "hello world", None)
+                    .to_unsigned_event(keys.public_key());
+                request_approver
+                    .sign_event_with_approval(unsigned_event, &SingleKeyManager { keys })
+                    .await
+            }
+        });
+        let (_, payload) = receiver.recv().await.unwrap();
+        assert_eq!(
+            request_approver
+                .in_progress_event_signings
+                .lock()
+                .unwrap()
+                .len(),
+            1
+        );
+
+        signing.abort();
+        assert!(signing.await.unwrap_err().is_cancelled());
+        assert!(request_approver
+            .in_progress_event_signings
+            .lock()
+            .unwrap()
+            .is_empty());
+
+        // Responding to the abandoned request does nothing.
+        request_approver
+            .respond_to_sign_event_request(
+                payload["event"]["id"].as_str().unwrap(),
+                true,
+                None,
+                false,
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    async fn retried_request_replaces_the_one_still_waiting() {
+        let keys = Keys::generate();
+        let key_manager = Arc::new(SingleKeyManager { keys: keys.clone() });
+        let (request_approver, mut receiver) = get_request_approver();
+
+        // The same event twice, as if an app retried a request that's still waiting on the user.
+        let unsigned_event = EventBuilder::new(Kind::TextNote, "hello world", None)
+            .to_unsigned_event(keys.public_key());
+        let sign = || {
+            let request_approver = request_approver.clone();
+            let key_manager = key_manager.clone();
+            let unsigned_event = unsigned_event.clone();
+            tokio::spawn(async move {
+                request_approver
+                    .sign_event_with_approval(unsigned_event, key_manager.as_ref())
+                    .await
+            })
+        };
+        let first_signing = sign();
+        let (_, first_payload) = receiver.recv().await.unwrap();
+        let retried_signing = sign();
+        let (_, retried_payload) = receiver.recv().await.unwrap();
+        assert_eq!(first_payload["event"]["id"], retried_payload["event"]["id"]);
+
+        // The first request is rejected, and its guard leaves the retry pending.
+        assert!(first_signing.await.unwrap().is_err());
+        assert_eq!(request_approver.list_pending_requests().len(), 1);
+
+        request_approver
+            .respond_to_sign_event_request(
+                retried_payload["event"]["id"].as_str().unwrap(),
+                true,
+                None,
+                false,
+            )
+            .await;
+        let event = retried_signing.await.unwrap().unwrap();
+        assert!(event.verify().is_ok());
+        assert!(request_approver
+            .in_progress_event_signings
+            .lock()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_sign_requests_are_all_answered() {
+        const REQUEST_COUNT: usize = 200;
+
+        let keys = Keys::generate();
+        let key_manager = Arc::new(SingleKeyManager { keys: keys.clone() });
+        let (request_approver, mut receiver) = get_request_approver();
+
+        // Approve every request as soon as it's shown, while the others are still arriving.
+        let responder = tokio::spawn({
+            let request_approver = request_approver.clone();
+            async move {
+                let mut responded_count = 0;
+                while responded_count < REQUEST_COUNT {
+                    let (name, payload) = receiver.recv().await.unwrap();
+                    if name != "sign_event_request" {
+                        continue;
+                    }
+                    let request_approver = request_approver.clone();
+                    tokio::spawn(async move {
+                        request_approver
+                            .respond_to_sign_event_request(
+                                payload["event"]["id"].as_str().unwrap(),
+                                true,
+                                None,
+                                false,
+                            )
+                            .await;
+                    });
+                    responded_count += 1;
+                }
+            }
+        });
+
+        let signings = (0..REQUEST_COUNT).map(|i| {
+            let request_approver = request_approver.clone();
+            let key_manager = key_manager.clone();
+            let unsigned_event = EventBuilder::new(Kind::TextNote, format!("note {i}"), None)
+                .to_unsigned_event(keys.public_key());
+            tokio::spawn(async move {
+                request_approver
+                    .sign_event_with_approval(unsigned_event, key_manager.as_ref())
+                    .await
+            })
+        });
+        let results =
+            tokio::time::timeout(Duration::from_secs(30), futures::future::join_all(signings))
+                .await
+                .expect("concurrent sign requests deadlocked");
+        responder.await.unwrap();
+
+        let mut contents: Vec<String> = results
+            .into_iter()
+            .map(|result| result.unwrap().unwrap().content.clone())
+            .collect();
+        contents.sort();
+        contents.dedup();
+        assert_eq!(contents.len(), REQUEST_COUNT);
+        assert!(request_approver
+            .in_progress_event_signings
+            .lock()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn watch_only_account_is_listed_but_cannot_sign() {
+        let key_manager = Arc::new(KeystacheKeyManager::new_with_database(
+            Database::new_in_temp_dir(),
+        ));
+        let keys = Keys::generate();
+        key_manager
+            .add_keypair(&keys.secret_key().unwrap().keypair(&Secp256k1::new()))
+            .unwrap();
+        let watched_public_key = Keys::generate().public_key();
+        key_manager
+            .add_watch_only_account(&watched_public_key)
+            .unwrap();
+
+        assert_eq!(
+            key_manager.list_account_entries().unwrap(),
+            vec![
+                AccountListEntry {
+                    npub: keys.public_key().to_bech32().unwrap(),
+                    can_sign: true,
+                },
+                AccountListEntry {
+                    npub: watched_public_key.to_bech32().unwrap(),
+                    can_sign: false,
+                },
+            ]
+        );
+        // An account that has a key can't also be added as watch-only.
+        assert!(key_manager
+            .add_watch_only_account(&keys.public_key())
+            .is_err());
+
+        // Signing for the watch-only account fails without the user being asked.
+        let (request_approver, mut receiver) =
+            get_request_approver_with(ApprovalTimeouts::default(), key_manager.clone());
+        let unsigned_event =
+            EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(watched_public_key);
+        assert!(request_approver
+            .sign_event_with_approval(unsigned_event, key_manager.as_ref())
+            .await
+            .is_err());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn relay_hints_are_added_to_bare_tags() {
+        let key_manager = Arc::new(KeystacheKeyManager::new_with_database(
+            Database::new_in_temp_dir(),
+        ));
+        let keys = Keys::generate();
+        key_manager
+            .add_keypair(&keys.secret_key().unwrap().keypair(&Secp256k1::new()))
+            .unwrap();
+        key_manager
+            .set_relay_policy(
+                &keys.public_key(),
+                "wss://relay.example.com",
+                RelayPolicy {
+                    read: true,
+                    write: true,
+                },
+            )
+            .unwrap();
+        key_manager
+            .set_relay_hints_enabled(&keys.public_key(), true)
+            .unwrap();
+        let (request_approver, mut receiver) =
+            get_request_approver_with(ApprovalTimeouts::default(), key_manager.clone());
+        request_approver.set_relay_hints(key_manager.get_relay_hints().unwrap());
+
+        let reply = EventBuilder::new(
+            Kind::TextNote,
+            "a reply",
+            [
+                Tag::parse(&["e", &EventId::all_zeros().to_hex()]).unwrap(),
+                Tag::parse(&["p", &keys.public_key().to_hex(), "wss://other.example.com"]).unwrap(),
+            ],
+        )
+        .to_unsigned_event(keys.public_key());
+        let (event, ()) = tokio::join!(
+            request_approver.sign_event_with_approval(reply, key_manager.as_ref()),
+            async {
+                let (_, payload) = receiver.recv().await.unwrap();
+                assert_eq!(payload["added_relay_hint"], "wss://relay.example.com");
+                assert_eq!(payload["event"]["tags"][0][2], "wss://relay.example.com");
+                request_approver
+                    .respond_to_sign_event_request(
+                        payload["event"]["id"].as_str().unwrap(),
+                        true,
+                        None,
+                        false,
+                    )
+                    .await;
+            }
+        );
+
+        // Only the bare tag gains a hint. The one that already had a hint keeps it.
+        let tags: Vec<Vec<String>> = event.unwrap().tags().iter().map(Tag::as_vec).collect();
+        assert_eq!(
+            tags,
+            vec![
+                vec![
+                    "e".to_string(),
+                    EventId::all_zeros().to_hex(),
+                    "wss://relay.example.com".to_string()
+                ],
+                vec![
+                    "p".to_string(),
+                    keys.public_key().to_hex(),
+                    "wss://other.example.com".to_string()
+                ],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn default_expiration_is_added_to_events_without_one() {
+        let key_manager = Arc::new(KeystacheKeyManager::new_with_database(
+            Database::new_in_temp_dir(),
+        ));
+        let keys = Keys::generate();
+        key_manager
+            .add_keypair(&keys.secret_key().unwrap().keypair(&Secp256k1::new()))
+            .unwrap();
+        key_manager
+            .set_default_expiration(&keys.public_key(), Some(Duration::from_secs(3600)))
+            .unwrap();
+        let (request_approver, mut receiver) =
+            get_request_approver_with(ApprovalTimeouts::default(), key_manager.clone());
+        request_approver.set_default_expirations(key_manager.get_default_expirations().unwrap());
+
+        let already_expiring = Timestamp::from(1_700_000_000);
+        for (tags, expected_added) in [
+            (vec![], true),
+            (vec![Tag::Expiration(already_expiring)], false),
+        ] {
+            let note = EventBuilder::new(Kind::TextNote, "gone in an hour", tags)
+                .to_unsigned_event(keys.public_key());
+            let signed_at = Timestamp::now();
+            let (event, ()) = tokio::join!(
+                request_approver.sign_event_with_approval(note, key_manager.as_ref()),
+                async {
+                    // Skip the `event_signed` event from the previous iteration.
+                    let payload = loop {
+                        let (name, payload) = receiver.recv().await.unwrap();
+                        if name == "sign_event_request" {
+                            break payload;
+                        }
+                    };
+                    assert_eq!(payload["added_expiration"].is_u64(), expected_added);
+                    request_approver
+                        .respond_to_sign_event_request(
+                            payload["event"]["id"].as_str().unwrap(),
+                            true,
+                            None,
+                            false,
+                        )
+                        .await;
+                }
+            );
+
+            let expirations: Vec<Timestamp> = event
+                .unwrap()
+                .tags()
+                .iter()
+                .filter_map(|tag| match tag {
+                    Tag::Expiration(expiration) => Some(*expiration),
+                    _ => None,
+                })
+                .collect();
+            if expected_added {
+                assert_eq!(expirations.len(), 1);
+                let ttl = expirations[0].as_u64() - signed_at.as_u64();
+                assert!((3600..=3601).contains(&ttl));
+            } else {
+                assert_eq!(expirations, vec![already_expiring]);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rejected_sign_does_not_emit_signed_event() {
+        let keys = Keys::generate();
+        let key_manager = SingleKeyManager { keys: keys.clone() };
+        let (request_approver, receiver) = get_request_approver();
+
+        let responder =
+            respond_to_next_sign_event_request(request_approver.clone(), receiver, false, None);
+
+        let unsigned_event = EventBuilder::new(Kind::TextNote, "hello world", None)
+            .to_unsigned_event(keys.public_key());
+        assert!(request_approver
+            .sign_event_with_approval(unsigned_event, &key_manager)
+            .await
+            .is_err());
+        drop(request_approver);
+
+        assert!(responder.await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn approved_with_edited_event_signs_edit() {
+        let keys = Keys::generate();
+        let key_manager = SingleKeyManager { keys: keys.clone() };
+        let (request_approver, receiver) = get_request_approver();
+
+        let unsigned_event = EventBuilder::new(
+            Kind::TextNote,
+            "hello world",
+            [
+                Tag::Hashtag("nostr".to_string()),
+                Tag::Hashtag("tracking".to_string()),
+            ],
+        )
+        .to_unsigned_event(keys.public_key());
+
+        // The user strips one of the tags before approving.
+        let mut edited_event = unsigned_event.clone();
+        edited_event.tags = vec![Tag::Hashtag("nostr".to_string())];
+
+        let responder = respond_to_next_sign_event_request(
+            request_approver.clone(),
+            receiver,
+            true,
+            Some(edited_event),
+        );
+
+        let event = request_approver
+            .sign_event_with_approval(unsigned_event, &key_manager)
+            .await
+            .unwrap();
+        drop(request_approver);
+        responder.await.unwrap();
+
+        assert!(event.verify().is_ok());
+        assert_eq!(event.tags(), &[Tag::Hashtag("nostr".to_string())]);
+        assert_eq!(event.content(), "hello world");
+    }
+
+    #[tokio::test]
+    async fn approved_with_swapped_event_is_refused() {
+        let keys = Keys::generate();
+        let key_manager = SingleKeyManager { keys: keys.clone() };
+
+        let unsigned_event = EventBuilder::new(Kind::TextNote, "hello world", None)
+            .to_unsigned_event(keys.public_key());
+
+        // Edits that change the kind or pubkey should cause an error rather than being signed.
+        for swapped_event in [
+            EventBuilder::new(Kind::Metadata, "{}", None).to_unsigned_event(keys.public_key()),
+            EventBuilder::new(Kind::TextNote, "hello world", None)
+                .to_unsigned_event(Keys::generate().public_key()),
+        ] {
+            let (request_approver, receiver) = get_request_approver();
+            let responder = respond_to_next_sign_event_request(
+                request_approver.clone(),
+                receiver,
+                true,
+                Some(swapped_event),
+            );
+
+            assert!(request_approver
+                .sign_event_with_approval(unsigned_event.clone(), &key_manager)
+                .await
+                .is_err());
+            drop(request_approver);
+
+            assert!(responder.await.unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_spanning_two_accounts_signed_with_each_key() {
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let key_manager = MultiKeyManager {
+            keys: vec![alice.clone(), bob.clone()],
+        };
+        let (request_approver, mut receiver) = get_request_approver();
+
+        let unsigned_events = vec![
+            EventBuilder::new(Kind::TextNote, "from alice", None)
+                .to_unsigned_event(alice.public_key()),
+            EventBuilder::new(Kind::TextNote, "from bob", None).to_unsigned_event(bob.public_key()),
+            EventBuilder::new(Kind::Reaction, "+", None).to_unsigned_event(alice.public_key()),
+        ];
+
+        let (events, ()) = tokio::join!(
+            request_approver.sign_events_with_approval(unsigned_events, &key_manager),
+            async {
+                let (name, payload) = receiver.recv().await.unwrap();
+                assert_eq!(name, "sign_events_request");
+
+                // The user is shown one group per account.
+                let accounts = payload["accounts"].as_array().unwrap();
+                assert_eq!(accounts.len(), 2);
+                assert_eq!(
+                    accounts[0]["user_npub"],
+                    alice.public_key().to_bech32().unwrap()
+                );
+                assert_eq!(accounts[0]["events"].as_array().unwrap().len(), 2);
+                assert_eq!(
+                    accounts[1]["user_npub"],
+                    bob.public_key().to_bech32().unwrap()
+                );
+                assert_eq!(accounts[1]["events"].as_array().unwrap().len(), 1);
+
+                request_approver
+                    .respond_to_sign_events_request(payload["batch_id"].as_str().unwrap(), true)
+                    .await;
+            }
+        );
+
+        let events = events.unwrap();
+        assert_eq!(events.len(), 3);
+        for (event, (content, author)) in events.iter().zip([
+            ("from alice", alice.public_key()),
+            ("from bob", bob.public_key()),
+            ("+", alice.public_key()),
+        ]) {
+            assert!(event.verify().is_ok());
+            assert_eq!(event.content(), content);
+            assert_eq!(event.author(), author);
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_with_missing_key_rejected_without_prompting() {
+        let alice = Keys::generate();
+        let key_manager = MultiKeyManager {
+            keys: vec![alice.clone()],
+        };
+        let (request_approver, mut receiver) = get_request_approver();
+
+        let unsigned_events = vec![
+            EventBuilder::new(Kind::TextNote, "from alice", None)
+                .to_unsigned_event(alice.public_key()),
+            EventBuilder::new(Kind::TextNote, "from a stranger", None)
+                .to_unsigned_event(Keys::generate().public_key()),
+        ];
+
+        assert!(request_approver
+            .sign_events_with_approval(unsigned_events, &key_manager)
+            .await
+            .is_err());
+        assert!(receiver.try_recv().is_err());
+        assert!(request_approver
+            .in_progress_batch_signings
+            .lock()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn sign_request_tagging_scam_pubkey_has_warning() {
+        let keys = Keys::generate();
+        let scammer = Keys::generate().public_key();
+        let (request_approver, mut receiver) = get_request_approver();
+        request_approver
+            .set_scam_list(ScamList::from_entries(&[scammer.to_bech32().unwrap()]).unwrap());
+
+        for (tagged, expected_warnings) in [
+            (
+                scammer,
+                serde_json::json!([{"type": "scam_list_match", "entry": scammer.to_bech32().unwrap()}]),
+            ),
+            (Keys::generate().public_key(), serde_json::json!([])),
+        ] {
+            let unsigned_event = EventBuilder::new(Kind::TextNote, "hi", [Tag::public_key(tagged)])
+                .to_unsigned_event(keys.public_key());
+            let (approval, ()) = tokio::join!(
+                request_approver.request_sign_event_approval(
+                    unsigned_event,
+                    keys.public_key(),
+                    None
+                ),
+                async {
+                    let (name, payload) = receiver.recv().await.unwrap();
+                    assert_eq!(name, "sign_event_request");
+                    assert_eq!(payload["warnings"], expected_warnings);
+                    request_approver
+                        .respond_to_sign_event_request(
+                            payload["event"]["id"].as_str().unwrap(),
+                            false,
+                            None,
+                            false,
+                        )
+                        .await;
+                }
+            );
+            assert_eq!(approval, Nip46RequestApproval::Reject);
+        }
+    }
+
+    /// Has one existing parameterized replaceable event.
+    struct SingleEventLookup {
+        event: Event,
+    }
+
+    #[async_trait]
+    impl ReplaceableEventLookup for SingleEventLookup {
+        async fn latest_replaceable_event(
+            &self,
+            author: PublicKey,
+            kind: Kind,
+            identifier: &str,
+        ) -> anyhow::Result<Option<Event>> {
+            Ok(Some(self.event.clone()).filter(|event| {
+                event.author() == author
+                    && event.kind() == kind
+                    && event.identifier() == Some(identifier)
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn sign_request_replacing_existing_event_has_warning() {
+        let keys = Keys::generate();
+        let kind = Kind::from(30023);
+        let existing_event = EventBuilder::new(kind, "old post", [Tag::Identifier("post".into())])
+            .to_event(&keys)
+            .unwrap();
+        let key_manager = Arc::new(KeystacheKeyManager::new_with_database(
+            Database::new_in_temp_dir(),
+        ));
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let request_approver = KeystacheRequestApprover::new(
+            Arc::new(ChannelEventEmitter { sender }),
+            Duration::from_secs(60),
+            Arc::new(NoPaymentBackend),
+            key_manager.clone(),
+            key_manager.clone(),
+            key_manager.clone(),
+            key_manager,
+            Arc::new(SingleEventLookup {
+                event: existing_event.clone(),
+            }),
+            ApprovalTimeouts::default(),
+        );
+
+        for (identifier, expected_warnings) in [
+            (
+                "post",
+                serde_json::json!([{
+                    "type": "replaces_existing_event",
+                    "identifier": "post",
+                    "existing_event_id": existing_event.id().to_hex(),
+                    "existing_created_at": existing_event.created_at().as_u64(),
+                }]),
+            ),
+            ("other post", serde_json::json!([])),
+        ] {
+            let unsigned_event =
+                EventBuilder::new(kind, "new post", [Tag::Identifier(identifier.into())])
+                    .to_unsigned_event(keys.public_key());
+            let (approval, ()) = tokio::join!(
+                request_approver.request_sign_event_approval(
+                    unsigned_event,
+                    keys.public_key(),
+                    None
+                ),
+                async {
+                    let (name, payload) = receiver.recv().await.unwrap();
+                    assert_eq!(name, "sign_event_request");
+                    assert_eq!(payload["warnings"], expected_warnings);
+                    request_approver
+                        .respond_to_sign_event_request(
+                            payload["event"]["id"].as_str().unwrap(),
+                            false,
+                            None,
+                            false,
+                        )
+                        .await;
+                }
+            );
+            assert_eq!(approval, Nip46RequestApproval::Reject);
+        }
+
+        // Events signed as JSON aren't looked up on relays.
+        let event_json = EventBuilder::new(kind, "new post", [Tag::Identifier("post".into())])
+            .to_unsigned_event(keys.public_key())
+            .as_json();
+        let key_manager = SingleKeyManager { keys };
+        let (result, ()) = tokio::join!(
+            request_approver.sign_event_json_with_approval(&event_json, &key_manager),
+            async {
+                let (_, payload) = receiver.recv().await.unwrap();
+                assert_eq!(payload["warnings"], serde_json::json!([]));
+                request_approver
+                    .respond_to_sign_event_request(
+                        payload["event"]["id"].as_str().unwrap(),
+                        false,
+                        None,
+                        false,
+                    )
+                    .await;
+            }
+        );
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn sign_request_shows_app_name() {
+        let database = Database::new_in_temp_dir();
+        let key_manager = KeystacheKeyManager::new_with_database(database.clone());
+        let keys = Keys::generate();
+        key_manager
+            .set_keypair(Keypair::from_secret_key(
+                &Secp256k1::new(),
+                keys.secret_key().unwrap(),
+            ))
+            .unwrap();
+        let named_app = Keys::generate().public_key();
+        let unnamed_app = Keys::generate().public_key();
+        for app in [named_app, unnamed_app] {
+            database
+                .register_application(None, &app, &keys.public_key())
+                .unwrap();
+        }
+        key_manager.set_app_name(&named_app, Some("Damus")).unwrap();
+
+        let (request_approver, mut receiver) = get_request_approver();
+        request_approver.set_app_names(key_manager.get_app_names().unwrap());
+
+        for (app, expected_name) in [
+            (named_app, "Damus".to_string()),
+            // Apps without a name are shown by their npub.
+            (unnamed_app, unnamed_app.to_bech32().unwrap()),
+        ] {
+            let unsigned_event =
+                EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(keys.public_key());
+            let (_, ()) = tokio::join!(
+                request_approver.request_sign_event_approval(
+                    unsigned_event,
+                    keys.public_key(),
+                    Some(app)
+                ),
+                async {
+                    let (_, payload) = receiver.recv().await.unwrap();
+                    assert_eq!(payload["app_name"], expected_name);
+                    assert_eq!(payload["app_npub"], app.to_bech32().unwrap());
+                    request_approver
+                        .respond_to_sign_event_request(
+                            payload["event"]["id"].as_str().unwrap(),
+                            false,
+                            None,
+                            false,
+                        )
+                        .await;
+                }
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn remembered_decision_is_used_for_same_app_and_kind() {
+        let public_key = Keys::generate().public_key();
+        let app = Keys::generate().public_key();
+        let (request_approver, mut receiver) = get_request_approver();
+
+        let text_note = EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(public_key);
+        let (approval, ()) = tokio::join!(
+            request_approver.request_sign_event_approval(text_note.clone(), public_key, Some(app)),
+            async {
+                let (_, payload) = receiver.recv().await.unwrap();
+                assert_eq!(payload["can_remember"], true);
+                request_approver
+                    .respond_to_sign_event_request(
+                        payload["event"]["id"].as_str().unwrap(),
+                        true,
+                        None,
+                        true,
+                    )
+                    .await;
+            }
+        );
+        assert_eq!(approval, Nip46RequestApproval::Approve);
+
+        // The second text note from the app is approved without asking.
+        assert_eq!(
+            request_approver
+                .request_sign_event_approval(text_note.clone(), public_key, Some(app))
+                .await,
+            Nip46RequestApproval::Approve
+        );
+        assert!(receiver.try_recv().is_err());
+
+        // A new kind from the same app, or the same kind from another app, still asks.
+        let reaction = EventBuilder::new(Kind::Reaction, "+", None).to_unsigned_event(public_key);
+        for (event, app) in [(reaction, app), (text_note, Keys::generate().public_key())] {
+            let (approval, ()) = tokio::join!(
+                request_approver.request_sign_event_approval(event, public_key, Some(app)),
+                async {
+                    let (name, payload) = receiver.recv().await.unwrap();
+                    assert_eq!(name, "sign_event_request");
+                    request_approver
+                        .respond_to_sign_event_request(
+                            payload["event"]["id"].as_str().unwrap(),
+                            false,
+                            None,
+                            false,
+                        )
+                        .await;
+                }
+            );
+            assert_eq!(approval, Nip46RequestApproval::Reject);
+        }
+    }
+
+    #[tokio::test]
+    async fn signing_history_is_kept_per_app() {
+        let public_key = Keys::generate().public_key();
+        let first_app = Keys::generate().public_key();
+        let second_app = Keys::generate().public_key();
+        let key_manager = Arc::new(KeystacheKeyManager::new_with_database(
+            Database::new_in_temp_dir(),
+        ));
+        let (request_approver, _receiver) =
+            get_request_approver_with(ApprovalTimeouts::default(), key_manager.clone());
+        key_manager
+            .remember_sign_decision(&first_app, Kind::TextNote, true)
+            .unwrap();
+        key_manager
+            .remember_sign_decision(&second_app, Kind::Reaction, false)
+            .unwrap();
+
+        let text_note = EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(public_key);
+        let reaction = EventBuilder::new(Kind::Reaction, "+", None).to_unsigned_event(public_key);
+        for (event, app) in [
+            (text_note.clone(), first_app),
+            (reaction, second_app),
+            (text_note, first_app),
+        ] {
+            request_approver
+                .request_sign_event_approval(event, public_key, Some(app))
+                .await;
+        }
+
+        let first_history = key_manager
+            .get_app_signing_history(&first_app, 10, 0)
+            .unwrap();
+        assert_eq!(first_history.len(), 2);
+        assert!(first_history.iter().all(|entry| {
+            entry.app_npub == first_app.to_bech32().unwrap()
+                && entry.kind == Kind::TextNote.as_u64()
+                && entry.outcome == SigningOutcome::Approved
+        }));
+
+        let second_history = key_manager
+            .get_app_signing_history(&second_app, 10, 0)
+            .unwrap();
+        assert_eq!(second_history.len(), 1);
+        assert_eq!(second_history[0].app_npub, second_app.to_bech32().unwrap());
+        assert_eq!(second_history[0].kind, Kind::Reaction.as_u64());
+        assert_eq!(second_history[0].outcome, SigningOutcome::Rejected);
+
+        assert_eq!(
+            key_manager
+                .get_app_signing_history(&first_app, 10, 1)
+                .unwrap(),
+            first_history[1..]
+        );
+    }
+
+    #[tokio::test]
+    async fn paused_servers_reject_new_requests_until_resumed() {
+        let key_manager = SingleKeyManager {
+            keys: Keys::generate(),
+        };
+        let public_key = key_manager.keys.public_key();
+        let app_public_key = Keys::generate().public_key();
+        let (request_approver, mut receiver) = get_request_approver();
+        let sign_request = |content: &str| {
+            nip46::Request::SignEvent(
+                EventBuilder::new(Kind::TextNote, content, None).to_unsigned_event(public_key),
+            )
+        };
+
+        // A request that's already waiting for the user when the servers are paused still goes through.
+        let (result, ()) = tokio::join!(
+            request_approver.handle_request(
+                sign_request("before"),
+                public_key,
+                app_public_key,
+                &key_manager
+            ),
+            async {
+                let (_, payload) = receiver.recv().await.unwrap();
+                request_approver.pause_servers();
+                request_approver
+                    .respond_to_sign_event_request(
+                        payload["event"]["id"].as_str().unwrap(),
+                        true,
+                        None,
+                        false,
+                    )
+                    .await;
+            }
+        );
+        assert!(result.is_ok());
+        assert_eq!(receiver.recv().await.unwrap().0, "event_signed");
+
+        // New requests are rejected without asking.
+        assert!(request_approver.are_servers_paused());
+        assert_eq!(
+            request_approver
+                .handle_request(
+                    sign_request("while paused"),
+                    public_key,
+                    app_public_key,
+                    &key_manager
+                )
+                .await,
+            Err(REQUEST_REJECTED.to_string())
+        );
+        assert!(receiver.try_recv().is_err());
+
+        request_approver.resume_servers();
+        let (result, ()) = tokio::join!(
+            request_approver.handle_request(
+                sign_request("after"),
+                public_key,
+                app_public_key,
+                &key_manager
+            ),
+            async {
+                let (_, payload) = receiver.recv().await.unwrap();
+                request_approver
+                    .respond_to_sign_event_request(
+                        payload["event"]["id"].as_str().unwrap(),
+                        true,
+                        None,
+                        false,
+                    )
+                    .await;
+            }
+        );
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn server_requests_are_handled_for_the_requesting_app() {
+        use crate::nip46_server::{self, Nip46Server};
+        use nip_55::json_rpc::JsonRpcResponseData;
+        use nostr_sdk::secp256k1::rand::thread_rng;
+
+        let database = Database::new_in_temp_dir();
+        let keypair = Keypair::new(&Secp256k1::new(), &mut thread_rng());
+        database.save_keypair(&keypair).unwrap();
+        let public_key: PublicKey = keypair.x_only_public_key().0.into();
+        let key_manager = Arc::new(KeystacheKeyManager::new_with_database(database));
+        let (request_approver, mut receiver) =
+            get_request_approver_with(ApprovalTimeouts::default(), key_manager.clone());
+        let uds_address = tempfile::TempDir::new()
+            .unwrap()
+            .into_path()
+            .join("nip55.sock")
+            .to_string_lossy()
+            .to_string();
+        let _server =
+            Nip46Server::start(&uds_address, key_manager.clone(), request_approver.clone())
+                .unwrap();
+
+        let remembered_app = Keys::generate();
+        let other_app = Keys::generate();
+        key_manager
+            .remember_sign_decision(&remembered_app.public_key(), Kind::TextNote, true)
+            .unwrap();
+        let sign_request = || {
+            nip46::Request::SignEvent(
+                EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(public_key),
+            )
+        };
+
+        // The decision remembered for the app is used, so the user isn't asked.
+        let response =
+            nip46_server::send_request(&uds_address, &remembered_app, public_key, sign_request())
+                .await
+                .unwrap();
+        let JsonRpcResponseData::Success { result } = response.data() else {
+            panic!("Expected the request to succeed");
+        };
+        let event = Event::from_json(result.as_str().unwrap()).unwrap();
+        assert_eq!(event.pubkey, public_key);
+        assert!(event.verify().is_ok());
+        assert_eq!(
+            receiver.recv().await.unwrap(),
+            (
+                "event_signed".to_string(),
+                serde_json::Value::String(event.as_json())
+            )
+        );
+
+        // Another app's request is asked about, and rejected.
+        let (response, ()) = tokio::join!(
+            nip46_server::send_request(&uds_address, &other_app, public_key, sign_request()),
+            async {
+                let (name, payload) = receiver.recv().await.unwrap();
+                assert_eq!(name, "sign_event_request");
+                request_approver
+                    .respond_to_sign_event_request(
+                        payload["event"]["id"].as_str().unwrap(),
+                        false,
+                        None,
+                        false,
+                    )
+                    .await;
+            }
+        );
+        assert!(matches!(
+            response.unwrap().data(),
+            JsonRpcResponseData::Error { .. }
+        ));
+
+        // Both outcomes are in the signing history of the app that asked.
+        for (app, outcome) in [
+            (&remembered_app, SigningOutcome::Approved),
+            (&other_app, SigningOutcome::Rejected),
+        ] {
+            let history = key_manager
+                .get_app_signing_history(&app.public_key(), 10, 0)
+                .unwrap();
+            assert_eq!(history.len(), 1);
+            assert_eq!(history[0].outcome, outcome);
+        }
+    }
+
+    #[tokio::test]
+    async fn server_refuses_apps_missing_from_app_key_allowlist() {
+        use crate::nip46_server::{self, Nip46Server};
+        use nostr_sdk::secp256k1::rand::thread_rng;
+
+        let database = Database::new_in_temp_dir();
+        let keypair = Keypair::new(&Secp256k1::new(), &mut thread_rng());
+        database.save_keypair(&keypair).unwrap();
+        let public_key: PublicKey = keypair.x_only_public_key().0.into();
+        let key_manager = Arc::new(KeystacheKeyManager::new_with_database(database));
+        let (request_approver, mut receiver) =
+            get_request_approver_with(ApprovalTimeouts::default(), key_manager.clone());
+        let uds_address = tempfile::TempDir::new()
+            .unwrap()
+            .into_path()
+            .join("nip55.sock")
+            .to_string_lossy()
+            .to_string();
+        let _server =
+            Nip46Server::start(&uds_address, key_manager.clone(), request_approver.clone())
+                .unwrap();
+
+        let allowed_app = Keys::generate();
+        request_approver.set_app_key_allowlist(
+            AppKeyAllowlist::new(vec![allowed_app.public_key().to_bech32().unwrap()]).unwrap(),
+        );
+        let sign_request = || {
+            nip46::Request::SignEvent(
+                EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(public_key),
+            )
+        };
+
+        // The connection is closed without a response, and the user isn't asked.
+        assert!(nip46_server::send_request(
+            &uds_address,
+            &Keys::generate(),
+            public_key,
+            sign_request()
+        )
+        .await
+        .is_err());
+        assert!(receiver.try_recv().is_err());
+
+        let (response, ()) = tokio::join!(
+            nip46_server::send_request(&uds_address, &allowed_app, public_key, sign_request()),
+            async {
+                let (name, payload) = receiver.recv().await.unwrap();
+                assert_eq!(name, "sign_event_request");
+                request_approver
+                    .respond_to_sign_event_request(
+                        payload["event"]["id"].as_str().unwrap(),
+                        true,
+                        None,
+                        false,
+                    )
+                    .await;
+            }
+        );
+        assert!(response.is_ok());
+    }
+
+    #[cfg(feature = "pkcs11")]
+    #[tokio::test]
+    async fn signs_with_hardware_signer_instead_of_vault() {
+        use crate::nip46_server::{self, Nip46Server};
+        use crate::pkcs11::{MockToken, Pkcs11Signer};
+        use nip_55::json_rpc::JsonRpcResponseData;
+
+        let keys = Keys::generate();
+        let (request_approver, mut receiver) = get_request_approver();
+        let token = Arc::new(MockToken {
+            key_label: "nostr".to_string(),
+            keys: keys.clone(),
+        });
+        request_approver.set_hardware_signer(Arc::new(Pkcs11Signer::new(token, "nostr").unwrap()));
+
+        // The vault has no key for the account, so the event can only be signed on the token.
+        let key_manager = KeystacheKeyManager::new_with_database(Database::new_in_temp_dir());
+        let unsigned_event =
+            EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(keys.public_key());
+        let (event, ()) = tokio::join!(
+            request_approver.sign_event_with_approval(unsigned_event, &key_manager),
+            async {
+                let (_, payload) = receiver.recv().await.unwrap();
+                request_approver
+                    .respond_to_sign_event_request(
+                        payload["event"]["id"].as_str().unwrap(),
+                        true,
+                        None,
+                        false,
+                    )
+                    .await;
+            }
+        );
+        let event = event.unwrap();
+        assert_eq!(event.author(), keys.public_key());
+        assert!(event.verify().is_ok());
+        assert_eq!(receiver.recv().await.unwrap().0, "event_signed");
+
+        // So are NIP-46 requests, which the server also decrypts and answers with the token.
+        let key_manager = Arc::new(key_manager);
+        let uds_address = tempfile::TempDir::new()
+            .unwrap()
+            .into_path()
+            .join("nip55.sock")
+            .to_string_lossy()
+            .to_string();
+        let _server =
+            Nip46Server::start(&uds_address, key_manager, request_approver.clone()).unwrap();
+        let app_keys = Keys::generate();
+        let unsigned_event =
+            EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(keys.public_key());
+        let (response, ()) = tokio::join!(
+            nip46_server::send_request(
+                &uds_address,
+                &app_keys,
+                keys.public_key(),
+                nip46::Request::SignEvent(unsigned_event)
+            ),
+            async {
+                let (_, payload) = receiver.recv().await.unwrap();
+                request_approver
+                    .respond_to_sign_event_request(
+                        payload["event"]["id"].as_str().unwrap(),
+                        true,
+                        None,
+                        false,
+                    )
+                    .await;
+            }
+        );
+        let response = response.unwrap();
+        let JsonRpcResponseData::Success { result } = response.data() else {
+            panic!("Expected the event to be signed");
+        };
+        let event = Event::from_json(result.as_str().unwrap()).unwrap();
+        assert_eq!(event.author(), keys.public_key());
+        assert!(event.verify().is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn session_grant_approves_until_it_expires() {
+        let public_key = Keys::generate().public_key();
+        let app = Keys::generate().public_key();
+        let (request_approver, mut receiver) = get_request_approver();
+        request_approver
+            .grant_session(app, Duration::from_secs(600), vec![Kind::TextNote])
+            .unwrap();
+
+        // Within the window, the app's text notes are approved without asking.
+        let text_note = EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(public_key);
+        tokio::time::advance(Duration::from_secs(599)).await;
+        assert_eq!(
+            request_approver
+                .request_sign_event_approval(text_note.clone(), public_key, Some(app))
+                .await,
+            Nip46RequestApproval::Approve
+        );
+        assert!(receiver.try_recv().is_err());
+
+        // Once it expires, the user is asked again, and the grant is cleaned up.
+        tokio::time::advance(Duration::from_secs(1)).await;
+        let (approval, ()) = tokio::join!(
+            request_approver.request_sign_event_approval(text_note, public_key, Some(app)),
+            async {
+                let (name, payload) = receiver.recv().await.unwrap();
+                assert_eq!(name, "sign_event_request");
+                request_approver
+                    .respond_to_sign_event_request(
+                        payload["event"]["id"].as_str().unwrap(),
+                        false,
+                        None,
+                        false,
+                    )
+                    .await;
+            }
+        );
+        assert_eq!(approval, Nip46RequestApproval::Reject);
+        assert!(request_approver.list_session_grants().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn quiet_hours_hold_back_requests() {
+        let public_key = Keys::generate().public_key();
+        let unsigned_event =
+            EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(public_key);
+        let quiet_hours_with = |action| QuietHours {
+            start_minute: 22 * 60,
+            end_minute: 7 * 60,
+            action,
+        };
+
+        // During quiet hours, requests are rejected without the user being asked.
+        let (mut request_approver, mut receiver) = get_request_approver();
+        Arc::get_mut(&mut request_approver).unwrap().local_time =
+            || NaiveTime::from_hms_opt(3, 0, 0).unwrap();
+        request_approver.set_quiet_hours(Some(quiet_hours_with(QuietHoursAction::Reject)));
+        assert_eq!(
+            request_approver
+                .request_sign_event_approval(unsigned_event.clone(), public_key, None)
+                .await,
+            Nip46RequestApproval::Reject
+        );
+        assert_eq!(
+            request_approver
+                .pay_invoice(Bolt11Invoice::from_str(INVOICE).unwrap(), public_key, None)
+                .await
+                .unwrap(),
+            PaymentResult::Rejected
+        );
+        assert!(receiver.try_recv().is_err());
+
+        // Or, if configured, the user is asked but has to unlock Keystache first.
+        request_approver.set_quiet_hours(Some(quiet_hours_with(QuietHoursAction::RequireUnlock)));
+        let (approval, ()) = tokio::join!(
+            request_approver.request_sign_event_approval(unsigned_event.clone(), public_key, None),
+            async {
+                let (_, payload) = receiver.recv().await.unwrap();
+                assert_eq!(payload["requires_unlock"], true);
+                request_approver
+                    .respond_to_sign_event_request(
+                        payload["event"]["id"].as_str().unwrap(),
+                        true,
+                        None,
+                        false,
+                    )
+                    .await;
+            }
+        );
+        assert_eq!(approval, Nip46RequestApproval::Approve);
+
+        // Outside of quiet hours, requests are handled as usual.
+        let (mut request_approver, mut receiver) = get_request_approver();
+        Arc::get_mut(&mut request_approver).unwrap().local_time =
+            || NaiveTime::from_hms_opt(12, 0, 0).unwrap();
+        request_approver.set_quiet_hours(Some(quiet_hours_with(QuietHoursAction::Reject)));
+        let (approval, ()) = tokio::join!(
+            request_approver.request_sign_event_approval(unsigned_event, public_key, None),
+            async {
+                let (_, payload) = receiver.recv().await.unwrap();
+                assert_eq!(payload["requires_unlock"], false);
+                request_approver
+                    .respond_to_sign_event_request(
+                        payload["event"]["id"].as_str().unwrap(),
+                        true,
+                        None,
+                        false,
+                    )
+                    .await;
+            }
+        );
+        assert_eq!(approval, Nip46RequestApproval::Approve);
+    }
+
+    #[tokio::test]
+    async fn paired_app_keeps_its_session_after_reconnecting() {
+        let database = Database::new_in_temp_dir();
+        let key_manager = Arc::new(KeystacheKeyManager::new_with_database(database.clone()));
+        let keys = Keys::generate();
+        let public_key = keys.public_key();
+        key_manager
+            .set_keypair(Keypair::from_secret_key(
+                &Secp256k1::new(),
+                keys.secret_key().unwrap(),
+            ))
+            .unwrap();
+        let app = Keys::generate().public_key();
+        database
+            .register_application(None, &app, &public_key)
+            .unwrap();
+
+        let (request_approver, _receiver) =
+            get_request_approver_with(ApprovalTimeouts::default(), key_manager.clone());
+        request_approver
+            .grant_session(app, Duration::from_secs(600), vec![Kind::TextNote])
+            .unwrap();
+        key_manager
+            .set_session_grants(&request_approver.stored_session_grants().unwrap())
+            .unwrap();
+
+        // Simulate a reconnect after everything in memory is lost, e.g. because Keystache restarted.
+        drop(request_approver);
+        let (request_approver, mut receiver) =
+            get_request_approver_with(ApprovalTimeouts::default(), key_manager.clone());
+        request_approver.restore_session_grants(&key_manager.get_session_grants().unwrap());
+
+        // The app is still paired, and still has its session.
+        let authorizations = key_manager.list_authorizations().unwrap();
+        assert_eq!(authorizations.len(), 1);
+        assert_eq!(authorizations[0].app_npub, app.to_bech32().unwrap());
+        let text_note = EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(public_key);
+        assert_eq!(
+            request_approver
+                .request_sign_event_approval(text_note, public_key, Some(app))
+                .await,
+            Nip46RequestApproval::Approve
+        );
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn lists_pending_requests() {
+        let (request_approver, mut receiver) = get_request_approver();
+        let public_key = Keys::generate().public_key();
+        let app_public_key = Keys::generate().public_key();
+        let unsigned_event =
+            EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(public_key);
+        assert!(request_approver.list_pending_requests().is_empty());
+
+        let (approval, payment, ()) = tokio::join!(
+            request_approver.request_sign_event_approval(
+                unsigned_event,
+                public_key,
+                Some(app_public_key)
+            ),
+            request_approver.pay_invoice(
+                Bolt11Invoice::from_str(INVOICE).unwrap(),
+                public_key,
+                None
+            ),
+            async {
+                let mut payloads = HashMap::new();
+                for _ in 0..2 {
+                    let (name, payload) = receiver.recv().await.unwrap();
+                    payloads.insert(name, payload);
+                }
+                let event_payload = &payloads["sign_event_request"];
+                let event_id = event_payload["event"]["id"].as_str().unwrap();
+
+                let mut pending = request_approver.list_pending_requests();
+                pending.sort_by_key(|request| request.id != event_id);
+                assert_eq!(pending.len(), 2);
+                assert_eq!(pending[0].id, event_id);
+                assert_eq!(pending[0].kind, PendingRequestKind::SignEvent);
+                assert_eq!(
+                    pending[0].app_npub,
+                    Some(app_public_key.to_bech32().unwrap())
+                );
+                assert_eq!(&pending[0].payload, event_payload);
+                assert_eq!(pending[1].id, INVOICE);
+                assert_eq!(pending[1].kind, PendingRequestKind::PayInvoice);
+                assert_eq!(pending[1].app_npub, None);
+                assert_eq!(&pending[1].payload, &payloads["pay_invoice_request"]);
+
+                request_approver
+                    .respond_to_sign_event_request(event_id, true, None, false)
+                    .await;
+                request_approver
+                    .respond_to_pay_invoice_request(INVOICE, false, None)
+                    .await
+                    .unwrap();
+            }
+        );
+        assert_eq!(approval, Nip46RequestApproval::Approve);
+        assert_eq!(payment.unwrap(), PaymentResult::Rejected);
+
+        // Requests that have been responded to aren't listed.
+        assert!(request_approver.list_pending_requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn max_single_payment_holds_back_large_invoices() {
+        let public_key = Keys::generate().public_key();
+        let invoice = payable_invoice();
+
+        // Invoices over the cap are rejected without the user being asked.
+        let (request_approver, mut receiver) = get_request_approver();
+        request_approver.set_max_single_payment(Some(MaxSinglePayment {
+            max_sats: 100_000,
+            action: OverCapAction::Reject,
+        }));
+        let err = request_approver
+            .pay_invoice(invoice.clone(), public_key, None)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PayInvoiceError>(),
+            Some(&PayInvoiceError::AmountTooLarge {
+                amount_sats: Some(250_000),
+                max_sats: 100_000,
+            })
+        );
+        assert!(receiver.try_recv().is_err());
+
+        // Or, if configured, the user is asked but has to unlock Keystache first.
+        request_approver.set_max_single_payment(Some(MaxSinglePayment {
+            max_sats: 100_000,
+            action: OverCapAction::RequireUnlock,
+        }));
+        let (approval, ()) = tokio::join!(
+            request_approver.pay_invoice(invoice.clone(), public_key, None),
+            async {
+                let (_, payload) = receiver.recv().await.unwrap();
+                assert_eq!(payload["requires_unlock"], true);
+                request_approver
+                    .respond_to_pay_invoice_request(
+                        payload["invoice"].as_str().unwrap(),
+                        false,
+                        None,
+                    )
+                    .await
+                    .unwrap();
+            }
+        );
+        assert_eq!(approval.unwrap(), PaymentResult::Rejected);
+
+        // Invoices under the cap are handled as usual.
+        request_approver.set_max_single_payment(Some(MaxSinglePayment {
+            max_sats: 1_000_000,
+            action: OverCapAction::Reject,
+        }));
+        let (approval, ()) = tokio::join!(
+            request_approver.pay_invoice(invoice.clone(), public_key, None),
+            async {
+                let (_, payload) = receiver.recv().await.unwrap();
+                assert_eq!(payload["requires_unlock"], false);
+                request_approver
+                    .respond_to_pay_invoice_request(
+                        payload["invoice"].as_str().unwrap(),
+                        true,
+                        None,
+                    )
+                    .await
+                    .unwrap();
+            }
+        );
+        assert_eq!(
+            approval.unwrap(),
+            PaymentResult::Paid(
+                PayInvoiceResponse::new(&invoice, &nostr_sdk::util::hex::encode(PREIMAGE)).unwrap()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn large_payment_needs_confirmation_phrase() {
+        let public_key = Keys::generate().public_key();
+        let invoice = payable_invoice();
+        let (request_approver, mut receiver) = get_request_approver();
+        request_approver.set_max_single_payment(Some(MaxSinglePayment {
+            max_sats: 100_000,
+            action: OverCapAction::RequireUnlock,
+        }));
+        request_approver.set_confirmation_phrases(ConfirmationPhrasePolicy {
+            actions: vec![HighRiskAction::LargePayment],
+        });
+
+        let (approval, ()) = tokio::join!(
+            request_approver.pay_invoice(invoice, public_key, None),
+            async {
+                let (_, payload) = receiver.recv().await.unwrap();
+                assert_eq!(payload["confirmation_phrase"], "pay invoice");
+                let invoice = payload["invoice"].as_str().unwrap();
+
+                // Approving without the exact phrase fails, and leaves the request waiting.
+                for typed_phrase in [None, Some("Pay invoice")] {
+                    assert!(request_approver
+                        .respond_to_pay_invoice_request(invoice, true, typed_phrase)
+                        .await
+                        .unwrap_err()
+                        .is::<confirmation_phrase::ConfirmationPhraseError>());
+                }
+                assert_eq!(request_approver.list_pending_requests().len(), 1);
+
+                request_approver
+                    .respond_to_pay_invoice_request(invoice, true, Some("pay invoice"))
+                    .await
+                    .unwrap();
+            }
+        );
+        assert!(matches!(approval.unwrap(), PaymentResult::Paid(_)));
+    }
+
+    #[tokio::test]
+    async fn max_event_age_rejects_old_events() {
+        let keys = Keys::generate();
+        let key_manager = SingleKeyManager { keys: keys.clone() };
+        let old_event = EventBuilder::new(Kind::TextNote, "hi", None)
+            .custom_created_at(Timestamp::now() - Duration::from_secs(2 * 60 * 60))
+            .to_unsigned_event(keys.public_key());
+
+        // With the policy on, the old event is rejected without the user being asked.
+        let (request_approver, mut receiver) = get_request_approver();
+        request_approver.set_max_event_age(Some(Duration::from_secs(60 * 60)));
+        assert!(request_approver
+            .sign_event_with_approval(old_event.clone(), &key_manager)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("maximum event age"));
+        assert_eq!(
+            request_approver
+                .request_sign_event_approval(old_event.clone(), keys.public_key(), None)
+                .await,
+            Nip46RequestApproval::Reject
+        );
+        assert!(receiver.try_recv().is_err());
+
+        // With the policy off, it's signed as usual.
+        request_approver.set_max_event_age(None);
+        let responder =
+            respond_to_next_sign_event_request(request_approver.clone(), receiver, true, None);
+        let event = request_approver
+            .sign_event_with_approval(old_event.clone(), &key_manager)
+            .await
+            .unwrap();
+        drop(request_approver);
+        responder.await.unwrap();
+        assert_eq!(event.created_at, old_event.created_at);
+    }
+
+    #[tokio::test]
+    async fn requests_fail_fast_without_approval_window() {
+        let keys = Keys::generate();
+        let key_manager = SingleKeyManager { keys: keys.clone() };
+        let keystache_key_manager = Arc::new(KeystacheKeyManager::new_with_database(
+            Database::new_in_temp_dir(),
+        ));
+        let request_approver = KeystacheRequestApprover::new(
+            Arc::new(WindowlessEventEmitter),
+            Duration::from_secs(60),
+            Arc::new(MockPaymentBackend),
+            keystache_key_manager.clone(),
+            keystache_key_manager.clone(),
+            keystache_key_manager.clone(),
+            keystache_key_manager.clone(),
+            keystache_key_manager,
+            ApprovalTimeouts::default(),
+        );
+        let unsigned_event =
+            EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(keys.public_key());
+
+        // Well within the approval timeout, so the requests can't have just timed out.
+        let fail_fast = Duration::from_secs(5);
+        let err = tokio::time::timeout(
+            fail_fast,
+            request_approver.sign_event_with_approval(unsigned_event.clone(), &key_manager),
+        )
+        .await
+        .unwrap()
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<NoApprovalWindowError>(),
+            Some(&NoApprovalWindowError {
+                reason: "no display".to_string()
+            })
+        );
+        assert!(err.to_string().contains("No window is open"));
+
+        assert_eq!(
+            tokio::time::timeout(
+                fail_fast,
+                request_approver.pay_invoice(
+                    Bolt11Invoice::from_str(INVOICE).unwrap(),
+                    keys.public_key(),
+                    None,
+                ),
+            )
+            .await
+            .unwrap()
+            .unwrap(),
+            PaymentResult::Rejected
+        );
+
+        assert_eq!(
+            tokio::time::timeout(
+                fail_fast,
+                request_approver.request_sign_event_approval(
+                    unsigned_event,
+                    keys.public_key(),
+                    None
+                )
+            )
+            .await
+            .unwrap(),
+            Nip46RequestApproval::Reject
+        );
+        assert!(request_approver.list_pending_requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_emit_does_not_leave_requests_pending() {
+        let keys = Keys::generate();
+        let key_manager = SingleKeyManager { keys: keys.clone() };
+        let keystache_key_manager = Arc::new(KeystacheKeyManager::new_with_database(
+            Database::new_in_temp_dir(),
+        ));
+        let request_approver = KeystacheRequestApprover::new(
+            Arc::new(FailingEventEmitter),
+            Duration::from_secs(60),
+            Arc::new(MockPaymentBackend),
+            keystache_key_manager.clone(),
+            keystache_key_manager.clone(),
+            keystache_key_manager.clone(),
+            keystache_key_manager.clone(),
+            keystache_key_manager,
+            ApprovalTimeouts::default(),
+        );
+        let unsigned_event =
+            EventBuilder::new(Kind::TextNote, "hi", None).to_unsigned_event(keys.public_key());
+
+        assert_eq!(
+            request_approver
+                .request_sign_event_approval(unsigned_event.clone(), keys.public_key(), None)
+                .await,
+            Nip46RequestApproval::Reject
+        );
+        assert!(request_approver
+            .sign_events_with_approval(vec![unsigned_event], &key_manager)
+            .await
+            .is_err());
+        assert_eq!(
+            request_approver
+                .pay_invoice(
+                    Bolt11Invoice::from_str(INVOICE).unwrap(),
+                    keys.public_key(),
+                    None
+                )
+                .await
+                .unwrap(),
+            PaymentResult::Rejected
+        );
+
+        assert!(request_approver
+            .in_progress_event_signings
+            .lock()
+            .unwrap()
+            .is_empty());
+        assert!(request_approver
+            .in_progress_batch_signings
+            .lock()
+            .unwrap()
+            .is_empty());
+        assert!(request_approver
+            .in_progress_invoice_payments
+            .lock()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn payments_are_recorded_in_payment_log() {
+        let key_manager = Arc::new(KeystacheKeyManager::new_with_database(
+            Database::new_in_temp_dir(),
+        ));
+        let (request_approver, mut receiver) =
+            get_request_approver_with(ApprovalTimeouts::default(), key_manager.clone());
+        let user_public_key = Keys::generate().public_key();
+        let app_public_key = Keys::generate().public_key();
+        let invoice = payable_invoice();
+
+        // The user rejects the first request to pay the invoice, then approves the app's retry.
+        for approved in [false, true] {
+            let (approval, ()) = tokio::join!(
+                request_approver.pay_invoice(
+                    invoice.clone(),
+                    user_public_key,
+                    Some(app_public_key)
+                ),
+                async {
+                    let (name, payload) = receiver.recv().await.unwrap();
+                    assert_eq!(name, "pay_invoice_request");
+                    request_approver
+                        .respond_to_pay_invoice_request(
+                            payload["invoice"].as_str().unwrap(),
+                            approved,
+                            None,
+                        )
+                        .await
+                        .unwrap();
+                }
+            );
+            let approval = approval.unwrap();
+            if approved {
+                assert!(matches!(approval, PaymentResult::Paid(_)));
+            } else {
+                assert_eq!(approval, PaymentResult::Rejected);
+            }
+        }
+
+        let history = key_manager
+            .get_payment_history(10, 0, &PaymentHistoryFilter::default())
+            .unwrap();
+        assert_eq!(history.len(), 2);
+        for (entry, (outcome, preimage)) in history.iter().zip([
+            (
+                PaymentOutcome::Paid,
+                Some(nostr_sdk::util::hex::encode(PREIMAGE)),
+            ),
+            (PaymentOutcome::Rejected, None),
+        ]) {
+            assert_eq!(entry.invoice, invoice.to_string());
+            assert_eq!(entry.amount_msats, Some(250_000_000));
+            assert_eq!(entry.description, Some("1 cup coffee".to_string()));
+            assert_eq!(entry.app_npub, Some(app_public_key.to_bech32().unwrap()));
+            assert_eq!(entry.outcome, outcome);
+            assert_eq!(entry.preimage, preimage);
+        }
+
+        // Filtering by outcome only returns the matching payment.
+        let rejected = key_manager
+            .get_payment_history(
+                10,
+                0,
+                &PaymentHistoryFilter {
+                    outcome: Some(PaymentOutcome::Rejected),
+                    app_npub: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(rejected, vec![history[1].clone()]);
+
+        // As does filtering by app.
+        assert!(key_manager
+            .get_payment_history(
+                10,
+                0,
+                &PaymentHistoryFilter {
+                    outcome: None,
+                    app_npub: Some(Keys::generate().public_key().to_bech32().unwrap()),
+                },
+            )
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn payments_are_held_without_payment_backend() {
+        let key_manager = Arc::new(KeystacheKeyManager::new_with_database(
+            Database::new_in_temp_dir(),
+        ));
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let request_approver = KeystacheRequestApprover::new(
+            Arc::new(ChannelEventEmitter { sender }),
+            Duration::from_secs(60),
+            Arc::new(NoPaymentBackend),
+            key_manager.clone(),
+            key_manager.clone(),
+            key_manager.clone(),
+            key_manager.clone(),
+            key_manager.clone(),
+            ApprovalTimeouts::default(),
+        );
+
+        // The user isn't asked to approve a payment that couldn't be made, and nothing is recorded.
+        let err = request_approver
+            .pay_invoice(payable_invoice(), Keys::generate().public_key(), None)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PayInvoiceError>(),
+            Some(&PayInvoiceError::NoPaymentBackend)
+        );
+        assert!(receiver.try_recv().is_err());
+        assert!(request_approver.list_pending_requests().is_empty());
+        assert!(key_manager
+            .get_payment_history(10, 0, &PaymentHistoryFilter::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn mismatched_preimage_fails_the_payment() {
+        let key_manager = Arc::new(KeystacheKeyManager::new_with_database(
+            Database::new_in_temp_dir(),
+        ));
+        let (request_approver, mut receiver) =
+            get_request_approver_with(ApprovalTimeouts::default(), key_manager.clone());
+        let user_public_key = Keys::generate().public_key();
+
+        // The backend pays every invoice with `PREIMAGE`, which doesn't hash to this invoice's payment hash.
+        for _ in 0..2 {
+            let (payment, ()) = tokio::join!(
+                request_approver.pay_invoice(
+                    Bolt11Invoice::from_str(INVOICE).unwrap(),
+                    user_public_key,
+                    None
+                ),
+                async {
+                    let (_, payload) = receiver.recv().await.unwrap();
+                    request_approver
+                        .respond_to_pay_invoice_request(
+                            payload["invoice"].as_str().unwrap(),
+                            true,
+                            None,
+                        )
+                        .await
+                        .unwrap();
+                }
+            );
+            assert!(matches!(payment.unwrap(), PaymentResult::Failed { .. }));
+        }
+
+        // Failed payments aren't deduplicated, so the retry asked the user again and was recorded separately.
+        let history = key_manager
+            .get_payment_history(10, 0, &PaymentHistoryFilter::default())
+            .unwrap();
+        assert_eq!(history.len(), 2);
+        for entry in &history {
+            assert_eq!(entry.outcome, PaymentOutcome::Failed);
+            assert_eq!(entry.preimage, None);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn requests_time_out_per_kind() {
+        let (request_approver, _receiver) = get_request_approver_with_timeouts(ApprovalTimeouts {
+            default_secs: 60,
+            sign_event_kind_secs: BTreeMap::from([(1, 10), (4, 30)]),
+            pay_invoice_secs: Some(300),
+        });
+        let public_key = Keys::generate().public_key();
+
+        for (kind, timeout) in [
+            (Kind::TextNote, Duration::from_secs(10)),
+            (Kind::EncryptedDirectMessage, Duration::from_secs(30)),
+            (Kind::Reaction, Duration::from_secs(60)),
+        ] {
+            let start = tokio::time::Instant::now();
+            let approval = request_approver
+                .request_sign_event_approval(
+                    EventBuilder::new(kind, "", None).to_unsigned_event(public_key),
+                    public_key,
+                    None,
+                )
+                .await;
+
+            assert_eq!(approval, Nip46RequestApproval::Reject);
+            assert_eq!(start.elapsed(), timeout, "timeout for kind {kind}");
+        }
+
+        let start = tokio::time::Instant::now();
+        let approval = request_approver
+            .pay_invoice(Bolt11Invoice::from_str(INVOICE).unwrap(), public_key, None)
+            .await
+            .unwrap();
+        assert_eq!(approval, PaymentResult::Rejected);
+        assert_eq!(start.elapsed(), Duration::from_secs(300));
+
+        // Timed out requests shouldn't be left pending.
+        assert!(request_approver
+            .in_progress_event_signings
+            .lock()
+            .unwrap()
+            .is_empty());
+        assert!(request_approver
+            .in_progress_invoice_payments
+            .lock()
+            .unwrap()
+            .is_empty());
+    }
+}
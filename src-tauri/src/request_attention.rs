@@ -0,0 +1,89 @@
+use crate::request_approver::PendingRequestKind;
+use serde::{Deserialize, Serialize};
+
+/// How Keystache draws the user's attention to a request that arrives while its window isn't focused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestAttention {
+    /// Whether to bring the window to the front and focus it.
+    pub focus_window: bool,
+
+    /// Whether to show a notification naming the app and what it's asking for.
+    pub notify: bool,
+}
+
+impl Default for RequestAttention {
+    fn default() -> Self {
+        Self {
+            focus_window: true,
+            notify: false,
+        }
+    }
+}
+
+/// Notification shown for a request that arrived while the window wasn't focused.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RequestNotification {
+    pub title: String,
+    pub body: String,
+}
+
+impl RequestAttention {
+    /// The notification to show for a request of `kind` from the app named `app_name` (if known), or `None` if
+    /// notifications are turned off.
+    pub fn notification(
+        &self,
+        kind: PendingRequestKind,
+        app_name: Option<&str>,
+    ) -> Option<RequestNotification> {
+        if !self.notify {
+            return None;
+        }
+        let action = match kind {
+            PendingRequestKind::SignEvent => "sign an event",
+            PendingRequestKind::SignEvents => "sign several events",
+            PendingRequestKind::DecryptDm => "decrypt a direct message",
+            PendingRequestKind::PayInvoice => "pay an invoice",
+        };
+        Some(RequestNotification {
+            title: "Keystache".to_string(),
+            body: format!("{} wants to {action}", app_name.unwrap_or("An app")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifies_only_when_enabled() {
+        let attention = RequestAttention {
+            focus_window: true,
+            notify: true,
+        };
+        assert_eq!(
+            attention.notification(PendingRequestKind::SignEvent, Some("Coracle")),
+            Some(RequestNotification {
+                title: "Keystache".to_string(),
+                body: "Coracle wants to sign an event".to_string(),
+            })
+        );
+        assert_eq!(
+            attention
+                .notification(PendingRequestKind::PayInvoice, None)
+                .unwrap()
+                .body,
+            "An app wants to pay an invoice"
+        );
+
+        let attention = RequestAttention {
+            notify: false,
+            ..attention
+        };
+        assert_eq!(
+            attention.notification(PendingRequestKind::SignEvent, Some("Coracle")),
+            None
+        );
+        assert!(!RequestAttention::default().notify);
+    }
+}
@@ -0,0 +1,174 @@
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
+use nostr_sdk::{Event, JsonUtil, PublicKey, Tag, ToBech32, UnsignedEvent};
+use std::collections::BTreeSet;
+
+/// Pubkeys and LNURLs reported as belonging to scammers or impersonators.
+/// Requests that reference an entry are flagged to the user before they approve them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScamList {
+    public_keys: BTreeSet<PublicKey>,
+
+    /// Lowercased LNURLs and lightning addresses.
+    lnurls: BTreeSet<String>,
+}
+
+impl ScamList {
+    /// Builds a list from entries that are each a pubkey (hex, npub, or `nostr:` URI),
+    /// an LNURL (`lnurl1...`), or a lightning address (`name@domain`). Errors on anything else.
+    pub fn from_entries(entries: &[String]) -> anyhow::Result<Self> {
+        let mut scam_list = Self::default();
+        for entry in entries {
+            let entry = entry.trim();
+            if let Ok(public_key) = PublicKey::parse(entry) {
+                scam_list.public_keys.insert(public_key);
+            } else if is_lnurl(entry) {
+                scam_list.lnurls.insert(entry.to_lowercase());
+            } else {
+                return Err(anyhow::anyhow!(
+                    "Scam list entry isn't a pubkey, LNURL, or lightning address: {entry}"
+                ));
+            }
+        }
+
+        Ok(scam_list)
+    }
+
+    /// Every entry in the list, with pubkeys as npubs. Can be passed back to [`ScamList::from_entries`].
+    pub fn entries(&self) -> anyhow::Result<Vec<String>> {
+        let mut entries = Vec::new();
+        for public_key in &self.public_keys {
+            entries.push(public_key.to_bech32()?);
+        }
+        entries.extend(self.lnurls.iter().cloned());
+        Ok(entries)
+    }
+
+    /// Entries of the list that an event references through its `p`, `zap`, or `lnurl` tags.
+    /// Pubkeys are returned as npubs.
+    pub fn matches_in_event(&self, event: &UnsignedEvent) -> Vec<String> {
+        self.matches_in_tags(&event.tags)
+    }
+
+    /// Entries of the list that an invoice pays. Only zap invoices, whose description is the
+    /// zap request, can be traced back to a pubkey or LNURL.
+    pub fn matches_in_invoice(&self, invoice: &Bolt11Invoice) -> Vec<String> {
+        let description = match invoice.description() {
+            Bolt11InvoiceDescription::Direct(description) => description.to_string(),
+            Bolt11InvoiceDescription::Hash(_) => return Vec::new(),
+        };
+        match Event::from_json(description) {
+            Ok(zap_request) => self.matches_in_tags(zap_request.tags()),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn matches_in_tags(&self, tags: &[Tag]) -> Vec<String> {
+        let mut matches = BTreeSet::new();
+        for tag in tags {
+            let tag = tag.as_vec();
+            let is_match = match (tag.first().map(String::as_str), tag.get(1)) {
+                (Some("p" | "zap"), Some(value)) => PublicKey::parse(value)
+                    .map(|public_key| self.public_keys.contains(&public_key))
+                    .unwrap_or(false),
+                (Some("lnurl"), Some(value)) => self.lnurls.contains(&value.to_lowercase()),
+                _ => false,
+            };
+            if !is_match {
+                continue;
+            }
+
+            let entry = match PublicKey::parse(&tag[1]) {
+                Ok(public_key) => public_key.to_bech32().unwrap_or_else(|_| tag[1].clone()),
+                Err(_) => tag[1].to_lowercase(),
+            };
+            matches.insert(entry);
+        }
+        matches.into_iter().collect()
+    }
+}
+
+/// Whether `value` is an LNURL or a lightning address.
+fn is_lnurl(value: &str) -> bool {
+    if value.to_lowercase().starts_with("lnurl1") {
+        return true;
+    }
+
+    match value.split_once('@') {
+        Some((name, domain)) => {
+            !name.is_empty()
+                && domain.contains('.')
+                && !value.contains(char::is_whitespace)
+                && !domain.contains('@')
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{EventBuilder, Keys, Kind};
+
+    #[test]
+    fn from_entries_accepts_pubkeys_and_lnurls() {
+        let public_key = Keys::generate().public_key();
+        let scam_list = ScamList::from_entries(&[
+            public_key.to_hex(),
+            format!("nostr:{}", public_key.to_bech32().unwrap()),
+            " Scammer@Example.com ".to_string(),
+            "LNURL1DP68GURN8GHJ7UM9WFMXJCM99E3K7MF0V9CXJ0M385EKVCENXC6R2C35XVUKXEFCV5MKVV34X5EKZD3EV56NYD3HXQURZEPEXEJXXEPNXSCRVWFNV9NXZCN9XQ6XYEFHVGCXXCMYXYMNSERXFQ5FNS".to_string(),
+        ])
+        .unwrap();
+
+        // Duplicates of the same pubkey collapse into one entry.
+        let entries = scam_list.entries().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0], public_key.to_bech32().unwrap());
+        assert!(entries.contains(&"scammer@example.com".to_string()));
+        assert_eq!(ScamList::from_entries(&entries).unwrap(), scam_list);
+
+        assert!(ScamList::from_entries(&["not an entry".to_string()]).is_err());
+    }
+
+    #[test]
+    fn flags_events_tagging_listed_pubkey() {
+        let scammer = Keys::generate().public_key();
+        let scam_list = ScamList::from_entries(&[scammer.to_bech32().unwrap()]).unwrap();
+        let author = Keys::generate().public_key();
+
+        let dm_to_scammer = EventBuilder::new(
+            Kind::EncryptedDirectMessage,
+            "hello",
+            [Tag::public_key(scammer)],
+        )
+        .to_unsigned_event(author);
+        assert_eq!(
+            scam_list.matches_in_event(&dm_to_scammer),
+            vec![scammer.to_bech32().unwrap()]
+        );
+
+        let clean_dm = EventBuilder::new(
+            Kind::EncryptedDirectMessage,
+            "hello",
+            [Tag::public_key(Keys::generate().public_key())],
+        )
+        .to_unsigned_event(author);
+        assert!(scam_list.matches_in_event(&clean_dm).is_empty());
+    }
+
+    #[test]
+    fn flags_zap_requests_to_listed_lnurl() {
+        let scam_list = ScamList::from_entries(&["scammer@example.com".to_string()]).unwrap();
+
+        let zap_request = EventBuilder::new(
+            Kind::ZapRequest,
+            "",
+            [Tag::Lnurl("Scammer@Example.com".to_string())],
+        )
+        .to_unsigned_event(Keys::generate().public_key());
+        assert_eq!(
+            scam_list.matches_in_event(&zap_request),
+            vec!["scammer@example.com".to_string()]
+        );
+    }
+}
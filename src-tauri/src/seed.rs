@@ -0,0 +1,49 @@
+use nostr_sdk::nips::nip06::FromMnemonic;
+use nostr_sdk::Keys;
+
+/// Account indices are hardened in the derivation path, so they must be below 2^31.
+const MAX_ACCOUNT_INDEX: u32 = (1 << 31) - 1;
+
+/// Derives the key for account `index` from a BIP-39 mnemonic, at the NIP-06 path `m/44'/1237'/<index>'/0/0`.
+/// The same mnemonic and index always derive the same key, so any account can be recovered from the mnemonic alone.
+pub fn derive_account(mnemonic: &str, index: u32) -> anyhow::Result<Keys> {
+    if index > MAX_ACCOUNT_INDEX {
+        return Err(anyhow::anyhow!(
+            "Account index must be at most {MAX_ACCOUNT_INDEX}"
+        ));
+    }
+
+    Keys::from_mnemonic_with_account(mnemonic.trim(), None, Some(index))
+        .map_err(|err| anyhow::anyhow!("Error deriving account from mnemonic: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // https://github.com/nostr-protocol/nips/blob/master/06.md#test-vectors
+    const MNEMONIC: &str =
+        "leader monkey parrot ring guide accident before fence cannon height naive bean";
+
+    #[test]
+    fn derives_distinct_reproducible_accounts() {
+        let account_0 = derive_account(MNEMONIC, 0).unwrap();
+        let account_1 = derive_account(MNEMONIC, 1).unwrap();
+
+        assert_eq!(
+            account_0.public_key().to_hex(),
+            "17162c921dc4d2518f9a101db33695df1afb56ab82f5ff3e5da6eec3ca5cd917"
+        );
+        assert_ne!(account_0.public_key(), account_1.public_key());
+        assert_eq!(
+            derive_account(MNEMONIC, 1).unwrap().public_key(),
+            account_1.public_key()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        assert!(derive_account("not a mnemonic", 0).is_err());
+        assert!(derive_account(MNEMONIC, MAX_ACCOUNT_INDEX + 1).is_err());
+    }
+}
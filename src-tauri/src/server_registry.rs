@@ -0,0 +1,262 @@
+use crate::nip46_server::{Nip46RequestHandler, Nip46Server};
+use nip_55::KeyManager;
+use nostr_sdk::{PublicKey, SecretKey};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A running NIP-46 server and the account that it is bound to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ServerInfo {
+    /// The Unix domain socket address that the server is listening on.
+    pub uds_address: String,
+
+    /// The account that the server signs for.
+    pub public_key: PublicKey,
+}
+
+/// Registry of NIP-46 servers, each listening on its own address and bound to a single account.
+/// Lets different clients (e.g. different browser profiles) map to different keys.
+pub struct ServerRegistry {
+    /// Map of Unix domain socket addresses to the servers listening on them.
+    servers: Mutex<HashMap<String, (PublicKey, Nip46Server)>>,
+}
+
+impl ServerRegistry {
+    pub fn new() -> Self {
+        Self {
+            servers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Starts a server on `uds_address` that only signs for `public_key`.
+    /// **MUST** be called from within a tokio runtime.
+    pub fn start_server(
+        &self,
+        uds_address: &str,
+        public_key: PublicKey,
+        key_manager: Arc<dyn KeyManager>,
+        request_handler: Arc<dyn Nip46RequestHandler>,
+    ) -> anyhow::Result<()> {
+        let mut servers = self.servers.lock().unwrap();
+
+        if servers.contains_key(uds_address) {
+            return Err(anyhow::anyhow!(
+                "A server is already running at {uds_address}"
+            ));
+        }
+
+        if key_manager.get_secret_key(&public_key).is_none() {
+            return Err(anyhow::anyhow!("No key available for account"));
+        }
+
+        let server = Nip46Server::start(
+            uds_address,
+            Arc::new(AccountBoundKeyManager {
+                key_manager,
+                public_key,
+            }),
+            request_handler,
+        )?;
+        servers.insert(uds_address.to_string(), (public_key, server));
+
+        Ok(())
+    }
+
+    /// Stops the server running on `uds_address`.
+    pub fn stop_server(&self, uds_address: &str) -> anyhow::Result<()> {
+        match self.servers.lock().unwrap().remove(uds_address) {
+            Some((_, server)) => {
+                server.stop();
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("No server is running at {uds_address}")),
+        }
+    }
+
+    /// Lists the running servers. Ordered by address in ascending order.
+    pub fn list_servers(&self) -> Vec<ServerInfo> {
+        let mut servers: Vec<ServerInfo> = self
+            .servers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(uds_address, (public_key, _))| ServerInfo {
+                uds_address: uds_address.clone(),
+                public_key: *public_key,
+            })
+            .collect();
+        servers.sort_by(|a, b| a.uds_address.cmp(&b.uds_address));
+        servers
+    }
+}
+
+/// Key manager that only exposes the secret key of a single account.
+struct AccountBoundKeyManager {
+    key_manager: Arc<dyn KeyManager>,
+    public_key: PublicKey,
+}
+
+impl KeyManager for AccountBoundKeyManager {
+    fn get_secret_key(&self, public_key: &PublicKey) -> Option<SecretKey> {
+        if *public_key != self.public_key {
+            return None;
+        }
+        self.key_manager.get_secret_key(public_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::key_manager::KeystacheKeyManager;
+    use crate::nip46_server::AutoSigningHandler;
+    use nip_55::nip46::Nip46OverNip55Client;
+    use nostr_sdk::secp256k1::rand::thread_rng;
+    use nostr_sdk::secp256k1::{Keypair, Secp256k1};
+    use nostr_sdk::{EventBuilder, Kind};
+
+    fn get_key_manager_with_keypairs(
+        keypair_count: usize,
+    ) -> (Arc<KeystacheKeyManager>, Vec<PublicKey>) {
+        let database = Database::new_in_temp_dir();
+        let secp = Secp256k1::new();
+        let public_keys = (0..keypair_count)
+            .map(|_| {
+                let keypair = Keypair::new(&secp, &mut thread_rng());
+                database.save_keypair(&keypair).unwrap();
+                keypair.x_only_public_key().0.into()
+            })
+            .collect();
+        (
+            Arc::new(KeystacheKeyManager::new_with_database(database)),
+            public_keys,
+        )
+    }
+
+    fn get_uds_address() -> String {
+        tempfile::TempDir::new()
+            .unwrap()
+            .into_path()
+            .join("nip55.sock")
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn servers_are_bound_to_their_accounts() {
+        let (key_manager, public_keys) = get_key_manager_with_keypairs(2);
+        let registry = ServerRegistry::new();
+        let uds_address_1 = get_uds_address();
+        let uds_address_2 = get_uds_address();
+
+        for (uds_address, public_key) in [
+            (&uds_address_1, public_keys[0]),
+            (&uds_address_2, public_keys[1]),
+        ] {
+            registry
+                .start_server(
+                    uds_address,
+                    public_key,
+                    key_manager.clone(),
+                    Arc::new(AutoSigningHandler),
+                )
+                .unwrap();
+        }
+
+        let mut expected_servers = vec![
+            ServerInfo {
+                uds_address: uds_address_1.clone(),
+                public_key: public_keys[0],
+            },
+            ServerInfo {
+                uds_address: uds_address_2.clone(),
+                public_key: public_keys[1],
+            },
+        ];
+        expected_servers.sort_by(|a, b| a.uds_address.cmp(&b.uds_address));
+        assert_eq!(registry.list_servers(), expected_servers);
+
+        // Each server should sign for its own account, and only its own account.
+        for (uds_address, own_public_key, other_public_key) in [
+            (&uds_address_1, public_keys[0], public_keys[1]),
+            (&uds_address_2, public_keys[1], public_keys[0]),
+        ] {
+            let client = Nip46OverNip55Client::new(uds_address.clone());
+
+            let event = client
+                .sign_event(
+                    EventBuilder::new(Kind::TextNote, "hello world", None)
+                        .to_unsigned_event(own_public_key),
+                    own_public_key,
+                )
+                .await
+                .unwrap();
+            assert_eq!(event.pubkey, own_public_key);
+            assert!(event.verify().is_ok());
+
+            assert!(client
+                .sign_event(
+                    EventBuilder::new(Kind::TextNote, "hello world", None)
+                        .to_unsigned_event(other_public_key),
+                    other_public_key,
+                )
+                .await
+                .is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn start_and_stop_server() {
+        let (key_manager, public_keys) = get_key_manager_with_keypairs(1);
+        let registry = ServerRegistry::new();
+        let uds_address = get_uds_address();
+
+        registry
+            .start_server(
+                &uds_address,
+                public_keys[0],
+                key_manager.clone(),
+                Arc::new(AutoSigningHandler),
+            )
+            .unwrap();
+
+        // Starting a second server at the same address should cause an error.
+        assert!(registry
+            .start_server(
+                &uds_address,
+                public_keys[0],
+                key_manager.clone(),
+                Arc::new(AutoSigningHandler),
+            )
+            .is_err());
+
+        registry.stop_server(&uds_address).unwrap();
+        assert!(registry.list_servers().is_empty());
+
+        // Stopping a server that isn't running should cause an error.
+        assert!(registry.stop_server(&uds_address).is_err());
+    }
+
+    #[tokio::test]
+    async fn start_server_for_unknown_account_error() {
+        let (key_manager, _) = get_key_manager_with_keypairs(1);
+        let registry = ServerRegistry::new();
+
+        let unknown_public_key = Keypair::new(&Secp256k1::new(), &mut thread_rng())
+            .x_only_public_key()
+            .0
+            .into();
+
+        assert!(registry
+            .start_server(
+                &get_uds_address(),
+                unknown_public_key,
+                key_manager,
+                Arc::new(AutoSigningHandler),
+            )
+            .is_err());
+        assert!(registry.list_servers().is_empty());
+    }
+}
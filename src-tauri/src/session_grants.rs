@@ -0,0 +1,213 @@
+use nostr_sdk::{FromBech32, Kind, PublicKey, ToBech32};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Longest that an app can be allowed to sign without asking, so that a grant can't be left open indefinitely.
+pub const MAX_SESSION_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Permission for an app to have events of certain kinds signed without asking, until it expires.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct SessionGrant {
+    allowed_kinds: Vec<Kind>,
+    expires_at: Instant,
+}
+
+/// A session grant, as listed to the user.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SessionGrantEntry {
+    pub app_npub: String,
+    pub allowed_kinds: Vec<u64>,
+
+    /// How long until the grant expires, rounded down to the second.
+    pub remaining_secs: u64,
+}
+
+/// A session grant as it's saved, so that it outlasts the connection it was granted on. Grants are keyed by the
+/// app's npub, so an app that reconnects (or reconnects after Keystache restarts) keeps its session until it expires.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredSessionGrant {
+    pub app_npub: String,
+    pub allowed_kinds: Vec<u64>,
+
+    /// Unix timestamp of when the grant expires.
+    pub expires_at: u64,
+}
+
+/// The apps that the user has let sign without asking for a while, keyed by the app's public key.
+#[derive(Default)]
+pub struct SessionGrants {
+    grants: HashMap<PublicKey, SessionGrant>,
+}
+
+impl SessionGrants {
+    /// Lets `app_public_key` have events of `allowed_kinds` signed without asking for `duration` from `now`,
+    /// replacing any grant the app already has.
+    pub fn grant(
+        &mut self,
+        app_public_key: PublicKey,
+        duration: Duration,
+        allowed_kinds: Vec<Kind>,
+        now: Instant,
+    ) -> anyhow::Result<()> {
+        if duration.is_zero() || duration > MAX_SESSION_DURATION {
+            return Err(anyhow::anyhow!(
+                "Session duration must be between 1 second and {} hours",
+                MAX_SESSION_DURATION.as_secs() / 3600
+            ));
+        }
+        if allowed_kinds.is_empty() {
+            return Err(anyhow::anyhow!("A session must allow at least one kind"));
+        }
+
+        self.grants.insert(
+            app_public_key,
+            SessionGrant {
+                allowed_kinds,
+                expires_at: now + duration,
+            },
+        );
+        Ok(())
+    }
+
+    /// Ends the app's grant, if it has one.
+    pub fn revoke(&mut self, app_public_key: &PublicKey) {
+        self.grants.remove(app_public_key);
+    }
+
+    /// Whether the app may have an event of `kind` signed without asking at `now`. Expired grants are removed.
+    pub fn allows(&mut self, app_public_key: &PublicKey, kind: Kind, now: Instant) -> bool {
+        self.remove_expired(now);
+        self.grants
+            .get(app_public_key)
+            .is_some_and(|grant| grant.allowed_kinds.contains(&kind))
+    }
+
+    /// Lists the grants that haven't expired by `now`, ordered by app npub. Expired grants are removed.
+    pub fn list(&mut self, now: Instant) -> anyhow::Result<Vec<SessionGrantEntry>> {
+        self.remove_expired(now);
+        let mut entries = self
+            .grants
+            .iter()
+            .map(|(app_public_key, grant)| {
+                Ok(SessionGrantEntry {
+                    app_npub: app_public_key.to_bech32()?,
+                    allowed_kinds: grant.allowed_kinds.iter().map(Kind::as_u64).collect(),
+                    remaining_secs: (grant.expires_at - now).as_secs(),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        entries.sort_by(|a, b| a.app_npub.cmp(&b.app_npub));
+        Ok(entries)
+    }
+
+    /// The grants that haven't expired by `now`, to be saved. `now_unix` is the same moment as a Unix timestamp.
+    pub fn list_stored(
+        &mut self,
+        now: Instant,
+        now_unix: u64,
+    ) -> anyhow::Result<Vec<StoredSessionGrant>> {
+        Ok(self
+            .list(now)?
+            .into_iter()
+            .map(|entry| StoredSessionGrant {
+                app_npub: entry.app_npub,
+                allowed_kinds: entry.allowed_kinds,
+                expires_at: now_unix + entry.remaining_secs,
+            })
+            .collect())
+    }
+
+    /// Restores saved grants, replacing any the apps already have. Grants that have expired by `now_unix`, or that
+    /// can't be read, are skipped.
+    pub fn restore(&mut self, stored_grants: &[StoredSessionGrant], now: Instant, now_unix: u64) {
+        for stored_grant in stored_grants {
+            let Ok(app_public_key) = PublicKey::from_bech32(&stored_grant.app_npub) else {
+                continue;
+            };
+            // A saved grant can't last longer than a new one could.
+            let duration = Duration::from_secs(stored_grant.expires_at.saturating_sub(now_unix))
+                .min(MAX_SESSION_DURATION);
+            let allowed_kinds = stored_grant
+                .allowed_kinds
+                .iter()
+                .copied()
+                .map(Kind::from)
+                .collect();
+            // Fails, and so skips the grant, if it has expired.
+            let _ = self.grant(app_public_key, duration, allowed_kinds, now);
+        }
+    }
+
+    fn remove_expired(&mut self, now: Instant) {
+        self.grants.retain(|_, grant| grant.expires_at > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::Keys;
+
+    #[test]
+    fn grants_expire() {
+        let app = Keys::generate().public_key();
+        let now = Instant::now();
+        let mut grants = SessionGrants::default();
+
+        grants
+            .grant(app, Duration::from_secs(600), vec![Kind::TextNote], now)
+            .unwrap();
+        assert!(grants.allows(&app, Kind::TextNote, now + Duration::from_secs(599)));
+        assert!(!grants.allows(&app, Kind::Reaction, now));
+        assert!(!grants.allows(&Keys::generate().public_key(), Kind::TextNote, now));
+        assert_eq!(grants.list(now).unwrap()[0].remaining_secs, 600);
+
+        // Once the grant expires, it's removed.
+        assert!(!grants.allows(&app, Kind::TextNote, now + Duration::from_secs(600)));
+        assert!(grants.list(now).unwrap().is_empty());
+
+        assert!(grants
+            .grant(app, Duration::ZERO, vec![Kind::TextNote], now)
+            .is_err());
+        assert!(grants
+            .grant(app, MAX_SESSION_DURATION * 2, vec![Kind::TextNote], now)
+            .is_err());
+        assert!(grants
+            .grant(app, Duration::from_secs(60), Vec::new(), now)
+            .is_err());
+    }
+
+    #[test]
+    fn restores_stored_grants() {
+        let app = Keys::generate().public_key();
+        let now = Instant::now();
+        let mut grants = SessionGrants::default();
+        grants
+            .grant(app, Duration::from_secs(600), vec![Kind::TextNote], now)
+            .unwrap();
+
+        let stored_grants = grants.list_stored(now, 1_000_000).unwrap();
+        assert_eq!(
+            stored_grants,
+            vec![StoredSessionGrant {
+                app_npub: app.to_bech32().unwrap(),
+                allowed_kinds: vec![1],
+                expires_at: 1_000_600,
+            }]
+        );
+
+        // Restored 100 seconds later, the grant has the rest of its time left.
+        let later = now + Duration::from_secs(100);
+        let mut restored = SessionGrants::default();
+        restored.restore(&stored_grants, later, 1_000_100);
+        assert_eq!(restored.list(later).unwrap()[0].remaining_secs, 500);
+        assert!(restored.allows(&app, Kind::TextNote, later));
+
+        // Grants that have expired since they were saved aren't restored.
+        let mut restored = SessionGrants::default();
+        restored.restore(&stored_grants, later, 1_000_600);
+        assert!(restored.list(later).unwrap().is_empty());
+    }
+}
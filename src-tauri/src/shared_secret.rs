@@ -0,0 +1,54 @@
+use nostr_sdk::secp256k1::{ecdh, Parity};
+use nostr_sdk::{PublicKey, SecretKey};
+
+/// Derives the ECDH shared secret between an account's key and a peer's public key, the same way NIP-04 does: the
+/// x-coordinate of the shared point, without hashing it. Both sides derive the same secret from their own secret key
+/// and the other's public key.
+///
+/// **Dangerous:** the secret decrypts every NIP-04 message ever sent between the two keys, so anyone who learns it
+/// can read them. It must be kept as carefully as the secret key itself.
+pub fn derive_shared_secret(secret_key: &SecretKey, peer_public_key: &PublicKey) -> [u8; 32] {
+    // Nostr public keys are x-only, so the point with the even y-coordinate is used, as in BIP-340.
+    let peer_point = peer_public_key.public_key(Parity::Even);
+    let shared_point = ecdh::shared_secret_point(&peer_point, secret_key);
+    let mut shared_secret = [0; 32];
+    shared_secret.copy_from_slice(&shared_point[..32]);
+    shared_secret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::util::generate_shared_key;
+    use nostr_sdk::Keys;
+    use std::str::FromStr;
+
+    #[test]
+    fn shared_secret_matches_nostr_sdk() {
+        let alice_keys = Keys::new(
+            SecretKey::from_str("6b911fd37cdf5c81d4c0adb1ab7fa822ed253ab0ad9aa18d77257c88b29b718e")
+                .unwrap(),
+        );
+        let bob_keys = Keys::new(
+            SecretKey::from_str("7b911fd37cdf5c81d4c0adb1ab7fa822ed253ab0ad9aa18d77257c88b29b718e")
+                .unwrap(),
+        );
+
+        let shared_secret =
+            derive_shared_secret(alice_keys.secret_key().unwrap(), &bob_keys.public_key());
+        assert_eq!(
+            shared_secret,
+            generate_shared_key(alice_keys.secret_key().unwrap(), &bob_keys.public_key())
+        );
+        assert_eq!(
+            shared_secret,
+            derive_shared_secret(bob_keys.secret_key().unwrap(), &alice_keys.public_key())
+        );
+
+        let other_keys = Keys::generate();
+        assert_ne!(
+            shared_secret,
+            derive_shared_secret(alice_keys.secret_key().unwrap(), &other_keys.public_key())
+        );
+    }
+}
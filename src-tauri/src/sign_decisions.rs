@@ -0,0 +1,30 @@
+use nostr_sdk::{Kind, PublicKey};
+use serde::Serialize;
+
+/// A decision the user asked to remember for every event of one kind that an app asks to sign.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RememberedSignDecision {
+    pub app_npub: String,
+    pub kind: u64,
+    pub approved: bool,
+}
+
+/// Somewhere to remember the user's sign decisions for each app and event kind, so that they're only
+/// asked the first time an app wants to sign a kind of event.
+pub trait SignDecisionStore: Send + Sync {
+    /// Returns whether the user approved signing events of `kind` for the app, or `None` if they haven't
+    /// asked for a decision to be remembered.
+    fn get_sign_decision(
+        &self,
+        app_public_key: &PublicKey,
+        kind: Kind,
+    ) -> anyhow::Result<Option<bool>>;
+
+    /// Remembers the decision for the app and kind, replacing any earlier one.
+    fn remember_sign_decision(
+        &self,
+        app_public_key: &PublicKey,
+        kind: Kind,
+        approved: bool,
+    ) -> anyhow::Result<()>;
+}
@@ -0,0 +1,579 @@
+use nostr_sdk::{PublicKey, Tag, Timestamp, ToBech32, UnsignedEvent};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::time::Duration;
+
+/// How far an event's `created_at` can be from the current time before the user is warned about it.
+const CREATED_AT_TOLERANCE_SECS: u64 = 10 * 60;
+
+/// Events with a `created_at` further than this into the future are rejected outright.
+const MAX_FUTURE_CREATED_AT_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// Name of the NIP-36 tag that marks an event's content as sensitive.
+const CONTENT_WARNING_TAG: &str = "content-warning";
+
+/// Kinds whose content is shown to other users as-is, so the content-warning policy applies to them.
+const CONTENT_WARNING_KINDS: [u64; 3] = [1, 42, 30023];
+
+/// Payload of the `sign_event_request` event emitted to the frontend
+/// when an event needs the user's approval to be signed.
+#[derive(Clone, Debug, Serialize)]
+pub struct SignEventRequestPayload {
+    /// The event to be signed.
+    pub event: UnsignedEvent,
+
+    /// The bech32-encoded public key of the account that will sign the event.
+    pub user_npub: String,
+
+    /// Human-readable name of the event's kind (e.g. "Reaction").
+    pub kind_label: String,
+
+    /// Anything about the request that the user should pay extra attention to before approving.
+    pub warnings: Vec<SignEventWarning>,
+
+    /// Whether the event is marked protected with a NIP-70 `-` tag, meaning only its author should publish it.
+    pub protected: bool,
+
+    /// Whether the event has a NIP-36 `content-warning` tag.
+    pub has_content_warning: bool,
+
+    /// The event with a `content-warning` tag added, if the content-warning policy suggests adding one.
+    /// Approving with this as the edited event signs it instead of the original.
+    pub suggested_event: Option<UnsignedEvent>,
+
+    /// Whether the request arrived during quiet hours, so Keystache must be unlocked before it can be approved.
+    pub requires_unlock: bool,
+
+    /// The relay hint that Keystache added to the event's `e` and `p` tags that didn't have one, if any.
+    pub added_relay_hint: Option<String>,
+
+    /// The NIP-40 expiration that Keystache added to the event, in seconds since the Unix epoch, if any.
+    pub added_expiration: Option<u64>,
+
+    /// If set, a NIP-13 nonce will be mined once the request is approved, so that the signed event's ID has this
+    /// many leading zero bits. Progress is reported with `pow_progress` events.
+    pub pow_difficulty: Option<u8>,
+
+    /// Whether the user can ask for their decision to be remembered for the app and the event's kind.
+    pub can_remember: bool,
+
+    /// The npub of the app that asked for the event to be signed, if known.
+    pub app_npub: Option<String>,
+
+    /// What to call the app in the prompt: the name the user gave it, or its npub if it hasn't been named.
+    /// `None` if the app isn't known.
+    pub app_name: Option<String>,
+}
+
+/// Payload of the `sign_events_request` event emitted to the frontend when a batch of events,
+/// possibly for several of the user's accounts, needs a single approval to be signed.
+#[derive(Clone, Debug, Serialize)]
+pub struct SignEventsRequestPayload {
+    /// Identifies the batch when responding to the request.
+    pub batch_id: String,
+
+    /// The events to be signed, grouped by the account that will sign them.
+    /// Accounts are in the order that their first event appears in the batch.
+    pub accounts: Vec<SignEventsAccountGroup>,
+
+    /// Whether the request arrived during quiet hours, so Keystache must be unlocked before it can be approved.
+    pub requires_unlock: bool,
+}
+
+/// The events in a batch that will be signed by one account.
+#[derive(Clone, Debug, Serialize)]
+pub struct SignEventsAccountGroup {
+    /// The bech32-encoded public key of the account.
+    pub user_npub: String,
+
+    pub events: Vec<SignEventRequestPayload>,
+}
+
+/// Something about a sign event request that should be called out to the user.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SignEventWarning {
+    /// The event's `created_at` isn't close to the current time,
+    /// meaning the event is backdated or postdated.
+    CreatedAtNotNow { created_at: u64, now: u64 },
+
+    /// The event references a pubkey or LNURL on the user's scam list.
+    ScamListMatch { entry: String },
+
+    /// The event has no NIP-36 `content-warning` tag, and the user asked to be warned about such events.
+    MissingContentWarning,
+
+    /// The event is parameterized replaceable, and the author's relays already have an event of the same kind
+    /// with the same `d` tag, which this event will replace.
+    ReplacesExistingEvent {
+        identifier: String,
+        existing_event_id: String,
+        existing_created_at: u64,
+    },
+}
+
+/// What to do about events that are signed without a NIP-36 `content-warning` tag.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentWarningPolicy {
+    /// Don't check for a `content-warning` tag.
+    #[default]
+    Off,
+
+    /// Warn the user when the tag is missing.
+    Warn,
+
+    /// Warn the user when the tag is missing, and suggest a copy of the event that has it.
+    SuggestTag,
+}
+
+impl SignEventRequestPayload {
+    pub fn new(event: UnsignedEvent, user_npub: String, now: Timestamp) -> Self {
+        let warnings = created_at_warnings(event.created_at, now);
+        let kind_label = match u16::try_from(event.kind.as_u64()) {
+            Ok(kind) => kind_label(kind).into_owned(),
+            Err(_) => unknown_kind_label(event.kind.as_u64()),
+        };
+        let protected = is_protected(&event.tags);
+        let has_content_warning = has_content_warning(&event.tags);
+        Self {
+            event,
+            user_npub,
+            kind_label,
+            warnings,
+            protected,
+            has_content_warning,
+            suggested_event: None,
+            requires_unlock: false,
+            added_relay_hint: None,
+            added_expiration: None,
+            pow_difficulty: None,
+            can_remember: false,
+            app_npub: None,
+            app_name: None,
+        }
+    }
+
+    /// Flags the event if `policy` calls for a `content-warning` tag and the event doesn't have one.
+    /// A tagged copy is only suggested if `edits_allowed`, since otherwise it couldn't be approved.
+    pub fn apply_content_warning_policy(
+        &mut self,
+        policy: ContentWarningPolicy,
+        edits_allowed: bool,
+    ) {
+        if policy == ContentWarningPolicy::Off
+            || self.has_content_warning
+            || !CONTENT_WARNING_KINDS.contains(&self.event.kind.as_u64())
+        {
+            return;
+        }
+
+        self.warnings.push(SignEventWarning::MissingContentWarning);
+        if policy == ContentWarningPolicy::SuggestTag && edits_allowed {
+            let mut suggested_event = self.event.clone();
+            suggested_event.id = None;
+            suggested_event
+                .tags
+                .push(Tag::parse(&[CONTENT_WARNING_TAG]).expect("content-warning tag is valid"));
+            self.suggested_event = Some(suggested_event);
+        }
+    }
+}
+
+impl SignEventsRequestPayload {
+    /// Groups `events` by their pubkey, keeping the events for each account in their original order.
+    pub fn new(
+        batch_id: String,
+        events: Vec<UnsignedEvent>,
+        now: Timestamp,
+    ) -> anyhow::Result<Self> {
+        let mut accounts: Vec<(PublicKey, SignEventsAccountGroup)> = Vec::new();
+        for event in events {
+            let index = match accounts
+                .iter()
+                .position(|(public_key, _)| *public_key == event.pubkey)
+            {
+                Some(index) => index,
+                None => {
+                    accounts.push((
+                        event.pubkey,
+                        SignEventsAccountGroup {
+                            user_npub: event.pubkey.to_bech32()?,
+                            events: Vec::new(),
+                        },
+                    ));
+                    accounts.len() - 1
+                }
+            };
+            let group = &mut accounts[index].1;
+            group.events.push(SignEventRequestPayload::new(
+                event,
+                group.user_npub.clone(),
+                now,
+            ));
+        }
+
+        Ok(Self {
+            batch_id,
+            accounts: accounts.into_iter().map(|(_, group)| group).collect(),
+            requires_unlock: false,
+        })
+    }
+}
+
+/// Returns a human-readable name for an event kind, for showing in approval prompts.
+/// Unknown kinds are labelled with their number, e.g. "Unknown (kind 12345)".
+pub fn kind_label(kind: u16) -> Cow<'static, str> {
+    let label = match kind {
+        0 => "Profile metadata",
+        1 => "Short text note",
+        3 => "Follow list",
+        4 => "Encrypted direct message",
+        5 => "Event deletion",
+        6 => "Repost",
+        7 => "Reaction",
+        8 => "Badge award",
+        13 => "Seal",
+        14 => "Direct message",
+        16 => "Generic repost",
+        40 => "Channel creation",
+        41 => "Channel metadata",
+        42 => "Channel message",
+        1063 => "File metadata",
+        1984 => "Report",
+        9734 => "Zap request",
+        9735 => "Zap",
+        10000 => "Mute list",
+        10002 => "Relay list",
+        22242 => "Relay authentication",
+        23194 => "Wallet request",
+        24133 => "Nostr Connect",
+        27235 => "HTTP auth",
+        30023 => "Long-form article",
+        30024 => "Draft long-form article",
+        30078 => "App-specific data",
+        _ => return Cow::Owned(unknown_kind_label(kind.into())),
+    };
+    Cow::Borrowed(label)
+}
+
+fn unknown_kind_label(kind: u64) -> String {
+    format!("Unknown (kind {kind})")
+}
+
+/// Whether the tags include a NIP-70 `-` tag, marking the event as protected.
+pub fn is_protected(tags: &[Tag]) -> bool {
+    tags.iter()
+        .any(|tag| tag.as_vec().first().is_some_and(|name| name == "-"))
+}
+
+/// Whether the tags include a NIP-36 `content-warning` tag, with or without a reason.
+pub fn has_content_warning(tags: &[Tag]) -> bool {
+    tags.iter().any(|tag| {
+        tag.as_vec()
+            .first()
+            .is_some_and(|name| name == CONTENT_WARNING_TAG)
+    })
+}
+
+/// Adds `relay_url` as the relay hint of every `e` and `p` tag that doesn't have one.
+/// Returns `None` if every such tag already has a hint, so nothing would change.
+pub fn add_relay_hints(tags: &[Tag], relay_url: &str) -> Option<Vec<Tag>> {
+    let mut changed = false;
+    let tags = tags
+        .iter()
+        .map(|tag| {
+            let mut values = tag.as_vec();
+            match values.as_slice() {
+                [name, _] if name == "e" || name == "p" => {
+                    values.push(relay_url.to_string());
+                    changed = true;
+                    Tag::parse(&values).unwrap_or_else(|_| tag.clone())
+                }
+                _ => tag.clone(),
+            }
+        })
+        .collect();
+    changed.then_some(tags)
+}
+
+/// Adds a NIP-40 `expiration` tag, unless the tags already have one.
+/// Returns `None` if they do, so nothing would change.
+pub fn add_expiration(tags: &[Tag], expiration: Timestamp) -> Option<Vec<Tag>> {
+    let has_expiration = tags.iter().any(|tag| {
+        tag.as_vec()
+            .first()
+            .is_some_and(|name| name == "expiration")
+    });
+    if has_expiration {
+        return None;
+    }
+    let mut tags = tags.to_vec();
+    tags.push(Tag::Expiration(expiration));
+    Some(tags)
+}
+
+/// Returns any warnings about an event's `created_at` relative to the current time.
+pub fn created_at_warnings(created_at: Timestamp, now: Timestamp) -> Vec<SignEventWarning> {
+    if created_at.as_u64().abs_diff(now.as_u64()) > CREATED_AT_TOLERANCE_SECS {
+        vec![SignEventWarning::CreatedAtNotNow {
+            created_at: created_at.as_u64(),
+            now: now.as_u64(),
+        }]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Checks that a `created_at` is one that can reasonably be signed.
+/// Timestamps before the Unix epoch or absurdly far into the future are rejected.
+pub fn validate_created_at(created_at: i64, now: Timestamp) -> anyhow::Result<Timestamp> {
+    let created_at = match u64::try_from(created_at) {
+        Ok(created_at) => created_at,
+        Err(_) => return Err(anyhow::anyhow!("created_at is before the Unix epoch")),
+    };
+
+    if created_at > now.as_u64().saturating_add(MAX_FUTURE_CREATED_AT_SECS) {
+        return Err(anyhow::anyhow!("created_at is too far in the future"));
+    }
+
+    Ok(Timestamp::from(created_at))
+}
+
+/// Checks that an event isn't older than `max_age`, if the user has set a maximum event age.
+pub fn validate_event_age(
+    created_at: Timestamp,
+    now: Timestamp,
+    max_age: Option<Duration>,
+) -> anyhow::Result<()> {
+    match max_age {
+        Some(max_age) if now.as_u64().saturating_sub(created_at.as_u64()) > max_age.as_secs() => {
+            Err(anyhow::anyhow!(
+                "Event is older than the maximum event age of {} seconds",
+                max_age.as_secs()
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{EventBuilder, Keys, Kind};
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn created_at_close_to_now_has_no_warning() {
+        let now = Timestamp::from(NOW);
+
+        assert!(created_at_warnings(now, now).is_empty());
+        assert!(created_at_warnings(Timestamp::from(NOW - 60), now).is_empty());
+        assert!(created_at_warnings(Timestamp::from(NOW + 60), now).is_empty());
+    }
+
+    #[test]
+    fn backdated_created_at_has_warning() {
+        let now = Timestamp::from(NOW);
+        let created_at = Timestamp::from(NOW - 24 * 60 * 60);
+
+        assert_eq!(
+            created_at_warnings(created_at, now),
+            vec![SignEventWarning::CreatedAtNotNow {
+                created_at: created_at.as_u64(),
+                now: NOW
+            }]
+        );
+    }
+
+    #[test]
+    fn postdated_created_at_has_warning() {
+        let now = Timestamp::from(NOW);
+        let created_at = Timestamp::from(NOW + 24 * 60 * 60);
+
+        assert_eq!(
+            created_at_warnings(created_at, now),
+            vec![SignEventWarning::CreatedAtNotNow {
+                created_at: created_at.as_u64(),
+                now: NOW
+            }]
+        );
+    }
+
+    #[test]
+    fn payload_includes_created_at_warning() {
+        let keys = Keys::generate();
+        let mut event = EventBuilder::new(Kind::TextNote, "hello world", None)
+            .to_unsigned_event(keys.public_key());
+        event.created_at = Timestamp::from(NOW - 24 * 60 * 60);
+
+        let payload = SignEventRequestPayload::new(event, "npub".to_string(), Timestamp::from(NOW));
+        assert_eq!(payload.warnings.len(), 1);
+    }
+
+    #[test]
+    fn payload_marks_protected_event() {
+        let keys = Keys::generate();
+
+        let event = EventBuilder::new(Kind::TextNote, "hello world", None)
+            .to_unsigned_event(keys.public_key());
+        let payload = SignEventRequestPayload::new(event, String::new(), Timestamp::from(NOW));
+        assert!(!payload.protected);
+
+        let event = EventBuilder::new(Kind::TextNote, "hello world", [Tag::parse(&["-"]).unwrap()])
+            .to_unsigned_event(keys.public_key());
+        let payload = SignEventRequestPayload::new(event, String::new(), Timestamp::from(NOW));
+        assert!(payload.protected);
+    }
+
+    #[test]
+    fn payload_flags_missing_content_warning() {
+        let keys = Keys::generate();
+        let mut event = EventBuilder::new(Kind::TextNote, "hello world", None)
+            .to_unsigned_event(keys.public_key());
+        event.created_at = Timestamp::from(NOW);
+
+        let mut payload =
+            SignEventRequestPayload::new(event.clone(), String::new(), Timestamp::from(NOW));
+        payload.apply_content_warning_policy(ContentWarningPolicy::Off, true);
+        assert!(!payload.has_content_warning);
+        assert!(payload.warnings.is_empty());
+
+        payload.apply_content_warning_policy(ContentWarningPolicy::SuggestTag, true);
+        assert_eq!(
+            payload.warnings,
+            vec![SignEventWarning::MissingContentWarning]
+        );
+        let suggested_event = payload.suggested_event.unwrap();
+        assert!(has_content_warning(&suggested_event.tags));
+        assert_eq!(suggested_event.content, event.content);
+
+        let mut payload = SignEventRequestPayload::new(event, String::new(), Timestamp::from(NOW));
+        payload.apply_content_warning_policy(ContentWarningPolicy::SuggestTag, false);
+        assert_eq!(
+            payload.warnings,
+            vec![SignEventWarning::MissingContentWarning]
+        );
+        assert_eq!(payload.suggested_event, None);
+
+        let mut event = EventBuilder::new(
+            Kind::TextNote,
+            "hello world",
+            [Tag::parse(&["content-warning", "spoilers"]).unwrap()],
+        )
+        .to_unsigned_event(keys.public_key());
+        event.created_at = Timestamp::from(NOW);
+        let mut payload = SignEventRequestPayload::new(event, String::new(), Timestamp::from(NOW));
+        payload.apply_content_warning_policy(ContentWarningPolicy::Warn, true);
+        assert!(payload.has_content_warning);
+        assert!(payload.warnings.is_empty());
+    }
+
+    #[test]
+    fn batch_payload_groups_events_by_account() {
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let events = vec![
+            EventBuilder::new(Kind::TextNote, "alice 1", None)
+                .to_unsigned_event(alice.public_key()),
+            EventBuilder::new(Kind::TextNote, "bob 1", None).to_unsigned_event(bob.public_key()),
+            EventBuilder::new(Kind::Reaction, "alice 2", None)
+                .to_unsigned_event(alice.public_key()),
+        ];
+
+        let payload =
+            SignEventsRequestPayload::new("batch".to_string(), events, Timestamp::from(NOW))
+                .unwrap();
+
+        assert_eq!(payload.batch_id, "batch");
+        let groups: Vec<(String, Vec<String>)> = payload
+            .accounts
+            .iter()
+            .map(|group| {
+                (
+                    group.user_npub.clone(),
+                    group
+                        .events
+                        .iter()
+                        .map(|event| event.event.content.clone())
+                        .collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                (
+                    alice.public_key().to_bech32().unwrap(),
+                    vec!["alice 1".to_string(), "alice 2".to_string()]
+                ),
+                (
+                    bob.public_key().to_bech32().unwrap(),
+                    vec!["bob 1".to_string()]
+                ),
+            ]
+        );
+        assert_eq!(payload.accounts[0].events[1].kind_label, "Reaction");
+    }
+
+    #[test]
+    fn validate_created_at_accepts_past_and_near_future() {
+        let now = Timestamp::from(NOW);
+
+        assert_eq!(validate_created_at(0, now).unwrap(), Timestamp::from(0));
+        assert_eq!(
+            validate_created_at(NOW as i64 - 1000, now).unwrap(),
+            Timestamp::from(NOW - 1000)
+        );
+        assert_eq!(
+            validate_created_at(NOW as i64 + 1000, now).unwrap(),
+            Timestamp::from(NOW + 1000)
+        );
+    }
+
+    #[test]
+    fn validate_created_at_before_epoch_error() {
+        assert!(validate_created_at(-1, Timestamp::from(NOW)).is_err());
+    }
+
+    #[test]
+    fn validate_created_at_far_future_error() {
+        let now = Timestamp::from(NOW);
+
+        assert!(validate_created_at((NOW + MAX_FUTURE_CREATED_AT_SECS + 1) as i64, now).is_err());
+        assert!(validate_created_at(i64::MAX, now).is_err());
+    }
+
+    #[test]
+    fn known_kind_labels() {
+        assert_eq!(kind_label(0), "Profile metadata");
+        assert_eq!(kind_label(1), "Short text note");
+        assert_eq!(kind_label(4), "Encrypted direct message");
+        assert_eq!(kind_label(6), "Repost");
+        assert_eq!(kind_label(7), "Reaction");
+        assert_eq!(kind_label(30023), "Long-form article");
+    }
+
+    #[test]
+    fn unknown_kind_label_includes_kind() {
+        assert_eq!(kind_label(12345), "Unknown (kind 12345)");
+        assert_eq!(kind_label(u16::MAX), "Unknown (kind 65535)");
+    }
+
+    #[test]
+    fn payload_includes_kind_label() {
+        let keys = Keys::generate();
+
+        let event =
+            EventBuilder::new(Kind::Reaction, "+", None).to_unsigned_event(keys.public_key());
+        let payload = SignEventRequestPayload::new(event, String::new(), Timestamp::from(NOW));
+        assert_eq!(payload.kind_label, "Reaction");
+
+        // Kinds too large for NIP-01 should still get a label.
+        let event =
+            EventBuilder::new(Kind::from(100_000), "", None).to_unsigned_event(keys.public_key());
+        let payload = SignEventRequestPayload::new(event, String::new(), Timestamp::from(NOW));
+        assert_eq!(payload.kind_label, "Unknown (kind 100000)");
+    }
+}
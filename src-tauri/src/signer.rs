@@ -0,0 +1,51 @@
+use nostr_sdk::nips::nip04;
+use nostr_sdk::{Event, Keys, PublicKey, UnsignedEvent};
+
+/// Something that can sign events for one account, e.g. a key held by Keystache or one that never leaves a
+/// hardware token.
+pub trait Signer: Send + Sync {
+    /// The public key of the account that events are signed for.
+    fn public_key(&self) -> PublicKey;
+
+    /// Signs the event, whose pubkey must be [`Self::public_key`].
+    fn sign_event(&self, event: UnsignedEvent) -> anyhow::Result<Event>;
+
+    /// Encrypts `plaintext` from the account to `peer_public_key`, as NIP-04 content.
+    fn nip04_encrypt(&self, peer_public_key: &PublicKey, plaintext: &str)
+        -> anyhow::Result<String>;
+
+    /// Decrypts NIP-04 `content` that `peer_public_key` sent to the account.
+    fn nip04_decrypt(&self, peer_public_key: &PublicKey, content: &str) -> anyhow::Result<String>;
+}
+
+impl Signer for Keys {
+    fn public_key(&self) -> PublicKey {
+        Keys::public_key(self)
+    }
+
+    fn sign_event(&self, event: UnsignedEvent) -> anyhow::Result<Event> {
+        event
+            .sign(self)
+            .map_err(|_| anyhow::anyhow!("Error signing event"))
+    }
+
+    fn nip04_encrypt(
+        &self,
+        peer_public_key: &PublicKey,
+        plaintext: &str,
+    ) -> anyhow::Result<String> {
+        Ok(nip04::encrypt(
+            self.secret_key()?,
+            peer_public_key,
+            plaintext,
+        )?)
+    }
+
+    fn nip04_decrypt(&self, peer_public_key: &PublicKey, content: &str) -> anyhow::Result<String> {
+        Ok(nip04::decrypt(
+            self.secret_key()?,
+            peer_public_key,
+            content,
+        )?)
+    }
+}
@@ -0,0 +1,70 @@
+use nostr_sdk::{Kind, PublicKey, ToBech32};
+use serde::{Deserialize, Serialize};
+
+/// How an app's request to sign an event ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SigningOutcome {
+    /// The event was approved for signing, by the user or by a remembered decision or session grant.
+    Approved,
+
+    /// The event was rejected, or the user didn't respond in time.
+    Rejected,
+}
+
+impl SigningOutcome {
+    /// Name the outcome is stored under in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Approved => "approved",
+            Self::Rejected => "rejected",
+        }
+    }
+}
+
+impl std::str::FromStr for SigningOutcome {
+    type Err = anyhow::Error;
+
+    fn from_str(outcome: &str) -> anyhow::Result<Self> {
+        match outcome {
+            "approved" => Ok(Self::Approved),
+            "rejected" => Ok(Self::Rejected),
+            _ => Err(anyhow::anyhow!("Unknown signing outcome: {outcome}")),
+        }
+    }
+}
+
+/// An app's request to sign an event, as recorded in the app's signing history.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SigningLogEntry {
+    /// npub of the application that asked for the event to be signed.
+    pub app_npub: String,
+
+    pub kind: u64,
+
+    /// When the request ended, as an RFC 3339 timestamp.
+    pub create_time: String,
+
+    pub outcome: SigningOutcome,
+}
+
+impl SigningLogEntry {
+    /// Builds an entry for a request from the app with `app_public_key` to sign an event of `kind` that ended now.
+    pub fn new(
+        app_public_key: &PublicKey,
+        kind: Kind,
+        outcome: SigningOutcome,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            app_npub: app_public_key.to_bech32()?,
+            kind: kind.as_u64(),
+            create_time: chrono::Utc::now().to_rfc3339(),
+            outcome,
+        })
+    }
+}
+
+/// Somewhere to record what apps have asked to sign.
+pub trait SigningLog: Send + Sync {
+    fn record_signing(&self, entry: &SigningLogEntry) -> anyhow::Result<()>;
+}
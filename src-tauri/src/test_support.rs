@@ -0,0 +1,19 @@
+//! Helpers shared by the tests of several modules.
+
+use nip_55::KeyManager;
+use nostr_sdk::{Keys, PublicKey, SecretKey};
+
+/// Key manager that only has the secret key of a single account.
+pub struct SingleKeyManager {
+    pub keys: Keys,
+}
+
+impl KeyManager for SingleKeyManager {
+    fn get_secret_key(&self, public_key: &PublicKey) -> Option<SecretKey> {
+        if *public_key == self.keys.public_key() {
+            self.keys.secret_key().ok().cloned()
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use crate::relays;
+use lightning_invoice::Bolt11Invoice;
+use nostr_sdk::{FromBech32, PublicKey, SecretKey, Url};
+use std::str::FromStr;
+
+/// Length of a bech32-encoded 32-byte key, e.g. an `npub` or `nsec`.
+const BECH32_KEY_LEN: usize = 63;
+
+/// Longest relay URL accepted. Matches the limit most browsers put on URLs.
+const MAX_RELAY_URL_LEN: usize = 2048;
+
+/// Longest invoice accepted. Matches the most characters that fit in a QR code, which is how most invoices are shared.
+const MAX_INVOICE_LEN: usize = 7089;
+
+/// Error from validating a command's input, with a message meant to be shown to the user.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The input is empty.
+    Empty { field: &'static str },
+
+    /// The input is longer than anything valid could be.
+    TooLong { field: &'static str, max_len: usize },
+
+    /// The input isn't shaped like what the command expects.
+    Invalid {
+        field: &'static str,
+        reason: &'static str,
+    },
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty { field } => write!(f, "{field} is empty"),
+            Self::TooLong { field, max_len } => {
+                write!(f, "{field} is longer than {max_len} characters")
+            }
+            Self::Invalid { field, reason } => write!(f, "Invalid {field}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Parses a secret key encoded as an `nsec`. Surrounding whitespace is ignored.
+pub fn validate_nsec(nsec: &str) -> Result<SecretKey, ValidationError> {
+    let nsec = check_length("nsec", nsec.trim(), BECH32_KEY_LEN)?;
+    if nsec.starts_with("npub1") {
+        return Err(ValidationError::Invalid {
+            field: "nsec",
+            reason: "this is a public key (npub), not a secret key",
+        });
+    }
+    if !nsec.starts_with("nsec1") {
+        return Err(ValidationError::Invalid {
+            field: "nsec",
+            reason: "secret keys start with \"nsec1\"",
+        });
+    }
+
+    SecretKey::from_bech32(nsec).map_err(|_| ValidationError::Invalid {
+        field: "nsec",
+        reason: "the key is mistyped or truncated",
+    })
+}
+
+/// Parses a public key encoded as an `npub`. Surrounding whitespace is ignored.
+pub fn validate_npub(npub: &str) -> Result<PublicKey, ValidationError> {
+    let npub = check_length("npub", npub.trim(), BECH32_KEY_LEN)?;
+    if npub.starts_with("nsec1") {
+        return Err(ValidationError::Invalid {
+            field: "npub",
+            reason: "this is a secret key (nsec), not a public key",
+        });
+    }
+    if !npub.starts_with("npub1") {
+        return Err(ValidationError::Invalid {
+            field: "npub",
+            reason: "public keys start with \"npub1\"",
+        });
+    }
+
+    PublicKey::from_bech32(npub).map_err(|_| ValidationError::Invalid {
+        field: "npub",
+        reason: "the key is mistyped or truncated",
+    })
+}
+
+/// Parses a relay URL, which must be a `ws://` or `wss://` URL with a host.
+pub fn validate_relay_url(url: &str) -> Result<Url, ValidationError> {
+    let url = check_length("relay URL", url.trim(), MAX_RELAY_URL_LEN)?;
+    let url = Url::parse(url).map_err(|_| ValidationError::Invalid {
+        field: "relay URL",
+        reason: "not a URL",
+    })?;
+
+    if url.scheme() != "ws" && url.scheme() != "wss" {
+        return Err(ValidationError::Invalid {
+            field: "relay URL",
+            reason: "relay URLs start with \"wss://\" or \"ws://\"",
+        });
+    }
+    if url.host_str().map_or(true, str::is_empty) {
+        return Err(ValidationError::Invalid {
+            field: "relay URL",
+            reason: "missing host",
+        });
+    }
+
+    Ok(url)
+}
+
+/// Parses a relay URL as typed by the user, returning it in the form relays are stored in (see
+/// [`relays::normalize_relay_url`]). A URL without a scheme is taken to be a `wss://` URL, but any other scheme
+/// than `ws://` or `wss://` (e.g. `https://`) is rejected rather than replaced, since it's likely not a relay.
+pub fn normalize_relay_url(input: &str) -> Result<String, ValidationError> {
+    let input = check_length("relay URL", input.trim(), MAX_RELAY_URL_LEN)?;
+    let url = if input.contains("://") {
+        validate_relay_url(input)?
+    } else {
+        validate_relay_url(&format!("wss://{input}"))?
+    };
+    Ok(relays::normalize_relay_url(&url))
+}
+
+/// Parses a BOLT11 Lightning invoice. Surrounding whitespace and a `lightning:` prefix are ignored.
+pub fn validate_invoice(invoice: &str) -> Result<Bolt11Invoice, ValidationError> {
+    let invoice = invoice.trim();
+    let invoice = check_length(
+        "invoice",
+        strip_prefix_ignore_case(invoice, "lightning:").unwrap_or(invoice),
+        MAX_INVOICE_LEN,
+    )?;
+    if !invoice.to_ascii_lowercase().starts_with("ln") {
+        return Err(ValidationError::Invalid {
+            field: "invoice",
+            reason: "Lightning invoices start with \"ln\"",
+        });
+    }
+
+    Bolt11Invoice::from_str(invoice).map_err(|_| ValidationError::Invalid {
+        field: "invoice",
+        reason: "the invoice is mistyped or truncated",
+    })
+}
+
+fn check_length<'a>(
+    field: &'static str,
+    value: &'a str,
+    max_len: usize,
+) -> Result<&'a str, ValidationError> {
+    if value.is_empty() {
+        return Err(ValidationError::Empty { field });
+    }
+    if value.len() > max_len {
+        return Err(ValidationError::TooLong { field, max_len });
+    }
+
+    Ok(value)
+}
+
+fn strip_prefix_ignore_case<'a>(value: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = value.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &value[prefix.len()..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{Keys, ToBech32};
+    use proptest::prelude::*;
+
+    // https://github.com/lightning/bolts/blob/master/11-payment-encoding.md#examples
+    const INVOICE: &str = "lnbc2500u1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdq5xysxxatsyp3k7enxv4jsxqzpu9qrsgquk0rl77nj30yxdy8j9vdx85fkpmdla2087ne0xh8nhedh8w27kyke0lp53ut353s06fv3qfegext0eh0ymjpf39tuven09sam30g4vgpfna3rh";
+
+    #[test]
+    fn nsec() {
+        let keys = Keys::generate();
+        let secret_key = keys.secret_key().unwrap();
+        let nsec = secret_key.to_bech32().unwrap();
+
+        assert_eq!(validate_nsec(&nsec).as_ref(), Ok(secret_key));
+        assert_eq!(
+            validate_nsec(&format!(" {nsec}\n")).as_ref(),
+            Ok(secret_key)
+        );
+
+        assert_eq!(
+            validate_nsec(""),
+            Err(ValidationError::Empty { field: "nsec" })
+        );
+        assert_eq!(
+            validate_nsec(&format!("{nsec}{nsec}")),
+            Err(ValidationError::TooLong {
+                field: "nsec",
+                max_len: BECH32_KEY_LEN
+            })
+        );
+        for invalid in [
+            keys.public_key().to_bech32().unwrap(),
+            "nothing like a key".to_string(),
+            nsec[..nsec.len() - 1].to_string(),
+        ] {
+            assert!(matches!(
+                validate_nsec(&invalid),
+                Err(ValidationError::Invalid { field: "nsec", .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn npub() {
+        let keys = Keys::generate();
+        let npub = keys.public_key().to_bech32().unwrap();
+
+        assert_eq!(validate_npub(&npub), Ok(keys.public_key()));
+        assert_eq!(validate_npub(&format!("{npub} ")), Ok(keys.public_key()));
+
+        assert_eq!(
+            validate_npub("  "),
+            Err(ValidationError::Empty { field: "npub" })
+        );
+        assert_eq!(
+            validate_npub(&format!("{npub}{npub}")),
+            Err(ValidationError::TooLong {
+                field: "npub",
+                max_len: BECH32_KEY_LEN
+            })
+        );
+        for invalid in [
+            keys.secret_key().unwrap().to_bech32().unwrap(),
+            keys.public_key().to_hex()[..BECH32_KEY_LEN].to_string(),
+            npub[..npub.len() - 1].to_string(),
+        ] {
+            assert!(matches!(
+                validate_npub(&invalid),
+                Err(ValidationError::Invalid { field: "npub", .. })
+            ));
+        }
+
+        // Telling the user they pasted a secret key where a public key belongs.
+        assert_eq!(
+            validate_npub(&keys.secret_key().unwrap().to_bech32().unwrap())
+                .unwrap_err()
+                .to_string(),
+            "Invalid npub: this is a secret key (nsec), not a public key"
+        );
+    }
+
+    #[test]
+    fn relay_url() {
+        for valid in [
+            "wss://relay.example.com",
+            "ws://localhost:7000",
+            " wss://relay.example.com/path ",
+        ] {
+            let url = validate_relay_url(valid).unwrap();
+            assert!(url.scheme() == "ws" || url.scheme() == "wss");
+        }
+
+        assert_eq!(
+            validate_relay_url(""),
+            Err(ValidationError::Empty { field: "relay URL" })
+        );
+        assert_eq!(
+            validate_relay_url(&format!("wss://{}.com", "a".repeat(MAX_RELAY_URL_LEN))),
+            Err(ValidationError::TooLong {
+                field: "relay URL",
+                max_len: MAX_RELAY_URL_LEN
+            })
+        );
+        for invalid in [
+            "relay.example.com",
+            "https://relay.example.com",
+            "wss://",
+            "not a url",
+        ] {
+            assert!(
+                matches!(
+                    validate_relay_url(invalid),
+                    Err(ValidationError::Invalid {
+                        field: "relay URL",
+                        ..
+                    })
+                ),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn relay_url_normalization() {
+        assert_eq!(
+            normalize_relay_url(" Relay.Example.com "),
+            Ok("wss://relay.example.com".to_string())
+        );
+        assert_eq!(
+            normalize_relay_url("relay.example.com:443/"),
+            Ok("wss://relay.example.com".to_string())
+        );
+        assert_eq!(
+            normalize_relay_url("ws://localhost:7000"),
+            Ok("ws://localhost:7000".to_string())
+        );
+        assert_eq!(
+            normalize_relay_url("wss://relay.example.com/inbox/"),
+            Ok("wss://relay.example.com/inbox".to_string())
+        );
+
+        assert_eq!(
+            normalize_relay_url("http://relay.example.com"),
+            Err(ValidationError::Invalid {
+                field: "relay URL",
+                reason: "relay URLs start with \"wss://\" or \"ws://\"",
+            })
+        );
+        assert_eq!(
+            normalize_relay_url(""),
+            Err(ValidationError::Empty { field: "relay URL" })
+        );
+        assert!(normalize_relay_url("not a url").is_err());
+    }
+
+    #[test]
+    fn invoice() {
+        let invoice = Bolt11Invoice::from_str(INVOICE).unwrap();
+
+        assert_eq!(validate_invoice(INVOICE), Ok(invoice.clone()));
+        assert_eq!(
+            validate_invoice(&format!("lightning:{INVOICE}\n")),
+            Ok(invoice.clone())
+        );
+        assert_eq!(
+            validate_invoice(&format!("LIGHTNING:{}", INVOICE.to_uppercase())),
+            Ok(invoice)
+        );
+
+        assert_eq!(
+            validate_invoice("lightning:"),
+            Err(ValidationError::Empty { field: "invoice" })
+        );
+        assert_eq!(
+            validate_invoice(&"lnbc".repeat(MAX_INVOICE_LEN)),
+            Err(ValidationError::TooLong {
+                field: "invoice",
+                max_len: MAX_INVOICE_LEN
+            })
+        );
+        for invalid in [
+            "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+            &INVOICE[..INVOICE.len() - 1],
+            "lnnotaninvoice",
+        ] {
+            assert!(matches!(
+                validate_invoice(invalid),
+                Err(ValidationError::Invalid {
+                    field: "invoice",
+                    ..
+                })
+            ));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn validating_arbitrary_input_never_panics(input in any::<String>()) {
+            let _ = validate_nsec(&input);
+            let _ = validate_npub(&input);
+            let _ = validate_relay_url(&input);
+            let _ = validate_relay_url(&format!("wss://{input}"));
+            let _ = validate_invoice(&input);
+            let _ = validate_invoice(&format!("lightning:{input}"));
+        }
+    }
+}
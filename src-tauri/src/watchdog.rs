@@ -0,0 +1,234 @@
+use crate::error_log;
+use serde::Serialize;
+use std::future::Future;
+use std::time::Duration;
+
+/// How a supervised task is relaunched after it exits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// How many times in a row the task is relaunched before giving up.
+    pub max_restarts: u32,
+
+    /// How long to wait before the first relaunch. Doubles after each relaunch.
+    pub initial_backoff: Duration,
+
+    /// The longest to ever wait before a relaunch.
+    pub max_backoff: Duration,
+
+    /// How long the task has to run before exiting for its restarts, and the backoff, to start over.
+    pub healthy_after: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            healthy_after: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// How long to wait before relaunching the task for the `restart_count`-th time (starting at 1).
+    fn backoff(&self, restart_count: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2_u32.saturating_pow(restart_count.saturating_sub(1)))
+            .min(self.max_backoff)
+    }
+}
+
+/// Reported each time a supervised task is relaunched.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TaskRestart {
+    /// How many times in a row the task has been relaunched, including this time.
+    pub restart_count: u32,
+
+    /// Why the task exited.
+    pub cause: String,
+}
+
+/// Runs a task, relaunching it with backoff whenever it exits or panics.
+/// Gives up once the task has been relaunched `policy.max_restarts` times without ever running for
+/// `policy.healthy_after`.
+/// `on_restart` is called just before each relaunch.
+/// Returns why the task last exited.
+pub async fn supervise<Task, TaskFuture>(
+    mut start_task: Task,
+    policy: RestartPolicy,
+    mut on_restart: impl FnMut(&TaskRestart),
+) -> String
+where
+    Task: FnMut() -> TaskFuture,
+    TaskFuture: Future<Output = anyhow::Result<()>> + Send + 'static,
+{
+    let mut restart_count = 0;
+
+    loop {
+        let started_at = tokio::time::Instant::now();
+        let cause = match tokio::spawn(start_task()).await {
+            Ok(Ok(())) => "task exited".to_string(),
+            Ok(Err(err)) => format!("task failed: {}", err),
+            Err(err) if err.is_panic() => "task panicked".to_string(),
+            Err(err) => err.to_string(),
+        };
+        if started_at.elapsed() >= policy.healthy_after {
+            restart_count = 0;
+        }
+
+        if restart_count >= policy.max_restarts {
+            error_log::report(format!(
+                "Supervised task exited ({cause}), giving up after {restart_count} restarts"
+            ));
+            return cause;
+        }
+
+        restart_count += 1;
+        error_log::report(format!(
+            "Supervised task exited ({cause}), restarting (attempt {restart_count})"
+        ));
+
+        tokio::time::sleep(policy.backoff(restart_count)).await;
+        on_restart(&TaskRestart {
+            restart_count,
+            cause,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    fn get_fast_policy(max_restarts: u32) -> RestartPolicy {
+        RestartPolicy {
+            max_restarts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+            healthy_after: Duration::from_secs(60),
+        }
+    }
+
+    #[tokio::test]
+    async fn relaunches_task_up_to_retry_cap() {
+        let launch_count = Arc::new(AtomicU32::new(0));
+        let mut restarts = Vec::new();
+
+        let launch_count_clone = launch_count.clone();
+        let cause = supervise(
+            move || {
+                launch_count_clone.fetch_add(1, Ordering::SeqCst);
+                async { Err(anyhow::anyhow!("server died")) }
+            },
+            get_fast_policy(3),
+            |restart| restarts.push(restart.clone()),
+        )
+        .await;
+
+        // The task should be launched once, then relaunched 3 times.
+        assert_eq!(launch_count.load(Ordering::SeqCst), 4);
+        assert_eq!(cause, "task failed: server died");
+        assert_eq!(
+            restarts,
+            (1..=3)
+                .map(|restart_count| TaskRestart {
+                    restart_count,
+                    cause: "task failed: server died".to_string(),
+                })
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn relaunches_panicked_task() {
+        let launch_count = Arc::new(AtomicU32::new(0));
+        let mut restarts = Vec::new();
+
+        let launch_count_clone = launch_count.clone();
+        supervise(
+            move || {
+                let launch_count = launch_count_clone.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    // Panic on the first launch, then exit cleanly.
+                    if launch_count == 0 {
+                        panic!("server panicked");
+                    }
+                    Ok(())
+                }
+            },
+            get_fast_policy(1),
+            |restart| restarts.push(restart.clone()),
+        )
+        .await;
+
+        assert_eq!(launch_count.load(Ordering::SeqCst), 2);
+        assert_eq!(restarts.len(), 1);
+        assert_eq!(restarts[0].cause, "task panicked");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn healthy_run_resets_restart_count() {
+        let launch_count = Arc::new(AtomicU32::new(0));
+        let mut restart_counts = Vec::new();
+
+        let launch_count_clone = launch_count.clone();
+        let policy = get_fast_policy(1);
+        supervise(
+            move || {
+                let launch_count = launch_count_clone.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    // Run long enough to count as healthy for the first 3 launches, then fail straight away.
+                    if launch_count < 3 {
+                        tokio::time::sleep(policy.healthy_after).await;
+                    }
+                    Err(anyhow::anyhow!("server died"))
+                }
+            },
+            policy,
+            |restart| restart_counts.push(restart.restart_count),
+        )
+        .await;
+
+        // Each healthy run is relaunched as if it were the first. The relaunch after the last one fails straight away,
+        // which uses up the single restart.
+        assert_eq!(launch_count.load(Ordering::SeqCst), 4);
+        assert_eq!(restart_counts, vec![1, 1, 1]);
+    }
+
+    #[tokio::test]
+    async fn no_restarts_allowed() {
+        let launch_count = Arc::new(AtomicU32::new(0));
+
+        let launch_count_clone = launch_count.clone();
+        supervise(
+            move || {
+                launch_count_clone.fetch_add(1, Ordering::SeqCst);
+                async { Ok(()) }
+            },
+            get_fast_policy(0),
+            |_| panic!("task should not be restarted"),
+        )
+        .await;
+
+        assert_eq!(launch_count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let policy = RestartPolicy {
+            max_restarts: 10,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5),
+            healthy_after: Duration::from_secs(60),
+        };
+
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(4), Duration::from_secs(5));
+        assert_eq!(policy.backoff(100), Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,238 @@
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
+use nostr_sdk::hashes::{sha256, Hash};
+use nostr_sdk::{Event, JsonUtil, Kind};
+use serde::Serialize;
+use std::str::FromStr;
+
+/// Whether a zap receipt matches the zap request it claims to be for.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ZapReceiptValidation {
+    /// Set if there are no discrepancies.
+    pub valid: bool,
+
+    pub discrepancies: Vec<ZapReceiptDiscrepancy>,
+}
+
+/// Something about a zap receipt that doesn't follow NIP-57 or doesn't match the zap request.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ZapReceiptDiscrepancy {
+    /// The receipt isn't a kind 9735 event.
+    NotAZapReceipt { kind: u64 },
+
+    /// The receipt's ID or signature is invalid.
+    InvalidSignature,
+
+    /// The receipt has no `bolt11` tag.
+    MissingBolt11,
+
+    /// The receipt's `bolt11` tag isn't a valid invoice.
+    InvalidBolt11 { reason: String },
+
+    /// The invoice isn't for the amount in the zap request's `amount` tag.
+    AmountMismatch {
+        requested_msats: u64,
+        invoice_msats: Option<u64>,
+    },
+
+    /// The receipt has no `description` tag.
+    MissingDescription,
+
+    /// The receipt's `description` tag isn't the zap request.
+    DescriptionMismatch,
+
+    /// The invoice's description hash isn't the hash of the receipt's `description` tag.
+    DescriptionHashMismatch,
+
+    /// The receipt's `p` tag isn't the zap request's recipient.
+    RecipientMismatch,
+
+    /// The receipt's `e` tag isn't the event that the zap request is for.
+    EventMismatch,
+}
+
+/// Checks a zap receipt against the zap request it's for, following the validation rules of NIP-57.
+/// Every discrepancy found is reported, not just the first. Checking that the receipt was published by the
+/// recipient's LNURL server is left to the caller, since it needs the server's `nostrPubkey`.
+/// Errors if `zap_request` isn't a zap request.
+pub fn validate_zap_receipt(
+    receipt: &Event,
+    zap_request: &Event,
+) -> anyhow::Result<ZapReceiptValidation> {
+    if zap_request.kind() != Kind::ZapRequest {
+        return Err(anyhow::anyhow!("Not a zap request"));
+    }
+
+    let mut discrepancies = Vec::new();
+    if receipt.kind() != Kind::ZapReceipt {
+        discrepancies.push(ZapReceiptDiscrepancy::NotAZapReceipt {
+            kind: receipt.kind().as_u64(),
+        });
+    }
+    if receipt.verify().is_err() {
+        discrepancies.push(ZapReceiptDiscrepancy::InvalidSignature);
+    }
+
+    let description = tag_value(receipt, "description");
+    match &description {
+        None => discrepancies.push(ZapReceiptDiscrepancy::MissingDescription),
+        Some(description) => {
+            let described_id = Event::from_json(description).ok().map(|event| event.id());
+            if described_id != Some(zap_request.id()) {
+                discrepancies.push(ZapReceiptDiscrepancy::DescriptionMismatch);
+            }
+        }
+    }
+
+    match tag_value(receipt, "bolt11").map(|bolt11| Bolt11Invoice::from_str(&bolt11)) {
+        None => discrepancies.push(ZapReceiptDiscrepancy::MissingBolt11),
+        Some(Err(err)) => discrepancies.push(ZapReceiptDiscrepancy::InvalidBolt11 {
+            reason: err.to_string(),
+        }),
+        Some(Ok(invoice)) => {
+            if let Some(requested_msats) =
+                tag_value(zap_request, "amount").and_then(|amount| amount.parse::<u64>().ok())
+            {
+                if invoice.amount_milli_satoshis() != Some(requested_msats) {
+                    discrepancies.push(ZapReceiptDiscrepancy::AmountMismatch {
+                        requested_msats,
+                        invoice_msats: invoice.amount_milli_satoshis(),
+                    });
+                }
+            }
+
+            // A missing description has already been reported, so only check the hash of one that's there.
+            if let Some(description) = &description {
+                let hash_matches = match invoice.description() {
+                    Bolt11InvoiceDescription::Hash(hash) => {
+                        sha256::Hash::hash(description.as_bytes())[..] == hash.0[..]
+                    }
+                    Bolt11InvoiceDescription::Direct(_) => false,
+                };
+                if !hash_matches {
+                    discrepancies.push(ZapReceiptDiscrepancy::DescriptionHashMismatch);
+                }
+            }
+        }
+    }
+
+    if tag_value(receipt, "p") != tag_value(zap_request, "p") {
+        discrepancies.push(ZapReceiptDiscrepancy::RecipientMismatch);
+    }
+    if let Some(event_id) = tag_value(zap_request, "e") {
+        if tag_value(receipt, "e") != Some(event_id) {
+            discrepancies.push(ZapReceiptDiscrepancy::EventMismatch);
+        }
+    }
+
+    Ok(ZapReceiptValidation {
+        valid: discrepancies.is_empty(),
+        discrepancies,
+    })
+}
+
+/// Returns the value of the event's first tag named `name`.
+fn tag_value(event: &Event, name: &str) -> Option<String> {
+    event
+        .tags()
+        .iter()
+        .find_map(|tag| match tag.as_vec().as_slice() {
+            [tag_name, value, ..] if tag_name == name => Some(value.clone()),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin_hashes::Hash as _;
+    use lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret};
+    use nostr_sdk::{EventBuilder, Keys, Tag};
+
+    /// Builds a receipt for `zap_request`, paid with an invoice for `amount_msats` whose description hash
+    /// commits to `description`.
+    fn zap_receipt(zap_request: &Event, description: &str, amount_msats: u64) -> Event {
+        let node_secret_key = secp256k1::SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let invoice = InvoiceBuilder::new(Currency::Bitcoin)
+            .description_hash(bitcoin_hashes::sha256::Hash::hash(description.as_bytes()))
+            .payment_hash(bitcoin_hashes::sha256::Hash::hash(&[0; 32]))
+            .payment_secret(PaymentSecret([0; 32]))
+            .amount_milli_satoshis(amount_msats)
+            .current_timestamp()
+            .min_final_cltv_expiry_delta(144)
+            .build_signed(|message| {
+                secp256k1::Secp256k1::new().sign_ecdsa_recoverable(message, &node_secret_key)
+            })
+            .unwrap();
+
+        let mut tags = vec![
+            Tag::parse(&["bolt11", &invoice.to_string()]).unwrap(),
+            Tag::parse(&["description", description]).unwrap(),
+        ];
+        for name in ["p", "e"] {
+            if let Some(value) = tag_value(zap_request, name) {
+                tags.push(Tag::parse(&[name, value.as_str()]).unwrap());
+            }
+        }
+        EventBuilder::new(Kind::ZapReceipt, "", tags)
+            .to_event(&Keys::generate())
+            .unwrap()
+    }
+
+    fn zap_request(amount_msats: u64) -> Event {
+        EventBuilder::new(
+            Kind::ZapRequest,
+            "Great post!",
+            [
+                Tag::parse(&["p", &Keys::generate().public_key().to_hex()]).unwrap(),
+                Tag::parse(&["e", &"ab".repeat(32)]).unwrap(),
+                Tag::parse(&["amount", &amount_msats.to_string()]).unwrap(),
+                Tag::parse(&["relays", "wss://relay.example.com"]).unwrap(),
+            ],
+        )
+        .to_event(&Keys::generate())
+        .unwrap()
+    }
+
+    #[test]
+    fn valid_zap_receipt() {
+        let zap_request = zap_request(21_000);
+        let receipt = zap_receipt(&zap_request, &zap_request.as_json(), 21_000);
+
+        assert_eq!(
+            validate_zap_receipt(&receipt, &zap_request).unwrap(),
+            ZapReceiptValidation {
+                valid: true,
+                discrepancies: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn mismatched_zap_receipt() {
+        let zap_request = zap_request(21_000);
+
+        let receipt = zap_receipt(&zap_request, &zap_request.as_json(), 1_000);
+        assert_eq!(
+            validate_zap_receipt(&receipt, &zap_request).unwrap(),
+            ZapReceiptValidation {
+                valid: false,
+                discrepancies: vec![ZapReceiptDiscrepancy::AmountMismatch {
+                    requested_msats: 21_000,
+                    invoice_msats: Some(1_000),
+                }],
+            }
+        );
+
+        let other_zap_request = self::zap_request(21_000);
+        let receipt = zap_receipt(&zap_request, &other_zap_request.as_json(), 21_000);
+        assert_eq!(
+            validate_zap_receipt(&receipt, &zap_request)
+                .unwrap()
+                .discrepancies,
+            vec![ZapReceiptDiscrepancy::DescriptionMismatch]
+        );
+
+        assert!(validate_zap_receipt(&receipt, &receipt).is_err());
+    }
+}
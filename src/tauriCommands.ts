@@ -1,7 +1,74 @@
 import { invoke } from "@tauri-apps/api";
 import { Event, listen } from "@tauri-apps/api/event";
 
-import { type UnsignedNostrEvent } from "./types";
+import {
+  type AccountCompleteness,
+  type AccountDataRefresh,
+  type AccountListEntry,
+  type AccountMetadata,
+  type AccountStats,
+  type AppAuthorization,
+  type ApprovalTimeouts,
+  type BulkImportResult,
+  type Capabilities,
+  type ConfirmationPhrasePolicy,
+  type ConnectionLogEntry,
+  type ConnectionQrPayload,
+  type Contact,
+  type ContentWarningPolicy,
+  type DatabaseDiagnosis,
+  type DecodedEntity,
+  type DecodedInvoice,
+  type DecryptDmRequestPayload,
+  type DuplicateRelays,
+  type EventVerification,
+  type FeeEstimate,
+  type HardwareKeyBinding,
+  type IdenticonSeed,
+  type IdentityCard,
+  type IdentityFingerprint,
+  type KeyCacheStats,
+  type KeyHealth,
+  type LightningAddress,
+  type LogKind,
+  type MaxSinglePayment,
+  type Nip05Profile,
+  type NostrEvent,
+  type NwcMethod,
+  type PasskeyAssertion,
+  type PayInvoiceRequestPayload,
+  type PayInvoiceResponse,
+  type PaymentHistoryFilter,
+  type PaymentLogEntry,
+  type PendingRequestEntry,
+  type PowProgressPayload,
+  type ProfileFields,
+  type QuietHours,
+  type RelayPolicy,
+  type RelayPublishResult,
+  type RelayReachabilityReport,
+  type RelayTestResult,
+  type RelayVisibilityPolicy,
+  type RememberedSignDecision,
+  type RequestAttention,
+  type RequestNotification,
+  type RotateAccountResponse,
+  type ServerInfo,
+  type ServerRestart,
+  type SessionGrantEntry,
+  type SetupState,
+  type SignEventRequestPayload,
+  type SignEventWarning,
+  type SignEventsRequestPayload,
+  type SigningBenchmark,
+  type SigningLogEntry,
+  type UnlockMethod,
+  type UnsignedNostrEvent,
+  type UpdateFollowListResponse,
+  type UpdateProfileMetadataResponse,
+  type VaultIntegrityReport,
+  type ZapReceiptValidation,
+} from "./types";
 
 // TODO: handle listening for getPublicKey requests
 
@@ -11,8 +78,12 @@ const getRandomInt = (max: number): number => {
 
 const signEventRequestHandlers: { [key: number]: SignEventRequestHandler } = {};
 
+const signEventsRequestHandlers: { [key: number]: SignEventsRequestHandler } = {};
+
 const payInvoiceRequestHandlers: { [key: number]: PayInvoiceRequestHandler } = {};
 
+const decryptDmRequestHandlers: { [key: number]: DecryptDmRequestHandler } = {};
+
 /**
  * Register a handler for sign event requests. Any number of handlers can be registered at once.
  * When a sign event request is received, all registered handlers will be called one at a time.
@@ -36,6 +107,28 @@ export const handleSignEventRequests = (handler: SignEventRequestHandler) => {
   };
 };
 
+/**
+ * Register a handler for requests to sign a batch of events, which may be for several accounts.
+ * Handlers are called the same way as sign event requests: the first handler to return true
+ * approves the whole batch, and if none do, the batch is denied.
+ * @param handler The handler to register. Will be called with the batch's events, grouped by the
+ * account that will sign them.
+ * @returns A function that can be called to unregister the handler.
+ */
+export const handleSignEventsRequests = (handler: SignEventsRequestHandler) => {
+  // Generate a random handler ID that is not already in use.
+  let handlerId = getRandomInt(1000000);
+  while (signEventsRequestHandlers[handlerId]) {
+    handlerId = getRandomInt(1000000);
+  }
+
+  signEventsRequestHandlers[handlerId] = handler;
+
+  return () => {
+    delete signEventsRequestHandlers[handlerId];
+  };
+};
+
 /**
  * Register a handler for pay invoice requests. Any number of handlers can be registered at once.
  * When a pay invoice request is received, all registered handlers will be called one at a time.
@@ -44,7 +137,10 @@ export const handleSignEventRequests = (handler: SignEventRequestHandler) => {
  * to be "failed" if any handler returned "failed", and "denied" if all handlers returned "denied"
  * (including if no handlers are registered). Currently the order in which handlers are called is
  * unspecified.
- * @param handler The handler to register. Will be called with invoices that other apps want to pay.
+ * @param handler The handler to register. Will be called with invoices that other apps want to pay,
+ * along with the estimated routing fee (null if estimating the fee failed), and the phrase the user
+ * must type to approve the payment (null if none is needed). To approve a payment that needs a
+ * phrase, return `{ typedPhrase }` with what the user typed instead of true.
  * @returns A function that can be called to unregister the handler.
  */
 export const handlePayInvoiceRequests = (handler: PayInvoiceRequestHandler) => {
@@ -61,12 +157,194 @@ export const handlePayInvoiceRequests = (handler: PayInvoiceRequestHandler) => {
   };
 };
 
+/**
+ * Register a handler for requests to decrypt a direct message. The first handler to return true
+ * approves decrypting the message, and if none do, the request is denied.
+ * @param handler The handler to register. Will be called with who sent the message, the account it
+ * will be decrypted with, and how it is encrypted.
+ * @returns A function that can be called to unregister the handler.
+ */
+export const handleDecryptDmRequests = (handler: DecryptDmRequestHandler) => {
+  // Generate a random handler ID that is not already in use.
+  let handlerId = getRandomInt(1000000);
+  while (decryptDmRequestHandlers[handlerId]) {
+    handlerId = getRandomInt(1000000);
+  }
+
+  decryptDmRequestHandlers[handlerId] = handler;
+
+  return () => {
+    delete decryptDmRequestHandlers[handlerId];
+  };
+};
+
+/**
+ * Listen for the signing server being restarted after it died unexpectedly.
+ * @param handler Called with how many times the server has been restarted and why it died.
+ * @returns A promise resolving to a function that can be called to stop listening.
+ */
+export const onServerRestarted = async (
+  handler: (restart: ServerRestart) => void,
+): Promise<() => void> => {
+  return await listen("server_restarted", (event: Event<ServerRestart>) => {
+    handler(event.payload);
+  });
+};
+
+/**
+ * Listen for the signing server being given up on after it kept dying. Apps can't connect until Keystache is restarted.
+ * @param handler Called with why the server last died.
+ * @returns A promise resolving to a function that can be called to stop listening.
+ */
+export const onServerStopped = async (
+  handler: (cause: string) => void,
+): Promise<() => void> => {
+  return await listen("server_stopped", (event: Event<string>) => {
+    handler(event.payload);
+  });
+};
+
+/**
+ * Listen for events that Keystache has signed after the user approved them.
+ * @param handler Called with the signed event as a JSON string.
+ * @returns A promise resolving to a function that can be called to stop listening.
+ */
+export const onEventSigned = async (
+  handler: (signedEventJson: string) => void,
+): Promise<() => void> => {
+  return await listen("event_signed", (event: Event<string>) => {
+    handler(event.payload);
+  });
+};
+
+/**
+ * Listen for progress while proof of work is mined for an event signed with `signEventWithPow`.
+ * @param handler Called every so often with how many nonces have been tried and the best result so far.
+ * @returns A promise resolving to a function that can be called to stop listening.
+ */
+export const onPowProgress = async (
+  handler: (progress: PowProgressPayload) => void,
+): Promise<() => void> => {
+  return await listen("pow_progress", (event: Event<PowProgressPayload>) => {
+    handler(event.payload);
+  });
+};
+
+/**
+ * Listen for notifications to show about requests that arrived while the window wasn't focused.
+ * Only sent if notifications are turned on with `setRequestAttention`.
+ * @param handler Called with the notification's title and body, e.g. to show with the
+ * Notification API.
+ * @returns A promise resolving to a function that can be called to stop listening.
+ */
+export const onRequestNotification = async (
+  handler: (notification: RequestNotification) => void,
+): Promise<() => void> => {
+  return await listen(
+    "request_notification",
+    (event: Event<RequestNotification>) => {
+      handler(event.payload);
+    },
+  );
+};
+
+/**
+ * Get what this build of Keystache supports, for apps to adapt to.
+ * @returns The supported NIPs as two-digit numbers, the configured payment backends, and the optional
+ * features compiled in.
+ */
+export const getCapabilities = async (): Promise<Capabilities> => {
+  return await invoke("get_capabilities");
+};
+
+/**
+ * List the requests that are waiting for the user, e.g. to rebuild the approval queue after a reload.
+ * Each request's payload is the one it was emitted with, so it can be handled like the original event.
+ * @returns The pending requests, oldest first.
+ */
+export const listPendingRequests = async (): Promise<PendingRequestEntry[]> => {
+  return await invoke("list_pending_requests");
+};
+
+/**
+ * Estimate the routing fee for paying an invoice, without paying it.
+ * @param invoice The Bolt11 invoice string.
+ * @returns The estimated fee in sats, or "not_supported" if the payment backend can't estimate fees.
+ * @throws If the invoice is invalid or the payment backend fails to estimate the fee.
+ */
+export const estimatePaymentFee = async (invoice: string): Promise<FeeEstimate> => {
+  return await invoke("estimate_payment_fee", { invoice });
+};
+
+/**
+ * Ask the user to approve paying an invoice from the active account, and pay it if they do.
+ * Paying an invoice that was paid recently returns the earlier result instead of paying it again.
+ * @param invoice The Bolt11 invoice string.
+ * @param appId The npub of the app asking for the payment, if any.
+ * @returns The payment hash and the preimage proving the invoice was paid.
+ * @throws If no payment backend is configured, or the payment is rejected or fails.
+ */
+export const payInvoice = async (
+  invoice: string,
+  appId?: string,
+): Promise<PayInvoiceResponse> => {
+  return await invoke("pay_invoice", { invoice, appId });
+};
+
 /**
  * Get the public key of the user's Nostr account from the Tauri backend.
+ * @param appId The npub of the app the key is for. If the app has its own identity turned on, that
+ * identity's public key is returned instead of the account's.
  * @returns The public key of the user's Nostr account.
  */
-export const getPublicKey = async (): Promise<string> => {
-  return await invoke("get_public_key");
+export const getPublicKey = async (appId?: string): Promise<string> => {
+  return await invoke("get_public_key", { appId });
+};
+
+/**
+ * Check whether an app is given an identity of its own instead of the active account's.
+ * @param appId The npub of the app.
+ * @returns Whether the app has its own identity.
+ * @throws If the npub is invalid or the Tauri database can't be read.
+ */
+export const getAppIdentityEnabled = async (appId: string): Promise<boolean> => {
+  return await invoke("get_app_identity_enabled", { appId });
+};
+
+/**
+ * Turn an app's own identity on or off. While on, the app is given a public key derived from the
+ * account's key and the app's npub, and events with that key are signed with the derived key.
+ * @param appId The npub of the app.
+ * @param enabled Whether the app should have its own identity.
+ * @returns A promise that resolves when the setting has been saved.
+ * @throws If the npub is invalid or the Tauri database fails to update.
+ */
+export const setAppIdentityEnabled = async (
+  appId: string,
+  enabled: boolean,
+): Promise<void> => {
+  return await invoke("set_app_identity_enabled", { appId, enabled });
+};
+
+/**
+ * Get a short fingerprint of the active account's public key, e.g. to render as an identicon so
+ * that the user can check at a glance that the expected identity is active.
+ * @returns The active account's npub and its fingerprint.
+ * @throws If there is no active account.
+ */
+export const getIdentityFingerprint = async (): Promise<IdentityFingerprint> => {
+  return await invoke("get_identity_fingerprint");
+};
+
+/**
+ * Get bytes derived from an npub that are always the same for it, e.g. to draw a consistent
+ * avatar and color for an account that has no profile picture.
+ * @param npub The npub to derive the seed from.
+ * @returns A hex-encoded seed, and a hue in degrees (0 up to 360) for the account's color.
+ * @throws If the npub is invalid.
+ */
+export const getIdenticonSeed = async (npub: string): Promise<IdenticonSeed> => {
+  return await invoke("get_identicon_seed", { npub });
 };
 
 /**
@@ -79,66 +357,1629 @@ export const setNsec = async (nsec: string): Promise<void> => {
   return await invoke("set_nsec", { nsec });
 }
 
-type SignEventRequestHandler = (
-  event: UnsignedNostrEvent, userPubkey: string
-) => Promise<boolean> | boolean;
+/**
+ * Copy a secret (e.g. an nSec) to the clipboard, and clear the clipboard after a timeout unless
+ * something else has been copied since.
+ * @param value The secret to copy.
+ * @param seconds How long to leave the secret in the clipboard for.
+ * @returns A promise that resolves once the secret has been copied.
+ * @throws If the timeout is zero or the clipboard can't be written to.
+ */
+export const copySecretToClipboardWithTimeout = async (
+  value: string,
+  seconds: number,
+): Promise<void> => {
+  return await invoke("copy_secret_to_clipboard_with_timeout", { value, seconds });
+};
 
-listen("sign_event_request", async (event: Event<[UnsignedNostrEvent, string]>) => {
-  let isApproved = false;
-  for (const handler of Object.values(signEventRequestHandlers)) {
-    isApproved = await handler(...event.payload);
-    if (isApproved) {
-      break;
-    }
-  }
-  respondToSignEventRequest(event.payload[0].id, isApproved);
-})
-  .then((unlisten) => {
-    // When vite reloads, a new event listener is created, so we need to unlisten to the old one.
-    // If we don't do this, each vite hot reload turns the old event listener into a phantom listener
-    // that has not event handlers and therefore immediately rejects all requests.
-    import.meta.hot?.on("vite:beforeUpdate", () => unlisten());
-    // TODO: Send a message to the Tauri backend to indicate that the event listener is ready.
-    // Before this, it should not send any `sign_event_request` events.
-  })
-  .catch((e) => {
-    console.error(e);
-  });
-const respondToSignEventRequest = async (
-  eventId: string,
-  approved: boolean,
+/**
+ * Add an account alongside any existing ones. Either the whole account is saved or none of it is.
+ * @param nsec The account's nSec.
+ * @param label A name for the account, or `null` to leave it unnamed.
+ * @param relayUrls Relays for the account, both read from and written to.
+ * @returns The npub of the new account.
+ * @throws If the nSec or a relay URL is invalid, or the account couldn't be saved.
+ */
+export const createAccount = async (
+  nsec: string,
+  label: string | null,
+  relayUrls: string[],
 ): Promise<string> => {
-  return await invoke("respond_to_sign_event_request", { eventId, approved });
+  return await invoke("create_account", { nsec, label, relayUrls });
 };
 
-type PayInvoiceRequestHandler = (
-  invoice: string,
-) => Promise<boolean> | boolean;
+/**
+ * Add an account derived from a BIP-39 mnemonic, at the NIP-06 path `m/44'/1237'/<index>'/0/0`.
+ * The same mnemonic and index always derive the same account. The mnemonic isn't stored.
+ * @param mnemonic The BIP-39 mnemonic (English wordlist).
+ * @param index The account index, e.g. 0 for the first account derived from the mnemonic.
+ * @returns The npub of the derived account.
+ * @throws If the mnemonic or index is invalid, or the account has already been added.
+ */
+export const deriveAccount = async (
+  mnemonic: string,
+  index: number,
+): Promise<string> => {
+  return await invoke("derive_account", { mnemonic, index });
+};
 
-listen("pay_invoice_request", async (event: Event<string>) => {
-  let isApproved = false;
-  for (const handler of Object.values(payInvoiceRequestHandlers)) {
-    isApproved = await handler(event.payload);
-    if (isApproved) {
-      break;
-    }
-  }
-  respondToPayInvoiceRequest(event.payload, isApproved);
-})
-  .then((unlisten) => {
-    // When vite reloads, a new event listener is created, so we need to unlisten to the old one.
-    // If we don't do this, each vite hot reload turns the old event listener into a phantom listener
-    // that has not event handlers and therefore immediately rejects all requests.
-    import.meta.hot?.on("vite:beforeUpdate", () => unlisten());
-    // TODO: Send a message to the Tauri backend to indicate that the event listener is ready.
-    // Before this, it should not send any `pay_invoice_request` events.
-  })
-  .catch((e) => {
-    console.error(e);
+/**
+ * List every app that has been paired with an account, along with what it has been granted.
+ * @returns Each app's npub and display name, the npub of the account it is paired with, its
+ * remembered sign decisions and session grant, the payment cap, and when it was last used.
+ * @throws If the Tauri database fails to read.
+ */
+export const listAuthorizations = async (): Promise<AppAuthorization[]> => {
+  return await invoke("list_authorizations");
+};
+
+/**
+ * List the sign decisions the user asked to remember for each app and event kind.
+ * @returns Each remembered decision, by the app's npub and the event kind.
+ * @throws If the Tauri database can't be read.
+ */
+export const listSignDecisions = async (): Promise<RememberedSignDecision[]> => {
+  return await invoke("list_sign_decisions");
+};
+
+/**
+ * Forget the remembered sign decision for an app and event kind, so the user is asked again.
+ * @param appId The app's npub.
+ * @param kind The event kind.
+ * @returns A promise that resolves once the decision has been forgotten.
+ * @throws If the npub is invalid or the Tauri database fails to update.
+ */
+export const forgetSignDecision = async (
+  appId: string,
+  kind: number,
+): Promise<void> => {
+  return await invoke("forget_sign_decision", { appId, kind });
+};
+
+/**
+ * Let an app have events of certain kinds signed without asking for a while, e.g. "sign freely
+ * for the next 10 minutes". Replaces any session the app already has.
+ * @param appId The app's npub.
+ * @param durationSecs How long the session lasts, in seconds. At most 24 hours.
+ * @param allowedKinds The event kinds that are signed without asking.
+ * @returns A promise that resolves once the session has been granted.
+ * @throws If the npub is invalid, the duration is zero or too long, or no kinds are allowed.
+ */
+export const grantSession = async (
+  appId: string,
+  durationSecs: number,
+  allowedKinds: number[],
+): Promise<void> => {
+  return await invoke("grant_session", { appId, durationSecs, allowedKinds });
+};
+
+/**
+ * End an app's session early, so that its requests are asked about again.
+ * @param appId The app's npub.
+ * @returns A promise that resolves once the session has ended.
+ * @throws If the npub is invalid.
+ */
+export const revokeSession = async (appId: string): Promise<void> => {
+  return await invoke("revoke_session", { appId });
+};
+
+/**
+ * List the apps with a session that hasn't expired yet.
+ * @returns Each app's npub, the kinds it may sign, and how long its session has left.
+ */
+export const listSessionGrants = async (): Promise<SessionGrantEntry[]> => {
+  return await invoke("list_session_grants");
+};
+
+/**
+ * Revoke an app's pairing and everything it has been granted.
+ * @param appId The app's npub.
+ * @returns A promise that resolves once the app's authorization has been removed.
+ * @throws If the npub is invalid or the Tauri database fails to update.
+ */
+export const revokeAuthorization = async (appId: string): Promise<void> => {
+  return await invoke("revoke_authorization", { appId });
+};
+
+/**
+ * Set the friendly name that approval prompts show for an app, instead of its npub.
+ * @param appId The app's npub.
+ * @param name The name, or `null` to clear it.
+ * @returns A promise that resolves once the name has been saved.
+ * @throws If the npub is invalid, the name is empty, or the app isn't paired with an account.
+ */
+export const setAppName = async (
+  appId: string,
+  name: string | null,
+): Promise<void> => {
+  return await invoke("set_app_name", { appId, name });
+};
+
+/**
+ * List what an app has asked to sign, most recent first, e.g. to show when reviewing its access.
+ * @param appId The npub of the app.
+ * @param limit The most requests to return.
+ * @param offset How many of the most recent requests to skip.
+ * @returns Each request's event kind, when it ended, and whether it was approved.
+ * @throws If the npub is invalid, or if the Tauri database fails to read.
+ */
+export const getAppSigningHistory = async (
+  appId: string,
+  limit: number,
+  offset: number,
+): Promise<SigningLogEntry[]> => {
+  return await invoke("get_app_signing_history", { appId, limit, offset });
+};
+
+/**
+ * List requests to pay invoices, most recent first.
+ * @param limit The most payments to return.
+ * @param offset How many of the most recent payments to skip.
+ * @param filter If provided, only payments matching it are returned.
+ * @returns Each payment's invoice, amount, description, requesting app, and outcome.
+ * @throws If the Tauri database fails to read.
+ */
+export const getPaymentHistory = async (
+  limit: number,
+  offset: number,
+  filter?: PaymentHistoryFilter,
+): Promise<PaymentLogEntry[]> => {
+  return await invoke("get_payment_history", {
+    limit,
+    offset,
+    filter: filter ?? null,
+  });
+};
+
+/**
+ * Replace the scam list. Sign and pay requests that reference an entry are flagged with a warning.
+ * @param entries Pubkeys (hex or npub), LNURLs, or lightning addresses.
+ * @returns A promise that resolves once the list has been saved.
+ * @throws If any entry is invalid, in which case nothing is saved, or the Tauri database fails to update.
+ */
+export const updateScamList = async (entries: string[]): Promise<void> => {
+  return await invoke("update_scam_list", { entries });
+};
+
+/**
+ * Get the scam list.
+ * @returns The list's entries, with pubkeys as npubs.
+ * @throws If the Tauri database fails to read.
+ */
+export const getScamList = async (): Promise<string[]> => {
+  return await invoke("get_scam_list");
+};
+
+/**
+ * Get where the user is in first-run setup, to decide which screen to show.
+ * @returns "vault_locked" until an encrypted vault is unlocked, "needs_vault_passphrase" until the
+ * vault has been encrypted with `encryptExistingKeys`, then "needs_first_account" until an account
+ * that can sign has been added, then "ready".
+ * @throws If the Tauri database fails to read.
+ */
+export const getSetupState = async (): Promise<SetupState> => {
+  return await invoke("get_setup_state");
+};
+
+/**
+ * Unlock the encrypted vault, e.g. when `getSetupState` returns "vault_locked".
+ * @param passphrase The vault passphrase.
+ * @returns A promise that resolves when the vault has been unlocked. Resolves straight away if
+ * the vault is already unlocked.
+ * @throws If the passphrase is wrong.
+ */
+export const unlockVault = async (passphrase: string): Promise<void> => {
+  return await invoke("unlock_vault", { passphrase });
+};
+
+/**
+ * Encrypt a vault that was created without a passphrase, including every stored key. Only needs
+ * to be done once: from the next launch on, the vault must be unlocked with `unlockVault`.
+ * @param passphrase The passphrase to encrypt the vault with.
+ * @returns A promise that resolves when the vault has been encrypted.
+ * @throws If the passphrase is empty or the vault is already encrypted.
+ */
+export const encryptExistingKeys = async (passphrase: string): Promise<void> => {
+  return await invoke("encrypt_existing_keys", { passphrase });
+};
+
+/**
+ * List all accounts, including watch-only ones.
+ * @returns Each account's npub and whether it can sign. Accounts that can sign come first, in the
+ * order they were added, followed by watch-only accounts.
+ * @throws If the Tauri database fails to read.
+ */
+export const listAccounts = async (): Promise<AccountListEntry[]> => {
+  return await invoke("list_accounts");
+};
+
+/**
+ * Add an npub as a watch-only account, to monitor it without being able to sign for it.
+ * @param npub The npub to watch.
+ * @returns A promise that resolves once the account has been added.
+ * @throws If the npub is invalid, Keystache already has its key, or the Tauri database fails to update.
+ */
+export const addWatchOnlyAccount = async (npub: string): Promise<void> => {
+  return await invoke("add_watch_only_account", { npub });
+};
+
+/**
+ * Replace an account with a newly generated key, e.g. because the old key was compromised.
+ * The new account inherits the old one's label and relays, and the old account is marked as retired.
+ * @param npub The npub of the account to rotate.
+ * @param announce Whether the old account should sign (with approval) and publish a note pointing
+ * to the new account.
+ * @returns The new account's npub, and the announcement and how each relay responded to it, if any.
+ * @throws If the npub is invalid, the account doesn't exist or is already retired, offline mode is
+ * on and `announce` is set, or the announcement is rejected.
+ */
+export const rotateAccount = async (
+  npub: string,
+  announce: boolean,
+): Promise<RotateAccountResponse> => {
+  return await invoke("rotate_account", { npub, announce });
+};
+
+/**
+ * Set an account's display label.
+ * @param npub The npub of the account.
+ * @param label The label, or `null` to clear it.
+ * @returns A promise that resolves once the label has been saved.
+ * @throws If the npub is invalid or the account doesn't exist.
+ */
+export const setAccountLabel = async (
+  npub: string,
+  label: string | null,
+): Promise<void> => {
+  return await invoke("set_account_label", { npub, label });
+};
+
+/**
+ * Get an account's label, and whether it has been retired in favour of another account.
+ * @param npub The npub of the account.
+ * @returns The account's metadata.
+ * @throws If the npub is invalid or the Tauri database fails to read.
+ */
+export const getAccountMetadata = async (
+  npub: string,
+): Promise<AccountMetadata> => {
+  return await invoke("get_account_metadata", { npub });
+};
+
+/**
+ * Get how much an account has been used, e.g. to show as a badge next to it.
+ * @param npub The npub of the account.
+ * @returns How many events the account has signed and invoices it has paid, and when it was last used.
+ * @throws If the npub is invalid or the Tauri database fails to read.
+ */
+export const getAccountStats = async (npub: string): Promise<AccountStats> => {
+  return await invoke("get_account_stats", { npub });
+};
+
+/**
+ * Get which steps of setting up an account well have been done, e.g. to show a setup-progress
+ * indicator.
+ * @param npub The npub of the account.
+ * @returns Whether the account has a label, relays, a verified NIP-05 and an acknowledged
+ * backup, and whether the vault is encrypted.
+ * @throws If the npub is invalid or the Tauri database fails to read.
+ */
+export const getAccountCompleteness = async (
+  npub: string,
+): Promise<AccountCompleteness> => {
+  return await invoke("get_account_completeness", { npub });
+};
+
+/**
+ * Record whether the user has confirmed that they've backed up an account's key.
+ * @param npub The npub of the account.
+ * @param acknowledged Whether the backup has been acknowledged.
+ * @throws If the npub is invalid or the Tauri database fails to write.
+ */
+export const setBackupAcknowledged = async (
+  npub: string,
+  acknowledged: boolean,
+): Promise<void> => {
+  return await invoke("set_backup_acknowledged", { npub, acknowledged });
+};
+
+/**
+ * Get how many secret keys are cached in memory and how often the cache has been used.
+ * The keys themselves are never returned.
+ * @returns The number of cached keys, and the cache's hit and miss counts.
+ */
+export const getCacheStats = async (): Promise<KeyCacheStats> => {
+  return await invoke("get_cache_stats");
+};
+
+/**
+ * Zeroize and forget every secret key cached in memory. Keys are read from the vault again when next needed.
+ * @returns A promise that resolves once the cache is empty.
+ */
+export const clearKeyCache = async (): Promise<void> => {
+  return await invoke("clear_key_cache");
+};
+
+/**
+ * Sign throwaway events with the active account's key and measure how fast signing is, for diagnosing
+ * slow signing. Nothing is shown to the user or sent to relays. Only call this when the user asks for it.
+ * @param count How many events to sign, from 1 to 10,000.
+ * @returns How many events were signed, how long it took, and the signing rate.
+ * @throws If `count` is out of range or there's no active account.
+ */
+export const benchmarkSigning = async (
+  count: number,
+): Promise<SigningBenchmark> => {
+  return await invoke("benchmark_signing", { count });
+};
+
+/**
+ * Erase all keys, settings, and pending requests. This can't be undone.
+ * @param passphrase The vault passphrase, to confirm the wipe.
+ * @param confirmationPhrase "wipe all data", typed by the user. Required if wiping needs a
+ * confirmation phrase (see `setConfirmationPhrases`).
+ * @returns A promise that resolves once everything has been erased.
+ * @throws "Wrong passphrase" if the passphrase is incorrect, if the confirmation phrase is missing
+ * or wrong, or if the Tauri database fails to update.
+ */
+export const wipeAllData = async (
+  passphrase: string,
+  confirmationPhrase?: string,
+): Promise<void> => {
+  return await invoke("wipe_all_data", { passphrase, confirmationPhrase });
+};
+
+/**
+ * Check the vault for corruption, without exposing any keys.
+ * @param passphrase The vault passphrase.
+ * @returns How many stored keys are intact or corrupt, and how many database pages are corrupt.
+ * @throws "Wrong passphrase" if the passphrase is incorrect, or if the Tauri database fails to read.
+ */
+export const verifyVaultIntegrity = async (
+  passphrase: string,
+): Promise<VaultIntegrityReport> => {
+  return await invoke("verify_vault_integrity", { passphrase });
+};
+
+/**
+ * Check that the key stored for an npub is a valid secret key for it, and that it isn't on the
+ * list of leaked keys (see `setLeakedKeys`). The key itself is never returned.
+ * @param npub The npub of the account to check.
+ * @returns Any warnings about the key. No warnings means it's healthy.
+ * @throws If the npub is invalid, there is no key for it, or the Tauri database fails to read.
+ */
+export const checkKeyHealth = async (npub: string): Promise<KeyHealth> => {
+  return await invoke("check_key_health", { npub });
+};
+
+/**
+ * Get the npubs of keys that are known to have leaked.
+ * @returns The npubs on the list.
+ * @throws If the Tauri database can't be read.
+ */
+export const getLeakedKeys = async (): Promise<string[]> => {
+  return await invoke("get_leaked_keys");
+};
+
+/**
+ * Replace the list of keys that are known to have leaked, e.g. from a published breach.
+ * `checkKeyHealth` warns about any stored key on the list.
+ * @param npubs The npubs of the leaked keys.
+ * @returns A promise that resolves when the list has been saved.
+ * @throws If any npub is invalid (nothing is saved), or if the Tauri database fails to update.
+ */
+export const setLeakedKeys = async (npubs: string[]): Promise<void> => {
+  return await invoke("set_leaked_keys", { npubs });
+};
+
+/**
+ * Fix any stored npub that doesn't match the nsec it's stored with, by re-deriving it from the nsec.
+ * @returns How many npubs were fixed.
+ * @throws "Vault is locked" if the vault hasn't been unlocked, or if the Tauri database fails to read.
+ */
+export const repairNpubs = async (): Promise<number> => {
+  return await invoke("repair_npubs");
+};
+
+/**
+ * Check the database for tables, columns and indexes that this version of Keystache expects but that are missing.
+ * Never deletes data.
+ * @param repair Whether to re-create missing tables and indexes. Missing columns are only reported.
+ * @returns What is missing, and what was re-created.
+ * @throws "Vault is locked" if the vault hasn't been unlocked, or if the Tauri database fails to read.
+ */
+export const diagnoseDatabase = async (
+  repair: boolean,
+): Promise<DatabaseDiagnosis> => {
+  return await invoke("diagnose_database", { repair });
+};
+
+/**
+ * Import many nSecs at once, e.g. when migrating from another signer.
+ * @param nsecList Newline-delimited list of nSecs. Blank lines are ignored.
+ * @returns The outcome for each nSec, in the same order as the list.
+ * @throws If the Tauri database fails to update.
+ */
+export const bulkImport = async (
+  nsecList: string,
+): Promise<BulkImportResult[]> => {
+  const nsecs = nsecList.split("\n").filter((nsec) => nsec.trim() !== "");
+  return await invoke("bulk_import", { nsecs });
+};
+
+/**
+ * Import the accounts in an encrypted Keystache backup file, e.g. one dropped onto the window.
+ * Nothing is imported unless the passphrase decrypts every key in the backup.
+ * @param path Path of the backup file, as supplied by the drop event.
+ * @param passphrase The passphrase the backup was encrypted with.
+ * @returns The outcome for each account in the backup, in the order they appear in it.
+ * @throws "Unrecognized backup format" if the file isn't a Keystache backup, "Wrong password" if the
+ * passphrase is incorrect, or if the file can't be read or the Tauri database fails to update.
+ */
+export const importBackupFile = async (
+  path: string,
+  passphrase: string,
+): Promise<BulkImportResult[]> => {
+  return await invoke("import_backup_file", { path, passphrase });
+};
+
+/**
+ * Import a key from a NIP-49 password-encrypted `ncryptsec` backup.
+ * @param ncryptsec The `ncryptsec1...` string.
+ * @param password The password the key was encrypted with.
+ * @returns The npub of the imported key.
+ * @throws "Wrong password" if the password doesn't decrypt the key, "Invalid ncryptsec" if the
+ * string is malformed, or if the Tauri database fails to update.
+ */
+export const importNcryptsec = async (
+  ncryptsec: string,
+  password: string,
+): Promise<string> => {
+  return await invoke("import_ncryptsec", { ncryptsec, password });
+};
+
+/**
+ * Derive the NIP-04 style ECDH shared secret between the active account and a peer, for building
+ * custom encrypted channels. DANGEROUS: the secret decrypts every NIP-04 message between the two
+ * keys, so it must be kept as carefully as the private key.
+ * @param peerPubkey The peer's npub.
+ * @param passphrase The vault passphrase. Required if no passkey is enrolled (see `getUnlockMethod`).
+ * @param assertion A passkey assertion over a challenge from `getPasskeyChallenge`. Required if a
+ * passkey is enrolled.
+ * @returns The hex-encoded 32-byte shared secret.
+ * @throws If the npub is invalid, there is no active account, or the passphrase or passkey check
+ * fails.
+ */
+export const deriveSharedSecret = async (
+  peerPubkey: string,
+  passphrase?: string,
+  assertion?: PasskeyAssertion,
+): Promise<string> => {
+  return await invoke("derive_shared_secret", {
+    peerPubkey,
+    passphrase,
+    assertion,
+  });
+};
+
+/**
+ * Export a key as a NIP-49 password-encrypted `ncryptsec` backup.
+ * @param npub The npub of the key to export.
+ * @param password The password to encrypt the key with.
+ * @param passphrase The vault passphrase. Required if no passkey is enrolled (see `getUnlockMethod`).
+ * @param assertion A passkey assertion over a challenge from `getPasskeyChallenge`. Required if a
+ * passkey is enrolled.
+ * @param confirmationPhrase "reveal secret", typed by the user. Required if exporting needs a
+ * confirmation phrase (see `setConfirmationPhrases`).
+ * @returns The `ncryptsec1...` string.
+ * @throws If the npub is invalid, there is no key for it, the confirmation phrase is missing or
+ * wrong, or the passphrase or passkey check fails.
+ */
+export const exportNcryptsec = async (
+  npub: string,
+  password: string,
+  passphrase?: string,
+  assertion?: PasskeyAssertion,
+  confirmationPhrase?: string,
+): Promise<string> => {
+  return await invoke("export_ncryptsec", {
+    npub,
+    password,
+    passphrase,
+    assertion,
+    confirmationPhrase,
+  });
+};
+
+/**
+ * Get which high-risk actions need the user to type a confirmation phrase.
+ * @returns The actions that need one. None do by default.
+ * @throws If the Tauri database can't be read.
+ */
+export const getConfirmationPhrases = async (): Promise<ConfirmationPhrasePolicy> => {
+  return await invoke("get_confirmation_phrases");
+};
+
+/**
+ * Set which high-risk actions need the user to type a confirmation phrase before they go ahead:
+ * "wipe all data" for `wipeAllData`, "reveal secret" for `exportNcryptsec`, and "pay invoice" for
+ * payments over the maximum single payment.
+ * @param confirmationPhrases The actions that need a phrase.
+ * @returns A promise that resolves when the setting has been saved.
+ * @throws If the Tauri database fails to update.
+ */
+export const setConfirmationPhrases = async (
+  confirmationPhrases: ConfirmationPhrasePolicy,
+): Promise<void> => {
+  return await invoke("set_confirmation_phrases", { confirmationPhrases });
+};
+
+/**
+ * Get how gated operations (e.g. exporting a key) must be authorized.
+ * @returns "passkey" if a passkey is enrolled, otherwise "passphrase".
+ * @throws If the Tauri database can't be read.
+ */
+export const getUnlockMethod = async (): Promise<UnlockMethod> => {
+  return await invoke("get_unlock_method");
+};
+
+/**
+ * Get a one-time challenge to pass to `navigator.credentials.get()` for a passkey assertion.
+ * @returns The challenge bytes.
+ * @throws If Keystache was built without the `webauthn` feature.
+ */
+export const getPasskeyChallenge = async (): Promise<number[]> => {
+  return await invoke("get_passkey_challenge");
+};
+
+/**
+ * Register a passkey created with `navigator.credentials.create()`. Only ES256 passkeys are
+ * supported.
+ * @param credentialId The credential's raw ID.
+ * @param publicKey The credential's public key, from `response.getPublicKey()`.
+ * @param passphrase The vault passphrase. Required if no passkey is enrolled yet.
+ * @param assertion An assertion from an already-enrolled passkey. Required if one is enrolled.
+ * @returns A promise that resolves when the passkey has been registered.
+ * @throws If the passkey is invalid or already registered, the passphrase or passkey check fails,
+ * or Keystache was built without the `webauthn` feature.
+ */
+export const registerPasskey = async (
+  credentialId: number[],
+  publicKey: number[],
+  passphrase?: string,
+  assertion?: PasskeyAssertion,
+): Promise<void> => {
+  return await invoke("register_passkey", {
+    credentialId,
+    publicKey,
+    passphrase,
+    assertion,
+  });
+};
+
+/**
+ * Unregister a passkey.
+ * @param credentialId The credential's raw ID.
+ * @param assertion An assertion from an enrolled passkey.
+ * @returns A promise that resolves when the passkey has been removed.
+ * @throws If the passkey check fails, the Tauri database fails to update, or Keystache was built
+ * without the `webauthn` feature.
+ */
+export const removePasskey = async (
+  credentialId: number[],
+  assertion: PasskeyAssertion,
+): Promise<void> => {
+  return await invoke("remove_passkey", { credentialId, assertion });
+};
+
+/**
+ * Sign the NIP-47 info event that advertises the active account as a wallet service.
+ * @param methods The NIP-47 methods the wallet service supports.
+ * @returns The signed kind-13194 event, for publishing to the wallet service's relay.
+ * @throws If no methods are given, no account is active, or Keystache was built without the
+ * `nwc-service` feature.
+ */
+export const signNwcInfoEvent = async (
+  methods: NwcMethod[],
+): Promise<NostrEvent> => {
+  return await invoke("sign_nwc_info_event", { methods });
+};
+
+/**
+ * Sign the active account's response to an app's NIP-47 request, encrypted to the app.
+ * @param request The app's kind-23194 request.
+ * @param response The response as NIP-47 JSON, e.g. `{"result_type":"get_balance","result":{"balance":21000}}`.
+ * @returns The signed kind-23195 response, for publishing to the wallet service's relay.
+ * @throws If the request or response is invalid, no account is active, or Keystache was built
+ * without the `nwc-service` feature.
+ */
+export const signNwcResponse = async (
+  request: NostrEvent,
+  response: string,
+): Promise<NostrEvent> => {
+  return await invoke("sign_nwc_response", { request, response });
+};
+
+/**
+ * Bind an account to a key on a PKCS#11 token, so that its events are signed, and its NIP-46
+ * requests answered, on the token. The account needs no key in the vault. Replaces any binding
+ * the account already has.
+ * @param binding Which token and key the account's key is.
+ * @returns A promise that resolves when the account has been bound.
+ * @throws If the token can't be opened, its key isn't the account's, the Tauri database fails
+ * to update, or Keystache was built without the `pkcs11` feature.
+ */
+export const bindHardwareKey = async (
+  binding: HardwareKeyBinding,
+): Promise<void> => {
+  return await invoke("bind_hardware_key", { binding });
+};
+
+/**
+ * Set how long a paid invoice is remembered for. Requests to pay the same invoice again within
+ * this window return the original result rather than paying twice.
+ * @param seconds The length of the window, in seconds.
+ * @returns A promise that resolves when the window has been set.
+ * @throws If the Tauri database fails to update.
+ */
+export const setPaymentDedupWindow = async (seconds: number): Promise<void> => {
+  return await invoke("set_payment_dedup_window", { seconds });
+};
+
+/**
+ * Get how long Keystache waits for the user to respond to each kind of request.
+ * @returns The approval timeouts, in seconds.
+ * @throws If the Tauri database fails to read.
+ */
+export const getApprovalTimeouts = async (): Promise<ApprovalTimeouts> => {
+  return await invoke("get_approval_timeouts");
+};
+
+/**
+ * Set how long Keystache waits for the user to respond to each kind of request before rejecting it.
+ * @param approvalTimeouts The approval timeouts, in seconds. Each must be at least one second.
+ * @returns A promise that resolves when the timeouts have been set.
+ * @throws If any timeout is zero or the Tauri database fails to update.
+ */
+export const setApprovalTimeouts = async (
+  approvalTimeouts: ApprovalTimeouts,
+): Promise<void> => {
+  return await invoke("set_approval_timeouts", { approvalTimeouts });
+};
+
+/**
+ * Get the daily window during which requests are held back.
+ * @returns The quiet hours, or `null` if they're off.
+ * @throws If the Tauri database can't be read.
+ */
+export const getQuietHours = async (): Promise<QuietHours | null> => {
+  return await invoke("get_quiet_hours");
+};
+
+/**
+ * Set a daily window, in local time, during which requests to sign events or pay invoices are
+ * rejected outright or need Keystache to be unlocked before they can be approved.
+ * @param quietHours The window, or `null` to turn quiet hours off.
+ * @returns A promise that resolves when quiet hours have been set.
+ * @throws If the window is empty or out of range, or the Tauri database fails to update.
+ */
+export const setQuietHours = async (
+  quietHours: QuietHours | null,
+): Promise<void> => {
+  return await invoke("set_quiet_hours", { quietHours });
+};
+
+/**
+ * Get the cap on the amount of any single payment.
+ * @returns The cap, or `null` if there isn't one.
+ * @throws If the Tauri database can't be read.
+ */
+export const getMaxSinglePayment = async (): Promise<MaxSinglePayment | null> => {
+  return await invoke("get_max_single_payment");
+};
+
+/**
+ * Cap the amount of any single payment, whichever app requests it. Invoices over the cap, or
+ * without an amount, are rejected outright or need Keystache to be unlocked before they can be paid.
+ * @param maxSinglePayment The cap, or `null` to remove it.
+ * @returns A promise that resolves when the cap has been set.
+ * @throws If the cap is zero, or the Tauri database fails to update.
+ */
+export const setMaxSinglePayment = async (
+  maxSinglePayment: MaxSinglePayment | null,
+): Promise<void> => {
+  return await invoke("set_max_single_payment", { maxSinglePayment });
+};
+
+/**
+ * Get how old an event can be and still be signed.
+ * @returns The maximum age in seconds, or `null` if events of any age can be signed.
+ * @throws If the Tauri database can't be read.
+ */
+export const getMaxEventAge = async (): Promise<number | null> => {
+  return await invoke("get_max_event_age");
+};
+
+/**
+ * Set how old an event can be and still be signed. Requests to sign older events are rejected
+ * without asking the user.
+ * @param seconds The maximum age in seconds, or `null` to accept events of any age.
+ * @returns A promise that resolves when the maximum age has been set.
+ * @throws If `seconds` is zero, or the Tauri database fails to update.
+ */
+export const setMaxEventAge = async (seconds: number | null): Promise<void> => {
+  return await invoke("set_max_event_age", { seconds });
+};
+
+/**
+ * Get how many days payment and connection log entries are kept for.
+ * @returns The number of days, or 0 if entries are kept forever.
+ * @throws If the Tauri database can't be read.
+ */
+export const getLogRetentionDays = async (): Promise<number> => {
+  return await invoke("get_log_retention_days");
+};
+
+/**
+ * Set how many days payment and connection log entries are kept for. Older entries are pruned
+ * straight away, and then once an hour.
+ * @param days The number of days to keep entries for, or 0 to keep them forever.
+ * @returns A promise that resolves when the retention has been set and old entries pruned.
+ * @throws If the Tauri database fails to update.
+ */
+export const setLogRetentionDays = async (days: number): Promise<void> => {
+  return await invoke("set_log_retention_days", { days });
+};
+
+/**
+ * Get what is done about events that are signed without a NIP-36 `content-warning` tag.
+ * @returns The content-warning policy.
+ * @throws If the Tauri database can't be read.
+ */
+export const getContentWarningPolicy =
+  async (): Promise<ContentWarningPolicy> => {
+    return await invoke("get_content_warning_policy");
+  };
+
+/**
+ * Set what is done about events that are signed without a NIP-36 `content-warning` tag.
+ * Only text notes, channel messages and long-form articles are checked.
+ * @param contentWarningPolicy `off` to not check, `warn` to warn the user, or `suggest_tag` to
+ * also suggest a copy of the event with the tag added.
+ * @returns A promise that resolves when the policy has been set.
+ * @throws If the Tauri database fails to update.
+ */
+export const setContentWarningPolicy = async (
+  contentWarningPolicy: ContentWarningPolicy,
+): Promise<void> => {
+  return await invoke("set_content_warning_policy", { contentWarningPolicy });
+};
+
+/**
+ * Get how the user's attention is drawn to requests that arrive while the window isn't focused.
+ * @returns Whether the window is focused, and whether a notification is shown.
+ * @throws If the Tauri database can't be read.
+ */
+export const getRequestAttention = async (): Promise<RequestAttention> => {
+  return await invoke("get_request_attention");
+};
+
+/**
+ * Set how the user's attention is drawn to requests that arrive while the window isn't focused.
+ * @param requestAttention Whether to focus the window (otherwise it's only flashed), and whether
+ * to show a notification naming the app and what it's asking for (see `onRequestNotification`).
+ * @returns A promise that resolves when the settings have been saved.
+ * @throws If the Tauri database fails to update.
+ */
+export const setRequestAttention = async (
+  requestAttention: RequestAttention,
+): Promise<void> => {
+  return await invoke("set_request_attention", { requestAttention });
+};
+
+/**
+ * Get whether offline mode is enabled.
+ * @returns True if offline mode is enabled.
+ * @throws If the Tauri database can't be read.
+ */
+export const getOfflineMode = async (): Promise<boolean> => {
+  return await invoke("get_offline_mode");
+};
+
+/**
+ * Enable or disable offline mode. While enabled, Keystache makes no outbound connections, and
+ * anything that needs the network (e.g. publishing to relays) throws instead. Signing and reading
+ * public keys still work.
+ * @param enabled Whether offline mode should be enabled.
+ * @returns A promise that resolves when offline mode has been updated.
+ * @throws If the Tauri database fails to update.
+ */
+export const setOfflineMode = async (enabled: boolean): Promise<void> => {
+  return await invoke("set_offline_mode", { enabled });
+};
+
+/**
+ * Get the patterns for which app keys the NIP-46 servers accept requests from.
+ * @returns The allowlist patterns. `["*"]` (the default) allows every app.
+ * @throws If the Tauri database can't be read.
+ */
+export const getAppKeyAllowlist = async (): Promise<string[]> => {
+  return await invoke("get_app_key_allowlist");
+};
+
+/**
+ * Set which app keys the NIP-46 servers accept requests from. Each pattern is the npub that an
+ * app signs its requests with, or `*`. `["*"]` allows every app, and an empty list blocks every
+ * app. Requests from other apps are refused without asking. Only apps that sign every request
+ * with the same key can be listed; NIP-55 clients that use a new key per request need `*`.
+ * @param patterns The allowlist patterns.
+ * @returns A promise that resolves when the allowlist has been set.
+ * @throws If a pattern is neither `*` nor a valid npub, or the Tauri database fails to update.
+ */
+export const setAppKeyAllowlist = async (patterns: string[]): Promise<void> => {
+  return await invoke("set_app_key_allowlist", { patterns });
+};
+
+/**
+ * Sign an event with an explicitly chosen `created_at`, for tools that legitimately need to
+ * backdate or postdate an event. The timestamp is shown to the user as part of the approval.
+ * @param event The event to sign. Its `created_at` is replaced with `createdAt`.
+ * @param createdAt The Unix timestamp, in seconds, to sign the event with.
+ * @returns The signed event.
+ * @throws If the timestamp is invalid, the user rejects the request, or signing fails.
+ */
+export const signEventWithTimestamp = async (
+  event: UnsignedNostrEvent,
+  createdAt: number,
+): Promise<NostrEvent> => {
+  return await invoke("sign_event_with_timestamp", { event, createdAt });
+};
+
+/**
+ * Sign an event handed over as JSON, e.g. from an air-gapped device, after the user approves.
+ * Never connects to relays, even if relays are configured, so it also works in offline mode.
+ * @param eventJson The unsigned event, as JSON.
+ * @returns The signed event, as JSON.
+ * @throws If the JSON isn't an unsigned event, the user rejects the request, or signing fails.
+ */
+export const signEventOffline = async (eventJson: string): Promise<string> => {
+  return await invoke("sign_event_offline", { eventJson });
+};
+
+/**
+ * Compute the ID an event will have once it's signed, without signing it.
+ * @param event The unsigned event. Any `id` it has is ignored.
+ * @returns The hex-encoded event ID.
+ */
+export const computeEventId = async (
+  event: UnsignedNostrEvent,
+): Promise<string> => {
+  return await invoke("compute_event_id", { event });
+};
+
+/**
+ * Sign an event as a specific stored account, without changing the active account. The event's
+ * pubkey is replaced with the account's.
+ * @param npub The npub of the account to sign as.
+ * @param event The unsigned event.
+ * @returns The signed event.
+ * @throws If the account isn't stored or the user rejects the request.
+ */
+export const signEventAs = async (
+  npub: string,
+  event: UnsignedNostrEvent,
+): Promise<NostrEvent> => {
+  return await invoke("sign_event_as", { npub, event });
+};
+
+/**
+ * Sign an event with NIP-13 proof of work, for relays that require it. Once the user approves, a
+ * nonce is mined so that the event's ID has enough leading zero bits. Listen with `onPowProgress`
+ * to show progress.
+ * @param event The unsigned event.
+ * @param difficulty The number of leading zero bits the event's ID must have.
+ * @returns The signed event, with a `nonce` tag.
+ * @throws If `difficulty` is zero, the user rejects the request, or mining is cancelled or takes
+ * longer than a minute.
+ */
+export const signEventWithPow = async (
+  event: UnsignedNostrEvent,
+  difficulty: number,
+): Promise<NostrEvent> => {
+  return await invoke("sign_event_with_pow", { event, difficulty });
+};
+
+/**
+ * Stop mining proof of work for an event being signed with `signEventWithPow`.
+ * @param eventId The ID of the event in the sign event request.
+ * @returns A promise that resolves once mining has been told to stop.
+ */
+export const cancelPow = async (eventId: string): Promise<void> => {
+  return await invoke("cancel_pow", { eventId });
+};
+
+/**
+ * Decrypt a NIP-04 or NIP-44 direct message addressed to the active account, after the user approves.
+ * @param event The signed direct message event.
+ * @returns The message's plaintext.
+ * @throws If the message isn't addressed to the active account, the user rejects the request,
+ * or decryption fails.
+ */
+export const decryptDm = async (event: NostrEvent): Promise<string> => {
+  return await invoke("decrypt_dm", { event });
+};
+
+/**
+ * Sign a batch of events, which may be for several accounts, with a single approval from the user.
+ * @param events The events to sign. Each is signed by the account matching its pubkey.
+ * @returns The signed events, in the same order as `events`.
+ * @throws If Keystache doesn't have the key for any of the events, the user rejects the request,
+ * or signing fails.
+ */
+export const signEvents = async (events: UnsignedNostrEvent[]): Promise<NostrEvent[]> => {
+  return await invoke("sign_events", { events });
+};
+
+/**
+ * Publish a signed event to the given relays.
+ * @param event The signed event to publish.
+ * @param relayUrls The URLs of the relays to publish to.
+ * @returns How each relay responded, in the same order as `relayUrls`. A relay that rejected the
+ * event includes the reason it gave (e.g. "blocked: spam").
+ * @throws If the event is invalid or offline mode is enabled.
+ */
+export const publishEvent = async (
+  event: NostrEvent,
+  relayUrls: string[],
+): Promise<RelayPublishResult[]> => {
+  return await invoke("publish_event", { event, relayUrls });
+};
+
+/**
+ * Get the relays configured for an account.
+ * @param npub The account's npub.
+ * @param appId The npub of the app the relays are for, if any. Private relays are left out
+ * unless the app is trusted.
+ * @returns Each relay's URL along with whether the account reads from and/or writes to it.
+ * @throws If either npub is invalid or the Tauri database can't be read.
+ */
+export const getRelays = async (
+  npub: string,
+  appId?: string,
+): Promise<[string, RelayPolicy][]> => {
+  return await invoke("get_relays", { npub, appId });
+};
+
+/**
+ * Get which relays are private, and which apps are trusted to see them.
+ * @returns The relay visibility policy. By default, no relays are private.
+ */
+export const getRelayVisibilityPolicy = async (): Promise<RelayVisibilityPolicy> => {
+  return await invoke("get_relay_visibility_policy");
+};
+
+/**
+ * Set which relays are private, and which apps are trusted to see them. Apps that aren't
+ * trusted are only shown an account's other relays.
+ * @param policy The private relay URLs and trusted app npubs.
+ * @returns A promise that resolves when the policy has been set.
+ * @throws If a relay URL or npub is invalid, or the Tauri database fails to update.
+ */
+export const setRelayVisibilityPolicy = async (
+  policy: RelayVisibilityPolicy,
+): Promise<void> => {
+  return await invoke("set_relay_visibility_policy", { policy });
+};
+
+/**
+ * Add a relay for an account, or update whether the account reads from and/or writes to it.
+ * @param npub The account's npub.
+ * @param url The URL of the relay. It's stored normalized, with a lowercase host and no default
+ * port or trailing slash.
+ * @param read Whether events are fetched from the relay.
+ * @param write Whether events are published to the relay.
+ * @returns A promise that resolves when the relay has been saved.
+ * @throws If the npub is invalid or the Tauri database fails to update.
+ */
+export const setRelayPolicy = async (
+  npub: string,
+  url: string,
+  read: boolean,
+  write: boolean,
+): Promise<void> => {
+  return await invoke("set_relay_policy", { npub, url, read, write });
+};
+
+/**
+ * Find an account's relay entries that point to the same relay, e.g. `wss://relay.example.com`
+ * and `wss://relay.example.com/`, so that the user can remove the extras.
+ * @param npub The account's npub.
+ * @returns Each group of equivalent entries, along with the URL they normalize to.
+ * @throws If the npub is invalid or the Tauri database can't be read.
+ */
+export const findDuplicateRelays = async (
+  npub: string,
+): Promise<DuplicateRelays[]> => {
+  return await invoke("find_duplicate_relays", { npub });
+};
+
+/**
+ * Remove a relay from an account.
+ * @param npub The account's npub.
+ * @param url The URL of the relay.
+ * @returns A promise that resolves when the relay has been removed.
+ * @throws If the npub is invalid or the Tauri database fails to update.
+ */
+export const removeRelay = async (npub: string, url: string): Promise<void> => {
+  return await invoke("remove_relay", { npub, url });
+};
+
+/**
+ * Get whether relay hints are added to the events an account signs.
+ * @param npub The account's npub.
+ * @returns True if relay hints are on for the account.
+ * @throws If the npub is invalid or the Tauri database fails to read.
+ */
+export const getRelayHintsEnabled = async (npub: string): Promise<boolean> => {
+  return await invoke("get_relay_hints_enabled", { npub });
+};
+
+/**
+ * Turn relay hints on or off for an account. While on, the account's first write relay is added
+ * as the relay hint of any `e` or `p` tag without one in the events it signs, and the approval
+ * prompt shows the added hint.
+ * @param npub The account's npub.
+ * @param enabled Whether relay hints should be added.
+ * @returns A promise that resolves when the setting has been saved.
+ * @throws If the npub is invalid or the Tauri database fails to update.
+ */
+export const setRelayHintsEnabled = async (
+  npub: string,
+  enabled: boolean,
+): Promise<void> => {
+  return await invoke("set_relay_hints_enabled", { npub, enabled });
+};
+
+/**
+ * Get how long after signing an account's events expire.
+ * @param npub The account's npub.
+ * @returns The expiration in seconds, or `null` if no expiration is added to the account's events.
+ * @throws If the npub is invalid or the Tauri database can't be read.
+ */
+export const getDefaultExpiration = async (
+  npub: string,
+): Promise<number | null> => {
+  return await invoke("get_default_expiration", { npub });
+};
+
+/**
+ * Set how long after signing an account's events expire. While set, a NIP-40 `expiration` tag is
+ * added to the events the account signs that don't already have one, and the approval prompt
+ * shows it.
+ * @param npub The account's npub.
+ * @param seconds The expiration in seconds, or `null` to stop adding expirations.
+ * @returns A promise that resolves when the setting has been saved.
+ * @throws If the npub is invalid, `seconds` is zero, or the Tauri database fails to update.
+ */
+export const setDefaultExpiration = async (
+  npub: string,
+  seconds: number | null,
+): Promise<void> => {
+  return await invoke("set_default_expiration", { npub, seconds });
+};
+
+/**
+ * Re-fetch an account's profile (kind 0) and relay list (kind 10002) from its read relays, and
+ * update the stored copies if the relays have newer ones. In offline mode, nothing is fetched.
+ * @param npub The account's npub.
+ * @returns The stored copies after refreshing, and which of them changed.
+ * @throws If the npub is invalid, every relay fails, or the Tauri database fails to update.
+ */
+export const refreshAccountData = async (npub: string): Promise<AccountDataRefresh> => {
+  return await invoke("refresh_account_data", { npub });
+};
+
+/**
+ * Fetch an account's latest profile metadata (kind 0) from its read relays.
+ * @param npub The account's npub.
+ * @returns The account's profile, or null if none of its relays have one.
+ * @throws If the npub is invalid, offline mode is enabled, or the relays' profile can't be parsed.
+ */
+export const getProfileMetadata = async (
+  npub: string,
+): Promise<ProfileFields | null> => {
+  return await invoke("get_profile_metadata", { npub });
+};
+
+/**
+ * Get the lightning address from an account's profile metadata, and check that it can be paid (e.g. zapped)
+ * through.
+ * @param npub The account's npub.
+ * @returns The address and whether its LNURL-pay endpoint works, or null if the account's profile has no
+ * lightning address.
+ * @throws If the npub is invalid, offline mode is enabled, or the relays' profile can't be parsed.
+ */
+export const getLightningAddress = async (
+  npub: string,
+): Promise<LightningAddress | null> => {
+  return await invoke("get_lightning_address", { npub });
+};
+
+/**
+ * Look up the account that a NIP-05 identifier points to, e.g. to send a DM or zap to someone
+ * by their NIP-05.
+ * @param identifier The NIP-05 identifier, as `name@domain` or just `domain` for `_@domain`.
+ * @returns The account's npub and any relays the domain lists for it.
+ * @throws If the identifier is invalid, offline mode is enabled, the domain can't be reached,
+ * or it has no pubkey for the name.
+ */
+export const resolveNip05 = async (identifier: string): Promise<Nip05Profile> => {
+  return await invoke("resolve_nip05", { identifier });
+};
+
+/**
+ * Check that a NIP-05 identifier points to an account, recording it as the account's verified
+ * identifier if it does. If it doesn't, any previously verified identifier is forgotten.
+ * @param npub The npub of the account.
+ * @param identifier The NIP-05 identifier, as `name@domain` or just `domain` for `_@domain`.
+ * @returns Whether the identifier points to the account.
+ * @throws If the npub or identifier is invalid, offline mode is enabled, the domain can't be
+ * reached, or it has no pubkey for the name.
+ */
+export const verifyAccountNip05 = async (
+  npub: string,
+  identifier: string,
+): Promise<boolean> => {
+  return await invoke("verify_account_nip05", { npub, identifier });
+};
+
+/**
+ * Import an account's NIP-02 follow list from its read relays, replacing its stored contacts.
+ * @param npub The account's npub.
+ * @returns The imported contacts, or an empty list if the relays have no follow list for the
+ * account, in which case the stored contacts are left as they are.
+ * @throws If the npub is invalid, offline mode is enabled, or the relays can't be queried.
+ */
+export const importFollowList = async (npub: string): Promise<Contact[]> => {
+  return await invoke("import_follow_list", { npub });
+};
+
+/**
+ * Sign a new follow list (kind 3) event for an account and save its contacts as the account's
+ * stored contacts. Signing goes through the same approval flow as any other sign event request.
+ * @param npub The account's npub.
+ * @param contacts The accounts to follow, by npub or hex public key, with any relay hints and
+ * petnames.
+ * @param publish Whether to publish the signed event to the account's write relays.
+ * @returns The signed event, along with how each relay responded if it was published.
+ * @throws If any contact's public key is invalid, the user rejects the request, or signing fails.
+ * Also throws if `publish` is set while offline mode is enabled.
+ */
+export const updateFollowList = async (
+  npub: string,
+  contacts: Contact[],
+  publish: boolean,
+): Promise<UpdateFollowListResponse> => {
+  return await invoke("update_follow_list", { npub, contacts, publish });
+};
+
+/**
+ * List an account's stored contacts, as last imported with `importFollowList` or saved with
+ * `updateFollowList`.
+ * @param npub The account's npub.
+ * @returns The contacts, ordered by npub.
+ * @throws If the npub is invalid.
+ */
+export const listContacts = async (npub: string): Promise<Contact[]> => {
+  return await invoke("list_contacts", { npub });
+};
+
+/**
+ * Sign a new profile metadata (kind 0) event for an account. Signing goes through the same
+ * approval flow as any other sign event request.
+ * @param npub The account's npub.
+ * @param fields The new profile fields.
+ * @param publish Whether to publish the signed event to the account's write relays.
+ * @returns The signed event, along with how each relay responded if it was published.
+ * @throws If the fields are invalid, the user rejects the request, or signing fails. Also throws
+ * if `publish` is set while offline mode is enabled.
+ */
+export const updateProfileMetadata = async (
+  npub: string,
+  fields: ProfileFields,
+  publish: boolean,
+): Promise<UpdateProfileMetadataResponse> => {
+  return await invoke("update_profile_metadata", { npub, fields, publish });
+};
+
+/**
+ * Check how many of an account's relays can currently be connected to, e.g. to show whether a DM
+ * to the account is likely to be delivered. Nothing is signed or published.
+ * @param npub The npub of the account.
+ * @returns Whether each relay was reachable, and how many were.
+ * @throws If offline mode is enabled, or the npub is invalid.
+ */
+export const checkRelayReachability = async (
+  npub: string,
+): Promise<RelayReachabilityReport> => {
+  return await invoke("check_relay_reachability", { npub });
+};
+
+/**
+ * Check a relay URL as typed by the user, and get it in the form it would be saved in.
+ * @param input The URL as typed. A URL without a scheme is taken to be a `wss://` URL.
+ * @returns The normalized URL, e.g. `wss://relay.example.com` for `Relay.Example.com/`.
+ * @throws A message explaining what's wrong, e.g. if the URL starts with `https://`.
+ */
+export const normalizeRelayUrl = async (input: string): Promise<string> => {
+  return await invoke("normalize_relay_url", { input });
+};
+
+/**
+ * Check that a relay can be connected to and answers a basic subscription, e.g. before adding it
+ * to an account. Nothing is saved or published.
+ * @param url The relay's `ws://` or `wss://` URL.
+ * @returns Whether the relay connected and responded, timed out, or failed.
+ * @throws If offline mode is enabled, or the URL is invalid.
+ */
+export const testRelay = async (url: string): Promise<RelayTestResult> => {
+  return await invoke("test_relay", { url });
+};
+
+/**
+ * Get the strings to render as QR codes so that other clients (e.g. mobile apps) can connect to
+ * an account. QR images should be generated from these in the frontend.
+ * @param npub The account's npub.
+ * @returns The account's npub. There's no `bunker://` URI, since Keystache doesn't listen for
+ * NIP-46 requests on relays.
+ * @throws If the npub is invalid.
+ */
+export const getConnectionQr = async (npub: string): Promise<ConnectionQrPayload> => {
+  return await invoke("get_connection_qr", { npub });
+};
+
+/**
+ * Get an account's NIP-19 `nprofile`, for sharing the account along with hints of where to find its events.
+ * @param npub The account's npub.
+ * @returns The `nprofile1...` string, with up to three of the account's write relays as hints.
+ * @throws If the npub is invalid or the Tauri database fails to read.
+ */
+export const getNprofile = async (npub: string): Promise<string> => {
+  return await invoke("get_nprofile", { npub });
+};
+
+/**
+ * Get a shareable card with everything needed to follow and verify an account. Only public data
+ * is included.
+ * @param npub The account's npub.
+ * @returns The account's npub and `nprofile`, along with the name, NIP-05 and lightning address
+ * from its profile metadata. Those are left out if the account has no profile metadata, or
+ * offline mode is enabled.
+ * @throws If the npub is invalid, or the account's relays can't be read or queried.
+ */
+export const exportIdentityCard = async (npub: string): Promise<IdentityCard> => {
+  return await invoke("export_identity_card", { npub });
+};
+
+/**
+ * Decode a pasted NIP-19 entity (`npub`, `nsec`, `note`, `nevent`, `nprofile`, `naddr` or `nrelay`).
+ * A `nostr:` prefix is allowed. For an nsec, only the fact that a secret key was detected is returned.
+ * @param input The entity to decode.
+ * @returns The entity's type and components, with public keys and event IDs hex-encoded.
+ * @throws A description of what's wrong with the input if it can't be decoded.
+ */
+export const decodeEntity = async (input: string): Promise<DecodedEntity> => {
+  return await invoke("decode_entity", { input });
+};
+
+/**
+ * Check whether a pasted event is authentic.
+ * @param eventJson The event, as JSON.
+ * @returns Whether the event's signature is valid and, if it has a NIP-26 delegation tag, whether
+ * the delegation covers the event's kind and creation time.
+ * @throws If the input isn't an event.
+ */
+export const verifyEvent = async (
+  eventJson: string,
+): Promise<EventVerification> => {
+  return await invoke("verify_event", { eventJson });
+};
+
+/**
+ * Decode a BOLT11 invoice to show the user what it's for, without paying it.
+ * @param invoice The invoice, optionally prefixed with `lightning:`.
+ * @returns The invoice's amount, description, network and expiry.
+ * @throws If the invoice is malformed or has expired.
+ */
+export const decodeInvoice = async (
+  invoice: string,
+): Promise<DecodedInvoice> => {
+  return await invoke("decode_invoice", { invoice });
+};
+
+/**
+ * Check a NIP-57 zap receipt against the zap request it's for.
+ * @param receiptEvent The kind 9735 zap receipt.
+ * @param zapRequest The kind 9734 zap request.
+ * @returns Whether the receipt is valid, and every discrepancy found between its invoice,
+ * description and signature and the zap request.
+ * @throws If `zapRequest` isn't a zap request.
+ */
+export const validateZapReceipt = async (
+  receiptEvent: NostrEvent,
+  zapRequest: NostrEvent,
+): Promise<ZapReceiptValidation> => {
+  return await invoke("validate_zap_receipt", { receiptEvent, zapRequest });
+};
+
+/**
+ * Start an additional signing server that only signs for one account. Running one server per
+ * account lets different clients (e.g. different browser profiles) map to different keys.
+ * @param npub The npub of the account the server signs for.
+ * @param udsAddress The Unix domain socket address for the server to listen on.
+ * @returns A promise that resolves when the server has started.
+ * @throws If the npub is invalid, there is no key for the account, or a server is already
+ * running at the address.
+ */
+export const startServer = async (npub: string, udsAddress: string): Promise<void> => {
+  return await invoke("start_server", { npub, udsAddress });
+};
+
+/**
+ * Stop a server started with `startServer`.
+ * @param udsAddress The Unix domain socket address the server is listening on.
+ * @returns A promise that resolves when the server has stopped.
+ * @throws If no server is running at the address.
+ */
+export const stopServer = async (udsAddress: string): Promise<void> => {
+  return await invoke("stop_server", { udsAddress });
+};
+
+/**
+ * Get the connection log of every NIP-46 server: when each server started and stopped, the requests
+ * it received, and any errors.
+ * @param since An RFC 3339 timestamp. If given, only entries from then onwards are returned.
+ * @returns The log entries, oldest first.
+ * @throws If `since` isn't a valid timestamp.
+ */
+export const getConnectionLogs = async (
+  since?: string,
+): Promise<ConnectionLogEntry[]> => {
+  return await invoke("get_connection_logs", { since });
+};
+
+/**
+ * Export the payment or connection log as CSV, e.g. for accounting.
+ * @param kind Which log to export.
+ * @param since An RFC 3339 timestamp. If given, only entries from then onwards are exported.
+ * @param until An RFC 3339 timestamp. If given, only entries up to then are exported.
+ * @returns The CSV, oldest entry first. Just the header row if no entries are in range.
+ * @throws If `since` or `until` isn't a valid timestamp, or the Tauri database fails to read.
+ */
+export const exportLogsCsv = async (
+  kind: LogKind,
+  since?: string,
+  until?: string,
+): Promise<string> => {
+  return await invoke("export_logs_csv", { kind, since, until });
+};
+
+/**
+ * List the servers started with `startServer`.
+ * @returns Each server's address and the public key of the account it is bound to.
+ */
+export const listServers = async (): Promise<ServerInfo[]> => {
+  return await invoke("list_servers");
+};
+
+/**
+ * Temporarily stop the NIP-70 server and the servers started with `startServer` from handling
+ * requests, e.g. during sensitive work. New requests are rejected without asking, while requests
+ * already waiting for the user can still be answered. Lasts until `resumeServer` or a restart.
+ * @returns A promise that resolves once new requests are being rejected.
+ */
+export const pauseServer = async (): Promise<void> => {
+  return await invoke("pause_server");
+};
+
+/**
+ * Let the servers handle requests again after `pauseServer`.
+ * @returns A promise that resolves once requests are being handled again.
+ */
+export const resumeServer = async (): Promise<void> => {
+  return await invoke("resume_server");
+};
+
+/**
+ * Check whether the servers are paused (see `pauseServer`).
+ * @returns True if new requests are being rejected.
+ */
+export const isServerPaused = async (): Promise<boolean> => {
+  return await invoke("is_server_paused");
+};
+
+/**
+ * A response that also asks for the decision to be used for later events of the same kind from the
+ * same app, without asking. Only has an effect if the request's `can_remember` is set.
+ */
+type RememberedSignEventResponse = { approved: boolean; remember: boolean };
+
+/**
+ * Returns whether to approve the request. Returning an edited copy of the event approves signing
+ * the edited version instead, as long as it has the same pubkey and kind as the original.
+ */
+type SignEventRequestHandler = (
+  event: UnsignedNostrEvent, userPubkey: string, warnings: SignEventWarning[], kindLabel: string
+) =>
+  | Promise<boolean | UnsignedNostrEvent | RememberedSignEventResponse>
+  | boolean
+  | UnsignedNostrEvent
+  | RememberedSignEventResponse;
+
+listen("sign_event_request", async (event: Event<SignEventRequestPayload>) => {
+  let isApproved = false;
+  let editedEvent: UnsignedNostrEvent | null = null;
+  let remember = false;
+  for (const handler of Object.values(signEventRequestHandlers)) {
+    const response = await handler(
+      event.payload.event,
+      event.payload.user_npub,
+      event.payload.warnings,
+      event.payload.kind_label,
+    );
+    if (typeof response === "boolean") {
+      isApproved = response;
+    } else if ("remember" in response) {
+      isApproved = response.approved;
+      remember = response.remember;
+    } else {
+      isApproved = true;
+      editedEvent = response;
+    }
+    if (isApproved) {
+      break;
+    }
+  }
+  respondToSignEventRequest(
+    event.payload.event.id,
+    isApproved,
+    editedEvent,
+    remember,
+  );
+})
+  .then((unlisten) => {
+    // When vite reloads, a new event listener is created, so we need to unlisten to the old one.
+    // If we don't do this, each vite hot reload turns the old event listener into a phantom listener
+    // that has not event handlers and therefore immediately rejects all requests.
+    import.meta.hot?.on("vite:beforeUpdate", () => unlisten());
+    // TODO: Send a message to the Tauri backend to indicate that the event listener is ready.
+    // Before this, it should not send any `sign_event_request` events.
+  })
+  .catch((e) => {
+    console.error(e);
+  });
+const respondToSignEventRequest = async (
+  eventId: string,
+  approved: boolean,
+  editedEvent: UnsignedNostrEvent | null,
+  remember = false,
+): Promise<string> => {
+  return await invoke("respond_to_sign_event_request", {
+    eventId,
+    approved,
+    editedEvent,
+    remember,
+  });
+};
+
+/** Returns whether to approve signing every event in the batch. */
+type SignEventsRequestHandler = (
+  payload: SignEventsRequestPayload
+) => Promise<boolean> | boolean;
+
+listen("sign_events_request", async (event: Event<SignEventsRequestPayload>) => {
+  let isApproved = false;
+  for (const handler of Object.values(signEventsRequestHandlers)) {
+    isApproved = await handler(event.payload);
+    if (isApproved) {
+      break;
+    }
+  }
+  respondToSignEventsRequest(event.payload.batch_id, isApproved);
+})
+  .then((unlisten) => {
+    // When vite reloads, a new event listener is created, so we need to unlisten to the old one.
+    // If we don't do this, each vite hot reload turns the old event listener into a phantom listener
+    // that has not event handlers and therefore immediately rejects all requests.
+    import.meta.hot?.on("vite:beforeUpdate", () => unlisten());
+  })
+  .catch((e) => {
+    console.error(e);
+  });
+const respondToSignEventsRequest = async (
+  batchId: string,
+  approved: boolean,
+): Promise<string> => {
+  return await invoke("respond_to_sign_events_request", { batchId, approved });
+};
+
+/** Returns whether to approve decrypting the message. */
+type DecryptDmRequestHandler = (
+  payload: DecryptDmRequestPayload
+) => Promise<boolean> | boolean;
+
+listen("decrypt_dm_request", async (event: Event<DecryptDmRequestPayload>) => {
+  let isApproved = false;
+  for (const handler of Object.values(decryptDmRequestHandlers)) {
+    isApproved = await handler(event.payload);
+    if (isApproved) {
+      break;
+    }
+  }
+  respondToDecryptDmRequest(event.payload.event_id, isApproved);
+})
+  .then((unlisten) => {
+    // When vite reloads, a new event listener is created, so we need to unlisten to the old one.
+    // If we don't do this, each vite hot reload turns the old event listener into a phantom listener
+    // that has not event handlers and therefore immediately rejects all requests.
+    import.meta.hot?.on("vite:beforeUpdate", () => unlisten());
+  })
+  .catch((e) => {
+    console.error(e);
+  });
+const respondToDecryptDmRequest = async (
+  eventId: string,
+  approved: boolean,
+): Promise<string> => {
+  return await invoke("respond_to_decrypt_dm_request", { eventId, approved });
+};
+
+type PayInvoiceRequestApproval = boolean | { typedPhrase: string };
+
+type PayInvoiceRequestHandler = (
+  invoice: string, feeEstimate: FeeEstimate | null, confirmationPhrase: string | null
+) => Promise<PayInvoiceRequestApproval> | PayInvoiceRequestApproval;
+
+listen("pay_invoice_request", async (event: Event<PayInvoiceRequestPayload>) => {
+  let isApproved = false;
+  let typedPhrase: string | undefined;
+  for (const handler of Object.values(payInvoiceRequestHandlers)) {
+    const approval = await handler(
+      event.payload.invoice,
+      event.payload.fee_estimate,
+      event.payload.confirmation_phrase,
+    );
+    if (typeof approval === "object") {
+      isApproved = true;
+      typedPhrase = approval.typedPhrase;
+    } else {
+      isApproved = approval;
+    }
+    if (isApproved) {
+      break;
+    }
+  }
+  respondToPayInvoiceRequest(event.payload.invoice, isApproved, typedPhrase);
+})
+  .then((unlisten) => {
+    // When vite reloads, a new event listener is created, so we need to unlisten to the old one.
+    // If we don't do this, each vite hot reload turns the old event listener into a phantom listener
+    // that has not event handlers and therefore immediately rejects all requests.
+    import.meta.hot?.on("vite:beforeUpdate", () => unlisten());
+    // TODO: Send a message to the Tauri backend to indicate that the event listener is ready.
+    // Before this, it should not send any `pay_invoice_request` events.
+  })
+  .catch((e) => {
+    console.error(e);
+  });
+const respondToPayInvoiceRequest = async (
+  invoice: string,
+  approved: boolean,
+  confirmationPhrase?: string,
+): Promise<string> => {
+  return await invoke("respond_to_pay_invoice_request", {
+    invoice,
+    approved,
+    confirmationPhrase,
   });
-const respondToPayInvoiceRequest = async (
-  invoice: string,
-  approved: boolean,
-): Promise<string> => {
-  return await invoke("respond_to_pay_invoice_request", { invoice, approved });
 };
@@ -6,3 +6,553 @@ export interface UnsignedNostrEvent {
   tags: string[][];
   content: string;
 }
+
+export interface NostrEvent extends UnsignedNostrEvent {
+  sig: string;
+}
+
+export type SignEventWarning =
+  | { type: "created_at_not_now"; created_at: number; now: number }
+  | { type: "scam_list_match"; entry: string }
+  | { type: "missing_content_warning" }
+  | {
+      type: "replaces_existing_event";
+      identifier: string;
+      existing_event_id: string;
+      existing_created_at: number;
+    };
+
+export interface RememberedSignDecision {
+  app_npub: string;
+  kind: number;
+  approved: boolean;
+}
+
+export interface StoredSessionGrant {
+  app_npub: string;
+  allowed_kinds: number[];
+  /** Unix timestamp of when the grant expires. */
+  expires_at: number;
+}
+
+export interface SessionGrantEntry {
+  app_npub: string;
+  allowed_kinds: number[];
+  remaining_secs: number;
+}
+
+export type ContentWarningPolicy = "off" | "warn" | "suggest_tag";
+
+export type HighRiskAction = "wipe_all_data" | "reveal_secret" | "large_payment";
+
+export interface ConfirmationPhrasePolicy {
+  /** The actions that need the user to type a confirmation phrase. */
+  actions: HighRiskAction[];
+}
+
+export interface RequestAttention {
+  focus_window: boolean;
+  notify: boolean;
+}
+
+export interface RequestNotification {
+  title: string;
+  body: string;
+}
+
+export interface SignEventRequestPayload {
+  event: UnsignedNostrEvent;
+  user_npub: string;
+  /** Human-readable name of the event's kind, e.g. "Reaction". */
+  kind_label: string;
+  warnings: SignEventWarning[];
+  /** Marked protected (NIP-70), so only the author should publish it. */
+  protected: boolean;
+  /** Has a NIP-36 `content-warning` tag. */
+  has_content_warning: boolean;
+  /**
+   * The event with a `content-warning` tag added, if the content-warning policy suggests one.
+   * Approve with it as the edited event to sign it instead.
+   */
+  suggested_event: UnsignedNostrEvent | null;
+  /** Set during quiet hours. Keystache must be unlocked before the request can be approved. */
+  requires_unlock: boolean;
+  /** Relay hint added to the event's `e` and `p` tags that had none, if the account has relay hints on. */
+  added_relay_hint: string | null;
+  /** The NIP-40 expiration Keystache added to the event, in seconds since the Unix epoch. */
+  added_expiration: number | null;
+  /** If set, proof of work is mined to this many leading zero bits once the request is approved. */
+  pow_difficulty: number | null;
+  /** The request comes from a known app, so the decision can be remembered for its kind. */
+  can_remember: boolean;
+  app_npub: string | null;
+  /** The name the user gave the app, or its npub if unnamed. `null` if the app isn't known. */
+  app_name: string | null;
+}
+
+export interface PowProgressPayload {
+  /** ID of the event in the sign event request. */
+  event_id: string;
+  difficulty: number;
+  attempts: number;
+  best_leading_zero_bits: number;
+}
+
+export interface RelayPublishResult {
+  url: string;
+  accepted: boolean;
+  message: string;
+}
+
+export interface RelayPolicy {
+  read: boolean;
+  write: boolean;
+}
+
+export interface DuplicateRelays {
+  normalized_url: string;
+  urls: string[];
+}
+
+export interface RelayVisibilityPolicy {
+  private_relays: string[];
+  trusted_apps: string[];
+}
+
+export interface ProfileFields {
+  name: string | null;
+  about: string | null;
+  picture: string | null;
+  nip05: string | null;
+  lud16: string | null;
+}
+
+export interface UpdateProfileMetadataResponse {
+  event: NostrEvent;
+  publish_results: RelayPublishResult[];
+}
+
+export interface UpdateFollowListResponse {
+  event: NostrEvent;
+  publish_results: RelayPublishResult[];
+}
+
+export interface ServerInfo {
+  uds_address: string;
+  public_key: string;
+}
+
+export interface ConnectionQrPayload {
+  npub: string;
+}
+
+export interface ServerRestart {
+  restart_count: number;
+  cause: string;
+}
+
+export type UnlockMethod = "passkey" | "passphrase";
+
+export interface PasskeyAssertion {
+  credential_id: number[];
+  authenticator_data: number[];
+  client_data_json: number[];
+  signature: number[];
+}
+
+export interface HardwareKeyBinding {
+  npub: string;
+  module_path: string;
+  token_label: string;
+  key_label: string;
+  /** The token's vendor-defined mechanism for BIP-340 Schnorr signing. */
+  schnorr_mechanism: number;
+  /** `null` to log in through the token's own PIN entry, e.g. a PIN pad. */
+  pin: string | null;
+}
+
+export type FeeEstimate =
+  | { type: "estimated"; fee_sats: number }
+  | { type: "not_supported" };
+
+export interface PayInvoiceResponse {
+  /** Hex-encoded. */
+  payment_hash: string;
+  /** Hex-encoded. Proves that the invoice was paid. */
+  preimage: string;
+}
+
+export interface PayInvoiceRequestPayload {
+  invoice: string;
+  /** Hex-encoded. */
+  payment_hash: string;
+  fee_estimate: FeeEstimate | null;
+  /** Entries of the scam list that the invoice pays, e.g. the recipient of a zap. */
+  scam_list_matches: string[];
+  /**
+   * Set during quiet hours, or if the invoice is over the maximum single payment. Keystache must be
+   * unlocked before the request can be approved.
+   */
+  requires_unlock: boolean;
+  /**
+   * The phrase the user must type to approve the payment (see `setConfirmationPhrases`), or `null`
+   * if none is needed.
+   */
+  confirmation_phrase: string | null;
+  app_npub: string | null;
+  /** The name the user gave the app, or its npub if unnamed. `null` if the app isn't known. */
+  app_name: string | null;
+}
+
+export type PendingRequestKind = "sign_event" | "sign_events" | "decrypt_dm" | "pay_invoice";
+
+export interface PendingRequestEntry {
+  /** What the request is responded to by: the ID of the event or batch, or the invoice. */
+  id: string;
+  kind: PendingRequestKind;
+  app_npub: string | null;
+  /** Unix timestamp of when the user was asked. */
+  requested_at: number;
+  /** The payload of the `<kind>_request` event that the request was emitted as. */
+  payload:
+    | SignEventRequestPayload
+    | SignEventsRequestPayload
+    | DecryptDmRequestPayload
+    | PayInvoiceRequestPayload;
+}
+
+export interface AccountDataRefresh {
+  profile_changed: boolean;
+  relay_list_changed: boolean;
+  /** The stored profile, if there is one. */
+  profile: ProfileFields | null;
+  /** The relays of the stored relay list, if there is one. */
+  relay_list: [string, RelayPolicy][] | null;
+}
+
+export type BulkImportResult =
+  | { type: "imported"; npub: string }
+  | { type: "already_present"; npub: string }
+  | { type: "invalid"; reason: string };
+
+export interface ApprovalTimeouts {
+  default_secs: number;
+  /** Keyed by event kind. */
+  sign_event_kind_secs: Record<number, number>;
+  pay_invoice_secs: number | null;
+}
+
+export interface VaultIntegrityReport {
+  valid_entries: number;
+  corrupt_entries: number;
+  corrupt_pages: number;
+}
+
+export type KeyHealthWarning = "invalid_secret" | "public_key_mismatch" | "leaked";
+
+export interface KeyHealth {
+  warnings: KeyHealthWarning[];
+}
+
+export interface DatabaseDiagnosis {
+  missing_tables: string[];
+  missing_columns: string[];
+  missing_indexes: string[];
+  repaired: string[];
+}
+
+export interface AppAuthorization {
+  app_npub: string;
+  display_name: string | null;
+  identity_npub: string;
+  sign_decisions: RememberedSignDecision[];
+  /** Only set if the app has a session grant that hasn't expired. */
+  session_grant: StoredSessionGrant | null;
+  /** The same for every app, since there are no per-app limits. */
+  max_single_payment: MaxSinglePayment | null;
+  /** RFC 3339 timestamp of when the app last had an event signed or asked for a payment. */
+  last_used_time: string | null;
+}
+
+export type SigningOutcome = "approved" | "rejected";
+
+export interface SigningLogEntry {
+  app_npub: string;
+  kind: number;
+  /** RFC 3339 timestamp of when the request ended. */
+  create_time: string;
+  outcome: SigningOutcome;
+}
+
+export type PaymentOutcome = "paid" | "failed" | "rejected";
+
+export interface PaymentLogEntry {
+  invoice: string;
+  amount_msats: number | null;
+  description: string | null;
+  app_npub: string | null;
+  /** RFC 3339 timestamp. */
+  create_time: string;
+  outcome: PaymentOutcome;
+  /** Only set for paid invoices. */
+  preimage: string | null;
+}
+
+export interface PaymentHistoryFilter {
+  outcome?: PaymentOutcome | null;
+  app_npub?: string | null;
+}
+
+export interface SignEventsAccountGroup {
+  user_npub: string;
+  events: SignEventRequestPayload[];
+}
+
+export interface SignEventsRequestPayload {
+  batch_id: string;
+  /** Grouped by the account that will sign them, in the order each account first appears. */
+  accounts: SignEventsAccountGroup[];
+  /** Set during quiet hours. Keystache must be unlocked before the batch can be approved. */
+  requires_unlock: boolean;
+}
+
+export type DmScheme = "nip04" | "nip44";
+
+export interface DecryptDmRequestPayload {
+  event_id: string;
+  sender_npub: string;
+  recipient_npub: string;
+  scheme: DmScheme;
+}
+
+export interface AccountMetadata {
+  label: string | null;
+  /** RFC 3339 timestamp, or `null` if the account is still in use. */
+  retire_time: string | null;
+  /** npub of the account that replaced this one, if it has been retired. */
+  successor_npub: string | null;
+  /** NIP-06 account index, if the account was derived from a mnemonic. */
+  derivation_index: number | null;
+}
+
+export type SetupState =
+  | "vault_locked"
+  | "needs_vault_passphrase"
+  | "needs_first_account"
+  | "ready";
+
+export interface AccountListEntry {
+  npub: string;
+  /** `false` for watch-only accounts, which Keystache has no key for. */
+  can_sign: boolean;
+}
+
+export interface AccountStats {
+  sign_count: number;
+  payment_count: number;
+  /** RFC 3339 timestamp, or `null` if the account has never been used. */
+  last_active_time: string | null;
+}
+
+export interface AccountCompleteness {
+  has_label: boolean;
+  has_relays: boolean;
+  has_verified_nip05: boolean;
+  has_acknowledged_backup: boolean;
+  /** Whether the vault is encrypted with a passphrase. */
+  uses_encryption: boolean;
+}
+
+export interface KeyCacheStats {
+  cached_keys: number;
+  hits: number;
+  misses: number;
+}
+
+export interface RotateAccountResponse {
+  new_npub: string;
+  /** The signed note announcing the move, if one was requested. */
+  announcement: NostrEvent | null;
+  publish_results: RelayPublishResult[];
+}
+
+export type QuietHoursAction = "reject" | "require_unlock";
+
+export interface QuietHours {
+  /** Minutes since local midnight. */
+  start_minute: number;
+  /** Minutes since local midnight. If before `start_minute`, the window spans midnight. */
+  end_minute: number;
+  action: QuietHoursAction;
+}
+
+export type OverCapAction = "reject" | "require_unlock";
+
+export type NwcMethod =
+  | "pay_invoice"
+  | "multi_pay_invoice"
+  | "pay_keysend"
+  | "multi_pay_keysend"
+  | "make_invoice"
+  | "lookup_invoice"
+  | "list_transactions"
+  | "get_balance"
+  | "get_info";
+
+export interface MaxSinglePayment {
+  max_sats: number;
+  action: OverCapAction;
+}
+
+export type ConnectionEvent =
+  | { type: "connected" }
+  | { type: "request"; method: string }
+  | { type: "error"; message: string }
+  | { type: "disconnected"; request_counts: { [method: string]: number } };
+
+export interface ConnectionLogEntry {
+  connection_id: number;
+  server_address: string;
+  /** RFC 3339 timestamp. */
+  time: string;
+  event: ConnectionEvent;
+}
+
+export interface RelayReachability {
+  url: string;
+  reachable: boolean;
+}
+
+export interface RelayReachabilityReport {
+  reachable_count: number;
+  relays: RelayReachability[];
+}
+
+export type RelayTestResult =
+  | { type: "connected" }
+  | { type: "timed_out" }
+  | { type: "error"; message: string };
+
+export type DecodedEntity =
+  | { type: "npub"; public_key: string }
+  | { type: "nsec" }
+  | { type: "ncryptsec" }
+  | { type: "note"; event_id: string }
+  | {
+      type: "nevent";
+      event_id: string;
+      author: string | null;
+      relays: string[];
+    }
+  | { type: "nprofile"; public_key: string; relays: string[] }
+  | {
+      type: "naddr";
+      kind: number;
+      public_key: string;
+      identifier: string;
+      relays: string[];
+    }
+  | { type: "nrelay"; url: string };
+
+export interface DelegationVerification {
+  delegator: string | null;
+  valid: boolean;
+  reason: string | null;
+}
+
+export interface EventVerification {
+  signature_valid: boolean;
+  delegation: DelegationVerification | null;
+}
+
+export interface DecodedInvoice {
+  payment_hash: string;
+  amount_msats: number | null;
+  description: string | null;
+  description_hash: string | null;
+  payee_public_key: string;
+  network: "bitcoin" | "testnet" | "regtest" | "simnet" | "signet";
+  created_at: number;
+  expires_at: number;
+}
+
+export type ZapReceiptDiscrepancy =
+  | { type: "not_a_zap_receipt"; kind: number }
+  | { type: "invalid_signature" }
+  | { type: "missing_bolt11" }
+  | { type: "invalid_bolt11"; reason: string }
+  | {
+      type: "amount_mismatch";
+      requested_msats: number;
+      invoice_msats: number | null;
+    }
+  | { type: "missing_description" }
+  | { type: "description_mismatch" }
+  | { type: "description_hash_mismatch" }
+  | { type: "recipient_mismatch" }
+  | { type: "event_mismatch" };
+
+export interface ZapReceiptValidation {
+  valid: boolean;
+  discrepancies: ZapReceiptDiscrepancy[];
+}
+
+export type LogKind = "payments" | "connections";
+
+export interface Capabilities {
+  nips: string[];
+  payment_backends: string[];
+  features: string[];
+}
+
+export type LightningAddressStatus =
+  | {
+      type: "resolved";
+      min_sendable_msats: number;
+      max_sendable_msats: number;
+      allows_nostr: boolean;
+    }
+  | { type: "unresolvable"; reason: string };
+
+export interface LightningAddress {
+  address: string;
+  status: LightningAddressStatus;
+}
+
+export interface SigningBenchmark {
+  count: number;
+  total_millis: number;
+  signs_per_second: number;
+}
+
+export interface IdentityCard {
+  npub: string;
+  nprofile: string;
+  name: string | null;
+  nip05: string | null;
+  lud16: string | null;
+}
+
+export interface IdentityFingerprint {
+  npub: string;
+  fingerprint: string;
+}
+
+export interface IdenticonSeed {
+  npub: string;
+  /** Hex-encoded. */
+  seed: string;
+  /** In degrees, from 0 up to 360. */
+  hue: number;
+}
+
+export interface Nip05Profile {
+  npub: string;
+  relays: string[];
+}
+
+export interface Contact {
+  npub: string;
+  relay_url: string | null;
+  petname: string | null;
+}